@@ -0,0 +1 @@
+pub trait Value = Send;
@@ -0,0 +1,163 @@
+//! Ordered traversal of all keys and values in a tree
+//!
+//! Each byte of a key is spread over two levels of the tree (lower bits first),
+//! so children are visited one byte at a time to yield keys in ascending order.
+
+use std::ops::{Bound, RangeBounds};
+
+use super::node::{FrozenNode, Node};
+use super::{CowTree, FrozenCowTree, Hash, NUM_STEPS, Value};
+
+const KEY_LENGTH: usize = NUM_STEPS / 2;
+
+/// Unifies mutable nodes and the frozen nodes they might reference
+enum NodeRef<'a, V: Value> {
+    Mutable(&'a Node<V>),
+    Frozen(&'a FrozenNode<V>),
+}
+
+impl<V: Value> Clone for NodeRef<'_, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V: Value> Copy for NodeRef<'_, V> {}
+
+impl<'a, V: Value> NodeRef<'a, V> {
+    fn from_node(node: &'a Node<V>) -> Self {
+        if let Some(frozen) = node.get_reference() {
+            Self::Frozen(frozen)
+        } else {
+            Self::Mutable(node)
+        }
+    }
+
+    fn get_child(self, idx: u8) -> Option<Self> {
+        match self {
+            Self::Mutable(node) => node.get_child(idx).map(Self::from_node),
+            Self::Frozen(node) => node.get_child(idx).map(Self::Frozen),
+        }
+    }
+
+    fn get_value(self) -> &'a V {
+        match self {
+            Self::Mutable(node) => node.get_value(),
+            Self::Frozen(node) => node.get_value(),
+        }
+    }
+}
+
+/// Iterates over the keys and values of a tree in ascending key order
+///
+/// Created by `CowTree::iter`, `CowTree::range` and their `FrozenCowTree` equivalents.
+pub struct Iter<'a, V: Value> {
+    /// Nodes that still need to be visited, along with their key prefix and its length
+    /// The next node to visit is at the end
+    pending: Vec<(Hash, usize, NodeRef<'a, V>)>,
+    start: Bound<Hash>,
+    end: Bound<Hash>,
+}
+
+impl<'a, V: Value> Iter<'a, V> {
+    pub(crate) fn new(root: &'a Node<V>, range: impl RangeBounds<Hash>) -> Self {
+        Self::from_root(NodeRef::from_node(root), range)
+    }
+
+    pub(crate) fn new_frozen(root: &'a FrozenNode<V>, range: impl RangeBounds<Hash>) -> Self {
+        Self::from_root(NodeRef::Frozen(root), range)
+    }
+
+    fn from_root(root: NodeRef<'a, V>, range: impl RangeBounds<Hash>) -> Self {
+        Self {
+            pending: vec![(Hash::default(), 0, root)],
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// Can the subtree with the given key prefix contain keys within the range?
+    fn overlaps(&self, prefix: &Hash, prefix_len: usize) -> bool {
+        let mut lowest = *prefix;
+        let mut highest = *prefix;
+        lowest[prefix_len..].fill(0);
+        highest[prefix_len..].fill(0xFF);
+
+        let after_start = match &self.start {
+            Bound::Included(start) => highest >= *start,
+            Bound::Excluded(start) => highest > *start,
+            Bound::Unbounded => true,
+        };
+
+        let before_end = match &self.end {
+            Bound::Included(end) => lowest <= *end,
+            Bound::Excluded(end) => lowest < *end,
+            Bound::Unbounded => true,
+        };
+
+        after_start && before_end
+    }
+
+    /// Queues all children of a node that are one byte further down the key
+    fn expand(&mut self, prefix: Hash, prefix_len: usize, node: NodeRef<'a, V>) {
+        let mut children = vec![];
+
+        for lower in 0..16u8 {
+            let Some(child) = node.get_child(lower) else {
+                continue;
+            };
+
+            for upper in 0..16u8 {
+                if let Some(grandchild) = child.get_child(upper) {
+                    children.push(((upper << 4) | lower, grandchild));
+                }
+            }
+        }
+
+        // Visit the lowest byte first
+        children.sort_by_key(|(byte, _)| std::cmp::Reverse(*byte));
+
+        for (byte, child) in children {
+            let mut key = prefix;
+            key[prefix_len] = byte;
+
+            if self.overlaps(&key, prefix_len + 1) {
+                self.pending.push((key, prefix_len + 1, child));
+            }
+        }
+    }
+}
+
+impl<'a, V: Value> Iterator for Iter<'a, V> {
+    type Item = (Hash, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((prefix, prefix_len, node)) = self.pending.pop() {
+            if prefix_len == KEY_LENGTH {
+                return Some((prefix, node.get_value()));
+            }
+
+            self.expand(prefix, prefix_len, node);
+        }
+
+        None
+    }
+}
+
+impl<'a, V: Value> IntoIterator for &'a CowTree<V> {
+    type Item = (Hash, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, V: Value> IntoIterator for &'a FrozenCowTree<V> {
+    type Item = (Hash, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -1,14 +1,41 @@
-#![feature(trait_alias)]
+#![cfg_attr(feature = "nightly", feature(trait_alias))]
 
 use generic_array::{GenericArray, typenum};
 
 mod node;
 use node::{FrozenNode, Node};
 
+mod stats;
+pub use stats::TreeStats;
+
+mod merkle;
+pub use merkle::{Digestible, MerkleProof, verify_proof};
+
+mod iter;
+pub use iter::Iter;
+
+mod storage;
+pub use storage::StoredNode;
+
+use std::ops::RangeBounds;
+
 pub type Hash = GenericArray<u8, typenum::U32>;
-pub trait Value = Send;
+// In a separate file, so that stable toolchains do not parse the alias
+#[cfg(feature = "nightly")]
+mod alias;
+#[cfg(feature = "nightly")]
+pub use alias::Value;
+
+/// Stable toolchains do not support trait aliases, so use a blanket implementation instead
+#[cfg(not(feature = "nightly"))]
+pub trait Value: Send {}
+
+#[cfg(not(feature = "nightly"))]
+impl<T: Send> Value for T {}
 
 const BITS_PER_NODE: usize = 4;
+/// The number of children of each branch (see `StoredNode::Branch`)
+pub const CHILDREN_PER_BRANCH: usize = 2_usize.pow(BITS_PER_NODE as u32);
 const HASH_LENGTH: usize = 256;
 const NUM_STEPS: usize = HASH_LENGTH / BITS_PER_NODE;
 
@@ -65,7 +92,7 @@ impl<V: Value> CowTree<V> {
 
         assert_eq!(nodes.len(), step);
 
-        if !nodes.is_empty() && !nodes[step].1.is_branch() {
+        if !nodes.is_empty() && !nodes[step - 1].1.is_branch() {
             let (idx, node) = nodes.pop().unwrap();
             let branch = node.into_branch();
             nodes.push((idx, Box::new(branch)));
@@ -116,6 +143,156 @@ impl<V: Value> CowTree<V> {
         Some(current_node.get_value())
     }
 
+    /// Returns a mutable reference to the value stored under `key` (if any)
+    ///
+    /// Nodes shared with a frozen tree are copied first, but only along
+    /// the path from the root to the value.
+    pub fn get_mut(&mut self, key: &Hash) -> Option<&mut V>
+    where
+        V: Clone,
+    {
+        // Do not copy any nodes if there is nothing to modify
+        self.get(key)?;
+
+        let mut current_node = &mut self.root;
+
+        for step in 0..NUM_STEPS {
+            let idx = Self::get_index(key, step);
+
+            current_node.make_mutable();
+            current_node = current_node.get_child_mut(idx).expect("Value disappeared");
+        }
+
+        current_node.make_mutable();
+        Some(current_node.get_value_mut())
+    }
+
+    /// Iterates over all keys and values in ascending key order
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter::new(&self.root, ..)
+    }
+
+    /// Iterates over all keys within `range` (and their values) in ascending key order
+    /// Subtrees outside of the range are skipped entirely.
+    pub fn range(&self, range: impl RangeBounds<Hash>) -> Iter<'_, V> {
+        Iter::new(&self.root, range)
+    }
+
+    /// Modifies the value stored under `key` in place
+    /// Returns false if there is no such value
+    pub fn update(&mut self, key: &Hash, update_fn: impl FnOnce(&mut V)) -> bool
+    where
+        V: Clone,
+    {
+        if let Some(value) = self.get_mut(key) {
+            update_fn(value);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the value stored under `key` and returns it (if any)
+    ///
+    /// Nodes without children are removed as well. Like with `get_mut`,
+    /// nodes shared with a frozen tree are copied first, so the frozen tree is not affected.
+    pub fn remove(&mut self, key: &Hash) -> Option<V>
+    where
+        V: Clone,
+    {
+        // Do not copy any nodes if there is nothing to remove
+        self.get(key)?;
+
+        Self::remove_from(&mut self.root, key, 0)
+    }
+
+    fn remove_from(node: &mut Node<V>, key: &Hash, step: usize) -> Option<V>
+    where
+        V: Clone,
+    {
+        let idx = Self::get_index(key, step);
+        node.make_mutable();
+
+        if step == NUM_STEPS - 1 {
+            let mut leaf = node.take_child(idx)?;
+            leaf.make_mutable();
+            return Some(leaf.into_value());
+        }
+
+        let child = node.get_child_mut(idx)?;
+        let value = Self::remove_from(child, key, step + 1)?;
+
+        if child.is_empty() {
+            node.take_child(idx);
+        } else {
+            child.collapse();
+        }
+
+        Some(value)
+    }
+
+    /// The Merkle root of all keys and values in this tree
+    ///
+    /// Hashes are not cached, so this traverses the entire tree.
+    pub fn root_hash(&self) -> Hash
+    where
+        V: Digestible,
+    {
+        self.root.compute_hash()
+    }
+
+    /// Generates a proof that `key` maps to its current value (see `verify_proof`)
+    /// Returns `None` if there is no such key
+    pub fn get_proof(&self, key: &Hash) -> Option<MerkleProof>
+    where
+        V: Digestible,
+    {
+        let mut siblings = Vec::with_capacity(NUM_STEPS);
+        let mut current_node = &self.root;
+
+        for step in 0..NUM_STEPS {
+            if let Some(frozen) = current_node.get_reference() {
+                return Self::get_frozen_proof(key, step, frozen, siblings);
+            }
+
+            let idx = Self::get_index(key, step);
+            let child = current_node.get_child(idx)?;
+
+            let mut hashes = current_node.child_hashes();
+            hashes[idx as usize] = None;
+            siblings.push(hashes);
+
+            current_node = child;
+        }
+
+        Some(MerkleProof { siblings })
+    }
+
+    fn get_frozen_proof(
+        key: &Hash,
+        start_step: usize,
+        start: &FrozenNode<V>,
+        mut siblings: Vec<[Option<Hash>; CHILDREN_PER_BRANCH]>,
+    ) -> Option<MerkleProof>
+    where
+        V: Digestible,
+    {
+        let mut current_node = start;
+
+        for step in start_step..NUM_STEPS {
+            let idx = Self::get_index(key, step);
+            let child = current_node.get_child(idx)?;
+
+            let mut hashes = current_node.child_hashes();
+            hashes[idx as usize] = None;
+            siblings.push(hashes);
+
+            current_node = child;
+        }
+
+        Some(MerkleProof { siblings })
+    }
+
     fn get_frozen<'a>(key: &Hash, start_step: usize, start: &'a FrozenNode<V>) -> Option<&'a V> {
         let mut current_node = start;
 
@@ -152,6 +329,40 @@ impl<V: Value> FrozenCowTree<V> {
         Some(current_node.get_value())
     }
 
+    /// Iterates over all keys and values in ascending key order
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter::new_frozen(&self.root, ..)
+    }
+
+    /// Iterates over all keys within `range` (and their values) in ascending key order
+    pub fn range(&self, range: impl RangeBounds<Hash>) -> Iter<'_, V> {
+        Iter::new_frozen(&self.root, range)
+    }
+
+    /// The Merkle root of all keys and values in this tree
+    pub fn root_hash(&self) -> Hash
+    where
+        V: Digestible,
+    {
+        self.root.compute_hash()
+    }
+
+    /// Generates a proof that `key` maps to its value (see `verify_proof`)
+    /// Returns `None` if there is no such key
+    pub fn get_proof(&self, key: &Hash) -> Option<MerkleProof>
+    where
+        V: Digestible,
+    {
+        CowTree::<V>::get_frozen_proof(key, 0, &self.root, Vec::with_capacity(NUM_STEPS))
+    }
+
+    /// Counts the nodes of this tree and estimates how much memory they use
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        self.root.collect_stats(false, &mut stats);
+        stats
+    }
+
     pub fn deep_clone(&self) -> CowTree<V> {
         let mut new_root = Node::make_branch();
 
@@ -173,8 +384,9 @@ impl<V: Value> FrozenCowTree<V> {
 
 #[cfg(test)]
 mod test {
-    use super::CowTree;
+    use super::{CowTree, Hash, StoredNode, verify_proof};
     use sha3::{Digest, Sha3_256};
+    use std::collections::HashMap;
 
     #[test]
     fn insert_get() {
@@ -225,4 +437,337 @@ mod test {
         assert_eq!(tree2.get(&key1), Some(&value1));
         assert_eq!(tree2.get(&key2), Some(&value2));
     }
+
+    #[test]
+    fn stats() {
+        let mut tree1 = CowTree::default();
+
+        let key = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some key we are hashing");
+            hasher.finalize()
+        };
+        tree1.insert(&key, 1u64);
+
+        let frozen = tree1.freeze();
+        let stats = frozen.stats();
+
+        // The root, one extension per step, and the leaf at the end
+        assert_eq!(stats.num_branches, 1);
+        assert_eq!(stats.num_extensions, super::NUM_STEPS - 1);
+        assert_eq!(stats.num_leaves, 1);
+        assert_eq!(stats.num_shared_subtrees, 0);
+        assert!(stats.estimated_bytes > 0);
+
+        // The clone references the first level of the original tree
+        let _clone = frozen.deep_clone();
+        assert_eq!(frozen.stats().num_shared_subtrees, 1);
+    }
+
+    #[test]
+    fn update_frozen() {
+        let mut tree1 = CowTree::default();
+
+        let key1 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some key we are hashing");
+            hasher.finalize()
+        };
+        let key2 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some other key we are hashing");
+            hasher.finalize()
+        };
+
+        tree1.insert(&key1, 1);
+        tree1.insert(&key2, 2);
+
+        let frozen = tree1.freeze();
+        let mut tree2 = frozen.deep_clone();
+
+        assert!(tree2.update(&key1, |value| *value += 10));
+        *tree2.get_mut(&key2).unwrap() += 20;
+
+        // The frozen tree is not affected
+        assert_eq!(frozen.get(&key1), Some(&1));
+        assert_eq!(frozen.get(&key2), Some(&2));
+
+        assert_eq!(tree2.get(&key1), Some(&11));
+        assert_eq!(tree2.get(&key2), Some(&22));
+
+        let mut missing = key1;
+        missing[0] ^= 0xFF;
+        assert!(!tree2.update(&missing, |value| *value += 1));
+
+        let frozen2 = tree2.freeze();
+        assert_eq!(frozen2.get(&key1), Some(&11));
+        assert_eq!(frozen2.get(&key2), Some(&22));
+    }
+
+    #[test]
+    fn remove() {
+        let mut tree1 = CowTree::default();
+
+        let key1 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some key we are hashing");
+            hasher.finalize()
+        };
+        // Shares a prefix with the first key
+        let mut key2 = key1;
+        key2[10] ^= 0xFF;
+
+        tree1.insert(&key1, 1);
+        tree1.insert(&key2, 2);
+
+        let frozen = tree1.freeze();
+        let mut tree2 = frozen.deep_clone();
+
+        assert_eq!(tree2.remove(&key1), Some(1));
+        assert_eq!(tree2.remove(&key1), None);
+
+        // The frozen tree is not affected
+        assert_eq!(frozen.get(&key1), Some(&1));
+        assert_eq!(frozen.get(&key2), Some(&2));
+
+        assert_eq!(tree2.get(&key1), None);
+        assert_eq!(tree2.get(&key2), Some(&2));
+
+        // The branch for the removed key is pruned
+        let frozen2 = tree2.freeze();
+        let stats = frozen2.stats();
+        assert_eq!(stats.num_branches, 1);
+        assert_eq!(stats.num_leaves, 1);
+
+        let mut tree3 = frozen2.deep_clone();
+        assert_eq!(tree3.remove(&key2), Some(2));
+
+        let stats = tree3.freeze().stats();
+        assert_eq!(stats.num_branches, 1);
+        assert_eq!(stats.num_extensions, 0);
+        assert_eq!(stats.num_leaves, 0);
+        assert_eq!(frozen2.get(&key2), Some(&2));
+    }
+
+    #[test]
+    fn root_hash() {
+        let key1 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some key we are hashing");
+            hasher.finalize()
+        };
+        let key2 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some other key we are hashing");
+            hasher.finalize()
+        };
+        let mut key3 = key1;
+        key3[10] ^= 0xFF;
+
+        let mut tree1 = CowTree::default();
+        tree1.insert(&key1, 1u64);
+        tree1.insert(&key2, 2u64);
+
+        // Insertion order and removed keys do not matter
+        let mut tree2 = CowTree::default();
+        tree2.insert(&key3, 3u64);
+        tree2.insert(&key2, 2u64);
+        tree2.insert(&key1, 1u64);
+        assert_eq!(tree2.remove(&key3), Some(3));
+
+        assert_eq!(tree1.root_hash(), tree2.root_hash());
+        assert_ne!(tree1.root_hash(), CowTree::<u64>::default().root_hash());
+
+        let frozen = tree1.freeze();
+        assert_eq!(frozen.root_hash(), tree2.root_hash());
+
+        // Changing a value in a clone changes its root, but not the original one
+        let mut tree3 = frozen.deep_clone();
+        assert_eq!(tree3.root_hash(), frozen.root_hash());
+
+        tree3.update(&key1, |value| *value += 1);
+        assert_ne!(tree3.root_hash(), frozen.root_hash());
+        assert_eq!(frozen.root_hash(), tree2.root_hash());
+    }
+
+    #[test]
+    fn proofs() {
+        let key1 = {
+            let mut hasher = Sha3_256::new();
+            hasher.update(b"this is some key we are hashing");
+            hasher.finalize()
+        };
+        let mut key2 = key1;
+        key2[10] ^= 0xFF;
+
+        let mut tree1 = CowTree::default();
+        tree1.insert(&key1, "first".to_string());
+        tree1.insert(&key2, "second".to_string());
+
+        let proof = tree1.get_proof(&key1).unwrap();
+        let root = tree1.root_hash();
+        assert!(verify_proof(&root, &key1, &"first".to_string(), &proof));
+        assert!(!verify_proof(&root, &key1, &"second".to_string(), &proof));
+        assert!(!verify_proof(&root, &key2, &"first".to_string(), &proof));
+
+        // Proofs also work across references to frozen nodes
+        let frozen = tree1.freeze();
+        let mut tree2 = frozen.deep_clone();
+        tree2.update(&key2, |value| value.push('!'));
+
+        let proof = tree2.get_proof(&key2).unwrap();
+        assert!(verify_proof(
+            &tree2.root_hash(),
+            &key2,
+            &"second!".to_string(),
+            &proof
+        ));
+        assert!(!verify_proof(
+            &frozen.root_hash(),
+            &key2,
+            &"second!".to_string(),
+            &proof
+        ));
+
+        let proof = frozen.get_proof(&key2).unwrap();
+        assert!(verify_proof(
+            &frozen.root_hash(),
+            &key2,
+            &"second".to_string(),
+            &proof
+        ));
+
+        let mut missing = key1;
+        missing[0] ^= 0xFF;
+        assert!(frozen.get_proof(&missing).is_none());
+    }
+
+    #[test]
+    fn iter() {
+        let keys: Vec<Hash> = (0..100u32)
+            .map(|i| Sha3_256::digest(i.to_le_bytes()))
+            .collect();
+
+        let mut tree1 = CowTree::default();
+        for (i, key) in keys.iter().enumerate() {
+            tree1.insert(key, i);
+        }
+
+        let mut sorted = keys.clone();
+        sorted.sort();
+
+        let visited: Vec<Hash> = tree1.iter().map(|(key, _)| key).collect();
+        assert_eq!(visited, sorted);
+
+        for (key, value) in &tree1 {
+            assert_eq!(keys[*value], key);
+        }
+
+        // Frozen trees and trees referencing them yield the same entries
+        let frozen = tree1.freeze();
+        let mut tree2 = frozen.deep_clone();
+        assert!(frozen.iter().map(|(key, _)| key).eq(sorted.iter().copied()));
+
+        tree2.remove(&sorted[0]);
+        tree2.update(&sorted[1], |value| *value += 1000);
+
+        let entries: Vec<_> = tree2.iter().collect();
+        assert_eq!(entries.len(), 99);
+        assert_eq!(entries[0].0, sorted[1]);
+        assert!(*entries[0].1 >= 1000);
+        assert_eq!(frozen.iter().count(), 100);
+
+        assert_eq!(CowTree::<u32>::default().iter().count(), 0);
+    }
+
+    #[test]
+    fn range() {
+        let mut keys: Vec<Hash> = (0..100u32)
+            .map(|i| Sha3_256::digest(i.to_le_bytes()))
+            .collect();
+
+        let mut tree = CowTree::default();
+        for key in keys.iter() {
+            tree.insert(key, ());
+        }
+
+        keys.sort();
+
+        let visited: Vec<Hash> = tree.range(keys[10]..keys[20]).map(|(key, _)| key).collect();
+        assert_eq!(visited, keys[10..20]);
+
+        let visited: Vec<Hash> = tree
+            .range(keys[10]..=keys[20])
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(visited, keys[10..=20]);
+
+        // Bounds do not need to be keys of the tree
+        let mut start = keys[50];
+        start[31] = start[31].wrapping_add(1);
+        let frozen = tree.freeze();
+        let visited: Vec<Hash> = frozen.range(start..).map(|(key, _)| key).collect();
+        assert_eq!(visited, keys[51..]);
+
+        assert_eq!(frozen.range(..keys[0]).count(), 0);
+    }
+
+    #[test]
+    fn export_import() {
+        let keys: Vec<Hash> = (0..100u32)
+            .map(|i| Sha3_256::digest(i.to_le_bytes()))
+            .collect();
+
+        let mut tree1 = CowTree::default();
+        for (value, key) in keys.iter().enumerate() {
+            tree1.insert(key, value as u64);
+        }
+
+        fn store(
+            stored: &mut HashMap<Hash, StoredNode<u64>>,
+            hash: &Hash,
+            node: StoredNode<&u64>,
+        ) -> Result<(), ()> {
+            let node = match node {
+                StoredNode::Leaf(value) => StoredNode::Leaf(*value),
+                StoredNode::Branch(hashes) => StoredNode::Branch(hashes),
+                StoredNode::Path { bits, child } => StoredNode::Path { bits, child },
+            };
+            stored.entry(*hash).or_insert(node);
+            Ok(())
+        }
+
+        let mut stored = HashMap::new();
+        let frozen1 = tree1.freeze();
+        let root1 = frozen1
+            .export(&mut |hash, node| store(&mut stored, hash, node))
+            .unwrap();
+        assert_eq!(root1, frozen1.root_hash());
+        let num_nodes = stored.len();
+
+        // Only the path to the modified value is new
+        let mut tree2 = frozen1.deep_clone();
+        assert!(tree2.update(&keys[0], |value| *value += 1000));
+        let frozen2 = tree2.freeze();
+
+        let root2 = frozen2
+            .export(&mut |hash, node| store(&mut stored, hash, node))
+            .unwrap();
+        assert_eq!(root2, frozen2.root_hash());
+        assert!(stored.len() - num_nodes < 10);
+
+        let mut load = |hash: &Hash| stored.get(hash).cloned().ok_or(());
+        let loaded1 = super::FrozenCowTree::import(&root1, &mut load).unwrap();
+        let loaded2 = super::FrozenCowTree::import(&root2, &mut load).unwrap();
+
+        assert_eq!(loaded1.root_hash(), root1);
+        assert_eq!(loaded2.root_hash(), root2);
+        assert_eq!(loaded1.get(&keys[0]), Some(&0));
+        assert_eq!(loaded2.get(&keys[0]), Some(&1000));
+        assert_eq!(loaded2.iter().count(), keys.len());
+
+        let mut missing = root1;
+        missing[0] ^= 0xFF;
+        assert!(super::FrozenCowTree::import(&missing, &mut load).is_err());
+    }
 }
@@ -0,0 +1,119 @@
+//! Merkle commitments to the contents of a tree
+//!
+//! Extensions are hashed like a branch with a single child,
+//! so the root hash only depends on the stored keys and values,
+//! not on how the tree was built.
+
+use sha3::{Digest, Sha3_256};
+
+use super::{CHILDREN_PER_BRANCH, CowTree, Hash, NUM_STEPS, Value};
+
+const LEAF_TAG: u8 = 0;
+const BRANCH_TAG: u8 = 1;
+
+/// Values that can be committed to in a Merkle root
+pub trait Digestible {
+    fn digest(&self) -> Hash;
+}
+
+impl Digestible for [u8] {
+    fn digest(&self) -> Hash {
+        Sha3_256::digest(self)
+    }
+}
+
+impl Digestible for Vec<u8> {
+    fn digest(&self) -> Hash {
+        self.as_slice().digest()
+    }
+}
+
+impl Digestible for String {
+    fn digest(&self) -> Hash {
+        self.as_bytes().digest()
+    }
+}
+
+macro_rules! impl_digestible_for_int {
+    ($($ty:ty),*) => {
+        $(
+            impl Digestible for $ty {
+                fn digest(&self) -> Hash {
+                    self.to_le_bytes().digest()
+                }
+            }
+        )*
+    };
+}
+
+impl_digestible_for_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// The hashes of the siblings of every node on the path from the root to a value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// Ordered from the root downwards; the entry on the path itself is always `None`
+    pub(crate) siblings: Vec<[Option<Hash>; CHILDREN_PER_BRANCH]>,
+}
+
+impl MerkleProof {
+    /// The size of the proof if sent over the network (in bytes)
+    pub fn get_size(&self) -> usize {
+        let num_hashes: usize = self
+            .siblings
+            .iter()
+            .map(|level| level.iter().flatten().count())
+            .sum();
+
+        // One bitmap per level that marks the children that are present
+        num_hashes * size_of::<Hash>() + self.siblings.len() * size_of::<u16>()
+    }
+}
+
+/// Checks that the tree with the given root hash maps `key` to `value`
+pub fn verify_proof<V: Value + Digestible>(
+    root: &Hash,
+    key: &Hash,
+    value: &V,
+    proof: &MerkleProof,
+) -> bool {
+    if proof.siblings.len() != NUM_STEPS {
+        return false;
+    }
+
+    let mut current = hash_leaf(value);
+
+    for (step, siblings) in proof.siblings.iter().enumerate().rev() {
+        let idx = CowTree::<V>::get_index(key, step) as usize;
+        if siblings[idx].is_some() {
+            return false;
+        }
+
+        let mut children = *siblings;
+        children[idx] = Some(current);
+        current = hash_branch(&children);
+    }
+
+    &current == root
+}
+
+pub(crate) fn hash_leaf<V: Digestible>(value: &V) -> Hash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([LEAF_TAG]);
+    hasher.update(value.digest());
+    hasher.finalize()
+}
+
+/// Missing children are hashed as zeros
+pub(crate) fn hash_branch(children: &[Option<Hash>; CHILDREN_PER_BRANCH]) -> Hash {
+    let mut hasher = Sha3_256::new();
+    hasher.update([BRANCH_TAG]);
+
+    for child in children.iter() {
+        match child {
+            Some(hash) => hasher.update(hash),
+            None => hasher.update(Hash::default()),
+        }
+    }
+
+    hasher.finalize()
+}
@@ -1,9 +1,8 @@
-use super::{BITS_PER_NODE, Value};
+use super::merkle::{hash_branch, hash_leaf};
+use super::{CHILDREN_PER_BRANCH, Digestible, Hash, TreeStats, Value};
 
 use std::rc::Rc;
 
-const CHILDREN_PER_BRANCH: usize = 2_usize.pow(BITS_PER_NODE as u32);
-
 pub enum Node<V: Value> {
     Leaf(V),
     Branch {
@@ -124,6 +123,24 @@ impl<V: Value> Node<V> {
         }
     }
 
+    /// Call `make_mutable` first if this node might be a reference
+    pub fn get_child_mut(&mut self, idx: u8) -> Option<&mut Self> {
+        assert!((idx as usize) < CHILDREN_PER_BRANCH);
+
+        match self {
+            Self::Leaf(_) => panic!("Cannot get child of leaf!"),
+            Self::Branch { children } => children[idx as usize].as_deref_mut(),
+            Self::Extension { bits, child } => {
+                if *bits == idx {
+                    child.as_deref_mut()
+                } else {
+                    None
+                }
+            }
+            Self::Reference(_) => panic!("Cannot get child of reference"),
+        }
+    }
+
     pub fn get_value(&self) -> &V {
         match self {
             Self::Leaf(v) => v,
@@ -131,6 +148,13 @@ impl<V: Value> Node<V> {
         }
     }
 
+    pub fn get_value_mut(&mut self) -> &mut V {
+        match self {
+            Self::Leaf(v) => v,
+            _ => panic!("Cannot get value of non-leaf!"),
+        }
+    }
+
     pub fn set_child(&mut self, idx: u8, new_child: Box<Self>) {
         assert!((idx as usize) < CHILDREN_PER_BRANCH);
 
@@ -169,6 +193,43 @@ impl<V: Value> Node<V> {
         matches!(self, Self::Branch { .. })
     }
 
+    /// Does this node have no children left?
+    /// Leaves and references are never empty
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Branch { children } => children.iter().all(|child| child.is_none()),
+            Self::Extension { child, .. } => child.is_none(),
+            Self::Leaf(_) | Self::Reference(_) => false,
+        }
+    }
+
+    /// Turns a branch with a single child into an extension,
+    /// so that the tree looks the same as if the removed keys had never been inserted
+    pub fn collapse(&mut self) {
+        let Self::Branch { children } = self else {
+            return;
+        };
+
+        let remaining: Vec<_> = (0..CHILDREN_PER_BRANCH)
+            .filter(|pos| children[*pos].is_some())
+            .collect();
+
+        if let [pos] = remaining[..] {
+            let child = children[pos].take();
+            *self = Self::Extension {
+                bits: pos as u8,
+                child,
+            };
+        }
+    }
+
+    pub fn into_value(self) -> V {
+        match self {
+            Self::Leaf(v) => v,
+            _ => panic!("Cannot get value of non-leaf!"),
+        }
+    }
+
     /// If this is a reference; it will return the frozen node it points to
     pub fn get_reference(&self) -> Option<&FrozenNode<V>> {
         if let Self::Reference(frozen) = self {
@@ -179,6 +240,42 @@ impl<V: Value> Node<V> {
     }
 }
 
+impl<V: Value + Digestible> Node<V> {
+    pub fn compute_hash(&self) -> Hash {
+        match self {
+            Self::Leaf(v) => hash_leaf(v),
+            Self::Reference(frozen) => frozen.compute_hash(),
+            _ => hash_branch(&self.child_hashes()),
+        }
+    }
+
+    /// The hashes of all children, indexed by position
+    pub fn child_hashes(&self) -> [Option<Hash>; CHILDREN_PER_BRANCH] {
+        match self {
+            Self::Leaf(_) => panic!("Leaves have no children"),
+            Self::Branch { children } => children
+                .each_ref()
+                .map(|child| child.as_ref().map(|c| c.compute_hash())),
+            Self::Extension { bits, child } => {
+                let mut hashes = [None; CHILDREN_PER_BRANCH];
+                hashes[*bits as usize] = child.as_ref().map(|c| c.compute_hash());
+                hashes
+            }
+            Self::Reference(frozen) => frozen.child_hashes(),
+        }
+    }
+}
+
+impl<V: Value + Clone> Node<V> {
+    /// If this is a reference, replace it with a copy of the frozen node it points to
+    /// The children of the copy are references, so only this node is duplicated
+    pub fn make_mutable(&mut self) {
+        if let Self::Reference(frozen) = self {
+            *self = frozen.shallow_copy();
+        }
+    }
+}
+
 impl<V: Value> FrozenNode<V> {
     pub fn get_value(&self) -> &V {
         match self {
@@ -215,7 +312,7 @@ impl<V: Value> FrozenNode<V> {
                 }
             }
             Self::Reference(c) => {
-                assert!(c.is_reference());
+                assert!(!c.is_reference());
                 c.get_child(idx)
             }
         }
@@ -224,4 +321,92 @@ impl<V: Value> FrozenNode<V> {
     pub fn is_reference(&self) -> bool {
         matches!(self, Self::Reference(_))
     }
+
+    /// Adds this node and all its descendants to `stats`
+    /// Only the topmost node of a shared subtree is counted as shared
+    pub fn collect_stats(&self, in_shared: bool, stats: &mut TreeStats) {
+        stats.estimated_bytes += size_of::<Self>();
+
+        match self {
+            Self::Leaf(_) => stats.num_leaves += 1,
+            Self::Branch { children } => {
+                stats.num_branches += 1;
+                for child in children.iter().flatten() {
+                    Self::collect_child_stats(child, in_shared, stats);
+                }
+            }
+            Self::Extension { child, .. } => {
+                stats.num_extensions += 1;
+                Self::collect_child_stats(child, in_shared, stats);
+            }
+            Self::Reference(target) => {
+                stats.num_references += 1;
+                Self::collect_child_stats(target, in_shared, stats);
+            }
+        }
+    }
+
+    fn collect_child_stats(child: &Rc<Self>, in_shared: bool, stats: &mut TreeStats) {
+        let shared = Rc::strong_count(child) > 1;
+        if shared && !in_shared {
+            stats.num_shared_subtrees += 1;
+        }
+
+        // Reference counts are stored next to the node
+        stats.estimated_bytes += 2 * size_of::<usize>();
+        child.collect_stats(in_shared || shared, stats);
+    }
+}
+
+impl<V: Value + Digestible> FrozenNode<V> {
+    pub fn compute_hash(&self) -> Hash {
+        match self {
+            Self::Leaf(v) => hash_leaf(v),
+            Self::Reference(other) => other.compute_hash(),
+            _ => hash_branch(&self.child_hashes()),
+        }
+    }
+
+    /// The hashes of all children, indexed by position
+    pub fn child_hashes(&self) -> [Option<Hash>; CHILDREN_PER_BRANCH] {
+        match self {
+            Self::Leaf(_) => panic!("Leaves have no children"),
+            Self::Branch { children } => children
+                .each_ref()
+                .map(|child| child.as_ref().map(|c| c.compute_hash())),
+            Self::Extension { bits, child } => {
+                let mut hashes = [None; CHILDREN_PER_BRANCH];
+                hashes[*bits as usize] = Some(child.compute_hash());
+                hashes
+            }
+            Self::Reference(other) => other.child_hashes(),
+        }
+    }
+}
+
+impl<V: Value + Clone> FrozenNode<V> {
+    /// Creates a mutable copy of this node that references the children of the original
+    fn shallow_copy(&self) -> Node<V> {
+        let reference =
+            |child: &Rc<Self>| Box::new(Node::Reference(Self::to_reference(child.clone())));
+
+        match self {
+            Self::Leaf(v) => Node::Leaf(v.clone()),
+            Self::Branch { children } => {
+                let mut new_children: [Option<Box<Node<V>>>; CHILDREN_PER_BRANCH] =
+                    Default::default();
+                for (pos, child) in children.iter().enumerate() {
+                    new_children[pos] = child.as_ref().map(reference);
+                }
+                Node::Branch {
+                    children: new_children,
+                }
+            }
+            Self::Extension { bits, child } => Node::Extension {
+                bits: *bits,
+                child: Some(reference(child)),
+            },
+            Self::Reference(other) => other.shallow_copy(),
+        }
+    }
 }
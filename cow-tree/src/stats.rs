@@ -0,0 +1,13 @@
+/// Node counts and memory usage of a tree
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    pub num_branches: usize,
+    pub num_extensions: usize,
+    pub num_leaves: usize,
+    /// Nodes pointing to (parts of) another frozen tree
+    pub num_references: usize,
+    /// Subtrees that are also part of other trees
+    pub num_shared_subtrees: usize,
+    /// Estimated memory used by all nodes, including shared ones (in bytes)
+    pub estimated_bytes: usize,
+}
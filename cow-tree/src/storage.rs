@@ -0,0 +1,138 @@
+use super::merkle::{hash_branch, hash_leaf};
+use super::node::FrozenNode;
+use super::{CHILDREN_PER_BRANCH, Digestible, FrozenCowTree, Hash, Value};
+
+use std::rc::Rc;
+
+/// A node as written to external storage (see `FrozenCowTree::export`)
+///
+/// Nodes refer to their children by hash, so subtrees that several trees
+/// have in common only need to be stored once.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoredNode<V> {
+    Leaf(V),
+    /// The hashes of all children, indexed by position
+    Branch([Option<Hash>; CHILDREN_PER_BRANCH]),
+    /// A chain of extensions, one for each of the given positions, that ends at `child`
+    Path {
+        bits: Vec<u8>,
+        child: Hash,
+    },
+}
+
+impl<V: Value + Digestible> FrozenCowTree<V> {
+    /// Passes every node of this tree to `store`, children before their parents,
+    /// and returns the root hash
+    ///
+    /// Nodes are identified by their hash, so `store` can skip nodes it has seen before.
+    /// Like `root_hash`, this traverses the entire tree.
+    pub fn export<E>(
+        &self,
+        store: &mut impl FnMut(&Hash, StoredNode<&V>) -> Result<(), E>,
+    ) -> Result<Hash, E> {
+        export_node(&self.root, store)
+    }
+
+    /// Rebuilds a tree from nodes written by `export`
+    pub fn import<E>(
+        root: &Hash,
+        load: &mut impl FnMut(&Hash) -> Result<StoredNode<V>, E>,
+    ) -> Result<Self, E> {
+        let root = import_node(root, load)?;
+        assert!(
+            matches!(root, FrozenNode::Branch { .. }),
+            "Root must be a branch"
+        );
+
+        Ok(Self { root })
+    }
+}
+
+/// Follows references until it reaches the node they point to
+fn resolve<V: Value>(node: &FrozenNode<V>) -> &FrozenNode<V> {
+    match node {
+        FrozenNode::Reference(target) => resolve(target),
+        _ => node,
+    }
+}
+
+fn export_node<V: Value + Digestible, E>(
+    node: &FrozenNode<V>,
+    store: &mut impl FnMut(&Hash, StoredNode<&V>) -> Result<(), E>,
+) -> Result<Hash, E> {
+    match resolve(node) {
+        FrozenNode::Leaf(value) => {
+            let hash = hash_leaf(value);
+            store(&hash, StoredNode::Leaf(value))?;
+            Ok(hash)
+        }
+        FrozenNode::Branch { children } => {
+            let mut hashes = [None; CHILDREN_PER_BRANCH];
+            for (pos, child) in children.iter().enumerate() {
+                if let Some(child) = child {
+                    hashes[pos] = Some(export_node(child, store)?);
+                }
+            }
+
+            let hash = hash_branch(&hashes);
+            store(&hash, StoredNode::Branch(hashes))?;
+            Ok(hash)
+        }
+        FrozenNode::Extension { .. } => {
+            // Every key has its own chain of extensions, so store them together
+            let mut bits = vec![];
+            let mut current = resolve(node);
+
+            while let FrozenNode::Extension { bits: idx, child } = current {
+                bits.push(*idx);
+                current = resolve(child);
+            }
+
+            let child = export_node(current, store)?;
+            let mut hash = child;
+
+            for idx in bits.iter().rev() {
+                let mut hashes = [None; CHILDREN_PER_BRANCH];
+                hashes[*idx as usize] = Some(hash);
+                hash = hash_branch(&hashes);
+            }
+
+            store(&hash, StoredNode::Path { bits, child })?;
+            Ok(hash)
+        }
+        FrozenNode::Reference(_) => unreachable!(),
+    }
+}
+
+fn import_node<V: Value, E>(
+    hash: &Hash,
+    load: &mut impl FnMut(&Hash) -> Result<StoredNode<V>, E>,
+) -> Result<FrozenNode<V>, E> {
+    let node = match load(hash)? {
+        StoredNode::Leaf(value) => FrozenNode::Leaf(value),
+        StoredNode::Branch(hashes) => {
+            let mut children: [Option<Rc<FrozenNode<V>>>; CHILDREN_PER_BRANCH] = Default::default();
+            for (pos, hash) in hashes.iter().enumerate() {
+                if let Some(hash) = hash {
+                    children[pos] = Some(Rc::new(import_node(hash, load)?));
+                }
+            }
+
+            FrozenNode::Branch { children }
+        }
+        StoredNode::Path { bits, child } => {
+            let mut node = import_node(&child, load)?;
+            for idx in bits.into_iter().rev() {
+                node = FrozenNode::Extension {
+                    bits: idx,
+                    child: Rc::new(node),
+                };
+            }
+
+            node
+        }
+    };
+
+    Ok(node)
+}
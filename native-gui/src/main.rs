@@ -12,21 +12,32 @@ use winit::window::WindowAttributes;
 
 use clap::Parser;
 
-use simba_visualizer::graphics::{Graphics, RenderLoop};
+use simba_visualizer::graphics::{Graphics, RenderLoop, RenderLoopConfig};
+use simba_visualizer::remote::RemoteSimulation;
 use simba_visualizer::scene::SceneManager;
-use simba_visualizer::ui::{CursorPosition, UiEvents, UiMessages};
+use simba_visualizer::simulation::SimulationHandle;
+use simba_visualizer::ui::{CursorPosition, UiEvents, UiMessage, UiMessages};
 use simba_visualizer::window_loop::WindowLoop;
 
-use simba::{Failures, Library, Simulation};
+use simba::{Failures, Library, Simulation, validate_setup};
+
+mod session;
+use session::Session;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     #[clap(help = "The name of the network to run on")]
-    network_name: String,
+    #[clap(required_unless_present = "connect")]
+    network_name: Option<String>,
 
     #[clap(help = "The name of the protocol to run")]
-    protocol_name: String,
+    #[clap(required_unless_present = "connect")]
+    protocol_name: Option<String>,
+
+    #[clap(long, conflicts_with_all = ["network_name", "protocol_name"])]
+    #[clap(help = "Visualize a simulation started with `simba serve` at the given address")]
+    connect: Option<String>,
 
     #[clap(long, short = 'p', default_value = "./library")]
     #[clap(help = "Where to look for the configuration files?")]
@@ -42,6 +53,48 @@ struct Args {
     #[clap(long)]
     #[clap(help = "Pause the simulation on startup")]
     start_paused: bool,
+
+    #[clap(long, default_value_t = 60)]
+    #[clap(help = "The maximum number of frames per second (0 means unlimited)")]
+    max_fps: u32,
+
+    #[clap(long)]
+    #[clap(help = "Do not wait for vertical sync when presenting frames")]
+    disable_vsync: bool,
+
+    #[clap(long)]
+    #[clap(help = "Draw every frame, even if nothing changed")]
+    always_redraw: bool,
+
+    #[clap(long)]
+    #[clap(help = "Do not restore or save cameras, view, speed, and window size")]
+    no_session: bool,
+
+    #[clap(long)]
+    #[clap(help = "Start with the high-contrast theme")]
+    high_contrast: bool,
+}
+
+fn create_simulation(args: &Args) -> anyhow::Result<Simulation> {
+    let library = match Library::new(&args.library_path) {
+        Ok(library) => library,
+        Err(err) => {
+            log::error!("Failed to open library: {err}");
+            std::process::exit(-1);
+        }
+    };
+
+    let protocol_name = args.protocol_name.as_deref().unwrap_or_default();
+    let network_name = args.network_name.as_deref().unwrap_or_default();
+
+    let protocol = library.get_protocol(protocol_name)?.clone();
+    let network = library.get_network(network_name)?.clone();
+    validate_setup(&protocol, &network, None, &[])?;
+
+    let failures = Failures::new(&protocol, &network, None, network.get_seed());
+
+    Simulation::new(protocol, network, failures, None)
+        .with_context(|| "Failed to create simulation")
 }
 
 #[tokio::main]
@@ -55,28 +108,42 @@ async fn main() -> anyhow::Result<()> {
         env_logger::init();
     }
 
-    let library = match Library::new(args.library_path) {
-        Ok(library) => library,
-        Err(err) => {
-            log::error!("Failed to open library: {err}");
-            std::process::exit(-1);
-        }
+    // A remote simulation is started by the server once we are connected,
+    // and a local one is started here once the scene is set up
+    let (simulation, local_simulation): (Arc<dyn SimulationHandle>, _) =
+        if let Some(address) = &args.connect {
+            let remote = RemoteSimulation::connect(address.as_str())?;
+            (Arc::new(remote), None)
+        } else {
+            let local = Arc::new(create_simulation(&args)?);
+            (local.clone(), Some(local))
+        };
+
+    let session_path = if args.no_session {
+        None
+    } else {
+        Session::get_default_path()
     };
-
-    let protocol = library.get_protocol(&args.protocol_name)?.clone();
-    let network = library.get_network(&args.network_name)?.clone();
+    let mut session = session_path
+        .as_deref()
+        .map(Session::load)
+        .unwrap_or_default();
 
     let ui_messages = Arc::new(UiMessages::default());
+    if args.high_contrast {
+        ui_messages.push(UiMessage::SetHighContrast(true));
+    }
     let ui_events = Arc::new(UiEvents::default());
 
     let winit_loop = WinitEventLoop::new().with_context(|| "Create winit event loop")?;
 
     let cursor_position = Arc::new(CursorPosition::default());
 
+    let (width, height) = session.window_size.unwrap_or((1440.0, 900.0));
     let attributes = WindowAttributes::default()
         .with_title("SimBA")
         .with_resizable(true)
-        .with_inner_size(Size::Logical(LogicalSize::new(1440.0, 900.0)));
+        .with_inner_size(Size::Logical(LogicalSize::new(width, height)));
 
     #[allow(deprecated)]
     let window = winit_loop
@@ -87,33 +154,37 @@ async fn main() -> anyhow::Result<()> {
 
     let (graphics, surface) = Graphics::new(&window).await?;
     let graphics = Arc::new(graphics);
-    let failures = Failures::new(network.num_nodes(), None);
-
-    let simulation = Arc::new(
-        Simulation::new(protocol, network, failures, None)
-            .with_context(|| "Failed to create simulation")?,
-    );
 
     let scene_mgr = Arc::new(
         SceneManager::new(graphics.clone(), ui_messages.clone(), simulation.clone()).await,
     );
 
+    session.restore_scenes(&scene_mgr);
+
     log::debug!("Everything set up!");
 
     if args.start_paused {
         simulation.set_rate_limit(0);
     } else {
-        // Start simulation speed to 10x of real time
-        simulation.set_rate_limit(1_000);
+        // Start simulation speed to 10x of real time (unless the previous session used another speed)
+        simulation.set_rate_limit(session.rate_limit.unwrap_or(1_000));
     }
 
     // Start simulation in the background
-    simulation.start();
+    if let Some(simulation) = &local_simulation {
+        simulation.start();
+    }
 
     log::debug!("Starting render loop");
 
     let stop_flag = Arc::new(AtomicBool::new(false));
 
+    let render_config = RenderLoopConfig {
+        target_fps: Some(args.max_fps),
+        vsync: !args.disable_vsync,
+        skip_unchanged_frames: !args.always_redraw,
+    };
+
     let render_thread = {
         let graphics = graphics.clone();
         let simulation = simulation.clone();
@@ -137,6 +208,7 @@ async fn main() -> anyhow::Result<()> {
                     simulation,
                     scene_mgr,
                     stop_flag,
+                    render_config,
                 )
                 .await;
 
@@ -151,7 +223,28 @@ async fn main() -> anyhow::Result<()> {
     stop_flag.store(true, Ordering::SeqCst);
 
     let _ = render_thread.join();
-    simulation.stop();
+    if let Some(simulation) = &local_simulation {
+        simulation.stop();
+    }
+
+    if let Some(path) = session_path {
+        let window_size: LogicalSize<f64> = {
+            let geometry = graphics.get_renderer().get_geometry();
+            geometry.window_size.to_logical(geometry.scale_factor)
+        };
+        session.window_size = Some((window_size.width, window_size.height));
+
+        // Do not start paused next time
+        if let Some(rate_limit) = simulation.get_rate_limit().filter(|limit| *limit > 0) {
+            session.rate_limit = Some(rate_limit);
+        }
+
+        session.store_scenes(&scene_mgr);
+
+        if let Err(err) = session.save(&path) {
+            log::warn!("Failed to save GUI session: {err}");
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use glam::Vec2;
+
+use serde::{Deserialize, Serialize};
+
+use simba_visualizer::scene::SceneManager;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CameraState {
+    position: (f32, f32),
+    zoom: f32,
+}
+
+/// GUI state that is restored on the next launch
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// The logical size of the window
+    #[serde(default)]
+    pub window_size: Option<(f64, f64)>,
+
+    /// The last non-zero simulation speed
+    #[serde(default)]
+    pub rate_limit: Option<u32>,
+
+    #[serde(default)]
+    active_view: Option<String>,
+
+    /// Camera states by view name
+    #[serde(default)]
+    cameras: HashMap<String, CameraState>,
+}
+
+impl Session {
+    /// Where sessions are stored, if there is a config directory on this platform
+    pub fn get_default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("simba").join("gui-session.ron"))
+    }
+
+    /// Loads the session from the given file
+    ///
+    /// Falls back to an empty session if the file does not exist or is invalid
+    pub fn load(path: &Path) -> Self {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    log::warn!("Failed to read session file {path:?}: {err}");
+                }
+                return Self::default();
+            }
+        };
+
+        match ron::from_str(&content) {
+            Ok(session) => {
+                log::debug!("Restored GUI session from {path:?}");
+                session
+            }
+            Err(err) => {
+                log::warn!("Failed to parse session file {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {parent:?}"))?;
+        }
+
+        let content = ron::ser::to_string_pretty(self, Default::default())
+            .with_context(|| "Failed to serialize session")?;
+
+        std::fs::write(path, content).with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    /// Moves the cameras back to where they were and selects the previous view
+    pub fn restore_scenes(&self, scene_mgr: &SceneManager) {
+        for view_type in scene_mgr.get_view_types() {
+            let Some(state) = self.cameras.get(&view_type.to_string()) else {
+                continue;
+            };
+
+            let camera = scene_mgr
+                .get_camera(*view_type)
+                .expect("No camera for view");
+            camera.look_at(Vec2::new(state.position.0, state.position.1));
+            camera.set_zoom(state.zoom);
+        }
+
+        let Some(view_name) = &self.active_view else {
+            return;
+        };
+
+        let view_type = scene_mgr
+            .get_view_types()
+            .iter()
+            .find(|view_type| view_type.to_string() == *view_name);
+
+        if let Some(view_type) = view_type {
+            if *view_type != scene_mgr.get_active_scene_type() {
+                scene_mgr.set_active_scene(*view_type);
+            }
+        } else {
+            log::warn!("Cannot restore unknown view \"{view_name}\"");
+        }
+    }
+
+    /// Remembers the camera states and the active view
+    pub fn store_scenes(&mut self, scene_mgr: &SceneManager) {
+        for view_type in scene_mgr.get_view_types() {
+            let camera = scene_mgr
+                .get_camera(*view_type)
+                .expect("No camera for view");
+            let position = camera.get_position();
+
+            let state = CameraState {
+                position: (position.x, position.y),
+                zoom: camera.get_zoom(),
+            };
+            self.cameras.insert(view_type.to_string(), state);
+        }
+
+        self.active_view = Some(scene_mgr.get_active_scene_type().to_string());
+    }
+}
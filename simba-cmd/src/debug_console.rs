@@ -0,0 +1,161 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, bail};
+
+use simba::{NetworkMetricType, NodeIndex, Simulation};
+
+const HELP: &str = "Available commands:
+  pause               Stop advancing the simulation
+  resume [rate]       Continue the simulation (at the given rate limit, if any)
+  step [n]            Process the next n events (default: 1)
+  time                Print the current simulation time
+  mempool <node>      List the transactions in the mempool of a node
+  head <node>         Print the chain head as seen by a node
+  inject <node>       Create a new transaction at a node
+  status <node> <txn> Print the status of a transaction as seen by a node
+  crash <node>        Stop a node from processing messages or transactions
+  help                Show this message
+  quit                Stop the simulation and exit";
+
+/// An interactive console to inspect and manipulate a running simulation
+pub struct DebugConsole<'a> {
+    simulation: &'a Simulation,
+    num_nodes: NodeIndex,
+}
+
+impl<'a> DebugConsole<'a> {
+    /// Note: The simulation must already be running
+    pub fn new(simulation: &'a Simulation) -> Self {
+        let num_nodes = simulation.get_network_metric(NetworkMetricType::NumMiningNodes)
+            + simulation.get_network_metric(NetworkMetricType::NumNonMiningNodes);
+
+        Self {
+            simulation,
+            num_nodes: num_nodes as NodeIndex,
+        }
+    }
+
+    /// Reads commands from stdin until the user quits (or closes stdin)
+    pub fn run(&self) -> anyhow::Result<()> {
+        println!("Simulation is paused. Type \"help\" for a list of commands.");
+
+        let stdin = std::io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            print!("> ");
+            std::io::stdout().flush()?;
+
+            let Some(line) = lines.next() else {
+                break;
+            };
+
+            let line = line?;
+            let args: Vec<&str> = line.split_whitespace().collect();
+
+            let Some((command, args)) = args.split_first() else {
+                continue;
+            };
+
+            if *command == "quit" || *command == "exit" {
+                break;
+            }
+
+            if let Err(err) = self.handle_command(command, args) {
+                println!("Error: {err:#}");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_command(&self, command: &str, args: &[&str]) -> anyhow::Result<()> {
+        match command {
+            "help" => println!("{HELP}"),
+            "pause" => self.simulation.set_rate_limit(0),
+            "resume" => {
+                if let Some(rate_limit) = args.first() {
+                    let rate_limit = rate_limit.parse().context("Invalid rate limit")?;
+                    self.simulation.set_rate_limit(rate_limit);
+                } else {
+                    self.simulation.remove_rate_limit();
+                }
+            }
+            "step" => {
+                let num_events = if let Some(num_events) = args.first() {
+                    num_events.parse().context("Invalid number of events")?
+                } else {
+                    1
+                };
+
+                if self.simulation.get_rate_limit() != Some(0) {
+                    bail!("Simulation must be paused to step through it");
+                }
+
+                let time = self.simulation.step(num_events);
+                println!("Time is now {time}");
+            }
+            "time" => println!("{}", self.simulation.get_current_time()),
+            "mempool" => {
+                let node_idx = self.parse_node_index(args)?;
+                let mempool = self.simulation.get_mempool(node_idx);
+
+                println!(
+                    "Node #{node_idx} has {} pending transaction(s)",
+                    mempool.len()
+                );
+                for txn_id in mempool {
+                    println!("  {txn_id:#X}");
+                }
+            }
+            "head" => {
+                let node_idx = self.parse_node_index(args)?;
+                match self.simulation.get_chain_head(node_idx) {
+                    Some((block_id, height)) => {
+                        println!("Chain head is block #{block_id:#X} at height {height}");
+                    }
+                    None => println!("Protocol does not have a chain"),
+                }
+            }
+            "inject" => {
+                let node_idx = self.parse_node_index(args)?;
+                match self.simulation.inject_transaction(node_idx) {
+                    Some(txn_id) => println!("Created transaction {txn_id:#X}"),
+                    None => bail!("Node #{node_idx} has crashed or does not accept transactions"),
+                }
+            }
+            "status" => {
+                let node_idx = self.parse_node_index(args)?;
+                let Some(txn_id) = args.get(1) else {
+                    bail!("Missing transaction id");
+                };
+
+                let txn_id = u128::from_str_radix(txn_id.trim_start_matches("0x"), 16)
+                    .context("Invalid transaction id")?;
+                let status = self.simulation.get_transaction_status(node_idx, txn_id);
+                println!("{status:?}");
+            }
+            "crash" => {
+                let node_idx = self.parse_node_index(args)?;
+                self.simulation.crash_node(node_idx);
+                println!("Node #{node_idx} crashed");
+            }
+            _ => bail!("Unknown command \"{command}\". Type \"help\" for a list of commands."),
+        }
+
+        Ok(())
+    }
+
+    fn parse_node_index(&self, args: &[&str]) -> anyhow::Result<NodeIndex> {
+        let Some(node_idx) = args.first() else {
+            bail!("Missing node index");
+        };
+
+        let node_idx: NodeIndex = node_idx.parse().context("Invalid node index")?;
+        if node_idx >= self.num_nodes {
+            bail!("No node with index {node_idx}");
+        }
+
+        Ok(node_idx)
+    }
+}
@@ -1,19 +1,176 @@
-use simba::{EndlessRunner, ExperimentRunner, Library, TestRunner};
+use simba::{
+    BUILTIN_PRESETS, Duration, EndlessRunner, ExperimentRunner, Library, NodeIndex, ScenarioRunner,
+    SeedSweepRunner, TestRunner, TimeoutConfig, get_json_schema, run_calibration, serve_remote,
+};
 
-use clap::Parser;
+use std::ffi::OsStr;
+use std::net::TcpListener;
+use std::ops::RangeInclusive;
+
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, Parser};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::env::CompleteEnv;
+
+use anyhow::{Context, bail};
 
 use tracing_flame::FlameLayer;
 use tracing_subscriber::{filter::EnvFilter, prelude::*};
 
-fn setup_tracing() -> impl Drop {
+mod debug_console;
+use debug_console::DebugConsole;
+
+mod node_logs;
+use node_logs::NodeLogLayer;
+
+/// Converts a filter of the form `node=N` into a directive
+/// that only keeps spans (and their children) of the specified node
+fn parse_trace_filter(filter: &str) -> anyhow::Result<String> {
+    let Some((key, value)) = filter.split_once('=') else {
+        bail!("Invalid trace filter \"{filter}\". Expected \"key=value\"");
+    };
+
+    match key {
+        "node" => {
+            let node_idx: NodeIndex = value
+                .parse()
+                .with_context(|| format!("Invalid node index \"{value}\""))?;
+            Ok(format!("[{{node={node_idx}}}]=trace"))
+        }
+        _ => bail!("Unknown trace filter key \"{key}\""),
+    }
+}
+
+/// Parses a range of seeds, e.g., `1..50` (exclusive), `1..=50` (inclusive), or just `7`
+fn parse_seed_range(range: &str) -> anyhow::Result<RangeInclusive<u64>> {
+    let parse_seed = |seed: &str| -> anyhow::Result<u64> {
+        seed.trim()
+            .parse()
+            .with_context(|| format!("Invalid seed \"{seed}\""))
+    };
+
+    if let Some((start, end)) = range.split_once("..=") {
+        Ok(parse_seed(start)?..=parse_seed(end)?)
+    } else if let Some((start, end)) = range.split_once("..") {
+        let end = parse_seed(end)?;
+        if end == 0 {
+            bail!("Seed range \"{range}\" is empty");
+        }
+        Ok(parse_seed(start)?..=(end - 1))
+    } else {
+        let seed = parse_seed(range)?;
+        Ok(seed..=seed)
+    }
+}
+
+/// Log to stderr, but also write everything emitted by a node to its own log file
+fn setup_node_logs(directory: &str) -> anyhow::Result<()> {
+    let node_log_layer = NodeLogLayer::new(directory)
+        .with_context(|| format!("Failed to create log directory \"{directory}\""))?;
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(node_log_layer)
+        .init();
+    Ok(())
+}
+
+fn setup_tracing(trace_filter: Option<&str>) -> anyhow::Result<impl Drop> {
     let fmt_layer = EnvFilter::from_default_env();
     let (flame_layer, _guard) = FlameLayer::with_file("./simba-trace.folded").unwrap();
 
+    let flame_filter = if let Some(trace_filter) = trace_filter {
+        let directive = parse_trace_filter(trace_filter)?;
+        Some(EnvFilter::try_new(directive)?)
+    } else {
+        None
+    };
+
     tracing_subscriber::registry()
         .with(fmt_layer)
-        .with(flame_layer)
+        .with(flame_layer.with_filter(flame_filter))
         .init();
-    _guard
+    Ok(_guard)
+}
+
+/// Where shell completions look for library entries
+/// Completion happens before arguments are parsed, so `--library-path` is not taken into account
+const DEFAULT_LIBRARY_PATH: &str = "./library";
+
+/// Suggests the library entries returned by `get_names` that start with `current`
+fn complete_library_entries(
+    current: &OsStr,
+    get_names: fn(&Library) -> Vec<&str>,
+) -> Vec<CompletionCandidate> {
+    let Ok(library) = Library::new(DEFAULT_LIBRARY_PATH) else {
+        return vec![];
+    };
+    let prefix = current.to_string_lossy();
+
+    get_names(&library)
+        .into_iter()
+        .filter(|name| name.starts_with(prefix.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+fn complete_networks(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_library_entries(current, Library::get_network_names)
+}
+
+fn complete_protocols(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_library_entries(current, Library::get_protocol_names)
+}
+
+fn complete_experiments(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_library_entries(current, Library::get_experiment_names)
+}
+
+fn complete_tests(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_library_entries(current, Library::get_test_names)
+}
+
+fn complete_scenarios(current: &OsStr) -> Vec<CompletionCandidate> {
+    complete_library_entries(current, Library::get_scenario_names)
+}
+
+/// Opens the library to run from, and returns it with the names of the network and protocol
+/// Presets consist of a network and protocol of the same name
+fn select_setup(
+    library_path: &str,
+    builtin: Option<String>,
+    network_name: Option<String>,
+    protocol_name: Option<String>,
+) -> anyhow::Result<(Library, String, String)> {
+    if let Some(name) = builtin {
+        return Ok((Library::builtin()?, name.clone(), name));
+    }
+
+    let network_name = network_name.context("No network given")?;
+    let protocol_name = protocol_name.context("No protocol given")?;
+    Ok((Library::new(library_path)?, network_name, protocol_name))
+}
+
+/// How list commands print library entries
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+enum ListFormat {
+    /// A message for humans
+    #[default]
+    Text,
+    /// A JSON array of names, for scripts
+    Json,
+}
+
+impl ListFormat {
+    /// `kind` describes the entries in the text format, e.g., "networks"
+    fn print(&self, kind: &str, names: Vec<&str>) -> anyhow::Result<()> {
+        match self {
+            Self::Text => print!("Found {kind}: {names:?}"),
+            Self::Json => println!("{}", serde_json::to_string(&names)?),
+        }
+        Ok(())
+    }
 }
 
 #[derive(Parser)]
@@ -30,6 +187,14 @@ struct Args {
     #[clap(help = "Should we collect tracing data?")]
     enable_tracing: bool,
 
+    #[clap(long, global = true, requires = "enable_tracing")]
+    #[clap(help = "Only trace a specific simulated entity, e.g., \"node=5\"")]
+    trace_filter: Option<String>,
+
+    #[clap(long, global = true, conflicts_with = "enable_tracing")]
+    #[clap(help = "Write the log output of each node to a separate file in this directory")]
+    node_logs: Option<String>,
+
     #[clap(long, short = 'j', global = true, required = false)]
     #[clap(help = "How many concurrent tasks? Will be the number of cores by default")]
     parallelism: Option<usize>,
@@ -53,33 +218,144 @@ enum Mode {
     #[clap(about = "Run any combination of network and protocol")]
     Endless {
         #[clap(help = "The name of network topology to use")]
-        network_name: String,
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_networks))]
+        network_name: Option<String>,
         #[clap(help = "The name of protocol to use")]
-        protocol_name: String,
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_protocols))]
+        protocol_name: Option<String>,
+        #[clap(long, value_parser = PossibleValuesParser::new(BUILTIN_PRESETS))]
+        #[clap(conflicts_with_all = ["network_name", "protocol_name"])]
+        #[clap(help = "Run a preset that ships with SimBA instead of an entry of the library")]
+        builtin: Option<String>,
         #[clap(
             long,
             short = 'o',
             help = "Overwrite parameter of the network or protocol"
         )]
         overwrite: Vec<String>,
+        #[clap(
+            long,
+            help = "Write all blocks to a .dot or .graphml file when the simulation is stopped"
+        )]
+        export_block_graph: Option<String>,
+        #[clap(
+            long,
+            help = "Log block rate, throughput, and mempool size every N (virtual) minutes"
+        )]
+        summary_interval: Option<u64>,
+        #[clap(long, help = "Stop after N (virtual) hours")]
+        hours: Option<u64>,
+        #[clap(
+            long,
+            conflicts_with = "hours",
+            help = "Stop once the longest chain has N blocks"
+        )]
+        blocks: Option<u64>,
+        #[clap(
+            long,
+            conflicts_with_all = ["export_block_graph", "summary_interval"],
+            help = "Run once for every seed in a range, e.g., \"1..50\", and aggregate the results. Requires --hours or --blocks"
+        )]
+        seeds: Option<String>,
+    },
+    #[clap(about = "Step through a simulation and inspect it interactively")]
+    Debug {
+        #[clap(help = "The name of network topology to use")]
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_networks))]
+        network_name: Option<String>,
+        #[clap(help = "The name of protocol to use")]
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_protocols))]
+        protocol_name: Option<String>,
+        #[clap(long, value_parser = PossibleValuesParser::new(BUILTIN_PRESETS))]
+        #[clap(conflicts_with_all = ["network_name", "protocol_name"])]
+        #[clap(help = "Run a preset that ships with SimBA instead of an entry of the library")]
+        builtin: Option<String>,
+    },
+    #[clap(about = "Run a simulation that a remote visualizer can connect to")]
+    Serve {
+        #[clap(help = "The name of network topology to use")]
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_networks))]
+        network_name: Option<String>,
+        #[clap(help = "The name of protocol to use")]
+        #[clap(required_unless_present = "builtin")]
+        #[clap(add = ArgValueCompleter::new(complete_protocols))]
+        protocol_name: Option<String>,
+        #[clap(long, value_parser = PossibleValuesParser::new(BUILTIN_PRESETS))]
+        #[clap(conflicts_with_all = ["network_name", "protocol_name"])]
+        #[clap(help = "Run a preset that ships with SimBA instead of an entry of the library")]
+        builtin: Option<String>,
+        #[clap(long, default_value = "127.0.0.1:7878")]
+        #[clap(help = "The address to wait for the visualizer on")]
+        address: String,
     },
     Test {
         #[clap(help = "The name of the test to run")]
+        #[clap(add = ArgValueCompleter::new(complete_tests))]
         test_name: String,
+        #[clap(long, help = "Record the results of this run as the new baseline")]
+        update_baseline: bool,
+        #[clap(long, help = "Write a summary of the run to a .md or .html file")]
+        report: Option<String>,
+    },
+    #[clap(about = "Run or inspect failure scenarios")]
+    Scenario {
+        #[clap(subcommand)]
+        command: ScenarioCommand,
+    },
+    #[clap(about = "Validate the network model against analytic expectations")]
+    Calibrate {
+        #[clap(
+            long,
+            default_value = "0.05",
+            help = "The maximum relative deviation from the expected values"
+        )]
+        tolerance: f64,
+    },
+    #[clap(about = "Print the JSON schema of the records consumed by external tools")]
+    Schema,
+    #[clap(about = "Lists all network topologies")]
+    ListNetworks {
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+    #[clap(about = "Lists all protocols")]
+    ListProtocols {
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum ScenarioCommand {
+    #[clap(about = "Run a scenario and check all of its checkpoints")]
+    Run {
+        #[clap(help = "The name of the scenario to run")]
+        #[clap(add = ArgValueCompleter::new(complete_scenarios))]
+        scenario_name: String,
+    },
+    #[clap(about = "Lists all scenarios")]
+    List {
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
     },
-    ListNetworks,
-    ListProtocols,
 }
 
 #[derive(clap::Subcommand)]
 enum ExpCommand {
     CountSteps {
         #[clap(help = "The name of the experiment to inspect")]
+        #[clap(add = ArgValueCompleter::new(complete_experiments))]
         experiment_name: String,
     },
     #[clap(about = "Run an experiment and output a CSV file")]
     Run {
         #[clap(help = "The name of the experiment to run")]
+        #[clap(add = ArgValueCompleter::new(complete_experiments))]
         experiment_name: String,
         #[clap(long, help = "Writes all messages to a messages.log file")]
         log_messages: bool,
@@ -87,6 +363,7 @@ enum ExpCommand {
     #[clap(about = "Run a single step of an experiment and output a CSV file")]
     SingleStep {
         #[clap(help = "The name of the experiment to run")]
+        #[clap(add = ArgValueCompleter::new(complete_experiments))]
         experiment_name: String,
         #[clap(help = "The index of the step")]
         index: usize,
@@ -94,14 +371,23 @@ enum ExpCommand {
         log_messages: bool,
     },
     #[clap(about = "Lists all experiments")]
-    List,
+    List {
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
+    // Answers completion requests from the shell, e.g., if `COMPLETE=bash` is set
+    CompleteEnv::with_factory(Args::command).complete();
+
     let args = Args::parse();
 
     let _tracing_guard = if args.enable_tracing {
-        Some(setup_tracing())
+        Some(setup_tracing(args.trace_filter.as_deref())?)
+    } else if let Some(directory) = &args.node_logs {
+        setup_node_logs(directory)?;
+        None
     } else {
         env_logger::init();
         None
@@ -172,16 +458,24 @@ fn main() -> anyhow::Result<()> {
                 #[cfg(feature = "cpuprofiler")]
                 cpuprofiler::PROFILER.lock().unwrap().stop().unwrap();
             }
-            ExpCommand::List => {
+            ExpCommand::List { format } => {
                 let library = Library::new(&args.library_path)?;
-                print!("Found experiments: {:?}", library.get_experiment_names());
+                format.print("experiments", library.get_experiment_names())?;
             }
         },
         Mode::Endless {
             network_name,
             protocol_name,
+            builtin,
             overwrite,
+            export_block_graph,
+            summary_interval,
+            hours,
+            blocks,
+            seeds,
         } => {
+            let (library, network_name, protocol_name) =
+                select_setup(&args.library_path, builtin, network_name, protocol_name)?;
             let mut overwrites = vec![];
 
             for arg in overwrite {
@@ -202,19 +496,133 @@ fn main() -> anyhow::Result<()> {
                 overwrites.push((param, value));
             }
 
-            let runner = EndlessRunner::new(
-                &args.library_path,
+            let timeout = if let Some(hours) = hours {
+                Some(TimeoutConfig::Seconds {
+                    warmup: 0,
+                    runtime: hours * 3600,
+                })
+            } else {
+                blocks.map(|blocks| TimeoutConfig::Blocks {
+                    warmup: 0,
+                    runtime: blocks,
+                })
+            };
+
+            if let Some(seeds) = seeds {
+                let Some(timeout) = timeout else {
+                    bail!("Seed sweeps need a timeout; set either --hours or --blocks");
+                };
+
+                let mut runner = SeedSweepRunner::new(
+                    &library,
+                    &network_name,
+                    &protocol_name,
+                    None,
+                    overwrites,
+                    parse_seed_range(&seeds)?,
+                    timeout,
+                )?;
+
+                if let Some(parallelism) = args.parallelism {
+                    runner = runner.with_parallelism(parallelism);
+                }
+
+                for summary in runner.run()? {
+                    println!(
+                        "{}: mean {:.3}, std. dev. {:.3}, min {:.3}, max {:.3}",
+                        summary.metric, summary.mean, summary.std_dev, summary.min, summary.max
+                    );
+                }
+
+                return Ok(());
+            }
+
+            let mut runner = EndlessRunner::new(
+                &library,
                 &network_name,
                 &protocol_name,
                 None,
                 overwrites,
                 stats_file,
+                export_block_graph,
             )?;
 
+            if let Some(minutes) = summary_interval {
+                runner = runner.with_summary_interval(Duration::from_seconds(minutes * 60));
+            }
+
+            if let Some(timeout) = timeout {
+                runner = runner.with_timeout(timeout)?;
+            }
+
             runner.run_until_ctrlc();
         }
-        Mode::Test { test_name } => {
-            let runner = match TestRunner::new(&args.library_path, &test_name, stats_file) {
+        Mode::Debug {
+            network_name,
+            protocol_name,
+            builtin,
+        } => {
+            let (library, network_name, protocol_name) =
+                select_setup(&args.library_path, builtin, network_name, protocol_name)?;
+            let runner = EndlessRunner::new(
+                &library,
+                &network_name,
+                &protocol_name,
+                None,
+                vec![],
+                stats_file,
+                None,
+            )?;
+
+            // Start paused so that the user can step through the simulation
+            let simulation = runner.get_simulation();
+            simulation.set_rate_limit(0);
+            runner.start();
+
+            DebugConsole::new(simulation).run()?;
+            runner.stop();
+        }
+        Mode::Serve {
+            network_name,
+            protocol_name,
+            builtin,
+            address,
+        } => {
+            let (library, network_name, protocol_name) =
+                select_setup(&args.library_path, builtin, network_name, protocol_name)?;
+            let runner = EndlessRunner::new(
+                &library,
+                &network_name,
+                &protocol_name,
+                None,
+                vec![],
+                stats_file,
+                None,
+            )?;
+
+            let listener = TcpListener::bind(&address)
+                .with_context(|| format!("Failed to listen on {address}"))?;
+            log::info!("Waiting for a visualizer to connect to {address}");
+
+            // The visualizer sets the speed once it is connected
+            let simulation = runner.get_simulation();
+            simulation.set_rate_limit(0);
+
+            // The simulation starts once the visualizer is connected and stops when it disconnects
+            serve_remote(simulation, &listener)?;
+        }
+        Mode::Test {
+            test_name,
+            update_baseline,
+            report,
+        } => {
+            let runner = match TestRunner::new(
+                &args.library_path,
+                &test_name,
+                stats_file,
+                update_baseline,
+                report,
+            ) {
                 Ok(runner) => runner,
                 Err(err) => {
                     log::error!("Failed to run test: {err}");
@@ -236,13 +644,55 @@ fn main() -> anyhow::Result<()> {
                 std::process::exit(1);
             }
         }
-        Mode::ListNetworks => {
+        Mode::Scenario { command } => match command {
+            ScenarioCommand::Run { scenario_name } => {
+                let runner = ScenarioRunner::new(&args.library_path, &scenario_name, stats_file)?;
+
+                if !runner.run() {
+                    std::process::exit(1);
+                }
+            }
+            ScenarioCommand::List { format } => {
+                let library = Library::new(&args.library_path)?;
+                format.print("scenarios", library.get_scenario_names())?;
+            }
+        },
+        Mode::Calibrate { tolerance } => {
+            let mut success = true;
+
+            for result in run_calibration()? {
+                let deviation = result.get_deviation();
+                let msg = format!(
+                    "{} of \"{}\" was {:.2}, expected {:.2} (deviation {:.2}%)",
+                    result.quantity,
+                    result.benchmark,
+                    result.measured,
+                    result.expected,
+                    deviation * 100.0
+                );
+
+                if deviation > tolerance {
+                    log::error!("{msg}");
+                    success = false;
+                } else {
+                    log::info!("{msg}");
+                }
+            }
+
+            if !success {
+                std::process::exit(1);
+            }
+        }
+        Mode::Schema => {
+            println!("{}", get_json_schema());
+        }
+        Mode::ListNetworks { format } => {
             let library = Library::new(&args.library_path)?;
-            print!("Found networks: {:?}", library.get_network_names());
+            format.print("networks", library.get_network_names())?;
         }
-        Mode::ListProtocols => {
+        Mode::ListProtocols { format } => {
             let library = Library::new(&args.library_path)?;
-            print!("Found protocols: {:?}", library.get_protocol_names());
+            format.print("protocols", library.get_protocol_names())?;
         }
     }
 
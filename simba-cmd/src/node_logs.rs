@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use simba::NodeIndex;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Writes all records emitted while a node's logic is executing
+/// to a separate file for that node
+///
+/// Files are line-buffered, because the global subscriber is never dropped
+pub struct NodeLogLayer {
+    directory: PathBuf,
+    files: Mutex<HashMap<NodeIndex, LineWriter<File>>>,
+}
+
+impl NodeLogLayer {
+    pub fn new(directory: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            files: Default::default(),
+        })
+    }
+}
+
+/// Extracts the message (and original target for records from the `log` crate)
+#[derive(Default)]
+struct RecordVisitor {
+    message: String,
+    target: Option<String>,
+    fields: String,
+}
+
+impl Visit for RecordVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "log.target" {
+            self.target = Some(value.to_string());
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.message, "{value:?}");
+            }
+            // Other metadata of `log` records that is already part of the output
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for NodeLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(node_idx) = simba::current_node() else {
+            return;
+        };
+
+        let mut visitor = RecordVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let target = visitor.target.as_deref().unwrap_or(metadata.target());
+
+        let mut files = self.files.lock().unwrap();
+        let file = match files.entry(node_idx) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.directory.join(format!("node-{node_idx}.log"));
+                match File::create(&path) {
+                    Ok(file) => entry.insert(LineWriter::new(file)),
+                    Err(err) => {
+                        eprintln!("Failed to create log file {path:?}: {err}");
+                        return;
+                    }
+                }
+            }
+        };
+
+        let result = writeln!(
+            file,
+            "[{} {target}] {}{}",
+            metadata.level(),
+            visitor.message,
+            visitor.fields
+        );
+
+        if let Err(err) = result {
+            eprintln!("Failed to write log record for node #{node_idx}: {err}");
+        }
+    }
+}
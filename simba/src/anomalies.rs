@@ -0,0 +1,209 @@
+//! Flags interesting moments in the statistics stream
+//!
+//! Long runs produce a lot of data, so the detector annotates data points
+//! where throughput drops, deep reorgs happen, or blocks propagate unusually slowly.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stats::GlobalStatistics;
+
+/// When a data point is considered an anomaly
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyThresholds {
+    /// How far throughput has to fall below its recent average (in percent)
+    pub throughput_drop: u32,
+    /// Reorgs of at least this depth are reported
+    pub reorg_depth: u64,
+    /// How far the block delay has to rise above its recent average (in percent)
+    pub propagation_spike: u32,
+    /// How many data points (seconds) the recent average is computed over
+    pub window: usize,
+}
+
+impl Default for AnomalyThresholds {
+    fn default() -> Self {
+        Self {
+            throughput_drop: 50,
+            reorg_depth: 3,
+            propagation_spike: 200,
+            window: 10,
+        }
+    }
+}
+
+/// Something unusual that happened during the last second
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Anomaly {
+    /// Committed transactions per second fell below the recent average
+    ThroughputDrop { average: u64, current: u64 },
+    /// A node switched to a fork that replaced this many blocks
+    DeepReorg { depth: u64 },
+    /// A block took much longer than usual to reach a node (in milliseconds)
+    PropagationSpike { average: u64, current: u64 },
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::ThroughputDrop { average, current } => {
+                write!(fmt, "Throughput dropped from {average} to {current} txns/s")
+            }
+            Self::DeepReorg { depth } => write!(fmt, "Reorg of depth {depth}"),
+            Self::PropagationSpike { average, current } => {
+                write!(fmt, "Block delay rose from {average}ms to {current}ms")
+            }
+        }
+    }
+}
+
+/// Compares each data point against the recent history
+#[derive(Default)]
+pub(crate) struct AnomalyDetector {
+    thresholds: AnomalyThresholds,
+    throughput: VecDeque<u64>,
+    /// Only contains seconds in which a node received a block
+    block_delays: VecDeque<u64>,
+    /// Set while throughput stays low, so a drop is only reported once
+    throughput_dropped: bool,
+}
+
+impl AnomalyDetector {
+    pub fn set_thresholds(&mut self, thresholds: AnomalyThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Forget the history, e.g., after warmup
+    pub fn reset(&mut self) {
+        self.throughput.clear();
+        self.block_delays.clear();
+        self.throughput_dropped = false;
+    }
+
+    /// Returns all anomalies found in the given data point
+    pub fn check(&mut self, stats: &GlobalStatistics) -> Vec<Anomaly> {
+        let mut anomalies = vec![];
+
+        if let Some(average) = self.get_average(&self.throughput) {
+            let limit = average * (100.0 - self.thresholds.throughput_drop as f64) / 100.0;
+            let dropped = (stats.committed_transactions as f64) < limit;
+
+            if dropped && !self.throughput_dropped {
+                anomalies.push(Anomaly::ThroughputDrop {
+                    average: average.round() as u64,
+                    current: stats.committed_transactions,
+                });
+            }
+            self.throughput_dropped = dropped;
+        }
+        Self::push(
+            &mut self.throughput,
+            stats.committed_transactions,
+            self.thresholds.window,
+        );
+
+        if stats.max_reorg_depth >= self.thresholds.reorg_depth {
+            anomalies.push(Anomaly::DeepReorg {
+                depth: stats.max_reorg_depth,
+            });
+        }
+
+        if stats.max_block_delay > 0 {
+            let factor = (100.0 + self.thresholds.propagation_spike as f64) / 100.0;
+
+            if let Some(average) = self.get_average(&self.block_delays)
+                && (stats.max_block_delay as f64) > average * factor
+            {
+                anomalies.push(Anomaly::PropagationSpike {
+                    average: average.round() as u64,
+                    current: stats.max_block_delay,
+                });
+            }
+            Self::push(
+                &mut self.block_delays,
+                stats.max_block_delay,
+                self.thresholds.window,
+            );
+        }
+
+        anomalies
+    }
+
+    /// The average of the history, or `None` if it is not full yet (or all zero)
+    fn get_average(&self, history: &VecDeque<u64>) -> Option<f64> {
+        if history.is_empty() || history.len() < self.thresholds.window {
+            return None;
+        }
+
+        let average = history.iter().sum::<u64>() as f64 / history.len() as f64;
+        (average > 0.0).then_some(average)
+    }
+
+    fn push(history: &mut VecDeque<u64>, value: u64, window: usize) {
+        history.push_back(value);
+        while history.len() > window {
+            history.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anomaly, AnomalyDetector};
+    use crate::stats::GlobalStatistics;
+
+    #[test]
+    fn throughput_drop() {
+        let mut detector = AnomalyDetector::default();
+
+        let normal = GlobalStatistics {
+            committed_transactions: 100,
+            ..Default::default()
+        };
+        for _ in 0..10 {
+            assert!(detector.check(&normal).is_empty());
+        }
+
+        let low = GlobalStatistics {
+            committed_transactions: 20,
+            ..Default::default()
+        };
+        assert_eq!(detector.check(&low), vec![Anomaly::ThroughputDrop {
+            average: 100,
+            current: 20,
+        }]);
+
+        // Only reported once while throughput stays low
+        assert!(detector.check(&low).is_empty());
+    }
+
+    #[test]
+    fn reorgs_and_delays() {
+        let mut detector = AnomalyDetector::default();
+
+        for _ in 0..10 {
+            let stats = GlobalStatistics {
+                max_block_delay: 200,
+                max_reorg_depth: 1,
+                ..Default::default()
+            };
+            assert!(detector.check(&stats).is_empty());
+        }
+
+        let stats = GlobalStatistics {
+            max_block_delay: 1000,
+            max_reorg_depth: 4,
+            ..Default::default()
+        };
+        assert_eq!(detector.check(&stats), vec![
+            Anomaly::DeepReorg { depth: 4 },
+            Anomaly::PropagationSpike {
+                average: 200,
+                current: 1000,
+            },
+        ]);
+    }
+}
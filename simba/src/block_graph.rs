@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use serde::{Deserialize, Serialize};
+
+use crate::logic::{AccountId, BlockId, TransactionId};
+
+/// A block in the block DAG
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockGraphNode {
+    pub identifier: BlockId,
+    /// `None` for the genesis block
+    pub parent: Option<BlockId>,
+    pub uncles: Vec<BlockId>,
+    pub height: u64,
+    pub num_transactions: usize,
+    /// Is this block part of the longest chain?
+    pub in_longest_chain: bool,
+}
+
+/// Everything known about a single block, including its transactions
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDetails {
+    pub identifier: BlockId,
+    /// `None` for the genesis block
+    pub parent: Option<BlockId>,
+    pub uncles: Vec<BlockId>,
+    pub height: u64,
+    /// The account that mined the block (`None` for the genesis block)
+    pub miner: Option<AccountId>,
+    /// Identifier and size (in bytes) of each transaction
+    pub transactions: Vec<(TransactionId, u64)>,
+}
+
+/// The file formats a block graph can be exported to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlockGraphFormat {
+    Dot,
+    GraphMl,
+}
+
+impl BlockGraphFormat {
+    /// Picks the format based on the file extension (.dot, .gv, or .graphml)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "dot" | "gv" => Some(Self::Dot),
+            "graphml" => Some(Self::GraphMl),
+            _ => None,
+        }
+    }
+}
+
+/// All blocks created during a simulation, including forks and uncles
+///
+/// Protocols without blocks produce an empty graph
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockGraph {
+    /// Sorted by height
+    pub blocks: Vec<BlockGraphNode>,
+}
+
+impl BlockGraph {
+    /// Writes the graph to the given file
+    /// The format is determined by the file extension
+    pub fn write_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = match BlockGraphFormat::from_path(path) {
+            Some(BlockGraphFormat::Dot) => self.to_dot(),
+            Some(BlockGraphFormat::GraphMl) => self.to_graphml(),
+            None => anyhow::bail!("Unknown block graph format for {path:?}"),
+        };
+
+        std::fs::write(path, content).with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    /// Generates a Graphviz graph, with blocks of the same height aligned
+    ///
+    /// Edges point from a block to its parent (solid) and uncles (dashed).
+    /// Blocks of the longest chain are filled.
+    pub fn to_dot(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "digraph blocks {{").unwrap();
+        writeln!(output, "  rankdir=RL;").unwrap();
+        writeln!(output, "  node [shape=box];").unwrap();
+
+        let mut heights: BTreeMap<u64, Vec<BlockId>> = BTreeMap::new();
+
+        for block in self.blocks.iter() {
+            let style = if block.in_longest_chain {
+                ", style=filled, fillcolor=lightblue"
+            } else {
+                ""
+            };
+
+            writeln!(
+                output,
+                "  \"{:X}\" [label=\"{}\\nheight={}\\ntxs={}\"{style}];",
+                block.identifier,
+                Self::short_id(block.identifier),
+                block.height,
+                block.num_transactions
+            )
+            .unwrap();
+
+            if let Some(parent) = block.parent {
+                writeln!(output, "  \"{:X}\" -> \"{parent:X}\";", block.identifier).unwrap();
+            }
+
+            for uncle in block.uncles.iter() {
+                writeln!(
+                    output,
+                    "  \"{:X}\" -> \"{uncle:X}\" [style=dashed];",
+                    block.identifier
+                )
+                .unwrap();
+            }
+
+            heights
+                .entry(block.height)
+                .or_default()
+                .push(block.identifier);
+        }
+
+        for blocks in heights.values().filter(|blocks| blocks.len() > 1) {
+            let nodes: Vec<_> = blocks.iter().map(|id| format!("\"{id:X}\"")).collect();
+            writeln!(output, "  {{ rank=same; {}; }}", nodes.join("; ")).unwrap();
+        }
+
+        writeln!(output, "}}").unwrap();
+        output
+    }
+
+    /// Generates a GraphML document, which most graph tools can import
+    pub fn to_graphml(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+        writeln!(
+            output,
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "  <key id=\"height\" for=\"node\" attr.name=\"height\" attr.type=\"long\"/>"
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "  <key id=\"txs\" for=\"node\" attr.name=\"num_transactions\" attr.type=\"long\"/>"
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "  <key id=\"main\" for=\"node\" attr.name=\"in_longest_chain\" attr.type=\"boolean\"/>"
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>"
+        )
+        .unwrap();
+        writeln!(output, "  <graph id=\"blocks\" edgedefault=\"directed\">").unwrap();
+
+        for block in self.blocks.iter() {
+            writeln!(output, "    <node id=\"{:X}\">", block.identifier).unwrap();
+            writeln!(output, "      <data key=\"height\">{}</data>", block.height).unwrap();
+            writeln!(
+                output,
+                "      <data key=\"txs\">{}</data>",
+                block.num_transactions
+            )
+            .unwrap();
+            writeln!(
+                output,
+                "      <data key=\"main\">{}</data>",
+                block.in_longest_chain
+            )
+            .unwrap();
+            writeln!(output, "    </node>").unwrap();
+        }
+
+        for block in self.blocks.iter() {
+            let parents = block.parent.iter().map(|parent| (parent, "parent"));
+            let uncles = block.uncles.iter().map(|uncle| (uncle, "uncle"));
+
+            for (target, kind) in parents.chain(uncles) {
+                writeln!(
+                    output,
+                    "    <edge source=\"{:X}\" target=\"{target:X}\"><data key=\"kind\">{kind}</data></edge>",
+                    block.identifier
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(output, "  </graph>").unwrap();
+        writeln!(output, "</graphml>").unwrap();
+        output
+    }
+
+    /// The first eight hex digits of a block id (enough to tell blocks apart visually)
+    fn short_id(identifier: BlockId) -> String {
+        let mut id = format!("{identifier:X}");
+        id.truncate(8);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{BlockGraph, BlockGraphFormat, BlockGraphNode};
+
+    fn make_graph() -> BlockGraph {
+        let genesis = BlockGraphNode {
+            identifier: 0,
+            parent: None,
+            uncles: vec![],
+            height: 0,
+            num_transactions: 0,
+            in_longest_chain: true,
+        };
+        let fork = BlockGraphNode {
+            identifier: 0xA,
+            parent: Some(0),
+            uncles: vec![],
+            height: 1,
+            num_transactions: 2,
+            in_longest_chain: false,
+        };
+        let main = BlockGraphNode {
+            identifier: 0xB,
+            parent: Some(0),
+            uncles: vec![],
+            height: 1,
+            num_transactions: 3,
+            in_longest_chain: true,
+        };
+        let head = BlockGraphNode {
+            identifier: 0xC,
+            parent: Some(0xB),
+            uncles: vec![0xA],
+            height: 2,
+            num_transactions: 1,
+            in_longest_chain: true,
+        };
+
+        BlockGraph {
+            blocks: vec![genesis, fork, main, head],
+        }
+    }
+
+    #[test]
+    fn export_dot() {
+        let dot = make_graph().to_dot();
+
+        assert!(dot.starts_with("digraph blocks {"));
+        assert!(dot.contains("\"C\" -> \"B\";"));
+        assert!(dot.contains("\"C\" -> \"A\" [style=dashed];"));
+        assert!(dot.contains("{ rank=same; \"A\"; \"B\"; }"));
+        assert_eq!(dot.matches("fillcolor").count(), 3);
+    }
+
+    #[test]
+    fn export_graphml() {
+        let graphml = make_graph().to_graphml();
+
+        assert_eq!(graphml.matches("<node ").count(), 4);
+        assert!(
+            graphml
+                .contains("<edge source=\"C\" target=\"A\"><data key=\"kind\">uncle</data></edge>")
+        );
+        assert!(
+            graphml.contains(
+                "<edge source=\"A\" target=\"0\"><data key=\"kind\">parent</data></edge>"
+            )
+        );
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            BlockGraphFormat::from_path(Path::new("chain.dot")),
+            Some(BlockGraphFormat::Dot)
+        );
+        assert_eq!(
+            BlockGraphFormat::from_path(Path::new("chain.graphml")),
+            Some(BlockGraphFormat::GraphMl)
+        );
+        assert_eq!(BlockGraphFormat::from_path(Path::new("chain.txt")), None);
+    }
+}
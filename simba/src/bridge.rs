@@ -0,0 +1,84 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use asim::time::Duration;
+
+use crate::clients::Client;
+use crate::logic::{AccountId, Transfer};
+use crate::random;
+
+/// Relays transactions from one chain to another
+///
+/// A transfer first locks assets on the source chain by moving them to the
+/// bridge's escrow account. Once that transaction is committed, the bridge
+/// releases the same amount from its reserve on the destination chain.
+pub struct Bridge {
+    /// Locks assets on the source chain
+    source: Rc<Client>,
+    /// Holds the bridge's reserve on the destination chain
+    destination: Rc<Client>,
+    /// The account on the source chain that holds all locked assets
+    escrow: AccountId,
+    /// The account on the destination chain that receives all released assets
+    recipient: AccountId,
+    amount: u64,
+    transfer_interval: Duration,
+    latencies: RefCell<Vec<Duration>>,
+}
+
+impl Bridge {
+    pub(crate) fn new(
+        source: Rc<Client>,
+        destination: Rc<Client>,
+        amount: u64,
+        transfer_interval: Duration,
+    ) -> Self {
+        Self {
+            source,
+            destination,
+            escrow: random::random(),
+            recipient: random::random(),
+            amount,
+            transfer_interval,
+            latencies: Default::default(),
+        }
+    }
+
+    pub(crate) async fn run(&self) {
+        loop {
+            if self.source.get_node().is_crashed() || self.destination.get_node().is_crashed() {
+                log::trace!("Bridge endpoint crashed; will not relay more transactions");
+                return;
+            }
+
+            let start = asim::time::now();
+
+            // Only release assets once the lock is committed
+            self.source
+                .submit_transfer(Transfer {
+                    recipient: self.escrow,
+                    amount: self.amount,
+                })
+                .await;
+            self.destination
+                .submit_transfer(Transfer {
+                    recipient: self.recipient,
+                    amount: self.amount,
+                })
+                .await;
+
+            let elapsed = asim::time::now() - start;
+            log::trace!("Bridge transfer took {} seconds", elapsed.to_seconds());
+            self.latencies.borrow_mut().push(elapsed);
+
+            if !self.transfer_interval.is_zero() {
+                asim::time::sleep(self.transfer_interval).await;
+            }
+        }
+    }
+
+    /// The end-to-end latency of all completed transfers
+    pub fn get_latencies(&self) -> Vec<Duration> {
+        self.latencies.borrow().clone()
+    }
+}
@@ -0,0 +1,192 @@
+use crate::config::{
+    Connectivity, NetworkConfiguration, ProtocolConfiguration, TimeoutConfig, TrafficPattern,
+    Workload,
+};
+use crate::failures::Failures;
+use crate::logic::{SpeedTestGlobalLogic, SpeedTestMessage};
+use crate::metrics::LinkGoodput;
+use crate::simulation::Simulation;
+
+/// How long each benchmark runs (in seconds)
+const RUNTIME: u64 = 10;
+
+/// Every sender offers roughly 8 Mbit/s to each of its destinations
+const SEND_SPEED: u64 = 1;
+
+/// A small network with known parameters that runs the speed test protocol
+struct Benchmark {
+    name: &'static str,
+    pattern: TrafficPattern,
+    num_nodes: u32,
+    /// Upload bandwidth of every node (in Mbit/s)
+    bandwidth: u64,
+    /// Latency of every link (in milliseconds)
+    latency: u64,
+    /// Links that are not saturated should deliver messages after
+    /// the link latency plus the transmission time
+    check_delay: bool,
+}
+
+const BENCHMARKS: [Benchmark; 3] = [
+    Benchmark {
+        name: "single-link transfer",
+        pattern: TrafficPattern::Pair,
+        num_nodes: 2,
+        bandwidth: 100,
+        latency: 50,
+        check_delay: true,
+    },
+    Benchmark {
+        name: "saturated single-link transfer",
+        pattern: TrafficPattern::Pair,
+        num_nodes: 2,
+        bandwidth: 5,
+        latency: 50,
+        check_delay: false,
+    },
+    Benchmark {
+        name: "fan-out broadcast",
+        pattern: TrafficPattern::Star,
+        num_nodes: 5,
+        bandwidth: 20,
+        latency: 50,
+        check_delay: false,
+    },
+];
+
+/// A value measured by a calibration benchmark next to its analytic expectation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationResult {
+    pub benchmark: &'static str,
+    pub quantity: &'static str,
+    pub expected: f64,
+    pub measured: f64,
+}
+
+impl CalibrationResult {
+    /// How far the measured value is off, relative to the expected value
+    pub fn get_deviation(&self) -> f64 {
+        ((self.measured - self.expected) / self.expected).abs()
+    }
+}
+
+impl Benchmark {
+    fn run(&self) -> anyhow::Result<Vec<CalibrationResult>> {
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: SEND_SPEED,
+            pattern: self.pattern,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes: self.num_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: self.bandwidth,
+            link_bandwidth: None,
+            link_latency: self.latency,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(self.num_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None)?;
+        simulation.run_until(TimeoutConfig::Seconds {
+            warmup: 0,
+            runtime: RUNTIME,
+        })?;
+
+        let links = simulation.get_link_goodput();
+        let num_destinations = match self.pattern {
+            TrafficPattern::Star => self.num_nodes - 1,
+            _ => 1,
+        };
+        let num_links = match self.pattern {
+            TrafficPattern::Pair => 2,
+            TrafficPattern::Star => self.num_nodes - 1,
+            _ => anyhow::bail!("No expectations for pattern {:?}", self.pattern),
+        };
+
+        if links.len() != num_links as usize {
+            anyhow::bail!(
+                "Benchmark \"{}\" used {} links, expected {num_links}",
+                self.name,
+                links.len()
+            );
+        }
+
+        let message_bits = (SpeedTestMessage::default().get_size() * 8) as f64;
+        let send_delay = SpeedTestGlobalLogic::get_send_delay(SEND_SPEED);
+        let offered = message_bits / send_delay.as_seconds_f64();
+
+        // Senders share their bandwidth among all their destinations
+        let bandwidth = (self.bandwidth as f64) * 1_000_000.0 / (num_destinations as f64);
+
+        // Nothing arrives during the first latency period
+        let latency = (self.latency as f64) / 1000.0;
+        let active_fraction = ((RUNTIME as f64) - latency) / (RUNTIME as f64);
+
+        let mut results = vec![CalibrationResult {
+            benchmark: self.name,
+            quantity: "goodput per link (bits/s)",
+            expected: offered.min(bandwidth) * active_fraction,
+            measured: average(&links, |link| link.goodput),
+        }];
+
+        if self.check_delay {
+            let transmission_time = 1000.0 * message_bits / bandwidth;
+            results.push(CalibrationResult {
+                benchmark: self.name,
+                quantity: "message delay (ms)",
+                expected: (self.latency as f64) + transmission_time,
+                measured: average(&links, |link| link.avg_delay),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+fn average(links: &[LinkGoodput], func: impl Fn(&LinkGoodput) -> f64) -> f64 {
+    let total: f64 = links.iter().map(func).sum();
+    total / (links.len() as f64)
+}
+
+/// Runs micro-benchmarks through the network model and returns their results,
+/// so that they can be compared against the analytic expectations
+pub fn run_calibration() -> anyhow::Result<Vec<CalibrationResult>> {
+    let mut results = vec![];
+
+    for benchmark in BENCHMARKS.iter() {
+        log::info!("Running calibration benchmark \"{}\"", benchmark.name);
+        results.append(&mut benchmark.run()?);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_calibration;
+
+    #[test]
+    fn network_model() {
+        let _ = env_logger::try_init();
+
+        for result in run_calibration().unwrap() {
+            assert!(
+                result.get_deviation() <= 0.05,
+                "{} of \"{}\" was {}, expected {}",
+                result.quantity,
+                result.benchmark,
+                result.measured,
+                result.expected
+            );
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Nodes leaving and joining the network while the simulation runs
+
+use std::rc::Rc;
+
+use asim::time::{Duration, Time};
+
+use rand::Rng;
+
+use crate::config::{ChurnAction, ChurnConfig};
+use crate::node::NodeIndex;
+use crate::random;
+use crate::scene::Scene;
+
+/// Picks an exponentially distributed duration with the given mean (in milliseconds)
+fn sample_duration(mean: u64) -> Duration {
+    let uniform: f64 = random::rng().random_range(0.0..1.0);
+    let millis = (-(mean as f64) * (1.0 - uniform).ln()).round() as u64;
+    Duration::from_millis(millis)
+}
+
+/// Starts the tasks that let the nodes of a chain leave and join
+/// Node indices in the configuration count from `first_node`
+pub(crate) fn spawn_churn(
+    scene: &Rc<Scene>,
+    config: &ChurnConfig,
+    first_node: NodeIndex,
+    num_nodes: u32,
+) {
+    match config {
+        ChurnConfig::Random {
+            mean_uptime,
+            mean_downtime,
+        } => {
+            assert!(*mean_uptime > 0, "Mean uptime must be non-zero");
+
+            for node_idx in first_node..first_node + num_nodes as NodeIndex {
+                let scene = scene.clone();
+                let (mean_uptime, mean_downtime) = (*mean_uptime, *mean_downtime);
+
+                asim::spawn(async move {
+                    loop {
+                        asim::time::sleep(sample_duration(mean_uptime)).await;
+                        scene.set_node_offline(node_idx, true);
+
+                        asim::time::sleep(sample_duration(mean_downtime)).await;
+                        scene.set_node_offline(node_idx, false);
+                    }
+                });
+            }
+        }
+        ChurnConfig::Scripted(events) => {
+            let mut events = events.clone();
+            events.sort_by_key(|event| event.time);
+
+            for event in events.iter() {
+                assert!(
+                    event.node < num_nodes as NodeIndex,
+                    "Churn event for non-existent node #{}",
+                    event.node
+                );
+            }
+
+            let scene = scene.clone();
+            asim::spawn(async move {
+                for event in events {
+                    let time = Time::from_millis(event.time);
+                    let now = asim::time::now();
+                    if time > now {
+                        asim::time::sleep(time - now).await;
+                    }
+
+                    let offline = event.action == ChurnAction::Leave;
+                    scene.set_node_offline(first_node + event.node, offline);
+                }
+            });
+        }
+    }
+}
@@ -1,12 +1,20 @@
+use crate::config::{FeeDistribution, FeeMarketConfig, RpcLoadConfig, TransactionSizeDistribution};
+use crate::latency::LatencyHistogram;
 use crate::logic::AccountId;
-use crate::logic::Transaction;
-use crate::node::{Node, get_node_logic};
+use crate::logic::{
+    OutputId, Transaction, TransactionFee, TransactionId, TransactionOutput, Transfer,
+};
+use crate::node::{Node, NodeData, RpcQuery, get_node_logic};
 use crate::object::{Object, ObjectId};
+use crate::random;
 
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use rand::Rng;
+use rand::seq::IteratorRandom;
+
 use asim::sync::Notify;
 use asim::time::{Duration, Time};
 
@@ -16,62 +24,127 @@ pub struct Client {
     start_delay: Duration,
     transaction_interval: Duration,
     node: Rc<Node>,
+    /// Determines how much the client bids for its transactions
+    fee_market: Option<FeeMarketConfig>,
+    /// Overrides the bids of the fee market (unless set to `FeeDistribution::FeeMarket`)
+    fees: FeeDistribution,
+    /// Determines how large the client's transactions are
+    transaction_sizes: TransactionSizeDistribution,
+    /// Each transaction sends `transfer_amount` to one of these accounts (other than our own)
+    transfer_recipients: Vec<AccountId>,
+    transfer_amount: u64,
+    /// The output (and its amount) the next transaction spends (UTXO model only)
+    /// Every transaction sends its change back to the client, which the next one spends.
+    unspent_output: Cell<Option<(OutputId, u64)>>,
+    /// The probability of a transaction being marked as a front-running victim
+    victim_fraction: f64,
+    /// How many blocks must be on top of a transaction before the client considers it final
+    /// If not set, the client relies on the node's commit rule
+    confirmations: Option<u64>,
+    /// How long it takes for a message from the node to reach the client
+    latency: Duration,
     next_nonce: AtomicU64,
+    /// The transaction the client is currently waiting for
+    pending_txn: Cell<Option<TransactionId>>,
     txn_issue_time: RefCell<Option<Time>>,
-    latencies: RefCell<Vec<Duration>>,
+    /// Is the pending transaction a victim?
+    txn_is_victim: Cell<bool>,
+    /// The latency of the most recently committed transaction
+    last_latency: Cell<Option<Duration>>,
+    latencies: RefCell<LatencyHistogram>,
+    victim_latencies: RefCell<LatencyHistogram>,
     commit_notify: Notify,
 }
 
 impl Client {
     pub(super) fn new(
+        account_id: AccountId,
         start_delay: Duration,
         transaction_interval: Duration,
         node: Rc<Node>,
+        fee_market: Option<FeeMarketConfig>,
+        victim_fraction: f64,
+        confirmations: Option<u64>,
     ) -> Self {
+        if let Some(depth) = confirmations {
+            get_node_logic(&node).add_confirmation_depth(depth);
+        }
+
         let identifier = ObjectId::random();
-        let account_id = rand::random::<u128>();
+        let pending_txn = Cell::new(None);
         let txn_issue_time = RefCell::new(None);
-        let latencies = RefCell::new(vec![]);
+        let txn_is_victim = Cell::new(false);
+        let last_latency = Cell::new(None);
+        let latencies = RefCell::new(LatencyHistogram::default());
+        let victim_latencies = RefCell::new(LatencyHistogram::default());
         let commit_notify = Notify::new();
         let next_nonce = AtomicU64::new(1);
 
         Self {
             identifier,
             account_id,
+            pending_txn,
             txn_issue_time,
+            txn_is_victim,
             next_nonce,
             start_delay,
             transaction_interval,
             node,
+            fee_market,
+            fees: Default::default(),
+            transaction_sizes: Default::default(),
+            transfer_recipients: vec![],
+            transfer_amount: 0,
+            unspent_output: Cell::new(None),
+            victim_fraction,
+            confirmations,
+            latency: Duration::ZERO,
+            last_latency,
             latencies,
+            victim_latencies,
             commit_notify,
         }
     }
 
+    pub(crate) fn with_transaction_sizes(mut self, sizes: TransactionSizeDistribution) -> Self {
+        self.transaction_sizes = sizes;
+        self
+    }
+
+    pub(crate) fn with_fees(mut self, fees: FeeDistribution) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    pub(crate) fn with_transfers(mut self, (recipients, amount): (Vec<AccountId>, u64)) -> Self {
+        self.transfer_recipients = recipients;
+        self.transfer_amount = amount;
+        self
+    }
+
+    /// Makes the client spend outputs instead of using its account balance
+    pub(crate) fn with_unspent_output(self, output: Option<(OutputId, u64)>) -> Self {
+        self.unspent_output.set(output);
+        self
+    }
+
+    pub(crate) fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
     pub(crate) async fn run(&self) {
         if !self.start_delay.is_zero() {
             asim::time::sleep(self.start_delay).await;
         }
 
         loop {
-            log::trace!("Issuing next transaction");
-
-            {
-                let mut issue_time = self.txn_issue_time.borrow_mut();
-                *issue_time = Some(asim::time::now());
+            if self.node.is_crashed() {
+                log::trace!("Node crashed; will not issue more transactions");
+                return;
             }
 
-            let nonce = self.next_nonce.fetch_add(1, Ordering::SeqCst);
-            let transaction = Transaction::new(self.account_id, nonce);
-
-            get_node_logic(&self.node).add_transaction(
-                &self.node,
-                Rc::new(transaction),
-                Some(self.get_identifier()),
-            );
-
-            // wait for commit
-            self.commit_notify.notified().await;
+            self.submit_transaction().await;
 
             let delay = self.transaction_interval;
             if !delay.is_zero() {
@@ -80,16 +153,221 @@ impl Client {
         }
     }
 
-    pub fn get_latencies(&self) -> Vec<Duration> {
-        let latencies = self.latencies.borrow();
-        latencies.clone()
+    /// Issues a new transaction and waits for it to be committed
+    /// Returns the commit latency
+    pub(crate) async fn submit_transaction(&self) -> Duration {
+        self.submit(None).await
+    }
+
+    /// Like `submit_transaction`, but sends the given transfer
+    /// instead of paying a random recipient
+    pub(crate) async fn submit_transfer(&self, transfer: Transfer) -> Duration {
+        self.submit(Some(transfer)).await
+    }
+
+    async fn submit(&self, transfer: Option<Transfer>) -> Duration {
+        log::trace!("Issuing next transaction");
+
+        {
+            let mut issue_time = self.txn_issue_time.borrow_mut();
+            *issue_time = Some(asim::time::now());
+        }
+
+        let nonce = self.next_nonce.fetch_add(1, Ordering::SeqCst);
+        let fee = self.fees.sample().or_else(|| {
+            self.fee_market.as_ref().map(|config| {
+                let mut rng = random::rng();
+                TransactionFee {
+                    max_fee: rng.random_range(0..=config.max_fee),
+                    max_tip: rng.random_range(0..=config.max_tip),
+                }
+            })
+        });
+        let mut transaction = match fee {
+            Some(fee) => Transaction::new_with_fee(self.account_id, nonce, fee),
+            None => Transaction::new(self.account_id, nonce),
+        };
+
+        let is_victim = self.victim_fraction > 0.0
+            && random::rng().random_range(0.0..1.0) < self.victim_fraction;
+        if is_victim {
+            transaction.mark_as_victim();
+        }
+        self.txn_is_victim.set(is_victim);
+        transaction.set_size(self.transaction_sizes.sample());
+
+        let transfer = transfer.or_else(|| {
+            self.transfer_recipients
+                .iter()
+                .filter(|account| **account != self.account_id)
+                .choose(&mut random::rng())
+                .map(|recipient| Transfer {
+                    recipient: *recipient,
+                    amount: self.transfer_amount,
+                })
+        });
+
+        if let Some((input, input_amount)) = self.unspent_output.get() {
+            self.spend_output(&mut transaction, input, input_amount, transfer);
+        } else if let Some(transfer) = transfer {
+            transaction.set_transfer(transfer);
+        }
+        self.pending_txn.set(Some(*transaction.get_identifier()));
+
+        get_node_logic(&self.node).add_transaction(
+            &self.node,
+            Rc::new(transaction),
+            Some(self.get_identifier()),
+        );
+
+        // wait for commit
+        self.commit_notify.notified().await;
+
+        self.last_latency.get().expect("No latency recorded")
+    }
+
+    /// Spends the given output to pay for the transfer (if any) and the maximum fee
+    /// The change becomes the output the next transaction spends
+    fn spend_output(
+        &self,
+        transaction: &mut Transaction,
+        input: OutputId,
+        input_amount: u64,
+        transfer: Option<Transfer>,
+    ) {
+        let mut outputs = vec![];
+        let mut spent = transaction.get_fee().max_fee;
+
+        if let Some(transfer) = transfer {
+            outputs.push(TransactionOutput {
+                owner: transfer.recipient,
+                amount: transfer.amount,
+            });
+            spent = spent.saturating_add(transfer.amount);
+        }
+
+        // If the input is too small, the transaction is invalid and never commits
+        let change = input_amount.saturating_sub(spent);
+        outputs.push(TransactionOutput {
+            owner: self.account_id,
+            amount: change,
+        });
+
+        let change_output = OutputId {
+            transaction: *transaction.get_identifier(),
+            index: (outputs.len() - 1) as u32,
+        };
+        self.unspent_output.set(Some((change_output, change)));
+
+        transaction.set_utxos(vec![input], outputs);
+    }
+
+    /// Periodically queries the node, like a wallet or block explorer would
+    pub(crate) async fn run_rpc_queries(&self, config: RpcLoadConfig) {
+        assert!(config.query_interval > 0, "Query interval must be non-zero");
+
+        let query_interval = Duration::from_millis(config.query_interval);
+        let processing_time = Duration::from_micros(config.processing_time);
+
+        if !self.start_delay.is_zero() {
+            asim::time::sleep(self.start_delay).await;
+        }
+
+        loop {
+            if self.node.is_crashed() {
+                log::trace!("Node crashed; will not send more queries");
+                return;
+            }
+
+            let query = if random::rng().random_range(0.0..1.0) < config.block_query_fraction {
+                RpcQuery::LatestBlock
+            } else {
+                RpcQuery::Balance(self.account_id)
+            };
+
+            NodeData::answer_rpc_query(&self.node, query, processing_time).await;
+            asim::time::sleep(query_interval).await;
+        }
+    }
+
+    pub(crate) fn get_node(&self) -> &Rc<Node> {
+        &self.node
+    }
+
+    /// How many transactions per second the client would issue
+    /// if every transaction committed instantly
+    pub(crate) fn get_demand(&self) -> f64 {
+        if self.transaction_interval.is_zero() {
+            f64::INFINITY
+        } else {
+            1.0 / self.transaction_interval.as_seconds_f64()
+        }
+    }
+
+    /// Commit latencies of all transactions issued by this client
+    pub fn get_latencies(&self) -> Ref<'_, LatencyHistogram> {
+        self.latencies.borrow()
+    }
+
+    /// Commit latencies of the transactions that were marked as front-running victims
+    pub fn get_victim_latencies(&self) -> Ref<'_, LatencyHistogram> {
+        self.victim_latencies.borrow()
+    }
+
+    /// The confirmation depth this client waits for (if any)
+    pub fn get_confirmations(&self) -> Option<u64> {
+        self.confirmations
     }
 
     pub fn get_account_id(&self) -> &AccountId {
         &self.account_id
     }
 
-    pub(crate) fn notify_transaction_commit(&self) {
+    /// The balance of this client's account as seen by the node it is connected to
+    pub fn get_balance(&self) -> Option<u64> {
+        get_node_logic(&self.node).get_balance(&self.account_id)
+    }
+
+    /// Called whenever one of the client's transactions reaches a new confirmation depth
+    pub(crate) fn notify_transaction_confirmation(
+        self: &Rc<Self>,
+        txn_id: &TransactionId,
+        depth: u64,
+    ) {
+        if self.confirmations != Some(depth) {
+            return;
+        }
+
+        // Transactions can be confirmed more than once if they are included again after a reorg
+        if self.pending_txn.get() != Some(*txn_id) {
+            return;
+        }
+
+        self.pending_txn.set(None);
+        self.notify_transaction_commit();
+    }
+
+    /// Sends a commit notification from the node to the client
+    ///
+    /// Unless the client is co-located with its node, the notification
+    /// has to wait for the node's uplink and then travel to the client.
+    pub(crate) fn notify_transaction_commit(self: &Rc<Self>) {
+        if self.latency.is_zero() {
+            self.receive_commit_notification();
+            return;
+        }
+
+        let size = std::mem::size_of::<TransactionId>() as u64;
+        let delay = self.node.record_upload(size) + self.latency;
+
+        let client = self.clone();
+        asim::spawn(async move {
+            asim::time::sleep(delay).await;
+            client.receive_commit_notification();
+        });
+    }
+
+    fn receive_commit_notification(&self) {
         let elapsed = {
             let issue_time = self
                 .txn_issue_time
@@ -103,9 +381,11 @@ impl Client {
             elapsed.to_seconds()
         );
 
-        {
-            let mut latencies = self.latencies.borrow_mut();
-            latencies.push(elapsed);
+        self.latencies.borrow_mut().record(elapsed);
+        self.last_latency.set(Some(elapsed));
+
+        if self.txn_is_victim.get() {
+            self.victim_latencies.borrow_mut().record(elapsed);
         }
 
         // wake up client loop
@@ -1,14 +1,98 @@
 use std::str::FromStr;
 
+use anyhow::Context;
+
+use asim::time::{Duration, Time};
+
+use rand::Rng;
+
 use serde::{Deserialize, Serialize};
 
+use crate::logic::{AccountId, DEFAULT_TRANSACTION_SIZE, TransactionFee};
 use crate::metrics::{ChainMetricType, MetricType};
 use crate::node::{Location, NodeIndex};
+use crate::random;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Connectivity {
     Full,
-    Sparse { min_conns_per_node: u32 },
+    /// Connects every node to its closest neighbors
+    Sparse {
+        min_conns_per_node: u32,
+        /// What to do if the resulting network is not connected
+        #[serde(default)]
+        on_disconnected: DisconnectedPolicy,
+    },
+    /// Connects every node to `degree` random nodes, regardless of location
+    /// The number of nodes times the degree must be even
+    RandomRegular {
+        degree: u32,
+    },
+    /// Connects every node to its `nearest` closest neighbors and adds `long_range`
+    /// links per node to more distant nodes (Kleinberg-style)
+    SmallWorld {
+        nearest: u32,
+        long_range: u32,
+        /// Long-range peers are picked with a probability proportional to `distance^-exponent`
+        /// Zero picks them uniformly at random; larger values prefer closer peers
+        #[serde(default = "default_small_world_exponent")]
+        exponent: f64,
+    },
+}
+
+fn default_small_world_exponent() -> f64 {
+    2.0
+}
+
+/// Which nodes of a generated network accept inbound connections
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Reachability {
+    /// Every node accepts inbound connections
+    #[default]
+    Public,
+    /// A fraction (0 to 1) of the nodes is behind a NAT and only opens outbound connections,
+    /// so two of these nodes are never connected to each other
+    Nat { fraction: f64 },
+}
+
+impl Reachability {
+    /// Decides for each of the nodes whether it accepts inbound connections
+    pub fn sample(&self, num_nodes: usize) -> Vec<bool> {
+        match self {
+            Self::Public => vec![true; num_nodes],
+            Self::Nat { fraction } => {
+                assert!(
+                    (0.0..1.0).contains(fraction),
+                    "NAT fraction must be at least 0 and less than 1"
+                );
+
+                // At least one node must accept connections for the network to be connected
+                let num_nat = (((num_nodes as f64) * fraction).round() as usize)
+                    .min(num_nodes.saturating_sub(1));
+                let mut reachable = vec![true; num_nodes];
+
+                let mut rng = random::rng();
+                for idx in rand::seq::index::sample(&mut rng, num_nodes, num_nat) {
+                    reachable[idx] = false;
+                }
+
+                reachable
+            }
+        }
+    }
+}
+
+/// How to handle a generated network that consists of multiple disconnected parts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectedPolicy {
+    /// Add links between the closest nodes of different parts
+    #[default]
+    Repair,
+    /// Place the nodes again until the network is connected
+    /// Falls back to `Repair` after the given number of attempts
+    Resample { max_attempts: u32 },
+    /// Keep the network as is
+    Ignore,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +104,23 @@ pub struct Workload {
     pub client_startup_interval: u64,
     /// Should clients pause between transaction commit and issuing a new transaction?
     pub transaction_interval: u64,
+    #[serde(default)]
+    pub client_assignment: ClientAssignment,
+    /// Clients do not send any queries if not set
+    #[serde(default)]
+    pub rpc_load: Option<RpcLoadConfig>,
+    /// How many confirmations clients wait for, e.g., `[1, 30]` for a mix of merchants
+    /// and exchanges. Depths are assigned to clients in a round-robin fashion.
+    /// If empty, clients rely on the protocol's commit rule.
+    #[serde(default)]
+    pub confirmation_depths: Vec<u64>,
+    /// One-way latency between a client and its node (in milliseconds)
+    /// Commit notifications take this long to reach the client
+    #[serde(default)]
+    pub client_latency: u64,
+    /// How much clients pay for their transactions
+    #[serde(default)]
+    pub fees: FeeDistribution,
 }
 
 impl Default for Workload {
@@ -28,10 +129,116 @@ impl Default for Workload {
             num_clients: 100,
             client_startup_interval: 1,
             transaction_interval: 1000,
+            client_assignment: Default::default(),
+            rpc_load: None,
+            confirmation_depths: vec![],
+            client_latency: 0,
+            fees: Default::default(),
         }
     }
 }
 
+/// Read-only queries clients send to their node, e.g., to look up balances or fetch blocks
+///
+/// Answering a query keeps the node busy, just like processing a message,
+/// so heavily queried nodes fall behind on consensus.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcLoadConfig {
+    /// Time between two queries of the same client (in milliseconds)
+    pub query_interval: u64,
+    /// Fraction (0 to 1) of queries that fetch the latest block; all others look up a balance
+    #[serde(default)]
+    pub block_query_fraction: f64,
+    /// How long the node needs to answer a query (in microseconds)
+    /// Sending the response takes additional time depending on its size and the node's bandwidth
+    pub processing_time: u64,
+}
+
+/// How clients are attached to nodes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientAssignment {
+    /// Every client picks a node at random
+    #[default]
+    Random,
+    /// The n-th client is attached to the n-th node (wrapping around)
+    RoundRobin,
+    /// Splits the world into `num_regions` bands of equal longitude
+    /// Clients are spread evenly across all regions that contain nodes,
+    /// and round-robin across the nodes within a region
+    Regional { num_regions: u32 },
+}
+
+impl ClientAssignment {
+    /// The region a location belongs to
+    /// Returns `None` unless clients are assigned by region
+    pub fn get_region(&self, location: &Location) -> Option<u32> {
+        match self {
+            Self::Random | Self::RoundRobin => None,
+            Self::Regional { num_regions } => Some(location.get_region(*num_regions)),
+        }
+    }
+}
+
+/// Which nodes send traffic to each other in a speed test
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrafficPattern {
+    /// The first node sends messages that every node forwards to its peers
+    #[default]
+    Flood,
+    /// The first two nodes send to each other
+    Pair,
+    /// The first node sends to each of its peers
+    Star,
+    /// Every node sends to each of its peers
+    AllToAll,
+    /// All other nodes send to the first node
+    Incast,
+}
+
+impl TrafficPattern {
+    /// Does this pattern need a direct link between senders and receivers?
+    pub fn needs_full_connectivity(&self) -> bool {
+        match self {
+            Self::Pair | Self::Incast => true,
+            Self::Flood | Self::Star | Self::AllToAll => false,
+        }
+    }
+}
+
+/// How voting power (e.g., stake) is spread across the nodes of a random network
+/// Only used by BFT protocols, which require a quorum of the total voting power
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum VotingPowerDistribution {
+    /// Every node has a voting power of one
+    #[default]
+    Equal,
+    /// The n-th node has a voting power proportional to 1/n^exponent,
+    /// so that few nodes hold most of the voting power
+    Zipf { exponent: f64 },
+    /// The voting power of each node, ordered by node index
+    /// Nodes that are not listed have a voting power of one
+    Explicit(Vec<u64>),
+}
+
+impl VotingPowerDistribution {
+    /// Voting power of the first node with a Zipf distribution
+    const ZIPF_MAX_VOTING_POWER: f64 = 1000.0;
+
+    pub fn get_voting_power(&self, num_nodes: u32) -> Vec<u64> {
+        (0..num_nodes as usize)
+            .map(|idx| match self {
+                Self::Equal => 1,
+                Self::Zipf { exponent } => {
+                    let rank = (idx + 1) as f64;
+                    let power = Self::ZIPF_MAX_VOTING_POWER / rank.powf(*exponent);
+                    (power.round() as u64).max(1)
+                }
+                Self::Explicit(powers) => powers.get(idx).copied().unwrap_or(1),
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum NakamotoBlockGenerationConfig {
     ProofOfWork {
@@ -58,29 +265,432 @@ impl Default for NakamotoBlockGenerationConfig {
     }
 }
 
+/// An account that exists before the simulation starts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    pub account: AccountId,
+    pub balance: u64,
+}
+
+/// The initial state of the ledger
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum GenesisConfig {
+    /// All accounts start out empty
+    #[default]
+    Empty,
+    /// The accounts are listed in the configuration itself
+    Accounts(Vec<GenesisAccount>),
+    /// The accounts are loaded from a RON file containing a list of `GenesisAccount`s
+    File(String),
+}
+
+impl GenesisConfig {
+    /// Returns all accounts of the genesis state (reading them from disk, if needed)
+    pub fn load_accounts(&self) -> anyhow::Result<Vec<GenesisAccount>> {
+        match self {
+            Self::Empty => Ok(vec![]),
+            Self::Accounts(accounts) => Ok(accounts.clone()),
+            Self::File(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read genesis file \"{path}\""))?;
+                ron::from_str(&content)
+                    .with_context(|| format!("Failed to parse genesis file \"{path}\""))
+            }
+        }
+    }
+}
+
+/// A group of miners that shares its block rewards
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MiningPoolConfig {
+    /// The nodes that mine on behalf of the pool
+    pub members: Vec<NodeIndex>,
+    /// Members that never publish the blocks they find (block withholding attack)
+    #[serde(default)]
+    pub withholding_members: Vec<NodeIndex>,
+}
+
+/// How a miner fills and releases its blocks
+///
+/// By default, miners include as many transactions as possible and announce blocks immediately.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinerPolicy {
+    /// The nodes that follow this policy; applies to all miners if empty
+    pub miners: Vec<NodeIndex>,
+    /// Mine empty blocks while fewer transactions than this are pending
+    pub min_mempool_size: u32,
+    /// Only fill blocks up to this percentage of the size limits
+    pub max_fill: Option<u32>,
+    /// Keep mining on a new block for this long before announcing it (in milliseconds)
+    pub release_delay: u64,
+}
+
+impl MinerPolicy {
+    pub fn applies_to(&self, node_index: NodeIndex) -> bool {
+        self.miners.is_empty() || self.miners.contains(&node_index)
+    }
+
+    /// Scales a block size limit according to `max_fill`
+    pub fn get_fill_limit(&self, limit: u64) -> u64 {
+        match self.max_fill {
+            Some(percent) => limit * (percent.min(100) as u64) / 100,
+            None => limit,
+        }
+    }
+}
+
+/// How nodes deal with peers that do not answer requests for blocks or transactions
+///
+/// Without this, nodes wait for a response forever and keep blocks that
+/// depend on the missing data in memory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestRetryConfig {
+    /// How long to wait for a response before asking another peer (in milliseconds)
+    /// Nodes check their requests once per timeout, so a retry can take up to twice as long
+    pub timeout: u64,
+    /// How many peers to ask before giving up on the object and all blocks waiting for it
+    #[serde(default = "default_max_request_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_request_attempts() -> u32 {
+    3
+}
+
+/// How nodes punish peers that send blocks or transactions they did not ask for,
+/// or blocks that violate their rules
+///
+/// Every offense adds a penalty to the peer's score. Once the score reaches
+/// the threshold, the node bans the peer and ignores all further messages from it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerScoringConfig {
+    /// Penalty for sending a block or transaction that was not requested
+    #[serde(default = "default_unrequested_penalty")]
+    pub unrequested_penalty: u32,
+    /// Penalty for sending a block that violates the node's rules (or builds on such a block)
+    #[serde(default = "default_invalid_block_penalty")]
+    pub invalid_block_penalty: u32,
+    /// Peers are banned once their penalties add up to this
+    pub ban_threshold: u32,
+}
+
+fn default_unrequested_penalty() -> u32 {
+    1
+}
+
+fn default_invalid_block_penalty() -> u32 {
+    10
+}
+
+/// An EIP-1559-style fee market
+///
+/// Every block has a base fee that all its transactions must pay.
+/// The base fee rises if blocks are larger than the target and falls if they are smaller.
+/// Transactions that pay a higher tip on top of the base fee are included first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeeMarketConfig {
+    /// The base fee of the first block
+    pub initial_base_fee: u64,
+    /// The number of transactions per block the base fee steers towards
+    pub target_block_size: u32,
+    /// The base fee changes by at most 1/denominator between two blocks
+    #[serde(default = "default_base_fee_change_denominator")]
+    pub base_fee_change_denominator: u64,
+    /// Clients pick the tip of each transaction uniformly at random from [0, max_tip]
+    pub max_tip: u64,
+    /// Clients pick the most they are willing to pay for a transaction
+    /// (base fee plus tip) uniformly at random from [0, max_fee]
+    pub max_fee: u64,
+}
+
+fn default_base_fee_change_denominator() -> u64 {
+    8
+}
+
+/// Keeps blocks in a file, so that chains can grow larger than the available memory
+///
+/// Only the most recently used blocks stay in memory.
+/// The file is removed once the simulation ends.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStoreConfig {
+    /// Where to create the file; must exist
+    pub directory: String,
+    /// How many blocks to keep in memory
+    /// Blocks that are still in use, e.g., sent over the network, are kept as well
+    #[serde(default = "default_block_cache_size")]
+    pub cache_size: usize,
+}
+
+fn default_block_cache_size() -> usize {
+    10_000
+}
+
+/// How transactions represent funds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionModel {
+    /// Transactions move funds between account balances and carry a nonce
+    #[default]
+    Accounts,
+    /// Transactions spend the outputs of earlier transactions and create new ones
+    /// Every genesis account starts with a single output holding its balance.
+    Utxo,
+}
+
+/// How transactions change the state of a Nakamoto-style ledger
+///
+/// With accounts, every transaction needs the next nonce of its account and enough balance
+/// to pay its transfer and fee. With UTXOs, every input must be unspent and owned by the
+/// sender, and the inputs must cover the outputs and the fee.
+/// Miners leave out transactions that are invalid.
+/// Clients should use distinct, funded genesis accounts; clients sharing an
+/// account pick conflicting nonces (or spend the same outputs).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ExecutionConfig {
+    #[serde(default)]
+    pub model: TransactionModel,
+    /// How much each client transaction sends to another genesis account (picked at random)
+    #[serde(default)]
+    pub transfer_amount: u64,
+    /// Credited to the miner of each block in addition to the tips
+    #[serde(default)]
+    pub block_reward: u64,
+}
+
+/// When a protocol upgrade takes effect
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpgradeActivation {
+    /// Blocks at or above this height follow the new rules
+    Height(u64),
+    /// Blocks created at or after this time (in seconds) follow the new rules
+    Time(u64),
+}
+
+/// A hard fork that changes protocol parameters during the run
+///
+/// Parameters that are not set keep the value they had before the upgrade.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProtocolUpgrade {
+    pub activation: UpgradeActivation,
+    #[serde(default)]
+    pub max_block_size: Option<u32>,
+    #[serde(default)]
+    pub max_block_bytes: Option<u64>,
+    /// Nodes that never adopt this upgrade, e.g., to simulate a contentious hard fork
+    /// They keep following the old rules and reject all blocks that violate them
+    #[serde(default)]
+    pub legacy_nodes: Vec<NodeIndex>,
+}
+
+/// How large the transactions issued by clients are
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TransactionSizeDistribution {
+    /// Every transaction is a simple transfer
+    #[default]
+    Transfer,
+    /// Every transaction has the given size (in bytes)
+    Constant(u64),
+    /// Sizes are picked uniformly at random from [min, max] (in bytes)
+    Uniform { min: u64, max: u64 },
+    /// Sizes (in bytes) are picked with the given relative weights,
+    /// e.g., mostly small transfers and a few large contract calls
+    Weighted(Vec<(u64, f64)>),
+}
+
+impl TransactionSizeDistribution {
+    /// Picks the size of a new transaction (in bytes)
+    pub fn sample(&self) -> u64 {
+        match self {
+            Self::Transfer => DEFAULT_TRANSACTION_SIZE,
+            Self::Constant(size) => *size,
+            Self::Uniform { min, max } => random::rng().random_range(*min..=*max),
+            Self::Weighted(sizes) => {
+                let total: f64 = sizes.iter().map(|(_, weight)| weight).sum();
+                let mut choice = random::rng().random_range(0.0..total);
+
+                for (size, weight) in sizes {
+                    if choice < *weight {
+                        return *size;
+                    }
+                    choice -= weight;
+                }
+
+                sizes.last().expect("No transaction sizes given").0
+            }
+        }
+    }
+}
+
+/// How much clients are willing to pay for each of their transactions
+///
+/// Unless their `OrderingPolicy` says otherwise, block producers include the transactions
+/// with the highest fees first. Except for `FeeMarket`, the entire fee can go to the
+/// block producer as a tip.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum FeeDistribution {
+    /// Use the bids of the protocol's fee market, or pay no fees if there is none
+    #[default]
+    FeeMarket,
+    /// Every transaction pays the given fee
+    Constant(u64),
+    /// Fees are picked uniformly at random from [min, max]
+    Uniform { min: u64, max: u64 },
+    /// Fees follow an exponential distribution with the given mean,
+    /// so that most transactions pay little and a few pay a lot
+    Exponential { mean: f64 },
+}
+
+impl FeeDistribution {
+    /// Picks the fee of a new transaction
+    /// Returns `None` if the fee market decides instead
+    pub fn sample(&self) -> Option<TransactionFee> {
+        let fee = match self {
+            Self::FeeMarket => return None,
+            Self::Constant(fee) => *fee,
+            Self::Uniform { min, max } => random::rng().random_range(*min..=*max),
+            Self::Exponential { mean } => {
+                let uniform: f64 = random::rng().random_range(0.0..1.0);
+                (-mean * (1.0 - uniform).ln()).round() as u64
+            }
+        };
+
+        Some(TransactionFee {
+            max_fee: fee,
+            max_tip: fee,
+        })
+    }
+}
+
+/// How block producers order the transactions they include
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum OrderingPolicy {
+    /// Oldest transactions first
+    Fifo,
+    /// Highest (effective) tip first; oldest first if tips are equal
+    #[default]
+    FeePriority,
+    /// Uniformly random order
+    Random,
+    /// Clients mark a fraction of their transactions as victims.
+    /// The listed block producers place a transaction of their own directly in front of every
+    /// victim they include. All other block producers use `FeePriority`.
+    FrontRunning {
+        attackers: Vec<NodeIndex>,
+        victim_fraction: f64,
+    },
+}
+
+impl OrderingPolicy {
+    /// The probability of a client transaction being marked as a victim
+    pub fn get_victim_fraction(&self) -> f64 {
+        match self {
+            Self::FrontRunning {
+                victim_fraction, ..
+            } => *victim_fraction,
+            Self::Fifo | Self::FeePriority | Self::Random => 0.0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ProtocolConfiguration {
     NakamotoConsensus {
         block_generation: NakamotoBlockGenerationConfig,
         #[allow(dead_code)] //TODO
         use_ghost: bool,
+        /// The maximum number of transactions in a block
         max_block_size: u32,
+        /// The maximum combined size of a block's transactions (in bytes)
+        /// Real protocols limit bytes (or weight) rather than transaction counts
+        #[serde(default)]
+        max_block_bytes: Option<u64>,
+        /// How large the transactions issued by clients are
+        #[serde(default)]
+        transaction_sizes: TransactionSizeDistribution,
         /// How many blocks until a transaction is confirmed?
         commit_delay: u64,
+        /// Number of (empty) blocks that already exist when the simulation starts
+        /// Allows skipping the warmup period
+        #[serde(default)]
+        premined_blocks: u64,
+        #[serde(default)]
+        genesis: GenesisConfig,
+        /// How many seconds before the start of the simulation the genesis block was created
+        /// The difficulty adjustment measures the interval of the first block from this timestamp
+        #[serde(default)]
+        genesis_age: u64,
+        /// Miners that are not part of any pool mine on their own
+        #[serde(default)]
+        mining_pools: Vec<MiningPoolConfig>,
+        /// Transactions pay no fees if not set
+        #[serde(default)]
+        fee_market: Option<FeeMarketConfig>,
+        /// Blocks carry the state of their parent unchanged if not set
+        #[serde(default)]
+        execution: Option<ExecutionConfig>,
+        #[serde(default)]
+        ordering_policy: OrderingPolicy,
+        /// Record when each node first saw every n-th block
+        /// Disabled if zero, as this needs memory for every node and sampled block
+        #[serde(default)]
+        first_seen_sampling: u32,
+        /// Parameter changes that all nodes apply once activated (in the given order)
+        #[serde(default)]
+        upgrades: Vec<ProtocolUpgrade>,
+        /// Each miner follows the first policy that applies to it
+        #[serde(default)]
+        miner_policies: Vec<MinerPolicy>,
+        /// Nodes wait for requested blocks and transactions forever if not set
+        #[serde(default)]
+        request_retries: Option<RequestRetryConfig>,
+        /// Nodes never ban their peers if not set
+        #[serde(default)]
+        peer_scoring: Option<PeerScoringConfig>,
+        /// Blocks are kept in memory if not set
+        #[serde(default)]
+        block_store: Option<BlockStoreConfig>,
     },
     PracticalBFT {
         max_block_size: u32,
         /// Maximum interval between blocks (in milliseconds)
         max_block_interval: u64,
+        #[serde(default)]
+        genesis: GenesisConfig,
+        /// Hand the proposer role to the next node after every slot (round-robin)
+        #[serde(default)]
+        rotate_proposer: bool,
+        /// Replicas ask for a new leader if a slot does not make progress for this long (in milliseconds)
+        /// Defaults to four times `max_block_interval`
+        #[serde(default)]
+        view_change_timeout: Option<u64>,
+    },
+    /// Crash-fault-tolerant consensus with an elected leader
+    Raft {
+        max_block_size: u32,
+        /// Maximum interval between blocks (in milliseconds)
+        max_block_interval: u64,
+        #[serde(default)]
+        genesis: GenesisConfig,
+        /// Followers start an election if they do not hear from the leader for this long (in milliseconds)
+        /// Each node picks a random timeout between this value and twice of it
+        /// Defaults to four times `max_block_interval`
+        #[serde(default)]
+        election_timeout: Option<u64>,
     },
     SpeedTest {
         /// Send speed in Mbit/s
+        /// Applies to every destination of a sender
         send_speed: u64,
+        #[serde(default)]
+        pattern: TrafficPattern,
     },
     Gossip {
         /// When to try fetching data from another peer (in milliseconds)
         retry_delay: u32,
         block_size: u32,
+        /// Relay new blocks to this many random peers instead of all of them
+        #[serde(default)]
+        fanout: Option<u32>,
     },
     Snowball {
         /// Number of consecutive rounds for it to be accepted: beta
@@ -90,6 +700,21 @@ pub enum ProtocolConfiguration {
         /// Number of sampled nodes to form quorum in each epoch: alpha/k
         query_threshold_weighted: f64,
     },
+    /// Snowball consensus repeated for every height of a linear chain of blocks
+    /// Mining nodes are validators; non-mining nodes only query them
+    Snowman {
+        max_block_size: u32,
+        /// Maximum interval between blocks (in milliseconds)
+        max_block_interval: u64,
+        #[serde(default)]
+        genesis: GenesisConfig,
+        /// Number of consecutive successful polls for a block to be accepted: beta
+        acceptance_threshold: u32,
+        /// Number of validators to sample when querying: k/n
+        sample_size_weighted: f64,
+        /// Number of sampled validators that need to agree in each poll: alpha/k
+        query_threshold_weighted: f64,
+    },
 }
 
 impl Default for ProtocolConfiguration {
@@ -99,11 +724,159 @@ impl Default for ProtocolConfiguration {
             use_ghost: false,
             commit_delay: 6,
             max_block_size: 1024 * 1024,
+            max_block_bytes: None,
+            transaction_sizes: Default::default(),
+            premined_blocks: 0,
+            genesis: GenesisConfig::Empty,
+            genesis_age: 0,
+            mining_pools: vec![],
+            fee_market: None,
+            execution: None,
+            ordering_policy: OrderingPolicy::default(),
+            first_seen_sampling: 0,
+            upgrades: vec![],
+            miner_policies: vec![],
+            request_retries: None,
+            peer_scoring: None,
+            block_store: None,
         }
     }
 }
 
 impl ProtocolConfiguration {
+    /// The initial state of the ledger
+    /// Returns `None` for protocols that do not have a ledger
+    pub fn get_genesis(&self) -> Option<&GenesisConfig> {
+        match self {
+            Self::NakamotoConsensus { genesis, .. }
+            | Self::PracticalBFT { genesis, .. }
+            | Self::Raft { genesis, .. }
+            | Self::Snowman { genesis, .. } => Some(genesis),
+            Self::SpeedTest { .. } | Self::Gossip { .. } | Self::Snowball { .. } => None,
+        }
+    }
+
+    /// The fee market used by this protocol (if any)
+    pub fn get_fee_market(&self) -> Option<&FeeMarketConfig> {
+        match self {
+            Self::NakamotoConsensus { fee_market, .. } => fee_market.as_ref(),
+            Self::PracticalBFT { .. }
+            | Self::Raft { .. }
+            | Self::SpeedTest { .. }
+            | Self::Gossip { .. }
+            | Self::Snowball { .. }
+            | Self::Snowman { .. } => None,
+        }
+    }
+
+    /// How transactions modify the account state
+    /// Returns `None` if the protocol does not execute transactions
+    pub fn get_execution(&self) -> Option<&ExecutionConfig> {
+        match self {
+            Self::NakamotoConsensus { execution, .. } => execution.as_ref(),
+            Self::PracticalBFT { .. }
+            | Self::Raft { .. }
+            | Self::SpeedTest { .. }
+            | Self::Gossip { .. }
+            | Self::Snowball { .. }
+            | Self::Snowman { .. } => None,
+        }
+    }
+
+    /// How large client transactions are
+    /// Protocols without variable transaction sizes return `None`
+    pub fn get_transaction_sizes(&self) -> Option<&TransactionSizeDistribution> {
+        match self {
+            Self::NakamotoConsensus {
+                transaction_sizes, ..
+            } => Some(transaction_sizes),
+            Self::PracticalBFT { .. }
+            | Self::Raft { .. }
+            | Self::SpeedTest { .. }
+            | Self::Gossip { .. }
+            | Self::Snowball { .. }
+            | Self::Snowman { .. } => None,
+        }
+    }
+
+    /// How block producers order transactions
+    /// Returns `None` for protocols without a configurable order
+    pub fn get_ordering_policy(&self) -> Option<&OrderingPolicy> {
+        match self {
+            Self::NakamotoConsensus {
+                ordering_policy, ..
+            } => Some(ordering_policy),
+            Self::PracticalBFT { .. }
+            | Self::Raft { .. }
+            | Self::SpeedTest { .. }
+            | Self::Gossip { .. }
+            | Self::Snowball { .. }
+            | Self::Snowman { .. } => None,
+        }
+    }
+
+    /// Does this protocol need a direct link between every pair of nodes?
+    pub fn needs_full_connectivity(&self) -> bool {
+        match self {
+            Self::NakamotoConsensus { .. } | Self::Gossip { .. } => false,
+            Self::PracticalBFT { .. }
+            | Self::Raft { .. }
+            | Self::Snowball { .. }
+            | Self::Snowman { .. } => true,
+            Self::SpeedTest { pattern, .. } => pattern.needs_full_connectivity(),
+        }
+    }
+
+    /// Can simulations of this protocol stop after a number of blocks (`TimeoutConfig::Blocks`)?
+    pub fn supports_block_timeout(&self) -> bool {
+        match self {
+            Self::Gossip { .. }
+            | Self::Snowball { .. }
+            | Self::Raft { .. }
+            | Self::Snowman { .. } => true,
+            Self::NakamotoConsensus { .. } | Self::PracticalBFT { .. } | Self::SpeedTest { .. } => {
+                false
+            }
+        }
+    }
+
+    /// Can `set` be called with this parameter?
+    pub fn supports_parameter(&self, parameter: &ParameterType) -> bool {
+        match self {
+            Self::NakamotoConsensus { .. } | Self::PracticalBFT { .. } | Self::Raft { .. } => {
+                matches!(
+                    parameter,
+                    ParameterType::MaxBlockSize
+                        | ParameterType::NumMiningNodes
+                        | ParameterType::NumNonMiningNodes
+                        | ParameterType::NumClients
+                )
+            }
+            Self::Gossip { .. } => matches!(
+                parameter,
+                ParameterType::GossipRetryDelay
+                    | ParameterType::GossipFanout
+                    | ParameterType::BlockSize
+            ),
+            Self::SpeedTest { .. } => false,
+            Self::Snowball { .. } => matches!(
+                parameter,
+                ParameterType::NumMiningNodes
+                    | ParameterType::NumNonMiningNodes
+                    | ParameterType::NumClients
+                    | ParameterType::AcceptanceThreshold
+            ),
+            Self::Snowman { .. } => matches!(
+                parameter,
+                ParameterType::MaxBlockSize
+                    | ParameterType::AcceptanceThreshold
+                    | ParameterType::NumMiningNodes
+                    | ParameterType::NumNonMiningNodes
+                    | ParameterType::NumClients
+            ),
+        }
+    }
+
     pub fn set(&mut self, parameter: &ParameterType, value: ParameterValue) {
         match *self {
             Self::NakamotoConsensus {
@@ -121,6 +894,10 @@ impl ProtocolConfiguration {
             Self::PracticalBFT {
                 ref mut max_block_size,
                 ..
+            }
+            | Self::Raft {
+                ref mut max_block_size,
+                ..
             } => match parameter {
                 ParameterType::MaxBlockSize => {
                     *max_block_size = value.try_into().unwrap();
@@ -133,10 +910,14 @@ impl ProtocolConfiguration {
             Self::Gossip {
                 ref mut retry_delay,
                 ref mut block_size,
+                ref mut fanout,
             } => match parameter {
                 ParameterType::GossipRetryDelay => {
                     *retry_delay = value.try_into().unwrap();
                 }
+                ParameterType::GossipFanout => {
+                    *fanout = Some(value.try_into().unwrap());
+                }
                 ParameterType::BlockSize => {
                     *block_size = value.try_into().unwrap();
                 }
@@ -156,6 +937,22 @@ impl ProtocolConfiguration {
                 }
                 _ => panic!("Parameter not supported"),
             },
+            Self::Snowman {
+                ref mut max_block_size,
+                ref mut acceptance_threshold,
+                ..
+            } => match parameter {
+                ParameterType::MaxBlockSize => {
+                    *max_block_size = value.try_into().unwrap();
+                }
+                ParameterType::AcceptanceThreshold => {
+                    *acceptance_threshold = value.try_into().unwrap();
+                }
+                ParameterType::NumMiningNodes
+                | ParameterType::NumNonMiningNodes
+                | ParameterType::NumClients => {}
+                _ => panic!("Parameter not supported"),
+            },
         }
     }
 }
@@ -172,6 +969,91 @@ impl NetworkConfiguration {
         }
     }
 
+    pub fn get_seed(&self) -> Option<u64> {
+        match self {
+            Self::Random { seed, .. } | Self::PreDefined { seed, .. } => *seed,
+        }
+    }
+
+    /// Makes this network (and all random decisions of a simulation of it) reproducible
+    pub fn set_seed(&mut self, new_seed: u64) {
+        match self {
+            Self::Random { seed, .. } | Self::PreDefined { seed, .. } => *seed = Some(new_seed),
+        }
+    }
+
+    /// Whether each node creates blocks (or votes), ordered by node index
+    pub fn get_mining_nodes(&self) -> Vec<bool> {
+        match self {
+            Self::Random {
+                num_mining_nodes, ..
+            } => (0..self.num_nodes())
+                .map(|idx| idx < *num_mining_nodes)
+                .collect(),
+            Self::PreDefined { nodes, .. } => nodes.iter().map(|node| node.is_mining).collect(),
+        }
+    }
+
+    /// The voting power of every node, ordered by node index
+    pub fn get_voting_power(&self) -> Vec<u64> {
+        match self {
+            Self::Random { voting_power, .. } => voting_power.get_voting_power(self.num_nodes()),
+            Self::PreDefined { nodes, .. } => nodes.iter().map(|node| node.voting_power).collect(),
+        }
+    }
+
+    /// Returns `None` if clients do not send queries
+    pub fn get_rpc_load(&self) -> Option<&RpcLoadConfig> {
+        match self {
+            Self::Random { workload, .. } => workload.rpc_load.as_ref(),
+            Self::PreDefined { .. } => None,
+        }
+    }
+
+    /// Returns `None` for predefined networks, where each client specifies its node
+    pub fn get_client_assignment(&self) -> Option<ClientAssignment> {
+        match self {
+            Self::Random { workload, .. } => Some(workload.client_assignment),
+            Self::PreDefined { .. } => None,
+        }
+    }
+
+    pub fn get_node_inbox(&self) -> Option<&InboxConfig> {
+        match self {
+            Self::Random { node_inbox, .. } | Self::PreDefined { node_inbox, .. } => {
+                node_inbox.as_ref()
+            }
+        }
+    }
+
+    /// Returns `None` if nodes never leave the network
+    pub fn get_churn(&self) -> Option<&ChurnConfig> {
+        match self {
+            Self::Random { churn, .. } | Self::PreDefined { churn, .. } => churn.as_ref(),
+        }
+    }
+
+    /// Returns `None` for predefined networks, which list their links explicitly
+    pub fn get_connectivity(&self) -> Option<&Connectivity> {
+        match self {
+            Self::Random { connectivity, .. } => Some(connectivity),
+            Self::PreDefined { .. } => None,
+        }
+    }
+
+    /// Can `set` be called with this parameter?
+    pub fn supports_parameter(&self, parameter: &ParameterType) -> bool {
+        match self {
+            Self::Random { .. } => true,
+            Self::PreDefined { .. } => !matches!(
+                parameter,
+                ParameterType::NumMiningNodes
+                    | ParameterType::NumNonMiningNodes
+                    | ParameterType::NumClients
+            ),
+        }
+    }
+
     pub fn set(&mut self, parameter: &ParameterType, value: ParameterValue) {
         match *self {
             Self::Random {
@@ -183,6 +1065,7 @@ impl NetworkConfiguration {
                 ParameterType::BlockSize
                 | ParameterType::MaxBlockSize
                 | ParameterType::GossipRetryDelay
+                | ParameterType::GossipFanout
                 | ParameterType::AcceptanceThreshold => {}
                 ParameterType::NumMiningNodes => {
                     *num_mining_nodes = value
@@ -204,6 +1087,7 @@ impl NetworkConfiguration {
                 ParameterType::BlockSize
                 | ParameterType::MaxBlockSize
                 | ParameterType::GossipRetryDelay
+                | ParameterType::GossipFanout
                 | ParameterType::AcceptanceThreshold => {}
                 ParameterType::NumMiningNodes
                 | ParameterType::NumNonMiningNodes
@@ -220,6 +1104,13 @@ pub struct NodeConfig {
     pub location: Location,
     pub bandwidth: u64,
     pub is_mining: bool,
+    /// Weight of the node's votes in BFT protocols
+    #[serde(default = "default_voting_power")]
+    pub voting_power: u64,
+}
+
+fn default_voting_power() -> u64 {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -235,6 +1126,33 @@ pub struct LinkConfig {
 pub struct ClientConfig {
     pub node: NodeIndex,
     pub transaction_interval: u64,
+    /// How many confirmations the client waits for (see `Workload::confirmation_depths`)
+    #[serde(default)]
+    pub confirmations: Option<u64>,
+    /// One-way latency between the client and its node (in milliseconds)
+    #[serde(default)]
+    pub latency: u64,
+}
+
+/// What happens to a message that arrives at a node with a full inbox
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Hold the message until there is space in the inbox
+    #[default]
+    Delay,
+    /// Discard the message
+    Drop,
+}
+
+/// Models the limited processing capacity of a node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxConfig {
+    /// How many messages can be queued at a node at once
+    pub capacity: usize,
+    /// How long it takes to process a single message (in microseconds)
+    pub processing_time: u64,
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,12 +1165,68 @@ pub enum NetworkConfiguration {
         link_bandwidth: Option<u64>,
         node_bandwidth: u64,
         connectivity: Connectivity,
+        /// Bounds the inbox of each node (unbounded if not set)
+        #[serde(default)]
+        node_inbox: Option<InboxConfig>,
+        #[serde(default)]
+        voting_power: VotingPowerDistribution,
+        /// Which nodes accept inbound connections
+        #[serde(default)]
+        reachability: Reachability,
+        /// Lets nodes leave and rejoin while the simulation runs (all nodes stay online if not set)
+        #[serde(default)]
+        churn: Option<ChurnConfig>,
+        /// Makes all random decisions reproducible (a random seed is picked if not set)
+        #[serde(default)]
+        seed: Option<u64>,
     },
     PreDefined {
         nodes: Vec<NodeConfig>,
         links: Vec<LinkConfig>,
         clients: Vec<ClientConfig>,
+        /// Bounds the inbox of each node (unbounded if not set)
+        #[serde(default)]
+        node_inbox: Option<InboxConfig>,
+        /// Lets nodes leave and rejoin while the simulation runs (all nodes stay online if not set)
+        #[serde(default)]
+        churn: Option<ChurnConfig>,
+        /// Makes all random decisions reproducible (a random seed is picked if not set)
+        #[serde(default)]
+        seed: Option<u64>,
+    },
+}
+
+/// Whether a node leaves or (re-)joins the network
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChurnAction {
+    Leave,
+    Join,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChurnEvent {
+    /// When the node leaves or joins (in milliseconds since the start of the simulation)
+    pub time: u64,
+    pub node: NodeIndex,
+    pub action: ChurnAction,
+}
+
+/// Nodes leaving and joining the network while the simulation runs
+///
+/// A node that left drops all messages and cannot be reached by its peers or clients,
+/// but keeps its state. Once it joins again, it syncs with its peers
+/// by requesting the blocks it missed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChurnConfig {
+    /// Every node alternates between being online and offline.
+    /// The length of each period is exponentially distributed with the given mean (in milliseconds).
+    Random {
+        mean_uptime: u64,
+        mean_downtime: u64,
     },
+    /// Nodes leave and join at the given times.
+    /// Nodes that leave at time zero only join the network later on.
+    Scripted(Vec<ChurnEvent>),
 }
 
 impl Default for NetworkConfiguration {
@@ -266,15 +1240,98 @@ impl Default for NetworkConfiguration {
             link_latency: 100,
             connectivity: Connectivity::Sparse {
                 min_conns_per_node: 5,
+                on_disconnected: Default::default(),
             },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
         }
     }
 }
 
+/// Which way a bridge moves assets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BridgeDirection {
+    #[default]
+    MainToSecondary,
+    SecondaryToMain,
+}
+
+/// A client that relays transactions between the two chains of a simulation
+///
+/// Each transfer locks funds on the source chain and, once that transaction
+/// is committed, releases the same amount on the destination chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    /// The node of the main chain the bridge is connected to
+    pub main_node: NodeIndex,
+    /// The node of the secondary chain the bridge is connected to
+    /// (counting from the first node of the secondary chain)
+    pub secondary_node: NodeIndex,
+    #[serde(default)]
+    pub direction: BridgeDirection,
+    /// How long to wait between two transfers (in milliseconds)
+    pub transfer_interval: u64,
+    /// How much each transfer moves between the chains
+    /// (only has an effect if the chains execute transactions)
+    #[serde(default = "default_bridge_amount")]
+    pub amount: u64,
+}
+
+fn default_bridge_amount() -> u64 {
+    1
+}
+
+/// Two parties that repeatedly exchange assets between the chains
+/// using hash time-locked contracts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AtomicSwapConfig {
+    /// The node of the main chain both parties are connected to
+    pub main_node: NodeIndex,
+    /// The node of the secondary chain both parties are connected to
+    /// (counting from the first node of the secondary chain)
+    pub secondary_node: NodeIndex,
+    /// When the initiator can refund its lock on the main chain
+    /// (in milliseconds after the swap started)
+    pub initiator_timeout: u64,
+    /// When the participant can refund its lock on the secondary chain
+    /// (in milliseconds after the swap started; must be less than `initiator_timeout`)
+    pub participant_timeout: u64,
+    /// How long to wait between two swaps (in milliseconds)
+    pub swap_interval: u64,
+}
+
+/// A second, independent chain that runs on a disjoint set of nodes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecondaryChainConfiguration {
+    pub protocol: ProtocolConfiguration,
+    pub network: NetworkConfiguration,
+    #[serde(default)]
+    pub bridges: Vec<BridgeConfig>,
+    #[serde(default)]
+    pub swaps: Vec<AtomicSwapConfig>,
+}
+
+/// Constraints on time series (e.g., `MetricType::Statistic`) apply to their mean,
+/// unless they say otherwise. Other metrics are treated as a series of a single value.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Constraint {
-    InRange { min: f64, max: f64 },
+    InRange {
+        min: f64,
+        max: f64,
+    },
     GreaterThan(f64),
+    /// Every sample must be within the range
+    AlwaysInRange {
+        min: f64,
+        max: f64,
+    },
+    /// Every sample must be within this deviation of the mean (e.g., 0.2 for ±20%)
+    Stable {
+        max_deviation: f64,
+    },
 }
 
 #[derive(
@@ -303,6 +1360,8 @@ pub enum ParameterType {
     AcceptanceThreshold,
     /// After what time should we try fetching data from another peer
     GossipRetryDelay,
+    /// To how many peers gossip relays a new block
+    GossipFanout,
 }
 
 impl TryFrom<&str> for ParameterType {
@@ -421,9 +1480,125 @@ pub struct Assert {
     pub constraint: Constraint,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Which nodes the faulty fraction of a `FailureConfig` is picked from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureTarget {
+    /// Every node except the first one is faulty with the given probability
+    #[default]
+    AnyNode,
+    /// The first mining nodes, which are the first to propose blocks in BFT protocols
+    Leaders,
+    /// The mining nodes with the most voting power or, if equal,
+    /// the members of the largest mining pools
+    TopMiners,
+}
+
+/// Crashes all nodes of some regions at once, e.g., to model a data center outage
+///
+/// Regions split the world into `num_regions` bands of equal longitude
+/// (see `ClientAssignment::Regional`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionOutage {
+    pub num_regions: u32,
+    /// The regions that go down
+    pub regions: Vec<u32>,
+    /// When the regions go down (in milliseconds since the start of the simulation)
+    #[serde(default)]
+    pub time: u64,
+}
+
+/// Splits the network into groups that cannot reach each other for some time
+///
+/// All nodes not listed in any group form one more group. If windows
+/// overlap, the one that starts later replaces the earlier one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionWindow {
+    /// When the partition begins (in milliseconds since the start of the simulation)
+    pub start: u64,
+    /// How long until the partition heals (in milliseconds)
+    pub duration: u64,
+    pub groups: Vec<Vec<NodeIndex>>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct FailureConfig {
+    /// The fraction of faulty nodes
+    /// With a target other than `AnyNode`, this is rounded to a number of mining nodes.
+    #[serde(default)]
     pub faulty_nodes: f64,
+    #[serde(default)]
+    pub target: FailureTarget,
+    /// Nodes that are faulty in addition to the ones picked by `faulty_nodes`
+    #[serde(default)]
+    pub nodes: Vec<NodeIndex>,
+    #[serde(default)]
+    pub region_outage: Option<RegionOutage>,
+    #[serde(default)]
+    pub asynchrony: Option<AsynchronyConfig>,
+    #[serde(default)]
+    pub partitions: Vec<PartitionWindow>,
+}
+
+/// A period during which the network does not deliver messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StallWindow {
+    /// When the stall begins (in milliseconds since the start of the simulation)
+    pub start: u64,
+    /// How long the stall lasts (in milliseconds)
+    pub length: u64,
+}
+
+/// Makes the network asynchronous for some time by stalling all messages
+///
+/// Messages that arrive at a node during a stall are only delivered
+/// once the stall ends. This allows testing the liveness of protocols
+/// that assume partial synchrony.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AsynchronyConfig {
+    /// Stall messages during the given windows
+    Windows(Vec<StallWindow>),
+    /// Stall messages for `stall_length` out of every `period` milliseconds
+    /// until the global stabilization time (`gst`, in milliseconds), after which
+    /// the network is synchronous
+    Periodic {
+        period: u64,
+        stall_length: u64,
+        gst: u64,
+    },
+}
+
+impl AsynchronyConfig {
+    /// When the stall that is going on at the given time ends
+    /// Returns `None` if messages are currently delivered
+    pub fn get_stall_end(&self, now: Time) -> Option<Time> {
+        match self {
+            Self::Windows(windows) => windows.iter().find_map(|window| {
+                let start = Time::from_millis(window.start);
+                let end = start + Duration::from_millis(window.length);
+                (now >= start && now < end).then_some(end)
+            }),
+            Self::Periodic {
+                period,
+                stall_length,
+                gst,
+            } => {
+                assert!(*period > 0, "Stall period must be non-zero");
+
+                let now = now.to_millis();
+                if now >= *gst {
+                    return None;
+                }
+
+                let offset = now % period;
+                if offset < *stall_length {
+                    let end = (now - offset + stall_length).min(*gst);
+                    Some(Time::from_millis(end))
+                } else {
+                    None
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -438,6 +1613,19 @@ pub struct ExperimentConfiguration {
     // We use a vec here to make sure parameters stay in the specified order
     pub data_ranges: Vec<(ParameterType, Interval)>,
     pub metrics: Vec<ChainMetricType>,
+
+    /// Also record wall-clock runtime, peak memory usage, and the number
+    /// of processed events for every step
+    #[serde(default)]
+    pub resource_usage: bool,
+}
+
+/// Compares the results of a test against a previous run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BaselineConfig {
+    pub metrics: Vec<MetricType>,
+    /// The maximum relative deviation from the baseline (e.g., 0.05 for 5%)
+    pub tolerance: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -446,6 +1634,55 @@ pub struct TestConfiguration {
     pub network: String,
     pub timeout: TimeoutConfig,
     pub asserts: Vec<Assert>,
+    #[serde(default)]
+    pub baseline: Option<BaselineConfig>,
+    /// How often to run the test (useful for random topologies)
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    /// How many of the runs may violate asserts without failing the test
+    #[serde(default)]
+    pub allowed_failures: u32,
+}
+
+fn default_repetitions() -> u32 {
+    1
+}
+
+/// Something that happens to the network at a specific time of a scenario
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ScenarioAction {
+    CrashNodes(Vec<NodeIndex>),
+    /// Nodes in different groups cannot reach each other;
+    /// all nodes not listed form another group
+    Partition(Vec<Vec<NodeIndex>>),
+    HealPartition,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScenarioEvent {
+    /// When the action happens (in seconds)
+    pub time: u64,
+    pub action: ScenarioAction,
+}
+
+/// Asserts that are checked at a specific time of a scenario
+///
+/// Metrics are computed over the time since the previous checkpoint.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// When to check the asserts (in seconds)
+    pub time: u64,
+    pub asserts: Vec<Assert>,
+}
+
+/// Scheduled failures and checks, e.g., to test if a protocol
+/// recovers once a partition heals
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScenarioConfiguration {
+    pub protocol: String,
+    pub network: String,
+    pub events: Vec<ScenarioEvent>,
+    pub checkpoints: Vec<Checkpoint>,
 }
 
 impl ExperimentConfiguration {
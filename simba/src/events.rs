@@ -1,13 +1,30 @@
+use std::collections::BTreeMap;
 use std::sync::{OnceLock, mpsc};
 
+use crate::anomalies::AnomalyThresholds;
+use crate::block_graph::{BlockDetails, BlockGraph};
 use crate::config::TimeoutConfig;
-use crate::logic::BlockId;
+use crate::fingerprint::Fingerprint;
+use crate::link::{LinkDirection, LinkDirectionStatistics};
+use crate::logic::{
+    AccountId, AccountState, BlockId, NodeChainInfo, TransactionId, TransactionSpec,
+    TransactionStatus,
+};
+use crate::memory::MemoryUsage;
 use crate::message::MessageType;
 use crate::node::NodeIndex;
 use crate::object::ObjectId;
-use crate::{ChainMetrics, GlobalStatistics, Location, NetworkMetricType, NodeStatistics};
+use crate::swap::SwapResults;
+use crate::{
+    ChainMetrics, ChainSplitStatistics, ClientInfo, ConfirmationStatistics, FeeMarketStatistics,
+    GlobalStatistics, LinkGoodput, Location, MempoolStatistics, MiningPoolStatistics,
+    NetworkMetricType, NodeStatistics, OrderingStatistics, PeerBanStatistics,
+    PropagationStatistics, StatisticsSnapshot,
+};
 
-use asim::time::Time;
+use asim::time::{Duration, Time};
+
+use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum OpRequest {
@@ -16,8 +33,62 @@ pub enum OpRequest {
     NodeLocation(NodeIndex),
     NodeStatistics(NodeIndex),
     NodeIdentifier(NodeIndex),
+    TransactionStatus {
+        node: NodeIndex,
+        transaction: TransactionId,
+    },
     GlobalStatistics,
+    ReorgHistogram,
+    CommitDelays,
+    LinkStatistics {
+        link: ObjectId,
+        direction: LinkDirection,
+    },
     CurrentTime,
+    /// Process the specified number of timer events
+    /// (only useful when the simulation is paused)
+    Step(u64),
+    /// Process timer events until the specified time is reached
+    /// (only useful when the simulation is paused)
+    StepUntil(Time),
+    Mempool(NodeIndex),
+    ChainHead(NodeIndex),
+    NodeChainInfo(NodeIndex),
+    AccountState {
+        node: NodeIndex,
+        account: AccountId,
+    },
+    InjectTransaction(NodeIndex),
+    SubmitTransaction {
+        node: NodeIndex,
+        spec: TransactionSpec,
+    },
+    CrashNode(NodeIndex),
+    SetAnomalyThresholds(AnomalyThresholds),
+    /// Nodes in different groups cannot reach each other;
+    /// all nodes not listed form another group
+    Partition(Vec<Vec<NodeIndex>>),
+    Fingerprint,
+    SecondaryChainMetrics(TimeoutConfig),
+    BridgeLatencies,
+    SwapResults,
+    MiningPoolStatistics,
+    ChainSplitStatistics,
+    PeerBanStatistics,
+    FeeMarketStatistics,
+    OrderingStatistics,
+    ConfirmationStatistics,
+    MempoolStatistics,
+    PropagationStatistics,
+    ClientAssignments,
+    LinkGoodput,
+    BlockGraph,
+    BlockDetails(BlockId),
+    NumProcessedEvents,
+    StatisticsHistory,
+    /// Memory used by the protocol structures of the specified node,
+    /// or of all nodes and the global logic if none is given
+    MemoryUsage(Option<NodeIndex>),
 }
 
 #[derive(PartialEq, Debug)]
@@ -26,30 +97,83 @@ pub enum OpResult {
     NetworkMetric(f64),
     NodeLocation(Location),
     NodeIdentifier(ObjectId),
+    TransactionStatus(TransactionStatus),
     CurrentTime(Time),
     NodeStatistics(NodeStatistics),
     GlobalStatistics(GlobalStatistics),
+    ReorgHistogram(BTreeMap<u64, u64>),
+    CommitDelays(Vec<Duration>),
+    LinkStatistics(LinkDirectionStatistics),
+    Mempool(Vec<TransactionId>),
+    ChainHead(Option<(BlockId, u64)>),
+    NodeChainInfo(Option<NodeChainInfo>),
+    AccountState(Option<AccountState>),
+    InjectedTransaction(Option<TransactionId>),
+    SubmittedTransaction(TransactionId),
+    Fingerprint(Fingerprint),
+    SecondaryChainMetrics(Option<ChainMetrics>),
+    BridgeLatencies(Vec<Duration>),
+    SwapResults(SwapResults),
+    MiningPoolStatistics(Vec<MiningPoolStatistics>),
+    ChainSplitStatistics(Option<ChainSplitStatistics>),
+    PeerBanStatistics(Option<PeerBanStatistics>),
+    FeeMarketStatistics(Option<FeeMarketStatistics>),
+    OrderingStatistics(OrderingStatistics),
+    ConfirmationStatistics(ConfirmationStatistics),
+    MempoolStatistics(MempoolStatistics),
+    PropagationStatistics(PropagationStatistics),
+    ClientAssignments(Vec<ClientInfo>),
+    LinkGoodput(Vec<LinkGoodput>),
+    BlockGraph(BlockGraph),
+    BlockDetails(Option<BlockDetails>),
+    NumProcessedEvents(u64),
+    StatisticsHistory(Vec<GlobalStatistics>),
+    MemoryUsage(MemoryUsage),
+    Done,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum LinkEvent {
-    Created { node1: NodeIndex, node2: NodeIndex },
+    Created {
+        node1: NodeIndex,
+        node2: NodeIndex,
+    },
     Active,
     Inactive,
+    /// The link connects two sides of a network partition
+    Severed,
+    /// The network partition was healed
+    Restored,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum NodeEvent {
+    /// Object identifiers are local to a process,
+    /// so this is sent as `RemoteEvent::NodeCreated` instead
+    #[serde(skip)]
     Created(ObjectId),
-    StatisticsUpdated,
+    /// The node's inbox is full and messages are being delayed or dropped
+    Overloaded,
+    /// The node has drained its inbox after being overloaded
+    Recovered,
+    /// The node stopped processing messages for the rest of the simulation
+    Crashed,
+    /// The node moved into a different side of a network partition
+    /// (`None` once the partition is healed)
+    Partitioned { group: Option<u32> },
+    /// The node left the network and is unreachable until it joins again
+    Removed,
+    /// The node (re-)joined the network
+    Joined,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum StatisticsEvent {
-    Updated,
+    /// Contains the latest data of all nodes
+    Updated(StatisticsSnapshot),
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum BlockEvent {
     Created {
         height: u64,
@@ -57,6 +181,20 @@ pub enum BlockEvent {
         uncles: Vec<BlockId>,
         num_transactions: usize,
     },
+    /// A node switched its chain head to another fork
+    Reorg {
+        old_head: BlockId,
+        new_head: BlockId,
+        /// How many blocks of the old fork were undone
+        depth: u64,
+    },
+    /// The block is considered final
+    /// This is only emitted once; when the first node commits the block
+    Committed {
+        /// Time from block creation until it was committed
+        #[serde(with = "crate::serde_time::duration_micros")]
+        delay: Duration,
+    },
 }
 
 #[derive(PartialEq, Debug)]
@@ -91,6 +229,7 @@ pub enum Event {
 #[derive(PartialEq, Eq, Debug)]
 pub enum Command {
     SetTimeout(TimeoutConfig),
+    PauseAt(Time),
     EnableEvents,
     OpRequest { op_id: u64, request: OpRequest },
     Destroy,
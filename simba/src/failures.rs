@@ -1,41 +1,123 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::config::FailureConfig;
+use crate::config::{
+    AsynchronyConfig, FailureConfig, FailureTarget, NetworkConfiguration, PartitionWindow,
+    ProtocolConfiguration, RegionOutage,
+};
 use crate::node::NodeIndex;
+use crate::random;
 
 pub struct Failures {
     num_nodes: u32,
     num_faulty_nodes: u32,
     faulty_nodes: Vec<bool>,
+    asynchrony: Option<AsynchronyConfig>,
+    region_outage: Option<RegionOutage>,
+    partitions: Vec<PartitionWindow>,
+}
+
+/// The nodes that are faulty regardless of the seed, i.e., the ones listed explicitly
+/// and the ones picked by a target other than `FailureTarget::AnyNode`
+pub(crate) fn get_targeted_nodes(
+    protocol: &ProtocolConfiguration,
+    network: &NetworkConfiguration,
+    config: &FailureConfig,
+) -> Vec<NodeIndex> {
+    let mining_nodes = network.get_mining_nodes();
+    let mut candidates: Vec<NodeIndex> = (0..mining_nodes.len() as NodeIndex)
+        .filter(|idx| mining_nodes[*idx as usize])
+        .collect();
+
+    match config.target {
+        FailureTarget::AnyNode => candidates.clear(),
+        // Proposer schedules start with the mining node with the lowest index
+        FailureTarget::Leaders => {}
+        FailureTarget::TopMiners => {
+            let voting_power = network.get_voting_power();
+            let pools = match protocol {
+                ProtocolConfiguration::NakamotoConsensus { mining_pools, .. } => {
+                    mining_pools.as_slice()
+                }
+                _ => &[],
+            };
+            let get_pool_size = |idx: &NodeIndex| {
+                pools
+                    .iter()
+                    .find(|pool| pool.members.contains(idx))
+                    .map_or(1, |pool| pool.members.len())
+            };
+
+            // Stable, so that ties keep ascending node indices
+            candidates.sort_by_key(|idx| {
+                std::cmp::Reverse((voting_power[*idx as usize], get_pool_size(idx)))
+            });
+        }
+    }
+
+    let num_targeted = (config.faulty_nodes * candidates.len() as f64).round() as usize;
+    candidates.truncate(num_targeted);
+
+    for idx in config.nodes.iter() {
+        if !candidates.contains(idx) {
+            candidates.push(*idx);
+        }
+    }
+
+    candidates.sort();
+    candidates
 }
 
 impl Failures {
-    pub fn new(num_nodes: u32, config: Option<FailureConfig>) -> Self {
+    /// Faulty nodes are picked as specified by `config`;
+    /// random picks are reproducible, as the same seed always picks the same nodes
+    pub fn new(
+        protocol: &ProtocolConfiguration,
+        network: &NetworkConfiguration,
+        config: Option<FailureConfig>,
+        seed: Option<u64>,
+    ) -> Self {
+        let num_nodes = network.num_nodes();
         let Some(config) = config else {
             return Self::none(num_nodes);
         };
 
-        let mut num_faulty_nodes = 0;
         let mut faulty_nodes = vec![false; num_nodes as usize];
 
-        //FIXME node0 still has a special role in some protocols
-        for idx in 1..num_nodes {
-            let faulty = {
-                let rand = rand::rng().random_range(0.0..1.0);
-                rand < config.faulty_nodes
+        for idx in get_targeted_nodes(protocol, network, &config) {
+            let Some(faulty) = faulty_nodes.get_mut(idx as usize) else {
+                panic!("Cannot make non-existent node #{idx} faulty");
             };
+            *faulty = true;
+        }
 
-            if faulty {
-                log::debug!("Node #{idx} is faulty");
-                faulty_nodes[idx as usize] = true;
-                num_faulty_nodes += 1;
+        if config.target == FailureTarget::AnyNode {
+            let mut rng = StdRng::seed_from_u64(random::resolve_seed(seed));
+
+            //FIXME node0 still has a special role in some protocols
+            for idx in 1..num_nodes {
+                let rand = rng.random_range(0.0..1.0);
+                if rand < config.faulty_nodes {
+                    faulty_nodes[idx as usize] = true;
+                }
             }
         }
 
+        for (idx, _) in faulty_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, faulty)| **faulty)
+        {
+            log::debug!("Node #{idx} is faulty");
+        }
+
         Self {
             num_nodes,
-            num_faulty_nodes,
+            num_faulty_nodes: faulty_nodes.iter().filter(|faulty| **faulty).count() as u32,
             faulty_nodes,
+            asynchrony: config.asynchrony,
+            region_outage: config.region_outage,
+            partitions: config.partitions,
         }
     }
 
@@ -44,15 +126,97 @@ impl Failures {
             num_nodes,
             num_faulty_nodes: 0,
             faulty_nodes: vec![false; num_nodes as usize],
+            asynchrony: None,
+            region_outage: None,
+            partitions: vec![],
         }
     }
 
+    /// Stall messages according to the given configuration
+    pub fn with_asynchrony(mut self, asynchrony: AsynchronyConfig) -> Self {
+        self.asynchrony = Some(asynchrony);
+        self
+    }
+
+    pub fn get_asynchrony(&self) -> Option<&AsynchronyConfig> {
+        self.asynchrony.as_ref()
+    }
+
+    /// Partition the network during the given windows
+    pub fn with_partitions(mut self, partitions: Vec<PartitionWindow>) -> Self {
+        self.partitions = partitions;
+        self
+    }
+
+    pub fn get_partitions(&self) -> &[PartitionWindow] {
+        &self.partitions
+    }
+
+    /// Returns `None` if no region goes down
+    pub fn get_region_outage(&self) -> Option<&RegionOutage> {
+        self.region_outage.as_ref()
+    }
+
     pub fn num_correct_nodes(&self) -> u32 {
         self.num_nodes - self.num_faulty_nodes
     }
 
+    /// The indices of all faulty nodes (in ascending order)
+    pub fn get_faulty_nodes(&self) -> Vec<NodeIndex> {
+        (0..self.num_nodes)
+            .filter(|idx| self.faulty_nodes[*idx as usize])
+            .collect()
+    }
+
     pub fn is_faulty(&self, index: &NodeIndex) -> bool {
         let index = *index as usize;
         *self.faulty_nodes.get(index).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::VotingPowerDistribution;
+
+    fn make_network(voting_power: VotingPowerDistribution) -> NetworkConfiguration {
+        let mut network = NetworkConfiguration::default();
+        if let NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes,
+            voting_power: power,
+            ..
+        } = &mut network
+        {
+            *num_mining_nodes = 4;
+            *num_non_mining_nodes = 2;
+            *power = voting_power;
+        }
+        network
+    }
+
+    #[test]
+    fn targeted_failures() {
+        let protocol = ProtocolConfiguration::default();
+        let network = make_network(VotingPowerDistribution::Explicit(vec![1, 5, 1, 3]));
+
+        let leaders = FailureConfig {
+            faulty_nodes: 0.5,
+            target: FailureTarget::Leaders,
+            ..Default::default()
+        };
+        let failures = Failures::new(&protocol, &network, Some(leaders), None);
+        assert_eq!(failures.get_faulty_nodes(), vec![0, 1]);
+
+        let top_miners = FailureConfig {
+            faulty_nodes: 0.5,
+            target: FailureTarget::TopMiners,
+            nodes: vec![5],
+            ..Default::default()
+        };
+        let failures = Failures::new(&protocol, &network, Some(top_miners), None);
+        assert_eq!(failures.get_faulty_nodes(), vec![1, 3, 5]);
+        assert_eq!(failures.num_correct_nodes(), 3);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A digest of the final state of a simulation run
+///
+/// Two runs with the same configuration and the same random inputs
+/// must produce the same fingerprint. A mismatch indicates nondeterminism.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint(u64);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(fmt, "{:016x}", self.0)
+    }
+}
+
+/// Computes a fingerprint using 64-bit FNV-1a
+///
+/// We do not use `std::hash::DefaultHasher` here, because its output
+/// is not guaranteed to be stable across Rust releases.
+pub(crate) struct FingerprintBuilder {
+    state: u64,
+}
+
+impl Default for FingerprintBuilder {
+    fn default() -> Self {
+        Self {
+            state: Self::OFFSET_BASIS,
+        }
+    }
+}
+
+impl FingerprintBuilder {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    pub fn add_u64(&mut self, value: u64) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    pub fn add_u128(&mut self, value: u128) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+
+    pub fn finish(self) -> Fingerprint {
+        Fingerprint(self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FingerprintBuilder;
+
+    #[test]
+    fn known_values() {
+        // Reference values of the FNV-1a specification
+        assert_eq!(FingerprintBuilder::default().finish().0, 0xcbf29ce484222325);
+
+        let mut builder = FingerprintBuilder::default();
+        builder.add_bytes(b"a");
+        assert_eq!(builder.finish().0, 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn order_matters() {
+        let mut first = FingerprintBuilder::default();
+        first.add_u64(1);
+        first.add_u64(2);
+
+        let mut second = FingerprintBuilder::default();
+        second.add_u64(2);
+        second.add_u64(1);
+
+        assert_ne!(first.finish(), second.finish());
+    }
+}
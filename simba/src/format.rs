@@ -0,0 +1,203 @@
+//! Human-friendly formatting of large numbers, data rates, and durations
+//!
+//! Used by run summaries, reports, and the visualizer, so that values like
+//! `1234567890` are shown as `1.2 Gbit/s` instead.
+
+use asim::time::{Duration, Time};
+
+/// Metric prefixes used for large values
+const PREFIXES: [(f64, &str); 4] = [(1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "k")];
+
+/// How numbers are written, e.g., `1,234.5` in English or `1.234,5` in German
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Groups the digits of large numbers (if set)
+    pub thousands_separator: Option<char>,
+    pub decimal_separator: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self::ENGLISH
+    }
+}
+
+impl NumberFormat {
+    pub const ENGLISH: Self = Self {
+        thousands_separator: Some(','),
+        decimal_separator: '.',
+    };
+
+    /// Picks the format based on a POSIX locale name, e.g., "de_DE.UTF-8"
+    ///
+    /// Unknown locales use the English format
+    pub fn from_locale(locale: &str) -> Self {
+        let language = locale
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default();
+
+        match language {
+            "de" | "da" | "es" | "id" | "it" | "nl" | "pt" | "tr" => Self {
+                thousands_separator: Some('.'),
+                decimal_separator: ',',
+            },
+            "cs" | "fi" | "fr" | "nb" | "pl" | "ru" | "sv" | "uk" => Self {
+                // Narrow no-break space
+                thousands_separator: Some('\u{202F}'),
+                decimal_separator: ',',
+            },
+            _ => Self::ENGLISH,
+        }
+    }
+
+    /// Uses the locale set in the environment (`LC_ALL`, `LC_NUMERIC`, or `LANG`)
+    pub fn from_env() -> Self {
+        for var in ["LC_ALL", "LC_NUMERIC", "LANG"] {
+            if let Ok(locale) = std::env::var(var)
+                && !locale.is_empty()
+            {
+                return Self::from_locale(&locale);
+            }
+        }
+
+        Self::default()
+    }
+
+    /// E.g., `1,234,567`
+    pub fn integer(&self, value: u64) -> String {
+        self.group_digits(&value.to_string())
+    }
+
+    /// E.g., `1,234.57` for a precision of two
+    pub fn decimal(&self, value: f64, precision: usize) -> String {
+        let formatted = format!("{:.precision$}", value.abs());
+        let (integral, fraction) = match formatted.split_once('.') {
+            Some((integral, fraction)) => (integral, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+
+        let mut result = String::new();
+        if value.is_sign_negative() && value != 0.0 {
+            result.push('-');
+        }
+
+        result.push_str(&self.group_digits(integral));
+        if let Some(fraction) = fraction {
+            result.push(self.decimal_separator);
+            result.push_str(fraction);
+        }
+
+        result
+    }
+
+    /// Abbreviates large counts, e.g., `3.4 M txns`
+    ///
+    /// Counts below a thousand are written out in full
+    pub fn count(&self, value: u64, unit: &str) -> String {
+        match Self::scale(value as f64) {
+            (scaled, Some(prefix)) => format!("{} {prefix} {unit}", self.decimal(scaled, 1)),
+            (_, None) => format!("{} {unit}", self.integer(value)),
+        }
+    }
+
+    /// E.g., `1.2 Gbit/s`
+    pub fn bitrate(&self, bits_per_second: f64) -> String {
+        match Self::scale(bits_per_second) {
+            (scaled, Some(prefix)) => format!("{} {prefix}bit/s", self.decimal(scaled, 1)),
+            (_, None) => format!("{} bit/s", self.decimal(bits_per_second, 0)),
+        }
+    }
+
+    /// E.g., `2h 13m`, `4m 05s`, `12.5s`, or `350ms`
+    pub fn duration(&self, duration: Duration) -> String {
+        let seconds = duration.as_seconds_f64();
+
+        if seconds >= 3600.0 {
+            let minutes = (seconds / 60.0) as u64;
+            format!("{}h {:02}m", self.integer(minutes / 60), minutes % 60)
+        } else if seconds >= 60.0 {
+            let seconds = seconds as u64;
+            format!("{}m {:02}s", seconds / 60, seconds % 60)
+        } else if seconds >= 1.0 {
+            format!("{}s", self.decimal(seconds, 1))
+        } else {
+            format!("{}ms", self.decimal(seconds * 1000.0, 0))
+        }
+    }
+
+    /// The virtual time elapsed since the simulation started
+    pub fn time(&self, time: Time) -> String {
+        self.duration(Duration::from_millis(time.to_millis()))
+    }
+
+    /// Picks the largest prefix that keeps the value at or above one
+    fn scale(value: f64) -> (f64, Option<&'static str>) {
+        PREFIXES
+            .iter()
+            .find(|(factor, _)| value.abs() >= *factor)
+            .map_or((value, None), |(factor, prefix)| {
+                (value / factor, Some(*prefix))
+            })
+    }
+
+    fn group_digits(&self, digits: &str) -> String {
+        let Some(separator) = self.thousands_separator else {
+            return digits.to_string();
+        };
+
+        let mut result = String::new();
+        for (idx, digit) in digits.chars().enumerate() {
+            if idx > 0 && (digits.len() - idx) % 3 == 0 {
+                result.push(separator);
+            }
+            result.push(digit);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asim::time::Duration;
+
+    use super::NumberFormat;
+
+    #[test]
+    fn english() {
+        let format = NumberFormat::default();
+
+        assert_eq!(format.integer(1_234_567), "1,234,567");
+        assert_eq!(format.integer(999), "999");
+        assert_eq!(format.decimal(-1234.567, 2), "-1,234.57");
+        assert_eq!(format.count(3_400_000, "txns"), "3.4 M txns");
+        assert_eq!(format.count(950, "txns"), "950 txns");
+        assert_eq!(format.bitrate(1.2e9), "1.2 Gbit/s");
+        assert_eq!(format.bitrate(512.0), "512 bit/s");
+    }
+
+    #[test]
+    fn locales() {
+        let german = NumberFormat::from_locale("de_DE.UTF-8");
+        assert_eq!(german.decimal(1234.5, 1), "1.234,5");
+
+        let french = NumberFormat::from_locale("fr_FR");
+        assert_eq!(french.integer(12_345), "12\u{202F}345");
+
+        assert_eq!(NumberFormat::from_locale("C"), NumberFormat::ENGLISH);
+    }
+
+    #[test]
+    fn durations() {
+        let format = NumberFormat::default();
+
+        assert_eq!(
+            format.duration(Duration::from_seconds(2 * 3600 + 13 * 60)),
+            "2h 13m"
+        );
+        assert_eq!(format.duration(Duration::from_seconds(245)), "4m 05s");
+        assert_eq!(format.duration(Duration::from_millis(12_500)), "12.5s");
+        assert_eq!(format.duration(Duration::from_millis(350)), "350ms");
+    }
+}
@@ -0,0 +1,138 @@
+use hdrhistogram::Histogram;
+
+use asim::time::Duration;
+
+/// The largest latency that can be told apart from others (one day, in microseconds)
+/// Anything above this is recorded as this value.
+const MAX_LATENCY: u64 = 24 * 60 * 60 * 1_000_000;
+
+/// Values are accurate to two significant digits (1%)
+const SIGNIFICANT_DIGITS: u8 = 2;
+
+/// Records latencies in constant memory
+///
+/// Only the distribution is kept, not the individual values,
+/// so long runs do not need more memory than short ones.
+/// The mean is tracked exactly; percentiles are accurate to 1%.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyHistogram {
+    /// In microseconds
+    histogram: Histogram<u64>,
+    /// Sum of all recorded latencies in milliseconds
+    total: f64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        let histogram = Histogram::new_with_bounds(1, MAX_LATENCY, SIGNIFICANT_DIGITS)
+            .expect("Invalid histogram bounds");
+
+        Self {
+            histogram,
+            total: 0.0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let millis = latency.as_millis_f64();
+        self.histogram
+            .saturating_record((millis * 1000.0).round() as u64);
+        self.total += millis;
+    }
+
+    /// Adds all values of another histogram to this one
+    pub fn merge(&mut self, other: &Self) {
+        self.histogram
+            .add(&other.histogram)
+            .expect("Histograms have the same bounds");
+        self.total += other.total;
+    }
+
+    /// The number of recorded values
+    pub fn len(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    /// The sum of all recorded latencies (in milliseconds)
+    pub fn get_total(&self) -> f64 {
+        self.total
+    }
+
+    /// The average latency (in milliseconds)
+    pub fn get_mean(&self) -> f64 {
+        if self.is_empty() {
+            0.0
+        } else {
+            self.total / (self.len() as f64)
+        }
+    }
+
+    /// The latency below which the given percentage (0 to 100) of values fall
+    pub fn get_percentile(&self, percentile: f64) -> Duration {
+        assert!(
+            (0.0..=100.0).contains(&percentile),
+            "Invalid percentile {percentile}"
+        );
+
+        let micros = self.histogram.value_at_quantile(percentile / 100.0);
+        Duration::from_micros(micros)
+    }
+
+    pub fn get_max(&self) -> Duration {
+        Duration::from_micros(self.histogram.max())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asim::time::Duration;
+
+    use super::LatencyHistogram;
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(histogram.len(), 100);
+        assert_eq!(histogram.get_mean(), 50.5);
+
+        let median = histogram.get_percentile(50.0).as_millis_f64();
+        assert!((median - 50.0).abs() <= 0.5, "median was {median}");
+
+        let tail = histogram.get_percentile(99.0).as_millis_f64();
+        assert!((tail - 99.0).abs() <= 1.0, "99th percentile was {tail}");
+    }
+
+    #[test]
+    fn merge() {
+        let mut first = LatencyHistogram::default();
+        first.record(Duration::from_millis(10));
+
+        let mut second = LatencyHistogram::default();
+        second.record(Duration::from_millis(30));
+        second.record(Duration::from_millis(50));
+
+        first.merge(&second);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first.get_total(), 90.0);
+        assert_eq!(first.get_mean(), 30.0);
+    }
+
+    #[test]
+    fn empty() {
+        let histogram = LatencyHistogram::default();
+
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.get_mean(), 0.0);
+        assert_eq!(histogram.get_percentile(99.0), Duration::from_micros(0));
+    }
+}
@@ -1,5 +1,6 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU32, Ordering};
 
@@ -11,20 +12,55 @@ use derivative::Derivative;
 
 use crate::emit_event;
 use crate::events::{BlockEvent, Event};
-use crate::logic::{AccountState, Block, BlockId, SIGNATURE_SIZE, Transaction, TransactionId};
+use crate::fingerprint::FingerprintBuilder;
+use crate::logic::{
+    AccountState, Block, BlockId, GENESIS_BLOCK, SIGNATURE_SIZE, Transaction, TransactionId,
+};
+use crate::memory::{self, MemoryUsage};
 use crate::node::NodeIndex;
 
-use asim::time::Time;
+use asim::sync::{Condvar, Mutex};
+use asim::time::{Duration, Time};
 
 pub type SlotNumber = u64;
 
 pub struct ConventionalGlobalLedger {
     all_blocks: RefCell<HashMap<BlockId, Rc<ConventionalBlock>>>,
     latest_commit: RefCell<Option<BlockId>>,
+    commit_height: Rc<CommitHeight>,
+    genesis_state: FrozenCowTree<AccountState>,
 }
 
+/// The height of the latest commit, which tasks can wait for
+#[derive(Default)]
+pub struct CommitHeight {
+    height: Mutex<u64>,
+    cond: Condvar,
+}
+
+impl CommitHeight {
+    /// Returns once a block at the given height (or above) was committed
+    pub async fn wait_for(&self, height: u64) {
+        let mut current = self.height.lock().await;
+        while *current < height {
+            current = self.cond.wait(current).await;
+        }
+    }
+
+    async fn set(&self, height: u64) {
+        let mut current = self.height.lock().await;
+        *current = (*current).max(height);
+        self.cond.notify_all();
+    }
+}
+
+/// Highest fee first; oldest first if fees are equal
+type TransactionPriority = (Reverse<u64>, Time, TransactionId);
+
 pub struct ConventionalNodeLedger {
     mempool: HashMap<TransactionId, Rc<Transaction>>,
+    /// All transactions of the mempool in the order they are added to blocks
+    by_priority: BTreeSet<TransactionPriority>,
 }
 
 #[derive(Derivative)]
@@ -124,13 +160,24 @@ impl ConventionalBlock {
 impl GlobalLedger for ConventionalGlobalLedger {}
 
 impl ConventionalGlobalLedger {
-    pub fn new() -> Self {
+    pub fn new(genesis_state: FrozenCowTree<AccountState>) -> Self {
         Self {
             all_blocks: Default::default(),
             latest_commit: RefCell::new(None),
+            commit_height: Default::default(),
+            genesis_state,
         }
     }
 
+    /// The state before any blocks were created
+    pub fn get_genesis_state(&self) -> &FrozenCowTree<AccountState> {
+        &self.genesis_state
+    }
+
+    pub fn has_committed_block(&self) -> bool {
+        self.latest_commit.borrow().is_some()
+    }
+
     pub fn get_latest_commit(&self) -> BlockId {
         self.latest_commit.borrow().expect("No block committed")
     }
@@ -139,13 +186,64 @@ impl ConventionalGlobalLedger {
         self.all_blocks.borrow().get(block_id).cloned()
     }
 
+    /// Allows waiting for the chain to grow without holding on to the ledger
+    pub fn get_commit_height(&self) -> Rc<CommitHeight> {
+        self.commit_height.clone()
+    }
+
+    /// Blocks are owned by the global ledger, so they are counted here and not by the nodes
+    pub fn get_memory_usage(&self) -> MemoryUsage {
+        let all_blocks = self.all_blocks.borrow();
+        let block_size = size_of::<ConventionalBlock>() as u64;
+
+        let mut usage = MemoryUsage::default();
+        usage.add(
+            "all_blocks",
+            memory::map_size(&all_blocks) + (all_blocks.len() as u64) * block_size,
+        );
+        usage
+    }
+
+    /// Adds all committed blocks to the fingerprint (starting at the latest)
+    pub fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) {
+        let Some(mut block_id) = *self.latest_commit.borrow() else {
+            return;
+        };
+
+        while block_id != GENESIS_BLOCK {
+            let block = self.get_block(&block_id).expect("Missing block");
+            fingerprint.add_u128(block_id);
+            fingerprint.add_u64(block.get_slot_number());
+            fingerprint.add_u64(block.num_transactions() as u64);
+            block_id = *block.get_parent_id();
+        }
+    }
+
     pub fn num_blocks(&self) -> usize {
         self.all_blocks.borrow().len()
     }
 
-    pub fn set_latest_commit(&self, block_id: BlockId) {
+    /// Marks the block as finalized
+    /// Returns the time from block creation until it was finalized
+    pub fn set_latest_commit(&self, block_id: BlockId) -> Duration {
+        let block = self.get_block(&block_id).expect("No such block");
+        let delay = asim::time::now() - block.get_creation_time();
+
         let mut lock = self.latest_commit.borrow_mut();
         *lock = Some(block_id);
+
+        let commit_height = self.commit_height.clone();
+        let height = block.get_height();
+        asim::spawn(async move {
+            commit_height.set(height).await;
+        });
+
+        emit_event!(Event::Block {
+            identifier: block_id,
+            event: BlockEvent::Committed { delay },
+        });
+
+        delay
     }
 
     pub fn add_block(&self, block_id: BlockId, block: Rc<ConventionalBlock>) {
@@ -171,30 +269,72 @@ impl NodeLedger for ConventionalNodeLedger {}
 
 impl ConventionalNodeLedger {
     pub fn new() -> Self {
-        let mempool = Default::default();
-        Self { mempool }
+        Self {
+            mempool: Default::default(),
+            by_priority: Default::default(),
+        }
+    }
+
+    /// There is no base fee, so block producers receive the entire fee (up to the tip)
+    fn get_priority(transaction: &Transaction) -> TransactionPriority {
+        let fee = transaction.get_fee().get_effective_tip(0).unwrap_or(0);
+        (
+            Reverse(fee),
+            transaction.get_creation_time(),
+            *transaction.get_identifier(),
+        )
     }
 
     // Add a new transaction; returns true if the txn was not known
     pub fn add_transaction(&mut self, transaction: Rc<Transaction>) -> bool {
-        let prev = self
-            .mempool
-            .insert(*transaction.get_identifier(), transaction);
-        prev.is_none()
+        let txn_id = *transaction.get_identifier();
+        if self.mempool.contains_key(&txn_id) {
+            return false;
+        }
+
+        self.by_priority.insert(Self::get_priority(&transaction));
+        self.mempool.insert(txn_id, transaction);
+        true
+    }
+
+    /// Remove transactions that were included in a block
+    pub fn remove_transactions(&mut self, transactions: &[Rc<Transaction>]) {
+        for txn in transactions {
+            if self.mempool.remove(txn.get_identifier()).is_some() {
+                self.by_priority.remove(&Self::get_priority(txn));
+            }
+        }
     }
 
     pub fn get_mempool_size(&self) -> u32 {
         self.mempool.len() as u32
     }
 
+    pub fn get_mempool(&self) -> Vec<TransactionId> {
+        self.mempool.keys().copied().collect()
+    }
+
+    pub fn add_memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.add(
+            "mempool",
+            memory::map_size(&self.mempool) + memory::btree_set_size(&self.by_priority),
+        );
+    }
+
+    /// Removes the (at most) `max_block_size` transactions with the highest fees from the mempool
+    /// The rest stays in the mempool for later blocks
     pub fn get_transactions_from_mempool(&mut self, max_block_size: u32) -> Vec<Rc<Transaction>> {
         let mut transactions = vec![];
 
-        for (_, txn) in self.mempool.drain() {
-            if (transactions.len() as u32) >= max_block_size {
+        while (transactions.len() as u32) < max_block_size {
+            let Some((_, _, txn_id)) = self.by_priority.pop_first() else {
                 break;
-            }
+            };
 
+            let txn = self
+                .mempool
+                .remove(&txn_id)
+                .expect("Mempool is inconsistent");
             transactions.push(txn);
         }
 
@@ -1,9 +1,185 @@
+use std::rc::Rc;
+
+use cow_tree::{CowTree, FrozenCowTree, Hash};
+
+use crate::config::{ExecutionConfig, GenesisAccount, TransactionModel};
+use crate::logic::{AccountId, AccountState, Transaction, TransactionOutput};
+
 mod conventional;
 mod nakamoto;
 
+mod utxo;
+pub(crate) use utxo::{
+    add_output, apply_utxo_transaction, genesis_output_id, get_utxo_balance, make_genesis_outputs,
+};
+
 pub use conventional::*;
 pub use nakamoto::*;
 
+/// Maps an account identifier to its position in the state tree
+pub(crate) fn account_key(account: &AccountId) -> Hash {
+    let mut key = Hash::default();
+    key[..size_of::<AccountId>()].copy_from_slice(&account.to_le_bytes());
+    key
+}
+
+/// Creates the state the first block builds on
+pub(crate) fn make_genesis_state(accounts: &[GenesisAccount]) -> FrozenCowTree<AccountState> {
+    let mut state = CowTree::default();
+
+    for account in accounts {
+        state.insert(
+            &account_key(&account.account),
+            AccountState::new(account.balance),
+        );
+    }
+
+    state.freeze()
+}
+
+/// Looks up the balance and nonce of an account in the given state
+pub(crate) fn get_account_state(
+    state: &FrozenCowTree<AccountState>,
+    account: &AccountId,
+) -> Option<AccountState> {
+    state.get(&account_key(account)).cloned()
+}
+
+/// Looks up the nonce of an account in the given state
+/// Accounts that never sent a transaction have a nonce of zero.
+pub(crate) fn get_nonce(state: &FrozenCowTree<AccountState>, account: &AccountId) -> u64 {
+    state
+        .get(&account_key(account))
+        .map_or(0, AccountState::get_nonce)
+}
+
+/// Why a transaction cannot be applied to a state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub(crate) enum InvalidTransaction {
+    /// The account already used this nonce (or a higher one)
+    StaleNonce,
+    /// The account has not used all lower nonces yet
+    FutureNonce,
+    /// The account (or the inputs) cannot pay for the transfer and the fee
+    InsufficientBalance,
+    /// An input does not exist (yet) or was spent already
+    SpentInput,
+    /// An input belongs to another account
+    ForeignInput,
+}
+
+impl InvalidTransaction {
+    /// Can the transaction become valid once other transactions were applied?
+    /// Missing inputs might still be created by a transaction that was not included yet.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::StaleNonce | Self::ForeignInput)
+    }
+}
+
+/// Adds funds to an account, creating the account if it does not exist yet
+pub(crate) fn credit(state: &mut CowTree<AccountState>, account: &AccountId, amount: u64) {
+    let key = account_key(account);
+    let found = state.update(&key, |account_state| {
+        account_state.set_balance(account_state.get_balance().saturating_add(amount));
+    });
+
+    if !found {
+        state.insert(&key, AccountState::new(amount));
+    }
+}
+
+/// Charges the transfer and fee of a transaction to its source and bumps the account's nonce
+///
+/// The base fee (if any) is burned; returns the tip, which the caller pays to the block producer.
+/// Does not modify the state if the transaction is invalid.
+pub(crate) fn apply_transaction(
+    state: &mut CowTree<AccountState>,
+    transaction: &Transaction,
+    base_fee: Option<u64>,
+) -> Result<u64, InvalidTransaction> {
+    let key = account_key(transaction.get_source());
+    let (balance, nonce) = state
+        .get(&key)
+        .map(|account_state| (account_state.get_balance(), account_state.get_nonce()))
+        .unwrap_or((0, 0));
+
+    if transaction.get_nonce() <= nonce {
+        return Err(InvalidTransaction::StaleNonce);
+    } else if transaction.get_nonce() > nonce + 1 {
+        return Err(InvalidTransaction::FutureNonce);
+    }
+
+    let base_fee = base_fee.unwrap_or(0);
+    let tip = transaction
+        .get_fee()
+        .get_effective_tip(base_fee)
+        .ok_or(InvalidTransaction::InsufficientBalance)?;
+    let amount = transaction
+        .get_transfer()
+        .map_or(0, |transfer| transfer.amount);
+
+    let cost = amount
+        .checked_add(base_fee + tip)
+        .filter(|cost| *cost <= balance)
+        .ok_or(InvalidTransaction::InsufficientBalance)?;
+
+    let charge = |account_state: &mut AccountState| {
+        account_state.set_balance(balance - cost);
+        account_state.set_nonce(transaction.get_nonce());
+    };
+
+    if !state.update(&key, charge) {
+        let mut account_state = AccountState::new(0);
+        charge(&mut account_state);
+        state.insert(&key, account_state);
+    }
+
+    if let Some(transfer) = transaction.get_transfer() {
+        credit(state, &transfer.recipient, transfer.amount);
+    }
+
+    Ok(tip)
+}
+
+/// Applies the transactions of a block and pays the block reward and all tips to its miner
+///
+/// Transactions that cannot be applied do not modify the state or outputs.
+/// Returns whether each transaction was applied, in order.
+pub(crate) fn execute_transactions(
+    execution: &ExecutionConfig,
+    state: &mut CowTree<AccountState>,
+    outputs: &mut CowTree<TransactionOutput>,
+    transactions: &[Rc<Transaction>],
+    mined_by: &AccountId,
+    base_fee: Option<u64>,
+) -> Vec<Result<(), InvalidTransaction>> {
+    let mut tips: u64 = 0;
+
+    let results = transactions
+        .iter()
+        .map(|transaction| {
+            let tip = match execution.model {
+                TransactionModel::Accounts => apply_transaction(state, transaction, base_fee),
+                TransactionModel::Utxo => apply_utxo_transaction(outputs, transaction, base_fee),
+            }?;
+
+            tips = tips.saturating_add(tip);
+            Ok(())
+        })
+        .collect();
+
+    let reward = execution.block_reward.saturating_add(tips);
+    match execution.model {
+        TransactionModel::Accounts => credit(state, mined_by, reward),
+        TransactionModel::Utxo => add_output(outputs, TransactionOutput {
+            owner: *mined_by,
+            amount: reward,
+        }),
+    }
+
+    results
+}
+
 /// Tracks the all existing blocks and the, currently existing, global state
 /// This should not be used by nodes directly, but only for collecting statistics
 #[allow(dead_code)]
@@ -14,3 +190,295 @@ pub trait GlobalLedger {}
 /// Nodes can also be affected by network partitions or hardware failures
 #[allow(dead_code)]
 pub trait NodeLedger {}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::utxo::output_key;
+    use super::{
+        ConventionalNodeLedger, InvalidTransaction, account_key, apply_transaction,
+        apply_utxo_transaction, execute_transactions, genesis_output_id, get_account_state,
+        make_genesis_outputs, make_genesis_state,
+    };
+    use crate::config::{ExecutionConfig, GenesisAccount, TransactionModel};
+    use crate::logic::{
+        AccountId, AccountState, OutputId, Transaction, TransactionFee, TransactionOutput, Transfer,
+    };
+
+    use cow_tree::FrozenCowTree;
+
+    fn get_balance(state: &FrozenCowTree<AccountState>, account: &AccountId) -> Option<u64> {
+        get_account_state(state, account).map(|account_state| account_state.get_balance())
+    }
+
+    fn make_transfer(source: u128, nonce: u64, recipient: u128, amount: u64) -> Transaction {
+        let fee = TransactionFee {
+            max_fee: 5,
+            max_tip: 5,
+        };
+        let mut transaction = Transaction::new_with_fee(source, nonce, fee);
+        transaction.set_transfer(Transfer { recipient, amount });
+        transaction
+    }
+
+    #[test]
+    fn genesis_balances() {
+        let accounts = vec![
+            GenesisAccount {
+                account: 1,
+                balance: 100,
+            },
+            GenesisAccount {
+                account: u128::MAX,
+                balance: 5,
+            },
+        ];
+
+        let state = make_genesis_state(&accounts);
+        assert_eq!(get_balance(&state, &1), Some(100));
+        assert_eq!(get_balance(&state, &u128::MAX), Some(5));
+        assert_eq!(get_balance(&state, &2), None);
+
+        // Later blocks build on a copy of the genesis state
+        let copy = state.deep_clone().freeze();
+        assert_eq!(get_balance(&copy, &1), Some(100));
+    }
+
+    #[asim::test]
+    async fn apply_transfers() {
+        let genesis = make_genesis_state(&[GenesisAccount {
+            account: 1,
+            balance: 100,
+        }]);
+        let mut state = genesis.deep_clone();
+
+        // The sender pays the amount and the tip; the recipient account is created
+        let tip = apply_transaction(&mut state, &make_transfer(1, 1, 2, 30), None);
+        assert_eq!(tip, Ok(5));
+
+        // Replays, gaps, and overdrafts are rejected without touching the state
+        let invalid = [
+            (make_transfer(1, 1, 2, 30), InvalidTransaction::StaleNonce),
+            (make_transfer(1, 3, 2, 30), InvalidTransaction::FutureNonce),
+            (
+                make_transfer(1, 2, 2, 61),
+                InvalidTransaction::InsufficientBalance,
+            ),
+            (
+                make_transfer(2, 1, 1, 30),
+                InvalidTransaction::InsufficientBalance,
+            ),
+        ];
+        for (transaction, err) in invalid {
+            assert_eq!(apply_transaction(&mut state, &transaction, None), Err(err));
+        }
+
+        // The base fee is burned and only the rest is tipped
+        let tip = apply_transaction(&mut state, &make_transfer(1, 2, 2, 10), Some(3));
+        assert_eq!(tip, Ok(2));
+
+        let state = state.freeze();
+        assert_eq!(get_balance(&state, &1), Some(50));
+        assert_eq!(get_balance(&state, &2), Some(40));
+        assert_eq!(state.get(&account_key(&1)).unwrap().get_nonce(), 2);
+
+        // The parent state is not modified
+        assert_eq!(get_balance(&genesis, &1), Some(100));
+        assert_eq!(get_balance(&genesis, &2), None);
+    }
+
+    #[asim::test]
+    async fn execute_block() {
+        let genesis = make_genesis_state(&[GenesisAccount {
+            account: 1,
+            balance: 100,
+        }]);
+        let mut state = genesis.deep_clone();
+        let mut outputs = make_genesis_outputs(&[]).deep_clone();
+        let execution = ExecutionConfig {
+            model: TransactionModel::Accounts,
+            transfer_amount: 10,
+            block_reward: 50,
+        };
+
+        // The second transfer overdraws the account and the third reuses a nonce
+        let transactions = [
+            make_transfer(1, 1, 2, 60),
+            make_transfer(1, 2, 2, 60),
+            make_transfer(1, 1, 2, 10),
+        ]
+        .map(Rc::new);
+        let results = execute_transactions(
+            &execution,
+            &mut state,
+            &mut outputs,
+            &transactions,
+            &3,
+            None,
+        );
+        assert_eq!(results, vec![
+            Ok(()),
+            Err(InvalidTransaction::InsufficientBalance),
+            Err(InvalidTransaction::StaleNonce),
+        ]);
+
+        // The miner gets the block reward and the tip of the valid transaction
+        let state = state.freeze();
+        assert_eq!(get_balance(&state, &1), Some(35));
+        assert_eq!(get_balance(&state, &2), Some(60));
+        assert_eq!(get_balance(&state, &3), Some(55));
+        assert_eq!(get_account_state(&state, &1).unwrap().get_nonce(), 1);
+    }
+
+    #[asim::test]
+    async fn diverging_state_roots() {
+        let genesis = make_genesis_state(&[GenesisAccount {
+            account: 1,
+            balance: 100,
+        }]);
+
+        // Two forks spend the same nonce differently
+        let mut fork1 = genesis.deep_clone();
+        let mut fork2 = genesis.deep_clone();
+        let transfer1 = make_transfer(1, 1, 2, 10);
+        let transfer2 = make_transfer(1, 1, 3, 10);
+        assert!(apply_transaction(&mut fork1, &transfer1, None).is_ok());
+        assert!(apply_transaction(&mut fork2, &transfer2, None).is_ok());
+
+        let fork1 = fork1.freeze();
+        let fork2 = fork2.freeze();
+        assert_ne!(fork1.root_hash(), fork2.root_hash());
+        assert_ne!(fork1.root_hash(), genesis.root_hash());
+
+        // Applying the same transactions leads to the same root
+        let mut replay = genesis.deep_clone();
+        assert!(apply_transaction(&mut replay, &transfer1, None).is_ok());
+        assert_eq!(replay.freeze().root_hash(), fork1.root_hash());
+    }
+
+    #[asim::test]
+    async fn spend_outputs() {
+        let accounts = [1, 2].map(|account| GenesisAccount {
+            account,
+            balance: 100,
+        });
+        let genesis = make_genesis_outputs(&accounts);
+        let mut outputs = genesis.deep_clone();
+
+        let spend = |source, inputs: Vec<OutputId>, amounts: &[(u128, u64)]| {
+            let fee = TransactionFee {
+                max_fee: 5,
+                max_tip: 5,
+            };
+            let mut transaction = Transaction::new_with_fee(source, 1, fee);
+            let outputs = amounts
+                .iter()
+                .map(|(owner, amount)| TransactionOutput {
+                    owner: *owner,
+                    amount: *amount,
+                })
+                .collect();
+            transaction.set_utxos(inputs, outputs);
+            transaction
+        };
+
+        // The miner gets everything that is not spent on outputs
+        let payment = spend(1, vec![genesis_output_id(0)], &[(2, 30), (1, 60)]);
+        assert_eq!(apply_utxo_transaction(&mut outputs, &payment, None), Ok(10));
+
+        let change = OutputId {
+            transaction: *payment.get_identifier(),
+            index: 1,
+        };
+        assert_eq!(
+            outputs.get(&output_key(&change)),
+            Some(&TransactionOutput {
+                owner: 1,
+                amount: 60
+            })
+        );
+
+        let invalid = [
+            // Double spends, both across and within transactions
+            (
+                spend(1, vec![genesis_output_id(0)], &[(1, 50)]),
+                InvalidTransaction::SpentInput,
+            ),
+            (
+                spend(1, vec![change, change], &[(1, 50)]),
+                InvalidTransaction::SpentInput,
+            ),
+            (
+                spend(1, vec![genesis_output_id(1)], &[(1, 50)]),
+                InvalidTransaction::ForeignInput,
+            ),
+            // The inputs cannot pay for the fee
+            (
+                spend(1, vec![change], &[(2, 58)]),
+                InvalidTransaction::InsufficientBalance,
+            ),
+        ];
+        for (transaction, err) in invalid {
+            assert_eq!(
+                apply_utxo_transaction(&mut outputs, &transaction, None),
+                Err(err)
+            );
+        }
+
+        // The base fee is burned
+        let payment = spend(1, vec![change], &[(2, 55)]);
+        assert_eq!(
+            apply_utxo_transaction(&mut outputs, &payment, Some(3)),
+            Ok(2)
+        );
+        assert!(outputs.get(&output_key(&change)).is_none());
+
+        // Forks that spend the same output differently end up with different roots
+        let mut fork = genesis.deep_clone();
+        let conflicting = spend(1, vec![genesis_output_id(0)], &[(3, 95)]);
+        assert!(apply_utxo_transaction(&mut fork, &conflicting, None).is_ok());
+        assert_ne!(fork.freeze().root_hash(), outputs.freeze().root_hash());
+
+        // Outputs of the genesis state are not affected
+        assert_eq!(genesis.iter().count(), 2);
+    }
+
+    #[asim::test]
+    async fn conventional_mempool_priority() {
+        let mut ledger = ConventionalNodeLedger::new();
+
+        let make_transaction = |fee| {
+            let fee = TransactionFee {
+                max_fee: fee,
+                max_tip: fee,
+            };
+            Rc::new(Transaction::new_with_fee(rand::random(), 1, fee))
+        };
+
+        let transactions: Vec<_> = [5, 20, 0, 10].into_iter().map(make_transaction).collect();
+        let ids = |transactions: Vec<Rc<Transaction>>| -> Vec<_> {
+            transactions
+                .iter()
+                .map(|txn| *txn.get_identifier())
+                .collect()
+        };
+        for txn in transactions.iter() {
+            assert!(ledger.add_transaction(txn.clone()));
+        }
+        assert!(!ledger.add_transaction(transactions[0].clone()));
+
+        // Highest fees first, and the remaining transactions stay in the mempool
+        let picked = ledger.get_transactions_from_mempool(2);
+        assert_eq!(
+            ids(picked),
+            ids(vec![transactions[1].clone(), transactions[3].clone()])
+        );
+        assert_eq!(ledger.get_mempool_size(), 2);
+
+        ledger.remove_transactions(&transactions[..1]);
+        let picked = ledger.get_transactions_from_mempool(10);
+        assert_eq!(ids(picked), ids(vec![transactions[2].clone()]));
+        assert_eq!(ledger.get_mempool_size(), 0);
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
 
 use asim::time::{Duration, Time};
@@ -9,14 +10,35 @@ use derivative::Derivative;
 
 use crate::config::Difficulty;
 use crate::logic::{
-    AccountId, AccountState, Block, BlockId, HASH_SIZE, NUM_SIZE, SIGNATURE_SIZE, TransactionId,
+    AccountId, AccountState, Block, BlockId, SIGNATURE_SIZE, TransactionId, TransactionOutput,
 };
+use crate::metrics::BlockPropagation;
+use crate::object::ObjectId;
+use crate::random;
+use crate::serde_time::{time_from_micros, time_to_micros, to_micros};
+
+use super::BlockFees;
+use super::store::{BlockRecord, ObjectNumbers};
+
+/// Keeps track of how a block spread through the network
+#[derive(Debug, Default)]
+struct PropagationTracker {
+    /// Time from creation until each node saw the block (in the order they saw it)
+    delays: Vec<Duration>,
+    /// How many hops it took the block to reach each node
+    /// This is only needed until the block reached all nodes
+    hops: HashMap<ObjectId, u32>,
+    /// Number of nodes reached after a given number of hops (index = hops)
+    hop_counts: Vec<u32>,
+    /// When each node first saw the block (in the order they saw it)
+    /// Only tracked for sampled blocks to bound memory usage
+    first_seen: Option<Vec<(ObjectId, Time)>>,
+}
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct NakamotoBlock {
     pub(super) identifier: BlockId,
-    #[allow(dead_code)] //TODO use for metrics
     mined_by: AccountId,
     parent: BlockId,
     uncles: Vec<BlockId>,
@@ -27,16 +49,27 @@ pub struct NakamotoBlock {
     creation_time: Time,
     /// Time it was seen by all nodes
     full_propagation_time: RefCell<Option<Time>>,
+    /// Time it was first committed by any node
+    commit_time: RefCell<Option<Time>>,
+    propagation: RefCell<PropagationTracker>,
     /// What was the difficulty for this block set to?
     /// TODO move difficulty tracking somewhere else
     difficulty: Difficulty,
 
     num_nodes: u32,
 
+    /// Only set if there is a fee market
+    fees: Option<BlockFees>,
+
     #[derivative(Debug = "ignore")]
     transactions: Vec<TransactionId>,
+    /// The size of each transaction (in bytes)
+    transaction_sizes: Vec<u64>,
     #[derivative(Debug = "ignore")]
     state: FrozenCowTree<AccountState>,
+    /// Only used with the UTXO model
+    #[derivative(Debug = "ignore")]
+    outputs: FrozenCowTree<TransactionOutput>,
 }
 
 impl NakamotoBlock {
@@ -49,10 +82,13 @@ impl NakamotoBlock {
         num_nodes: u32,
         difficulty: Difficulty,
         transactions: Vec<TransactionId>,
+        transaction_sizes: Vec<u64>,
+        fees: Option<BlockFees>,
         state: FrozenCowTree<AccountState>,
+        outputs: FrozenCowTree<TransactionOutput>,
     ) -> Self {
         Self::new_with_id(
-            rand::random(),
+            random::random(),
             mined_by,
             parent,
             uncles,
@@ -60,7 +96,10 @@ impl NakamotoBlock {
             num_nodes,
             difficulty,
             transactions,
+            transaction_sizes,
+            fees,
             state,
+            outputs,
         )
     }
 
@@ -74,8 +113,17 @@ impl NakamotoBlock {
         num_nodes: u32,
         difficulty: Difficulty,
         transactions: Vec<TransactionId>,
+        transaction_sizes: Vec<u64>,
+        fees: Option<BlockFees>,
         state: FrozenCowTree<AccountState>,
+        outputs: FrozenCowTree<TransactionOutput>,
     ) -> Self {
+        assert_eq!(
+            transactions.len(),
+            transaction_sizes.len(),
+            "Need exactly one size per transaction"
+        );
+
         log::trace!(
             "Node {mined_by} found a new block with id {identifier:#X} and height {height}"
         );
@@ -88,11 +136,16 @@ impl NakamotoBlock {
             uncles,
             height,
             transactions,
+            transaction_sizes,
             creation_time: asim::time::now(),
             difficulty,
+            fees,
             state,
+            outputs,
             seen_by: AtomicU32::new(0),
             full_propagation_time: RefCell::new(None),
+            commit_time: RefCell::new(None),
+            propagation: Default::default(),
         }
     }
 
@@ -126,19 +179,218 @@ impl NakamotoBlock {
     }
 
     pub fn mark_as_seen(&self) {
+        let now = asim::time::now();
+        let mut propagation = self.propagation.borrow_mut();
+        propagation.delays.push(now - self.creation_time);
+
         let prev = self.seen_by.fetch_add(1, AtomicOrdering::SeqCst);
         if prev + 1 == self.num_nodes {
-            let _ = self
-                .full_propagation_time
-                .borrow_mut()
-                .insert(asim::time::now());
+            let _ = self.full_propagation_time.borrow_mut().insert(now);
+            propagation.hops.clear();
+        }
+    }
+
+    /// Keep track of when each individual node first sees this block
+    pub fn track_first_seen(&self) {
+        let mut propagation = self.propagation.borrow_mut();
+        if propagation.first_seen.is_none() {
+            propagation.first_seen = Some(vec![]);
+        }
+    }
+
+    /// When the given node first saw this block
+    /// Returns `None` if the node has not seen it yet or the block is not sampled
+    pub fn get_first_seen_time(&self, node: &ObjectId) -> Option<Time> {
+        let propagation = self.propagation.borrow();
+        propagation
+            .first_seen
+            .as_ref()?
+            .iter()
+            .find_map(|(seen_by, time)| (seen_by == node).then_some(*time))
+    }
+
+    /// Records that a node received the block and over how many hops it got there
+    /// `received_from` is the peer that sent the block, or `None` if the node mined it
+    pub fn record_arrival(&self, node: ObjectId, received_from: Option<ObjectId>) {
+        let mut propagation = self.propagation.borrow_mut();
+
+        if let Some(first_seen) = propagation.first_seen.as_mut() {
+            first_seen.push((node, asim::time::now()));
+        }
+
+        let hops = match received_from {
+            Some(peer) => match propagation.hops.get(&peer) {
+                Some(hops) => hops + 1,
+                None => {
+                    log::warn!("Received block from a peer that never saw it");
+                    return;
+                }
+            },
+            None => 0,
+        };
+
+        // Stop tracking individual nodes once everybody has the block
+        if self.full_propagation_time.borrow().is_none() {
+            propagation.hops.insert(node, hops);
+        }
+
+        let hops = hops as usize;
+        if propagation.hop_counts.len() <= hops {
+            propagation.hop_counts.resize(hops + 1, 0);
+        }
+        propagation.hop_counts[hops] += 1;
+    }
+
+    /// How the block spread through the network so far
+    /// Returns `None` for blocks that were never sent over the network, e.g., premined blocks
+    pub fn get_propagation(&self) -> Option<BlockPropagation> {
+        let propagation = self.propagation.borrow();
+
+        if propagation.hop_counts.is_empty() {
+            return None;
+        }
+
+        Some(BlockPropagation {
+            identifier: self.identifier,
+            num_nodes: self.num_nodes,
+            delays: propagation.delays.clone(),
+            hop_counts: propagation.hop_counts.clone(),
+            first_seen: propagation.first_seen.clone(),
+        })
+    }
+
+    /// Marks the block as committed
+    /// Returns the time from creation until commit, if this is the first node to commit it
+    pub fn mark_as_committed(&self) -> Option<Duration> {
+        let mut commit_time = self.commit_time.borrow_mut();
+
+        if commit_time.is_some() {
+            return None;
         }
+
+        let now = asim::time::now();
+        *commit_time = Some(now);
+        Some(now - self.creation_time)
     }
 
     pub fn get_difficulty(&self) -> &Difficulty {
         &self.difficulty
     }
 
+    /// Returns `None` if the block was not created under a fee market
+    pub fn get_fees(&self) -> Option<&BlockFees> {
+        self.fees.as_ref()
+    }
+
+    /// The outputs that are unspent after this block
+    /// Always empty unless the UTXO model is used
+    pub fn get_outputs(&self) -> &FrozenCowTree<TransactionOutput> {
+        &self.outputs
+    }
+
+    /// Converts the block into a form that can be written to disk
+    /// The state and outputs are not included, as they are stored separately
+    pub(super) fn to_record(&self, objects: &mut ObjectNumbers) -> BlockRecord {
+        let propagation = self.propagation.borrow();
+
+        // Sorted, so that the record does not depend on the order of the hash map
+        let mut hops: Vec<_> = propagation
+            .hops
+            .iter()
+            .map(|(node, hops)| (objects.get_number(*node), *hops))
+            .collect();
+        hops.sort_unstable();
+
+        BlockRecord {
+            identifier: self.identifier,
+            mined_by: self.mined_by,
+            parent: self.parent,
+            uncles: self.uncles.clone(),
+            height: self.height,
+            seen_by: self.seen_by.load(AtomicOrdering::SeqCst),
+            creation_time: time_to_micros(self.creation_time),
+            full_propagation_time: self.full_propagation_time.borrow().map(time_to_micros),
+            commit_time: self.commit_time.borrow().map(time_to_micros),
+            delays: propagation.delays.iter().copied().map(to_micros).collect(),
+            hops,
+            hop_counts: propagation.hop_counts.clone(),
+            first_seen: propagation.first_seen.as_ref().map(|first_seen| {
+                first_seen
+                    .iter()
+                    .map(|(node, time)| (objects.get_number(*node), time_to_micros(*time)))
+                    .collect()
+            }),
+            difficulty: self.difficulty,
+            num_nodes: self.num_nodes,
+            fees: self.fees.as_ref().map(|fees| {
+                let delays = fees
+                    .get_inclusion_delays()
+                    .iter()
+                    .copied()
+                    .map(to_micros)
+                    .collect();
+                (fees.get_base_fee(), delays)
+            }),
+            transactions: self.transactions.clone(),
+            transaction_sizes: self.transaction_sizes.clone(),
+        }
+    }
+
+    /// The inverse of `to_record`
+    pub(super) fn from_record(
+        record: BlockRecord,
+        objects: &ObjectNumbers,
+        state: FrozenCowTree<AccountState>,
+        outputs: FrozenCowTree<TransactionOutput>,
+    ) -> Self {
+        let propagation = PropagationTracker {
+            delays: record
+                .delays
+                .into_iter()
+                .map(Duration::from_micros)
+                .collect(),
+            hops: record
+                .hops
+                .into_iter()
+                .map(|(node, hops)| (objects.get_object(node), hops))
+                .collect(),
+            hop_counts: record.hop_counts,
+            first_seen: record.first_seen.map(|first_seen| {
+                first_seen
+                    .into_iter()
+                    .map(|(node, time)| (objects.get_object(node), time_from_micros(time)))
+                    .collect()
+            }),
+        };
+
+        let fees = record.fees.map(|(base_fee, delays)| {
+            BlockFees::new(
+                base_fee,
+                delays.into_iter().map(Duration::from_micros).collect(),
+            )
+        });
+
+        Self {
+            identifier: record.identifier,
+            mined_by: record.mined_by,
+            parent: record.parent,
+            uncles: record.uncles,
+            height: record.height,
+            seen_by: AtomicU32::new(record.seen_by),
+            creation_time: time_from_micros(record.creation_time),
+            full_propagation_time: RefCell::new(record.full_propagation_time.map(time_from_micros)),
+            commit_time: RefCell::new(record.commit_time.map(time_from_micros)),
+            propagation: RefCell::new(propagation),
+            difficulty: record.difficulty,
+            num_nodes: record.num_nodes,
+            fees,
+            transactions: record.transactions,
+            transaction_sizes: record.transaction_sizes,
+            state,
+            outputs,
+        }
+    }
+
     /// Get block data size (in bytes)
     pub fn get_size(&self) -> u64 {
         SIGNATURE_SIZE
@@ -146,18 +398,17 @@ impl NakamotoBlock {
 
     /// Get block size including all transaction data
     pub fn get_total_size(&self) -> u64 {
-        self.get_size() + (self.transactions.len() as u64) * self.get_transaction_size()
-    }
-
-    /// Get size of a size of a transaction
-    /// TODO support variable size transactions
-    fn get_transaction_size(&self) -> u64 {
-        2 * HASH_SIZE + 5 * NUM_SIZE + SIGNATURE_SIZE
+        self.get_size() + self.transaction_sizes.iter().sum::<u64>()
     }
 
     pub fn get_transactions(&self) -> &[TransactionId] {
         &self.transactions
     }
+
+    /// The size of each transaction (in bytes), in the same order as `get_transactions`
+    pub fn get_transaction_sizes(&self) -> &[u64] {
+        &self.transaction_sizes
+    }
 }
 
 impl Block for NakamotoBlock {
@@ -0,0 +1,86 @@
+use std::cmp::Ordering;
+
+use asim::time::Duration;
+
+use crate::config::FeeMarketConfig;
+
+/// The fee market data of a single block
+#[derive(Clone, Debug)]
+pub struct BlockFees {
+    base_fee: u64,
+    /// How long each transaction waited until it was included in the block
+    inclusion_delays: Vec<Duration>,
+}
+
+impl BlockFees {
+    pub fn new(base_fee: u64, inclusion_delays: Vec<Duration>) -> Self {
+        Self {
+            base_fee,
+            inclusion_delays,
+        }
+    }
+
+    pub fn get_base_fee(&self) -> u64 {
+        self.base_fee
+    }
+
+    pub fn get_inclusion_delays(&self) -> &[Duration] {
+        &self.inclusion_delays
+    }
+}
+
+/// Computes the base fee of a block from the base fee and size of its parent (as in EIP-1559)
+pub(super) fn compute_next_base_fee(
+    config: &FeeMarketConfig,
+    parent_base_fee: u64,
+    parent_size: u32,
+) -> u64 {
+    let target = config.target_block_size.max(1) as u128;
+    let denominator = config.base_fee_change_denominator.max(1) as u128;
+    let parent_size = parent_size as u128;
+    let base_fee = parent_base_fee as u128;
+
+    let next = match parent_size.cmp(&target) {
+        Ordering::Equal => base_fee,
+        Ordering::Greater => {
+            // Always increase by at least one, so a base fee of zero can recover
+            let delta = (base_fee * (parent_size - target) / target / denominator).max(1);
+            base_fee + delta
+        }
+        Ordering::Less => {
+            let delta = base_fee * (target - parent_size) / target / denominator;
+            base_fee - delta
+        }
+    };
+
+    next.min(u64::MAX as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compute_next_base_fee;
+    use crate::config::FeeMarketConfig;
+
+    #[test]
+    fn base_fee_adjustment() {
+        let config = FeeMarketConfig {
+            initial_base_fee: 1000,
+            target_block_size: 100,
+            base_fee_change_denominator: 8,
+            max_tip: 10,
+            max_fee: 2000,
+        };
+
+        assert_eq!(compute_next_base_fee(&config, 1000, 100), 1000);
+
+        // Full blocks (twice the target) raise the base fee by 12.5%
+        assert_eq!(compute_next_base_fee(&config, 1000, 200), 1125);
+
+        // Empty blocks lower it by 12.5%
+        assert_eq!(compute_next_base_fee(&config, 1000, 0), 875);
+
+        // A base fee of zero must be able to increase again
+        assert_eq!(compute_next_base_fee(&config, 0, 101), 1);
+        assert_eq!(compute_next_base_fee(&config, 0, 0), 0);
+    }
+}
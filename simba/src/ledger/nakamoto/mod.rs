@@ -1,23 +1,36 @@
-use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 
-use asim::time::Time;
+use asim::time::{Duration, Time};
 
-use cow_tree::FrozenCowTree;
+use crate::block_graph::{BlockDetails, BlockGraph, BlockGraphNode};
+use crate::random;
 
-use crate::config::Difficulty;
+use cow_tree::{CowTree, FrozenCowTree};
+
+use crate::config::{Difficulty, ExecutionConfig, FeeMarketConfig, OrderingPolicy};
 use crate::emit_event;
 use crate::events::{BlockEvent, Event};
+use crate::fingerprint::FingerprintBuilder;
 use crate::logic::{
     AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, GENESIS_HEIGHT, Transaction,
-    TransactionId,
+    TransactionId, TransactionOutput, TransactionStatus,
 };
+use crate::memory::{self, MemoryUsage};
+use crate::metrics::{FeeMarketStatistics, PropagationStatistics};
 
 mod block;
 pub use block::NakamotoBlock;
 
+mod fees;
+pub use fees::BlockFees;
+
+mod store;
+pub use store::{BlockStore, DiskBlockStore, MemoryBlockStore};
+
 use rand::prelude::IteratorRandom;
+use rand::seq::SliceRandom;
 
 use super::{GlobalLedger, NodeLedger};
 
@@ -26,17 +39,34 @@ uint::construct_uint! {
 }
 
 pub type NotifyCommitFn = Box<dyn Fn(&AccountId, &TransactionId)>;
+pub type NotifyConfirmationFn = Box<dyn Fn(&AccountId, &TransactionId, u64)>;
+pub type NotifyReorgFn = Box<dyn Fn(u64)>;
+pub type NotifyBlockCommitFn = Box<dyn Fn(Duration)>;
 
 pub const MAX_DIFF_TARGET: DiffTarget = DiffTarget([u64::MAX, u64::MAX, u64::MAX, u64::MAX]);
 
 pub struct NakamotoGlobalLedger {
     num_nodes: u32,
-    all_blocks: HashMap<BlockId, Rc<NakamotoBlock>>,
+    /// Shared with all node ledgers
+    all_blocks: Rc<dyn BlockStore>,
     longest_chain: (BlockId, u64),
+    genesis_state: FrozenCowTree<AccountState>,
+    /// Only used with the UTXO model
+    genesis_outputs: FrozenCowTree<TransactionOutput>,
+    fee_market: Option<FeeMarketConfig>,
+    /// Blocks do not modify the state if not set
+    execution: Option<ExecutionConfig>,
+    /// Track per-node first-seen times for every n-th block (disabled if zero)
+    first_seen_sampling: u32,
+    /// Blocks generated while sampling was enabled
+    num_sampling_candidates: u64,
 }
 
 pub struct NakamotoNodeLedger {
-    blocks: HashMap<BlockId, Rc<NakamotoBlock>>,
+    /// The blocks this node knows about
+    /// The blocks themselves are kept by the store
+    blocks: HashSet<BlockId>,
+    store: Rc<dyn BlockStore>,
 
     /// Keeps track of the head of all forks
     forks: HashMap<BlockId, u64>,
@@ -44,29 +74,140 @@ pub struct NakamotoNodeLedger {
     ///The longest chain we picked to mine on
     longest_chain: (BlockId, u64),
 
+    /// The blocks of the longest chain indexed by their height
+    /// (starting with the genesis block)
+    chain_index: Vec<BlockId>,
+
     /// Keeps track of which blocks are marked as uncle by the main chain
     marked_as_uncle: HashSet<BlockId>,
 
     /// Transaction data
-    applied_transactions: HashSet<TransactionId>,
+    /// (applied transactions are mapped to the block that contains them)
+    applied_transactions: HashMap<TransactionId, BlockId>,
     mempool: HashSet<TransactionId>,
     known_transactions: HashMap<TransactionId, Rc<Transaction>>,
+    /// When this node first learned about each transaction
+    first_seen: HashMap<TransactionId, Time>,
+
+    /// Confirmation depths that clients of this node wait for
+    confirmation_depths: BTreeSet<u64>,
 
     /// Callbacks
     notify_transaction_commit_fn: Option<NotifyCommitFn>,
+    notify_transaction_confirmation_fn: Option<NotifyConfirmationFn>,
+    notify_reorg_fn: Option<NotifyReorgFn>,
+    notify_block_commit_fn: Option<NotifyBlockCommitFn>,
 }
 
 impl GlobalLedger for NakamotoGlobalLedger {}
 
 impl NakamotoGlobalLedger {
-    pub fn new(num_nodes: u32) -> Self {
-        let all_blocks = Default::default();
+    pub fn new(num_nodes: u32, genesis_state: FrozenCowTree<AccountState>) -> Self {
+        let all_blocks = Rc::new(MemoryBlockStore::default());
         let longest_chain = (GENESIS_BLOCK, GENESIS_HEIGHT);
+        let fee_market = None;
 
         Self {
             num_nodes,
             all_blocks,
             longest_chain,
+            genesis_state,
+            genesis_outputs: CowTree::default().freeze(),
+            fee_market,
+            execution: None,
+            first_seen_sampling: 0,
+            num_sampling_candidates: 0,
+        }
+    }
+
+    /// Keeps blocks in the given store instead of memory
+    /// Must be called before any blocks are created
+    pub fn set_block_store(&mut self, store: Rc<dyn BlockStore>) {
+        assert_eq!(
+            self.all_blocks.num_blocks(),
+            0,
+            "Cannot change the block store after blocks were created"
+        );
+        self.all_blocks = store;
+    }
+
+    /// The store node ledgers should use (see `NakamotoNodeLedger::with_store`)
+    pub fn get_block_store(&self) -> Rc<dyn BlockStore> {
+        self.all_blocks.clone()
+    }
+
+    /// Record when each node first saw every n-th generated block
+    /// A value of zero disables tracking
+    pub fn set_first_seen_sampling(&mut self, interval: u32) {
+        self.first_seen_sampling = interval;
+    }
+
+    /// Makes transactions pay a base fee that adjusts with block fullness
+    pub fn set_fee_market(&mut self, config: FeeMarketConfig) {
+        self.fee_market = Some(config);
+    }
+
+    /// Makes blocks apply their transactions to the state
+    pub fn set_execution(&mut self, config: ExecutionConfig) {
+        self.execution = Some(config);
+    }
+
+    pub fn get_execution(&self) -> Option<&ExecutionConfig> {
+        self.execution.as_ref()
+    }
+
+    /// The base fee of a new block built on top of the given parent
+    /// Returns `None` if there is no fee market
+    pub fn get_next_base_fee(&self, parent_id: &BlockId) -> anyhow::Result<Option<u64>> {
+        let Some(config) = self.fee_market.as_ref() else {
+            return Ok(None);
+        };
+
+        // Premined blocks do not have fees, so the fee market starts at the first real block
+        let parent_fees = self
+            .get_block(parent_id)?
+            .and_then(|parent| parent.get_fees().map(|fees| (fees.get_base_fee(), parent)));
+
+        let base_fee = match parent_fees {
+            Some((base_fee, parent)) => {
+                fees::compute_next_base_fee(config, base_fee, parent.num_transactions() as u32)
+            }
+            None => config.initial_base_fee,
+        };
+
+        Ok(Some(base_fee))
+    }
+
+    /// The state before any blocks were created
+    pub fn get_genesis_state(&self) -> &FrozenCowTree<AccountState> {
+        &self.genesis_state
+    }
+
+    /// Sets the outputs that exist before any blocks were created (UTXO model only)
+    pub fn set_genesis_outputs(&mut self, outputs: FrozenCowTree<TransactionOutput>) {
+        self.genesis_outputs = outputs;
+    }
+
+    pub fn get_genesis_outputs(&self) -> &FrozenCowTree<TransactionOutput> {
+        &self.genesis_outputs
+    }
+
+    /// Copies of the state and outputs a new block on top of the given parent starts with
+    pub(crate) fn get_parent_state(
+        &self,
+        parent_id: &BlockId,
+    ) -> anyhow::Result<(CowTree<AccountState>, CowTree<TransactionOutput>)> {
+        if *parent_id == GENESIS_BLOCK {
+            Ok((
+                self.genesis_state.deep_clone(),
+                self.genesis_outputs.deep_clone(),
+            ))
+        } else {
+            let parent = self.all_blocks.get(parent_id)?.expect("No such parent");
+            Ok((
+                parent.get_state().deep_clone(),
+                parent.get_outputs().deep_clone(),
+            ))
         }
     }
 
@@ -79,8 +220,11 @@ impl NakamotoGlobalLedger {
         height: u64,
         difficulty: Difficulty,
         transactions: Vec<TransactionId>,
+        transaction_sizes: Vec<u64>,
+        fees: Option<BlockFees>,
         state: FrozenCowTree<AccountState>,
-    ) -> Rc<NakamotoBlock> {
+        outputs: FrozenCowTree<TransactionOutput>,
+    ) -> anyhow::Result<Rc<NakamotoBlock>> {
         let block = Rc::new(NakamotoBlock::new(
             mined_by,
             parent,
@@ -89,12 +233,22 @@ impl NakamotoGlobalLedger {
             self.num_nodes,
             difficulty,
             transactions,
+            transaction_sizes,
+            fees,
             state,
+            outputs,
         ));
 
+        if self.first_seen_sampling > 0 {
+            self.num_sampling_candidates += 1;
+            if self.num_sampling_candidates % (self.first_seen_sampling as u64) == 0 {
+                block.track_first_seen();
+            }
+        }
+
         let block_id = *block.get_identifier();
 
-        self.all_blocks.insert(block_id, block.clone());
+        self.all_blocks.insert(block.clone())?;
 
         emit_event!(Event::Block {
             identifier: block_id,
@@ -116,55 +270,273 @@ impl NakamotoGlobalLedger {
             );
         }
 
-        block
+        Ok(block)
     }
 
-    pub fn get_total_blocks_mined(&self, start: Time, end: Time) -> u64 {
+    /// Creates a chain of empty blocks on top of the genesis block
+    ///
+    /// Used to warm-start a simulation. Must be called before any nodes
+    /// are created, so they can import the chain.
+    pub fn premine(&mut self, num_blocks: u64, difficulty: Difficulty) -> anyhow::Result<()> {
+        assert_eq!(
+            self.longest_chain.0, GENESIS_BLOCK,
+            "Can only premine on top of the genesis block"
+        );
+
+        let mut parent_id = GENESIS_BLOCK;
+        let mut state = self.genesis_state.deep_clone().freeze();
+        let mut outputs = self.genesis_outputs.deep_clone().freeze();
+
+        for height in GENESIS_HEIGHT + 1..=GENESIS_HEIGHT + num_blocks {
+            let block = self.generate_block(
+                0,
+                parent_id,
+                vec![],
+                height,
+                difficulty,
+                vec![],
+                vec![],
+                None,
+                state,
+                outputs,
+            )?;
+
+            parent_id = *block.get_identifier();
+            state = block.get_state().deep_clone().freeze();
+            outputs = block.get_outputs().deep_clone().freeze();
+        }
+
+        if num_blocks > 0 {
+            log::info!("Premined {num_blocks} block(s)");
+        }
+
+        Ok(())
+    }
+
+    pub fn get_total_blocks_mined(&self, start: Time, end: Time) -> anyhow::Result<u64> {
         let mut count: u64 = 0;
 
-        for (_, block) in self.all_blocks.iter() {
+        for block_id in self.all_blocks.get_identifiers() {
+            let block = self.all_blocks.get(&block_id)?.expect("Missing block");
             let creation_time = block.get_creation_time();
             if creation_time >= start && creation_time <= end {
                 count += 1;
             }
         }
 
-        count
+        Ok(count)
     }
 
     pub fn get_longest_chain(&self) -> (BlockId, u64) {
         self.longest_chain
     }
 
-    pub fn get_block(&self, block_id: &BlockId) -> Option<Rc<NakamotoBlock>> {
-        self.all_blocks.get(block_id).cloned()
+    /// Estimated memory used by the state at the head of the longest chain (in bytes)
+    pub fn get_state_size(&self) -> anyhow::Result<u64> {
+        let (head, _) = self.longest_chain;
+        let stats = match self.all_blocks.get(&head)? {
+            Some(block) => block.get_state().stats(),
+            None => self.genesis_state.stats(),
+        };
+
+        Ok(stats.estimated_bytes as u64)
+    }
+
+    /// Blocks are owned by the global ledger, so they are counted here and not by the nodes
+    pub fn get_memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        usage.add("all_blocks", self.all_blocks.get_memory_usage());
+        usage
+    }
+
+    pub fn get_block(&self, block_id: &BlockId) -> anyhow::Result<Option<Rc<NakamotoBlock>>> {
+        self.all_blocks.get(block_id)
+    }
+
+    /// Returns all blocks of the longest chain, starting with the oldest one
+    pub fn get_longest_chain_blocks(&self) -> anyhow::Result<Vec<Rc<NakamotoBlock>>> {
+        let mut blocks = vec![];
+        let (mut block_id, _) = self.longest_chain;
+
+        while block_id != GENESIS_BLOCK {
+            let block = self.get_block(&block_id)?.expect("Missing block");
+            block_id = *block.get_parent_id();
+            blocks.push(block);
+        }
+
+        blocks.reverse();
+        Ok(blocks)
+    }
+
+    /// Returns all blocks (including forks) as a DAG
+    pub fn get_block_graph(&self) -> anyhow::Result<BlockGraph> {
+        let longest_chain: HashSet<BlockId> = self
+            .get_longest_chain_blocks()?
+            .iter()
+            .map(|block| *block.get_identifier())
+            .collect();
+
+        let genesis = BlockGraphNode {
+            identifier: GENESIS_BLOCK,
+            parent: None,
+            uncles: vec![],
+            height: GENESIS_HEIGHT,
+            num_transactions: 0,
+            in_longest_chain: true,
+        };
+
+        let mut blocks = vec![];
+
+        for block_id in self.all_blocks.get_identifiers() {
+            let block = self.all_blocks.get(&block_id)?.expect("Missing block");
+            blocks.push(BlockGraphNode {
+                identifier: *block.get_identifier(),
+                parent: Some(*block.get_parent_id()),
+                uncles: block.get_uncle_ids().to_vec(),
+                height: block.get_height(),
+                num_transactions: block.num_transactions(),
+                in_longest_chain: longest_chain.contains(block.get_identifier()),
+            });
+        }
+
+        blocks.sort_by_key(|block| (block.height, block.identifier));
+        blocks.insert(0, genesis);
+
+        Ok(BlockGraph { blocks })
+    }
+
+    /// Returns `None` if there is no such block
+    pub fn get_block_details(&self, block_id: &BlockId) -> anyhow::Result<Option<BlockDetails>> {
+        if *block_id == GENESIS_BLOCK {
+            return Ok(Some(BlockDetails {
+                identifier: GENESIS_BLOCK,
+                parent: None,
+                uncles: vec![],
+                height: GENESIS_HEIGHT,
+                miner: None,
+                transactions: vec![],
+            }));
+        }
+
+        let Some(block) = self.all_blocks.get(block_id)? else {
+            return Ok(None);
+        };
+        let transactions = block
+            .get_transactions()
+            .iter()
+            .copied()
+            .zip(block.get_transaction_sizes().iter().copied())
+            .collect();
+
+        Ok(Some(BlockDetails {
+            identifier: *block_id,
+            parent: Some(*block.get_parent_id()),
+            uncles: block.get_uncle_ids().to_vec(),
+            height: block.get_height(),
+            miner: Some(block.get_miner()),
+            transactions,
+        }))
+    }
+
+    /// Returns `None` if there is no fee market
+    /// Note: the demand is not known to the ledger and has to be set by the caller
+    pub fn get_fee_market_statistics(&self) -> anyhow::Result<Option<FeeMarketStatistics>> {
+        let Some(config) = self.fee_market.as_ref() else {
+            return Ok(None);
+        };
+
+        let mut statistics = FeeMarketStatistics {
+            target_block_size: config.target_block_size,
+            ..Default::default()
+        };
+
+        for block in self.get_longest_chain_blocks()? {
+            let Some(fees) = block.get_fees() else {
+                continue;
+            };
+
+            statistics.base_fees.push(fees.get_base_fee());
+            statistics.block_sizes.push(block.num_transactions() as u32);
+            statistics
+                .inclusion_delays
+                .extend_from_slice(fees.get_inclusion_delays());
+        }
+
+        Ok(Some(statistics))
+    }
+
+    /// How the blocks of the longest chain spread through the network
+    /// Premined blocks are skipped, as they were never propagated
+    pub fn get_propagation_statistics(&self) -> anyhow::Result<PropagationStatistics> {
+        let blocks = self
+            .get_longest_chain_blocks()?
+            .iter()
+            .filter_map(|block| block.get_propagation())
+            .collect();
+
+        Ok(PropagationStatistics { blocks })
+    }
+
+    /// Adds all blocks of the longest chain to the fingerprint (starting at the head)
+    pub fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) -> anyhow::Result<()> {
+        let (mut block_id, _) = self.longest_chain;
+
+        while block_id != GENESIS_BLOCK {
+            let block = self.get_block(&block_id)?.expect("Missing block");
+            fingerprint.add_u128(block_id);
+            fingerprint.add_u64(block.get_height());
+            fingerprint.add_u64(block.num_transactions() as u64);
+            block_id = *block.get_parent_id();
+        }
+
+        Ok(())
     }
 }
 
 impl NodeLedger for NakamotoNodeLedger {}
 
 impl NakamotoNodeLedger {
+    /// Creates a ledger with its own store
     pub fn new() -> Self {
+        Self::with_store(Rc::new(MemoryBlockStore::default()))
+    }
+
+    /// Creates a ledger that looks up blocks in the given store
+    /// (usually the one of the global ledger)
+    pub fn with_store(store: Rc<dyn BlockStore>) -> Self {
         // genesis block does not contain any data and does not contribute to chain length
         let longest_chain = (GENESIS_BLOCK, 0);
 
         let blocks = Default::default();
         let forks = Default::default();
+        let chain_index = vec![GENESIS_BLOCK];
         let known_transactions = Default::default();
+        let first_seen = Default::default();
         let applied_transactions = Default::default();
         let mempool = Default::default();
         let marked_as_uncle = Default::default();
+        let confirmation_depths = Default::default();
         let notify_transaction_commit_fn = None;
+        let notify_transaction_confirmation_fn = None;
+        let notify_reorg_fn = None;
+        let notify_block_commit_fn = None;
 
         Self {
             longest_chain,
+            chain_index,
             blocks,
+            store,
             forks,
             known_transactions,
+            first_seen,
             marked_as_uncle,
             applied_transactions,
             mempool,
+            confirmation_depths,
             notify_transaction_commit_fn,
+            notify_transaction_confirmation_fn,
+            notify_reorg_fn,
+            notify_block_commit_fn,
         }
     }
 
@@ -172,35 +544,174 @@ impl NakamotoNodeLedger {
         self.notify_transaction_commit_fn = Some(func);
     }
 
+    /// Sets a function to be called whenever a transaction reaches one of the
+    /// confirmation depths registered with `add_confirmation_depth`
+    /// The last argument is the number of confirmations
+    pub fn set_notify_transaction_confirmation_fn(&mut self, func: NotifyConfirmationFn) {
+        self.notify_transaction_confirmation_fn = Some(func);
+    }
+
+    /// Notify about transactions that are buried under this many blocks
+    /// (a transaction in the chain head has one confirmation)
+    pub fn add_confirmation_depth(&mut self, depth: u64) {
+        assert!(depth > 0, "Confirmation depth must be at least one");
+        self.confirmation_depths.insert(depth);
+    }
+
+    /// Sets a function to be called whenever the chain head switches to another fork
+    /// The argument is the number of blocks that were undone
+    pub fn set_notify_reorg_fn(&mut self, func: NotifyReorgFn) {
+        self.notify_reorg_fn = Some(func);
+    }
+
+    /// Sets a function to be called when this node is the first to commit a block
+    /// The argument is the time from block creation until commit
+    pub fn set_notify_block_commit_fn(&mut self, func: NotifyBlockCommitFn) {
+        self.notify_block_commit_fn = Some(func);
+    }
+
+    /// The number of chain tips known to this node (including the longest chain)
+    pub fn num_forks(&self) -> usize {
+        // The genesis block is the only tip until the first block arrives
+        self.forks.len().max(1)
+    }
+
     pub fn get_longest_chain(&self) -> (BlockId, u64) {
         self.longest_chain
     }
 
+    /// Get the block of the longest chain at the given height
+    /// Returns `None` for the genesis block or heights above the chain head
+    pub fn get_block_at_height(&self, height: u64) -> anyhow::Result<Option<Rc<NakamotoBlock>>> {
+        match self.chain_index.get(height as usize) {
+            Some(block_id) => self.store.get(block_id),
+            None => Ok(None),
+        }
+    }
+
     pub fn is_marked_as_uncle(&self, block_id: &BlockId) -> bool {
         self.marked_as_uncle.contains(block_id)
     }
 
-    pub fn get_transactions_from_mempool(&self, max_block_size: u32) -> Vec<TransactionId> {
-        let mut transactions = vec![];
+    pub fn get_mempool_size(&self) -> u32 {
+        self.mempool.len() as u32
+    }
 
-        for txn_id in self.mempool.iter() {
-            if (transactions.len() as u32) >= max_block_size {
-                break;
-            }
+    pub fn get_mempool(&self) -> Vec<TransactionId> {
+        self.mempool.iter().copied().collect()
+    }
+
+    pub fn add_memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.add(
+            "blocks",
+            memory::set_size(&self.blocks) + memory::map_size(&self.forks),
+        );
+        usage.add("chain_index", memory::vec_size(&self.chain_index));
+        usage.add("mempool", memory::set_size(&self.mempool));
+        usage.add(
+            "transactions",
+            memory::map_size(&self.known_transactions)
+                + memory::map_size(&self.applied_transactions)
+                + memory::map_size(&self.first_seen),
+        );
+    }
 
-            transactions.push(*txn_id);
+    /// Picks the transactions for a new block according to the given ordering policy
+    ///
+    /// If there is a base fee, only transactions that can pay it are considered.
+    /// Without a fee market, all transactions have a tip of zero.
+    /// Transactions that exceed the remaining space of a byte limit are skipped,
+    /// so smaller transactions after them may still be included.
+    pub fn get_transactions_from_mempool(
+        &self,
+        max_block_size: u32,
+        max_block_bytes: Option<u64>,
+        base_fee: Option<u64>,
+        policy: &OrderingPolicy,
+    ) -> Vec<TransactionId> {
+        let mut candidates: Vec<(u64, &Rc<Transaction>)> = self
+            .mempool
+            .iter()
+            .filter_map(|txn_id| {
+                let transaction = self.known_transactions.get(txn_id).unwrap();
+                let tip = match base_fee {
+                    Some(base_fee) => transaction.get_fee().get_effective_tip(base_fee)?,
+                    None => 0,
+                };
+                Some((tip, transaction))
+            })
+            .collect();
+
+        // Ties are broken by identifier to stay deterministic
+        match policy {
+            OrderingPolicy::Fifo => candidates.sort_unstable_by_key(|(_, transaction)| {
+                (
+                    transaction.get_creation_time(),
+                    *transaction.get_identifier(),
+                )
+            }),
+            OrderingPolicy::FeePriority | OrderingPolicy::FrontRunning { .. } => candidates
+                .sort_unstable_by_key(|(tip, transaction)| {
+                    (
+                        Reverse(*tip),
+                        transaction.get_creation_time(),
+                        *transaction.get_identifier(),
+                    )
+                }),
+            OrderingPolicy::Random => {
+                // The order of the mempool depends on the hash set, so sort it to be reproducible
+                candidates.sort_unstable_by_key(|(_, transaction)| *transaction.get_identifier());
+                candidates.shuffle(&mut random::rng());
+            }
         }
 
-        transactions
+        let mut remaining_bytes = max_block_bytes.unwrap_or(u64::MAX);
+
+        candidates
+            .into_iter()
+            .filter(|(_, transaction)| {
+                if transaction.get_size() > remaining_bytes {
+                    false
+                } else {
+                    remaining_bytes -= transaction.get_size();
+                    true
+                }
+            })
+            .take(max_block_size as usize)
+            .map(|(_, transaction)| *transaction.get_identifier())
+            .collect()
     }
 
     /// Check if a transaction does not only exist but is currently
     /// also considered part of the longest chain
-    // Only used for testing right now
-    // TODO expose via node?
-    #[allow(dead_code)]
     pub fn is_transaction_applied(&self, txn_id: &TransactionId) -> bool {
-        self.applied_transactions.contains(txn_id)
+        self.applied_transactions.contains_key(txn_id)
+    }
+
+    /// Get the status of a transaction with respect to the current longest chain
+    pub fn get_transaction_status(
+        &self,
+        txn_id: &TransactionId,
+        commit_delay: u64,
+    ) -> anyhow::Result<TransactionStatus> {
+        let status = if let Some(block_id) = self.applied_transactions.get(txn_id) {
+            let block = self
+                .get_block(block_id)?
+                .expect("Transaction was applied by an unknown block");
+            let depth = self.longest_chain.1 - block.get_height();
+
+            if depth >= commit_delay {
+                TransactionStatus::Committed { depth }
+            } else {
+                TransactionStatus::Applied { depth }
+            }
+        } else if self.mempool.contains(txn_id) {
+            TransactionStatus::Pending
+        } else {
+            TransactionStatus::Unknown
+        };
+
+        Ok(status)
     }
 
     pub fn knows_transaction(&self, txn_id: &TransactionId) -> bool {
@@ -211,12 +722,21 @@ impl NakamotoNodeLedger {
         self.known_transactions.get(txn_id).cloned()
     }
 
+    /// When this node first learned about the transaction
+    pub fn get_first_seen_time(&self, txn_id: &TransactionId) -> Option<Time> {
+        self.first_seen.get(txn_id).copied()
+    }
+
     pub fn has_block(&self, block_id: &BlockId) -> bool {
-        self.blocks.contains_key(block_id)
+        self.blocks.contains(block_id)
     }
 
-    pub fn get_block(&self, block_id: &BlockId) -> Option<Rc<NakamotoBlock>> {
-        self.blocks.get(block_id).cloned()
+    pub fn get_block(&self, block_id: &BlockId) -> anyhow::Result<Option<Rc<NakamotoBlock>>> {
+        if self.blocks.contains(block_id) {
+            self.store.get(block_id)
+        } else {
+            Ok(None)
+        }
     }
 
     /// Adds a new block to the ledger
@@ -227,16 +747,17 @@ impl NakamotoNodeLedger {
         &mut self,
         block: Rc<NakamotoBlock>,
         commit_delay: u64,
-    ) -> (bool, Option<Rc<NakamotoBlock>>) {
+    ) -> anyhow::Result<(bool, Option<Rc<NakamotoBlock>>)> {
         let block_id = block.get_identifier();
         let parent_id = *block.get_parent_id();
         let height = block.get_height();
 
-        let prev = self.blocks.insert(*block.get_identifier(), block.clone());
-        if prev.is_some() {
+        if self.blocks.contains(block_id) {
             log::trace!("Got same block more than once");
-            return (false, None);
+            return Ok((false, None));
         };
+        self.store.insert(block.clone())?;
+        self.blocks.insert(*block_id);
 
         self.forks.remove(&parent_id);
         self.forks.insert(*block_id, height);
@@ -250,23 +771,23 @@ impl NakamotoNodeLedger {
                 self.longest_chain = self.pick_fork();
                 assert_ne!(self.longest_chain.0, GENESIS_BLOCK);
 
-                let new_head = self.blocks.get(&self.longest_chain.0).unwrap().clone();
-                self.update_chain_head(None, &new_head, commit_delay);
+                let new_head = self.get_block(&self.longest_chain.0)?.unwrap();
+                self.update_chain_head(None, &new_head, commit_delay)?;
                 chain_head = Some(new_head);
             } else {
                 // Tied or longer than current chain
-                let old_head = self.blocks.get(&self.longest_chain.0).unwrap().clone();
+                let old_head = self.get_block(&self.longest_chain.0)?.unwrap();
                 self.longest_chain = self.pick_fork();
-                let new_head = self.blocks.get(&self.longest_chain.0).unwrap().clone();
+                let new_head = self.get_block(&self.longest_chain.0)?.unwrap();
 
                 if old_head.get_identifier() != new_head.get_identifier() {
-                    self.update_chain_head(Some(&old_head), &new_head, commit_delay);
+                    self.update_chain_head(Some(&old_head), &new_head, commit_delay)?;
                     chain_head = Some(new_head);
                 }
             }
         }
 
-        (true, chain_head)
+        Ok((true, chain_head))
     }
 
     pub fn update_chain_head(
@@ -274,19 +795,20 @@ impl NakamotoNodeLedger {
         old_head: Option<&Rc<NakamotoBlock>>,
         new_head: &Rc<NakamotoBlock>,
         commit_delay: u64,
-    ) {
+    ) -> anyhow::Result<()> {
         let mut new_chain = VecDeque::new();
 
         // This walks back the old forks and then walks forward on the new fork
         if let Some(old_head) = old_head {
             assert!(old_head.get_height() <= new_head.get_height());
 
-            let mut old_ancestor = old_head;
-            let mut new_ancestor = new_head;
+            let mut old_ancestor = old_head.clone();
+            let mut new_ancestor = new_head.clone();
 
             while new_ancestor.get_height() > old_ancestor.get_height() {
+                let parent = self.get_block(new_ancestor.get_parent_id())?.unwrap();
                 new_chain.push_back(new_ancestor);
-                new_ancestor = self.blocks.get(new_ancestor.get_parent_id()).unwrap();
+                new_ancestor = parent;
             }
 
             let mut walk_back_count = 0;
@@ -311,25 +833,57 @@ impl NakamotoNodeLedger {
                     }
                 }
 
+                let next_id = *new_ancestor.get_parent_id();
                 new_chain.push_back(new_ancestor);
 
-                let next_id = new_ancestor.get_parent_id();
-
-                if *next_id == GENESIS_BLOCK {
+                if next_id == GENESIS_BLOCK {
                     // Common ancestor is the genesis block
                     // No need to process it (it's empty)
                     break;
                 } else {
-                    new_ancestor = self.blocks.get(next_id).unwrap();
-                    old_ancestor = self.blocks.get(old_ancestor.get_parent_id()).unwrap();
+                    new_ancestor = self.get_block(&next_id)?.unwrap();
+                    old_ancestor = self.get_block(old_ancestor.get_parent_id())?.unwrap();
+                }
+            }
+
+            if walk_back_count > 0 {
+                log::trace!(
+                    "Chain head switched from #{:#X} to #{:#X}; reverted {walk_back_count} block(s)",
+                    old_head.get_identifier(),
+                    new_head.get_identifier()
+                );
+
+                emit_event!(Event::Block {
+                    identifier: *new_head.get_identifier(),
+                    event: BlockEvent::Reorg {
+                        old_head: *old_head.get_identifier(),
+                        new_head: *new_head.get_identifier(),
+                        depth: walk_back_count,
+                    }
+                });
+
+                if let Some(func) = &self.notify_reorg_fn {
+                    func(walk_back_count);
                 }
             }
         } else {
-            new_chain.push_back(new_head)
+            new_chain.push_back(new_head.clone())
         }
 
+        // Blocks above this height were not part of the previous chain
+        let ancestor_height = new_head.get_height() - (new_chain.len() as u64);
+
+        self.chain_index.truncate(ancestor_height as usize + 1);
+        assert_eq!(
+            self.chain_index.len() as u64,
+            ancestor_height + 1,
+            "New chain does not connect to the previous one"
+        );
+
         // Apply new block(s)
         while let Some(new_block) = new_chain.pop_back() {
+            self.chain_index.push(*new_block.get_identifier());
+
             for uncle_id in new_block.get_uncle_ids() {
                 if !self.marked_as_uncle.insert(*uncle_id) {
                     panic!("Block was marked as uncle twice");
@@ -338,21 +892,31 @@ impl NakamotoNodeLedger {
 
             for txn_id in new_block.get_transactions() {
                 self.mempool.remove(txn_id);
-                self.applied_transactions.insert(*txn_id);
+                self.applied_transactions
+                    .insert(*txn_id, *new_block.get_identifier());
             }
         }
 
+        let old_height = old_head.map_or(ancestor_height, |head| head.get_height());
+        self.notify_confirmations(new_head, old_height, ancestor_height)?;
+
         // After the new fork has been applied, we can check for commits
         if let Some(old_head) = old_head {
             if new_head.get_height() > old_head.get_height() && new_head.get_height() > commit_delay
             {
-                let mut committed_block = new_head;
-
-                for _ in 0..commit_delay {
-                    committed_block = self
-                        .blocks
-                        .get(committed_block.get_parent_id())
-                        .expect("Failed to get committed block; this should not happen");
+                let committed_block = self
+                    .get_block_at_height(new_head.get_height() - commit_delay)?
+                    .expect("Failed to get committed block; this should not happen");
+
+                if let Some(delay) = committed_block.mark_as_committed() {
+                    emit_event!(Event::Block {
+                        identifier: *committed_block.get_identifier(),
+                        event: BlockEvent::Committed { delay },
+                    });
+
+                    if let Some(func) = &self.notify_block_commit_fn {
+                        func(delay);
+                    }
                 }
 
                 for txn_id in committed_block.get_transactions() {
@@ -361,7 +925,7 @@ impl NakamotoNodeLedger {
                         .known_transactions
                         .get(txn_id)
                         .expect("block contained unknown transaction");
-                    if !self.applied_transactions.contains(txn_id) {
+                    if !self.is_transaction_applied(txn_id) {
                         panic!("Committed transaction was never applied");
                     }
 
@@ -371,6 +935,62 @@ impl NakamotoNodeLedger {
                 }
             }
         }
+
+        Ok(())
+    }
+
+    /// Notifies about all transactions that reached a registered confirmation
+    /// depth because the chain head moved to `new_head`
+    fn notify_confirmations(
+        &self,
+        new_head: &Rc<NakamotoBlock>,
+        old_height: u64,
+        ancestor_height: u64,
+    ) -> anyhow::Result<()> {
+        let Some(func) = &self.notify_transaction_confirmation_fn else {
+            return Ok(());
+        };
+        let Some(max_depth) = self.confirmation_depths.last() else {
+            return Ok(());
+        };
+
+        let mut block = new_head.clone();
+
+        loop {
+            let confirmations = new_head.get_height() - block.get_height() + 1;
+            if confirmations > *max_depth {
+                break;
+            }
+
+            // Blocks of the new fork had no confirmations before
+            let prev_confirmations = if block.get_height() > ancestor_height {
+                0
+            } else {
+                old_height - block.get_height() + 1
+            };
+
+            if prev_confirmations < confirmations {
+                for depth in self
+                    .confirmation_depths
+                    .range(prev_confirmations + 1..=confirmations)
+                {
+                    for txn_id in block.get_transactions() {
+                        let txn = self
+                            .known_transactions
+                            .get(txn_id)
+                            .expect("block contained unknown transaction");
+                        func(txn.get_source(), txn_id, *depth);
+                    }
+                }
+            }
+
+            if *block.get_parent_id() == GENESIS_BLOCK {
+                break;
+            }
+            block = self.get_block(block.get_parent_id())?.unwrap();
+        }
+
+        Ok(())
     }
 
     /// Picks the longest chain
@@ -392,7 +1012,10 @@ impl NakamotoNodeLedger {
             }
         }
 
-        let mut rng = rand::rng();
+        // The order of forks depends on the hash map, so sort them to be reproducible
+        longest_forks.sort_unstable();
+
+        let mut rng = random::rng();
         let block = longest_forks.into_iter().choose(&mut rng).unwrap();
 
         (block, max_length)
@@ -402,6 +1025,12 @@ impl NakamotoNodeLedger {
         &self.forks
     }
 
+    /// Removes a transaction that can never be applied from the mempool
+    /// The transaction stays known, so it is not requested again.
+    pub fn discard_transaction(&mut self, txn_id: &TransactionId) {
+        self.mempool.remove(txn_id);
+    }
+
     pub fn add_transaction(&mut self, transaction: Rc<Transaction>) -> bool {
         let txn_id = *transaction.get_identifier();
 
@@ -413,6 +1042,7 @@ impl NakamotoNodeLedger {
         }
 
         self.mempool.insert(txn_id);
+        self.first_seen.insert(txn_id, asim::time::now());
 
         if self.mempool.len() > 1_000_000 {
             log::warn!("Mempool size is very large");
@@ -0,0 +1,644 @@
+//! Where the blocks of a Nakamoto chain are kept
+//!
+//! The global ledger and all node ledgers share one store,
+//! so every block is only kept once, no matter how many nodes know about it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+use anyhow::Context;
+
+use cow_tree::{CHILDREN_PER_BRANCH, Digestible, FrozenCowTree, Hash, StoredNode, Value};
+
+use lru::LruCache;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BlockStoreConfig, Difficulty};
+use crate::logic::{AccountId, AccountState, Block, BlockId, TransactionId, TransactionOutput};
+use crate::memory;
+use crate::object::ObjectId;
+
+use super::NakamotoBlock;
+
+/// How many recently loaded state trees to keep around, so that blocks loaded together share them
+const NUM_LOADED_TREES: NonZeroUsize = NonZeroUsize::new(16).unwrap();
+
+/// Outdated records are only removed once they take up at least this many bytes
+const MIN_COMPACTION_SIZE: u64 = 64 * 1024 * 1024;
+
+pub trait BlockStore {
+    /// Adds a block, unless it is already stored
+    fn insert(&self, block: Rc<NakamotoBlock>) -> anyhow::Result<()>;
+
+    fn get(&self, block_id: &BlockId) -> anyhow::Result<Option<Rc<NakamotoBlock>>>;
+
+    fn contains(&self, block_id: &BlockId) -> bool;
+
+    fn num_blocks(&self) -> usize;
+
+    /// The identifiers of all stored blocks (in no particular order)
+    fn get_identifiers(&self) -> Vec<BlockId>;
+
+    /// Estimated memory used by the store and the blocks it keeps in memory (in bytes)
+    fn get_memory_usage(&self) -> u64;
+}
+
+/// Keeps all blocks in memory
+#[derive(Default)]
+pub struct MemoryBlockStore {
+    blocks: RefCell<HashMap<BlockId, Rc<NakamotoBlock>>>,
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn insert(&self, block: Rc<NakamotoBlock>) -> anyhow::Result<()> {
+        self.blocks
+            .borrow_mut()
+            .entry(*block.get_identifier())
+            .or_insert(block);
+        Ok(())
+    }
+
+    fn get(&self, block_id: &BlockId) -> anyhow::Result<Option<Rc<NakamotoBlock>>> {
+        Ok(self.blocks.borrow().get(block_id).cloned())
+    }
+
+    fn contains(&self, block_id: &BlockId) -> bool {
+        self.blocks.borrow().contains_key(block_id)
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    fn get_identifiers(&self) -> Vec<BlockId> {
+        self.blocks.borrow().keys().copied().collect()
+    }
+
+    fn get_memory_usage(&self) -> u64 {
+        let blocks = self.blocks.borrow();
+        let block_size = size_of::<NakamotoBlock>() as u64;
+
+        memory::map_size(&*blocks) + (blocks.len() as u64) * block_size
+    }
+}
+
+/// A block as written to disk
+///
+/// All times and durations are in microseconds.
+/// Nodes are identified by the numbers assigned to them by `ObjectNumbers`.
+#[derive(Serialize, Deserialize)]
+pub(super) struct BlockRecord {
+    pub identifier: BlockId,
+    pub mined_by: AccountId,
+    pub parent: BlockId,
+    pub uncles: Vec<BlockId>,
+    pub height: u64,
+    pub seen_by: u32,
+    pub creation_time: u64,
+    pub full_propagation_time: Option<u64>,
+    pub commit_time: Option<u64>,
+    pub delays: Vec<u64>,
+    pub hops: Vec<(u64, u32)>,
+    pub hop_counts: Vec<u32>,
+    pub first_seen: Option<Vec<(u64, u64)>>,
+    pub difficulty: Difficulty,
+    pub num_nodes: u32,
+    /// The base fee and inclusion delays
+    pub fees: Option<(u64, Vec<u64>)>,
+    pub transactions: Vec<TransactionId>,
+    pub transaction_sizes: Vec<u64>,
+}
+
+/// Object identifiers cannot be serialized, so blocks on disk refer to nodes by number
+#[derive(Default)]
+pub(super) struct ObjectNumbers {
+    objects: Vec<ObjectId>,
+    numbers: HashMap<ObjectId, u64>,
+}
+
+impl ObjectNumbers {
+    pub fn get_number(&mut self, object: ObjectId) -> u64 {
+        *self.numbers.entry(object).or_insert_with(|| {
+            self.objects.push(object);
+            (self.objects.len() - 1) as u64
+        })
+    }
+
+    pub fn get_object(&self, number: u64) -> ObjectId {
+        *self
+            .objects
+            .get(number as usize)
+            .expect("Block refers to an unknown node")
+    }
+}
+
+/// Where a record was written to
+#[derive(Clone, Copy, Debug)]
+struct Location {
+    offset: u64,
+    length: u64,
+}
+
+/// An append-only file of records, which is removed when dropped
+struct RecordFile {
+    path: PathBuf,
+    file: File,
+    length: u64,
+    /// Bytes taken up by records that were replaced by newer ones
+    garbage: u64,
+}
+
+impl RecordFile {
+    fn create(directory: &Path) -> anyhow::Result<Self> {
+        // Several simulations might run in the same process
+        static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+        let name = format!(
+            "simba-blocks-{}-{}.dat",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, AtomicOrdering::SeqCst)
+        );
+        let path = directory.join(name);
+
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create block store at {path:?}"))?;
+
+        log::debug!("Storing blocks in {path:?}");
+
+        Ok(Self {
+            path,
+            file,
+            length: 0,
+            garbage: 0,
+        })
+    }
+
+    fn append(&mut self, data: &[u8]) -> anyhow::Result<Location> {
+        let location = Location {
+            offset: self.length,
+            length: data.len() as u64,
+        };
+
+        self.file
+            .seek(SeekFrom::Start(location.offset))
+            .and_then(|_| self.file.write_all(data))
+            .with_context(|| format!("Failed to write to block store at {:?}", self.path))?;
+        self.length += location.length;
+
+        Ok(location)
+    }
+
+    fn read(&mut self, location: Location) -> anyhow::Result<Vec<u8>> {
+        let mut data = vec![0; location.length as usize];
+
+        self.file
+            .seek(SeekFrom::Start(location.offset))
+            .and_then(|_| self.file.read_exact(&mut data))
+            .with_context(|| format!("Failed to read from block store at {:?}", self.path))?;
+
+        Ok(data)
+    }
+
+    /// Marks a record as outdated
+    fn discard(&mut self, location: Location) {
+        self.garbage += location.length;
+    }
+
+    /// Is it worth copying all current records to a new file?
+    fn needs_compaction(&self) -> bool {
+        self.garbage >= MIN_COMPACTION_SIZE && 2 * self.garbage >= self.length
+    }
+
+    /// Copies a record from another file
+    fn copy_from(&mut self, other: &mut Self, location: Location) -> anyhow::Result<Location> {
+        let data = other.read(location)?;
+        self.append(&data)
+    }
+}
+
+impl Drop for RecordFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove block store {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// A tree node as written to disk
+/// Hashes are stored as bytes, and branches only list the children they have.
+#[derive(Serialize, Deserialize)]
+enum NodeRecord<V> {
+    Leaf(V),
+    Branch(Vec<(u8, Vec<u8>)>),
+    Path { bits: Vec<u8>, child: Vec<u8> },
+}
+
+impl<V> NodeRecord<V> {
+    fn new(node: StoredNode<V>) -> Self {
+        match node {
+            StoredNode::Leaf(value) => Self::Leaf(value),
+            StoredNode::Branch(children) => Self::Branch(
+                children
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(pos, hash)| hash.map(|hash| (pos as u8, hash.to_vec())))
+                    .collect(),
+            ),
+            StoredNode::Path { bits, child } => Self::Path {
+                bits,
+                child: child.to_vec(),
+            },
+        }
+    }
+
+    fn into_node(self) -> anyhow::Result<StoredNode<V>> {
+        let to_hash = |bytes: Vec<u8>| {
+            anyhow::ensure!(bytes.len() == size_of::<Hash>(), "Invalid hash");
+            Ok(Hash::clone_from_slice(&bytes))
+        };
+
+        let node = match self {
+            Self::Leaf(value) => StoredNode::Leaf(value),
+            Self::Branch(children) => {
+                let mut hashes = [None; CHILDREN_PER_BRANCH];
+                for (pos, hash) in children {
+                    let slot = hashes
+                        .get_mut(pos as usize)
+                        .context("Invalid child position")?;
+                    *slot = Some(to_hash(hash)?);
+                }
+                StoredNode::Branch(hashes)
+            }
+            Self::Path { bits, child } => StoredNode::Path {
+                bits,
+                child: to_hash(child)?,
+            },
+        };
+
+        Ok(node)
+    }
+}
+
+/// Stores the nodes of state trees, identified by their hash
+///
+/// Trees of consecutive blocks only differ in the accounts their transactions touched,
+/// so all other nodes are shared and only written once.
+struct TreeStore<V: Value> {
+    locations: HashMap<Hash, Location>,
+    /// Copies of loaded trees share their nodes with the original
+    loaded: LruCache<Hash, FrozenCowTree<V>>,
+}
+
+impl<V: Value + Clone + Digestible + Serialize + DeserializeOwned> TreeStore<V> {
+    fn new() -> Self {
+        Self {
+            locations: Default::default(),
+            loaded: LruCache::new(NUM_LOADED_TREES),
+        }
+    }
+
+    /// Writes all nodes of the tree that were not written before and returns its root hash
+    fn store(&mut self, file: &mut RecordFile, tree: &FrozenCowTree<V>) -> anyhow::Result<Hash> {
+        let locations = &mut self.locations;
+
+        tree.export(
+            &mut |hash: &Hash, node: StoredNode<&V>| -> anyhow::Result<()> {
+                if !locations.contains_key(hash) {
+                    let data = serde_json::to_vec(&NodeRecord::new(node))
+                        .context("Failed to serialize tree node")?;
+                    locations.insert(*hash, file.append(&data)?);
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn load(&mut self, file: &mut RecordFile, root: &Hash) -> anyhow::Result<FrozenCowTree<V>> {
+        if let Some(tree) = self.loaded.get(root) {
+            return Ok(tree.deep_clone().freeze());
+        }
+
+        let locations = &self.locations;
+        let tree = FrozenCowTree::import(root, &mut |hash: &Hash| -> anyhow::Result<_> {
+            let location = *locations.get(hash).context("No such tree node")?;
+            let record: NodeRecord<V> = serde_json::from_slice(&file.read(location)?)
+                .context("Failed to parse tree node")?;
+            record.into_node()
+        })?;
+
+        let copy = tree.deep_clone().freeze();
+        self.loaded.put(*root, tree);
+
+        Ok(copy)
+    }
+
+    /// Copies all nodes to another file and returns where they were written to
+    fn copy_to(
+        &self,
+        from: &mut RecordFile,
+        to: &mut RecordFile,
+    ) -> anyhow::Result<HashMap<Hash, Location>> {
+        self.locations
+            .iter()
+            .map(|(hash, location)| Ok((*hash, to.copy_from(from, *location)?)))
+            .collect()
+    }
+
+    fn get_memory_usage(&self) -> u64 {
+        memory::map_size(&self.locations)
+    }
+}
+
+/// A block that was written to disk at least once
+#[derive(Clone, Copy)]
+struct StoredBlock {
+    location: Location,
+    /// Digest of the record, so that unchanged blocks are not written again
+    digest: u64,
+    state: Hash,
+    outputs: Hash,
+}
+
+struct DiskStoreInner {
+    directory: PathBuf,
+    file: RecordFile,
+    /// The most recently used blocks
+    cache: LruCache<BlockId, Rc<NakamotoBlock>>,
+    /// Blocks that left the cache while they were still referenced elsewhere,
+    /// e.g., by a message in transit
+    ///
+    /// Loading them again would create a second copy, which might diverge,
+    /// so they stay in memory until they are no longer in use.
+    pinned: HashMap<BlockId, Rc<NakamotoBlock>>,
+    /// Pinned blocks are checked again once there are this many of them
+    max_pinned: usize,
+    stored: HashMap<BlockId, StoredBlock>,
+    states: TreeStore<AccountState>,
+    outputs: TreeStore<TransactionOutput>,
+    objects: ObjectNumbers,
+}
+
+impl DiskStoreInner {
+    /// Moves the least recently used blocks out of the cache until at most `cache_size` remain
+    fn evict(&mut self, cache_size: usize) -> anyhow::Result<()> {
+        while self.cache.len() > cache_size {
+            let Some((block_id, block)) = self.cache.pop_lru() else {
+                break;
+            };
+
+            if Rc::strong_count(&block) > 1 {
+                self.pinned.insert(block_id, block);
+            } else if let Err(err) = self.write(&block) {
+                // Keep the block, as it is not on disk
+                self.pinned.insert(block_id, block);
+                return Err(err);
+            }
+        }
+
+        if self.pinned.len() >= self.max_pinned {
+            self.release_pinned()?;
+            // Avoid checking the same blocks over and over again
+            self.max_pinned = (2 * self.pinned.len()).max(cache_size).max(1);
+        }
+
+        if self.file.needs_compaction() {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all pinned blocks that are no longer referenced elsewhere to disk
+    fn release_pinned(&mut self) -> anyhow::Result<()> {
+        let unused: Vec<BlockId> = self
+            .pinned
+            .iter()
+            .filter(|(_, block)| Rc::strong_count(block) == 1)
+            .map(|(block_id, _)| *block_id)
+            .collect();
+
+        for block_id in unused {
+            let block = self.pinned.get(&block_id).unwrap().clone();
+            self.write(&block)?;
+            self.pinned.remove(&block_id);
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, block: &NakamotoBlock) -> anyhow::Result<()> {
+        let block_id = *block.get_identifier();
+        let record = block.to_record(&mut self.objects);
+        let data = serde_json::to_vec(&record).context("Failed to serialize block")?;
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&data);
+        let digest = hasher.finish();
+
+        // The state of a block never changes, but its propagation data might
+        let (state, outputs) = match self.stored.get(&block_id) {
+            Some(stored) if stored.digest == digest => return Ok(()),
+            Some(stored) => {
+                let stored = *stored;
+                self.file.discard(stored.location);
+                (stored.state, stored.outputs)
+            }
+            None => (
+                self.states.store(&mut self.file, block.get_state())?,
+                self.outputs.store(&mut self.file, block.get_outputs())?,
+            ),
+        };
+
+        let location = self.file.append(&data)?;
+        self.stored.insert(block_id, StoredBlock {
+            location,
+            digest,
+            state,
+            outputs,
+        });
+
+        Ok(())
+    }
+
+    fn load(&mut self, block_id: &BlockId) -> anyhow::Result<Option<NakamotoBlock>> {
+        let Some(stored) = self.stored.get(block_id).copied() else {
+            return Ok(None);
+        };
+
+        let record: BlockRecord = serde_json::from_slice(&self.file.read(stored.location)?)
+            .context("Failed to parse block")?;
+        let state = self.states.load(&mut self.file, &stored.state)?;
+        let outputs = self.outputs.load(&mut self.file, &stored.outputs)?;
+
+        Ok(Some(NakamotoBlock::from_record(
+            record,
+            &self.objects,
+            state,
+            outputs,
+        )))
+    }
+
+    /// Copies all current records to a new file, so that outdated ones no longer take up space
+    fn compact(&mut self) -> anyhow::Result<()> {
+        let mut file = RecordFile::create(&self.directory)?;
+
+        // Nothing is changed until all records were copied successfully
+        let states = self.states.copy_to(&mut self.file, &mut file)?;
+        let outputs = self.outputs.copy_to(&mut self.file, &mut file)?;
+        let blocks = self
+            .stored
+            .iter()
+            .map(|(block_id, stored)| {
+                Ok((*block_id, file.copy_from(&mut self.file, stored.location)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.states.locations = states;
+        self.outputs.locations = outputs;
+        for (block_id, location) in blocks {
+            self.stored.get_mut(&block_id).unwrap().location = location;
+        }
+
+        log::debug!(
+            "Compacted block store from {} to {} bytes",
+            self.file.length,
+            file.length
+        );
+
+        // Removes the old file
+        self.file = file;
+        Ok(())
+    }
+}
+
+/// Keeps the most recently used blocks in memory and writes all others to a file
+///
+/// Queries that cover all blocks, such as the block graph, read every block from disk.
+pub struct DiskBlockStore {
+    cache_size: usize,
+    inner: RefCell<DiskStoreInner>,
+}
+
+impl DiskBlockStore {
+    pub fn new(config: &BlockStoreConfig) -> anyhow::Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        let file = RecordFile::create(&directory)?;
+
+        let inner = DiskStoreInner {
+            directory,
+            file,
+            cache: LruCache::unbounded(),
+            pinned: Default::default(),
+            max_pinned: config.cache_size.max(1),
+            stored: Default::default(),
+            states: TreeStore::new(),
+            outputs: TreeStore::new(),
+            objects: Default::default(),
+        };
+
+        Ok(Self {
+            cache_size: config.cache_size,
+            inner: RefCell::new(inner),
+        })
+    }
+}
+
+impl BlockStore for DiskBlockStore {
+    fn insert(&self, block: Rc<NakamotoBlock>) -> anyhow::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        let block_id = *block.get_identifier();
+
+        if inner.cache.contains(&block_id)
+            || inner.pinned.contains_key(&block_id)
+            || inner.stored.contains_key(&block_id)
+        {
+            return Ok(());
+        }
+
+        inner.cache.put(block_id, block);
+        inner.evict(self.cache_size)
+    }
+
+    fn get(&self, block_id: &BlockId) -> anyhow::Result<Option<Rc<NakamotoBlock>>> {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(block) = inner.cache.get(block_id) {
+            return Ok(Some(block.clone()));
+        }
+
+        let block = match inner.pinned.remove(block_id) {
+            Some(block) => block,
+            None => match inner.load(block_id)? {
+                Some(block) => Rc::new(block),
+                None => return Ok(None),
+            },
+        };
+
+        inner.cache.put(*block_id, block.clone());
+        inner.evict(self.cache_size)?;
+
+        Ok(Some(block))
+    }
+
+    fn contains(&self, block_id: &BlockId) -> bool {
+        let inner = self.inner.borrow();
+        inner.cache.contains(block_id)
+            || inner.pinned.contains_key(block_id)
+            || inner.stored.contains_key(block_id)
+    }
+
+    fn num_blocks(&self) -> usize {
+        let inner = self.inner.borrow();
+        let num_unwritten = inner
+            .cache
+            .iter()
+            .map(|(block_id, _)| block_id)
+            .chain(inner.pinned.keys())
+            .filter(|block_id| !inner.stored.contains_key(*block_id))
+            .count();
+
+        inner.stored.len() + num_unwritten
+    }
+
+    fn get_identifiers(&self) -> Vec<BlockId> {
+        let inner = self.inner.borrow();
+        let mut identifiers: Vec<_> = inner.stored.keys().copied().collect();
+
+        identifiers.extend(
+            inner
+                .cache
+                .iter()
+                .map(|(block_id, _)| block_id)
+                .chain(inner.pinned.keys())
+                .filter(|block_id| !inner.stored.contains_key(*block_id))
+                .copied(),
+        );
+
+        identifiers
+    }
+
+    fn get_memory_usage(&self) -> u64 {
+        let inner = self.inner.borrow();
+        let cached_size = size_of::<(BlockId, Rc<NakamotoBlock>)>() + size_of::<NakamotoBlock>();
+
+        (inner.cache.len() * cached_size) as u64
+            + memory::map_size(&inner.pinned)
+            + (inner.pinned.len() * size_of::<NakamotoBlock>()) as u64
+            + memory::map_size(&inner.stored)
+            + inner.states.get_memory_usage()
+            + inner.outputs.get_memory_usage()
+    }
+}
@@ -1,9 +1,19 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::config::Difficulty;
-use crate::logic::{Block, GENESIS_BLOCK, GENESIS_HEIGHT, Transaction, TransactionId};
+use crate::config::{BlockStoreConfig, Difficulty, OrderingPolicy};
+use crate::ledger::{account_key, get_account_state};
+use crate::logic::{
+    AccountState, Block, BlockId, DEFAULT_TRANSACTION_SIZE, GENESIS_BLOCK, GENESIS_HEIGHT,
+    Transaction, TransactionFee, TransactionId, TransactionStatus,
+};
 
-use super::{NakamotoBlock, NakamotoNodeLedger};
+use crate::object::ObjectId;
+use crate::random;
+
+use super::{BlockStore, DiskBlockStore, NakamotoBlock, NakamotoGlobalLedger, NakamotoNodeLedger};
+
+use asim::time::Duration;
 
 use cow_tree::CowTree;
 
@@ -15,6 +25,7 @@ fn make_initial_block(transactions: Vec<TransactionId>) -> Rc<NakamotoBlock> {
     let identifier = rand::random();
     let mined_by = rand::random();
     let uncles = vec![];
+    let transaction_sizes = vec![DEFAULT_TRANSACTION_SIZE; transactions.len()];
 
     Rc::new(NakamotoBlock::new_with_id(
         identifier,
@@ -25,6 +36,9 @@ fn make_initial_block(transactions: Vec<TransactionId>) -> Rc<NakamotoBlock> {
         0,
         Difficulty::default(),
         transactions,
+        transaction_sizes,
+        None,
+        CowTree::default().freeze(),
         CowTree::default().freeze(),
     ))
 }
@@ -35,6 +49,7 @@ fn make_next_block(
 ) -> Rc<NakamotoBlock> {
     let identifier = rand::random();
     let uncles = vec![];
+    let transaction_sizes = vec![DEFAULT_TRANSACTION_SIZE; transactions.len()];
 
     Rc::new(NakamotoBlock::new_with_id(
         identifier,
@@ -45,6 +60,9 @@ fn make_next_block(
         0,
         Difficulty::default(),
         transactions,
+        transaction_sizes,
+        None,
+        CowTree::default().freeze(),
         CowTree::default().freeze(),
     ))
 }
@@ -55,11 +73,17 @@ async fn switch_chain_head() {
 
     let mut ledger = NakamotoNodeLedger::new();
 
+    let reorgs = Rc::new(RefCell::new(vec![]));
+    {
+        let reorgs = reorgs.clone();
+        ledger.set_notify_reorg_fn(Box::new(move |depth| reorgs.borrow_mut().push(depth)));
+    }
+
     let mut fork1 = vec![];
     let mut fork2 = vec![];
 
     let start = make_initial_block(vec![]);
-    ledger.add_new_block(start.clone(), commit_delay);
+    ledger.add_new_block(start.clone(), commit_delay).unwrap();
 
     let mut prev = start.clone();
     for _ in 0..15 {
@@ -67,7 +91,7 @@ async fn switch_chain_head() {
         fork1.push(*tx.get_identifier());
         let block = make_next_block(&prev, vec![*tx.get_identifier()]);
         ledger.add_transaction(tx);
-        ledger.add_new_block(block.clone(), commit_delay);
+        ledger.add_new_block(block.clone(), commit_delay).unwrap();
         prev = block;
     }
 
@@ -84,13 +108,33 @@ async fn switch_chain_head() {
         fork2.push(*tx.get_identifier());
         let block = make_next_block(&prev, vec![*tx.get_identifier()]);
         ledger.add_transaction(tx);
-        ledger.add_new_block(block.clone(), commit_delay);
+        ledger.add_new_block(block.clone(), commit_delay).unwrap();
         prev = block;
     }
 
     assert_eq!(ledger.forks.len(), 2);
     assert_eq!(&ledger.get_longest_chain().0, prev.get_identifier());
 
+    // Switching to the second fork undid all blocks of the first fork
+    assert_eq!(*reorgs.borrow(), vec![15]);
+
+    // The height index only contains blocks of the second fork
+    let mut block = prev.clone();
+    while *block.get_parent_id() != GENESIS_BLOCK {
+        let parent = ledger
+            .get_block_at_height(block.get_height() - 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(parent.get_identifier(), block.get_parent_id());
+        block = parent;
+    }
+    assert!(
+        ledger
+            .get_block_at_height(prev.get_height() + 1)
+            .unwrap()
+            .is_none()
+    );
+
     for tx_id in fork1.iter() {
         assert!(!ledger.is_transaction_applied(tx_id));
     }
@@ -107,3 +151,464 @@ async fn switch_chain_head() {
         assert!(ledger.knows_transaction(tx_id));
     }
 }
+
+#[asim::test]
+async fn transaction_status() {
+    let commit_delay = 2;
+
+    let mut ledger = NakamotoNodeLedger::new();
+
+    let tx = make_transaction();
+    let tx_id = *tx.get_identifier();
+
+    assert_eq!(
+        ledger.get_transaction_status(&tx_id, commit_delay).unwrap(),
+        TransactionStatus::Unknown
+    );
+
+    ledger.add_transaction(tx);
+    assert_eq!(
+        ledger.get_transaction_status(&tx_id, commit_delay).unwrap(),
+        TransactionStatus::Pending
+    );
+
+    let mut prev = make_initial_block(vec![tx_id]);
+    ledger.add_new_block(prev.clone(), commit_delay).unwrap();
+    assert_eq!(
+        ledger.get_transaction_status(&tx_id, commit_delay).unwrap(),
+        TransactionStatus::Applied { depth: 0 }
+    );
+
+    for depth in 1..=3 {
+        let block = make_next_block(&prev, vec![]);
+        ledger.add_new_block(block.clone(), commit_delay).unwrap();
+        prev = block;
+
+        let expected = if depth >= commit_delay {
+            TransactionStatus::Committed { depth }
+        } else {
+            TransactionStatus::Applied { depth }
+        };
+
+        assert_eq!(
+            ledger.get_transaction_status(&tx_id, commit_delay).unwrap(),
+            expected
+        );
+    }
+}
+
+#[asim::test]
+async fn first_seen_time() {
+    let mut ledger = NakamotoNodeLedger::new();
+
+    let tx = make_transaction();
+    let tx_id = *tx.get_identifier();
+    assert_eq!(ledger.get_first_seen_time(&tx_id), None);
+
+    asim::time::sleep(Duration::from_millis(10)).await;
+    let now = asim::time::now();
+    ledger.add_transaction(tx.clone());
+    assert_eq!(ledger.get_first_seen_time(&tx_id), Some(now));
+
+    // Receiving the transaction again must not change when it was first seen
+    asim::time::sleep(Duration::from_millis(10)).await;
+    ledger.add_transaction(tx);
+    assert_eq!(ledger.get_first_seen_time(&tx_id), Some(now));
+}
+
+#[asim::test]
+async fn block_propagation() {
+    let block = make_initial_block(vec![]);
+    assert_eq!(block.get_propagation(), None);
+
+    let miner = ObjectId::random();
+    let peer1 = ObjectId::random();
+    let peer2 = ObjectId::random();
+
+    block.mark_as_seen();
+    block.record_arrival(miner, None);
+
+    asim::time::sleep(Duration::from_millis(10)).await;
+    block.mark_as_seen();
+    block.record_arrival(peer1, Some(miner));
+
+    asim::time::sleep(Duration::from_millis(10)).await;
+    block.mark_as_seen();
+    block.record_arrival(peer2, Some(peer1));
+
+    let propagation = block.get_propagation().unwrap();
+    assert_eq!(propagation.hop_counts, vec![1, 1, 1]);
+    assert_eq!(propagation.delays, vec![
+        Duration::ZERO,
+        Duration::from_millis(10),
+        Duration::from_millis(20)
+    ]);
+
+    // Blocks are not sampled by default
+    assert_eq!(propagation.first_seen, None);
+    assert_eq!(block.get_first_seen_time(&peer1), None);
+}
+
+#[asim::test]
+async fn first_seen_sampling() {
+    let mut global_ledger = NakamotoGlobalLedger::new(2, CowTree::default().freeze());
+    global_ledger.set_first_seen_sampling(2);
+
+    let mut make_block = || {
+        global_ledger
+            .generate_block(
+                rand::random(),
+                GENESIS_BLOCK,
+                vec![],
+                GENESIS_HEIGHT + 1,
+                Difficulty::default(),
+                vec![],
+                vec![],
+                None,
+                CowTree::default().freeze(),
+                CowTree::default().freeze(),
+            )
+            .unwrap()
+    };
+
+    // Only every second block is sampled
+    let unsampled = make_block();
+    let sampled = make_block();
+
+    let miner = ObjectId::random();
+    let peer = ObjectId::random();
+    let start = asim::time::now();
+
+    for block in [&unsampled, &sampled] {
+        block.mark_as_seen();
+        block.record_arrival(miner, None);
+    }
+
+    asim::time::sleep(Duration::from_millis(10)).await;
+    for block in [&unsampled, &sampled] {
+        block.mark_as_seen();
+        block.record_arrival(peer, Some(miner));
+    }
+
+    assert_eq!(unsampled.get_first_seen_time(&peer), None);
+    assert_eq!(sampled.get_first_seen_time(&miner), Some(start));
+    assert_eq!(
+        sampled.get_first_seen_time(&peer),
+        Some(start + Duration::from_millis(10))
+    );
+
+    let propagation = sampled.get_propagation().unwrap();
+    assert_eq!(
+        propagation.first_seen,
+        Some(vec![
+            (miner, start),
+            (peer, start + Duration::from_millis(10))
+        ])
+    );
+}
+
+#[asim::test]
+async fn import_premined_chain() {
+    let num_blocks = 20;
+    let commit_delay = 6;
+
+    let mut global_ledger = NakamotoGlobalLedger::new(1, CowTree::default().freeze());
+    global_ledger
+        .premine(num_blocks, Difficulty::default())
+        .unwrap();
+
+    let (head_id, height) = global_ledger.get_longest_chain();
+    assert_eq!(height, GENESIS_HEIGHT + num_blocks);
+
+    let blocks = global_ledger.get_longest_chain_blocks().unwrap();
+    assert_eq!(blocks.len() as u64, num_blocks);
+    assert_eq!(*blocks[0].get_parent_id(), GENESIS_BLOCK);
+    assert_eq!(*blocks.last().unwrap().get_identifier(), head_id);
+
+    let mut ledger = NakamotoNodeLedger::new();
+    for block in blocks {
+        ledger.add_new_block(block, commit_delay).unwrap();
+    }
+
+    assert_eq!(ledger.get_longest_chain(), (head_id, height));
+}
+
+#[asim::test]
+async fn export_block_graph() {
+    let mut ledger = NakamotoGlobalLedger::new(1, CowTree::default().freeze());
+
+    let mut make_block = |parent, uncles, height| {
+        let block = ledger
+            .generate_block(
+                rand::random(),
+                parent,
+                uncles,
+                height,
+                Difficulty::default(),
+                vec![],
+                vec![],
+                None,
+                CowTree::default().freeze(),
+                CowTree::default().freeze(),
+            )
+            .unwrap();
+        *block.get_identifier()
+    };
+
+    // Two competing blocks; the first one becomes part of the longest chain
+    let main = make_block(GENESIS_BLOCK, vec![], GENESIS_HEIGHT + 1);
+    let fork = make_block(GENESIS_BLOCK, vec![], GENESIS_HEIGHT + 1);
+    let head = make_block(main, vec![fork], GENESIS_HEIGHT + 2);
+
+    let graph = ledger.get_block_graph().unwrap();
+    assert_eq!(graph.blocks.len(), 4);
+
+    let genesis = &graph.blocks[0];
+    assert_eq!(genesis.identifier, GENESIS_BLOCK);
+    assert_eq!(genesis.parent, None);
+
+    let find = |id: BlockId| {
+        graph
+            .blocks
+            .iter()
+            .find(|block| block.identifier == id)
+            .unwrap()
+    };
+    assert!(find(main).in_longest_chain);
+    assert!(!find(fork).in_longest_chain);
+    assert!(find(head).in_longest_chain);
+    assert_eq!(find(head).parent, Some(main));
+    assert_eq!(find(head).uncles, vec![fork]);
+    assert_eq!(graph.blocks.last().unwrap().identifier, head);
+}
+
+#[asim::test]
+async fn block_details() {
+    let mut ledger = NakamotoGlobalLedger::new(1, CowTree::default().freeze());
+    let transactions: Vec<TransactionId> = vec![rand::random(), rand::random()];
+
+    let block = ledger
+        .generate_block(
+            7,
+            GENESIS_BLOCK,
+            vec![],
+            GENESIS_HEIGHT + 1,
+            Difficulty::default(),
+            transactions.clone(),
+            vec![100, 300],
+            None,
+            CowTree::default().freeze(),
+            CowTree::default().freeze(),
+        )
+        .unwrap();
+    assert_eq!(block.get_total_size(), block.get_size() + 400);
+
+    let details = ledger
+        .get_block_details(block.get_identifier())
+        .unwrap()
+        .unwrap();
+    assert_eq!(details.parent, Some(GENESIS_BLOCK));
+    assert_eq!(details.miner, Some(7));
+    assert_eq!(details.transactions, vec![
+        (transactions[0], 100),
+        (transactions[1], 300)
+    ]);
+
+    assert_eq!(
+        ledger
+            .get_block_details(&GENESIS_BLOCK)
+            .unwrap()
+            .unwrap()
+            .miner,
+        None
+    );
+    assert!(ledger.get_block_details(&rand::random()).unwrap().is_none());
+}
+
+#[asim::test]
+async fn pick_transactions_by_tip() {
+    let mut ledger = NakamotoNodeLedger::new();
+
+    let make_transaction_with_fee = |max_fee, max_tip| {
+        let fee = TransactionFee { max_fee, max_tip };
+        Rc::new(Transaction::new_with_fee(rand::random(), 1, fee))
+    };
+
+    let cannot_pay = make_transaction_with_fee(90, 50);
+    let low_tip = make_transaction_with_fee(105, 50);
+    let high_tip = make_transaction_with_fee(200, 20);
+    let mid_tip = make_transaction_with_fee(110, 10);
+
+    for txn in [&cannot_pay, &low_tip, &high_tip, &mid_tip] {
+        ledger.add_transaction(txn.clone());
+    }
+
+    let picked =
+        ledger.get_transactions_from_mempool(2, None, Some(100), &OrderingPolicy::FeePriority);
+    assert_eq!(picked, vec![
+        *high_tip.get_identifier(),
+        *mid_tip.get_identifier()
+    ]);
+
+    // Without a fee market, every transaction can be picked
+    let picked = ledger.get_transactions_from_mempool(10, None, None, &OrderingPolicy::FeePriority);
+    assert_eq!(picked.len(), 4);
+}
+
+#[asim::test]
+async fn pick_transactions_by_size() {
+    let mut ledger = NakamotoNodeLedger::new();
+    let mut expected = vec![];
+
+    for size in [100, 500, 200, 150] {
+        let mut txn = Transaction::new(rand::random(), 1);
+        txn.set_size(size);
+        expected.push(*txn.get_identifier());
+        ledger.add_transaction(Rc::new(txn));
+
+        asim::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The second transaction does not fit, but smaller ones after it still do
+    let picked = ledger.get_transactions_from_mempool(10, Some(500), None, &OrderingPolicy::Fifo);
+    assert_eq!(picked, vec![expected[0], expected[2], expected[3]]);
+
+    // The transaction count still applies
+    let picked = ledger.get_transactions_from_mempool(1, Some(500), None, &OrderingPolicy::Fifo);
+    assert_eq!(picked, vec![expected[0]]);
+}
+
+#[asim::test]
+async fn pick_transactions_in_order() {
+    let mut ledger = NakamotoNodeLedger::new();
+    let mut expected = vec![];
+
+    for _ in 0..5 {
+        let txn = make_transaction();
+        expected.push(*txn.get_identifier());
+        ledger.add_transaction(txn);
+
+        asim::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let picked = ledger.get_transactions_from_mempool(3, None, None, &OrderingPolicy::Fifo);
+    assert_eq!(picked, expected[..3]);
+
+    let mut picked = ledger.get_transactions_from_mempool(10, None, None, &OrderingPolicy::Random);
+    picked.sort();
+    expected.sort();
+    assert_eq!(picked, expected);
+}
+
+#[asim::test]
+async fn pick_transactions_randomly_with_seed() {
+    let transactions: Vec<_> = (0..20).map(|_| make_transaction()).collect();
+
+    // Each ledger iterates its mempool in a different order
+    let mut ledger1 = NakamotoNodeLedger::new();
+    let mut ledger2 = NakamotoNodeLedger::new();
+    for txn in transactions.iter() {
+        ledger1.add_transaction(txn.clone());
+    }
+    for txn in transactions.iter().rev() {
+        ledger2.add_transaction(txn.clone());
+    }
+
+    random::set_seed(42);
+    let picked1 = ledger1.get_transactions_from_mempool(10, None, None, &OrderingPolicy::Random);
+    random::set_seed(42);
+    let picked2 = ledger2.get_transactions_from_mempool(10, None, None, &OrderingPolicy::Random);
+
+    assert_eq!(picked1, picked2);
+}
+
+#[asim::test]
+async fn disk_block_store() {
+    let num_blocks = 20;
+    let commit_delay = 6;
+    let account = 1;
+
+    let mut genesis_state = CowTree::default();
+    genesis_state.insert(&account_key(&account), AccountState::new(100));
+
+    let config = BlockStoreConfig {
+        directory: std::env::temp_dir().to_string_lossy().into(),
+        cache_size: 2,
+    };
+    let store: Rc<dyn BlockStore> = Rc::new(DiskBlockStore::new(&config).unwrap());
+
+    let mut global_ledger = NakamotoGlobalLedger::new(1, genesis_state.freeze());
+    global_ledger.set_block_store(store.clone());
+    global_ledger
+        .premine(num_blocks, Difficulty::default())
+        .unwrap();
+    assert_eq!(store.num_blocks() as u64, num_blocks);
+
+    let first_id = *global_ledger
+        .get_longest_chain_blocks()
+        .unwrap()
+        .first()
+        .unwrap()
+        .get_identifier();
+
+    let mut ledger = NakamotoNodeLedger::with_store(store.clone());
+    for block in global_ledger.get_longest_chain_blocks().unwrap() {
+        ledger.add_new_block(block, commit_delay).unwrap();
+    }
+    assert_eq!(
+        ledger.get_longest_chain(),
+        global_ledger.get_longest_chain()
+    );
+
+    // Only the most recently used blocks are kept in memory
+    let first = Rc::downgrade(&ledger.get_block(&first_id).unwrap().unwrap());
+    for height in GENESIS_HEIGHT + 2..=GENESIS_HEIGHT + num_blocks {
+        ledger.get_block_at_height(height).unwrap().unwrap();
+    }
+    assert!(first.upgrade().is_none());
+
+    // Blocks read back from disk are unchanged
+    let first = ledger
+        .get_block_at_height(GENESIS_HEIGHT + 1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(*first.get_identifier(), first_id);
+    assert_eq!(*first.get_parent_id(), GENESIS_BLOCK);
+    assert_eq!(
+        get_account_state(first.get_state(), &account),
+        Some(AccountState::new(100))
+    );
+    assert_eq!(first.get_full_propagation_delay(), Some(Duration::ZERO));
+
+    let graph = global_ledger.get_block_graph().unwrap();
+    assert_eq!(graph.blocks.len() as u64, num_blocks + 1);
+}
+
+#[asim::test]
+async fn disk_block_store_keeps_referenced_blocks() {
+    let config = BlockStoreConfig {
+        directory: std::env::temp_dir().to_string_lossy().into(),
+        cache_size: 1,
+    };
+    let store = DiskBlockStore::new(&config).unwrap();
+
+    // Only the first block is referenced outside of the store
+    let first = make_initial_block(vec![]);
+    store.insert(first.clone()).unwrap();
+
+    let second = make_next_block(&first, vec![]);
+    let second_id = *second.get_identifier();
+    store.insert(second.clone()).unwrap();
+
+    store.insert(make_next_block(&second, vec![])).unwrap();
+    drop(second);
+    assert_eq!(store.num_blocks(), 3);
+
+    // Blocks that are still in use are not loaded a second time
+    let loaded = store.get(first.get_identifier()).unwrap().unwrap();
+    assert!(Rc::ptr_eq(&loaded, &first));
+
+    let loaded = store.get(&second_id).unwrap().unwrap();
+    assert_eq!(*loaded.get_identifier(), second_id);
+    assert_eq!(loaded.get_parent_id(), first.get_identifier());
+}
@@ -0,0 +1,132 @@
+//! Execution of transactions that spend and create outputs (the UTXO model)
+
+use std::collections::HashSet;
+
+use cow_tree::{CowTree, FrozenCowTree, Hash};
+
+use crate::config::GenesisAccount;
+use crate::logic::{AccountId, OutputId, Transaction, TransactionId, TransactionOutput};
+use crate::random;
+
+use super::InvalidTransaction;
+
+/// The transaction that "created" the outputs of the genesis accounts
+const GENESIS_TRANSACTION: TransactionId = 0;
+
+/// Maps an output identifier to its position in the tree of unspent outputs
+pub(crate) fn output_key(output_id: &OutputId) -> Hash {
+    let mut key = Hash::default();
+    let transaction = output_id.transaction.to_le_bytes();
+    let index = output_id.index.to_le_bytes();
+
+    key[..transaction.len()].copy_from_slice(&transaction);
+    key[transaction.len()..transaction.len() + index.len()].copy_from_slice(&index);
+    key
+}
+
+/// The output holding the balance of the n-th genesis account
+pub(crate) fn genesis_output_id(account_idx: usize) -> OutputId {
+    OutputId {
+        transaction: GENESIS_TRANSACTION,
+        index: account_idx.try_into().expect("Too many genesis accounts"),
+    }
+}
+
+/// Creates one output for every genesis account
+pub(crate) fn make_genesis_outputs(
+    accounts: &[GenesisAccount],
+) -> FrozenCowTree<TransactionOutput> {
+    let mut outputs = CowTree::default();
+
+    for (idx, account) in accounts.iter().enumerate() {
+        outputs.insert(&output_key(&genesis_output_id(idx)), TransactionOutput {
+            owner: account.account,
+            amount: account.balance,
+        });
+    }
+
+    outputs.freeze()
+}
+
+/// Adds up all unspent outputs owned by an account
+/// Returns `None` if the account does not own any outputs.
+pub(crate) fn get_utxo_balance(
+    outputs: &FrozenCowTree<TransactionOutput>,
+    account: &AccountId,
+) -> Option<u64> {
+    outputs
+        .iter()
+        .filter(|(_, output)| output.owner == *account)
+        .map(|(_, output)| output.amount)
+        .reduce(u64::saturating_add)
+}
+
+/// Adds an output that is not created by any transaction, e.g., a block reward
+pub(crate) fn add_output(outputs: &mut CowTree<TransactionOutput>, output: TransactionOutput) {
+    let output_id = OutputId {
+        transaction: random::random(),
+        index: 0,
+    };
+    outputs.insert(&output_key(&output_id), output);
+}
+
+/// Spends the inputs of a transaction and adds its outputs
+///
+/// The inputs have to cover the outputs plus the base fee and tip of the transaction.
+/// The base fee (if any) is burned; returns the remainder of the inputs, which the caller
+/// pays to the block producer. Does not modify the tree if the transaction is invalid.
+pub(crate) fn apply_utxo_transaction(
+    outputs: &mut CowTree<TransactionOutput>,
+    transaction: &Transaction,
+    base_fee: Option<u64>,
+) -> Result<u64, InvalidTransaction> {
+    let mut spent = HashSet::new();
+    let mut input_amount: u64 = 0;
+
+    for input in transaction.get_inputs() {
+        // Spending the same output twice within a transaction is a double spend as well
+        if !spent.insert(*input) {
+            return Err(InvalidTransaction::SpentInput);
+        }
+
+        let output = outputs
+            .get(&output_key(input))
+            .ok_or(InvalidTransaction::SpentInput)?;
+        if output.owner != *transaction.get_source() {
+            return Err(InvalidTransaction::ForeignInput);
+        }
+
+        input_amount = input_amount.saturating_add(output.amount);
+    }
+
+    let base_fee = base_fee.unwrap_or(0);
+    let tip = transaction
+        .get_fee()
+        .get_effective_tip(base_fee)
+        .ok_or(InvalidTransaction::InsufficientBalance)?;
+
+    let output_amount = transaction
+        .get_outputs()
+        .iter()
+        .try_fold(0u64, |sum, output| sum.checked_add(output.amount))
+        .ok_or(InvalidTransaction::InsufficientBalance)?;
+
+    let remainder = input_amount
+        .checked_sub(output_amount)
+        .filter(|fee| *fee >= base_fee + tip)
+        .ok_or(InvalidTransaction::InsufficientBalance)?;
+
+    for input in transaction.get_inputs() {
+        outputs.remove(&output_key(input));
+    }
+
+    for (idx, output) in transaction.get_outputs().iter().enumerate() {
+        let output_id = OutputId {
+            transaction: *transaction.get_identifier(),
+            index: idx as u32,
+        };
+        outputs.insert(&output_key(&output_id), *output);
+    }
+
+    Ok(remainder - base_fee)
+}
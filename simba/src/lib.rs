@@ -1,48 +1,107 @@
-#![feature(let_chains)]
+#![cfg_attr(feature = "nightly", feature(let_chains))]
 // See https://github.com/rust-lang/rust-clippy/issues/6353
 #![allow(clippy::await_holding_refcell_ref)]
 // Generated by uint crate
 #![allow(clippy::assign_op_pattern)]
 #![allow(clippy::ptr_offset_with_cast)]
 
+mod anomalies;
+mod block_graph;
+mod bridge;
+mod calibration;
+mod churn;
 mod clients;
 mod config;
 mod connection;
 mod events;
 mod failures;
+mod fingerprint;
+mod format;
+mod latency;
 mod ledger;
 mod library;
 mod link;
 mod logic;
+mod memory;
 mod message;
 mod metrics;
 mod node;
 mod object;
+mod random;
+mod remote;
+mod report;
 mod scene;
+mod schema;
+mod serde_time;
 mod simulation;
 mod stats;
+mod swap;
+mod topology;
+mod validation;
 
 #[cfg(feature = "runners")]
 mod runners;
 
 // The public API
+pub use anomalies::{Anomaly, AnomalyThresholds};
+pub use block_graph::{BlockDetails, BlockGraph, BlockGraphFormat, BlockGraphNode};
+pub use calibration::{CalibrationResult, run_calibration};
 pub use config::{
-    Assert, Connectivity, Constraint, ExperimentConfiguration, NetworkConfiguration, ParameterType,
-    ProtocolConfiguration, TestConfiguration,
+    Assert, AsynchronyConfig, AtomicSwapConfig, BaselineConfig, BlockStoreConfig, BridgeConfig,
+    BridgeDirection, Checkpoint, ChurnAction, ChurnConfig, ChurnEvent, ClientAssignment,
+    Connectivity, Constraint, DisconnectedPolicy, ExecutionConfig, ExperimentConfiguration,
+    FailureConfig, FailureTarget, FeeDistribution, FeeMarketConfig, GenesisAccount, GenesisConfig,
+    InboxConfig, MinerPolicy, MiningPoolConfig, NetworkConfiguration, OrderingPolicy,
+    OverflowPolicy, ParameterType, PartitionWindow, PeerScoringConfig, ProtocolConfiguration,
+    ProtocolUpgrade, Reachability, RegionOutage, RequestRetryConfig, RpcLoadConfig, ScenarioAction,
+    ScenarioConfiguration, ScenarioEvent, SecondaryChainConfiguration, StallWindow,
+    TestConfiguration, TimeoutConfig, TrafficPattern, TransactionModel,
+    TransactionSizeDistribution, UpgradeActivation, VotingPowerDistribution,
 };
 pub use events::{BlockEvent, LinkEvent, NodeEvent, StatisticsEvent};
 pub use failures::Failures;
-pub use library::Library;
-pub use link::{Bandwidth, Latency};
-pub use logic::{Block, BlockId, GENESIS_BLOCK};
+pub use fingerprint::Fingerprint;
+pub use format::NumberFormat;
+pub use latency::LatencyHistogram;
+pub use library::{BUILTIN_PRESETS, Library};
+pub use link::{Bandwidth, Latency, LinkDirection, LinkDirectionStatistics};
+pub use logic::{
+    AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, NodeChainInfo, TransactionFee,
+    TransactionId, TransactionSpec, TransactionStatus,
+};
+pub use memory::MemoryUsage;
 pub use message::Message;
-pub use metrics::{ChainMetricType, ChainMetrics, MetricType, NetworkMetricType};
-pub use node::{Location, NodeIndex};
+pub use metrics::{
+    BlockPropagation, ChainMetricType, ChainMetrics, ChainSplitStatistics, ClientInfo,
+    ConfirmationStatistics, FeeMarketStatistics, LinkGoodput, MempoolStatistics, MetricType,
+    MiningPoolStatistics, NetworkMetricType, OrderingStatistics, PeerBanStatistics,
+    PropagationStatistics,
+};
+pub use node::{Location, NodeIndex, current_node};
 pub use object::{Object, ObjectId};
-pub use simulation::Simulation;
-pub use stats::{GlobalStatistics, NodeStatistics};
+pub use remote::{
+    RemoteEvent, RemoteMessage, RemoteRequest, RemoteResponse, read_remote_message, serve_remote,
+    write_remote_message,
+};
+pub use report::{ReportFormat, RunReport};
+pub use schema::{
+    ChainMetricsRecord, ConfigSnapshot, EventRecord, Record, RecordData, SCHEMA_VERSION,
+    StatisticsRecord,
+};
+pub use simulation::{EventCallback, Simulation, SimulationSpeed, StatsEventCallback};
+pub use stats::{GlobalStatistics, NodeStatistics, StatisticType, StatisticsSnapshot};
+pub use swap::SwapResults;
+pub use validation::{SetupProblem, find_setup_problems, validate_setup, validate_timeout};
+
+// Time is part of the public API, so users should not need to depend on asim themselves
+pub use asim::time::{Duration, Time};
+
+#[cfg(feature = "schema")]
+pub use schema::get_json_schema;
 
 #[cfg(feature = "runners")]
-pub use runners::{EndlessRunner, ExperimentRunner, TestRunner};
+pub use runners::{
+    EndlessRunner, ExperimentRunner, ScenarioRunner, SeedSweepRunner, SweepSummary, TestRunner,
+};
 
 type RcCell<T> = std::rc::Rc<std::cell::RefCell<T>>;
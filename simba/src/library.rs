@@ -2,15 +2,43 @@ use std::collections::HashMap;
 use std::fs::{File, read_dir};
 use std::path::Path;
 
+use anyhow::Context;
+
 use crate::{
-    ExperimentConfiguration, NetworkConfiguration, ProtocolConfiguration, TestConfiguration,
+    ExperimentConfiguration, NetworkConfiguration, ProtocolConfiguration, ScenarioConfiguration,
+    TestConfiguration,
 };
 
+/// Presets that ship with SimBA, so something can be run without a library directory
+/// Each preset is a protocol and a network of the same name.
+pub const BUILTIN_PRESETS: [&str; 4] = ["bitcoin", "ethereum", "pbft", "gossip"];
+
+/// The protocol and network of every preset (in the same order as `BUILTIN_PRESETS`)
+const BUILTIN_FILES: [(&str, &str); 4] = [
+    (
+        include_str!("../presets/protocols/bitcoin.ron"),
+        include_str!("../presets/networks/bitcoin.ron"),
+    ),
+    (
+        include_str!("../presets/protocols/ethereum.ron"),
+        include_str!("../presets/networks/ethereum.ron"),
+    ),
+    (
+        include_str!("../presets/protocols/pbft.ron"),
+        include_str!("../presets/networks/pbft.ron"),
+    ),
+    (
+        include_str!("../presets/protocols/gossip.ron"),
+        include_str!("../presets/networks/gossip.ron"),
+    ),
+];
+
 pub struct Library {
     protocols: HashMap<String, ProtocolConfiguration>,
     networks: HashMap<String, NetworkConfiguration>,
     tests: HashMap<String, TestConfiguration>,
     experiments: HashMap<String, ExperimentConfiguration>,
+    scenarios: HashMap<String, ScenarioConfiguration>,
 }
 
 impl Library {
@@ -80,6 +108,13 @@ impl Library {
         let tests = Self::read_config_files(base_path, "tests")?;
         let experiments = Self::read_config_files(base_path, "experiments")?;
 
+        // Scenarios were added later, so older libraries might not have them
+        let scenarios = if base_path.join("scenarios").exists() {
+            Self::read_config_files(base_path, "scenarios")?
+        } else {
+            Default::default()
+        };
+
         log::info!("Found protocol configurations: {:?}", protocols.keys());
         log::info!("Found network configurations: {:?}", networks.keys());
 
@@ -88,6 +123,31 @@ impl Library {
             networks,
             tests,
             experiments,
+            scenarios,
+        })
+    }
+
+    /// A library that only contains the built-in presets (see `BUILTIN_PRESETS`)
+    pub fn builtin() -> anyhow::Result<Self> {
+        let mut protocols = HashMap::default();
+        let mut networks = HashMap::default();
+
+        for (name, (protocol, network)) in BUILTIN_PRESETS.iter().zip(BUILTIN_FILES) {
+            let protocol = ron::from_str(protocol)
+                .with_context(|| format!("Failed to parse protocol of preset \"{name}\""))?;
+            let network = ron::from_str(network)
+                .with_context(|| format!("Failed to parse network of preset \"{name}\""))?;
+
+            protocols.insert(name.to_string(), protocol);
+            networks.insert(name.to_string(), network);
+        }
+
+        Ok(Self {
+            protocols,
+            networks,
+            tests: Default::default(),
+            experiments: Default::default(),
+            scenarios: Default::default(),
         })
     }
 
@@ -136,6 +196,22 @@ impl Library {
         }
     }
 
+    pub fn get_scenario(&self, name: &str) -> anyhow::Result<&ScenarioConfiguration> {
+        match self.scenarios.get(name) {
+            Some(scenario) => Ok(scenario),
+            None => {
+                anyhow::bail!(
+                    "No such scenario \"{name}\", found {:?}",
+                    self.get_scenario_names()
+                );
+            }
+        }
+    }
+
+    pub fn get_scenario_names(&self) -> Vec<&str> {
+        self.scenarios.keys().map(|k| k.as_str()).collect()
+    }
+
     pub fn get_test_names(&self) -> Vec<&str> {
         self.tests.keys().map(|k| k.as_str()).collect()
     }
@@ -152,3 +228,22 @@ impl Library {
         self.protocols.keys().map(|k| k.as_str()).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BUILTIN_PRESETS, Library};
+    use crate::{Failures, Simulation};
+
+    #[test]
+    fn builtin_presets() {
+        let library = Library::builtin().unwrap();
+
+        for name in BUILTIN_PRESETS {
+            let protocol = library.get_protocol(name).unwrap().clone();
+            let network = library.get_network(name).unwrap().clone();
+
+            let failures = Failures::none(network.num_nodes());
+            assert!(Simulation::new(protocol, network, failures, None).is_ok());
+        }
+    }
+}
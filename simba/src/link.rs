@@ -3,18 +3,265 @@ use crate::node::{Node, NodeData};
 use crate::object::{Object, ObjectId};
 use crate::{Message, emit_event};
 
-use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+
+use asim::network::NetworkMessage;
+use asim::time::{Duration, START_TIME, Time};
+
+use serde::{Deserialize, Serialize};
 
 pub use asim::network::{Bandwidth, Latency};
 
 pub type Link = asim::network::Link<Message, NodeData>;
 
+/// The direction of traffic on a link
+/// Forward is from the first to the second node the link was created with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, derive_more::Display, Serialize, Deserialize)]
+pub enum LinkDirection {
+    Forward,
+    Backward,
+}
+
+impl LinkDirection {
+    fn as_index(&self) -> usize {
+        match self {
+            Self::Forward => 0,
+            Self::Backward => 1,
+        }
+    }
+}
+
+/// Traffic statistics for one direction of a link
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkDirectionStatistics {
+    /// Total number of messages sent
+    pub num_messages: u64,
+    /// Total number of bytes sent
+    pub num_bytes: u64,
+    /// Number of messages that have been received already
+    pub num_delivered: u64,
+    /// Total number of bytes that have been received already
+    pub num_delivered_bytes: u64,
+    /// Total time delivered messages spent waiting for transmission (in seconds)
+    pub total_queueing_delay: f64,
+}
+
+impl LinkDirectionStatistics {
+    /// The average time a message waited for transmission (in milliseconds)
+    pub fn get_average_queueing_delay(&self) -> f64 {
+        if self.num_delivered == 0 {
+            0.0
+        } else {
+            1000.0 * self.total_queueing_delay / (self.num_delivered as f64)
+        }
+    }
+}
+
+/// Keeps track of the traffic on a link
+pub(crate) struct LinkStatistics {
+    first_node: ObjectId,
+    latency: Latency,
+    /// Upload bandwidth of the sender in each direction (in Mbit/s)
+    bandwidths: [u64; 2],
+    start: Cell<Time>,
+    directions: RefCell<[LinkDirectionStatistics; 2]>,
+    /// Send times and sizes of messages that have not been received yet
+    in_flight: RefCell<[VecDeque<(Time, u64)>; 2]>,
+}
+
+impl LinkStatistics {
+    fn new(first_node: ObjectId, latency: Latency, bandwidths: [u64; 2]) -> Self {
+        Self {
+            first_node,
+            latency,
+            bandwidths,
+            start: Cell::new(asim::time::now()),
+            directions: Default::default(),
+            in_flight: Default::default(),
+        }
+    }
+
+    fn get_direction(&self, source: &ObjectId) -> LinkDirection {
+        if *source == self.first_node {
+            LinkDirection::Forward
+        } else {
+            LinkDirection::Backward
+        }
+    }
+
+    fn record_message_sent(&self, source: &ObjectId, size: u64) {
+        let idx = self.get_direction(source).as_index();
+
+        let mut directions = self.directions.borrow_mut();
+        directions[idx].num_messages += 1;
+        directions[idx].num_bytes += size;
+
+        self.in_flight.borrow_mut()[idx].push_back((asim::time::now(), size));
+    }
+
+    /// Links deliver messages in order, so the oldest message in flight is the one received
+    ///
+    /// Must be called for every message that arrives, even if the receiver drops it.
+    pub(crate) fn record_message_received(&self, source: &ObjectId) {
+        let idx = self.get_direction(source).as_index();
+
+        let Some((send_time, size)) = self.in_flight.borrow_mut()[idx].pop_front() else {
+            log::warn!("Received message that was never sent over this link");
+            return;
+        };
+
+        // Whatever is left after propagation and transmission was spent waiting
+        let elapsed = asim::time::now() - send_time;
+        let transmission_time = get_transmission_time(size, self.bandwidths[idx]);
+        let queueing_delay = (elapsed.as_seconds_f64()
+            - self.latency.as_seconds_f64()
+            - transmission_time.as_seconds_f64())
+        .max(0.0);
+
+        let mut directions = self.directions.borrow_mut();
+        directions[idx].num_delivered += 1;
+        directions[idx].num_delivered_bytes += size;
+        directions[idx].total_queueing_delay += queueing_delay;
+    }
+
+    pub(crate) fn get_latency(&self) -> Latency {
+        self.latency
+    }
+
+    pub(crate) fn get(&self, direction: LinkDirection) -> LinkDirectionStatistics {
+        self.directions.borrow()[direction.as_index()].clone()
+    }
+
+    /// Average throughput in the specified direction (in bits/s)
+    pub(crate) fn get_utilization(&self, direction: LinkDirection) -> f64 {
+        let elapsed = (asim::time::now() - self.start.get()).as_seconds_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        let num_bytes = self.directions.borrow()[direction.as_index()].num_bytes;
+        (num_bytes * 8) as f64 / elapsed
+    }
+
+    /// Average rate at which data arrived in the specified direction (in bits/s)
+    pub(crate) fn get_goodput(&self, direction: LinkDirection) -> f64 {
+        let elapsed = (asim::time::now() - self.start.get()).as_seconds_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        let num_bytes = self.directions.borrow()[direction.as_index()].num_delivered_bytes;
+        (num_bytes * 8) as f64 / elapsed
+    }
+
+    /// Forget all traffic that happened so far
+    /// Used, for example, after warmup
+    pub(crate) fn reset(&self) {
+        self.start.set(asim::time::now());
+        *self.directions.borrow_mut() = Default::default();
+    }
+}
+
+/// How long it takes to send `size` bytes at the given bandwidth (in Mbit/s)
+fn get_transmission_time(size: u64, bandwidth: u64) -> Duration {
+    // Bits divided by Mbit/s yields microseconds
+    Duration::from_micros(size * 8 / bandwidth.max(1))
+}
+
+/// The upload capacity of a node, which is shared by all of its links
+///
+/// Transmissions are served one after another, so a node that sends to many peers
+/// at once saturates its uplink even if every single link could carry more data.
+pub(crate) struct Uplink {
+    /// Upload bandwidth in Mbit/s
+    bandwidth: u64,
+    /// Start of the current (or most recent) busy period
+    busy_since: Cell<Time>,
+    /// When all queued transmissions will be complete
+    busy_until: Cell<Time>,
+    /// Busy time of earlier periods that has not been reported yet
+    unreported: Cell<Duration>,
+    reported_until: Cell<Time>,
+}
+
+impl Uplink {
+    pub(crate) fn new(bandwidth: u64) -> Self {
+        let start = START_TIME;
+
+        Self {
+            bandwidth,
+            busy_since: Cell::new(start),
+            busy_until: Cell::new(start),
+            unreported: Cell::new(Duration::ZERO),
+            reported_until: Cell::new(start),
+        }
+    }
+
+    /// Queues the transmission of `size` bytes
+    /// Returns how long it takes until they have been sent (including the wait for earlier ones)
+    pub(crate) fn transmit(&self, size: u64) -> Duration {
+        let now = asim::time::now();
+        let transmission_time = get_transmission_time(size, self.bandwidth);
+
+        if self.busy_until.get() <= now {
+            // The previous busy period is over, so it can be reported in full
+            let finished = self.get_busy_time(self.busy_until.get());
+            self.unreported.set(self.unreported.get() + finished);
+
+            self.busy_since.set(now);
+            self.busy_until.set(now + transmission_time);
+        } else {
+            self.busy_until
+                .set(self.busy_until.get() + transmission_time);
+        }
+
+        self.busy_until.get() - now
+    }
+
+    /// How long the uplink was busy since this was last called
+    pub(crate) fn take_busy_time(&self) -> Duration {
+        let now = asim::time::now();
+        let current = self.get_busy_time(self.busy_until.get().min(now));
+
+        self.reported_until.set(now);
+        self.unreported.replace(Duration::ZERO) + current
+    }
+
+    /// Unreported busy time of the current period up to `end`
+    fn get_busy_time(&self, end: Time) -> Duration {
+        let start = self.busy_since.get().max(self.reported_until.get());
+
+        if end > start {
+            end - start
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
 /// Listens for changes to the link and emits events
-#[derive(Default)]
-struct LinkCallback {}
+struct LinkCallback {
+    statistics: Rc<LinkStatistics>,
+    /// Both ends of the link, so that sent data can be accounted to the sender's uplink
+    nodes: [Weak<Node>; 2],
+}
 
 impl asim::network::LinkCallback<Message, NodeData> for LinkCallback {
     fn message_sent(&self, source: &ObjectId, destination: &ObjectId, message: &Message) {
+        self.statistics
+            .record_message_sent(source, message.get_size());
+
+        let sender = self
+            .nodes
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|node| node.get_identifier() == *source);
+        if let Some(sender) = sender {
+            sender.get_data().record_upload(message.get_size());
+        }
+
         emit_event!(Event::MessageSent {
             source: *source,
             target: *destination,
@@ -42,6 +289,21 @@ pub(super) fn create_link(
     node2: Rc<Node>,
     _bandwidth: Option<Bandwidth>,
     latency: Latency,
-) -> Rc<Link> {
-    Node::connect(node1, node2, latency, Box::new(LinkCallback::default()))
+) -> (Rc<Link>, Rc<LinkStatistics>) {
+    let bandwidths = [
+        node1.get_data().get_bandwidth(),
+        node2.get_data().get_bandwidth(),
+    ];
+    let statistics = Rc::new(LinkStatistics::new(
+        node1.get_identifier(),
+        latency,
+        bandwidths,
+    ));
+    let callback = LinkCallback {
+        statistics: statistics.clone(),
+        nodes: [Rc::downgrade(&node1), Rc::downgrade(&node2)],
+    };
+
+    let link = Node::connect(node1, node2, latency, Box::new(callback));
+    (link, statistics)
 }
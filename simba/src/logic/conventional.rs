@@ -0,0 +1,216 @@
+//! Functionality shared by protocols that use the conventional ledger (PBFT, Raft, and Snowman)
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use asim::time::{Duration, Time};
+
+use crate::clients::Client;
+use crate::config::TimeoutConfig;
+use crate::latency::LatencyHistogram;
+use crate::ledger::{ConventionalBlock, ConventionalGlobalLedger};
+use crate::link::Link;
+use crate::logic::{Block, BlockId, GENESIS_BLOCK};
+use crate::metrics::ChainMetrics;
+use crate::node::Node;
+use crate::object::ObjectId;
+
+/// Keeps track of when blocks were finalized by validators and observers
+pub(super) struct FinalizationTracker {
+    num_nodes: u32,
+    num_validators: u32,
+    blocks: RefCell<HashMap<BlockId, Finalization>>,
+}
+
+#[derive(Default)]
+struct Finalization {
+    num_nodes: u32,
+    num_validators: u32,
+    /// How long it took for all (correct) nodes to finalize the block
+    full_delay: Option<Duration>,
+    /// How long it took for all (correct) validators to finalize the block
+    validator_delay: Option<Duration>,
+}
+
+impl FinalizationTracker {
+    /// Only correct nodes are counted, as faulty ones never finalize blocks
+    pub fn new(num_nodes: u32, num_validators: u32) -> Self {
+        Self {
+            num_nodes,
+            num_validators,
+            blocks: Default::default(),
+        }
+    }
+
+    pub fn record(&self, block: &ConventionalBlock, is_validator: bool) {
+        let delay = asim::time::now() - block.get_creation_time();
+        let mut blocks = self.blocks.borrow_mut();
+        let entry = blocks.entry(*block.get_identifier()).or_default();
+
+        entry.num_nodes += 1;
+        if entry.num_nodes == self.num_nodes {
+            entry.full_delay = Some(delay);
+        }
+
+        if is_validator {
+            entry.num_validators += 1;
+            if entry.num_validators == self.num_validators {
+                entry.validator_delay = Some(delay);
+            }
+        }
+    }
+
+    /// The average delay (in milliseconds) until all nodes and all validators finalized a block
+    fn get_average_delays(&self) -> (f64, f64) {
+        let average = |delays: Vec<Duration>| {
+            if delays.is_empty() {
+                0.0
+            } else {
+                let total: f64 = delays.iter().map(|delay| delay.as_millis_f64()).sum();
+                total / (delays.len() as f64)
+            }
+        };
+
+        let blocks = self.blocks.borrow();
+        let full = blocks
+            .values()
+            .filter_map(|entry| entry.full_delay)
+            .collect();
+        let validators = blocks
+            .values()
+            .filter_map(|entry| entry.validator_delay)
+            .collect();
+
+        (average(full), average(validators))
+    }
+}
+
+/// Moves the global commit to `block`, unless it is already at this height or later
+/// This allows multiple nodes to report the same commit
+pub(super) fn advance_latest_commit(
+    global_ledger: &ConventionalGlobalLedger,
+    node: &Node,
+    block: &ConventionalBlock,
+) {
+    if global_ledger.has_committed_block() {
+        let latest = global_ledger
+            .get_block(&global_ledger.get_latest_commit())
+            .expect("No such block");
+        if latest.get_height() >= block.get_height() {
+            return;
+        }
+    }
+
+    let delay = global_ledger.set_latest_commit(*block.get_identifier());
+    node.get_statistics().record_block_commit(delay);
+}
+
+/// Computes the metrics of a chain, where all nodes agree on the latest commit
+pub(super) fn get_metrics(
+    global_ledger: &ConventionalGlobalLedger,
+    finalizations: &FinalizationTracker,
+    timeout: TimeoutConfig,
+    clients: &[Rc<Client>],
+    links: &BTreeMap<ObjectId, Rc<Link>>,
+) -> ChainMetrics {
+    let latest_commit = global_ledger.get_latest_commit();
+
+    let mut end_block = global_ledger.get_block(&latest_commit).expect("No blocks");
+    loop {
+        match timeout {
+            TimeoutConfig::Seconds { warmup, runtime } => {
+                let end = Time::from_seconds(warmup + runtime);
+                if end_block.get_creation_time() <= end {
+                    break;
+                }
+            }
+            TimeoutConfig::Blocks { warmup, runtime } => {
+                if end_block.get_height() <= warmup + runtime {
+                    break;
+                }
+            }
+        }
+
+        end_block = global_ledger
+            .get_block(end_block.get_parent_id())
+            .expect("No parent block");
+    }
+
+    let mut blocks_in_interval = 0;
+    let mut num_transactions = 0;
+    let mut total_size = 0;
+
+    let end_time = end_block.get_creation_time();
+    let mut next_block = end_block;
+
+    loop {
+        match timeout {
+            TimeoutConfig::Seconds { warmup, .. } => {
+                let start = Time::from_seconds(warmup);
+                if next_block.get_creation_time() < start {
+                    break;
+                }
+            }
+            TimeoutConfig::Blocks { warmup, .. } => {
+                if next_block.get_height() < warmup {
+                    break;
+                }
+            }
+        }
+
+        blocks_in_interval += 1;
+
+        num_transactions += next_block.num_transactions() as u64;
+        total_size += next_block.get_size();
+
+        if next_block.get_parent_id() == &GENESIS_BLOCK {
+            break;
+        } else {
+            next_block = global_ledger
+                .get_block(next_block.get_parent_id())
+                .expect("No parent block");
+        }
+    }
+
+    let elapsed = end_time - next_block.get_creation_time();
+
+    // FIXME this also counts blocks in the warmup period
+    let avg_block_interval = elapsed.as_seconds_f64() / (global_ledger.num_blocks() as f64);
+
+    let avg_block_size = (total_size as f64) / (blocks_in_interval as f64);
+
+    let mut latencies = LatencyHistogram::default();
+    for client in clients {
+        latencies.merge(&client.get_latencies());
+    }
+
+    // FIXME latencies also contains transactions during warmup period
+    // assert_eq!(latencies.len(), num_transactions);
+
+    let avg_latency = latencies.get_total() / (num_transactions as f64);
+
+    let mut num_network_messages = 0;
+    for link in links.values() {
+        num_network_messages += link.num_total_messages();
+    }
+
+    // FIXME this also contains blocks during the warmup period
+    let (avg_block_propagation, avg_validator_block_propagation) =
+        finalizations.get_average_delays();
+
+    ChainMetrics {
+        total_blocks_mined: blocks_in_interval,
+        num_network_messages,
+        total_blocks_accepted: blocks_in_interval,
+        longest_chain_length: global_ledger.num_blocks() as u64,
+        avg_latency,
+        avg_block_interval,
+        avg_block_propagation,
+        avg_validator_block_propagation,
+        num_transactions,
+        elapsed,
+        avg_block_size,
+        avg_duplicate_messages: 0.0,
+    }
+}
@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap};
 use std::rc::Rc;
-use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering as AtomicOrdering};
 
 use asim::sync::{Condvar, Mutex};
 use asim::time::{Duration, Time};
@@ -9,11 +9,14 @@ use asim::time::{Duration, Time};
 use derivative::Derivative;
 
 use crate::Connectivity;
+use crate::failures::Failures;
 use crate::logic::{BlockId, Client, GlobalLogic, Link, NodeLogic, TimeoutConfig};
+use crate::memory::{self, MemoryUsage};
 use crate::message::MessageType;
 use crate::metrics::ChainMetrics;
 use crate::node::NodeIndex;
 use crate::object::ObjectId;
+use crate::random;
 
 mod node;
 pub use node::GossipNodeLogic;
@@ -33,24 +36,41 @@ pub struct GossipBlock {
     #[derivative(Debug = "ignore")]
     num_nodes: u32,
     #[derivative(Debug = "ignore")]
+    num_validators: u32,
+    #[derivative(Debug = "ignore")]
     block_counter: Rc<BlockCounter>,
     /// How many nodes have seen this block?
     seen_by: AtomicU32,
+    /// How many mining nodes have seen this block?
+    seen_by_validators: AtomicU32,
+    /// How many redundant announcements or copies of this block nodes received
+    num_duplicates: AtomicU64,
     /// Creation time in seconds
     creation_time: Time,
     /// Time it was seen by all nodes
     full_propagation_time: RefCell<Option<Time>>,
+    /// Time it was seen by all mining nodes
+    validator_propagation_time: RefCell<Option<Time>>,
 }
 
 impl GossipBlock {
-    fn new(payload: Vec<u8>, num_nodes: u32, block_counter: Rc<BlockCounter>) -> Self {
+    fn new(
+        payload: Vec<u8>,
+        num_nodes: u32,
+        num_validators: u32,
+        block_counter: Rc<BlockCounter>,
+    ) -> Self {
         Self {
             payload,
             num_nodes,
-            identifier: rand::random(),
+            num_validators,
+            identifier: random::random(),
             block_counter,
             full_propagation_time: RefCell::new(None),
+            validator_propagation_time: RefCell::new(None),
             seen_by: AtomicU32::new(0),
+            seen_by_validators: AtomicU32::new(0),
+            num_duplicates: AtomicU64::new(0),
             creation_time: asim::time::now(),
         }
     }
@@ -74,7 +94,33 @@ impl GossipBlock {
         }
     }
 
-    fn mark_as_seen(&self) {
+    /// How long did it take for all (correct) mining nodes to see this block?
+    /// Returns None, if the block has not reached all of them yet
+    pub fn get_validator_propagation_delay(&self) -> Option<Duration> {
+        self.validator_propagation_time
+            .borrow()
+            .map(|seen_time| seen_time - self.creation_time)
+    }
+
+    pub fn num_duplicates(&self) -> u64 {
+        self.num_duplicates.load(AtomicOrdering::SeqCst)
+    }
+
+    fn record_duplicate(&self) {
+        self.num_duplicates.fetch_add(1, AtomicOrdering::SeqCst);
+    }
+
+    fn mark_as_seen(&self, is_validator: bool) {
+        if is_validator {
+            let prev = self.seen_by_validators.fetch_add(1, AtomicOrdering::SeqCst);
+            if prev + 1 == self.num_validators {
+                let _ = self
+                    .validator_propagation_time
+                    .borrow_mut()
+                    .insert(asim::time::now());
+            }
+        }
+
         let prev = self.seen_by.fetch_add(1, AtomicOrdering::SeqCst);
         if prev + 1 == self.num_nodes {
             let _ = self
@@ -119,29 +165,71 @@ pub struct GossipGlobalLogic {
     block_size: u32,
     retry_delay: u32,
     num_nodes: u32,
+    /// How many (correct) nodes are mining nodes; the others only relay blocks
+    num_validators: u32,
+    /// The node that creates the block; this is always a mining node
+    producer: NodeIndex,
+    fanout: Option<u32>,
     all_blocks: Rc<RefCell<HashMap<BlockId, Rc<GossipBlock>>>>,
     block_counter: Rc<BlockCounter>,
 }
 
 impl GossipGlobalLogic {
-    pub fn instantiate(block_size: u32, retry_delay: u32, num_nodes: u32) -> Rc<dyn GlobalLogic> {
+    /// `mining_nodes` contains whether each node is a mining node, ordered by node index
+    pub fn instantiate(
+        block_size: u32,
+        retry_delay: u32,
+        failures: &Failures,
+        mining_nodes: Vec<bool>,
+        fanout: Option<u32>,
+    ) -> Rc<dyn GlobalLogic> {
+        if let Some(fanout) = fanout {
+            log::info!("Gossip relays new blocks to {fanout} random peers");
+        }
+
+        let num_nodes = failures.num_correct_nodes();
+        let validators: Vec<_> = mining_nodes
+            .iter()
+            .enumerate()
+            .filter(|(idx, is_mining)| **is_mining && !failures.is_faulty(&(*idx as NodeIndex)))
+            .map(|(idx, _)| idx as NodeIndex)
+            .collect();
+        let producer = *validators
+            .first()
+            .expect("Need at least one correct mining node");
+        let num_validators = validators.len() as u32;
+
+        if num_validators < num_nodes {
+            log::info!(
+                "{} of {num_nodes} nodes only relay blocks",
+                num_nodes - num_validators
+            );
+        }
+
         Rc::new(Self {
             block_counter: Default::default(),
             all_blocks: Default::default(),
             block_size,
             num_nodes,
+            num_validators,
+            producer,
             retry_delay,
+            fanout,
         })
     }
 }
 
 #[async_trait::async_trait(?Send)]
 impl GlobalLogic for GossipGlobalLogic {
-    fn new_node_logic(&self, _node_idx: NodeIndex) -> Rc<dyn NodeLogic> {
+    fn new_node_logic(&self, node_idx: NodeIndex) -> Rc<dyn NodeLogic> {
         Rc::new(GossipNodeLogic::new(
+            node_idx,
             self.block_size,
             self.retry_delay,
             self.num_nodes,
+            self.num_validators,
+            self.producer,
+            self.fanout,
             self.all_blocks.clone(),
             self.block_counter.clone(),
         ))
@@ -155,11 +243,19 @@ impl GlobalLogic for GossipGlobalLogic {
     ) -> ChainMetrics {
         let mut total_block_propagation = Duration::ZERO;
         let mut propagated_block_count = 0;
+        let mut num_duplicates = 0;
+        let mut total_validator_propagation = Duration::ZERO;
+        let mut validator_block_count = 0;
 
         for (_, block) in self.all_blocks.borrow().iter() {
             if let Some(delay) = block.get_full_propagation_delay() {
                 total_block_propagation += delay;
                 propagated_block_count += 1;
+                num_duplicates += block.num_duplicates();
+            }
+            if let Some(delay) = block.get_validator_propagation_delay() {
+                total_validator_propagation += delay;
+                validator_block_count += 1;
             }
         }
 
@@ -167,6 +263,10 @@ impl GlobalLogic for GossipGlobalLogic {
 
         let avg_block_propagation =
             total_block_propagation.as_millis_f64() / (propagated_block_count as f64);
+        let avg_duplicate_messages =
+            (num_duplicates as f64) / ((propagated_block_count * self.num_nodes as u64) as f64);
+        let avg_validator_block_propagation =
+            total_validator_propagation.as_millis_f64() / (validator_block_count as f64);
 
         let mut num_network_messages = 0;
         for link in links.values() {
@@ -184,6 +284,8 @@ impl GlobalLogic for GossipGlobalLogic {
             total_blocks_accepted: propagated_block_count,
             longest_chain_length: 0,
             total_blocks_mined: 0,
+            avg_duplicate_messages,
+            avg_validator_block_propagation,
         }
     }
 
@@ -191,6 +293,19 @@ impl GlobalLogic for GossipGlobalLogic {
         true
     }
 
+    /// Includes the payload of every block
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let all_blocks = self.all_blocks.borrow();
+        let blocks = all_blocks
+            .values()
+            .map(|block| (size_of::<GossipBlock>() + block.payload.capacity()) as u64)
+            .sum::<u64>();
+
+        let mut usage = MemoryUsage::default();
+        usage.add("all_blocks", memory::map_size(&all_blocks) + blocks);
+        usage
+    }
+
     async fn wait_for_blocks(&self, blocks: u64) {
         let mut count = self.block_counter.count.lock().await;
         while (*count as u64) < blocks {
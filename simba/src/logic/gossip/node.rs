@@ -5,9 +5,13 @@ use std::rc::Rc;
 use asim::sync::{SyncCondvar, SyncMutex};
 use asim::time::Duration;
 
+use rand::seq::IteratorRandom;
+
 use crate::logic::{NodeLogic, Transaction};
-use crate::node::Node;
+use crate::memory::{self, MemoryUsage};
+use crate::node::{Node, NodeIndex};
 use crate::object::ObjectId;
+use crate::random;
 use crate::{BlockId, Message};
 
 use super::{BlockCounter, GossipBlock, GossipMessage};
@@ -18,16 +22,28 @@ pub struct GossipNodeLogic {
     block_cond: Rc<SyncCondvar>,
     block_counter: Rc<BlockCounter>,
     all_blocks: Rc<RefCell<HashMap<BlockId, Rc<GossipBlock>>>>,
+    /// The index of this node within the chain
+    node_index: NodeIndex,
     num_nodes: u32,
+    num_validators: u32,
+    /// The only node that creates a block
+    producer: NodeIndex,
+    /// Relay new blocks to all peers if not set
+    fanout: Option<u32>,
     block_size: u32,
     retry_delay: Duration,
 }
 
 impl GossipNodeLogic {
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
+        node_index: NodeIndex,
         block_size: u32,
         retry_delay: u32,
         num_nodes: u32,
+        num_validators: u32,
+        producer: NodeIndex,
+        fanout: Option<u32>,
         all_blocks: Rc<RefCell<HashMap<BlockId, Rc<GossipBlock>>>>,
         block_counter: Rc<BlockCounter>,
     ) -> Self {
@@ -37,23 +53,54 @@ impl GossipNodeLogic {
             block_cond: Default::default(),
             block_size,
             retry_delay: Duration::from_millis(retry_delay as u64),
+            node_index,
             num_nodes,
+            num_validators,
+            producer,
+            fanout,
             all_blocks,
             block_counter,
         }
     }
 
+    /// Announce a block to our peers (except the one we got it from)
+    fn relay_block(&self, block_id: BlockId, node: &Node, source: Option<ObjectId>) {
+        let message = GossipMessage::NotifyNewBlock(block_id);
+
+        let Some(fanout) = self.fanout else {
+            node.broadcast(message.into(), source);
+            return;
+        };
+
+        let peers = node
+            .get_peers()
+            .into_iter()
+            .filter(|peer| Some(*peer) != source)
+            .choose_multiple(&mut random::rng(), fanout as usize);
+
+        for peer in peers {
+            node.send_to(&peer, message.clone());
+        }
+    }
+
+    /// Count a message for a block we already have (or already requested)
+    fn record_duplicate(&self, block_id: &BlockId) {
+        if let Some(block) = self.all_blocks.borrow().get(block_id) {
+            block.record_duplicate();
+        }
+    }
+
     /// Record a new block that we received
     fn add_block(&self, block: Rc<GossipBlock>, node: &Node, source: Option<ObjectId>) {
         let block_id = block.get_identifier();
         log::trace!("Got new block with id={block_id}");
 
-        block.mark_as_seen();
+        block.mark_as_seen(node.is_mining());
         self.known_blocks
             .lock()
             .insert(block.get_identifier(), block);
         self.block_cond.notify_all();
-        node.broadcast(GossipMessage::NotifyNewBlock(block_id).into(), source);
+        self.relay_block(block_id, node, source);
     }
 
     /// Create a new block and send it
@@ -61,11 +108,15 @@ impl GossipNodeLogic {
         &self,
         node: &Node,
         payload: Vec<u8>,
-        num_nodes: u32,
         all_blocks: &RefCell<HashMap<BlockId, Rc<GossipBlock>>>,
         block_counter: Rc<BlockCounter>,
     ) {
-        let block = Rc::new(GossipBlock::new(payload, num_nodes, block_counter));
+        let block = Rc::new(GossipBlock::new(
+            payload,
+            self.num_nodes,
+            self.num_validators,
+            block_counter,
+        ));
         log::debug!("Created new block with id={}", block.get_identifier());
         all_blocks
             .borrow_mut()
@@ -123,17 +174,12 @@ impl GossipNodeLogic {
 impl NodeLogic for GossipNodeLogic {
     fn init(&self, _node: Rc<Node>) {}
 
-    #[tracing::instrument(skip(self, node))]
-    async fn run(&self, node: Rc<Node>, _is_mining: bool) {
-        if node.get_index() == 0 {
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
+    async fn run(&self, node: Rc<Node>, is_mining: bool) {
+        // Non-mining nodes only relay the blocks of others
+        if is_mining && self.node_index == self.producer {
             let payload = vec![0u8; self.block_size as usize];
-            self.generate_block(
-                &node,
-                payload,
-                self.num_nodes,
-                &self.all_blocks,
-                self.block_counter.clone(),
-            );
+            self.generate_block(&node, payload, &self.all_blocks, self.block_counter.clone());
         }
     }
 
@@ -142,11 +188,14 @@ impl NodeLogic for GossipNodeLogic {
         _node: &Node,
         _transaction: Rc<Transaction>,
         _source: Option<ObjectId>,
-    ) {
-        // Do nothing
+    ) -> bool {
+        false
     }
 
-    #[tracing::instrument(skip(self, node, message))]
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
         let message: GossipMessage = message.try_into().unwrap();
         match message {
@@ -156,6 +205,8 @@ impl NodeLogic for GossipNodeLogic {
 
                 if is_new {
                     self.request_new_block(node.clone(), source, block_id);
+                } else {
+                    self.record_duplicate(&block_id);
                 }
             }
             GossipMessage::GetBlock(block_id) => {
@@ -167,8 +218,20 @@ impl NodeLogic for GossipNodeLogic {
             GossipMessage::SendBlock(block) => {
                 if self.requested_blocks.lock().remove(&block.get_identifier()) {
                     self.add_block(block, node, Some(source))
+                } else {
+                    block.record_duplicate();
                 }
             }
         }
     }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        usage.add("known_blocks", memory::map_size(&self.known_blocks.lock()));
+        usage.add(
+            "requested_blocks",
+            memory::set_size(&self.requested_blocks.lock()),
+        );
+        usage
+    }
 }
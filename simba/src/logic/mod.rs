@@ -1,12 +1,23 @@
+use crate::block_graph::{BlockDetails, BlockGraph};
 use crate::clients::Client;
 use crate::config::TimeoutConfig;
+use crate::fingerprint::FingerprintBuilder;
 use crate::link::Link;
-use crate::metrics::ChainMetrics;
+use crate::memory::MemoryUsage;
+use crate::metrics::{
+    ChainMetrics, ChainSplitStatistics, FeeMarketStatistics, MiningPoolStatistics,
+    PeerBanStatistics, PropagationStatistics,
+};
 use crate::node::{Node, NodeIndex};
 use crate::object::ObjectId;
+use crate::random;
 use crate::{Connectivity, Message};
 
-use cow_tree::FrozenCowTree;
+use asim::time::Time;
+
+use cow_tree::{Digestible, FrozenCowTree, Hash};
+
+use serde::{Deserialize, Serialize};
 
 use std::collections::BTreeMap;
 use std::rc::Rc;
@@ -20,12 +31,20 @@ pub use gossip::*;
 mod nakamoto;
 pub use nakamoto::*;
 
+mod conventional;
+
 mod pbft;
 pub use pbft::*;
 
+mod raft;
+pub use raft::*;
+
 mod snowball;
 pub use snowball::*;
 
+mod snowman;
+pub use snowman::*;
+
 mod ethereum2;
 //pub use ethereum2::*;
 
@@ -51,17 +70,122 @@ pub const HASH_SIZE: u64 = 16;
 /// Size of an integer
 pub const NUM_SIZE: u64 = 4;
 
+/// Size of a simple transfer (in bytes)
+pub const DEFAULT_TRANSACTION_SIZE: u64 = 2 * HASH_SIZE + 5 * NUM_SIZE + SIGNATURE_SIZE;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AccountState {
-    #[allow(dead_code)]
     balance: u64,
+    /// The nonce of the last transaction applied for this account (zero if there is none)
+    nonce: u64,
+}
+
+/// The status of a transaction as seen by a specific node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// The node does not know about this transaction
+    Unknown,
+    /// The transaction is in the node's mempool
+    Pending,
+    /// The transaction is part of the node's current chain
+    /// but might still be reverted
+    Applied {
+        /// How many blocks have been appended after the one containing the transaction
+        depth: u64,
+    },
+    /// The transaction is considered final by the node
+    Committed { depth: u64 },
 }
 
 #[derive(Debug)]
 pub struct Transaction {
     identifier: TransactionId,
-    // TODO support UTXO model as well
     source: AccountId,
     nonce: u64,
+    fee: TransactionFee,
+    /// Funds moved to another account (if any)
+    transfer: Option<Transfer>,
+    /// The outputs spent by this transaction (UTXO model only)
+    inputs: Vec<OutputId>,
+    /// The outputs created by this transaction (UTXO model only)
+    outputs: Vec<TransactionOutput>,
+    creation_time: Time,
+    /// Marked as a target for front-running
+    is_victim: bool,
+    /// Size of the transaction (in bytes)
+    size: u64,
+}
+
+/// What a transaction offers to pay in an EIP-1559-style fee market
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransactionFee {
+    /// The most the transaction pays in total (base fee plus tip)
+    pub max_fee: u64,
+    /// The most the transaction pays to the block producer on top of the base fee
+    pub max_tip: u64,
+}
+
+impl TransactionFee {
+    /// The tip the block producer receives for the given base fee
+    /// Returns `None` if the transaction cannot pay the base fee
+    pub fn get_effective_tip(&self, base_fee: u64) -> Option<u64> {
+        self.max_fee
+            .checked_sub(base_fee)
+            .map(|remainder| remainder.min(self.max_tip))
+    }
+}
+
+/// Moves funds from the source of a transaction to another account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub recipient: AccountId,
+    pub amount: u64,
+}
+
+/// Identifies an output by the transaction that created it and its position
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutputId {
+    pub transaction: TransactionId,
+    pub index: u32,
+}
+
+/// Funds that can be spent by their owner (UTXO model only)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionOutput {
+    pub owner: AccountId,
+    pub amount: u64,
+}
+
+impl Digestible for TransactionOutput {
+    fn digest(&self) -> Hash {
+        [
+            self.owner.to_le_bytes().as_slice(),
+            &self.amount.to_le_bytes(),
+        ]
+        .concat()
+        .digest()
+    }
+}
+
+/// A node's view of the chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeChainInfo {
+    pub head: BlockId,
+    pub height: u64,
+    /// The number of chain tips the node knows about (including its head)
+    pub num_forks: usize,
+}
+
+/// A transaction submitted from outside the simulation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionSpec {
+    /// The sending account; defaults to the account of the receiving node
+    pub source: Option<AccountId>,
+    /// Must be the next nonce of the source account if the protocol executes transactions
+    pub nonce: u64,
+    pub fee: TransactionFee,
+    /// When to hand the transaction to the node; defaults to immediately
+    pub submit_at: Option<Time>,
 }
 
 pub trait Block {
@@ -73,13 +197,62 @@ pub trait Block {
     fn get_state(&self) -> &FrozenCowTree<AccountState>;
 }
 
+impl AccountState {
+    pub fn new(balance: u64) -> Self {
+        Self { balance, nonce: 0 }
+    }
+
+    pub fn get_balance(&self) -> u64 {
+        self.balance
+    }
+
+    pub fn get_nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub(crate) fn set_balance(&mut self, balance: u64) {
+        self.balance = balance;
+    }
+
+    pub(crate) fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+}
+
+impl Digestible for AccountState {
+    fn digest(&self) -> Hash {
+        [self.balance.to_le_bytes(), self.nonce.to_le_bytes()]
+            .concat()
+            .digest()
+    }
+}
+
 impl Transaction {
     pub(crate) fn new(source: AccountId, nonce: u64) -> Self {
-        let identifier = rand::random::<TransactionId>();
+        Self::new_with_fee(source, nonce, TransactionFee::default())
+    }
+
+    pub(crate) fn new_with_fee(source: AccountId, nonce: u64, fee: TransactionFee) -> Self {
+        Self::new_with_id(random::random(), source, nonce, fee)
+    }
+
+    pub(crate) fn new_with_id(
+        identifier: TransactionId,
+        source: AccountId,
+        nonce: u64,
+        fee: TransactionFee,
+    ) -> Self {
         Self {
             identifier,
             source,
             nonce,
+            fee,
+            transfer: None,
+            inputs: vec![],
+            outputs: vec![],
+            creation_time: asim::time::now(),
+            is_victim: false,
+            size: DEFAULT_TRANSACTION_SIZE,
         }
     }
 
@@ -94,6 +267,51 @@ impl Transaction {
     pub fn get_nonce(&self) -> u64 {
         self.nonce
     }
+
+    pub fn get_fee(&self) -> &TransactionFee {
+        &self.fee
+    }
+
+    pub fn get_transfer(&self) -> Option<&Transfer> {
+        self.transfer.as_ref()
+    }
+
+    pub fn get_inputs(&self) -> &[OutputId] {
+        &self.inputs
+    }
+
+    pub fn get_outputs(&self) -> &[TransactionOutput] {
+        &self.outputs
+    }
+
+    pub fn get_creation_time(&self) -> Time {
+        self.creation_time
+    }
+
+    pub fn is_victim(&self) -> bool {
+        self.is_victim
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn mark_as_victim(&mut self) {
+        self.is_victim = true;
+    }
+
+    pub(crate) fn set_size(&mut self, size: u64) {
+        self.size = size;
+    }
+
+    pub(crate) fn set_transfer(&mut self, transfer: Transfer) {
+        self.transfer = Some(transfer);
+    }
+
+    pub(crate) fn set_utxos(&mut self, inputs: Vec<OutputId>, outputs: Vec<TransactionOutput>) {
+        self.inputs = inputs;
+        self.outputs = outputs;
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -101,7 +319,66 @@ pub trait NodeLogic {
     async fn run(&self, node: Rc<Node>, _is_mining: bool);
     fn init(&self, _node: Rc<Node>);
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message);
-    fn add_transaction(&self, node: &Node, transction: Rc<Transaction>, source: Option<ObjectId>);
+
+    /// Hand a new transaction to this node
+    /// Returns false if the protocol does not process transactions, in which case it is dropped
+    fn add_transaction(
+        &self,
+        node: &Node,
+        transction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) -> bool;
+
+    /// Get the status of a transaction as seen by this node
+    /// Protocols that do not keep track of transactions always return `Unknown`
+    fn get_transaction_status(&self, _txn_id: &TransactionId) -> TransactionStatus {
+        TransactionStatus::Unknown
+    }
+
+    /// Get all transactions in this node's mempool
+    fn get_mempool(&self) -> Vec<TransactionId> {
+        vec![]
+    }
+
+    /// Get the identifier and height of the block this node considers the head of the chain
+    /// Protocols without a chain return `None`
+    fn get_chain_head(&self) -> Option<(BlockId, u64)> {
+        None
+    }
+
+    /// Get the size of a block (including its transactions) in bytes
+    /// Returns `None` if the block is unknown or the protocol has no blocks
+    fn get_block_size(&self, _block_id: &BlockId) -> Option<u64> {
+        None
+    }
+
+    /// Get the number of chain tips this node knows about
+    /// Protocols without forks only have a single chain
+    fn get_num_forks(&self) -> usize {
+        1
+    }
+
+    /// Get the balance and nonce of an account as seen by this node
+    /// Returns `None` if the account does not exist or the protocol does not track state
+    fn get_account_state(&self, _account: &AccountId) -> Option<AccountState> {
+        None
+    }
+
+    /// Get the balance of an account as seen by this node
+    fn get_balance(&self, account: &AccountId) -> Option<u64> {
+        self.get_account_state(account)
+            .map(|account_state| account_state.get_balance())
+    }
+
+    /// Notify clients once their transactions are buried under this many blocks
+    /// Protocols with immediate finality ignore this and notify clients on commit
+    fn add_confirmation_depth(&self, _depth: u64) {}
+
+    /// Estimated memory used by the data structures of this node, such as mempools or pending messages
+    /// Protocols that do not track this return no structures
+    fn get_memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::default()
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -115,6 +392,66 @@ pub trait GlobalLogic {
     ) -> ChainMetrics;
     fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool;
     async fn wait_for_blocks(&self, blocks: u64);
+
+    /// Add the final state of the ledger to a run fingerprint
+    /// Protocols without a ledger do not add anything
+    fn add_to_fingerprint(&self, _fingerprint: &mut FingerprintBuilder) {}
+
+    /// Returns `None` if the protocol does not have a fee market
+    fn get_fee_market_statistics(&self) -> Option<FeeMarketStatistics> {
+        None
+    }
+
+    /// Get the revenue of all mining pools
+    /// Protocols without mining pools return an empty list
+    fn get_mining_pool_statistics(&self) -> Vec<MiningPoolStatistics> {
+        vec![]
+    }
+
+    /// How many victim transactions in the longest chain were front-run
+    fn get_front_run_victims(&self) -> u64 {
+        0
+    }
+
+    /// Returns `None` if all nodes follow the same protocol version
+    fn get_chain_split_statistics(&self) -> Option<ChainSplitStatistics> {
+        None
+    }
+
+    /// Returns `None` if nodes do not ban their peers
+    fn get_peer_ban_statistics(&self) -> Option<PeerBanStatistics> {
+        None
+    }
+
+    /// Get all blocks created so far, including forks
+    /// Protocols without blocks return an empty graph
+    fn get_block_graph(&self) -> BlockGraph {
+        BlockGraph::default()
+    }
+
+    /// Look up a single block and its transactions
+    /// Returns `None` if there is no such block or the protocol does not have blocks
+    fn get_block_details(&self, _block_id: &BlockId) -> Option<BlockDetails> {
+        None
+    }
+
+    /// Estimated memory used by the ledger state at the head of the longest chain (in bytes)
+    /// Protocols without an account state return zero
+    fn get_state_size(&self) -> u64 {
+        0
+    }
+
+    /// How quickly blocks reached the other nodes
+    /// Protocols that do not track this return no blocks
+    fn get_propagation_statistics(&self) -> PropagationStatistics {
+        PropagationStatistics::default()
+    }
+
+    /// Estimated memory used by data structures shared by all nodes, such as the global ledger
+    /// Protocols that do not track this return no structures
+    fn get_memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::default()
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -127,6 +464,7 @@ impl NodeLogic for DummyLogic {
         _node: &Node,
         _transction: Rc<Transaction>,
         _source: Option<ObjectId>,
-    ) {
+    ) -> bool {
+        false
     }
 }
@@ -10,6 +10,7 @@ use crate::config::{
 use crate::ledger::{DiffTarget, MAX_DIFF_TARGET, NakamotoBlock};
 use crate::logic::Block;
 use crate::node::NodeIndex;
+use crate::random;
 
 use rand::RngCore;
 
@@ -29,6 +30,8 @@ struct ProofOfWork {
     difficulty_adjustment: DifficultyAdjustment,
     difficulty: Difficulty,
     difficulty_target: DiffTarget,
+    /// Time between the creation of the genesis block and the start of the simulation
+    genesis_age: Duration,
 }
 
 /// Simplistic implementation of Ouroboros
@@ -43,7 +46,7 @@ struct Ouroboros {
 impl BlockGenerator for ProofOfWork {
     fn should_create_block(&mut self, _idx: NodeIndex) -> bool {
         // TODO should be a function of the node's compute power
-        let mut rng = rand::rng();
+        let mut rng = random::rng();
 
         let mut value = DiffTarget([0, 0, 0, 0]);
         for idx in 0..4 {
@@ -71,7 +74,7 @@ impl BlockGenerator for ProofOfWork {
         let elapsed = if let Some(parent) = parent_block {
             new_block.get_creation_time() - parent.get_creation_time()
         } else {
-            new_block.get_creation_time() - START_TIME
+            (new_block.get_creation_time() + self.genesis_age) - START_TIME
         };
 
         let chain_length = new_block.get_height();
@@ -170,6 +173,7 @@ impl BlockGenerator for Ouroboros {
 pub fn make_block_generator(
     num_nodes: u32,
     config: &NakamotoBlockGenerationConfig,
+    genesis_age: Duration,
 ) -> Box<dyn BlockGenerator> {
     match config {
         NakamotoBlockGenerationConfig::ProofOfWork {
@@ -184,6 +188,7 @@ pub fn make_block_generator(
                 difficulty_target: diff_target,
                 difficulty_adjustment: *difficulty_adjustment,
                 target_block_interval: Time::from_seconds(*target_block_interval),
+                genesis_age,
             })
         }
         NakamotoBlockGenerationConfig::Ouroboros {
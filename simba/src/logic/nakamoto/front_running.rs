@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::ledger::NakamotoGlobalLedger;
+use crate::logic::{AccountId, BlockId, Transaction, TransactionFee};
+use crate::node::NodeIndex;
+
+/// Block producers that place their own transactions in front of victim transactions
+pub(super) struct FrontRunner {
+    attackers: Vec<NodeIndex>,
+    /// How many victims were front-run in each block created by an attacker
+    front_run_victims: RefCell<HashMap<BlockId, u64>>,
+}
+
+impl FrontRunner {
+    pub fn new(attackers: &[NodeIndex]) -> Self {
+        Self {
+            attackers: attackers.to_vec(),
+            front_run_victims: Default::default(),
+        }
+    }
+
+    pub fn is_attacker(&self, node_index: NodeIndex) -> bool {
+        self.attackers.contains(&node_index)
+    }
+
+    /// Creates a transaction that outbids the victim, so it will be ordered first
+    /// The nonce has to be the next one of the attacker's account for the attack to be valid.
+    pub fn make_transaction(
+        &self,
+        account: AccountId,
+        nonce: u64,
+        victim: &Transaction,
+    ) -> Transaction {
+        let fee = TransactionFee {
+            max_fee: victim.get_fee().max_fee.saturating_add(1),
+            max_tip: victim.get_fee().max_tip.saturating_add(1),
+        };
+
+        Transaction::new_with_fee(account, nonce, fee)
+    }
+
+    pub fn record_block(&self, block_id: BlockId, num_victims: u64) {
+        self.front_run_victims
+            .borrow_mut()
+            .insert(block_id, num_victims);
+    }
+
+    /// Counts the front-run victims in the longest chain
+    /// (victims in orphaned blocks are not affected)
+    pub fn count_victims(&self, ledger: &NakamotoGlobalLedger) -> anyhow::Result<u64> {
+        let front_run_victims = self.front_run_victims.borrow();
+
+        let count = ledger
+            .get_longest_chain_blocks()?
+            .iter()
+            .filter_map(|block| front_run_victims.get(block.get_identifier()))
+            .sum();
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrontRunner;
+
+    use crate::config::Difficulty;
+    use crate::ledger::NakamotoGlobalLedger;
+    use crate::logic::{GENESIS_BLOCK, Transaction, TransactionFee};
+
+    use cow_tree::CowTree;
+
+    #[asim::test]
+    async fn count_victims_in_chain() {
+        let front_runner = FrontRunner::new(&[1]);
+        assert!(front_runner.is_attacker(1));
+        assert!(!front_runner.is_attacker(0));
+
+        let fee = TransactionFee {
+            max_fee: 10,
+            max_tip: 2,
+        };
+        let victim = Transaction::new_with_fee(rand::random(), 1, fee);
+        let attack = front_runner.make_transaction(rand::random(), 1, &victim);
+        assert_eq!(attack.get_nonce(), 1);
+        assert_eq!(attack.get_fee().max_fee, 11);
+        assert_eq!(attack.get_fee().max_tip, 3);
+
+        // Two competing blocks at the same height; only the first one is part of the longest chain
+        let mut ledger = NakamotoGlobalLedger::new(2, CowTree::default().freeze());
+        let mut blocks = vec![];
+        for _ in 0..2 {
+            let block = ledger
+                .generate_block(
+                    0,
+                    GENESIS_BLOCK,
+                    vec![],
+                    1,
+                    Difficulty::default(),
+                    vec![],
+                    vec![],
+                    None,
+                    CowTree::default().freeze(),
+                    CowTree::default().freeze(),
+                )
+                .unwrap();
+            blocks.push(*block.get_identifier());
+        }
+
+        front_runner.record_block(blocks[0], 2);
+        front_runner.record_block(blocks[1], 5);
+
+        assert_eq!(front_runner.count_victims(&ledger).unwrap(), 2);
+    }
+}
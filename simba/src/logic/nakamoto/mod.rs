@@ -5,18 +5,31 @@ use std::rc::Rc;
 use asim::time::{Duration, Time};
 
 use crate::RcCell;
+use crate::block_graph::{BlockDetails, BlockGraph};
 use crate::clients::Client;
-use crate::config::{Connectivity, NakamotoBlockGenerationConfig, TimeoutConfig};
-use crate::ledger::{NakamotoBlock, NakamotoGlobalLedger};
+use crate::config::{
+    BlockStoreConfig, Connectivity, ExecutionConfig, FeeMarketConfig, GenesisAccount, MinerPolicy,
+    MiningPoolConfig, NakamotoBlockGenerationConfig, OrderingPolicy, PeerScoringConfig,
+    ProtocolUpgrade, RequestRetryConfig, TimeoutConfig, TransactionModel,
+};
+use crate::fingerprint::FingerprintBuilder;
+use crate::latency::LatencyHistogram;
+use crate::ledger::{
+    DiskBlockStore, NakamotoBlock, NakamotoGlobalLedger, make_genesis_outputs, make_genesis_state,
+};
 use crate::link::Link;
 use crate::logic::{
-    Block, BlockId, GENESIS_BLOCK, GlobalLogic, HASH_SIZE, NUM_SIZE, NodeLogic, SIGNATURE_SIZE,
-    Transaction, TransactionId,
+    Block, BlockId, GENESIS_BLOCK, GlobalLogic, NodeLogic, Transaction, TransactionId,
 };
+use crate::memory::MemoryUsage;
 use crate::message::MessageType;
-use crate::metrics::ChainMetrics;
+use crate::metrics::{
+    ChainMetrics, ChainSplitStatistics, FeeMarketStatistics, MiningPoolStatistics,
+    PeerBanStatistics, PropagationStatistics,
+};
 use crate::node::NodeIndex;
 use crate::object::ObjectId;
+use crate::simulation::unwrap_or_report;
 
 mod node;
 pub use node::NakamotoNodeLogic;
@@ -24,6 +37,20 @@ pub use node::NakamotoNodeLogic;
 mod block_generator;
 use block_generator::{BlockGenerator, make_block_generator};
 
+mod pool;
+use pool::MiningPool;
+
+mod front_running;
+use front_running::FrontRunner;
+
+mod reputation;
+use reputation::PeerScoring;
+
+mod requests;
+
+mod upgrades;
+use upgrades::{BlockLimits, UpgradeSchedule};
+
 #[derive(Clone, Debug)]
 pub enum NakamotoMessage {
     NotifyNewBlock(BlockId),
@@ -41,7 +68,7 @@ impl NakamotoMessage {
             Self::NotifyNewTransaction(_) | Self::GetTransaction(_) => {
                 std::mem::size_of::<TransactionId>() as u64
             }
-            Self::SendTransaction(_) => 2 * HASH_SIZE + 5 * NUM_SIZE + SIGNATURE_SIZE,
+            Self::SendTransaction(txn) => txn.get_size(),
             Self::SendBlock(block) => block.get_size(),
         }
     }
@@ -57,59 +84,123 @@ impl NakamotoMessage {
 
 pub struct NakamotoGlobalLogic {
     global_ledger: RcCell<NakamotoGlobalLedger>,
-    max_block_size: u32,
+    upgrade_schedule: Rc<UpgradeSchedule>,
     commit_delay: u64,
     use_ghost: bool,
     num_block_generators: u32,
     block_generation_config: NakamotoBlockGenerationConfig,
+    /// Time between the creation of the genesis block and the start of the simulation
+    genesis_age: Duration,
+    mining_pools: Vec<Rc<MiningPool>>,
+    ordering_policy: OrderingPolicy,
+    front_runner: Option<Rc<FrontRunner>>,
+    miner_policies: Vec<MinerPolicy>,
+    request_retries: Option<RequestRetryConfig>,
+    /// Only set if nodes ban misbehaving peers
+    peer_scoring: Option<Rc<PeerScoring>>,
 }
 
 impl NakamotoGlobalLogic {
+    #[allow(clippy::too_many_arguments)]
     pub fn instantiate(
         block_generation_config: NakamotoBlockGenerationConfig,
         num_block_generators: u32,
         max_block_size: u32,
+        max_block_bytes: Option<u64>,
         commit_delay: u64,
         use_ghost: bool,
-    ) -> Rc<dyn GlobalLogic> {
-        let global_ledger = Rc::new(RefCell::new(NakamotoGlobalLedger::new(
-            num_block_generators,
-        )));
+        premined_blocks: u64,
+        genesis: &[GenesisAccount],
+        genesis_age: Duration,
+        mining_pools: &[MiningPoolConfig],
+        fee_market: Option<FeeMarketConfig>,
+        execution: Option<ExecutionConfig>,
+        ordering_policy: OrderingPolicy,
+        first_seen_sampling: u32,
+        upgrades: Vec<ProtocolUpgrade>,
+        miner_policies: Vec<MinerPolicy>,
+        request_retries: Option<RequestRetryConfig>,
+        peer_scoring: Option<PeerScoringConfig>,
+        block_store: Option<BlockStoreConfig>,
+        faulty_nodes: Vec<NodeIndex>,
+    ) -> anyhow::Result<Rc<dyn GlobalLogic>> {
+        let mut global_ledger =
+            NakamotoGlobalLedger::new(num_block_generators, make_genesis_state(genesis));
+
+        if let Some(config) = block_store {
+            let store = DiskBlockStore::new(&config)?;
+            global_ledger.set_block_store(Rc::new(store));
+        }
+
+        if let Some(fee_market) = fee_market {
+            global_ledger.set_fee_market(fee_market);
+        }
+
+        if let Some(execution) = execution {
+            if execution.model == TransactionModel::Utxo {
+                global_ledger.set_genesis_outputs(make_genesis_outputs(genesis));
+            }
+            global_ledger.set_execution(execution);
+        }
+
+        let initial_difficulty =
+            make_block_generator(num_block_generators, &block_generation_config, genesis_age)
+                .get_difficulty();
+        global_ledger.premine(premined_blocks, initial_difficulty)?;
+
+        // Premined blocks are never sent over the network, so only sample blocks mined afterwards
+        global_ledger.set_first_seen_sampling(first_seen_sampling);
+
+        let global_ledger = Rc::new(RefCell::new(global_ledger));
+        let mining_pools = mining_pools
+            .iter()
+            .map(|config| Rc::new(MiningPool::new(config)))
+            .collect();
+
+        let initial_limits = BlockLimits {
+            max_block_size,
+            max_block_bytes,
+        };
+        let upgrade_schedule = Rc::new(UpgradeSchedule::new(initial_limits, upgrades));
+
+        let front_runner = match &ordering_policy {
+            OrderingPolicy::FrontRunning { attackers, .. } => {
+                Some(Rc::new(FrontRunner::new(attackers)))
+            }
+            OrderingPolicy::Fifo | OrderingPolicy::FeePriority | OrderingPolicy::Random => None,
+        };
 
-        Rc::new(Self {
+        let peer_scoring =
+            peer_scoring.map(|config| Rc::new(PeerScoring::new(config, faulty_nodes)));
+
+        Ok(Rc::new(Self {
             block_generation_config,
+            genesis_age,
             global_ledger,
             num_block_generators,
-            max_block_size,
+            upgrade_schedule,
             commit_delay,
             use_ghost,
-        })
-    }
-}
-
-#[async_trait::async_trait(?Send)]
-impl GlobalLogic for NakamotoGlobalLogic {
-    fn new_node_logic(&self, _node_idx: NodeIndex) -> Rc<dyn NodeLogic> {
-        Rc::new(NakamotoNodeLogic::new(
-            &self.block_generation_config,
-            self.global_ledger.clone(),
-            self.max_block_size,
-            self.num_block_generators,
-            self.commit_delay,
-            self.use_ghost,
-        ))
+            mining_pools,
+            ordering_policy,
+            front_runner,
+            miner_policies,
+            request_retries,
+            peer_scoring,
+        }))
     }
 
-    fn get_metrics(
+    /// See `GlobalLogic::get_metrics`
+    fn compute_metrics(
         &self,
         timeout: TimeoutConfig,
         clients: &[Rc<Client>],
         links: &BTreeMap<ObjectId, Rc<Link>>,
-    ) -> ChainMetrics {
+    ) -> anyhow::Result<ChainMetrics> {
         let blockchain = self.global_ledger.borrow_mut();
         let (latest_block, _height) = blockchain.get_longest_chain();
 
-        let mut end_block = blockchain.get_block(&latest_block).expect("No blocks");
+        let mut end_block = blockchain.get_block(&latest_block)?.expect("No blocks");
         loop {
             match timeout {
                 TimeoutConfig::Seconds { runtime, warmup } => {
@@ -126,7 +217,7 @@ impl GlobalLogic for NakamotoGlobalLogic {
             }
 
             end_block = blockchain
-                .get_block(end_block.get_parent_id())
+                .get_block(end_block.get_parent_id())?
                 .expect("No parent block");
         }
 
@@ -171,7 +262,7 @@ impl GlobalLogic for NakamotoGlobalLogic {
                 break;
             } else {
                 next_block = blockchain
-                    .get_block(next_block.get_parent_id())
+                    .get_block(next_block.get_parent_id())?
                     .expect("No parent block");
             }
         }
@@ -179,19 +270,18 @@ impl GlobalLogic for NakamotoGlobalLogic {
         let start_time = next_block.get_creation_time();
         let elapsed = end_time - start_time;
 
-        let total_blocks_mined = blockchain.get_total_blocks_mined(start_time, end_time);
+        let total_blocks_mined = blockchain.get_total_blocks_mined(start_time, end_time)?;
 
-        let mut latencies = vec![];
+        let mut latencies = LatencyHistogram::default();
         for client in clients {
-            latencies.append(&mut client.get_latencies().clone());
+            latencies.merge(&client.get_latencies());
         }
 
         // num_transactions contains applied but uncommitted transactions as well
         // FIXME also contains transactions during warmup period
-        // assert_eq!(latencies.len(), num_transactions as usize);
+        // assert_eq!(latencies.len(), num_transactions);
 
-        let avg_latency =
-            latencies.iter().map(|t| t.as_millis_f64()).sum::<f64>() / (latencies.len() as f64);
+        let avg_latency = latencies.get_total() / (latencies.len() as f64);
 
         let avg_block_size = (total_size as f64) / elapsed.as_seconds_f64();
         let avg_block_interval = elapsed.as_seconds_f64() / (blocks_in_interval as f64);
@@ -201,7 +291,7 @@ impl GlobalLogic for NakamotoGlobalLogic {
             num_network_messages += link.num_total_messages();
         }
 
-        ChainMetrics {
+        Ok(ChainMetrics {
             total_blocks_mined,
             longest_chain_length,
             avg_block_interval,
@@ -213,7 +303,59 @@ impl GlobalLogic for NakamotoGlobalLogic {
                 / (total_propagated_blocks as f64),
             total_blocks_accepted: blocks_in_interval,
             elapsed,
-        }
+            avg_duplicate_messages: 0.0,
+            avg_validator_block_propagation: 0.0,
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl GlobalLogic for NakamotoGlobalLogic {
+    fn new_node_logic(&self, node_idx: NodeIndex) -> Rc<dyn NodeLogic> {
+        let mining_pool = self
+            .mining_pools
+            .iter()
+            .find(|pool| pool.is_member(node_idx))
+            .cloned();
+
+        let front_runner = self
+            .front_runner
+            .as_ref()
+            .filter(|front_runner| front_runner.is_attacker(node_idx))
+            .cloned();
+
+        let miner_policy = self
+            .miner_policies
+            .iter()
+            .find(|policy| policy.applies_to(node_idx))
+            .cloned()
+            .unwrap_or_default();
+
+        Rc::new(NakamotoNodeLogic::new(
+            node_idx,
+            mining_pool,
+            front_runner,
+            &self.block_generation_config,
+            self.genesis_age,
+            self.global_ledger.clone(),
+            self.upgrade_schedule.clone(),
+            self.num_block_generators,
+            self.commit_delay,
+            self.use_ghost,
+            self.ordering_policy.clone(),
+            miner_policy,
+            self.request_retries.clone(),
+            self.peer_scoring.clone(),
+        ))
+    }
+
+    fn get_metrics(
+        &self,
+        timeout: TimeoutConfig,
+        clients: &[Rc<Client>],
+        links: &BTreeMap<ObjectId, Rc<Link>>,
+    ) -> ChainMetrics {
+        unwrap_or_report(self.compute_metrics(timeout, clients, links))
     }
 
     fn is_compatible_with_connectivity(&self, _connectivity: &Connectivity) -> bool {
@@ -223,4 +365,63 @@ impl GlobalLogic for NakamotoGlobalLogic {
     async fn wait_for_blocks(&self, _blocks: u64) {
         unimplemented!();
     }
+
+    fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) {
+        unwrap_or_report(self.global_ledger.borrow().add_to_fingerprint(fingerprint));
+    }
+
+    fn get_front_run_victims(&self) -> u64 {
+        match &self.front_runner {
+            Some(front_runner) => {
+                unwrap_or_report(front_runner.count_victims(&self.global_ledger.borrow()))
+            }
+            None => 0,
+        }
+    }
+
+    fn get_fee_market_statistics(&self) -> Option<FeeMarketStatistics> {
+        unwrap_or_report(self.global_ledger.borrow().get_fee_market_statistics())
+    }
+
+    fn get_chain_split_statistics(&self) -> Option<ChainSplitStatistics> {
+        self.upgrade_schedule.get_chain_split_statistics()
+    }
+
+    fn get_peer_ban_statistics(&self) -> Option<PeerBanStatistics> {
+        self.peer_scoring
+            .as_ref()
+            .map(|peer_scoring| peer_scoring.get_statistics())
+    }
+
+    fn get_mining_pool_statistics(&self) -> Vec<MiningPoolStatistics> {
+        let blockchain = self.global_ledger.borrow();
+
+        let statistics = self
+            .mining_pools
+            .iter()
+            .map(|pool| pool.get_statistics(&blockchain, self.num_block_generators))
+            .collect();
+
+        unwrap_or_report(statistics)
+    }
+
+    fn get_block_graph(&self) -> BlockGraph {
+        unwrap_or_report(self.global_ledger.borrow().get_block_graph())
+    }
+
+    fn get_block_details(&self, block_id: &BlockId) -> Option<BlockDetails> {
+        unwrap_or_report(self.global_ledger.borrow().get_block_details(block_id))
+    }
+
+    fn get_state_size(&self) -> u64 {
+        unwrap_or_report(self.global_ledger.borrow().get_state_size())
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        self.global_ledger.borrow().get_memory_usage()
+    }
+
+    fn get_propagation_statistics(&self) -> PropagationStatistics {
+        unwrap_or_report(self.global_ledger.borrow().get_propagation_statistics())
+    }
 }
@@ -1,26 +1,41 @@
-use crate::config::NakamotoBlockGenerationConfig;
-use crate::ledger::{NakamotoBlock, NakamotoGlobalLedger, NakamotoNodeLedger};
+use crate::config::{
+    MinerPolicy, NakamotoBlockGenerationConfig, OrderingPolicy, RequestRetryConfig,
+    TransactionModel,
+};
+use crate::ledger::{
+    self, BlockFees, NakamotoBlock, NakamotoGlobalLedger, NakamotoNodeLedger, get_account_state,
+    get_utxo_balance,
+};
 use crate::logic::{
-    AccountId, Block, BlockId, GENESIS_BLOCK, NodeLogic, Transaction, TransactionId,
+    AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, NodeLogic, Transaction, TransactionId,
+    TransactionStatus,
 };
-use crate::node::Node;
-use crate::object::ObjectId;
+use crate::memory::{self, MemoryUsage};
+use crate::node::{Node, NodeIndex};
+use crate::object::{Object, ObjectId};
+use crate::simulation::{report_failure, unwrap_or_report};
 use crate::{Message, RcCell};
 
-use cow_tree::CowTree;
+use asim::time::{Duration, Time};
 
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use super::NakamotoMessage;
-use super::{BlockGenerator, make_block_generator};
+use super::reputation::{Offense, PeerScores, PeerScoring};
+use super::requests::{RequestTracker, RetryAction};
+use super::{
+    BlockGenerator, BlockLimits, FrontRunner, MiningPool, UpgradeSchedule, make_block_generator,
+};
 
 struct NodeState {
     local_ledger: NakamotoNodeLedger,
+    /// Needed to execute the transactions of blocks on top of their parent's state
+    global_ledger: RcCell<NakamotoGlobalLedger>,
 
-    requested_blocks: HashSet<BlockId>,
-    requested_transactions: HashSet<TransactionId>,
+    requested_blocks: RequestTracker<BlockId>,
+    requested_transactions: RequestTracker<TransactionId>,
 
     /// NakamotoBlocks for which we do not have a parent yet
     pending_blocks_ancestors: HashMap<BlockId, Vec<(ObjectId, Rc<NakamotoBlock>)>>,
@@ -28,17 +43,40 @@ struct NodeState {
     /// NakamotoBlocks for which we do not have all transactions yet
     pending_blocks_transactions: HashMap<TransactionId, Vec<(ObjectId, Rc<NakamotoBlock>)>>,
 
+    /// When blocks that have not been added to the ledger yet first arrived
+    block_arrivals: HashMap<BlockId, Time>,
+
     block_generator: Box<dyn BlockGenerator>,
+
+    /// The index of this node within the chain (decides which upgrades it adopted)
+    node_index: NodeIndex,
+    upgrade_schedule: Rc<UpgradeSchedule>,
+    /// Blocks that violate the rules of this node or build on such blocks
+    rejected_blocks: HashSet<BlockId>,
+    /// How long our own blocks are held back before they are announced
+    release_delay: Duration,
+    /// Unanswered requests are never retried if not set
+    request_retries: Option<RequestRetryConfig>,
+    /// Peers are never banned if not set
+    peer_scores: Option<PeerScores>,
 }
 
 pub struct NakamotoNodeLogic {
+    /// The index of this node within the chain
+    /// (this only differs from the node's global index in multi-chain simulations)
+    node_index: NodeIndex,
+    /// The pool this node mines for (if any)
+    mining_pool: Option<Rc<MiningPool>>,
+    /// Only set if this node front-runs victim transactions
+    front_runner: Option<Rc<FrontRunner>>,
     state: RefCell<NodeState>,
     global_ledger: RcCell<NakamotoGlobalLedger>,
 
     /// Parameters
-    max_block_size: u32,
     commit_delay: u64,
     use_ghost: bool,
+    ordering_policy: OrderingPolicy,
+    miner_policy: MinerPolicy,
 }
 
 impl NodeState {
@@ -48,21 +86,22 @@ impl NodeState {
         transaction: Rc<Transaction>,
         source: Option<ObjectId>,
         commit_delay: u64,
-    ) {
+    ) -> anyhow::Result<()> {
         let txn_id = *transaction.get_identifier();
 
         if !self.local_ledger.add_transaction(transaction) {
-            return;
+            return Ok(());
         }
 
         if let Some(mut blocks) = self.pending_blocks_transactions.remove(&txn_id) {
             for (id, block) in blocks.drain(..) {
-                self.add_new_block(node, block, Some(id), commit_delay);
+                self.add_new_block(node, block, Some(id), commit_delay)?;
             }
         }
 
         let message = NakamotoMessage::NotifyNewTransaction(txn_id);
         node.broadcast(message.into(), source);
+        Ok(())
     }
 
     fn add_new_block(
@@ -71,21 +110,39 @@ impl NodeState {
         block: Rc<NakamotoBlock>,
         received_from: Option<ObjectId>,
         commit_delay: u64,
-    ) {
+    ) -> anyhow::Result<()> {
         let mut missing_txn = None;
         let parent_id = *block.get_parent_id();
         let block_id = *block.get_identifier();
 
+        let rejected_ancestor = std::iter::once(&parent_id)
+            .chain(block.get_uncle_ids())
+            .any(|ancestor_id| self.rejected_blocks.contains(ancestor_id));
+
+        if rejected_ancestor || !self.upgrade_schedule.is_valid(self.node_index, &block) {
+            if let Some(source) = received_from {
+                self.penalize(node, source, Offense::InvalidBlock);
+            }
+            self.reject_block(node, block_id);
+            return Ok(());
+        }
+
+        let arrival = *self
+            .block_arrivals
+            .entry(block_id)
+            .or_insert_with(asim::time::now);
+
         // See if we are missing a transaction
         for txn_id in block.get_transactions() {
             if !self.local_ledger.knows_transaction(txn_id) {
                 missing_txn = Some(txn_id);
 
+                let source = received_from
+                    .expect("Got transaction from self, but do not know all transactions");
+
                 // Only request if we have not requested it yet
-                if self.requested_transactions.insert(*txn_id) {
+                if self.requested_transactions.insert(*txn_id, source) {
                     let message = NakamotoMessage::GetTransaction(*txn_id);
-                    let source = received_from
-                        .expect("Got transaction from self, but do not know all transactions");
                     node.send_to(&source, message);
                 }
             }
@@ -97,7 +154,7 @@ impl NodeState {
                 .entry(*missing_txn)
                 .or_default()
                 .push((idx, block));
-            return;
+            return Ok(());
         }
 
         // Don't add the block if we do not have the parent or uncle (yet)
@@ -122,31 +179,54 @@ impl NodeState {
                 .push((source, block));
 
             for ancestor_id in missing_ancestors {
-                if self.requested_blocks.insert(ancestor_id) {
+                if self.requested_blocks.insert(ancestor_id, source) {
                     let message = NakamotoMessage::GetBlock(ancestor_id);
                     node.send_to(&source, message);
                 }
             }
-            return;
+            return Ok(());
         }
 
-        let (is_new_block, new_head) = self.local_ledger.add_new_block(block, commit_delay);
+        // Blocks of other nodes must lead to the state they claim
+        if let Some(source) = received_from
+            && !self.has_valid_state(&block)?
+        {
+            log::debug!("Block #{block_id:#X} does not match the result of its transactions");
+            self.penalize(node, source, Offense::InvalidBlock);
+            self.reject_block(node, block_id);
+            return Ok(());
+        }
+
+        self.block_arrivals.remove(&block_id);
+        let (is_new_block, new_head) = self
+            .local_ledger
+            .add_new_block(block.clone(), commit_delay)?;
 
         // This might return false due to concurrency
         // (we received the same block multiple times at once)
         if !is_new_block {
-            return;
+            return Ok(());
         }
 
+        block.record_arrival(node.get_identifier(), received_from);
+        if received_from.is_some() {
+            node.get_statistics()
+                .record_block_delay(asim::time::now() - block.get_creation_time());
+        }
+        self.record_mempool_statistics(node, &block, arrival, received_from.is_some());
+
         log::trace!(
             "Node {} got a new block with index {:#X}",
             node.get_index(),
             block_id
         );
-        node.broadcast(
-            NakamotoMessage::NotifyNewBlock(block_id).into(),
-            received_from,
-        );
+        // Our own blocks might be announced later (see `NakamotoNodeLogic::run`)
+        if received_from.is_some() || self.release_delay.is_zero() {
+            node.broadcast(
+                NakamotoMessage::NotifyNewBlock(block_id).into(),
+                received_from,
+            );
+        }
 
         if let Some(new_head) = new_head {
             let parent_id = new_head.get_parent_id();
@@ -154,7 +234,7 @@ impl NodeState {
             if parent_id == &GENESIS_BLOCK {
                 self.block_generator.update_chain_head(&new_head, None);
             } else {
-                let parent = self.local_ledger.get_block(parent_id).unwrap();
+                let parent = self.local_ledger.get_block(parent_id)?.unwrap();
                 self.block_generator
                     .update_chain_head(&new_head, Some(&parent));
             }
@@ -162,7 +242,176 @@ impl NodeState {
 
         if let Some(mut blocks) = self.pending_blocks_ancestors.remove(&block_id) {
             for (idx, block) in blocks.drain(..) {
-                self.add_new_block(node, block, Some(idx), commit_delay);
+                self.add_new_block(node, block, Some(idx), commit_delay)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up transactions that are known to be in the local ledger
+    fn get_transactions(&self, txn_ids: &[TransactionId]) -> Vec<Rc<Transaction>> {
+        txn_ids
+            .iter()
+            .map(|txn_id| self.local_ledger.get_transaction(txn_id).unwrap())
+            .collect()
+    }
+
+    /// Executes the transactions of a block on top of its parent's state
+    /// Returns false if any of them cannot be applied or the block claims a different state.
+    fn has_valid_state(&self, block: &NakamotoBlock) -> anyhow::Result<bool> {
+        let blockchain = self.global_ledger.borrow();
+        let Some(execution) = blockchain.get_execution() else {
+            return Ok(true);
+        };
+
+        let parent_id = block.get_parent_id();
+        let (mut state, mut outputs) = blockchain.get_parent_state(parent_id)?;
+        let transactions = self.get_transactions(block.get_transactions());
+        let all_applied = ledger::execute_transactions(
+            execution,
+            &mut state,
+            &mut outputs,
+            &transactions,
+            &block.get_miner(),
+            blockchain.get_next_base_fee(parent_id)?,
+        )
+        .iter()
+        .all(Result::is_ok);
+
+        Ok(all_applied
+            && state.freeze().root_hash() == block.get_state().root_hash()
+            && outputs.freeze().root_hash() == block.get_outputs().root_hash())
+    }
+
+    /// Rejects the block and all pending blocks that build on it
+    fn reject_block(&mut self, node: &Node, block_id: BlockId) {
+        if !self.rejected_blocks.insert(block_id) {
+            return;
+        }
+
+        log::trace!("Node {} rejected block #{block_id:#X}", node.get_index());
+
+        self.block_arrivals.remove(&block_id);
+        self.upgrade_schedule.record_rejected_block();
+        node.get_statistics().record_rejected_block();
+
+        if let Some(blocks) = self.pending_blocks_ancestors.remove(&block_id) {
+            for (_, block) in blocks {
+                self.reject_block(node, *block.get_identifier());
+            }
+        }
+    }
+
+    fn penalize(&mut self, node: &Node, peer: ObjectId, offense: Offense) {
+        let Some(peer_scores) = &mut self.peer_scores else {
+            return;
+        };
+
+        if peer_scores.penalize(peer, offense) {
+            log::debug!("Node {} banned peer {peer:?} ({offense})", node.get_index());
+        }
+    }
+
+    fn is_banned(&self, peer: &ObjectId) -> bool {
+        self.peer_scores
+            .as_ref()
+            .is_some_and(|peer_scores| peer_scores.is_banned(peer))
+    }
+
+    /// Drops all pending blocks that build on the given block
+    /// Unlike rejected blocks, they will be requested again if a peer announces them
+    fn drop_pending_blocks(&mut self, block_id: BlockId) {
+        if let Some(blocks) = self.pending_blocks_ancestors.remove(&block_id) {
+            for (_, block) in blocks {
+                let block_id = *block.get_identifier();
+                self.block_arrivals.remove(&block_id);
+                self.drop_pending_blocks(block_id);
+            }
+        }
+    }
+
+    /// Asks other peers for blocks and transactions that were not received in time
+    /// Requests that failed too often are dropped, along with the blocks waiting for them
+    fn retry_requests(&mut self, node: &Node) {
+        let Some(config) = &self.request_retries else {
+            return;
+        };
+
+        let timeout = Duration::from_millis(config.timeout);
+        let max_attempts = config.max_attempts;
+        let peers: Vec<ObjectId> = node
+            .get_peers()
+            .into_iter()
+            .filter(|peer| !self.is_banned(peer))
+            .collect();
+
+        for action in self.requested_blocks.expire(timeout, max_attempts, &peers) {
+            node.get_statistics().record_stalled_request();
+
+            match action {
+                RetryAction::Resend(block_id, peer) => {
+                    node.send_to(&peer, NakamotoMessage::GetBlock(block_id));
+                }
+                RetryAction::Evict(block_id) => {
+                    log::debug!("Node {} gave up on block #{block_id:#X}", node.get_index());
+                    self.drop_pending_blocks(block_id);
+                }
+            }
+        }
+
+        for action in self
+            .requested_transactions
+            .expire(timeout, max_attempts, &peers)
+        {
+            node.get_statistics().record_stalled_request();
+
+            match action {
+                RetryAction::Resend(txn_id, peer) => {
+                    node.send_to(&peer, NakamotoMessage::GetTransaction(txn_id));
+                }
+                RetryAction::Evict(txn_id) => {
+                    log::debug!(
+                        "Node {} gave up on transaction #{txn_id:#X}",
+                        node.get_index()
+                    );
+
+                    if let Some(blocks) = self.pending_blocks_transactions.remove(&txn_id) {
+                        for (_, block) in blocks {
+                            let block_id = *block.get_identifier();
+                            self.block_arrivals.remove(&block_id);
+                            self.drop_pending_blocks(block_id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records how many of the block's transactions this node knew about already
+    /// and how long they waited to be included
+    fn record_mempool_statistics(
+        &self,
+        node: &Node,
+        block: &NakamotoBlock,
+        arrival: Time,
+        is_remote: bool,
+    ) {
+        let mut statistics = node.get_statistics();
+
+        for txn_id in block.get_transactions() {
+            let first_seen = self
+                .local_ledger
+                .get_first_seen_time(txn_id)
+                .expect("Block contains unknown transaction");
+
+            // Our own blocks only contain transactions we know of
+            if is_remote {
+                statistics.record_block_transaction(first_seen <= arrival);
+            }
+
+            if first_seen <= block.get_creation_time() {
+                statistics.record_mempool_age(block.get_creation_time() - first_seen);
             }
         }
     }
@@ -174,131 +423,311 @@ impl NodeState {
         source: ObjectId,
         message: Message,
         commit_delay: u64,
-    ) {
+    ) -> anyhow::Result<()> {
         let message: NakamotoMessage = message.try_into().expect("Invalid message type");
 
+        if self.is_banned(&source) {
+            return Ok(());
+        }
+
         match message {
             NakamotoMessage::NotifyNewBlock(identifier) => {
                 if !self.local_ledger.has_block(&identifier)
-                    && !self.requested_blocks.contains(&identifier)
+                    && !self.rejected_blocks.contains(&identifier)
+                    && self.requested_blocks.insert(identifier, source)
                 {
-                    self.requested_blocks.insert(identifier);
                     node.send_to(&source, NakamotoMessage::GetBlock(identifier));
                 }
             }
             NakamotoMessage::GetBlock(identifier) => {
-                let block = self
-                    .local_ledger
-                    .get_block(&identifier)
-                    .expect("No such block");
+                // Retried requests might go to peers that do not have the block
+                let Some(block) = self.local_ledger.get_block(&identifier)? else {
+                    log::debug!("Peer asked for unknown block #{identifier:#X}");
+                    return Ok(());
+                };
 
                 node.send_to(&source, NakamotoMessage::SendBlock(block));
             }
             NakamotoMessage::SendBlock(block) => {
                 if !self.requested_blocks.remove(block.get_identifier()) {
                     log::error!("Got block we did not ask for");
+                    self.penalize(node, source, Offense::Unrequested);
                 }
-                self.add_new_block(node, block, Some(source), commit_delay);
+                self.add_new_block(node, block, Some(source), commit_delay)?;
             }
             NakamotoMessage::GetTransaction(txn_id) => {
-                let txn = self
-                    .local_ledger
-                    .get_transaction(&txn_id)
-                    .expect("No such transaction");
+                let Some(txn) = self.local_ledger.get_transaction(&txn_id) else {
+                    log::debug!("Peer asked for unknown transaction #{txn_id:#X}");
+                    return Ok(());
+                };
 
                 let msg = NakamotoMessage::SendTransaction(txn);
                 node.send_to(&source, msg);
             }
             NakamotoMessage::NotifyNewTransaction(txn_id) => {
                 if !self.local_ledger.knows_transaction(&txn_id)
-                    && !self.requested_transactions.contains(&txn_id)
+                    && self.requested_transactions.insert(txn_id, source)
                 {
                     let msg = NakamotoMessage::GetTransaction(txn_id);
                     node.send_to(&source, msg);
-                    self.requested_transactions.insert(txn_id);
                 }
             }
             NakamotoMessage::SendTransaction(txn) => {
-                //TODO check nonce and discard old transactions
-
+                // Transactions with stale nonces are kept, as blocks on other forks might include them.
+                // generate_block() discards them once they cannot be applied on top of the chain head.
                 if !self.requested_transactions.remove(txn.get_identifier()) {
                     log::error!("Got transaction we did not ask for");
+                    self.penalize(node, source, Offense::Unrequested);
                 }
 
-                self.add_transaction(node, txn, Some(source), commit_delay);
+                self.add_transaction(node, txn, Some(source), commit_delay)?;
             }
         }
+
+        Ok(())
     }
 
-    #[tracing::instrument(skip(self, node, global_chain))]
+    /// Creates a new block on top of the current chain head and returns its identifier
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(
+        skip(self, node, front_runner, miner_policy),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     pub fn generate_block(
         &mut self,
         node: &Node,
-        global_chain: &RcCell<NakamotoGlobalLedger>,
-        max_block_size: u32,
+        mined_by: AccountId,
         commit_delay: u64,
         use_ghost: bool,
-    ) {
+        ordering_policy: &OrderingPolicy,
+        front_runner: Option<&FrontRunner>,
+        miner_policy: &MinerPolicy,
+    ) -> anyhow::Result<BlockId> {
+        let global_chain = self.global_ledger.clone();
         let (parent_id, height) = self.local_ledger.get_longest_chain();
         let difficulty = self.block_generator.get_difficulty();
-        let transactions = self
-            .local_ledger
-            .get_transactions_from_mempool(max_block_size);
+        let BlockLimits {
+            max_block_size,
+            max_block_bytes,
+        } = self
+            .upgrade_schedule
+            .get_block_limits(self.node_index, height + 1, asim::time::now());
+        let max_block_size = miner_policy.get_fill_limit(max_block_size as u64) as u32;
+        let max_block_bytes = max_block_bytes.map(|limit| miner_policy.get_fill_limit(limit));
+
+        let base_fee = global_chain.borrow().get_next_base_fee(&parent_id)?;
+        let mut transactions =
+            if self.local_ledger.get_mempool_size() < miner_policy.min_mempool_size {
+                log::trace!("Mempool is below threshold; mining an empty block");
+                vec![]
+            } else {
+                self.local_ledger.get_transactions_from_mempool(
+                    max_block_size,
+                    max_block_bytes,
+                    base_fee,
+                    ordering_policy,
+                )
+            };
+
+        // Pairs of attack and victim transactions
+        let mut front_runs = vec![];
+        if let Some(front_runner) = front_runner {
+            let selected = std::mem::take(&mut transactions);
+            let mut remaining_bytes = max_block_bytes.unwrap_or(u64::MAX);
+
+            // Attacks need to use the next nonces of our account to be valid
+            let mut nonce = {
+                let (state, _) = global_chain.borrow().get_parent_state(&parent_id)?;
+                ledger::get_nonce(&state.freeze(), &node.get_account_id())
+            };
+
+            for txn_id in selected {
+                let transaction = self.local_ledger.get_transaction(&txn_id).unwrap();
+                let attack = transaction.is_victim().then(|| {
+                    front_runner.make_transaction(node.get_account_id(), nonce + 1, &transaction)
+                });
+
+                let needed = if attack.is_some() { 2 } else { 1 };
+                let needed_bytes = transaction.get_size()
+                    + attack.as_ref().map(|attack| attack.get_size()).unwrap_or(0);
+
+                if transactions.len() + needed > max_block_size as usize
+                    || needed_bytes > remaining_bytes
+                {
+                    continue;
+                }
+                remaining_bytes -= needed_bytes;
+
+                if let Some(attack) = attack {
+                    let attack = Rc::new(attack);
+                    transactions.push(*attack.get_identifier());
+                    front_runs.push((*attack.get_identifier(), txn_id));
+                    self.add_transaction(node, attack, None, commit_delay)?;
+                    nonce += 1;
+                }
+
+                transactions.push(txn_id);
+            }
+        }
+
+        let (state, outputs) = {
+            let blockchain = global_chain.borrow();
+            let (mut state, mut outputs) = blockchain.get_parent_state(&parent_id)?;
+
+            // Leave out transactions that cannot be applied on top of the parent
+            if let Some(execution) = blockchain.get_execution() {
+                let candidates = self.get_transactions(&transactions);
+                let mut results = ledger::execute_transactions(
+                    execution,
+                    &mut state,
+                    &mut outputs,
+                    &candidates,
+                    &mined_by,
+                    base_fee,
+                )
+                .into_iter();
+                let mut discarded = vec![];
+
+                transactions.retain(|txn_id| match results.next().unwrap() {
+                    Ok(()) => true,
+                    Err(err) => {
+                        log::trace!("Skipping invalid transaction #{txn_id:#X}: {err}");
+                        if err.is_permanent() {
+                            discarded.push(*txn_id);
+                        }
+                        false
+                    }
+                });
+
+                for txn_id in discarded {
+                    self.local_ledger.discard_transaction(&txn_id);
+                }
+            }
+
+            (state.freeze(), outputs.freeze())
+        };
+
+        // Only count attacks that made it into the block together with their victim
+        let num_front_run = front_runs
+            .iter()
+            .filter(|(attack, victim)| {
+                transactions.contains(attack) && transactions.contains(victim)
+            })
+            .count() as u64;
+
+        let transaction_sizes = transactions
+            .iter()
+            .map(|txn_id| {
+                self.local_ledger
+                    .get_transaction(txn_id)
+                    .unwrap()
+                    .get_size()
+            })
+            .collect();
+
+        let fees = base_fee.map(|base_fee| {
+            let now = asim::time::now();
+            let inclusion_delays = transactions
+                .iter()
+                .map(|txn_id| {
+                    let transaction = self.local_ledger.get_transaction(txn_id).unwrap();
+                    now - transaction.get_creation_time()
+                })
+                .collect();
+
+            BlockFees::new(base_fee, inclusion_delays)
+        });
 
         let block = {
             let mut uncles = vec![];
             let mut blockchain = global_chain.borrow_mut();
 
-            let state = if parent_id == GENESIS_BLOCK {
-                CowTree::default().freeze()
-            } else {
-                //TODO actually modify state
-                let parent = blockchain.get_block(&parent_id).unwrap();
-
-                // Reference all blocks not referenced by the parent
-                if use_ghost {
-                    for (uncle_id, _) in self.local_ledger.get_forks().iter() {
-                        if *uncle_id != parent_id && !self.local_ledger.is_marked_as_uncle(uncle_id)
-                        {
-                            uncles.push(*uncle_id);
-                        }
+            // Reference all blocks not referenced by the parent
+            if use_ghost && parent_id != GENESIS_BLOCK {
+                for (uncle_id, _) in self.local_ledger.get_forks().iter() {
+                    if *uncle_id != parent_id && !self.local_ledger.is_marked_as_uncle(uncle_id) {
+                        uncles.push(*uncle_id);
                     }
                 }
-
-                parent.get_state().deep_clone().freeze()
-            };
+            }
 
             blockchain.generate_block(
-                node.get_account_id(),
+                mined_by,
                 parent_id,
                 uncles,
                 height + 1,
                 difficulty,
                 transactions,
+                transaction_sizes,
+                fees,
                 state,
-            )
+                outputs,
+            )?
         };
 
-        self.add_new_block(node, block, None, commit_delay);
+        if let Some(front_runner) = front_runner
+            && num_front_run > 0
+        {
+            front_runner.record_block(*block.get_identifier(), num_front_run);
+        }
+
+        let block_id = *block.get_identifier();
+        self.add_new_block(node, block, None, commit_delay)?;
+        Ok(block_id)
     }
 }
 
 impl NakamotoNodeLogic {
+    /// Announces one of our blocks after the given delay
+    /// In the meantime, we already mine on top of it
+    fn release_block(&self, node: &Rc<Node>, block_id: BlockId, delay: Duration) {
+        let node = node.clone();
+
+        asim::spawn(async move {
+            asim::time::sleep(delay).await;
+            log::trace!("Releasing block with index {block_id:#X}");
+            node.broadcast(NakamotoMessage::NotifyNewBlock(block_id).into(), None);
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
+        node_index: NodeIndex,
+        mining_pool: Option<Rc<MiningPool>>,
+        front_runner: Option<Rc<FrontRunner>>,
         block_generation_config: &NakamotoBlockGenerationConfig,
+        genesis_age: Duration,
         global_ledger: RcCell<NakamotoGlobalLedger>,
-        max_block_size: u32,
+        upgrade_schedule: Rc<UpgradeSchedule>,
         num_block_generators: u32,
         commit_delay: u64,
         use_ghost: bool,
+        ordering_policy: OrderingPolicy,
+        miner_policy: MinerPolicy,
+        request_retries: Option<RequestRetryConfig>,
+        peer_scoring: Option<Rc<PeerScoring>>,
     ) -> Self {
         let requested_blocks = Default::default();
         let requested_transactions = Default::default();
         let pending_blocks_ancestors = Default::default();
         let pending_blocks_transactions = Default::default();
+        let block_arrivals = Default::default();
 
-        let block_generator = make_block_generator(num_block_generators, block_generation_config);
-        let local_ledger = NakamotoNodeLedger::new();
+        let block_generator =
+            make_block_generator(num_block_generators, block_generation_config, genesis_age);
+        let mut local_ledger =
+            NakamotoNodeLedger::with_store(global_ledger.borrow().get_block_store());
+
+        // Import premined blocks (if any)
+        // This happens before init() sets up the callbacks, so they will not show up in the
+        // statistics. Because premined blocks have no meaningful creation time, we also do not
+        // pass them to the block generator; difficulty adjustment starts with the first real block.
+        for block in unwrap_or_report(global_ledger.borrow().get_longest_chain_blocks()) {
+            if let Err(err) = local_ledger.add_new_block(block, commit_delay) {
+                report_failure(err);
+                break;
+            }
+        }
 
         let state = NodeState {
             requested_blocks,
@@ -306,15 +735,27 @@ impl NakamotoNodeLogic {
             block_generator,
             pending_blocks_ancestors,
             pending_blocks_transactions,
+            block_arrivals,
             local_ledger,
+            global_ledger: global_ledger.clone(),
+            node_index,
+            upgrade_schedule,
+            rejected_blocks: Default::default(),
+            release_delay: Duration::from_millis(miner_policy.release_delay),
+            request_retries,
+            peer_scores: peer_scoring.map(PeerScores::new),
         };
 
         Self {
+            node_index,
+            mining_pool,
+            front_runner,
             commit_delay,
             state: RefCell::new(state),
             global_ledger,
-            max_block_size,
             use_ghost,
+            ordering_policy,
+            miner_policy,
         }
     }
 }
@@ -322,57 +763,266 @@ impl NakamotoNodeLogic {
 #[async_trait::async_trait(?Send)]
 impl NodeLogic for NakamotoNodeLogic {
     fn init(&self, node: Rc<Node>) {
+        if let Some(peer_scores) = &self.state.borrow().peer_scores {
+            peer_scores.register_node(node.get_identifier(), self.node_index);
+        }
+
         // Avoid cyclic dependencies between node and logic
         let node = Rc::downgrade(&node);
 
         let notify_commit_fn = {
+            let node = node.clone();
             Box::new(move |source: &AccountId, _txn_id: &TransactionId| {
                 let node = node.upgrade().unwrap();
-                if let Some(client) = node.get_client(source) {
+                node.get_statistics().record_transaction_commit();
+
+                // Clients with a confirmation depth are notified separately
+                if let Some(client) = node.get_client(source)
+                    && client.get_confirmations().is_none()
+                {
                     client.notify_transaction_commit();
                 }
             })
         };
 
+        let notify_confirmation_fn = {
+            let node = node.clone();
+            Box::new(
+                move |source: &AccountId, txn_id: &TransactionId, depth: u64| {
+                    let node = node.upgrade().unwrap();
+                    if let Some(client) = node.get_client(source) {
+                        client.notify_transaction_confirmation(txn_id, depth);
+                    }
+                },
+            )
+        };
+
+        let notify_reorg_fn = {
+            let node = node.clone();
+            Box::new(move |depth: u64| {
+                let node = node.upgrade().unwrap();
+                node.get_statistics().record_reorg(depth);
+            })
+        };
+
+        let notify_block_commit_fn = Box::new(move |delay: Duration| {
+            let node = node.upgrade().unwrap();
+            node.get_statistics().record_block_commit(delay);
+        });
+
         let mut state = self.state.borrow_mut();
         state
             .local_ledger
             .set_notify_transaction_commit_fn(notify_commit_fn);
+        state
+            .local_ledger
+            .set_notify_transaction_confirmation_fn(notify_confirmation_fn);
+        state.local_ledger.set_notify_reorg_fn(notify_reorg_fn);
+        state
+            .local_ledger
+            .set_notify_block_commit_fn(notify_block_commit_fn);
     }
 
-    #[tracing::instrument(skip(self, node))]
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
     async fn run(&self, node: Rc<Node>, is_mining: bool) {
+        let retry_interval = self
+            .state
+            .borrow()
+            .request_retries
+            .as_ref()
+            .map(|config| Duration::from_millis(config.timeout));
+
         if !is_mining {
+            // Non-mining nodes only need to check for unanswered requests
+            if let Some(retry_interval) = retry_interval {
+                loop {
+                    asim::time::sleep(retry_interval).await;
+                    self.state.borrow_mut().retry_requests(&node);
+                }
+            }
             return;
         }
 
         let block_generation_resolution = { self.state.borrow().block_generator.get_resolution() };
+        let mut last_retry = asim::time::now();
 
         loop {
             {
                 let mut state = self.state.borrow_mut();
-                if state.block_generator.should_create_block(node.get_index()) {
-                    state.generate_block(
-                        &node,
-                        &self.global_ledger,
-                        self.max_block_size,
-                        self.commit_delay,
-                        self.use_ghost,
-                    );
+
+                if let Some(retry_interval) = retry_interval
+                    && asim::time::now() - last_retry >= retry_interval
+                {
+                    state.retry_requests(&node);
+                    last_retry = asim::time::now();
+                }
+
+                if state.block_generator.should_create_block(self.node_index) {
+                    match &self.mining_pool {
+                        Some(pool) if pool.is_withholding(self.node_index) => {
+                            log::trace!("Withholding block from pool");
+                            pool.record_withheld_block();
+                        }
+                        mining_pool => {
+                            let mined_by = match mining_pool {
+                                Some(pool) => pool.get_account(),
+                                None => node.get_account_id(),
+                            };
+
+                            let result = state.generate_block(
+                                &node,
+                                mined_by,
+                                self.commit_delay,
+                                self.use_ghost,
+                                &self.ordering_policy,
+                                self.front_runner.as_deref(),
+                                &self.miner_policy,
+                            );
+
+                            let block_id = match result {
+                                Ok(block_id) => block_id,
+                                Err(err) => {
+                                    report_failure(err);
+                                    return;
+                                }
+                            };
+
+                            if !state.release_delay.is_zero() {
+                                self.release_block(&node, block_id, state.release_delay);
+                            }
+                        }
+                    }
                 }
             }
             asim::time::sleep(block_generation_resolution).await;
         }
     }
 
-    fn add_transaction(&self, node: &Node, transaction: Rc<Transaction>, source: Option<ObjectId>) {
+    fn add_transaction(
+        &self,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) -> bool {
         let mut state = self.state.borrow_mut();
-        state.add_transaction(node, transaction, source, self.commit_delay);
+
+        match state.add_transaction(node, transaction, source, self.commit_delay) {
+            Ok(()) => true,
+            Err(err) => {
+                report_failure(err);
+                false
+            }
+        }
     }
 
-    #[tracing::instrument(skip(self, node, message))]
+    fn get_transaction_status(&self, txn_id: &TransactionId) -> TransactionStatus {
+        let state = self.state.borrow();
+        let status = state
+            .local_ledger
+            .get_transaction_status(txn_id, self.commit_delay);
+
+        status.unwrap_or_else(|err| {
+            report_failure(err);
+            TransactionStatus::Unknown
+        })
+    }
+
+    fn get_mempool(&self) -> Vec<TransactionId> {
+        self.state.borrow().local_ledger.get_mempool()
+    }
+
+    fn add_confirmation_depth(&self, depth: u64) {
+        self.state
+            .borrow_mut()
+            .local_ledger
+            .add_confirmation_depth(depth);
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let state = self.state.borrow();
+
+        let mut usage = MemoryUsage::default();
+        state.local_ledger.add_memory_usage(&mut usage);
+        usage.add(
+            "pending_blocks",
+            memory::nested_map_size(&state.pending_blocks_ancestors)
+                + memory::nested_map_size(&state.pending_blocks_transactions)
+                + memory::map_size(&state.block_arrivals),
+        );
+        usage.add(
+            "requests",
+            state.requested_blocks.get_memory_usage()
+                + state.requested_transactions.get_memory_usage(),
+        );
+        usage.add("rejected_blocks", memory::set_size(&state.rejected_blocks));
+        usage.add(
+            "peer_scores",
+            state
+                .peer_scores
+                .as_ref()
+                .map_or(0, |peer_scores| peer_scores.get_memory_usage()),
+        );
+        usage
+    }
+
+    fn get_chain_head(&self) -> Option<(BlockId, u64)> {
+        Some(self.state.borrow().local_ledger.get_longest_chain())
+    }
+
+    fn get_block_size(&self, block_id: &BlockId) -> Option<u64> {
+        let state = self.state.borrow();
+        let block = unwrap_or_report(state.local_ledger.get_block(block_id))?;
+        Some(block.get_total_size())
+    }
+
+    fn get_num_forks(&self) -> usize {
+        self.state.borrow().local_ledger.num_forks()
+    }
+
+    fn get_account_state(&self, account: &AccountId) -> Option<AccountState> {
+        let state = self.state.borrow();
+        let global_ledger = self.global_ledger.borrow();
+        let (head_id, _) = state.local_ledger.get_longest_chain();
+        let head = if head_id == GENESIS_BLOCK {
+            None
+        } else {
+            match state.local_ledger.get_block(&head_id) {
+                Ok(head) => Some(head.expect("No such block")),
+                Err(err) => {
+                    report_failure(err);
+                    return None;
+                }
+            }
+        };
+
+        // With the UTXO model, the balance is the sum of the account's unspent outputs
+        let is_utxo = global_ledger
+            .get_execution()
+            .is_some_and(|execution| execution.model == TransactionModel::Utxo);
+        if is_utxo {
+            let outputs = match &head {
+                Some(head) => head.get_outputs(),
+                None => global_ledger.get_genesis_outputs(),
+            };
+            return get_utxo_balance(outputs, account).map(AccountState::new);
+        }
+
+        let account_states = match &head {
+            Some(head) => head.get_state(),
+            None => global_ledger.get_genesis_state(),
+        };
+        get_account_state(account_states, account)
+    }
+
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
         let mut state = self.state.borrow_mut();
-        state.handle_message(node, source, message, self.commit_delay);
+
+        if let Err(err) = state.handle_message(node, source, message, self.commit_delay) {
+            report_failure(err);
+        }
     }
 }
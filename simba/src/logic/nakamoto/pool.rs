@@ -0,0 +1,130 @@
+use std::cell::Cell;
+
+use crate::config::MiningPoolConfig;
+use crate::ledger::NakamotoGlobalLedger;
+use crate::logic::{AccountId, Block, GENESIS_BLOCK};
+use crate::metrics::MiningPoolStatistics;
+use crate::node::NodeIndex;
+use crate::random;
+
+/// A group of miners that shares its block rewards
+///
+/// Blocks found by members are credited to the pool's account.
+/// Withholding members never publish the blocks they find, but
+/// still (implicitly) receive a share of the pool's revenue.
+pub(super) struct MiningPool {
+    account: AccountId,
+    members: Vec<NodeIndex>,
+    withholding_members: Vec<NodeIndex>,
+    withheld_blocks: Cell<u64>,
+}
+
+impl MiningPool {
+    /// Withholding members must also be members of the pool (see `find_protocol_problems`)
+    pub fn new(config: &MiningPoolConfig) -> Self {
+        Self {
+            account: random::random(),
+            members: config.members.clone(),
+            withholding_members: config.withholding_members.clone(),
+            withheld_blocks: Cell::new(0),
+        }
+    }
+
+    pub fn get_account(&self) -> AccountId {
+        self.account
+    }
+
+    pub fn is_member(&self, node_idx: NodeIndex) -> bool {
+        self.members.contains(&node_idx)
+    }
+
+    pub fn is_withholding(&self, node_idx: NodeIndex) -> bool {
+        self.withholding_members.contains(&node_idx)
+    }
+
+    pub fn record_withheld_block(&self) {
+        self.withheld_blocks.set(self.withheld_blocks.get() + 1);
+    }
+
+    /// Compares the pool's share of the longest chain with its share of the mining power
+    /// (assuming all block generators have the same mining power)
+    pub fn get_statistics(
+        &self,
+        blockchain: &NakamotoGlobalLedger,
+        num_block_generators: u32,
+    ) -> anyhow::Result<MiningPoolStatistics> {
+        let (mut block_id, chain_length) = blockchain.get_longest_chain();
+        let mut blocks_in_chain = 0;
+
+        while block_id != GENESIS_BLOCK {
+            let block = blockchain.get_block(&block_id)?.expect("Missing block");
+            if block.get_miner() == self.account {
+                blocks_in_chain += 1;
+            }
+            block_id = *block.get_parent_id();
+        }
+
+        let share = (self.members.len() as f64) / (num_block_generators as f64);
+
+        Ok(MiningPoolStatistics {
+            num_members: self.members.len() as u32,
+            num_withholding_members: self.withholding_members.len() as u32,
+            blocks_in_chain,
+            withheld_blocks: self.withheld_blocks.get(),
+            expected_blocks: share * (chain_length as f64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cow_tree::CowTree;
+
+    use super::MiningPool;
+    use crate::config::{Difficulty, MiningPoolConfig};
+    use crate::ledger::NakamotoGlobalLedger;
+    use crate::logic::{Block, GENESIS_BLOCK};
+
+    #[asim::test]
+    async fn revenue_loss() {
+        let pool = MiningPool::new(&MiningPoolConfig {
+            members: vec![0, 1],
+            withholding_members: vec![1],
+        });
+
+        assert!(pool.is_member(1));
+        assert!(!pool.is_member(2));
+        assert!(pool.is_withholding(1));
+        assert!(!pool.is_withholding(0));
+
+        // The pool has half of the mining power, but only mines one of four blocks
+        let mut ledger = NakamotoGlobalLedger::new(4, CowTree::default().freeze());
+        let mut parent = GENESIS_BLOCK;
+        for height in 1..=4 {
+            let miner = if height == 1 { pool.get_account() } else { 0 };
+            let block = ledger
+                .generate_block(
+                    miner,
+                    parent,
+                    vec![],
+                    height,
+                    Difficulty::default(),
+                    vec![],
+                    vec![],
+                    None,
+                    CowTree::default().freeze(),
+                    CowTree::default().freeze(),
+                )
+                .unwrap();
+            parent = *block.get_identifier();
+        }
+
+        pool.record_withheld_block();
+
+        let stats = pool.get_statistics(&ledger, 4).unwrap();
+        assert_eq!(stats.blocks_in_chain, 1);
+        assert_eq!(stats.withheld_blocks, 1);
+        assert_eq!(stats.expected_blocks, 2.0);
+        assert_eq!(stats.get_revenue_loss(), 0.5);
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use asim::time::Time;
+
+use crate::config::PeerScoringConfig;
+use crate::memory;
+use crate::metrics::PeerBanStatistics;
+use crate::node::NodeIndex;
+use crate::object::ObjectId;
+
+/// Keeps track of the bans of all nodes
+///
+/// Whether a ban was justified is decided using the failure configuration,
+/// which the nodes themselves do not know about.
+pub(super) struct PeerScoring {
+    config: PeerScoringConfig,
+    faulty_nodes: Vec<NodeIndex>,
+    /// Maps the identifiers of nodes to their index (filled in as nodes are initialized)
+    node_indices: RefCell<HashMap<ObjectId, NodeIndex>>,
+    num_penalties: Cell<u64>,
+    num_bans: Cell<u64>,
+    false_positive_bans: Cell<u64>,
+    first_ban: Cell<Option<Time>>,
+}
+
+impl PeerScoring {
+    pub fn new(config: PeerScoringConfig, faulty_nodes: Vec<NodeIndex>) -> Self {
+        Self {
+            config,
+            faulty_nodes,
+            node_indices: Default::default(),
+            num_penalties: Cell::new(0),
+            num_bans: Cell::new(0),
+            false_positive_bans: Cell::new(0),
+            first_ban: Cell::new(None),
+        }
+    }
+
+    pub fn register_node(&self, identifier: ObjectId, index: NodeIndex) {
+        self.node_indices.borrow_mut().insert(identifier, index);
+    }
+
+    fn record_ban(&self, peer: &ObjectId) {
+        self.num_bans.set(self.num_bans.get() + 1);
+        if self.first_ban.get().is_none() {
+            self.first_ban.set(Some(asim::time::now()));
+        }
+
+        let is_faulty = self
+            .node_indices
+            .borrow()
+            .get(peer)
+            .is_some_and(|index| self.faulty_nodes.contains(index));
+
+        if !is_faulty {
+            self.false_positive_bans
+                .set(self.false_positive_bans.get() + 1);
+        }
+    }
+
+    pub fn get_statistics(&self) -> PeerBanStatistics {
+        PeerBanStatistics {
+            num_penalties: self.num_penalties.get(),
+            num_bans: self.num_bans.get(),
+            false_positive_bans: self.false_positive_bans.get(),
+            first_ban: self.first_ban.get(),
+        }
+    }
+}
+
+/// Why a node penalizes one of its peers
+#[derive(Clone, Copy, Debug, derive_more::Display)]
+pub(super) enum Offense {
+    /// Sent a block or transaction the node did not ask for
+    Unrequested,
+    /// Sent a block that violates the node's rules (or builds on such a block)
+    InvalidBlock,
+}
+
+/// The scores a single node gives to its peers
+pub(super) struct PeerScores {
+    scoring: Rc<PeerScoring>,
+    scores: HashMap<ObjectId, u32>,
+    banned_peers: HashSet<ObjectId>,
+}
+
+impl PeerScores {
+    pub fn new(scoring: Rc<PeerScoring>) -> Self {
+        Self {
+            scoring,
+            scores: Default::default(),
+            banned_peers: Default::default(),
+        }
+    }
+
+    /// Lets the global statistics know who the node is, so that its bans can be classified
+    pub fn register_node(&self, identifier: ObjectId, index: NodeIndex) {
+        self.scoring.register_node(identifier, index);
+    }
+
+    pub fn is_banned(&self, peer: &ObjectId) -> bool {
+        self.banned_peers.contains(peer)
+    }
+
+    /// Returns true if this offense got the peer banned
+    pub fn penalize(&mut self, peer: ObjectId, offense: Offense) -> bool {
+        let config = &self.scoring.config;
+        let penalty = match offense {
+            Offense::Unrequested => config.unrequested_penalty,
+            Offense::InvalidBlock => config.invalid_block_penalty,
+        };
+
+        self.scoring
+            .num_penalties
+            .set(self.scoring.num_penalties.get() + 1);
+
+        let score = self.scores.entry(peer).or_default();
+        *score = score.saturating_add(penalty);
+
+        if *score < config.ban_threshold || !self.banned_peers.insert(peer) {
+            return false;
+        }
+
+        self.scoring.record_ban(&peer);
+        true
+    }
+
+    /// Estimated memory usage in bytes
+    pub fn get_memory_usage(&self) -> u64 {
+        memory::map_size(&self.scores) + memory::set_size(&self.banned_peers)
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use asim::time::{Duration, Time};
+
+use rand::seq::IteratorRandom;
+
+use crate::memory;
+use crate::object::ObjectId;
+use crate::random;
+
+/// A block or transaction this node asked its peers for
+struct PendingRequest {
+    /// When the most recent attempt was sent
+    sent_at: Time,
+    /// All peers asked so far (the most recent one last)
+    asked: Vec<ObjectId>,
+    /// Peers that announced the object, but were not asked yet
+    candidates: Vec<ObjectId>,
+}
+
+/// What to do about a request that was not answered in time
+pub(super) enum RetryAction<K> {
+    /// Ask another peer (or the same one again if there is no other)
+    Resend(K, ObjectId),
+    /// Give up on the request
+    Evict(K),
+}
+
+/// Keeps track of outstanding requests, so that they can be retried or dropped
+/// if peers do not answer
+pub(super) struct RequestTracker<K> {
+    requests: HashMap<K, PendingRequest>,
+}
+
+impl<K> Default for RequestTracker<K> {
+    fn default() -> Self {
+        Self {
+            requests: Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Ord + Copy> RequestTracker<K> {
+    /// Records that `peer` is asked for the object
+    /// Returns false if there already is a request for it; `peer` is then asked if that request fails
+    pub fn insert(&mut self, key: K, peer: ObjectId) -> bool {
+        if let Some(request) = self.requests.get_mut(&key) {
+            request.add_candidate(peer);
+            return false;
+        }
+
+        let request = PendingRequest {
+            sent_at: asim::time::now(),
+            asked: vec![peer],
+            candidates: vec![],
+        };
+        self.requests.insert(key, request);
+        true
+    }
+
+    /// Returns false if the object was not requested (or the request was evicted already)
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.requests.remove(key).is_some()
+    }
+
+    /// Find all requests sent more than `timeout` ago
+    ///
+    /// Requests that were sent `max_attempts` times are evicted.
+    /// All others are sent again, preferably to a peer that announced the object,
+    /// otherwise to a random peer that was not asked yet.
+    pub fn expire(
+        &mut self,
+        timeout: Duration,
+        max_attempts: u32,
+        peers: &[ObjectId],
+    ) -> Vec<RetryAction<K>> {
+        let now = asim::time::now();
+
+        // Sort to keep simulations deterministic
+        let mut expired: Vec<K> = self
+            .requests
+            .iter()
+            .filter(|(_, request)| now - request.sent_at >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        expired.sort();
+
+        let mut actions = vec![];
+
+        for key in expired {
+            let request = self.requests.get_mut(&key).unwrap();
+
+            if request.asked.len() >= max_attempts as usize {
+                self.requests.remove(&key);
+                actions.push(RetryAction::Evict(key));
+                continue;
+            }
+
+            let peer = request.next_peer(peers);
+            request.sent_at = now;
+            request.asked.push(peer);
+            actions.push(RetryAction::Resend(key, peer));
+        }
+
+        actions
+    }
+
+    /// Estimated memory usage in bytes
+    pub fn get_memory_usage(&self) -> u64 {
+        let peers = self
+            .requests
+            .values()
+            .map(|request| memory::vec_size(&request.asked) + memory::vec_size(&request.candidates))
+            .sum::<u64>();
+
+        memory::map_size(&self.requests) + peers
+    }
+}
+
+impl PendingRequest {
+    fn add_candidate(&mut self, peer: ObjectId) {
+        if !self.asked.contains(&peer) && !self.candidates.contains(&peer) {
+            self.candidates.push(peer);
+        }
+    }
+
+    fn next_peer(&mut self, peers: &[ObjectId]) -> ObjectId {
+        if !self.candidates.is_empty() {
+            return self.candidates.remove(0);
+        }
+
+        peers
+            .iter()
+            .filter(|peer| !self.asked.contains(peer))
+            .choose(&mut random::rng())
+            .copied()
+            .unwrap_or_else(|| *self.asked.last().unwrap())
+    }
+}
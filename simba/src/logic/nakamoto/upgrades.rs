@@ -0,0 +1,178 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+
+use asim::time::Time;
+
+use crate::config::{ProtocolUpgrade, UpgradeActivation};
+use crate::ledger::NakamotoBlock;
+use crate::logic::Block;
+use crate::metrics::ChainSplitStatistics;
+use crate::node::NodeIndex;
+
+/// How many transactions a block may contain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct BlockLimits {
+    pub max_block_size: u32,
+    pub max_block_bytes: Option<u64>,
+}
+
+/// Decides which parameters apply to a block, so that all nodes
+/// switch to the new rules at the same height (or time)
+///
+/// Legacy nodes skip the upgrades they did not adopt, which may split the chain.
+pub(super) struct UpgradeSchedule {
+    initial_limits: BlockLimits,
+    upgrades: Vec<ProtocolUpgrade>,
+    /// The most upgrades that were active for any block so far (only used for logging)
+    num_activated: Cell<usize>,
+    rejected_blocks: Cell<u64>,
+    first_rejection: Cell<Option<Time>>,
+    last_rejection: Cell<Option<Time>>,
+}
+
+impl UpgradeSchedule {
+    pub fn new(initial_limits: BlockLimits, upgrades: Vec<ProtocolUpgrade>) -> Self {
+        Self {
+            initial_limits,
+            upgrades,
+            num_activated: Cell::new(0),
+            rejected_blocks: Cell::new(0),
+            first_rejection: Cell::new(None),
+            last_rejection: Cell::new(None),
+        }
+    }
+
+    /// The limits a node applies to a block with the given height that is created at the given time
+    pub fn get_block_limits(&self, node_index: NodeIndex, height: u64, time: Time) -> BlockLimits {
+        let mut limits = self.initial_limits;
+        let mut num_active = 0;
+
+        for upgrade in self.upgrades.iter() {
+            if upgrade.legacy_nodes.contains(&node_index) {
+                continue;
+            }
+
+            let active = match upgrade.activation {
+                UpgradeActivation::Height(activation_height) => height >= activation_height,
+                UpgradeActivation::Time(seconds) => time >= Time::from_seconds(seconds),
+            };
+
+            if !active {
+                continue;
+            }
+
+            num_active += 1;
+
+            if let Some(max_block_size) = upgrade.max_block_size {
+                limits.max_block_size = max_block_size;
+            }
+            if let Some(max_block_bytes) = upgrade.max_block_bytes {
+                limits.max_block_bytes = Some(max_block_bytes);
+            }
+        }
+
+        if num_active > self.num_activated.get() {
+            log::info!(
+                "Protocol upgrade activated at height {height}; block limits are now {limits:?}"
+            );
+            self.num_activated.set(num_active);
+        }
+
+        limits
+    }
+
+    /// Does the block follow the rules of the given node?
+    pub fn is_valid(&self, node_index: NodeIndex, block: &NakamotoBlock) -> bool {
+        let limits =
+            self.get_block_limits(node_index, block.get_height(), block.get_creation_time());
+        let num_bytes: u64 = block.get_transaction_sizes().iter().sum();
+
+        block.num_transactions() <= limits.max_block_size as usize
+            && num_bytes <= limits.max_block_bytes.unwrap_or(u64::MAX)
+    }
+
+    /// Record that a node rejected a block (or one of its descendants)
+    pub fn record_rejected_block(&self) {
+        let now = asim::time::now();
+
+        self.rejected_blocks.set(self.rejected_blocks.get() + 1);
+        if self.first_rejection.get().is_none() {
+            self.first_rejection.set(Some(now));
+        }
+        self.last_rejection.set(Some(now));
+    }
+
+    /// Returns `None` if all nodes adopt all upgrades
+    pub fn get_chain_split_statistics(&self) -> Option<ChainSplitStatistics> {
+        let legacy_nodes: HashSet<NodeIndex> = self
+            .upgrades
+            .iter()
+            .flat_map(|upgrade| upgrade.legacy_nodes.iter().copied())
+            .collect();
+
+        if legacy_nodes.is_empty() {
+            return None;
+        }
+
+        Some(ChainSplitStatistics {
+            num_legacy_nodes: legacy_nodes.len() as u32,
+            rejected_blocks: self.rejected_blocks.get(),
+            first_rejection: self.first_rejection.get(),
+            last_rejection: self.last_rejection.get(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asim::time::Time;
+
+    use super::{BlockLimits, UpgradeSchedule};
+    use crate::config::{ProtocolUpgrade, UpgradeActivation};
+
+    #[test]
+    fn activation() {
+        let initial_limits = BlockLimits {
+            max_block_size: 100,
+            max_block_bytes: None,
+        };
+
+        let schedule = UpgradeSchedule::new(initial_limits, vec![
+            ProtocolUpgrade {
+                activation: UpgradeActivation::Height(5000),
+                max_block_size: Some(200),
+                max_block_bytes: None,
+                legacy_nodes: vec![1],
+            },
+            ProtocolUpgrade {
+                activation: UpgradeActivation::Time(60),
+                max_block_size: None,
+                max_block_bytes: Some(1_000_000),
+                legacy_nodes: vec![],
+            },
+        ]);
+
+        let before = Time::from_seconds(10);
+        let after = Time::from_seconds(60);
+
+        assert_eq!(schedule.get_block_limits(0, 4999, before), initial_limits);
+        assert_eq!(schedule.get_block_limits(0, 5000, before), BlockLimits {
+            max_block_size: 200,
+            max_block_bytes: None,
+        });
+        assert_eq!(schedule.get_block_limits(0, 4999, after), BlockLimits {
+            max_block_size: 100,
+            max_block_bytes: Some(1_000_000),
+        });
+
+        // Blocks of forks below the activation height still use the old rules
+        assert_eq!(schedule.get_block_limits(0, 10, before), initial_limits);
+
+        // Legacy nodes never apply the first upgrade
+        assert_eq!(schedule.get_block_limits(1, 5000, before), initial_limits);
+
+        let statistics = schedule.get_chain_split_statistics().unwrap();
+        assert_eq!(statistics.num_legacy_nodes, 1);
+        assert_eq!(statistics.rejected_blocks, 0);
+    }
+}
@@ -1,37 +1,70 @@
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::RcCell;
 use crate::clients::Client;
-use crate::config::{Connectivity, TimeoutConfig};
-use crate::ledger::{ConventionalBlock, ConventionalGlobalLedger, SlotNumber};
+use crate::config::{Connectivity, GenesisAccount, TimeoutConfig};
+use crate::failures::Failures;
+use crate::fingerprint::FingerprintBuilder;
+use crate::ledger::{ConventionalBlock, ConventionalGlobalLedger, SlotNumber, make_genesis_state};
 use crate::link::Link;
-use crate::logic::{Block, GENESIS_BLOCK, GlobalLogic, NodeLogic, SIGNATURE_SIZE, Transaction};
+use crate::logic::{GlobalLogic, NodeLogic, SIGNATURE_SIZE, Transaction};
+use crate::memory::{self, MemoryUsage};
 use crate::message::MessageType;
 use crate::metrics::ChainMetrics;
 use crate::node::NodeIndex;
 use crate::object::ObjectId;
 
-use asim::time::{Duration, Time};
+use asim::time::Duration;
+
+use super::conventional::{FinalizationTracker, get_metrics};
 
 mod node;
 pub use node::PbftNodeLogic;
 
+/// Views start at zero and increase every time the replicas replace the leader
+pub type ViewNumber = u64;
+
+/// A block that a quorum prepared in the given view
+type PreparedBlock = (ViewNumber, Rc<ConventionalBlock>);
+
 #[derive(Clone, Debug)]
 pub enum PbftMessage {
     SendTransaction(Rc<Transaction>),
-    PrePrepare { block: Rc<ConventionalBlock> },
-    Prepare { slot: SlotNumber },
-    Commit { slot: SlotNumber },
+    PrePrepare {
+        block: Rc<ConventionalBlock>,
+        view: ViewNumber,
+    },
+    Prepare {
+        slot: SlotNumber,
+        view: ViewNumber,
+    },
+    Commit {
+        slot: SlotNumber,
+        view: ViewNumber,
+    },
+    /// Asks for a new leader, because the slot did not make progress in time
+    /// Contains the block the sender prepared for this slot (if any),
+    /// so that the new leader proposes it again
+    ViewChange {
+        slot: SlotNumber,
+        view: ViewNumber,
+        prepared: Option<PreparedBlock>,
+    },
 }
 
 impl PbftMessage {
     pub fn get_size(&self) -> u64 {
+        let header_size = (size_of::<SlotNumber>() + size_of::<ViewNumber>()) as u64;
+
         let body_size = match self {
             Self::SendTransaction(_) => 0,
-            Self::PrePrepare { block } => block.get_size(),
-            Self::Prepare { .. } | Self::Commit { .. } => std::mem::size_of::<SlotNumber>() as u64,
+            Self::PrePrepare { block, .. } => block.get_size() + size_of::<ViewNumber>() as u64,
+            Self::Prepare { .. } | Self::Commit { .. } => header_size,
+            Self::ViewChange { prepared, .. } => {
+                header_size + prepared.as_ref().map_or(0, |(_, block)| block.get_size())
+            }
         };
 
         body_size + SIGNATURE_SIZE
@@ -39,12 +72,25 @@ impl PbftMessage {
 
     fn get_slot(&self) -> Option<SlotNumber> {
         match self {
-            Self::PrePrepare { block } => Some(block.get_slot_number()),
-            Self::Prepare { slot } | Self::Commit { slot } => Some(*slot),
+            Self::PrePrepare { block, .. } => Some(block.get_slot_number()),
+            Self::Prepare { slot, .. }
+            | Self::Commit { slot, .. }
+            | Self::ViewChange { slot, .. } => Some(*slot),
             Self::SendTransaction(_) => None,
         }
     }
 
+    /// The view this message was sent in
+    /// Returns `None` for view changes (which ask for a future view) and transactions
+    fn get_view(&self) -> Option<ViewNumber> {
+        match self {
+            Self::PrePrepare { view, .. }
+            | Self::Prepare { view, .. }
+            | Self::Commit { view, .. } => Some(*view),
+            Self::ViewChange { .. } | Self::SendTransaction(_) => None,
+        }
+    }
+
     pub fn get_type(&self) -> MessageType {
         match self {
             Self::SendTransaction(_) => MessageType::Transaction,
@@ -58,8 +104,70 @@ pub struct PbftGlobalLogic {
 
     //Parameters
     max_block_size: u32,
-    quorum_size: u32,
     max_block_interval: Duration,
+    view_change_timeout: Duration,
+    schedule: ProposerSchedule,
+    voting_power: Rc<VotingPower>,
+    finalizations: Rc<FinalizationTracker>,
+    /// Faulty nodes do not take part in consensus, ordered by node index
+    faulty_nodes: Vec<bool>,
+}
+
+/// How much each node's vote counts and how much is needed for a quorum
+struct VotingPower {
+    /// Voting power of each node, ordered by node index
+    by_index: Vec<u64>,
+    /// Voting power of each node by identifier, filled in as nodes are created
+    by_identifier: RefCell<HashMap<ObjectId, u64>>,
+    quorum: u64,
+}
+
+impl VotingPower {
+    fn register_node(&self, node_id: NodeIndex, identifier: ObjectId) {
+        let power = *self
+            .by_index
+            .get(node_id as usize)
+            .expect("No voting power for node");
+        self.by_identifier.borrow_mut().insert(identifier, power);
+    }
+
+    /// Do the given nodes together hold enough voting power for a quorum?
+    fn is_quorum(&self, nodes: &HashSet<ObjectId>) -> bool {
+        let by_identifier = self.by_identifier.borrow();
+        let power: u64 = nodes
+            .iter()
+            .map(|node_id| by_identifier.get(node_id).expect("Unknown voter"))
+            .sum();
+        power >= self.quorum
+    }
+}
+
+/// Decides which node proposes the block for a slot
+///
+/// Every view change hands the proposer role to the next validator.
+#[derive(Clone, Debug)]
+struct ProposerSchedule {
+    /// All validators (including faulty ones), ordered by node index
+    validators: Rc<Vec<NodeIndex>>,
+    rotate: bool,
+}
+
+impl ProposerSchedule {
+    fn get_proposer(&self, slot: SlotNumber, view: ViewNumber) -> NodeIndex {
+        // Slots start at one
+        let offset = if self.rotate { slot - 1 } else { 0 };
+        self.validators[((offset + view) % (self.validators.len() as u64)) as usize]
+    }
+
+    fn get_role(&self, slot: SlotNumber, view: ViewNumber, node_id: NodeIndex) -> PbftRole {
+        if self.get_proposer(slot, view) == node_id {
+            PbftRole::Leader
+        } else if self.validators.contains(&node_id) {
+            PbftRole::Replica
+        } else {
+            PbftRole::Observer
+        }
+    }
 }
 
 /// Keeps track of the state of a single consensus round
@@ -68,32 +176,167 @@ struct RoundState {
     block: Option<Rc<ConventionalBlock>>,
     prepared_nodes: HashSet<ObjectId>,
     committed_nodes: HashSet<ObjectId>,
+    /// The block this node prepared most recently (kept across views)
+    prepared: Option<PreparedBlock>,
+    /// Requests to move to a specific (future) view
+    view_changes: HashMap<ViewNumber, ViewChangeVotes>,
+    /// Messages sent in a view this node has not moved to yet
+    future_messages: Vec<(ObjectId, PbftMessage)>,
+}
+
+impl RoundState {
+    /// Forget all progress of the current view, except for the prepared block
+    fn reset_view(&mut self, new_view: ViewNumber) {
+        self.block = None;
+        self.prepared_nodes.clear();
+        self.committed_nodes.clear();
+        self.view_changes.retain(|view, _| *view > new_view);
+    }
+
+    /// Memory used by this round in addition to its entry in the map of rounds
+    fn get_memory_usage(&self) -> u64 {
+        let votes = self
+            .view_changes
+            .values()
+            .map(|votes| memory::set_size(&votes.nodes))
+            .sum::<u64>();
+
+        memory::set_size(&self.prepared_nodes)
+            + memory::set_size(&self.committed_nodes)
+            + memory::map_size(&self.view_changes)
+            + votes
+            + memory::vec_size(&self.future_messages)
+    }
+}
+
+#[derive(Default)]
+struct ViewChangeVotes {
+    nodes: HashSet<ObjectId>,
+    /// The block that was prepared in the most recent view (if any)
+    prepared: Option<PreparedBlock>,
+}
+
+impl ViewChangeVotes {
+    fn add(&mut self, source: ObjectId, prepared: Option<PreparedBlock>) {
+        self.nodes.insert(source);
+
+        if let Some((view, block)) = prepared
+            && self
+                .prepared
+                .as_ref()
+                .is_none_or(|(prev_view, _)| view > *prev_view)
+        {
+            self.prepared = Some((view, block));
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, derive_more::Display)]
 enum PbftRole {
     Leader,
     Replica,
+    /// Follows consensus without voting or proposing (non-mining nodes)
+    Observer,
 }
 
 impl PbftGlobalLogic {
+    /// `voting_power` contains the voting power of every node, ordered by node index
+    ///
+    /// Non-mining nodes (as given by `mining_nodes`) act as observers.
+    /// They finalize blocks like everybody else, but never vote or propose.
+    ///
+    /// Faulty validators stay in the proposer schedule, so replicas
+    /// have to replace them with a view change once it is their turn.
+    #[allow(clippy::too_many_arguments)]
     pub fn instantiate(
-        num_nodes: u32,
+        failures: &Failures,
         max_block_size: u32,
         max_block_interval: u64,
+        view_change_timeout: u64,
+        genesis: &[GenesisAccount],
+        rotate_proposer: bool,
+        mut voting_power: Vec<u64>,
+        mining_nodes: Vec<bool>,
     ) -> Rc<dyn GlobalLogic> {
-        let f = (num_nodes - 1) / 3;
-        let quorum_size = num_nodes - f;
-        let global_ledger = Rc::new(RefCell::new(ConventionalGlobalLedger::new()));
+        for (power, is_mining) in voting_power.iter_mut().zip(mining_nodes.iter()) {
+            if !is_mining {
+                *power = 0;
+            }
+        }
+
+        let faulty_nodes: Vec<bool> = (0..mining_nodes.len())
+            .map(|idx| failures.is_faulty(&(idx as NodeIndex)))
+            .collect();
+
+        let validators: Vec<_> = mining_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, is_mining)| **is_mining)
+            .map(|(idx, _)| idx as NodeIndex)
+            .collect();
+        let num_validators = validators
+            .iter()
+            .filter(|idx| !faulty_nodes[**idx as usize])
+            .count() as u32;
+        let num_observers = failures.num_correct_nodes() - num_validators;
+
+        // Like with node counts, the quorum only considers correct nodes
+        let total_power: u64 = voting_power
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !failures.is_faulty(&(*idx as NodeIndex)))
+            .map(|(_, power)| *power)
+            .sum();
+        assert!(total_power > 0, "Correct validators have no voting power");
+
+        let f = (total_power - 1) / 3;
+        let voting_power = Rc::new(VotingPower {
+            by_index: voting_power,
+            by_identifier: Default::default(),
+            quorum: total_power - f,
+        });
+
+        let global_ledger = Rc::new(RefCell::new(ConventionalGlobalLedger::new(
+            make_genesis_state(genesis),
+        )));
         let max_block_interval = Duration::from_millis(max_block_interval);
+        let view_change_timeout = Duration::from_millis(view_change_timeout);
+
+        if total_power == num_validators as u64 {
+            log::info!(
+                "PBFT set up to tolerate {f} failures for a total of {num_validators} validators"
+            );
+        } else {
+            log::info!(
+                "PBFT set up to tolerate faulty nodes with a voting power of {f} out of {total_power}"
+            );
+        }
+        if num_observers > 0 {
+            log::info!("PBFT has {num_observers} observer(s) that do not vote");
+        }
+        if rotate_proposer {
+            log::info!("PBFT proposer rotates every slot");
+        }
+
+        let finalizations = Rc::new(FinalizationTracker::new(
+            failures.num_correct_nodes(),
+            num_validators,
+        ));
 
-        log::info!("PBFT set up to tolerate {f} failures for a total of {num_nodes} nodes");
+        let schedule = ProposerSchedule {
+            validators: Rc::new(validators),
+            rotate: rotate_proposer,
+        };
 
         Rc::new(Self {
-            quorum_size,
+            voting_power,
+            finalizations,
             max_block_size,
             max_block_interval,
+            view_change_timeout,
             global_ledger,
+            schedule,
+            faulty_nodes,
         })
     }
 }
@@ -103,10 +346,14 @@ impl GlobalLogic for PbftGlobalLogic {
     fn new_node_logic(&self, node_id: NodeIndex) -> Rc<dyn NodeLogic> {
         Rc::new(PbftNodeLogic::new(
             self.global_ledger.clone(),
-            self.quorum_size,
+            self.voting_power.clone(),
             self.max_block_size,
             self.max_block_interval,
+            self.view_change_timeout,
+            self.schedule.clone(),
+            self.finalizations.clone(),
             node_id,
+            self.faulty_nodes[node_id as usize],
         ))
     }
 
@@ -116,107 +363,20 @@ impl GlobalLogic for PbftGlobalLogic {
         clients: &[Rc<Client>],
         links: &BTreeMap<ObjectId, Rc<Link>>,
     ) -> ChainMetrics {
-        let global_ledger = self.global_ledger.borrow_mut();
-
-        let latest_commit = global_ledger.get_latest_commit();
-
-        let mut end_block = global_ledger.get_block(&latest_commit).expect("No blocks");
-        loop {
-            match timeout {
-                TimeoutConfig::Seconds { warmup, runtime } => {
-                    let end = Time::from_seconds(warmup + runtime);
-                    if end_block.get_creation_time() <= end {
-                        break;
-                    }
-                }
-                TimeoutConfig::Blocks { warmup, runtime } => {
-                    if end_block.get_height() <= warmup + runtime {
-                        break;
-                    }
-                }
-            }
-
-            end_block = global_ledger
-                .get_block(end_block.get_parent_id())
-                .expect("No parent block");
-        }
-
-        let mut blocks_in_interval = 0;
-        let mut num_transactions = 0;
-        let mut total_size = 0;
-
-        let end_time = end_block.get_creation_time();
-        let mut next_block = end_block;
-
-        loop {
-            match timeout {
-                TimeoutConfig::Seconds { warmup, .. } => {
-                    let start = Time::from_seconds(warmup);
-                    if next_block.get_creation_time() < start {
-                        break;
-                    }
-                }
-                TimeoutConfig::Blocks { warmup, .. } => {
-                    if next_block.get_height() < warmup {
-                        break;
-                    }
-                }
-            }
-
-            blocks_in_interval += 1;
-
-            num_transactions += next_block.num_transactions() as u64;
-            total_size += next_block.get_size();
-
-            if next_block.get_parent_id() == &GENESIS_BLOCK {
-                break;
-            } else {
-                next_block = global_ledger
-                    .get_block(next_block.get_parent_id())
-                    .expect("No parent block");
-            }
-        }
-
-        let elapsed = end_time - next_block.get_creation_time();
-
-        // FIXME this also counts blocks in the warmup period
-        let avg_block_interval = elapsed.as_seconds_f64() / (global_ledger.num_blocks() as f64);
-
-        let avg_block_size = (total_size as f64) / (blocks_in_interval as f64);
-
-        let mut latencies = vec![];
-        for client in clients {
-            latencies.append(&mut client.get_latencies().clone());
-        }
-
-        // FIXME latencies also contains transactions during warmup period
-        // assert_eq!(latencies.len(), num_transactions as usize);
-
-        let avg_latency =
-            latencies.iter().map(|t| t.as_millis_f64()).sum::<f64>() / (num_transactions as f64);
-
-        let mut num_network_messages = 0;
-        for link in links.values() {
-            num_network_messages += link.num_total_messages();
-        }
-
-        ChainMetrics {
-            total_blocks_mined: blocks_in_interval,
-            num_network_messages,
-            total_blocks_accepted: blocks_in_interval,
-            longest_chain_length: global_ledger.num_blocks() as u64,
-            avg_latency,
-            avg_block_interval,
-            avg_block_propagation: 0.0, //TODO
-            num_transactions,
-            elapsed,
-            avg_block_size,
-        }
+        get_metrics(
+            &self.global_ledger.borrow(),
+            &self.finalizations,
+            timeout,
+            clients,
+            links,
+        )
     }
 
     fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool {
         match connectivity {
-            Connectivity::Sparse { .. } => false,
+            Connectivity::Sparse { .. }
+            | Connectivity::RandomRegular { .. }
+            | Connectivity::SmallWorld { .. } => false,
             Connectivity::Full => true,
         }
     }
@@ -224,4 +384,12 @@ impl GlobalLogic for PbftGlobalLogic {
     async fn wait_for_blocks(&self, _blocks: u64) {
         unimplemented!();
     }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        self.global_ledger.borrow().get_memory_usage()
+    }
+
+    fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) {
+        self.global_ledger.borrow().add_to_fingerprint(fingerprint);
+    }
 }
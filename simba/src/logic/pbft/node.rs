@@ -1,9 +1,15 @@
 use crate::ledger::{
     ConventionalBlock, ConventionalGlobalLedger, ConventionalNodeLedger, SlotNumber,
+    get_account_state,
 };
-use crate::logic::{Block, GENESIS_BLOCK, NodeLogic, Transaction};
+use crate::logic::{
+    AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, GENESIS_HEIGHT, NodeLogic, Transaction,
+    TransactionId,
+};
+use crate::memory::{self, MemoryUsage};
 use crate::node::{Node, NodeIndex};
 use crate::object::{Object, ObjectId};
+use crate::random;
 use crate::{Message, RcCell};
 
 use std::cell::RefCell;
@@ -11,9 +17,10 @@ use std::cmp::Ordering;
 
 use asim::time::{Duration, Time};
 
-use cow_tree::CowTree;
-
-use super::{PbftMessage, PbftRole, RoundState};
+use super::{
+    FinalizationTracker, PbftMessage, PbftRole, ProposerSchedule, RoundState, ViewNumber,
+    VotingPower,
+};
 
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -21,15 +28,23 @@ use std::rc::Rc;
 use asim::sync::Notify;
 
 struct NodeState {
+    node_id: NodeIndex,
     role: PbftRole,
+    schedule: ProposerSchedule,
     rounds: HashMap<SlotNumber, RoundState>,
     pending_messages: HashMap<SlotNumber, Vec<(ObjectId, PbftMessage)>>,
     current_round: SlotNumber,
+    current_view: ViewNumber,
+    /// The view this node asked to move to
+    /// Nodes stop voting once they asked for a view change
+    requested_view: ViewNumber,
 
     local_ledger: ConventionalNodeLedger,
 
     last_block_time: Time,
-    last_proposed_round: Option<SlotNumber>,
+    last_proposed_round: Option<(SlotNumber, ViewNumber)>,
+
+    finalizations: Rc<FinalizationTracker>,
 }
 
 pub struct PbftNodeLogic {
@@ -39,8 +54,11 @@ pub struct PbftNodeLogic {
 
     //Parameters
     max_block_size: u32,
-    quorum_size: u32,
+    voting_power: Rc<VotingPower>,
     max_block_interval: Duration,
+    view_change_timeout: Duration,
+    /// Faulty nodes ignore all consensus messages
+    faulty: bool,
 }
 
 impl NodeState {
@@ -82,9 +100,9 @@ impl NodeState {
     fn should_propose_block(&self) -> bool {
         if self.role == PbftRole::Leader {
             match self.last_proposed_round {
-                Some(num) => {
+                Some((num, view)) => {
                     assert!(num <= self.current_round);
-                    num < self.current_round
+                    (num, view) != (self.current_round, self.current_view)
                 }
                 None => true,
             }
@@ -93,26 +111,48 @@ impl NodeState {
         }
     }
 
+    /// Does this node still vote in the current view?
+    fn is_voting(&self) -> bool {
+        self.role != PbftRole::Observer && self.requested_view == self.current_view
+    }
+
+    fn get_position(&self) -> (SlotNumber, ViewNumber) {
+        (self.current_round, self.current_view)
+    }
+
+    /// Is there something the current slot is waiting for?
+    fn has_pending_work(&self) -> bool {
+        let round = self.rounds.get(&self.current_round).unwrap();
+        round.block.is_some() || self.local_ledger.get_mempool_size() > 0
+    }
+
     fn maybe_commit(
         &mut self,
         node: &Node,
-        quorum_size: u32,
+        voting_power: &VotingPower,
         max_block_size: u32,
         global_ledger: &RcCell<ConventionalGlobalLedger>,
         propose_notify: &Notify,
     ) {
+        // Observers never vote, and replicas stop voting once they asked for a view change
+        if !self.is_voting() {
+            return;
+        }
+
         let round = self.rounds.get_mut(&self.current_round).unwrap();
 
         // Only send commit once we have prepared ourselves!
         // Also, only send commit message once
-        if (round.prepared_nodes.len() as u32) >= quorum_size
+        if voting_power.is_quorum(&round.prepared_nodes)
             && round.prepared_nodes.contains(&node.get_identifier())
             && !round.committed_nodes.contains(&node.get_identifier())
         {
             round.committed_nodes.insert(node.get_identifier());
+            round.prepared = Some((self.current_view, round.block.clone().unwrap()));
 
             let message = PbftMessage::Commit {
                 slot: self.current_round,
+                view: self.current_view,
             };
             node.broadcast(message.into(), None);
 
@@ -129,7 +169,7 @@ impl NodeState {
             // Other nodes might already have committed
             self.maybe_finalize(
                 node,
-                quorum_size,
+                voting_power,
                 max_block_size,
                 global_ledger,
                 propose_notify,
@@ -140,52 +180,74 @@ impl NodeState {
     fn maybe_finalize(
         &mut self,
         node: &Node,
-        quorum_size: u32,
+        voting_power: &VotingPower,
         max_block_size: u32,
         global_ledger: &RcCell<ConventionalGlobalLedger>,
         propose_notify: &Notify,
     ) {
+        let is_voting = self.is_voting();
         let round = self.rounds.get_mut(&self.current_round).unwrap();
 
         // Only finish round once we have committed ourselves
-        if (round.committed_nodes.len() as u32) >= quorum_size
-            && round.committed_nodes.contains(&node.get_identifier())
+        // (or, for nodes that do not vote, once we know the block)
+        let is_observer = self.role == PbftRole::Observer;
+        if voting_power.is_quorum(&round.committed_nodes)
+            && round.block.is_some()
+            && (!is_voting || round.committed_nodes.contains(&node.get_identifier()))
         {
-            let block = round.block.as_ref().unwrap();
+            let block = round.block.clone().unwrap();
             block.mark_as_accepted();
 
+            // Other nodes might propose the next block, so all of them have to track what was included
+            self.local_ledger
+                .remove_transactions(block.get_transactions());
+            self.last_block_time = block.get_creation_time();
+            self.finalizations.record(&block, !is_observer);
+
             for txn in block.get_transactions().iter() {
+                node.get_statistics().record_transaction_commit();
+
                 if let Some(client) = node.get_client(txn.get_source()) {
                     client.notify_transaction_commit();
                 }
             }
 
             if self.role == PbftRole::Leader {
-                global_ledger
+                let delay = global_ledger
                     .borrow_mut()
                     .set_latest_commit(*block.get_identifier());
+                node.get_statistics().record_block_commit(delay);
 
                 log::debug!("Leader finalized block for slot #{}", self.current_round);
-                propose_notify.notify_one();
             } else {
                 log::trace!(
-                    "Replica #{} finalized block for slot #{}",
+                    "{} #{} finalized block for slot #{}",
+                    self.role,
                     node.get_index(),
                     self.current_round
                 );
             }
 
+            // A view change for the finalized slot is not needed anymore
             self.current_round += 1;
+            self.requested_view = self.current_view;
             self.rounds
                 .insert(self.current_round, RoundState::default());
 
+            self.role = self
+                .schedule
+                .get_role(self.current_round, self.current_view, self.node_id);
+
+            // Wake up the main loop, which might need to propose or restart its timer
+            propose_notify.notify_one();
+
             if let Some(mut messages) = self.pending_messages.remove(&self.current_round) {
                 for (source, message) in messages.drain(..) {
                     self.handle_message(
                         node,
                         source,
                         message,
-                        quorum_size,
+                        voting_power,
                         max_block_size,
                         global_ledger,
                         propose_notify,
@@ -201,7 +263,7 @@ impl NodeState {
         node: &Node,
         source: ObjectId,
         message: PbftMessage,
-        quorum_size: u32,
+        voting_power: &VotingPower,
         max_block_size: u32,
         global_ledger: &RcCell<ConventionalGlobalLedger>,
         propose_notify: &Notify,
@@ -215,8 +277,8 @@ impl NodeState {
 
         match self.current_round.cmp(&round_num) {
             Ordering::Greater => {
-                // discard?
                 log::trace!("Got message for past round");
+                return;
             }
             Ordering::Less => {
                 self.pending_messages
@@ -231,13 +293,67 @@ impl NodeState {
 
         let round = self.rounds.get_mut(&round_num).unwrap();
 
+        if let Some(view) = message.get_view() {
+            match self.current_view.cmp(&view) {
+                Ordering::Greater => {
+                    log::trace!("Got message for past view");
+                    return;
+                }
+                Ordering::Less => {
+                    round.future_messages.push((source, message));
+                    log::trace!("Got message for future view");
+                    return;
+                }
+                Ordering::Equal => {}
+            }
+        }
+
         match message {
-            PbftMessage::PrePrepare { block } => {
+            PbftMessage::ViewChange { view, prepared, .. } => {
+                if view <= self.current_view {
+                    log::trace!("Got request for past view");
+                    return;
+                }
+
+                let votes = round.view_changes.entry(view).or_default();
+                votes.add(source, prepared);
+
+                if voting_power.is_quorum(&votes.nodes) {
+                    self.enter_view(
+                        node,
+                        view,
+                        voting_power,
+                        max_block_size,
+                        global_ledger,
+                        propose_notify,
+                    );
+                }
+            }
+            PbftMessage::PrePrepare { block, .. } => {
                 if round.block.is_some() {
                     panic!("Got pre-prepare more than once");
                 }
 
                 round.block = Some(block);
+
+                if !self.is_voting() {
+                    log::trace!(
+                        "{} #{} got block for slot #{round_num} without voting",
+                        self.role,
+                        node.get_index()
+                    );
+
+                    // Commits might have arrived before the block
+                    self.maybe_finalize(
+                        node,
+                        voting_power,
+                        max_block_size,
+                        global_ledger,
+                        propose_notify,
+                    );
+                    return;
+                }
+
                 round.prepared_nodes.insert(node.get_identifier());
 
                 if self.role == PbftRole::Leader {
@@ -249,12 +365,15 @@ impl NodeState {
                     );
                 }
 
-                let message = PbftMessage::Prepare { slot: round_num };
+                let message = PbftMessage::Prepare {
+                    slot: round_num,
+                    view: self.current_view,
+                };
                 node.broadcast(message.into(), None);
 
                 self.maybe_commit(
                     node,
-                    quorum_size,
+                    voting_power,
                     max_block_size,
                     global_ledger,
                     propose_notify,
@@ -264,7 +383,7 @@ impl NodeState {
                 round.prepared_nodes.insert(source);
                 self.maybe_commit(
                     node,
-                    quorum_size,
+                    voting_power,
                     max_block_size,
                     global_ledger,
                     propose_notify,
@@ -274,7 +393,7 @@ impl NodeState {
                 round.committed_nodes.insert(source);
                 self.maybe_finalize(
                     node,
-                    quorum_size,
+                    voting_power,
                     max_block_size,
                     global_ledger,
                     propose_notify,
@@ -286,17 +405,125 @@ impl NodeState {
         }
     }
 
+    /// Switch to a new view once a quorum asked for it
+    ///
+    /// If any of them prepared a block for the current slot,
+    /// the new leader proposes it again, so that nodes that already
+    /// committed it stay consistent with the others.
+    fn enter_view(
+        &mut self,
+        node: &Node,
+        view: ViewNumber,
+        voting_power: &VotingPower,
+        max_block_size: u32,
+        global_ledger: &RcCell<ConventionalGlobalLedger>,
+        propose_notify: &Notify,
+    ) {
+        let round = self.rounds.get_mut(&self.current_round).unwrap();
+        let votes = round.view_changes.remove(&view).unwrap();
+        round.reset_view(view);
+        let future_messages = std::mem::take(&mut round.future_messages);
+
+        self.current_view = view;
+        self.requested_view = view;
+        self.role = self
+            .schedule
+            .get_role(self.current_round, view, self.node_id);
+
+        log::debug!(
+            "Node #{} moved to view {view} at slot #{} as {}",
+            node.get_index(),
+            self.current_round,
+            self.role
+        );
+
+        // Wake up the main loop, which might need to propose or restart its timer
+        propose_notify.notify_one();
+
+        if self.role == PbftRole::Leader
+            && let Some((_, block)) = votes.prepared
+        {
+            log::debug!(
+                "Proposing prepared block for slot #{} again",
+                self.current_round
+            );
+            self.last_proposed_round = Some((self.current_round, view));
+
+            let message = PbftMessage::PrePrepare { block, view };
+            node.broadcast(message.clone().into(), None);
+            self.handle_message(
+                node,
+                node.get_identifier(),
+                message,
+                voting_power,
+                max_block_size,
+                global_ledger,
+                propose_notify,
+            );
+        }
+
+        for (source, message) in future_messages {
+            self.handle_message(
+                node,
+                source,
+                message,
+                voting_power,
+                max_block_size,
+                global_ledger,
+                propose_notify,
+            );
+        }
+    }
+
+    /// Ask to replace the leader, because the current slot did not make progress
+    /// Repeated requests ask for the next view after the previously requested one
+    fn start_view_change(
+        &mut self,
+        node: &Node,
+        voting_power: &VotingPower,
+        max_block_size: u32,
+        global_ledger: &RcCell<ConventionalGlobalLedger>,
+        propose_notify: &Notify,
+    ) {
+        self.requested_view += 1;
+
+        log::debug!(
+            "Node #{} asks for view {} at slot #{}",
+            node.get_index(),
+            self.requested_view,
+            self.current_round
+        );
+
+        let round = self.rounds.get(&self.current_round).unwrap();
+        let message = PbftMessage::ViewChange {
+            slot: self.current_round,
+            view: self.requested_view,
+            prepared: round.prepared.clone(),
+        };
+
+        node.broadcast(message.clone().into(), None);
+        self.handle_message(
+            node,
+            node.get_identifier(),
+            message,
+            voting_power,
+            max_block_size,
+            global_ledger,
+            propose_notify,
+        );
+    }
+
     fn propose_block(
         &mut self,
         node: &Node,
         global_ledger: &RcCell<ConventionalGlobalLedger>,
-        quorum_size: u32,
+        voting_power: &VotingPower,
         max_block_size: u32,
         propose_notify: &Notify,
     ) {
         log::debug!("Proposing block for slot #{}", self.current_round);
         self.last_block_time = asim::time::now();
-        self.last_proposed_round = Some(self.current_round);
+        self.last_proposed_round = Some((self.current_round, self.current_view));
 
         let parent = if self.current_round > 1 {
             let prev_round = self.current_round - 1;
@@ -312,7 +539,7 @@ impl NodeState {
             GENESIS_BLOCK
         };
 
-        let block_id = rand::random();
+        let block_id = random::random();
         let creation_time = asim::time::now();
 
         let transactions = self
@@ -320,13 +547,21 @@ impl NodeState {
             .get_transactions_from_mempool(max_block_size);
         assert!(!transactions.is_empty());
 
-        //FIXME
-        let block_state = CowTree::default().freeze();
+        //FIXME actually modify state
+        let block_state = {
+            let global_ledger = global_ledger.borrow();
+            if parent == GENESIS_BLOCK {
+                global_ledger.get_genesis_state().deep_clone().freeze()
+            } else {
+                let parent = global_ledger.get_block(&parent).expect("No such block");
+                parent.get_state().deep_clone().freeze()
+            }
+        };
 
         let block = Rc::new(ConventionalBlock::new(
             block_id,
             parent,
-            node.get_index(),
+            self.node_id,
             transactions,
             creation_time,
             self.current_round,
@@ -337,7 +572,10 @@ impl NodeState {
             .borrow_mut()
             .add_block(block_id, block.clone());
 
-        let message = PbftMessage::PrePrepare { block };
+        let message = PbftMessage::PrePrepare {
+            block,
+            view: self.current_view,
+        };
 
         node.broadcast(message.clone().into(), None);
 
@@ -346,7 +584,7 @@ impl NodeState {
             node,
             node.get_identifier(),
             message,
-            quorum_size,
+            voting_power,
             max_block_size,
             global_ledger,
             propose_notify,
@@ -383,8 +621,13 @@ impl NodeState {
 
 #[async_trait::async_trait(?Send)]
 impl NodeLogic for PbftNodeLogic {
-    fn init(&self, _node: Rc<Node>) {}
+    fn init(&self, node: Rc<Node>) {
+        let node_id = self.state.borrow().node_id;
+        self.voting_power
+            .register_node(node_id, node.get_identifier());
+    }
 
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
     async fn run(&self, node: Rc<Node>, _is_mining: bool) {
         loop {
             let node_role = self.state.borrow().role;
@@ -403,7 +646,7 @@ impl NodeLogic for PbftNodeLogic {
                                 state.propose_block(
                                     &node,
                                     &self.global_ledger,
-                                    self.quorum_size,
+                                    &self.voting_power,
                                     self.max_block_size,
                                     &self.propose_notify,
                                 );
@@ -430,15 +673,44 @@ impl NodeLogic for PbftNodeLogic {
                         self.propose_notify.notified().await;
                     }
                 }
-                PbftRole::Replica => {
-                    //TODO maybe do view change?
+                PbftRole::Observer => {
+                    // Observers only react to messages
                     return;
                 }
+                PbftRole::Replica => {
+                    let (position, has_pending_work) = {
+                        let state = self.state.borrow();
+                        (state.get_position(), state.has_pending_work())
+                    };
+
+                    // Wait until it is our turn to propose,
+                    // or ask for a new leader if the current slot does not make progress
+                    tokio::select! {
+                        _ = asim::time::sleep(self.view_change_timeout) => {
+                            let mut state = self.state.borrow_mut();
+                            if has_pending_work && state.get_position() == position {
+                                state.start_view_change(
+                                    &node,
+                                    &self.voting_power,
+                                    self.max_block_size,
+                                    &self.global_ledger,
+                                    &self.propose_notify,
+                                );
+                            }
+                        },
+                        _ = self.propose_notify.notified() => {},
+                    }
+                }
             }
         }
     }
 
-    fn add_transaction(&self, node: &Node, transaction: Rc<Transaction>, source: Option<ObjectId>) {
+    fn add_transaction(
+        &self,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) -> bool {
         let mut state = self.state.borrow_mut();
         state.add_transaction(
             node,
@@ -447,17 +719,72 @@ impl NodeLogic for PbftNodeLogic {
             &self.propose_notify,
             self.max_block_size,
         );
+        true
+    }
+
+    fn get_mempool(&self) -> Vec<TransactionId> {
+        self.state.borrow().local_ledger.get_mempool()
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let state = self.state.borrow();
+        let rounds = state
+            .rounds
+            .values()
+            .map(RoundState::get_memory_usage)
+            .sum::<u64>();
+
+        let mut usage = MemoryUsage::default();
+        state.local_ledger.add_memory_usage(&mut usage);
+        usage.add("rounds", memory::map_size(&state.rounds) + rounds);
+        usage.add(
+            "pending_messages",
+            memory::nested_map_size(&state.pending_messages),
+        );
+        usage
+    }
+
+    /// Blocks are committed by all nodes at once, so this returns the latest global commit
+    fn get_chain_head(&self) -> Option<(BlockId, u64)> {
+        let global_ledger = self.global_ledger.borrow();
+        if !global_ledger.has_committed_block() {
+            return Some((GENESIS_BLOCK, GENESIS_HEIGHT));
+        }
+
+        let block_id = global_ledger.get_latest_commit();
+        let block = global_ledger.get_block(&block_id)?;
+        Some((block_id, block.get_height()))
+    }
+
+    fn get_account_state(&self, account: &AccountId) -> Option<AccountState> {
+        let global_ledger = self.global_ledger.borrow();
+        if !global_ledger.has_committed_block() {
+            return get_account_state(global_ledger.get_genesis_state(), account);
+        }
+
+        let block = global_ledger.get_block(&global_ledger.get_latest_commit())?;
+        get_account_state(block.get_state(), account)
     }
 
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
         let message: PbftMessage = message.try_into().expect("Not a PBFT message");
+
+        // Faulty nodes do not take part in consensus
+        if self.faulty {
+            return;
+        }
+
         let mut state = self.state.borrow_mut();
 
         state.handle_message(
             node,
             source,
             message,
-            self.quorum_size,
+            &self.voting_power,
             self.max_block_size,
             &self.global_ledger,
             &self.propose_notify,
@@ -466,22 +793,24 @@ impl NodeLogic for PbftNodeLogic {
 }
 
 impl PbftNodeLogic {
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
         global_ledger: RcCell<ConventionalGlobalLedger>,
-        quorum_size: u32,
+        voting_power: Rc<VotingPower>,
         max_block_size: u32,
         max_block_interval: Duration,
+        view_change_timeout: Duration,
+        schedule: ProposerSchedule,
+        finalizations: Rc<FinalizationTracker>,
         node_id: NodeIndex,
+        faulty: bool,
     ) -> Self {
-        let role = if node_id == 0 {
-            PbftRole::Leader
-        } else {
-            PbftRole::Replica
-        };
+        let current_round = 1;
+        let current_view = 0;
+        let role = schedule.get_role(current_round, current_view, node_id);
 
         log::debug!("Created PBFT node with role {role}");
 
-        let current_round = 1;
         let last_proposed_round = None;
         let mut rounds = HashMap::new();
         let pending_messages = Default::default();
@@ -492,21 +821,28 @@ impl PbftNodeLogic {
         rounds.insert(current_round, RoundState::default());
 
         let state = RefCell::new(NodeState {
+            node_id,
             role,
+            schedule,
             current_round,
+            current_view,
+            requested_view: current_view,
             rounds,
             pending_messages,
             local_ledger,
             last_proposed_round,
             last_block_time,
+            finalizations,
         });
 
         let propose_notify = Notify::new();
 
         Self {
             global_ledger,
-            quorum_size,
+            voting_power,
             max_block_interval,
+            view_change_timeout,
+            faulty,
             state,
             max_block_size,
             propose_notify,
@@ -0,0 +1,271 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+use crate::RcCell;
+use crate::clients::Client;
+use crate::config::{Connectivity, GenesisAccount, TimeoutConfig};
+use crate::failures::Failures;
+use crate::fingerprint::FingerprintBuilder;
+use crate::ledger::{ConventionalBlock, ConventionalGlobalLedger, SlotNumber, make_genesis_state};
+use crate::link::Link;
+use crate::logic::{GlobalLogic, NUM_SIZE, NodeLogic, SIGNATURE_SIZE, Transaction};
+use crate::memory::MemoryUsage;
+use crate::message::MessageType;
+use crate::metrics::ChainMetrics;
+use crate::node::NodeIndex;
+use crate::object::ObjectId;
+
+use asim::time::Duration;
+
+use super::conventional::{FinalizationTracker, get_metrics};
+
+mod node;
+pub use node::RaftNodeLogic;
+
+/// Terms start at zero and increase with every election
+pub type Term = u64;
+
+/// An entry of the replicated log
+/// Each entry holds one block, and the index of an entry is the slot of its block
+type LogEntry = (Term, Rc<ConventionalBlock>);
+
+/// Messages of the Raft protocol
+///
+/// Raft only tolerates crash faults, so unlike PBFT messages
+/// (except for transactions) do not need to be signed.
+#[derive(Clone, Debug)]
+pub enum RaftMessage {
+    SendTransaction(Rc<Transaction>),
+    /// Sent by candidates to ask for votes
+    RequestVote {
+        term: Term,
+        last_log_index: SlotNumber,
+        last_log_term: Term,
+    },
+    Vote {
+        term: Term,
+        granted: bool,
+    },
+    /// Replicates the leader's log; contains no entries if it is only a heartbeat
+    AppendEntries {
+        term: Term,
+        prev_log_index: SlotNumber,
+        prev_log_term: Term,
+        entries: Vec<LogEntry>,
+        commit_index: SlotNumber,
+    },
+    /// Tells the leader how much of its log the sender holds
+    /// If `success` is false, `match_index` is the sender's commit index,
+    /// which the leader can always replicate from
+    AppendResponse {
+        term: Term,
+        success: bool,
+        match_index: SlotNumber,
+    },
+}
+
+impl RaftMessage {
+    pub fn get_size(&self) -> u64 {
+        match self {
+            Self::SendTransaction(_) => SIGNATURE_SIZE,
+            Self::RequestVote { .. } | Self::AppendResponse { .. } => 3 * NUM_SIZE,
+            Self::Vote { .. } => 2 * NUM_SIZE,
+            Self::AppendEntries { entries, .. } => {
+                let entries_size: u64 = entries
+                    .iter()
+                    .map(|(_, block)| NUM_SIZE + block.get_size())
+                    .sum();
+                4 * NUM_SIZE + entries_size
+            }
+        }
+    }
+
+    /// The term of the sender
+    /// Returns `None` for transactions
+    fn get_term(&self) -> Option<Term> {
+        match self {
+            Self::RequestVote { term, .. }
+            | Self::Vote { term, .. }
+            | Self::AppendEntries { term, .. }
+            | Self::AppendResponse { term, .. } => Some(*term),
+            Self::SendTransaction(_) => None,
+        }
+    }
+
+    pub fn get_type(&self) -> MessageType {
+        match self {
+            Self::SendTransaction(_) => MessageType::Transaction,
+            Self::AppendEntries { entries, .. } if !entries.is_empty() => MessageType::Block,
+            _ => MessageType::Other,
+        }
+    }
+}
+
+/// The nodes that vote in elections and on log entries
+///
+/// Non-mining nodes replicate the log as learners, but never vote or lead.
+struct RaftCluster {
+    /// Whether each node votes, ordered by node index
+    voters_by_index: Vec<bool>,
+    /// Identifiers of all voters, filled in as nodes are created
+    voters: RefCell<HashSet<ObjectId>>,
+    /// A majority of all voters (including faulty ones)
+    quorum: usize,
+}
+
+impl RaftCluster {
+    fn register_node(&self, node_id: NodeIndex, identifier: ObjectId) {
+        if self.voters_by_index[node_id as usize] {
+            self.voters.borrow_mut().insert(identifier);
+        }
+    }
+
+    fn is_voter(&self, identifier: &ObjectId) -> bool {
+        self.voters.borrow().contains(identifier)
+    }
+
+    fn is_quorum(&self, num_voters: usize) -> bool {
+        num_voters >= self.quorum
+    }
+}
+
+/// How a node participates in consensus
+#[derive(Clone, Copy, Debug, PartialEq, derive_more::Display)]
+enum RaftRole {
+    Follower,
+    Candidate,
+    Leader,
+    /// Replicates the log without voting (non-mining nodes)
+    Learner,
+}
+
+pub struct RaftGlobalLogic {
+    global_ledger: RcCell<ConventionalGlobalLedger>,
+
+    //Parameters
+    max_block_size: u32,
+    max_block_interval: Duration,
+    /// Minimum election timeout (in milliseconds)
+    election_timeout: u64,
+    cluster: Rc<RaftCluster>,
+    finalizations: Rc<FinalizationTracker>,
+    /// Faulty nodes do not take part in consensus, ordered by node index
+    faulty_nodes: Vec<bool>,
+}
+
+impl RaftGlobalLogic {
+    /// Non-mining nodes (as given by `mining_nodes`) act as learners.
+    ///
+    /// Unlike PBFT, the quorum is a majority of all voters, because Raft
+    /// only makes progress if a majority of the cluster is reachable.
+    pub fn instantiate(
+        failures: &Failures,
+        max_block_size: u32,
+        max_block_interval: u64,
+        election_timeout: u64,
+        genesis: &[GenesisAccount],
+        mining_nodes: Vec<bool>,
+    ) -> Rc<dyn GlobalLogic> {
+        let faulty_nodes: Vec<bool> = (0..mining_nodes.len())
+            .map(|idx| failures.is_faulty(&(idx as NodeIndex)))
+            .collect();
+
+        let num_voters = mining_nodes.iter().filter(|is_mining| **is_mining).count();
+        assert!(num_voters > 0, "Raft needs at least one voter");
+
+        let num_correct_voters = mining_nodes
+            .iter()
+            .zip(faulty_nodes.iter())
+            .filter(|(is_mining, is_faulty)| **is_mining && !**is_faulty)
+            .count() as u32;
+        let num_learners = failures.num_correct_nodes() - num_correct_voters;
+
+        let quorum = num_voters / 2 + 1;
+        log::info!(
+            "Raft set up to tolerate {} crashed nodes for a total of {num_voters} voters",
+            num_voters - quorum
+        );
+        if num_learners > 0 {
+            log::info!("Raft has {num_learners} learner(s) that do not vote");
+        }
+
+        let cluster = Rc::new(RaftCluster {
+            voters_by_index: mining_nodes,
+            voters: Default::default(),
+            quorum,
+        });
+
+        let global_ledger = Rc::new(RefCell::new(ConventionalGlobalLedger::new(
+            make_genesis_state(genesis),
+        )));
+
+        let finalizations = Rc::new(FinalizationTracker::new(
+            failures.num_correct_nodes(),
+            num_correct_voters,
+        ));
+
+        Rc::new(Self {
+            global_ledger,
+            max_block_size,
+            max_block_interval: Duration::from_millis(max_block_interval),
+            election_timeout,
+            cluster,
+            finalizations,
+            faulty_nodes,
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl GlobalLogic for RaftGlobalLogic {
+    fn new_node_logic(&self, node_id: NodeIndex) -> Rc<dyn NodeLogic> {
+        Rc::new(RaftNodeLogic::new(
+            self.global_ledger.clone(),
+            self.cluster.clone(),
+            self.finalizations.clone(),
+            self.max_block_size,
+            self.max_block_interval,
+            self.election_timeout,
+            node_id,
+            self.faulty_nodes[node_id as usize],
+        ))
+    }
+
+    fn get_metrics(
+        &self,
+        timeout: TimeoutConfig,
+        clients: &[Rc<Client>],
+        links: &BTreeMap<ObjectId, Rc<Link>>,
+    ) -> ChainMetrics {
+        get_metrics(
+            &self.global_ledger.borrow(),
+            &self.finalizations,
+            timeout,
+            clients,
+            links,
+        )
+    }
+
+    fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool {
+        match connectivity {
+            Connectivity::Sparse { .. }
+            | Connectivity::RandomRegular { .. }
+            | Connectivity::SmallWorld { .. } => false,
+            Connectivity::Full => true,
+        }
+    }
+
+    async fn wait_for_blocks(&self, blocks: u64) {
+        let commit_height = self.global_ledger.borrow().get_commit_height();
+        commit_height.wait_for(blocks).await;
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        self.global_ledger.borrow().get_memory_usage()
+    }
+
+    fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) {
+        self.global_ledger.borrow().add_to_fingerprint(fingerprint);
+    }
+}
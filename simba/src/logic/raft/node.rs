@@ -0,0 +1,718 @@
+use crate::ledger::{
+    ConventionalBlock, ConventionalGlobalLedger, ConventionalNodeLedger, SlotNumber,
+    get_account_state,
+};
+use crate::logic::conventional::advance_latest_commit;
+use crate::logic::{
+    AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, GENESIS_HEIGHT, NodeLogic, Transaction,
+    TransactionId,
+};
+use crate::memory::{self, MemoryUsage};
+use crate::node::{Node, NodeIndex};
+use crate::object::{Object, ObjectId};
+use crate::random;
+use crate::{Message, RcCell};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use asim::sync::Notify;
+use asim::time::{Duration, Time};
+
+use rand::Rng;
+
+use super::{FinalizationTracker, LogEntry, RaftCluster, RaftMessage, RaftRole, Term};
+
+struct NodeState {
+    role: RaftRole,
+    term: Term,
+    voted_for: Option<ObjectId>,
+    /// Votes received in the current term (only used by candidates)
+    votes: HashSet<ObjectId>,
+
+    /// The entry at position `i` has index (and slot) `i+1`
+    log: Vec<LogEntry>,
+    commit_index: SlotNumber,
+
+    /// The next log entry to send to each peer (only used by the leader)
+    next_index: HashMap<ObjectId, SlotNumber>,
+    /// The highest log entry each voter is known to hold (only used by the leader)
+    match_index: HashMap<ObjectId, SlotNumber>,
+
+    /// Followers start an election if they do not hear from a leader until then
+    election_deadline: Time,
+    last_heartbeat: Time,
+    last_block_time: Time,
+
+    local_ledger: ConventionalNodeLedger,
+    finalizations: Rc<FinalizationTracker>,
+}
+
+pub struct RaftNodeLogic {
+    state: RefCell<NodeState>,
+    global_ledger: RcCell<ConventionalGlobalLedger>,
+    cluster: Rc<RaftCluster>,
+    /// Wakes up the main loop if the role changed or there are new transactions
+    notify: Notify,
+
+    //Parameters
+    node_id: NodeIndex,
+    max_block_size: u32,
+    max_block_interval: Duration,
+    /// Minimum election timeout (in milliseconds)
+    election_timeout: u64,
+    heartbeat_interval: Duration,
+    /// Faulty nodes ignore all consensus messages
+    faulty: bool,
+}
+
+impl NodeState {
+    fn last_log_index(&self) -> SlotNumber {
+        self.log.len() as SlotNumber
+    }
+
+    /// Returns zero for the (empty) entry before the first one
+    fn get_term_at(&self, index: SlotNumber) -> Term {
+        if index == 0 {
+            0
+        } else {
+            self.log[(index - 1) as usize].0
+        }
+    }
+
+    fn last_log_term(&self) -> Term {
+        self.get_term_at(self.last_log_index())
+    }
+
+    /// Is the log of a candidate at least as recent as ours?
+    fn is_up_to_date(&self, last_log_index: SlotNumber, last_log_term: Term) -> bool {
+        let own_term = self.last_log_term();
+        last_log_term > own_term
+            || (last_log_term == own_term && last_log_index >= self.last_log_index())
+    }
+
+    /// Is there an entry that is not committed yet?
+    fn has_uncommitted_entries(&self) -> bool {
+        self.commit_index < self.last_log_index()
+    }
+
+    /// Appends an entry and removes its transactions from the mempool,
+    /// so that they are not proposed again by a future leader
+    fn append_entry(&mut self, entry: LogEntry) {
+        self.local_ledger
+            .remove_transactions(entry.1.get_transactions());
+        self.log.push(entry);
+    }
+
+    /// Removes all entries starting at `index`, which conflict with the leader's log
+    /// Their transactions are returned to the mempool
+    fn truncate_log(&mut self, index: SlotNumber) {
+        assert!(index > self.commit_index, "Cannot remove committed entries");
+
+        for (_, block) in self.log.drain((index - 1) as usize..) {
+            for txn in block.get_transactions() {
+                self.local_ledger.add_transaction(txn.clone());
+            }
+        }
+    }
+}
+
+impl RaftNodeLogic {
+    fn is_voter(&self, state: &NodeState) -> bool {
+        state.role != RaftRole::Learner
+    }
+
+    /// Picks a new random election deadline, so that nodes do not keep starting elections at the same time
+    fn reset_election_deadline(&self, state: &mut NodeState) {
+        let timeout = random::rng().random_range(self.election_timeout..=2 * self.election_timeout);
+        state.election_deadline = asim::time::now() + Duration::from_millis(timeout);
+    }
+
+    /// Move to a new term as a follower (or learner)
+    fn step_down(&self, state: &mut NodeState, term: Term) {
+        if state.role == RaftRole::Leader {
+            log::debug!("Leader stepped down in term {term}");
+        }
+
+        state.term = term;
+        state.voted_for = None;
+        state.votes.clear();
+
+        if self.is_voter(state) {
+            state.role = RaftRole::Follower;
+        }
+
+        self.reset_election_deadline(state);
+        self.notify.notify_one();
+    }
+
+    fn start_election(&self, state: &mut NodeState, node: &Node) {
+        state.term += 1;
+        state.role = RaftRole::Candidate;
+        state.voted_for = Some(node.get_identifier());
+        state.votes.clear();
+        state.votes.insert(node.get_identifier());
+        self.reset_election_deadline(state);
+
+        log::debug!(
+            "Node #{} started an election for term {}",
+            node.get_index(),
+            state.term
+        );
+
+        let message = RaftMessage::RequestVote {
+            term: state.term,
+            last_log_index: state.last_log_index(),
+            last_log_term: state.last_log_term(),
+        };
+        node.broadcast(message.into(), None);
+
+        // There might be no other voters
+        self.maybe_become_leader(state, node);
+    }
+
+    fn maybe_become_leader(&self, state: &mut NodeState, node: &Node) {
+        if state.role != RaftRole::Candidate || !self.cluster.is_quorum(state.votes.len()) {
+            return;
+        }
+
+        log::debug!(
+            "Node #{} became leader for term {}",
+            node.get_index(),
+            state.term
+        );
+
+        state.role = RaftRole::Leader;
+        state.next_index.clear();
+        state.match_index.clear();
+
+        // Entries of earlier terms are committed together with the first block of this term
+        state.last_block_time = asim::time::now();
+
+        self.send_heartbeat(state, node);
+        self.notify.notify_one();
+    }
+
+    /// Sends all entries a peer does not have yet (or an empty heartbeat)
+    fn send_entries(&self, state: &mut NodeState, node: &Node, peer: ObjectId) {
+        let last_log_index = state.last_log_index();
+        let next_index = state
+            .next_index
+            .get(&peer)
+            .copied()
+            .unwrap_or(last_log_index + 1);
+
+        let prev_log_index = next_index - 1;
+        let message = RaftMessage::AppendEntries {
+            term: state.term,
+            prev_log_index,
+            prev_log_term: state.get_term_at(prev_log_index),
+            entries: state.log[prev_log_index as usize..].to_vec(),
+            commit_index: state.commit_index,
+        };
+
+        // Assume the peer will accept the entries; it will tell us otherwise
+        state.next_index.insert(peer, last_log_index + 1);
+        node.send_to(&peer, message);
+    }
+
+    /// Sends new entries and the commit index to all peers
+    fn send_heartbeat(&self, state: &mut NodeState, node: &Node) {
+        state.last_heartbeat = asim::time::now();
+
+        for peer in node.get_peers() {
+            self.send_entries(state, node, peer);
+        }
+    }
+
+    fn propose_block(&self, state: &mut NodeState, node: &Node) {
+        let slot = state.last_log_index() + 1;
+        log::debug!("Proposing block for slot #{slot}");
+
+        let parent = state
+            .log
+            .last()
+            .map_or(GENESIS_BLOCK, |(_, block)| *block.get_identifier());
+
+        let block_id = random::random();
+        let creation_time = asim::time::now();
+        state.last_block_time = creation_time;
+
+        let transactions = state
+            .local_ledger
+            .get_transactions_from_mempool(self.max_block_size);
+        assert!(!transactions.is_empty());
+
+        // Raft does not execute transactions, so blocks keep the state of their parent
+        let block_state = {
+            let global_ledger = self.global_ledger.borrow();
+            if parent == GENESIS_BLOCK {
+                global_ledger.get_genesis_state().deep_clone().freeze()
+            } else {
+                let parent = global_ledger.get_block(&parent).expect("No such block");
+                parent.get_state().deep_clone().freeze()
+            }
+        };
+
+        let block = Rc::new(ConventionalBlock::new(
+            block_id,
+            parent,
+            self.node_id,
+            transactions,
+            creation_time,
+            slot,
+            block_state,
+        ));
+
+        self.global_ledger
+            .borrow_mut()
+            .add_block(block_id, block.clone());
+
+        state.append_entry((state.term, block));
+        self.send_heartbeat(state, node);
+
+        // There might be no other voters
+        self.maybe_advance_commit(state, node);
+    }
+
+    /// Do we have enough pending transactions or did enough time elapse?
+    fn can_propose_block(&self, state: &NodeState) -> Result<(), Option<Duration>> {
+        // Only one block is replicated at a time
+        if state.has_uncommitted_entries() {
+            return Err(None);
+        }
+
+        let mempool_size = state.local_ledger.get_mempool_size();
+        if mempool_size == 0 {
+            log::trace!("Cannot propose yet: no transactions");
+            return Err(None);
+        }
+
+        let elapsed = asim::time::now() - state.last_block_time;
+        if elapsed >= self.max_block_interval || mempool_size >= self.max_block_size {
+            Ok(())
+        } else {
+            Err(Some(self.max_block_interval - elapsed))
+        }
+    }
+
+    /// Commit the most recent entry a quorum holds, if it is from the current term
+    ///
+    /// Entries from earlier terms are committed indirectly, as Raft
+    /// cannot tell whether they will survive the next leader change otherwise.
+    fn maybe_advance_commit(&self, state: &mut NodeState, node: &Node) {
+        let mut new_commit = None;
+
+        for index in (state.commit_index + 1)..=state.last_log_index() {
+            if state.get_term_at(index) != state.term {
+                continue;
+            }
+
+            // The leader holds all of its entries
+            let num_replicas = 1 + state
+                .match_index
+                .values()
+                .filter(|match_index| **match_index >= index)
+                .count();
+
+            if self.cluster.is_quorum(num_replicas) {
+                new_commit = Some(index);
+            }
+        }
+
+        if let Some(index) = new_commit {
+            self.commit_entries(state, node, index);
+
+            // Let the followers know right away
+            self.send_heartbeat(state, node);
+            self.notify.notify_one();
+        }
+    }
+
+    /// Commit all entries up to (and including) `index`
+    fn commit_entries(&self, state: &mut NodeState, node: &Node, index: SlotNumber) {
+        let index = index.min(state.last_log_index());
+        let is_voter = self.is_voter(state);
+
+        while state.commit_index < index {
+            state.commit_index += 1;
+            let (_, block) = state.log[(state.commit_index - 1) as usize].clone();
+
+            block.mark_as_accepted();
+            state.finalizations.record(&block, is_voter);
+
+            for txn in block.get_transactions().iter() {
+                node.get_statistics().record_transaction_commit();
+
+                if let Some(client) = node.get_client(txn.get_source()) {
+                    client.notify_transaction_commit();
+                }
+            }
+
+            // A new leader might commit entries that an earlier leader already committed
+            if state.role == RaftRole::Leader {
+                advance_latest_commit(&self.global_ledger.borrow(), node, &block);
+                log::debug!("Leader committed block for slot #{}", state.commit_index);
+            } else {
+                log::trace!(
+                    "{} #{} committed block for slot #{}",
+                    state.role,
+                    node.get_index(),
+                    state.commit_index
+                );
+            }
+        }
+    }
+
+    fn add_to_mempool(
+        &self,
+        state: &mut NodeState,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) {
+        if !state.local_ledger.add_transaction(transaction.clone()) {
+            return;
+        }
+
+        // Forward to other nodes?
+        if source.is_none() {
+            let message = RaftMessage::SendTransaction(transaction);
+            node.broadcast(message.into(), None);
+        }
+
+        if state.role == RaftRole::Leader {
+            let pool_size = state.local_ledger.get_mempool_size();
+
+            // Wake up the leader to start the proposal timer or if the mempool is full
+            if pool_size >= self.max_block_size || pool_size == 1 {
+                self.notify.notify_one();
+            }
+        }
+    }
+
+    fn process_message(
+        &self,
+        state: &mut NodeState,
+        node: &Node,
+        source: ObjectId,
+        message: RaftMessage,
+    ) {
+        if let Some(term) = message.get_term()
+            && term > state.term
+        {
+            self.step_down(state, term);
+        }
+
+        match message {
+            RaftMessage::SendTransaction(txn) => {
+                self.add_to_mempool(state, node, txn, Some(source));
+            }
+            RaftMessage::RequestVote {
+                term,
+                last_log_index,
+                last_log_term,
+            } => {
+                // Learners do not vote
+                if !self.is_voter(state) {
+                    return;
+                }
+
+                let granted = term == state.term
+                    && state.voted_for.is_none_or(|candidate| candidate == source)
+                    && state.is_up_to_date(last_log_index, last_log_term);
+
+                if granted {
+                    state.voted_for = Some(source);
+                    self.reset_election_deadline(state);
+                }
+
+                let message = RaftMessage::Vote {
+                    term: state.term,
+                    granted,
+                };
+                node.send_to(&source, message);
+            }
+            RaftMessage::Vote { term, granted } => {
+                if granted && term == state.term && self.cluster.is_voter(&source) {
+                    state.votes.insert(source);
+                    self.maybe_become_leader(state, node);
+                }
+            }
+            RaftMessage::AppendEntries {
+                term,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                commit_index,
+            } => {
+                if term < state.term {
+                    let message = RaftMessage::AppendResponse {
+                        term: state.term,
+                        success: false,
+                        match_index: state.commit_index,
+                    };
+                    node.send_to(&source, message);
+                    return;
+                }
+
+                // There is a leader for this term
+                if state.role == RaftRole::Candidate {
+                    state.role = RaftRole::Follower;
+                    self.notify.notify_one();
+                }
+                self.reset_election_deadline(state);
+
+                let is_consistent = prev_log_index <= state.last_log_index()
+                    && state.get_term_at(prev_log_index) == prev_log_term;
+
+                if !is_consistent {
+                    log::trace!(
+                        "Node #{} is missing entries before slot #{}",
+                        node.get_index(),
+                        prev_log_index + 1
+                    );
+
+                    let message = RaftMessage::AppendResponse {
+                        term: state.term,
+                        success: false,
+                        match_index: state.commit_index,
+                    };
+                    node.send_to(&source, message);
+                    return;
+                }
+
+                let has_entries = !entries.is_empty();
+                let last_new_index = prev_log_index + entries.len() as SlotNumber;
+
+                for (offset, entry) in entries.into_iter().enumerate() {
+                    let index = prev_log_index + 1 + offset as SlotNumber;
+
+                    if index <= state.last_log_index() {
+                        if state.get_term_at(index) == entry.0 {
+                            continue;
+                        }
+                        state.truncate_log(index);
+                    }
+
+                    state.append_entry(entry);
+                }
+
+                if commit_index > state.commit_index {
+                    self.commit_entries(state, node, commit_index.min(last_new_index));
+                }
+
+                // Heartbeats are not acknowledged
+                if has_entries {
+                    let message = RaftMessage::AppendResponse {
+                        term: state.term,
+                        success: true,
+                        match_index: last_new_index,
+                    };
+                    node.send_to(&source, message);
+                }
+            }
+            RaftMessage::AppendResponse {
+                term,
+                success,
+                match_index,
+            } => {
+                if state.role != RaftRole::Leader || term != state.term {
+                    return;
+                }
+
+                if success {
+                    if self.cluster.is_voter(&source) {
+                        let entry = state.match_index.entry(source).or_default();
+                        *entry = (*entry).max(match_index);
+                        self.maybe_advance_commit(state, node);
+                    }
+                } else {
+                    // Replicate everything after the peer's commit index again
+                    state.next_index.insert(source, match_index + 1);
+                    self.send_entries(state, node, source);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl NodeLogic for RaftNodeLogic {
+    fn init(&self, node: Rc<Node>) {
+        self.cluster
+            .register_node(self.node_id, node.get_identifier());
+    }
+
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
+    async fn run(&self, node: Rc<Node>, _is_mining: bool) {
+        // Learners only react to messages
+        if self.faulty || !self.is_voter(&self.state.borrow()) {
+            return;
+        }
+
+        self.reset_election_deadline(&mut self.state.borrow_mut());
+
+        loop {
+            let wait_time = {
+                let mut state = self.state.borrow_mut();
+                let now = asim::time::now();
+
+                if state.role == RaftRole::Leader {
+                    match self.can_propose_block(&state) {
+                        Ok(()) => {
+                            self.propose_block(&mut state, &node);
+                            continue;
+                        }
+                        Err(propose_wait) => {
+                            let elapsed = now - state.last_heartbeat;
+                            if elapsed >= self.heartbeat_interval {
+                                self.send_heartbeat(&mut state, &node);
+                                continue;
+                            }
+
+                            let heartbeat_wait = self.heartbeat_interval - elapsed;
+                            match propose_wait {
+                                Some(wait) if wait < heartbeat_wait => wait,
+                                _ => heartbeat_wait,
+                            }
+                        }
+                    }
+                } else if now >= state.election_deadline {
+                    self.start_election(&mut state, &node);
+                    continue;
+                } else {
+                    state.election_deadline - now
+                }
+            };
+
+            // Wait for the next timeout, or until the role changes or transactions arrive
+            tokio::select! {
+                _ = asim::time::sleep(wait_time) => {},
+                _ = self.notify.notified() => {},
+            }
+        }
+    }
+
+    fn add_transaction(
+        &self,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) -> bool {
+        let mut state = self.state.borrow_mut();
+        self.add_to_mempool(&mut state, node, transaction, source);
+        true
+    }
+
+    fn get_mempool(&self) -> Vec<TransactionId> {
+        self.state.borrow().local_ledger.get_mempool()
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let state = self.state.borrow();
+
+        let mut usage = MemoryUsage::default();
+        state.local_ledger.add_memory_usage(&mut usage);
+        usage.add("log", memory::vec_size(&state.log));
+        usage.add(
+            "replication",
+            memory::map_size(&state.next_index) + memory::map_size(&state.match_index),
+        );
+        usage.add("votes", memory::set_size(&state.votes));
+        usage
+    }
+
+    /// Returns the latest block this node committed
+    fn get_chain_head(&self) -> Option<(BlockId, u64)> {
+        let state = self.state.borrow();
+        if state.commit_index == 0 {
+            return Some((GENESIS_BLOCK, GENESIS_HEIGHT));
+        }
+
+        let (_, block) = &state.log[(state.commit_index - 1) as usize];
+        Some((*block.get_identifier(), block.get_height()))
+    }
+
+    fn get_account_state(&self, account: &AccountId) -> Option<AccountState> {
+        let state = self.state.borrow();
+        if state.commit_index == 0 {
+            let global_ledger = self.global_ledger.borrow();
+            return get_account_state(global_ledger.get_genesis_state(), account);
+        }
+
+        let (_, block) = &state.log[(state.commit_index - 1) as usize];
+        get_account_state(block.get_state(), account)
+    }
+
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
+    fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
+        let message: RaftMessage = message.try_into().expect("Not a Raft message");
+
+        // Faulty nodes do not take part in consensus
+        if self.faulty {
+            return;
+        }
+
+        let mut state = self.state.borrow_mut();
+        self.process_message(&mut state, node, source, message);
+    }
+}
+
+impl RaftNodeLogic {
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn new(
+        global_ledger: RcCell<ConventionalGlobalLedger>,
+        cluster: Rc<RaftCluster>,
+        finalizations: Rc<FinalizationTracker>,
+        max_block_size: u32,
+        max_block_interval: Duration,
+        election_timeout: u64,
+        node_id: NodeIndex,
+        faulty: bool,
+    ) -> Self {
+        let role = if cluster.voters_by_index[node_id as usize] {
+            RaftRole::Follower
+        } else {
+            RaftRole::Learner
+        };
+
+        log::debug!("Created Raft node with role {role}");
+
+        let state = RefCell::new(NodeState {
+            role,
+            term: 0,
+            voted_for: None,
+            votes: Default::default(),
+            log: vec![],
+            commit_index: 0,
+            next_index: Default::default(),
+            match_index: Default::default(),
+            election_deadline: Time::from_millis(0),
+            last_heartbeat: Time::from_millis(0),
+            last_block_time: Time::from_millis(0),
+            local_ledger: ConventionalNodeLedger::new(),
+            finalizations,
+        });
+
+        // Leaders have to send heartbeats well before followers time out
+        let heartbeat_interval = Duration::from_millis((election_timeout / 4).max(1));
+
+        Self {
+            state,
+            global_ledger,
+            cluster,
+            notify: Notify::new(),
+            node_id,
+            max_block_size,
+            max_block_interval,
+            election_timeout,
+            heartbeat_interval,
+            faulty,
+        }
+    }
+}
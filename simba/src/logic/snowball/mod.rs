@@ -101,12 +101,16 @@ impl GlobalLogic for SnowballGlobalLogic {
             num_transactions: 1,
             elapsed,
             avg_block_size: 1.0,
+            avg_duplicate_messages: 0.0,
+            avg_validator_block_propagation: 0.0,
         }
     }
 
     fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool {
         match connectivity {
-            Connectivity::Sparse { .. } => false,
+            Connectivity::Sparse { .. }
+            | Connectivity::RandomRegular { .. }
+            | Connectivity::SmallWorld { .. } => false,
             Connectivity::Full => true,
         }
     }
@@ -19,6 +19,7 @@ use crate::Message;
 use crate::logic::{NodeLogic, Transaction};
 use crate::node::Node;
 use crate::object::{Object, ObjectId};
+use crate::random;
 
 #[derive(Debug, Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 pub enum Color {
@@ -85,7 +86,7 @@ impl NodeState {
         log::trace!("Running SnowballNodeState:start_next_sample()");
         // self.current_candidate is col in paper, not using any col_0 for initial value
         let nodes = node.get_peers(); //get all nodes in network
-        let mut rng = &mut rand::rng();
+        let mut rng = &mut random::rng();
         assert!(sample_size as usize <= nodes.len());
         let sampled_nodes = nodes
             .into_iter()
@@ -161,6 +162,7 @@ impl NodeState {
 impl NodeLogic for SnowballNodeLogic {
     fn init(&self, _node: Rc<Node>) {}
 
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
     async fn run(&self, node: Rc<Node>, _is_mining: bool) {
         log::trace!("Running SnowballNodeLogic:run()");
         let mut candidate_preference = HashMap::new(); // d[] in paper
@@ -221,10 +223,14 @@ impl NodeLogic for SnowballNodeLogic {
         _node: &Node,
         _transaction: Rc<Transaction>,
         _source: Option<ObjectId>,
-    ) {
-        //do nothing for now
+    ) -> bool {
+        false
     }
 
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
         let mut state = self.state.borrow_mut();
         state.handle_message(node, source, message);
@@ -243,7 +249,7 @@ impl SnowballNodeLogic {
         log::debug!("Created SnowballNodeLogic");
 
         // generate a random number between 0 and 3
-        let mut rng = rand::rng();
+        let mut rng = random::rng();
         let random_number: u8 = rng.random_range(0..=2);
         let current_candidate = match random_number {
             1 => Color::Red,
@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use rand::seq::IteratorRandom;
+
+use crate::RcCell;
+use crate::clients::Client;
+use crate::config::{Connectivity, GenesisAccount, TimeoutConfig};
+use crate::failures::Failures;
+use crate::fingerprint::FingerprintBuilder;
+use crate::ledger::{ConventionalBlock, ConventionalGlobalLedger, make_genesis_state};
+use crate::link::Link;
+use crate::logic::{
+    BlockId, GlobalLogic, HASH_SIZE, NUM_SIZE, NodeLogic, SIGNATURE_SIZE, Transaction,
+};
+use crate::memory::MemoryUsage;
+use crate::message::MessageType;
+use crate::metrics::ChainMetrics;
+use crate::node::NodeIndex;
+use crate::object::ObjectId;
+use crate::random;
+
+use asim::time::Duration;
+
+use super::conventional::{FinalizationTracker, get_metrics};
+
+mod node;
+pub use node::SnowmanNodeLogic;
+
+/// Identifies a poll, so that late responses to earlier polls can be ignored
+pub type PollId = u64;
+
+#[derive(Clone, Debug)]
+pub enum SnowmanMessage {
+    SendTransaction(Rc<Transaction>),
+    /// A new block for the next height
+    Propose(Rc<ConventionalBlock>),
+    /// Asks for the receiver's preferred block at the given height
+    Query {
+        height: u64,
+        poll: PollId,
+        preference: BlockId,
+    },
+    /// Contains `None` if the sender has no preference for this height (yet)
+    QueryResponse {
+        height: u64,
+        poll: PollId,
+        preference: Option<BlockId>,
+    },
+}
+
+impl SnowmanMessage {
+    pub fn get_size(&self) -> u64 {
+        let body_size = match self {
+            Self::SendTransaction(_) => 0,
+            Self::Propose(block) => block.get_size(),
+            Self::Query { .. } | Self::QueryResponse { .. } => HASH_SIZE + 2 * NUM_SIZE,
+        };
+
+        body_size + SIGNATURE_SIZE
+    }
+
+    pub fn get_type(&self) -> MessageType {
+        match self {
+            Self::SendTransaction(_) => MessageType::Transaction,
+            Self::Propose(_) => MessageType::Block,
+            Self::Query { .. } | Self::QueryResponse { .. } => MessageType::Other,
+        }
+    }
+}
+
+/// The parameters of each Snowball instance (using the names of the Avalanche paper)
+#[derive(Clone, Copy, Debug)]
+struct SnowballParameters {
+    /// Consecutive successful polls needed to accept a block: beta
+    acceptance_threshold: u32,
+    /// Validators to query in every poll: k
+    sample_size: usize,
+    /// Responses needed for a poll to succeed: alpha
+    query_threshold: u32,
+}
+
+/// The nodes that propose blocks and answer queries
+///
+/// Non-mining nodes accept blocks by querying validators, but are never queried themselves.
+struct ValidatorSet {
+    /// All validators (including faulty ones), ordered by node index
+    by_index: Vec<NodeIndex>,
+    /// Identifiers of all validators, filled in as nodes are created (in ascending order)
+    identifiers: RefCell<Vec<ObjectId>>,
+}
+
+impl ValidatorSet {
+    fn register_node(&self, node_id: NodeIndex, identifier: ObjectId) {
+        if self.by_index.contains(&node_id) {
+            let mut identifiers = self.identifiers.borrow_mut();
+            let pos = identifiers.partition_point(|other| *other < identifier);
+            identifiers.insert(pos, identifier);
+        }
+    }
+
+    fn is_validator(&self, node_id: NodeIndex) -> bool {
+        self.by_index.contains(&node_id)
+    }
+
+    /// Picks `sample_size` random validators other than `own_id`
+    fn sample(&self, own_id: &ObjectId, sample_size: usize) -> Vec<ObjectId> {
+        self.identifiers
+            .borrow()
+            .iter()
+            .filter(|identifier| *identifier != own_id)
+            .copied()
+            .choose_multiple(&mut random::rng(), sample_size)
+    }
+
+    /// Who proposes the block at the given height
+    /// Every time nodes give up waiting for a proposal, the next validator takes over
+    fn get_proposer(&self, height: u64, attempt: u32) -> NodeIndex {
+        let offset = height + attempt as u64;
+        self.by_index[(offset % (self.by_index.len() as u64)) as usize]
+    }
+}
+
+pub struct SnowmanGlobalLogic {
+    global_ledger: RcCell<ConventionalGlobalLedger>,
+
+    //Parameters
+    max_block_size: u32,
+    max_block_interval: Duration,
+    parameters: SnowballParameters,
+    validators: Rc<ValidatorSet>,
+    finalizations: Rc<FinalizationTracker>,
+    /// Faulty nodes do not take part in consensus, ordered by node index
+    faulty_nodes: Vec<bool>,
+}
+
+impl SnowmanGlobalLogic {
+    /// Mining nodes (as given by `mining_nodes`) are validators
+    ///
+    /// Like in Snowball, the sample size is relative to the number of validators,
+    /// and the query threshold is relative to the sample size.
+    #[allow(clippy::too_many_arguments)]
+    pub fn instantiate(
+        failures: &Failures,
+        max_block_size: u32,
+        max_block_interval: u64,
+        genesis: &[GenesisAccount],
+        acceptance_threshold: u32,
+        sample_size_weighted: f64,
+        query_threshold_weighted: f64,
+        mining_nodes: Vec<bool>,
+    ) -> Rc<dyn GlobalLogic> {
+        let faulty_nodes: Vec<bool> = (0..mining_nodes.len())
+            .map(|idx| failures.is_faulty(&(idx as NodeIndex)))
+            .collect();
+
+        let validators: Vec<NodeIndex> = mining_nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, is_mining)| **is_mining)
+            .map(|(idx, _)| idx as NodeIndex)
+            .collect();
+        let num_validators = validators.len();
+        let num_correct_validators = validators
+            .iter()
+            .filter(|idx| !faulty_nodes[**idx as usize])
+            .count() as u32;
+
+        // Validators never query themselves
+        let sample_size = ((num_validators as f64 * sample_size_weighted).ceil() as usize)
+            .min(num_validators.saturating_sub(1));
+        assert!(sample_size > 0, "Snowman needs at least two validators");
+
+        let query_threshold = (sample_size as f64 * query_threshold_weighted).ceil() as u32;
+
+        log::info!(
+            "Snowman set up with {num_validators} validators, sampling {sample_size} of them, and requiring {query_threshold} votes per poll"
+        );
+
+        let parameters = SnowballParameters {
+            acceptance_threshold,
+            sample_size,
+            query_threshold,
+        };
+
+        let validators = Rc::new(ValidatorSet {
+            by_index: validators,
+            identifiers: Default::default(),
+        });
+
+        let global_ledger = Rc::new(RefCell::new(ConventionalGlobalLedger::new(
+            make_genesis_state(genesis),
+        )));
+
+        let finalizations = Rc::new(FinalizationTracker::new(
+            failures.num_correct_nodes(),
+            num_correct_validators,
+        ));
+
+        Rc::new(Self {
+            global_ledger,
+            max_block_size,
+            max_block_interval: Duration::from_millis(max_block_interval),
+            parameters,
+            validators,
+            finalizations,
+            faulty_nodes,
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl GlobalLogic for SnowmanGlobalLogic {
+    fn new_node_logic(&self, node_id: NodeIndex) -> Rc<dyn NodeLogic> {
+        Rc::new(SnowmanNodeLogic::new(
+            self.global_ledger.clone(),
+            self.validators.clone(),
+            self.finalizations.clone(),
+            self.parameters,
+            self.max_block_size,
+            self.max_block_interval,
+            node_id,
+            self.faulty_nodes[node_id as usize],
+        ))
+    }
+
+    fn get_metrics(
+        &self,
+        timeout: TimeoutConfig,
+        clients: &[Rc<Client>],
+        links: &BTreeMap<ObjectId, Rc<Link>>,
+    ) -> ChainMetrics {
+        get_metrics(
+            &self.global_ledger.borrow(),
+            &self.finalizations,
+            timeout,
+            clients,
+            links,
+        )
+    }
+
+    fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool {
+        match connectivity {
+            Connectivity::Sparse { .. }
+            | Connectivity::RandomRegular { .. }
+            | Connectivity::SmallWorld { .. } => false,
+            Connectivity::Full => true,
+        }
+    }
+
+    async fn wait_for_blocks(&self, blocks: u64) {
+        let commit_height = self.global_ledger.borrow().get_commit_height();
+        commit_height.wait_for(blocks).await;
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        self.global_ledger.borrow().get_memory_usage()
+    }
+
+    fn add_to_fingerprint(&self, fingerprint: &mut FingerprintBuilder) {
+        self.global_ledger.borrow().add_to_fingerprint(fingerprint);
+    }
+}
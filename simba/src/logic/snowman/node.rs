@@ -0,0 +1,630 @@
+use crate::ledger::{
+    ConventionalBlock, ConventionalGlobalLedger, ConventionalNodeLedger, get_account_state,
+};
+use crate::logic::conventional::advance_latest_commit;
+use crate::logic::{
+    AccountId, AccountState, Block, BlockId, GENESIS_BLOCK, GENESIS_HEIGHT, NodeLogic, Transaction,
+    TransactionId,
+};
+use crate::memory::{self, MemoryUsage};
+use crate::node::{Node, NodeIndex};
+use crate::object::{Object, ObjectId};
+use crate::random;
+use crate::{Message, RcCell};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use asim::sync::Notify;
+use asim::time::{Duration, Time};
+
+use super::{FinalizationTracker, PollId, SnowballParameters, SnowmanMessage, ValidatorSet};
+
+/// A Snowball instance deciding between the blocks proposed for one height
+struct HeightState {
+    /// All known blocks for this height that extend the last accepted block
+    candidates: HashMap<BlockId, Rc<ConventionalBlock>>,
+    /// The block this node currently votes for: col in the paper
+    preference: Option<BlockId>,
+    /// Number of successful polls for each block: d[] in the paper
+    confidence: HashMap<BlockId, u32>,
+    /// The block the last successful poll was for: lastcol in the paper
+    last_successful: Option<BlockId>,
+    /// cnt in the paper
+    consecutive_successes: u32,
+    /// When the node started waiting for a proposal
+    waiting_since: Time,
+    /// How often the node gave up waiting for a proposal
+    attempt: u32,
+}
+
+impl HeightState {
+    fn new() -> Self {
+        Self {
+            candidates: Default::default(),
+            preference: None,
+            confidence: Default::default(),
+            last_successful: None,
+            consecutive_successes: 0,
+            waiting_since: asim::time::now(),
+            attempt: 0,
+        }
+    }
+
+    fn add_candidate(&mut self, block: Rc<ConventionalBlock>) {
+        let block_id = *block.get_identifier();
+        if self.preference.is_none() {
+            self.preference = Some(block_id);
+        }
+        self.candidates.insert(block_id, block);
+    }
+}
+
+/// A query that was sent to a sample of validators
+struct Poll {
+    id: PollId,
+    height: u64,
+    /// The sampled validators and their responses (if any)
+    responses: HashMap<ObjectId, Option<Option<BlockId>>>,
+    deadline: Time,
+}
+
+impl Poll {
+    fn is_complete(&self) -> bool {
+        self.responses.values().all(|response| response.is_some())
+    }
+}
+
+struct NodeState {
+    /// All accepted blocks; the block at position `i` has height `i+1`
+    chain: Vec<Rc<ConventionalBlock>>,
+    height: HeightState,
+    /// Blocks for later heights that arrived early
+    future_blocks: Vec<Rc<ConventionalBlock>>,
+
+    poll: Option<Poll>,
+    next_poll_id: PollId,
+
+    /// The height and attempt this node proposed a block for most recently
+    last_proposal: Option<(u64, u32)>,
+    last_block_time: Time,
+
+    local_ledger: ConventionalNodeLedger,
+    finalizations: Rc<FinalizationTracker>,
+}
+
+pub struct SnowmanNodeLogic {
+    state: RefCell<NodeState>,
+    global_ledger: RcCell<ConventionalGlobalLedger>,
+    validators: Rc<ValidatorSet>,
+    /// Wakes up the main loop if there are new blocks, transactions, or responses
+    notify: Notify,
+
+    //Parameters
+    node_id: NodeIndex,
+    parameters: SnowballParameters,
+    max_block_size: u32,
+    max_block_interval: Duration,
+    /// How long to wait for responses to a query
+    query_timeout: Duration,
+    /// How long to wait for a proposal before the next validator takes over
+    proposal_timeout: Duration,
+    /// Faulty nodes ignore all consensus messages
+    faulty: bool,
+}
+
+impl NodeState {
+    /// The height this node is currently deciding on
+    fn current_height(&self) -> u64 {
+        self.chain.len() as u64 + 1
+    }
+
+    fn last_accepted_id(&self) -> BlockId {
+        self.chain
+            .last()
+            .map_or(GENESIS_BLOCK, |block| *block.get_identifier())
+    }
+
+    /// Returns the block this node votes for at the given height (if any)
+    fn get_preference(&self, height: u64) -> Option<BlockId> {
+        let current_height = self.current_height();
+
+        if height < current_height {
+            Some(*self.chain[(height - 1) as usize].get_identifier())
+        } else if height == current_height {
+            self.height.preference
+        } else {
+            None
+        }
+    }
+
+    /// Only blocks that extend the last accepted block are considered
+    fn add_block(&mut self, block: Rc<ConventionalBlock>) {
+        let height = block.get_height();
+        let current_height = self.current_height();
+
+        if height > current_height {
+            self.future_blocks.push(block);
+        } else if height == current_height && *block.get_parent_id() == self.last_accepted_id() {
+            self.height.add_candidate(block);
+        }
+    }
+}
+
+impl SnowmanNodeLogic {
+    fn is_validator(&self) -> bool {
+        self.validators.is_validator(self.node_id)
+    }
+
+    fn add_to_mempool(
+        &self,
+        state: &mut NodeState,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) {
+        if !state.local_ledger.add_transaction(transaction.clone()) {
+            return;
+        }
+
+        // Forward to other nodes?
+        if source.is_none() {
+            let message = SnowmanMessage::SendTransaction(transaction);
+            node.broadcast(message.into(), None);
+        }
+
+        let pool_size = state.local_ledger.get_mempool_size();
+
+        // Only start waiting for a proposal once there is something to propose
+        if pool_size == 1 && state.height.candidates.is_empty() {
+            state.height.waiting_since = asim::time::now();
+        }
+
+        if pool_size == 1 || pool_size >= self.max_block_size {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Is it our turn to propose and did we not do so already?
+    fn should_propose_block(&self, state: &NodeState) -> bool {
+        let height = state.current_height();
+        let attempt = state.height.attempt;
+
+        state.height.candidates.is_empty()
+            && self.validators.get_proposer(height, attempt) == self.node_id
+            && state.last_proposal != Some((height, attempt))
+    }
+
+    /// Do we have enough pending transactions or did enough time elapse?
+    fn can_propose_block(&self, state: &NodeState) -> Result<(), Option<Duration>> {
+        let mempool_size = state.local_ledger.get_mempool_size();
+        if mempool_size == 0 {
+            log::trace!("Cannot propose yet: no transactions");
+            return Err(None);
+        }
+
+        let elapsed = asim::time::now() - state.last_block_time;
+        if elapsed >= self.max_block_interval || mempool_size >= self.max_block_size {
+            Ok(())
+        } else {
+            Err(Some(self.max_block_interval - elapsed))
+        }
+    }
+
+    fn propose_block(&self, state: &mut NodeState, node: &Node) {
+        let height = state.current_height();
+        log::debug!("Node #{} proposes block at height {height}", self.node_id);
+
+        state.last_proposal = Some((height, state.height.attempt));
+
+        let parent = state.last_accepted_id();
+        let block_id = random::random();
+        let creation_time = asim::time::now();
+
+        let transactions = state
+            .local_ledger
+            .get_transactions_from_mempool(self.max_block_size);
+        assert!(!transactions.is_empty());
+
+        // Snowman does not execute transactions, so blocks keep the state of their parent
+        let block_state = {
+            let global_ledger = self.global_ledger.borrow();
+            match state.chain.last() {
+                Some(parent) => parent.get_state().deep_clone().freeze(),
+                None => global_ledger.get_genesis_state().deep_clone().freeze(),
+            }
+        };
+
+        let block = Rc::new(ConventionalBlock::new(
+            block_id,
+            parent,
+            self.node_id,
+            transactions,
+            creation_time,
+            height,
+            block_state,
+        ));
+
+        self.global_ledger
+            .borrow_mut()
+            .add_block(block_id, block.clone());
+
+        node.broadcast(SnowmanMessage::Propose(block.clone()).into(), None);
+        state.add_block(block);
+    }
+
+    fn start_poll(&self, state: &mut NodeState, node: &Node) {
+        let preference = state.height.preference.expect("No preference");
+        let height = state.current_height();
+
+        let poll_id = state.next_poll_id;
+        state.next_poll_id += 1;
+
+        let sample = self
+            .validators
+            .sample(&node.get_identifier(), self.parameters.sample_size);
+
+        for peer in sample.iter() {
+            let message = SnowmanMessage::Query {
+                height,
+                poll: poll_id,
+                preference,
+            };
+            node.send_to(peer, message);
+        }
+
+        state.poll = Some(Poll {
+            id: poll_id,
+            height,
+            responses: sample.into_iter().map(|peer| (peer, None)).collect(),
+            deadline: asim::time::now() + self.query_timeout,
+        });
+    }
+
+    /// Runs one step of Snowball using the responses of the poll
+    /// Missing responses count as votes for no block
+    fn finish_poll(&self, state: &mut NodeState, node: &Node) {
+        let poll = state.poll.take().expect("No poll");
+
+        // The height was decided while waiting for responses
+        if poll.height != state.current_height() {
+            return;
+        }
+
+        let mut votes: HashMap<BlockId, u32> = HashMap::new();
+        for block_id in poll.responses.into_values().flatten().flatten() {
+            *votes.entry(block_id).or_default() += 1;
+        }
+
+        // Sort to keep simulations deterministic
+        let majority = votes
+            .into_iter()
+            .filter(|(block_id, count)| {
+                *count >= self.parameters.query_threshold
+                    && state.height.candidates.contains_key(block_id)
+            })
+            .max_by_key(|(block_id, count)| (*count, *block_id))
+            .map(|(block_id, _)| block_id);
+
+        let height = &mut state.height;
+        let Some(block_id) = majority else {
+            height.consecutive_successes = 0;
+            return;
+        };
+
+        let confidence = {
+            let entry = height.confidence.entry(block_id).or_default();
+            *entry += 1;
+            *entry
+        };
+
+        let preferred_confidence = height
+            .preference
+            .and_then(|preference| height.confidence.get(&preference).copied())
+            .unwrap_or(0);
+        if confidence > preferred_confidence {
+            height.preference = Some(block_id);
+        }
+
+        if height.last_successful == Some(block_id) {
+            height.consecutive_successes += 1;
+        } else {
+            height.last_successful = Some(block_id);
+            height.consecutive_successes = 1;
+        }
+
+        if height.consecutive_successes >= self.parameters.acceptance_threshold {
+            self.accept_block(state, node, block_id);
+        }
+    }
+
+    fn accept_block(&self, state: &mut NodeState, node: &Node, block_id: BlockId) {
+        let block = state
+            .height
+            .candidates
+            .get(&block_id)
+            .expect("No such block")
+            .clone();
+
+        log::trace!(
+            "Node #{} accepted block at height {}",
+            self.node_id,
+            block.get_height()
+        );
+
+        block.mark_as_accepted();
+        state
+            .local_ledger
+            .remove_transactions(block.get_transactions());
+        state.finalizations.record(&block, self.is_validator());
+
+        for txn in block.get_transactions().iter() {
+            node.get_statistics().record_transaction_commit();
+
+            if let Some(client) = node.get_client(txn.get_source()) {
+                client.notify_transaction_commit();
+            }
+        }
+
+        // The first node to accept the block reports the commit
+        advance_latest_commit(&self.global_ledger.borrow(), node, &block);
+
+        state.chain.push(block);
+        state.height = HeightState::new();
+        state.last_block_time = asim::time::now();
+
+        let future_blocks = std::mem::take(&mut state.future_blocks);
+        for block in future_blocks {
+            state.add_block(block);
+        }
+    }
+
+    fn process_message(
+        &self,
+        state: &mut NodeState,
+        node: &Node,
+        source: ObjectId,
+        message: SnowmanMessage,
+    ) {
+        match message {
+            SnowmanMessage::SendTransaction(txn) => {
+                self.add_to_mempool(state, node, txn, Some(source));
+            }
+            SnowmanMessage::Propose(block) => {
+                state.add_block(block);
+                self.notify.notify_one();
+            }
+            SnowmanMessage::Query {
+                height,
+                poll,
+                preference,
+            } => {
+                // Adopt the queried block if we have no preference yet
+                if height == state.current_height()
+                    && state.height.preference.is_none()
+                    && state.height.candidates.contains_key(&preference)
+                {
+                    state.height.preference = Some(preference);
+                    self.notify.notify_one();
+                }
+
+                let message = SnowmanMessage::QueryResponse {
+                    height,
+                    poll,
+                    preference: state.get_preference(height),
+                };
+                node.send_to(&source, message);
+            }
+            SnowmanMessage::QueryResponse {
+                poll, preference, ..
+            } => {
+                let Some(current) = &mut state.poll else {
+                    return;
+                };
+
+                if current.id != poll {
+                    return;
+                }
+
+                // Ignore responses from nodes that were not sampled
+                if let Some(response) = current.responses.get_mut(&source) {
+                    *response = Some(preference);
+                }
+
+                if current.is_complete() {
+                    self.notify.notify_one();
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl NodeLogic for SnowmanNodeLogic {
+    fn init(&self, node: Rc<Node>) {
+        self.validators
+            .register_node(self.node_id, node.get_identifier());
+    }
+
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
+    async fn run(&self, node: Rc<Node>, _is_mining: bool) {
+        if self.faulty {
+            return;
+        }
+
+        loop {
+            let wait_time = {
+                let mut state = self.state.borrow_mut();
+                let now = asim::time::now();
+                let mut wait_time: Option<Duration> = None;
+                let mut wait_for = |duration: Duration| {
+                    if wait_time.is_none_or(|current| duration < current) {
+                        wait_time = Some(duration);
+                    }
+                };
+
+                if let Some((complete, deadline)) = state
+                    .poll
+                    .as_ref()
+                    .map(|poll| (poll.is_complete(), poll.deadline))
+                {
+                    if complete || now >= deadline {
+                        self.finish_poll(&mut state, &node);
+                        continue;
+                    }
+                    wait_for(deadline - now);
+                }
+
+                if self.should_propose_block(&state) {
+                    match self.can_propose_block(&state) {
+                        Ok(()) => {
+                            self.propose_block(&mut state, &node);
+                            continue;
+                        }
+                        Err(Some(propose_wait)) => wait_for(propose_wait),
+                        Err(None) => {}
+                    }
+                }
+
+                if state.poll.is_none() && state.height.preference.is_some() {
+                    self.start_poll(&mut state, &node);
+                    continue;
+                }
+
+                // Let the next validator propose if there is no block for this height
+                if state.height.candidates.is_empty() && state.local_ledger.get_mempool_size() > 0 {
+                    let elapsed = now - state.height.waiting_since;
+                    if elapsed >= self.proposal_timeout {
+                        state.height.attempt += 1;
+                        state.height.waiting_since = now;
+                        log::debug!(
+                            "Node #{} gave up waiting for a proposal at height {}",
+                            self.node_id,
+                            state.current_height()
+                        );
+                        continue;
+                    }
+                    wait_for(self.proposal_timeout - elapsed);
+                }
+
+                wait_time
+            };
+
+            // Wait for the next timeout, or until blocks, transactions, or responses arrive
+            match wait_time {
+                Some(wait_time) => {
+                    tokio::select! {
+                        _ = asim::time::sleep(wait_time) => {},
+                        _ = self.notify.notified() => {},
+                    }
+                }
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    fn add_transaction(
+        &self,
+        node: &Node,
+        transaction: Rc<Transaction>,
+        source: Option<ObjectId>,
+    ) -> bool {
+        let mut state = self.state.borrow_mut();
+        self.add_to_mempool(&mut state, node, transaction, source);
+        true
+    }
+
+    fn get_mempool(&self) -> Vec<TransactionId> {
+        self.state.borrow().local_ledger.get_mempool()
+    }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let state = self.state.borrow();
+
+        let mut usage = MemoryUsage::default();
+        state.local_ledger.add_memory_usage(&mut usage);
+        usage.add("chain", memory::vec_size(&state.chain));
+        usage.add(
+            "candidates",
+            memory::map_size(&state.height.candidates)
+                + memory::map_size(&state.height.confidence)
+                + memory::vec_size(&state.future_blocks),
+        );
+        usage
+    }
+
+    /// Returns the latest block this node accepted
+    fn get_chain_head(&self) -> Option<(BlockId, u64)> {
+        let state = self.state.borrow();
+        match state.chain.last() {
+            Some(block) => Some((*block.get_identifier(), block.get_height())),
+            None => Some((GENESIS_BLOCK, GENESIS_HEIGHT)),
+        }
+    }
+
+    fn get_account_state(&self, account: &AccountId) -> Option<AccountState> {
+        let state = self.state.borrow();
+        match state.chain.last() {
+            Some(block) => get_account_state(block.get_state(), account),
+            None => get_account_state(self.global_ledger.borrow().get_genesis_state(), account),
+        }
+    }
+
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
+    fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
+        let message: SnowmanMessage = message.try_into().expect("Not a Snowman message");
+
+        // Faulty nodes do not take part in consensus
+        if self.faulty {
+            return;
+        }
+
+        let mut state = self.state.borrow_mut();
+        self.process_message(&mut state, node, source, message);
+    }
+}
+
+impl SnowmanNodeLogic {
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn new(
+        global_ledger: RcCell<ConventionalGlobalLedger>,
+        validators: Rc<ValidatorSet>,
+        finalizations: Rc<FinalizationTracker>,
+        parameters: SnowballParameters,
+        max_block_size: u32,
+        max_block_interval: Duration,
+        node_id: NodeIndex,
+        faulty: bool,
+    ) -> Self {
+        let state = RefCell::new(NodeState {
+            chain: vec![],
+            height: HeightState::new(),
+            future_blocks: vec![],
+            poll: None,
+            next_poll_id: 0,
+            last_proposal: None,
+            last_block_time: Time::from_millis(0),
+            local_ledger: ConventionalNodeLedger::new(),
+            finalizations,
+        });
+
+        Self {
+            state,
+            global_ledger,
+            validators,
+            notify: Notify::new(),
+            node_id,
+            parameters,
+            max_block_size,
+            max_block_interval,
+            // Polls should finish well within a block interval
+            query_timeout: max_block_interval,
+            proposal_timeout: 4 * max_block_interval,
+            faulty,
+        }
+    }
+}
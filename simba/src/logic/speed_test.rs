@@ -1,15 +1,17 @@
 use std::cell::RefCell;
 /// Logic that can be used to test the network speed
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::clients::Client;
-use crate::config::{Connectivity, TimeoutConfig};
+use crate::config::{Connectivity, TimeoutConfig, TrafficPattern};
 use crate::link::Link;
 use crate::logic::{ChainMetrics, GlobalLogic, NodeLogic, Transaction};
+use crate::memory::{self, MemoryUsage};
 use crate::message::Message;
 use crate::node::{Node, NodeIndex};
-use crate::object::ObjectId;
+use crate::object::{Object, ObjectId};
+use crate::random;
 
 use asim::time::Duration;
 
@@ -21,10 +23,16 @@ pub struct SpeedTestMessage {
 //TODO use gossip logic and remove this...
 pub struct SpeedTestGlobalLogic {
     send_speed: u64,
+    pattern: TrafficPattern,
+    node_ids: Rc<RefCell<HashMap<NodeIndex, ObjectId>>>,
 }
 
 pub struct SpeedTestNodeLogic {
     send_speed: u64,
+    pattern: TrafficPattern,
+    node_index: NodeIndex,
+    /// Identifiers of all nodes, so that we can address specific ones
+    node_ids: Rc<RefCell<HashMap<NodeIndex, ObjectId>>>,
     known_messages: RefCell<HashSet<u64>>,
 }
 
@@ -42,22 +50,38 @@ impl SpeedTestMessage {
 impl Default for SpeedTestMessage {
     fn default() -> Self {
         Self {
-            uid: rand::random(),
+            uid: random::random(),
         }
     }
 }
 
 impl SpeedTestGlobalLogic {
-    pub fn instantiate(send_speed: u64) -> Rc<dyn GlobalLogic> {
-        Rc::new(Self { send_speed })
+    pub fn instantiate(send_speed: u64, pattern: TrafficPattern) -> Rc<dyn GlobalLogic> {
+        log::info!("Speed test uses the {pattern:?} traffic pattern");
+
+        Rc::new(Self {
+            send_speed,
+            pattern,
+            node_ids: Default::default(),
+        })
+    }
+
+    /// Time between two messages to the same destination
+    pub(crate) fn get_send_delay(send_speed: u64) -> Duration {
+        // How many 1kbyte packet per second?
+        let send_speed = send_speed * 1024;
+        Duration::from_micros(1_000_000 / send_speed)
     }
 }
 
 #[async_trait::async_trait(?Send)]
 impl GlobalLogic for SpeedTestGlobalLogic {
-    fn new_node_logic(&self, _node_index: NodeIndex) -> Rc<dyn NodeLogic> {
+    fn new_node_logic(&self, node_index: NodeIndex) -> Rc<dyn NodeLogic> {
         Rc::new(SpeedTestNodeLogic {
             send_speed: self.send_speed,
+            pattern: self.pattern,
+            node_index,
+            node_ids: self.node_ids.clone(),
             known_messages: Default::default(),
         })
     }
@@ -71,35 +95,121 @@ impl GlobalLogic for SpeedTestGlobalLogic {
         ChainMetrics::default()
     }
 
-    fn is_compatible_with_connectivity(&self, _connectivity: &Connectivity) -> bool {
-        true
+    fn is_compatible_with_connectivity(&self, connectivity: &Connectivity) -> bool {
+        match connectivity {
+            Connectivity::Sparse { .. }
+            | Connectivity::RandomRegular { .. }
+            | Connectivity::SmallWorld { .. } => !self.pattern.needs_full_connectivity(),
+            Connectivity::Full => true,
+        }
     }
 
     async fn wait_for_blocks(&self, _blocks: u64) {
         unimplemented!();
     }
+
+    fn get_memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        usage.add(
+            "known_messages",
+            memory::set_size(&self.known_messages.borrow()),
+        );
+        usage
+    }
+}
+
+impl SpeedTestNodeLogic {
+    fn get_node_id(&self, node_index: NodeIndex) -> ObjectId {
+        *self
+            .node_ids
+            .borrow()
+            .get(&node_index)
+            .expect("No such node")
+    }
+
+    /// The nodes this node sends traffic to
+    /// Returns `None` if it floods the network instead
+    fn get_destinations(&self, node: &Node) -> Option<Vec<ObjectId>> {
+        let destinations = match self.pattern {
+            TrafficPattern::Flood => return None,
+            TrafficPattern::Pair => match self.node_index {
+                0 => vec![self.get_node_id(1)],
+                1 => vec![self.get_node_id(0)],
+                _ => vec![],
+            },
+            TrafficPattern::Star => {
+                if self.node_index == 0 {
+                    node.get_peers()
+                } else {
+                    vec![]
+                }
+            }
+            TrafficPattern::AllToAll => node.get_peers(),
+            TrafficPattern::Incast => {
+                if self.node_index == 0 {
+                    vec![]
+                } else {
+                    vec![self.get_node_id(0)]
+                }
+            }
+        };
+
+        Some(destinations)
+    }
 }
 
 #[async_trait::async_trait(?Send)]
 impl NodeLogic for SpeedTestNodeLogic {
+    #[tracing::instrument(skip(self, node), fields(node = node.get_index()))]
     async fn run(&self, node: Rc<Node>, _is_mining: bool) {
+        let destinations = self.get_destinations(&node);
+
         // Run sender logic?
-        if node.get_index() == 0 {
-            // How many 1kbyte packet per second?
-            let send_speed = self.send_speed * 1024;
-            let send_delay = Duration::from_micros(1_000_000 / send_speed);
-            log::debug!("Sending {send_speed} 1kb packets per second. Send delay is {send_delay}.");
-
-            loop {
-                node.broadcast(SpeedTestMessage::default().into(), None);
-                asim::time::sleep(send_delay).await;
+        let is_sender = match &destinations {
+            Some(destinations) => !destinations.is_empty(),
+            None => self.node_index == 0,
+        };
+
+        if !is_sender {
+            return;
+        }
+
+        let send_delay = SpeedTestGlobalLogic::get_send_delay(self.send_speed);
+        log::debug!(
+            "Sending {} 1kb packets per second. Send delay is {send_delay}.",
+            self.send_speed * 1024
+        );
+
+        loop {
+            match &destinations {
+                Some(destinations) => {
+                    for destination in destinations {
+                        node.send_to(destination, SpeedTestMessage::default());
+                    }
+                }
+                None => node.broadcast(SpeedTestMessage::default().into(), None),
             }
+
+            asim::time::sleep(send_delay).await;
         }
     }
 
-    fn init(&self, _node: Rc<Node>) {}
+    fn init(&self, node: Rc<Node>) {
+        self.node_ids
+            .borrow_mut()
+            .insert(self.node_index, node.get_identifier());
+    }
 
+    #[tracing::instrument(
+        skip(self, node, message),
+        fields(node = node.get_index(), vtime_ms = asim::time::now().to_millis())
+    )]
     fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
+        // Only flooded messages are forwarded
+        if self.pattern != TrafficPattern::Flood {
+            return;
+        }
+
         // Forward to all peers
         let message: SpeedTestMessage = message.try_into().unwrap();
         if self.known_messages.borrow_mut().insert(message.get_uid()) {
@@ -112,7 +222,7 @@ impl NodeLogic for SpeedTestNodeLogic {
         _node: &Node,
         _transction: Rc<Transaction>,
         _source: Option<ObjectId>,
-    ) {
-        unimplemented!();
+    ) -> bool {
+        false
     }
 }
@@ -0,0 +1,66 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Estimated memory used by the data structures of a protocol, by name (in bytes)
+///
+/// Estimates are shallow: they cover the containers and their entries,
+/// but not data behind pointers, such as blocks shared between nodes.
+/// Shared data is reported once by the structure that owns it.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    structures: BTreeMap<String, u64>,
+}
+
+impl MemoryUsage {
+    /// Adds the size of a data structure
+    /// Sizes of structures with the same name (e.g., from different nodes) are summed up
+    pub fn add(&mut self, name: &str, bytes: u64) {
+        *self.structures.entry(name.to_string()).or_default() += bytes;
+    }
+
+    pub fn merge(&mut self, other: &MemoryUsage) {
+        for (name, bytes) in other.structures.iter() {
+            self.add(name, *bytes);
+        }
+    }
+
+    /// Returns zero if there is no such structure
+    pub fn get(&self, name: &str) -> u64 {
+        self.structures.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn get_total(&self) -> u64 {
+        self.structures.values().sum()
+    }
+
+    /// All structures (in alphabetical order) and their sizes
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.structures
+            .iter()
+            .map(|(name, bytes)| (name.as_str(), *bytes))
+    }
+}
+
+/// Hash tables store one control byte per bucket next to each entry
+pub(crate) fn map_size<K, V>(map: &HashMap<K, V>) -> u64 {
+    (map.capacity() * (size_of::<(K, V)>() + 1)) as u64
+}
+
+pub(crate) fn set_size<T>(set: &HashSet<T>) -> u64 {
+    (set.capacity() * (size_of::<T>() + 1)) as u64
+}
+
+/// B-trees store up to eleven entries per node; assume they are two thirds full
+pub(crate) fn btree_set_size<T>(set: &BTreeSet<T>) -> u64 {
+    (set.len() * size_of::<T>() * 3 / 2) as u64
+}
+
+pub(crate) fn vec_size<T>(vec: &Vec<T>) -> u64 {
+    (vec.capacity() * size_of::<T>()) as u64
+}
+
+/// Like `map_size`, but also counts the vectors stored in the map
+pub(crate) fn nested_map_size<K, T>(map: &HashMap<K, Vec<T>>) -> u64 {
+    map_size(map) + map.values().map(vec_size).sum::<u64>()
+}
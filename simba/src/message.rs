@@ -1,5 +1,6 @@
 use crate::logic::{
-    GossipMessage, NakamotoMessage, PbftMessage, SnowballMessage, SpeedTestMessage,
+    GossipMessage, NakamotoMessage, PbftMessage, RaftMessage, SnowballMessage, SnowmanMessage,
+    SpeedTestMessage,
 };
 
 #[derive(PartialEq, Eq, Debug, derive_more::Display)]
@@ -13,10 +14,12 @@ pub enum MessageType {
 pub enum Message {
     Nakamoto(NakamotoMessage),
     PracticalBFT(PbftMessage),
+    Raft(RaftMessage),
     Dummy(DummyMessage),
     SpeedTest(SpeedTestMessage),
     Gossip(GossipMessage),
     Snowball(SnowballMessage),
+    Snowman(SnowmanMessage),
 }
 
 #[derive(Default, Debug, Clone)]
@@ -40,6 +43,12 @@ impl From<PbftMessage> for Message {
     }
 }
 
+impl From<RaftMessage> for Message {
+    fn from(msg: RaftMessage) -> Self {
+        Self::Raft(msg)
+    }
+}
+
 impl From<SpeedTestMessage> for Message {
     fn from(msg: SpeedTestMessage) -> Self {
         Self::SpeedTest(msg)
@@ -52,6 +61,12 @@ impl From<SnowballMessage> for Message {
     }
 }
 
+impl From<SnowmanMessage> for Message {
+    fn from(msg: SnowmanMessage) -> Self {
+        Self::Snowman(msg)
+    }
+}
+
 impl From<DummyMessage> for Message {
     fn from(msg: DummyMessage) -> Self {
         Self::Dummy(msg)
@@ -106,6 +121,18 @@ impl TryInto<PbftMessage> for Message {
     }
 }
 
+impl TryInto<RaftMessage> for Message {
+    type Error = ();
+
+    fn try_into(self) -> Result<RaftMessage, ()> {
+        if let Self::Raft(inner) = self {
+            Ok(inner)
+        } else {
+            Err(())
+        }
+    }
+}
+
 impl TryInto<SnowballMessage> for Message {
     type Error = ();
 
@@ -118,6 +145,18 @@ impl TryInto<SnowballMessage> for Message {
     }
 }
 
+impl TryInto<SnowmanMessage> for Message {
+    type Error = ();
+
+    fn try_into(self) -> Result<SnowmanMessage, ()> {
+        if let Self::Snowman(inner) = self {
+            Ok(inner)
+        } else {
+            Err(())
+        }
+    }
+}
+
 impl TryInto<DummyMessage> for Message {
     type Error = ();
 
@@ -137,8 +176,10 @@ impl asim::network::NetworkMessage for Message {
             Self::Dummy(_) => 0,
             Self::Gossip(msg) => msg.get_size(),
             Self::Snowball(msg) => msg.get_size(),
+            Self::Snowman(msg) => msg.get_size(),
             Self::Nakamoto(msg) => msg.get_size(),
             Self::PracticalBFT(msg) => msg.get_size(),
+            Self::Raft(msg) => msg.get_size(),
             Self::SpeedTest(msg) => msg.get_size(),
         }
     }
@@ -150,8 +191,10 @@ impl Message {
             Self::SpeedTest(_) | Self::Dummy(_) => MessageType::Other,
             Self::Gossip(msg) => msg.get_type(),
             Self::Snowball(msg) => msg.get_type(),
+            Self::Snowman(msg) => msg.get_type(),
             Self::Nakamoto(msg) => msg.get_type(),
             Self::PracticalBFT(msg) => msg.get_type(),
+            Self::Raft(msg) => msg.get_type(),
         }
     }
 }
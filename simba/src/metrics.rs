@@ -1,16 +1,24 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::latency::LatencyHistogram;
+use crate::link::LinkDirection;
+use crate::logic::{AccountId, BlockId};
 use crate::node::NodeIndex;
+use crate::object::ObjectId;
+use crate::stats::StatisticType;
 
-use asim::time::Duration;
+use asim::time::{Duration, Time};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display, Serialize, Deserialize)]
 pub enum MetricType {
     Chain(ChainMetricType),
     Network(NetworkMetricType),
+    /// Sampled every second after warmup
+    Statistic(StatisticType),
 }
 
 #[derive(
@@ -40,6 +48,10 @@ pub enum ChainMetricType {
     BlockPropagationDelay,
     BlockSize,
     NumNetworkMessages,
+    /// How many redundant messages does a node receive for each block?
+    DuplicateMessages,
+    /// Same as `BlockPropagationDelay`, but only considers mining nodes (or validators)
+    ValidatorPropagationDelay,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -48,11 +60,24 @@ pub enum NetworkMetricType {
     NodeBandwidth(NodeIndex),
     /// How many other nodes a node is connected to
     NodePeerCount(NodeIndex),
-    /// How many nodes are there in total?
+    /// How many nodes participate in block creation?
     NumMiningNodes,
+    /// How many nodes only relay blocks and transactions?
     NumNonMiningNodes,
     /// How many links are there in total?
     NumLinks,
+    /// The average number of peers per node
+    AverageNodeDegree,
+    /// The maximum number of hops between any two (connected) nodes
+    Diameter,
+    /// The average latency of the fastest path between two nodes (in milliseconds)
+    AverageShortestPathLatency,
+    /// The number of bytes sent over all links, in both directions
+    TotalBytesTransferred,
+    /// The bandwidth used in one direction of a link in bits/s
+    /// (link identifiers are generated at runtime, so this cannot be used in config files)
+    #[serde(skip)]
+    LinkUtilization(ObjectId, LinkDirection),
 }
 
 impl fmt::Display for NetworkMetricType {
@@ -63,6 +88,13 @@ impl fmt::Display for NetworkMetricType {
             Self::NumMiningNodes => write!(fmt, "Number of Mining Nodes"),
             Self::NumNonMiningNodes => write!(fmt, "Number of Non-Mining Nodes"),
             Self::NumLinks => write!(fmt, "Number of Network Links"),
+            Self::AverageNodeDegree => write!(fmt, "Average Node Degree"),
+            Self::Diameter => write!(fmt, "Network Diameter"),
+            Self::AverageShortestPathLatency => write!(fmt, "Average Shortest Path Latency"),
+            Self::TotalBytesTransferred => write!(fmt, "Total Bytes Transferred"),
+            Self::LinkUtilization(link_id, direction) => {
+                write!(fmt, "Utilization of Link {link_id} ({direction})")
+            }
         }
     }
 }
@@ -87,6 +119,343 @@ pub struct ChainMetrics {
     pub elapsed: Duration,
     pub avg_block_size: f64,
     pub num_network_messages: u64,
+    /// Average number of redundant messages a node received per block
+    /// (only tracked by gossip)
+    pub avg_duplicate_messages: f64,
+    /// Average time for a block to reach all (correct) mining nodes or validators
+    /// (only tracked by gossip and PBFT)
+    pub avg_validator_block_propagation: f64,
+}
+
+/// The revenue of a mining pool, assuming every block is worth the same reward
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct MiningPoolStatistics {
+    pub num_members: u32,
+    pub num_withholding_members: u32,
+    /// Blocks in the longest chain that were mined by the pool
+    pub blocks_in_chain: u64,
+    /// Blocks found by withholding members that were never published
+    pub withheld_blocks: u64,
+    /// How many blocks of the longest chain the pool should have mined
+    /// based on its share of the mining power
+    pub expected_blocks: f64,
+}
+
+impl MiningPoolStatistics {
+    /// The fraction of its expected revenue the pool did not receive
+    pub fn get_revenue_loss(&self) -> f64 {
+        if self.expected_blocks == 0.0 {
+            0.0
+        } else {
+            1.0 - (self.blocks_in_chain as f64) / self.expected_blocks
+        }
+    }
+}
+
+/// How often nodes rejected blocks because they follow different protocol versions
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct ChainSplitStatistics {
+    /// Nodes that did not adopt at least one of the protocol upgrades
+    pub num_legacy_nodes: u32,
+    /// Blocks that nodes rejected because they violate their rules (counted once per node)
+    pub rejected_blocks: u64,
+    /// When the first block was rejected (`None` if the chain never split)
+    pub first_rejection: Option<Time>,
+    /// When the last block was rejected
+    pub last_rejection: Option<Time>,
+}
+
+impl ChainSplitStatistics {
+    /// How long nodes kept rejecting each other's blocks
+    pub fn get_split_duration(&self) -> Duration {
+        match (self.first_rejection, self.last_rejection) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// How often nodes banned peers for sending data they did not ask for or that violates their rules
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct PeerBanStatistics {
+    /// Penalties nodes gave to their peers
+    pub num_penalties: u64,
+    /// Peers banned (counted once per node)
+    pub num_bans: u64,
+    /// Bans of peers that are not faulty
+    pub false_positive_bans: u64,
+    /// When the first peer was banned (`None` if no peer was banned)
+    pub first_ban: Option<Time>,
+}
+
+impl PeerBanStatistics {
+    /// The fraction of bans that hit correct peers
+    pub fn get_false_positive_rate(&self) -> f64 {
+        if self.num_bans == 0 {
+            0.0
+        } else {
+            (self.false_positive_bans as f64) / (self.num_bans as f64)
+        }
+    }
+}
+
+/// How the base fee of an EIP-1559-style fee market evolved along the longest chain
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct FeeMarketStatistics {
+    /// The base fee of each block in the longest chain (oldest first)
+    pub base_fees: Vec<u64>,
+    /// The number of transactions in each block of the longest chain (oldest first)
+    pub block_sizes: Vec<u32>,
+    /// How long each transaction in the longest chain waited until it was included in a block
+    pub inclusion_delays: Vec<Duration>,
+    /// The block size the base fee steers towards
+    pub target_block_size: u32,
+    /// Transactions per second the clients would issue if every transaction committed instantly
+    pub demand: f64,
+}
+
+impl FeeMarketStatistics {
+    pub fn get_avg_base_fee(&self) -> f64 {
+        if self.base_fees.is_empty() {
+            0.0
+        } else {
+            (self.base_fees.iter().sum::<u64>() as f64) / (self.base_fees.len() as f64)
+        }
+    }
+
+    /// The standard deviation of the relative base fee change between two consecutive blocks
+    pub fn get_base_fee_volatility(&self) -> f64 {
+        let changes: Vec<f64> = self
+            .base_fees
+            .windows(2)
+            .filter(|fees| fees[0] > 0)
+            .map(|fees| (fees[1] as f64) / (fees[0] as f64) - 1.0)
+            .collect();
+
+        if changes.is_empty() {
+            return 0.0;
+        }
+
+        let mean = changes.iter().sum::<f64>() / (changes.len() as f64);
+        let variance = changes
+            .iter()
+            .map(|change| (change - mean).powi(2))
+            .sum::<f64>()
+            / (changes.len() as f64);
+
+        variance.sqrt()
+    }
+
+    /// How full blocks were on average, relative to the target block size
+    pub fn get_avg_utilization(&self) -> f64 {
+        if self.block_sizes.is_empty() || self.target_block_size == 0 {
+            0.0
+        } else {
+            let total: u64 = self.block_sizes.iter().map(|size| *size as u64).sum();
+            (total as f64) / ((self.block_sizes.len() as f64) * (self.target_block_size as f64))
+        }
+    }
+
+    /// The average inclusion delay (in milliseconds)
+    pub fn get_avg_inclusion_delay(&self) -> f64 {
+        if self.inclusion_delays.is_empty() {
+            0.0
+        } else {
+            let total: f64 = self
+                .inclusion_delays
+                .iter()
+                .map(|delay| delay.as_millis_f64())
+                .sum();
+            total / (self.inclusion_delays.len() as f64)
+        }
+    }
+}
+
+/// How a single block spread through the network
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlockPropagation {
+    pub identifier: BlockId,
+    /// The number of nodes the block has to reach
+    pub num_nodes: u32,
+    /// Time from creation until each node saw the block (in ascending order)
+    pub delays: Vec<Duration>,
+    /// Number of nodes that received the block after a given number of hops (index = hops)
+    pub hop_counts: Vec<u32>,
+    /// When each node first saw the block (in ascending order)
+    /// Only set for sampled blocks
+    pub first_seen: Option<Vec<(ObjectId, Time)>>,
+}
+
+impl BlockPropagation {
+    /// The time until the given fraction (0 to 1) of nodes saw the block
+    /// Returns `None` if the block did not reach that many nodes (yet)
+    pub fn get_delay_until(&self, fraction: f64) -> Option<Duration> {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "Invalid fraction {fraction}"
+        );
+
+        let num_nodes = ((self.num_nodes as f64) * fraction).ceil() as usize;
+        self.delays.get(num_nodes.max(1) - 1).copied()
+    }
+}
+
+/// Which node a client is attached to and how its transactions fared
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClientInfo {
+    pub account: AccountId,
+    pub node: NodeIndex,
+    /// Only set if clients are assigned by region
+    pub region: Option<u32>,
+    /// The number of committed transactions
+    pub num_transactions: u64,
+    /// Average commit latency (in milliseconds)
+    pub avg_latency: f64,
+}
+
+/// The traffic that arrived over one direction of a link
+#[derive(Debug, PartialEq, Clone)]
+pub struct LinkGoodput {
+    pub source: NodeIndex,
+    pub destination: NodeIndex,
+    /// Rate at which data arrived at the destination (in bits/s)
+    pub goodput: f64,
+    /// Upload bandwidth of the source (in bits/s)
+    /// This is shared by all links of the source node
+    pub bandwidth: f64,
+    /// Average time between sending and receiving a message (in milliseconds)
+    pub avg_delay: f64,
+}
+
+/// How the blocks of the longest chain spread through the network
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct PropagationStatistics {
+    /// Oldest block first
+    pub blocks: Vec<BlockPropagation>,
+}
+
+impl PropagationStatistics {
+    /// The average time (in milliseconds) blocks needed to reach the given fraction of nodes
+    /// Only considers blocks that reached that many nodes
+    pub fn get_avg_delay_until(&self, fraction: f64) -> f64 {
+        let delays: Vec<_> = self
+            .blocks
+            .iter()
+            .filter_map(|block| block.get_delay_until(fraction))
+            .collect();
+
+        if delays.is_empty() {
+            0.0
+        } else {
+            let total: f64 = delays.iter().map(|delay| delay.as_millis_f64()).sum();
+            total / (delays.len() as f64)
+        }
+    }
+
+    /// How often a node received a block after a given number of hops (index = hops)
+    pub fn get_hop_histogram(&self) -> Vec<u64> {
+        let mut result = vec![];
+
+        for block in self.blocks.iter() {
+            if result.len() < block.hop_counts.len() {
+                result.resize(block.hop_counts.len(), 0);
+            }
+
+            for (hops, count) in block.hop_counts.iter().enumerate() {
+                result[hops] += *count as u64;
+            }
+        }
+
+        result
+    }
+}
+
+/// How well transactions propagated ahead of the blocks that include them
+///
+/// Every node that receives a block counts once for each transaction in it.
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct MempoolStatistics {
+    /// How long transactions were in a node's mempool before a block included them
+    pub mempool_ages: LatencyHistogram,
+    /// Transactions a node already had when a block containing them arrived
+    pub num_known_transactions: u64,
+    /// Transactions a node only learned about after a block containing them arrived
+    pub num_missing_transactions: u64,
+}
+
+impl MempoolStatistics {
+    pub fn merge(&mut self, other: &Self) {
+        self.mempool_ages.merge(&other.mempool_ages);
+        self.num_known_transactions += other.num_known_transactions;
+        self.num_missing_transactions += other.num_missing_transactions;
+    }
+
+    /// The average time a transaction spent in a mempool until it was included (in milliseconds)
+    pub fn get_avg_mempool_age(&self) -> f64 {
+        self.mempool_ages.get_mean()
+    }
+
+    /// The fraction of transactions that nodes already had when the block containing them arrived
+    pub fn get_known_fraction(&self) -> f64 {
+        let total = self.num_known_transactions + self.num_missing_transactions;
+
+        if total == 0 {
+            0.0
+        } else {
+            (self.num_known_transactions as f64) / (total as f64)
+        }
+    }
+}
+
+/// How transaction ordering affected transactions marked as victims
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct OrderingStatistics {
+    /// Commit latencies of all client transactions (including victims)
+    pub latencies: LatencyHistogram,
+    /// Commit latencies of victim transactions only
+    pub victim_latencies: LatencyHistogram,
+    /// Victims in the longest chain that had an attacker transaction placed in front of them
+    pub front_run_victims: u64,
+}
+
+impl OrderingStatistics {
+    /// The average commit latency of all transactions (in milliseconds)
+    pub fn get_avg_latency(&self) -> f64 {
+        self.latencies.get_mean()
+    }
+
+    /// The average commit latency of victim transactions (in milliseconds)
+    pub fn get_avg_victim_latency(&self) -> f64 {
+        self.victim_latencies.get_mean()
+    }
+
+    /// The fraction of committed victims that were front-run
+    pub fn get_front_run_rate(&self) -> f64 {
+        if self.victim_latencies.is_empty() {
+            0.0
+        } else {
+            (self.front_run_victims as f64) / (self.victim_latencies.len() as f64)
+        }
+    }
+}
+
+/// Commit latencies grouped by how many confirmations clients waited for
+///
+/// Clients that rely on the protocol's commit rule are not included.
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct ConfirmationStatistics {
+    pub latencies: BTreeMap<u64, LatencyHistogram>,
+}
+
+impl ConfirmationStatistics {
+    /// The average latency of clients waiting for the given number of confirmations (in milliseconds)
+    /// Returns `None` if no such client committed a transaction
+    pub fn get_avg_latency(&self, confirmations: u64) -> Option<f64> {
+        self.latencies
+            .get(&confirmations)
+            .filter(|latencies| !latencies.is_empty())
+            .map(|latencies| latencies.get_mean())
+    }
 }
 
 impl ChainMetrics {
@@ -118,6 +487,8 @@ impl ChainMetrics {
             ChainMetricType::BlockPropagationDelay => self.avg_block_propagation,
             ChainMetricType::Latency => self.avg_latency,
             ChainMetricType::NumNetworkMessages => self.num_network_messages as f64,
+            ChainMetricType::DuplicateMessages => self.avg_duplicate_messages,
+            ChainMetricType::ValidatorPropagationDelay => self.avg_validator_block_propagation,
         }
     }
 }
@@ -1,20 +1,84 @@
-use std::cell::{RefCell, RefMut};
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
 use std::rc::{Rc, Weak};
+use std::task::{Context, Poll};
 
 use asim::network::NetworkMessage;
+use asim::sync::Notify;
+use asim::time::{Duration, Time};
 
 use serde::{Deserialize, Serialize};
 
-use crate::Message;
 use crate::clients::Client;
-use crate::link::Bandwidth;
-use crate::logic::{AccountId, NodeLogic, Transaction};
+use crate::config::{AsynchronyConfig, InboxConfig, OverflowPolicy};
+use crate::events::{Event, NodeEvent};
+use crate::link::{Bandwidth, LinkStatistics, Uplink};
+use crate::logic::{
+    AccountId, AccountState, BlockId, NUM_SIZE, NodeChainInfo, NodeLogic, Transaction,
+    TransactionId, TransactionStatus,
+};
+use crate::memory::MemoryUsage;
 use crate::object::ObjectId;
+use crate::random;
 use crate::stats::NodeStatsCollector;
+use crate::{Message, emit_event};
 
 pub type NodeIndex = u32;
 
+thread_local! {
+    /// The node whose logic is currently being executed (if any)
+    static CURRENT_NODE: Cell<Option<NodeIndex>> = const { Cell::new(None) };
+}
+
+/// Returns the index of the node whose logic is currently being executed on this thread
+///
+/// This allows, for example, to route log records to per-node log files.
+pub fn current_node() -> Option<NodeIndex> {
+    CURRENT_NODE.get()
+}
+
+/// Marks the current thread as executing the logic of a specific node
+/// until it is dropped
+struct NodeScope {
+    previous: Option<NodeIndex>,
+}
+
+impl NodeScope {
+    fn enter(index: NodeIndex) -> Self {
+        let previous = CURRENT_NODE.replace(Some(index));
+        Self { previous }
+    }
+}
+
+impl Drop for NodeScope {
+    fn drop(&mut self) {
+        CURRENT_NODE.set(self.previous);
+    }
+}
+
+/// Wraps a task so that it is associated with a node every time it is polled
+/// The task stops once the node crashes
+struct NodeTask<F: Future<Output = ()>> {
+    node: Rc<Node>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future<Output = ()>> Future for NodeTask<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let data = self.node.get_data();
+        if data.is_crashed() {
+            return Poll::Ready(());
+        }
+
+        let _scope = NodeScope::enter(data.get_index());
+        self.inner.as_mut().poll(cx)
+    }
+}
+
 pub struct NodeCallback {
     inner: Rc<dyn NodeLogic>,
 }
@@ -28,10 +92,61 @@ impl NodeCallback {
 #[async_trait::async_trait(?Send)]
 impl asim::network::NodeCallback<Message, NodeData> for NodeCallback {
     async fn handle_message(&self, node: &Rc<Node>, source: ObjectId, message: Message) {
-        node.get_data()
-            .statistics
+        let data = node.get_data();
+
+        // The message left the link, even if it is dropped below
+        if let Some(link_stats) = data.peer_links.borrow().get(&source) {
+            link_stats.record_message_received(&source);
+        }
+
+        if data.is_crashed() {
+            return;
+        }
+
+        if data.unreachable_peers.borrow().contains(&source) {
+            log::trace!(
+                "Node #{} dropped message from {source} due to a partition",
+                data.index
+            );
+            return;
+        }
+
+        if data.is_offline() || data.offline_peers.borrow().contains(&source) {
+            log::trace!(
+                "Node #{} dropped message from {source} due to churn",
+                data.index
+            );
+            return;
+        }
+
+        // Hold the message back until the network is synchronous again
+        if let Some(asynchrony) = &data.asynchrony {
+            while let Some(end) = asynchrony.get_stall_end(asim::time::now()) {
+                asim::time::sleep(end - asim::time::now()).await;
+            }
+
+            if data.is_crashed() {
+                return;
+            }
+        }
+
+        data.statistics
             .borrow_mut()
             .record_incoming_data(message.get_size());
+
+        if let Some(inbox) = &data.inbox {
+            if !inbox.enqueue(data.index).await {
+                log::trace!("Node #{} dropped message from {source}", data.index);
+                data.statistics.borrow_mut().record_dropped_message();
+                return;
+            }
+
+            let processing_time = Duration::from_micros(inbox.config.processing_time);
+            inbox.wait_for_turn(processing_time).await;
+            inbox.dequeue(data.index);
+        }
+
+        let _scope = NodeScope::enter(data.index);
         self.inner.handle_message(node, source, message);
     }
 
@@ -75,12 +190,22 @@ impl Location {
 
     pub fn new_random() -> Self {
         // Modulo with negative values does not work as expected
-        let longitude = (rand::random::<u32>() % 360) as i16 - 180;
-        let latitude = (rand::random::<u32>() % 180) as i16 - 90;
+        let longitude = (random::random::<u32>() % 360) as i16 - 180;
+        let latitude = (random::random::<u32>() % 180) as i16 - 90;
 
         Self::new(longitude, latitude)
     }
 
+    /// Splits the world into `num_regions` bands of equal longitude
+    /// and returns the one this location belongs to
+    pub fn get_region(&self, num_regions: u32) -> u32 {
+        assert!(num_regions > 0, "Need at least one region");
+
+        let offset = (self.longitude - Self::MIN_LONGITUDE) as u32;
+        let width = (Self::MAX_LONGITUDE - Self::MIN_LONGITUDE) as u32;
+        offset * num_regions / width
+    }
+
     pub fn distance(&self, other: &Location) -> f32 {
         // TODO wrap around
         let lat = (self.latitude - other.latitude) as f32;
@@ -91,47 +216,173 @@ impl Location {
     }
 }
 
+/// A bounded queue of messages waiting to be processed by a node
+struct Inbox {
+    config: InboxConfig,
+    num_queued: Cell<usize>,
+    /// When the node will be done with all messages queued so far
+    busy_until: Cell<Time>,
+    overloaded: Cell<bool>,
+    slot_notify: Notify,
+}
+
+impl Inbox {
+    fn new(config: InboxConfig) -> Self {
+        assert!(config.capacity > 0, "Inbox capacity must be non-zero");
+
+        Self {
+            config,
+            num_queued: Cell::new(0),
+            busy_until: Cell::new(asim::time::START_TIME),
+            overloaded: Cell::new(false),
+            slot_notify: Notify::new(),
+        }
+    }
+
+    /// Reserves a slot for a new message
+    /// Returns false if the message was dropped
+    async fn enqueue(&self, index: NodeIndex) -> bool {
+        while self.num_queued.get() >= self.config.capacity {
+            if !self.overloaded.replace(true) {
+                log::debug!("Node #{index} is overloaded");
+                emit_event!(Event::Node {
+                    index,
+                    event: NodeEvent::Overloaded,
+                });
+            }
+
+            match self.config.overflow_policy {
+                OverflowPolicy::Drop => return false,
+                OverflowPolicy::Delay => self.slot_notify.notified().await,
+            }
+        }
+
+        self.num_queued.set(self.num_queued.get() + 1);
+        true
+    }
+
+    /// Waits until all previously queued messages have been processed
+    /// and then keeps the node busy for the given time
+    async fn wait_for_turn(&self, processing_time: Duration) {
+        let now = asim::time::now();
+        let start = if self.busy_until.get() > now {
+            self.busy_until.get()
+        } else {
+            now
+        };
+
+        let done = start + processing_time;
+        self.busy_until.set(done);
+
+        if done > now {
+            asim::time::sleep(done - now).await;
+        }
+    }
+
+    fn dequeue(&self, index: NodeIndex) {
+        let num_queued = self.num_queued.get() - 1;
+        self.num_queued.set(num_queued);
+        self.slot_notify.notify_one();
+
+        if num_queued == 0 && self.overloaded.replace(false) {
+            log::debug!("Node #{index} recovered from overload");
+            emit_event!(Event::Node {
+                index,
+                event: NodeEvent::Recovered,
+            });
+        }
+    }
+}
+
+/// A read-only request by a client
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RpcQuery {
+    Balance(AccountId),
+    LatestBlock,
+}
+
 pub struct NodeData {
     index: NodeIndex,
     account_id: AccountId,
     location: Location,
+    /// Upload bandwidth in Mbit/s
+    bandwidth: u64,
+    /// Shared by all links and responses to client queries
+    uplink: Uplink,
     clients: RefCell<HashMap<AccountId, Weak<Client>>>,
     statistics: RefCell<NodeStatsCollector>,
+    inbox: Option<Inbox>,
+    /// Stalls incoming messages (if set)
+    asynchrony: Option<AsynchronyConfig>,
+    is_mining: bool,
+    crashed: Cell<bool>,
+    /// Statistics of the links to our peers, indexed by peer
+    peer_links: RefCell<HashMap<ObjectId, Rc<LinkStatistics>>>,
+    /// Nodes on the other side of a network partition
+    unreachable_peers: RefCell<HashSet<ObjectId>>,
+    /// The side of the network partition this node is on (if any)
+    partition_group: Cell<Option<u32>>,
+    /// Set while the node has left the network
+    offline: Cell<bool>,
+    /// Peers that have currently left the network
+    offline_peers: RefCell<HashSet<ObjectId>>,
 }
 
 impl asim::network::NodeData for NodeData {}
 
+/// `bandwidth` is given in Mbit/s
+#[allow(clippy::too_many_arguments)]
 pub fn create_node(
     index: NodeIndex,
     location: Location,
-    bandwidth: Bandwidth,
+    bandwidth: u64,
+    inbox: Option<InboxConfig>,
+    asynchrony: Option<AsynchronyConfig>,
     logic: Rc<dyn NodeLogic>,
     is_mining: bool,
     faulty: bool,
 ) -> Rc<Node> {
     let callback = NodeCallback { inner: logic };
 
-    let account_id = rand::random::<u128>();
+    let account_id = random::random::<u128>();
 
     let data = NodeData {
         account_id,
         index,
         location,
+        bandwidth,
+        uplink: Uplink::new(bandwidth),
         clients: RefCell::new(Default::default()),
         statistics: RefCell::new(Default::default()),
+        inbox: inbox.map(Inbox::new),
+        asynchrony,
+        is_mining,
+        crashed: Cell::new(false),
+        peer_links: RefCell::new(Default::default()),
+        unreachable_peers: RefCell::new(Default::default()),
+        partition_group: Cell::new(None),
+        offline: Cell::new(false),
+        offline_peers: RefCell::new(Default::default()),
     };
 
+    let bandwidth = Bandwidth::from_megabits_per_second(bandwidth);
     let obj = asim::network::Node::new(bandwidth, data, Box::new(callback));
 
-    get_node_logic(&obj).init(obj.clone());
+    {
+        let _scope = NodeScope::enter(index);
+        get_node_logic(&obj).init(obj.clone());
+    }
 
     // Only non-faulty nodes do something
     // TODO add proper Byzantine behavior
     if !faulty {
         let obj = obj.clone();
         let obj_ptr = obj.clone();
-        asim::spawn(async move {
-            get_node_logic(&obj).run(obj_ptr, is_mining).await;
+        asim::spawn(NodeTask {
+            node: obj.clone(),
+            inner: Box::pin(async move {
+                get_node_logic(&obj).run(obj_ptr, is_mining).await;
+            }),
         });
     }
 
@@ -143,12 +394,97 @@ impl NodeData {
         self.statistics.borrow_mut()
     }
 
+    /// Accounts for data sent by this node
+    /// Returns how long it takes until the data has been uploaded
+    pub(crate) fn record_upload(&self, size: u64) -> Duration {
+        self.uplink.transmit(size)
+    }
+
+    /// How long the node's uplink was busy since this was last called
+    pub(crate) fn take_upload_busy_time(&self) -> Duration {
+        self.uplink.take_busy_time()
+    }
+
     pub(crate) fn add_client(&self, client: &Rc<Client>) {
         let account_id = *client.get_account_id();
         let mut clients = self.clients.borrow_mut();
         clients.insert(account_id, Rc::downgrade(client));
     }
 
+    /// Drop all messages from these nodes until this is called again
+    pub(crate) fn set_unreachable_peers(&self, peers: HashSet<ObjectId>) {
+        *self.unreachable_peers.borrow_mut() = peers;
+    }
+
+    pub(crate) fn set_partition_group(&self, group: Option<u32>) {
+        if self.partition_group.replace(group) != group {
+            emit_event!(Event::Node {
+                index: self.index,
+                event: NodeEvent::Partitioned { group },
+            });
+        }
+    }
+
+    pub fn get_partition_group(&self) -> Option<u32> {
+        self.partition_group.get()
+    }
+
+    /// Makes the node leave or (re-)join the network
+    /// The node keeps its state while it is offline
+    pub(crate) fn set_offline(&self, offline: bool) {
+        if self.offline.replace(offline) == offline {
+            return;
+        }
+
+        if offline {
+            log::info!("Node #{} left the network", self.index);
+        } else {
+            log::info!("Node #{} joined the network", self.index);
+        }
+
+        emit_event!(Event::Node {
+            index: self.index,
+            event: if offline {
+                NodeEvent::Removed
+            } else {
+                NodeEvent::Joined
+            },
+        });
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.get()
+    }
+
+    /// Drop all messages from this peer while it is offline
+    pub(crate) fn set_peer_offline(&self, peer: ObjectId, offline: bool) {
+        let mut offline_peers = self.offline_peers.borrow_mut();
+        if offline {
+            offline_peers.insert(peer);
+        } else {
+            offline_peers.remove(&peer);
+        }
+    }
+
+    pub(crate) fn add_peer_link(&self, peer: ObjectId, statistics: Rc<LinkStatistics>) {
+        self.peer_links.borrow_mut().insert(peer, statistics);
+    }
+
+    /// All peers this node can currently reach (in ascending order)
+    pub(crate) fn get_peers(&self) -> Vec<ObjectId> {
+        let unreachable_peers = self.unreachable_peers.borrow();
+        let offline_peers = self.offline_peers.borrow();
+        let mut peers: Vec<ObjectId> = self
+            .peer_links
+            .borrow()
+            .keys()
+            .filter(|peer| !unreachable_peers.contains(peer) && !offline_peers.contains(peer))
+            .copied()
+            .collect();
+        peers.sort();
+        peers
+    }
+
     pub fn get_client(&self, account_id: &AccountId) -> Option<Rc<Client>> {
         let clients = self.clients.borrow();
         clients
@@ -156,9 +492,100 @@ impl NodeData {
             .map(|client| client.upgrade().unwrap())
     }
 
-    #[allow(dead_code)]
-    pub fn add_transaction(self_ptr: &Rc<Node>, transaction: Rc<Transaction>) {
-        get_node_logic(self_ptr).add_transaction(self_ptr, transaction, None);
+    /// Hands a transaction to the node as if it was issued by a client
+    /// Returns false if the node has crashed or its protocol does not process transactions
+    pub fn add_transaction(self_ptr: &Rc<Node>, transaction: Rc<Transaction>) -> bool {
+        if self_ptr.is_crashed() {
+            return false;
+        }
+
+        let _scope = NodeScope::enter(self_ptr.get_index());
+        get_node_logic(self_ptr).add_transaction(self_ptr, transaction, None)
+    }
+
+    /// Answers a query of a client
+    ///
+    /// This keeps the node busy for the processing time plus the time it takes
+    /// to upload the response, which delays any messages that arrive in the meantime.
+    /// Returns false if the node crashed or dropped the query
+    pub(crate) async fn answer_rpc_query(
+        self_ptr: &Rc<Node>,
+        query: RpcQuery,
+        processing_time: Duration,
+    ) -> bool {
+        let data = self_ptr.get_data();
+        if data.is_crashed() {
+            return false;
+        }
+
+        let response_size = {
+            let _scope = NodeScope::enter(data.index);
+            let logic = get_node_logic(self_ptr);
+
+            match query {
+                RpcQuery::Balance(account) => {
+                    let _ = logic.get_balance(&account);
+                    NUM_SIZE
+                }
+                RpcQuery::LatestBlock => logic
+                    .get_chain_head()
+                    .and_then(|(block_id, _)| logic.get_block_size(&block_id))
+                    .unwrap_or(NUM_SIZE),
+            }
+        };
+
+        // The response has to wait for anything else the node is uploading
+        let upload_time = data.record_upload(response_size);
+
+        if let Some(inbox) = &data.inbox {
+            if !inbox.enqueue(data.index).await {
+                log::trace!("Node #{} dropped RPC query", data.index);
+                data.statistics.borrow_mut().record_dropped_rpc_query();
+                return false;
+            }
+
+            inbox.wait_for_turn(processing_time + upload_time).await;
+            inbox.dequeue(data.index);
+        }
+
+        data.statistics.borrow_mut().record_rpc_query(response_size);
+        true
+    }
+
+    pub fn get_mempool(self_ptr: &Node) -> Vec<TransactionId> {
+        get_node_logic(self_ptr).get_mempool()
+    }
+
+    pub fn get_memory_usage(self_ptr: &Node) -> MemoryUsage {
+        get_node_logic(self_ptr).get_memory_usage()
+    }
+
+    pub fn get_chain_head(self_ptr: &Node) -> Option<(BlockId, u64)> {
+        get_node_logic(self_ptr).get_chain_head()
+    }
+
+    pub fn get_account_state(self_ptr: &Node, account: &AccountId) -> Option<AccountState> {
+        get_node_logic(self_ptr).get_account_state(account)
+    }
+
+    pub fn get_chain_info(self_ptr: &Node) -> Option<NodeChainInfo> {
+        let logic = get_node_logic(self_ptr);
+        let (head, height) = logic.get_chain_head()?;
+
+        Some(NodeChainInfo {
+            head,
+            height,
+            num_forks: logic.get_num_forks(),
+        })
+    }
+
+    pub fn get_transaction_status(self_ptr: &Node, txn_id: &TransactionId) -> TransactionStatus {
+        get_node_logic(self_ptr).get_transaction_status(txn_id)
+    }
+
+    /// Upload bandwidth in Mbit/s
+    pub fn get_bandwidth(&self) -> u64 {
+        self.bandwidth
     }
 
     pub fn get_location(&self) -> &Location {
@@ -172,4 +599,148 @@ impl NodeData {
     pub fn get_account_id(&self) -> AccountId {
         self.account_id
     }
+
+    /// Does this node participate in block creation?
+    pub fn is_mining(&self) -> bool {
+        self.is_mining
+    }
+
+    /// Stop this node from processing messages, transactions, or running its main task
+    pub(crate) fn crash(&self) {
+        log::info!("Node #{} crashed", self.index);
+        self.crashed.set(true);
+
+        emit_event!(Event::Node {
+            index: self.index,
+            event: NodeEvent::Crashed,
+        });
+    }
+
+    pub fn is_crashed(&self) -> bool {
+        self.crashed.get()
+    }
+
+    /// Number of messages waiting to be processed by this node
+    pub fn num_queued_messages(&self) -> usize {
+        self.inbox
+            .as_ref()
+            .map(|inbox| inbox.num_queued.get())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    use crate::events::EVENT_HANDLER;
+
+    fn make_inbox(capacity: usize, overflow_policy: OverflowPolicy) -> Rc<Inbox> {
+        Rc::new(Inbox::new(InboxConfig {
+            capacity,
+            processing_time: 1000,
+            overflow_policy,
+        }))
+    }
+
+    fn enable_events() -> mpsc::Receiver<(Time, Event)> {
+        let (sender, receiver) = mpsc::channel();
+        EVENT_HANDLER.with(|hdl| {
+            hdl.set((asim::time::now(), sender))
+                .expect("Events were already enabled")
+        });
+        receiver
+    }
+
+    fn get_node_events(receiver: &mpsc::Receiver<(Time, Event)>) -> Vec<NodeEvent> {
+        receiver
+            .try_iter()
+            .filter_map(|(_, event)| match event {
+                Event::Node { event, .. } => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[asim::test]
+    async fn inbox_drops_when_full() {
+        let events = enable_events();
+        let inbox = make_inbox(2, OverflowPolicy::Drop);
+
+        assert!(inbox.enqueue(0).await);
+        assert!(inbox.enqueue(0).await);
+        assert!(get_node_events(&events).is_empty());
+
+        assert!(!inbox.enqueue(0).await);
+        assert!(!inbox.enqueue(0).await);
+        assert_eq!(inbox.num_queued.get(), 2);
+        assert_eq!(get_node_events(&events), vec![NodeEvent::Overloaded]);
+
+        // The node only recovers once its inbox is empty
+        inbox.dequeue(0);
+        assert!(get_node_events(&events).is_empty());
+        assert!(inbox.enqueue(0).await);
+
+        inbox.dequeue(0);
+        inbox.dequeue(0);
+        assert_eq!(get_node_events(&events), vec![NodeEvent::Recovered]);
+    }
+
+    #[asim::test]
+    async fn inbox_delays_when_full() {
+        let events = enable_events();
+        let inbox = make_inbox(1, OverflowPolicy::Delay);
+        assert!(inbox.enqueue(0).await);
+
+        let accepted = Rc::new(Cell::new(false));
+        {
+            let inbox = inbox.clone();
+            let accepted = accepted.clone();
+            asim::spawn(async move {
+                accepted.set(inbox.enqueue(0).await);
+            });
+        }
+
+        asim::time::sleep(Duration::from_millis(1)).await;
+        assert!(!accepted.get());
+        assert_eq!(get_node_events(&events), vec![NodeEvent::Overloaded]);
+
+        // The waiting message takes the freed slot
+        inbox.dequeue(0);
+        asim::time::sleep(Duration::from_millis(1)).await;
+        assert!(accepted.get());
+        assert_eq!(inbox.num_queued.get(), 1);
+        assert_eq!(get_node_events(&events), vec![NodeEvent::Recovered]);
+    }
+
+    #[asim::test]
+    async fn inbox_processes_in_order() {
+        let inbox = make_inbox(4, OverflowPolicy::Delay);
+        let processing_time = Duration::from_micros(inbox.config.processing_time);
+        let start = asim::time::now();
+
+        let done = Rc::new(RefCell::new(vec![]));
+        for _ in 0..3 {
+            let inbox = inbox.clone();
+            let done = done.clone();
+            asim::spawn(async move {
+                assert!(inbox.enqueue(0).await);
+                inbox.wait_for_turn(processing_time).await;
+                inbox.dequeue(0);
+                done.borrow_mut().push(asim::time::now() - start);
+            });
+        }
+
+        asim::time::sleep(Duration::from_millis(10)).await;
+
+        // Each message waits for all messages queued before it
+        assert_eq!(*done.borrow(), vec![
+            processing_time,
+            processing_time + processing_time,
+            processing_time + processing_time + processing_time,
+        ]);
+        assert_eq!(inbox.num_queued.get(), 0);
+    }
 }
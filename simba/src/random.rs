@@ -0,0 +1,75 @@
+//! Reproducible randomness
+//!
+//! All random decisions of a simulation, such as node placement, fork selection,
+//! or block and transaction identifiers, are drawn from a single generator.
+//! The generator is local to the simulation's worker thread, so runs with the
+//! same seed make the same decisions.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rand::distr::{Distribution, StandardUniform};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+thread_local! {
+    static GENERATOR: Rc<RefCell<StdRng>> = Rc::new(RefCell::new(StdRng::from_os_rng()));
+}
+
+/// A handle to the generator of the current thread (similar to `rand::rng()`)
+#[derive(Clone)]
+pub(crate) struct SimRng(Rc<RefCell<StdRng>>);
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.borrow_mut().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.borrow_mut().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.borrow_mut().fill_bytes(dest)
+    }
+}
+
+/// Picks a seed if none was configured, so that it can be logged and reused
+pub(crate) fn resolve_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(rand::random)
+}
+
+/// Restarts the generator of the current thread from the given seed
+pub(crate) fn set_seed(seed: u64) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub(crate) fn rng() -> SimRng {
+    GENERATOR.with(|generator| SimRng(generator.clone()))
+}
+
+/// Same as `rand::random()`, but uses the generator of the current thread
+pub(crate) fn random<T>() -> T
+where
+    StandardUniform: Distribution<T>,
+{
+    rng().random()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::{random, rng, set_seed};
+
+    #[test]
+    fn same_seed() {
+        set_seed(42);
+        let first: (u64, u32) = (random(), rng().random_range(0..1000));
+
+        set_seed(42);
+        let second: (u64, u32) = (random(), rng().random_range(0..1000));
+
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,402 @@
+//! Observe a simulation running in another process (or on another machine)
+//!
+//! The server forwards all events a visualizer needs and answers its queries.
+//! Messages are sent over TCP as JSON, one message per line.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, mpsc};
+
+use anyhow::Context;
+
+use asim::time::Time;
+
+use parking_lot::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::block_graph::{BlockDetails, BlockGraph};
+use crate::events::{BlockEvent, LinkEvent, NodeEvent, StatisticsEvent};
+use crate::logic::{BlockId, NodeChainInfo};
+use crate::metrics::NetworkMetricType;
+use crate::node::{Location, NodeIndex};
+use crate::object::ObjectId;
+use crate::simulation::{Simulation, SimulationSpeed};
+
+/// A query sent by the client
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemoteRequest {
+    CurrentTime,
+    NodeLocation(NodeIndex),
+    NodeChainInfo(NodeIndex),
+    Speed,
+    SetRateLimit(u32),
+    RateLimit,
+    /// Pause once the specified (virtual) time is reached (in milliseconds)
+    PauseAt(u64),
+    BlockGraph,
+    BlockDetails(BlockId),
+}
+
+/// The answer to a `RemoteRequest`
+/// Requests are answered in the order they were received
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RemoteResponse {
+    /// In milliseconds
+    CurrentTime(u64),
+    NodeLocation(Location),
+    NodeChainInfo(Option<NodeChainInfo>),
+    Speed(SimulationSpeed),
+    RateLimit(Option<u32>),
+    BlockGraph(BlockGraph),
+    BlockDetails(Option<BlockDetails>),
+    Done,
+    /// The request was invalid, e.g., because it refers to a node that does not exist
+    Error(String),
+}
+
+/// An event of the simulation
+///
+/// Object identifiers are only meaningful within a process,
+/// so the server replaces them with sequence numbers.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum RemoteEvent {
+    Block {
+        identifier: BlockId,
+        event: BlockEvent,
+    },
+    NodeCreated {
+        index: NodeIndex,
+        identifier: u64,
+    },
+    /// Never contains `NodeEvent::Created`
+    Node {
+        index: NodeIndex,
+        event: NodeEvent,
+    },
+    Link {
+        identifier: u64,
+        event: LinkEvent,
+    },
+    Statistics(StatisticsEvent),
+}
+
+/// Everything the server sends to the client
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum RemoteMessage {
+    Response(RemoteResponse),
+    Event(RemoteEvent),
+}
+
+/// Writes a message as a single line of JSON
+pub fn write_remote_message<T: Serialize>(
+    writer: &mut impl Write,
+    message: &T,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(message).with_context(|| "Failed to serialize message")?;
+    line.push('\n');
+
+    writer
+        .write_all(line.as_bytes())
+        .with_context(|| "Failed to send message")
+}
+
+/// Reads a message written by `write_remote_message`
+/// Returns `None` once the connection is closed
+pub fn read_remote_message<T: DeserializeOwned>(
+    reader: &mut impl BufRead,
+) -> anyhow::Result<Option<T>> {
+    let mut line = String::new();
+
+    if reader
+        .read_line(&mut line)
+        .with_context(|| "Failed to receive message")?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    let message = serde_json::from_str(&line).with_context(|| "Received invalid message")?;
+    Ok(Some(message))
+}
+
+/// How many messages can wait for the sending thread before the client is disconnected
+const MAX_PENDING_MESSAGES: usize = 100_000;
+
+/// Hands messages to the sending thread
+#[derive(Clone)]
+struct MessageSender {
+    sender: mpsc::SyncSender<RemoteMessage>,
+    stream: Arc<TcpStream>,
+}
+
+impl MessageSender {
+    /// Returns false if the client is gone
+    ///
+    /// Dropping single events would leave the client with an inconsistent view,
+    /// so a client that cannot keep up is disconnected instead.
+    fn send(&self, message: RemoteMessage) -> bool {
+        match self.sender.try_send(message) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                log::warn!("Disconnecting client, because it cannot keep up with the simulation");
+                let _ = self.stream.shutdown(Shutdown::Both);
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// Numbers object identifiers in the order they are first seen
+#[derive(Default)]
+struct Identifiers {
+    numbers: HashMap<ObjectId, u64>,
+}
+
+impl Identifiers {
+    fn get(&mut self, identifier: ObjectId) -> u64 {
+        let next = self.numbers.len() as u64;
+        *self.numbers.entry(identifier).or_insert(next)
+    }
+}
+
+/// Waits for a client to connect and runs the simulation until the client disconnects
+///
+/// The simulation must not have been started yet or have any event callbacks set,
+/// so that the client sees all events, including those setting up the scene.
+pub fn serve_remote(simulation: &Simulation, listener: &TcpListener) -> anyhow::Result<()> {
+    let (stream, address) = listener
+        .accept()
+        .with_context(|| "Failed to accept client")?;
+    log::info!("Client {address} connected");
+
+    stream.set_nodelay(true)?;
+
+    // Events and responses are sent by a dedicated thread,
+    // so that a slow client does not stall the simulation
+    let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_MESSAGES);
+    let sender = MessageSender {
+        sender,
+        stream: Arc::new(stream.try_clone()?),
+    };
+    let mut writer = stream.try_clone()?;
+
+    std::thread::spawn(move || {
+        for message in receiver {
+            if let Err(err) = write_remote_message::<RemoteMessage>(&mut writer, &message) {
+                log::debug!("Stopped sending to client: {err:?}");
+                break;
+            }
+        }
+    });
+
+    set_event_callbacks(simulation, &sender);
+    simulation.start();
+
+    let num_nodes = (simulation.get_network_metric(NetworkMetricType::NumMiningNodes)
+        + simulation.get_network_metric(NetworkMetricType::NumNonMiningNodes))
+        as NodeIndex;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    loop {
+        let request = match read_remote_message::<RemoteRequest>(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(err) => {
+                log::warn!("Disconnecting client: {err:?}");
+                break;
+            }
+        };
+
+        let response = handle_request(simulation, num_nodes, request);
+        if !sender.send(RemoteMessage::Response(response)) {
+            break;
+        }
+    }
+
+    log::info!("Client {address} disconnected");
+
+    // Make sure the sending thread exits on its next write
+    let _ = stream.shutdown(Shutdown::Both);
+    simulation.stop();
+
+    Ok(())
+}
+
+fn set_event_callbacks(simulation: &Simulation, sender: &MessageSender) {
+    let identifiers = Arc::new(Mutex::new(Identifiers::default()));
+
+    {
+        let sender = sender.clone();
+        simulation.set_block_event_callback(Box::new(move |identifier, event| {
+            sender.send(RemoteMessage::Event(RemoteEvent::Block {
+                identifier,
+                event,
+            }));
+        }));
+    }
+
+    {
+        let sender = sender.clone();
+        let identifiers = identifiers.clone();
+        simulation.set_node_event_callback(Box::new(move |index, event| {
+            let event = if let NodeEvent::Created(identifier) = event {
+                let identifier = identifiers.lock().get(identifier);
+                RemoteEvent::NodeCreated { index, identifier }
+            } else {
+                RemoteEvent::Node { index, event }
+            };
+
+            sender.send(RemoteMessage::Event(event));
+        }));
+    }
+
+    {
+        let sender = sender.clone();
+        simulation.set_link_event_callback(Box::new(move |identifier, event| {
+            let identifier = identifiers.lock().get(identifier);
+            sender.send(RemoteMessage::Event(RemoteEvent::Link {
+                identifier,
+                event,
+            }));
+        }));
+    }
+
+    let sender = sender.clone();
+    simulation.set_stats_event_callback(Box::new(move |event| {
+        sender.send(RemoteMessage::Event(RemoteEvent::Statistics(event)));
+    }));
+}
+
+fn handle_request(
+    simulation: &Simulation,
+    num_nodes: NodeIndex,
+    request: RemoteRequest,
+) -> RemoteResponse {
+    match request {
+        RemoteRequest::NodeLocation(node_idx) | RemoteRequest::NodeChainInfo(node_idx)
+            if node_idx >= num_nodes =>
+        {
+            RemoteResponse::Error(format!("No node with index {node_idx}"))
+        }
+        RemoteRequest::CurrentTime => {
+            RemoteResponse::CurrentTime(simulation.get_current_time().to_millis())
+        }
+        RemoteRequest::NodeLocation(node_idx) => {
+            RemoteResponse::NodeLocation(simulation.get_node_location(node_idx))
+        }
+        RemoteRequest::NodeChainInfo(node_idx) => {
+            RemoteResponse::NodeChainInfo(simulation.get_node_chain_info(node_idx))
+        }
+        RemoteRequest::Speed => RemoteResponse::Speed(simulation.get_speed()),
+        RemoteRequest::SetRateLimit(rate_limit) => {
+            simulation.set_rate_limit(rate_limit);
+            RemoteResponse::Done
+        }
+        RemoteRequest::RateLimit => RemoteResponse::RateLimit(simulation.get_rate_limit()),
+        RemoteRequest::PauseAt(millis) => {
+            simulation.pause_at(Time::from_millis(millis));
+            RemoteResponse::Done
+        }
+        RemoteRequest::BlockGraph => RemoteResponse::BlockGraph(simulation.get_block_graph()),
+        RemoteRequest::BlockDetails(block_id) => {
+            RemoteResponse::BlockDetails(simulation.get_block_details(block_id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Connectivity, Failures, NetworkConfiguration, ProtocolConfiguration};
+
+    use asim::time::Duration;
+
+    #[test]
+    fn message_roundtrip() {
+        let message = RemoteMessage::Event(RemoteEvent::Block {
+            identifier: u128::MAX,
+            event: BlockEvent::Committed {
+                delay: Duration::from_millis(1500),
+            },
+        });
+
+        let mut buffer = vec![];
+        write_remote_message(&mut buffer, &message).unwrap();
+
+        let result: RemoteMessage = read_remote_message(&mut &buffer[..]).unwrap().unwrap();
+        assert_eq!(result, message);
+    }
+
+    #[test]
+    fn serve_client() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+                .unwrap();
+
+            write_remote_message(&mut stream, &RemoteRequest::NodeChainInfo(5)).unwrap();
+            write_remote_message(&mut stream, &RemoteRequest::NodeLocation(1)).unwrap();
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut nodes = vec![];
+            let mut responses = vec![];
+
+            while nodes.len() < num_mining_nodes as usize || responses.len() < 2 {
+                match read_remote_message(&mut reader).unwrap().unwrap() {
+                    RemoteMessage::Response(response) => {
+                        responses.push(response);
+                    }
+                    RemoteMessage::Event(RemoteEvent::NodeCreated { index, .. }) => {
+                        nodes.push(index);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Requests for unknown nodes are rejected without affecting later ones
+            assert!(matches!(responses[0], RemoteResponse::Error(_)));
+            assert!(matches!(responses[1], RemoteResponse::NodeLocation(_)));
+
+            nodes.sort();
+            nodes
+        });
+
+        // Returns once the client disconnects
+        serve_remote(&simulation, &listener).unwrap();
+
+        let nodes = client.join().unwrap();
+        assert_eq!(nodes, vec![0, 1]);
+    }
+}
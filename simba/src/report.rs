@@ -0,0 +1,656 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use asim::time::Time;
+
+use crate::config::{NetworkConfiguration, ProtocolConfiguration, TimeoutConfig};
+use crate::fingerprint::Fingerprint;
+use crate::format::NumberFormat;
+use crate::metrics::{ChainMetrics, ClientInfo, PropagationStatistics};
+use crate::stats::GlobalStatistics;
+
+/// The file formats a report can be written as
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// Picks the format based on the file extension (.md, .markdown, .html, or .htm)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// A network-wide statistic that is plotted over time
+struct Series {
+    name: &'static str,
+    unit: &'static str,
+    get: fn(&GlobalStatistics) -> u64,
+}
+
+const SERIES: [Series; 3] = [
+    Series {
+        name: "Network traffic",
+        unit: "bytes/s",
+        get: |stats| stats.network_traffic,
+    },
+    Series {
+        name: "Reorganizations",
+        unit: "per second",
+        get: |stats| stats.reorgs,
+    },
+    Series {
+        name: "Dropped messages",
+        unit: "per second",
+        get: |stats| stats.dropped_messages,
+    },
+];
+
+const CHART_WIDTH: f64 = 600.0;
+const CHART_HEIGHT: f64 = 150.0;
+
+/// The fractions of nodes reached that are listed in reports
+const PROPAGATION_FRACTIONS: [f64; 3] = [0.5, 0.9, 1.0];
+
+/// A summary of a single simulation run
+///
+/// Reports do not reference any external files, so they can be shared as is.
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    pub title: String,
+    pub protocol: ProtocolConfiguration,
+    pub network: NetworkConfiguration,
+    pub timeout: TimeoutConfig,
+    /// Identifies the run; two runs with the same fingerprint behaved identically
+    pub fingerprint: Fingerprint,
+    /// Whether all asserts held (if the run was a test)
+    pub passed: Option<bool>,
+    pub chain_metrics: ChainMetrics,
+    /// Number of reorganizations by depth
+    pub reorg_histogram: BTreeMap<u64, u64>,
+    pub propagation: PropagationStatistics,
+    /// Which node each client was attached to
+    pub clients: Vec<ClientInfo>,
+    /// The network-wide statistics, one data point per second
+    pub statistics: Vec<(Time, GlobalStatistics)>,
+}
+
+impl RunReport {
+    /// Writes the report to the given file
+    /// The format is determined by the file extension
+    pub fn write_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let content = match ReportFormat::from_path(path) {
+            Some(ReportFormat::Markdown) => self.to_markdown(),
+            Some(ReportFormat::Html) => self.to_html(),
+            None => anyhow::bail!("Unknown report format for {path:?}"),
+        };
+
+        std::fs::write(path, content).with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "# {}", self.title).unwrap();
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Summary").unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "| Metric | Value |").unwrap();
+        writeln!(output, "|---|---|").unwrap();
+        for (name, value) in self.get_summary() {
+            writeln!(output, "| {name} | {value} |").unwrap();
+        }
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Reorganizations").unwrap();
+        writeln!(output).unwrap();
+        if self.reorg_histogram.is_empty() {
+            writeln!(output, "No reorganizations happened.").unwrap();
+        } else {
+            writeln!(output, "| Depth | Count |").unwrap();
+            writeln!(output, "|---|---|").unwrap();
+            for (depth, count) in self.reorg_histogram.iter() {
+                writeln!(output, "| {depth} | {count} |").unwrap();
+            }
+        }
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Block Propagation").unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "| Nodes reached | Average delay (ms) |").unwrap();
+        writeln!(output, "|---|---|").unwrap();
+        for fraction in PROPAGATION_FRACTIONS {
+            let delay = self.propagation.get_avg_delay_until(fraction);
+            writeln!(output, "| {:.0}% | {delay:.2} |", fraction * 100.0).unwrap();
+        }
+        writeln!(output).unwrap();
+        writeln!(output, "| Hops | Nodes |").unwrap();
+        writeln!(output, "|---|---|").unwrap();
+        for (hops, count) in self.propagation.get_hop_histogram().iter().enumerate() {
+            writeln!(output, "| {hops} | {count} |").unwrap();
+        }
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Clients").unwrap();
+        writeln!(output).unwrap();
+        let (group_name, groups) = self.get_client_groups();
+        writeln!(
+            output,
+            "| {group_name} | Clients | Transactions | Average latency (ms) |"
+        )
+        .unwrap();
+        writeln!(output, "|---|---|---|---|").unwrap();
+        for (group, num_clients, num_txns, latency) in groups {
+            writeln!(
+                output,
+                "| {group} | {num_clients} | {num_txns} | {latency:.2} |"
+            )
+            .unwrap();
+        }
+        writeln!(output).unwrap();
+        writeln!(output, "| Client | Account | Node | Region |").unwrap();
+        writeln!(output, "|---|---|---|---|").unwrap();
+        for (idx, client) in self.clients.iter().enumerate() {
+            writeln!(
+                output,
+                "| {idx} | {:#x} | {} | {} |",
+                client.account,
+                client.node,
+                format_region(client.region)
+            )
+            .unwrap();
+        }
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Network Statistics").unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "| Statistic | Min | Mean | Max |").unwrap();
+        writeln!(output, "|---|---|---|---|").unwrap();
+        for series in SERIES.iter() {
+            let (min, mean, max) = self.get_range(series);
+            writeln!(
+                output,
+                "| {} ({}) | {min} | {mean:.2} | {max} |",
+                series.name, series.unit
+            )
+            .unwrap();
+        }
+        writeln!(output).unwrap();
+
+        writeln!(output, "## Configuration").unwrap();
+        writeln!(output).unwrap();
+        for (name, config) in self.get_configuration() {
+            writeln!(output, "### {name}").unwrap();
+            writeln!(output).unwrap();
+            writeln!(output, "```ron\n{config}\n```").unwrap();
+            writeln!(output).unwrap();
+        }
+
+        output
+    }
+
+    /// Generates a single HTML page with the charts embedded as SVG
+    pub fn to_html(&self) -> String {
+        let mut output = String::new();
+        let title = escape_html(&self.title);
+
+        writeln!(output, "<!DOCTYPE html>").unwrap();
+        writeln!(output, "<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+        writeln!(output, "<title>{title}</title>").unwrap();
+        writeln!(
+            output,
+            "<style>body {{ font-family: sans-serif; max-width: 50em; margin: auto; }} \
+             table {{ border-collapse: collapse; }} \
+             td, th {{ border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }}</style>"
+        )
+        .unwrap();
+        writeln!(output, "</head>\n<body>").unwrap();
+        writeln!(output, "<h1>{title}</h1>").unwrap();
+
+        writeln!(output, "<h2>Summary</h2>\n<table>").unwrap();
+        for (name, value) in self.get_summary() {
+            writeln!(
+                output,
+                "<tr><th>{name}</th><td>{}</td></tr>",
+                escape_html(&value)
+            )
+            .unwrap();
+        }
+        writeln!(output, "</table>").unwrap();
+
+        writeln!(output, "<h2>Reorganizations</h2>").unwrap();
+        if self.reorg_histogram.is_empty() {
+            writeln!(output, "<p>No reorganizations happened.</p>").unwrap();
+        } else {
+            writeln!(output, "<table>\n<tr><th>Depth</th><th>Count</th></tr>").unwrap();
+            for (depth, count) in self.reorg_histogram.iter() {
+                writeln!(output, "<tr><td>{depth}</td><td>{count}</td></tr>").unwrap();
+            }
+            writeln!(output, "</table>").unwrap();
+        }
+
+        writeln!(output, "<h2>Block Propagation</h2>\n<table>").unwrap();
+        writeln!(
+            output,
+            "<tr><th>Nodes reached</th><th>Average delay (ms)</th></tr>"
+        )
+        .unwrap();
+        for fraction in PROPAGATION_FRACTIONS {
+            let delay = self.propagation.get_avg_delay_until(fraction);
+            writeln!(
+                output,
+                "<tr><td>{:.0}%</td><td>{delay:.2}</td></tr>",
+                fraction * 100.0
+            )
+            .unwrap();
+        }
+        writeln!(output, "</table>").unwrap();
+        writeln!(
+            output,
+            "<p>Fraction of nodes reached over time (averaged over all blocks)</p>"
+        )
+        .unwrap();
+        output.push_str(&self.get_propagation_chart());
+        writeln!(output, "<table>\n<tr><th>Hops</th><th>Nodes</th></tr>").unwrap();
+        for (hops, count) in self.propagation.get_hop_histogram().iter().enumerate() {
+            writeln!(output, "<tr><td>{hops}</td><td>{count}</td></tr>").unwrap();
+        }
+        writeln!(output, "</table>").unwrap();
+
+        writeln!(output, "<h2>Clients</h2>\n<table>").unwrap();
+        let (group_name, groups) = self.get_client_groups();
+        writeln!(
+            output,
+            "<tr><th>{group_name}</th><th>Clients</th><th>Transactions</th>\
+             <th>Average latency (ms)</th></tr>"
+        )
+        .unwrap();
+        for (group, num_clients, num_txns, latency) in groups {
+            writeln!(
+                output,
+                "<tr><td>{group}</td><td>{num_clients}</td><td>{num_txns}</td>\
+                 <td>{latency:.2}</td></tr>"
+            )
+            .unwrap();
+        }
+        writeln!(output, "</table>").unwrap();
+        writeln!(
+            output,
+            "<details>\n<summary>All clients</summary>\n<table>\n\
+             <tr><th>Client</th><th>Account</th><th>Node</th><th>Region</th></tr>"
+        )
+        .unwrap();
+        for (idx, client) in self.clients.iter().enumerate() {
+            writeln!(
+                output,
+                "<tr><td>{idx}</td><td>{:#x}</td><td>{}</td><td>{}</td></tr>",
+                client.account,
+                client.node,
+                format_region(client.region)
+            )
+            .unwrap();
+        }
+        writeln!(output, "</table>\n</details>").unwrap();
+
+        writeln!(output, "<h2>Network Statistics</h2>").unwrap();
+        for series in SERIES.iter() {
+            let (min, mean, max) = self.get_range(series);
+            writeln!(
+                output,
+                "<h3>{} ({})</h3>\n<p>min={min}, mean={mean:.2}, max={max}</p>",
+                series.name, series.unit
+            )
+            .unwrap();
+            output.push_str(&self.get_chart(series, max));
+        }
+
+        writeln!(output, "<h2>Configuration</h2>").unwrap();
+        for (name, config) in self.get_configuration() {
+            writeln!(
+                output,
+                "<h3>{name}</h3>\n<pre>{}</pre>",
+                escape_html(&config)
+            )
+            .unwrap();
+        }
+
+        writeln!(output, "</body>\n</html>").unwrap();
+        output
+    }
+
+    fn get_summary(&self) -> Vec<(&'static str, String)> {
+        let metrics = &self.chain_metrics;
+        // Reports are shared, so they do not depend on the local environment
+        let format = NumberFormat::default();
+        let mut summary = vec![("Fingerprint", self.fingerprint.to_string())];
+
+        if let Some(passed) = self.passed {
+            let outcome = if passed { "passed" } else { "failed" };
+            summary.push(("Outcome", outcome.to_string()));
+        }
+
+        let num_reorgs: u64 = self.reorg_histogram.values().sum();
+        let max_reorg_depth = self.reorg_histogram.keys().last().copied().unwrap_or(0);
+
+        summary.extend([
+            ("Timeout", format!("{:?}", self.timeout)),
+            ("Elapsed", format.duration(metrics.elapsed)),
+            ("Blocks mined", format.integer(metrics.total_blocks_mined)),
+            (
+                "Blocks accepted",
+                format.integer(metrics.total_blocks_accepted),
+            ),
+            (
+                "Longest chain",
+                format.integer(metrics.longest_chain_length),
+            ),
+            (
+                "Throughput (txns/s)",
+                format!("{:.2}", metrics.get_throughput()),
+            ),
+            (
+                "Block rate (blocks/s)",
+                format!("{:.4}", metrics.get_block_rate()),
+            ),
+            (
+                "Orphan rate (blocks/s)",
+                format!("{:.4}", metrics.get_orphan_rate()),
+            ),
+            (
+                "Average block interval (s)",
+                format!("{:.2}", metrics.avg_block_interval),
+            ),
+            (
+                "Average latency (ms)",
+                format!("{:.2}", metrics.avg_latency),
+            ),
+            (
+                "Average block propagation",
+                format!("{:.2}", metrics.avg_block_propagation),
+            ),
+            (
+                "Average block size",
+                format!("{:.2}", metrics.avg_block_size),
+            ),
+            (
+                "Network messages",
+                format.integer(metrics.num_network_messages),
+            ),
+            ("Reorganizations", num_reorgs.to_string()),
+            ("Deepest reorganization", max_reorg_depth.to_string()),
+        ]);
+
+        summary
+    }
+
+    /// Clients grouped by region (if known) or by node
+    /// Returns the group name and, for each group, its number of clients,
+    /// committed transactions, and average latency
+    fn get_client_groups(&self) -> (&'static str, Vec<(u32, usize, u64, f64)>) {
+        let by_region = self.clients.iter().all(|client| client.region.is_some());
+
+        let mut groups: BTreeMap<u32, (usize, u64, f64)> = BTreeMap::new();
+        for client in self.clients.iter() {
+            let key = if by_region {
+                client.region.unwrap()
+            } else {
+                client.node
+            };
+
+            let (num_clients, num_txns, total_latency) = groups.entry(key).or_default();
+            *num_clients += 1;
+            *num_txns += client.num_transactions;
+            *total_latency += client.avg_latency * (client.num_transactions as f64);
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|(key, (num_clients, num_txns, total_latency))| {
+                let latency = if num_txns == 0 {
+                    0.0
+                } else {
+                    total_latency / (num_txns as f64)
+                };
+                (key, num_clients, num_txns, latency)
+            })
+            .collect();
+
+        let name = if by_region { "Region" } else { "Node" };
+        (name, groups)
+    }
+
+    fn get_configuration(&self) -> [(&'static str, String); 2] {
+        let pretty = ron::ser::PrettyConfig::default();
+        let protocol = ron::ser::to_string_pretty(&self.protocol, pretty.clone())
+            .unwrap_or_else(|_| format!("{:#?}", self.protocol));
+        let network = ron::ser::to_string_pretty(&self.network, pretty)
+            .unwrap_or_else(|_| format!("{:#?}", self.network));
+
+        [("Protocol", protocol), ("Network", network)]
+    }
+
+    /// Minimum, mean, and maximum of a statistic over the entire run
+    fn get_range(&self, series: &Series) -> (u64, f64, u64) {
+        if self.statistics.is_empty() {
+            return (0, 0.0, 0);
+        }
+
+        let values = self.statistics.iter().map(|(_, stats)| (series.get)(stats));
+        let min = values.clone().min().unwrap();
+        let max = values.clone().max().unwrap();
+        let mean = (values.sum::<u64>() as f64) / (self.statistics.len() as f64);
+
+        (min, mean, max)
+    }
+
+    /// Plots a statistic as an SVG line chart
+    fn get_chart(&self, series: &Series, max: u64) -> String {
+        let (Some((start, _)), Some((end, _))) = (self.statistics.first(), self.statistics.last())
+        else {
+            return "<p>No data points were collected.</p>\n".to_string();
+        };
+
+        let start = start.to_millis() as f64;
+        let length = (end.to_millis() as f64 - start).max(1.0);
+        let max = (max as f64).max(1.0);
+
+        let points: Vec<_> = self
+            .statistics
+            .iter()
+            .map(|(time, stats)| {
+                let x = (time.to_millis() as f64 - start) / length;
+                let y = ((series.get)(stats) as f64) / max;
+                (x, y)
+            })
+            .collect();
+
+        make_svg_chart(&points)
+    }
+
+    /// Plots the average delay until a block reached a certain fraction of nodes
+    fn get_propagation_chart(&self) -> String {
+        if self.propagation.blocks.is_empty() {
+            return "<p>No blocks were propagated.</p>\n".to_string();
+        }
+
+        let curve: Vec<_> = (1..=20)
+            .map(|step| {
+                let fraction = (step as f64) / 20.0;
+                (self.propagation.get_avg_delay_until(fraction), fraction)
+            })
+            .collect();
+
+        let max_delay = curve.iter().map(|(delay, _)| *delay).fold(1.0, f64::max);
+
+        let points: Vec<_> = curve
+            .into_iter()
+            .map(|(delay, fraction)| (delay / max_delay, fraction))
+            .collect();
+
+        make_svg_chart(&points)
+    }
+}
+
+/// Draws a line through the given points
+/// Coordinates are expected to be between zero and one.
+fn make_svg_chart(points: &[(f64, f64)]) -> String {
+    let points: Vec<_> = points
+        .iter()
+        .map(|(x, y)| {
+            let x = x * CHART_WIDTH;
+            let y = CHART_HEIGHT - y * CHART_HEIGHT;
+            format!("{x:.1},{y:.1}")
+        })
+        .collect();
+
+    format!(
+        "<svg width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" \
+         viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\" style=\"border: 1px solid #ccc\">\
+         <polyline fill=\"none\" stroke=\"steelblue\" stroke-width=\"1.5\" points=\"{}\"/>\
+         </svg>\n",
+        points.join(" ")
+    )
+}
+
+fn format_region(region: Option<u32>) -> String {
+    region.map_or_else(|| "-".to_string(), |region| region.to_string())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use asim::time::{Duration, Time};
+
+    use super::{ReportFormat, RunReport};
+    use crate::config::{NetworkConfiguration, ProtocolConfiguration, TimeoutConfig};
+    use crate::fingerprint::FingerprintBuilder;
+    use crate::metrics::{BlockPropagation, ChainMetrics, ClientInfo, PropagationStatistics};
+    use crate::stats::GlobalStatistics;
+
+    fn make_report() -> RunReport {
+        let chain_metrics = ChainMetrics {
+            total_blocks_mined: 12,
+            total_blocks_accepted: 10,
+            elapsed: Duration::from_seconds(60),
+            ..Default::default()
+        };
+
+        let statistics = (0..3)
+            .map(|secs| {
+                let stats = GlobalStatistics {
+                    reorgs: secs,
+                    ..Default::default()
+                };
+                (Time::from_seconds(secs), stats)
+            })
+            .collect();
+
+        RunReport {
+            title: "Test <run>".to_string(),
+            protocol: ProtocolConfiguration::SpeedTest {
+                send_speed: 10,
+                pattern: Default::default(),
+            },
+            network: NetworkConfiguration::default(),
+            timeout: TimeoutConfig::Seconds {
+                warmup: 0,
+                runtime: 60,
+            },
+            fingerprint: FingerprintBuilder::default().finish(),
+            passed: Some(true),
+            chain_metrics,
+            reorg_histogram: BTreeMap::from([(1, 2), (3, 1)]),
+            propagation: PropagationStatistics {
+                blocks: vec![BlockPropagation {
+                    identifier: 1,
+                    num_nodes: 4,
+                    delays: [0, 10, 20, 40].map(Duration::from_millis).to_vec(),
+                    hop_counts: vec![1, 2, 1],
+                    first_seen: None,
+                }],
+            },
+            clients: vec![
+                ClientInfo {
+                    account: 0xa,
+                    node: 0,
+                    region: Some(1),
+                    num_transactions: 10,
+                    avg_latency: 100.0,
+                },
+                ClientInfo {
+                    account: 0xb,
+                    node: 1,
+                    region: Some(1),
+                    num_transactions: 30,
+                    avg_latency: 200.0,
+                },
+                ClientInfo {
+                    account: 0xc,
+                    node: 2,
+                    region: Some(0),
+                    num_transactions: 0,
+                    avg_latency: 0.0,
+                },
+            ],
+            statistics,
+        }
+    }
+
+    #[test]
+    fn report_markdown() {
+        let markdown = make_report().to_markdown();
+
+        assert!(markdown.starts_with("# Test <run>"));
+        assert!(markdown.contains("| Outcome | passed |"));
+        assert!(markdown.contains("| Reorganizations | 3 |"));
+        assert!(markdown.contains("| Deepest reorganization | 3 |"));
+        assert!(markdown.contains("| Reorganizations (per second) | 0 | 1.00 | 2 |"));
+        assert!(markdown.contains("| 50% | 10.00 |"));
+        assert!(markdown.contains("| 100% | 40.00 |"));
+        assert!(markdown.contains("| Hops | Nodes |\n|---|---|\n| 0 | 1 |\n| 1 | 2 |\n| 2 | 1 |"));
+        assert!(markdown.contains("| Region | Clients | Transactions | Average latency (ms) |"));
+        assert!(markdown.contains("| 0 | 1 | 0 | 0.00 |\n| 1 | 2 | 40 | 175.00 |"));
+        assert!(markdown.contains("| 1 | 0xb | 1 | 1 |"));
+        assert!(markdown.contains("SpeedTest"));
+    }
+
+    #[test]
+    fn report_html() {
+        let html = make_report().to_html();
+
+        assert!(html.contains("<title>Test &lt;run&gt;</title>"));
+        assert_eq!(html.matches("<svg ").count(), 4);
+        assert!(html.contains("<tr><td>1</td><td>2</td></tr>"));
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ReportFormat::from_path(Path::new("run.md")),
+            Some(ReportFormat::Markdown)
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("run.html")),
+            Some(ReportFormat::Html)
+        );
+        assert_eq!(ReportFormat::from_path(Path::new("run.pdf")), None);
+    }
+}
@@ -1,9 +1,13 @@
 /// This module contains helper classes to run simulation
 ///
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::io::Write;
-use std::sync::Arc;
+use std::ops::RangeInclusive;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
 
 use anyhow::Context;
 
@@ -11,18 +15,26 @@ use fast_float::parse;
 
 use parking_lot::{Condvar, Mutex};
 
-use asim::time::Time;
+use asim::time::{Duration, Time};
 
+use crate::block_graph::BlockGraphFormat;
 use crate::config::{
-    Constraint, ExperimentConfiguration, FailureConfig, Interval, ParameterType, ParameterValue,
-    TestConfiguration,
+    Checkpoint, Constraint, ExperimentConfiguration, FailureConfig, Interval, NetworkConfiguration,
+    ParameterType, ParameterValue, ProtocolConfiguration, ScenarioAction, ScenarioConfiguration,
+    TestConfiguration, TimeoutConfig,
 };
+use crate::events::StatisticsEvent;
 use crate::failures::Failures;
+use crate::format::NumberFormat;
 use crate::library::Library;
 use crate::message::MessageType;
-use crate::metrics::MetricType;
+use crate::metrics::{ChainMetricType, ChainMetrics, MetricType};
 use crate::object::ObjectId;
+use crate::report::{ReportFormat, RunReport};
+use crate::schema::{ConfigSnapshot, SCHEMA_VERSION};
 use crate::simulation::Simulation;
+use crate::stats::GlobalStatistics;
+use crate::validation::{find_setup_problems, report_problems, validate_setup, validate_timeout};
 
 struct MessageLogger {
     file: Mutex<File>,
@@ -44,32 +56,71 @@ impl MessageLogger {
 /// Runs a specific setup forever
 pub struct EndlessRunner {
     simulation: Simulation,
+    protocol: ProtocolConfiguration,
+    num_nodes: u32,
+    /// Where to write the block graph to once the simulation is stopped
+    block_graph_file: Option<String>,
+    /// How often to log a summary of the chain's progress (in virtual time)
+    summary_interval: Option<Duration>,
+    /// Stop by itself once this is reached, instead of only on Ctrl-C
+    timeout: Option<TimeoutConfig>,
 }
 
+/// How often to check for summaries and stop conditions (in real time)
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
 impl EndlessRunner {
+    /// Uses the given network and protocol of `library`, e.g., `Library::builtin()`
     pub fn new(
-        library_path: &str,
+        library: &Library,
         network_name: &str,
         protocol_name: &str,
         failures: Option<FailureConfig>,
         overwrites: Vec<(ParameterType, ParameterValue)>,
         stats_file: Option<String>,
+        block_graph_file: Option<String>,
     ) -> anyhow::Result<Self> {
-        let library = Library::new(library_path)?;
+        if let Some(path) = &block_graph_file
+            && BlockGraphFormat::from_path(Path::new(path)).is_none()
+        {
+            anyhow::bail!("Block graph file \"{path}\" must end with .dot, .gv, or .graphml");
+        }
 
         let mut protocol = library.get_protocol(protocol_name)?.clone();
         let mut network = library.get_network(network_name)?.clone();
 
-        let failures = Failures::new(network.num_nodes(), failures);
-
+        validate_setup(&protocol, &network, failures.as_ref(), &overwrites)?;
         for (param, val) in overwrites {
             protocol.set(&param, val);
             network.set(&param, val);
         }
 
-        let simulation = Simulation::new(protocol, network, failures, stats_file)?;
+        let failures = Failures::new(&protocol, &network, failures, network.get_seed());
+
+        let num_nodes = network.num_nodes();
+        let simulation = Simulation::new(protocol.clone(), network, failures, stats_file)?;
+
+        Ok(Self {
+            simulation,
+            protocol,
+            num_nodes,
+            block_graph_file,
+            summary_interval: None,
+            timeout: None,
+        })
+    }
+
+    /// Log block rate, throughput, and mempool size periodically
+    pub fn with_summary_interval(mut self, interval: Duration) -> Self {
+        self.summary_interval = Some(interval);
+        self
+    }
 
-        Ok(Self { simulation })
+    /// Stop after the specified (virtual) time or number of blocks
+    pub fn with_timeout(mut self, timeout: TimeoutConfig) -> anyhow::Result<Self> {
+        validate_timeout(&self.protocol, &timeout)?;
+        self.timeout = Some(timeout);
+        Ok(self)
     }
 
     /// Spawn simulation in a dedicated task
@@ -82,7 +133,16 @@ impl EndlessRunner {
         self.simulation.stop();
     }
 
+    pub fn get_simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// Runs until Ctrl-C is pressed or the timeout (if any) is reached
     pub fn run_until_ctrlc(&self) {
+        if let Some(timeout) = self.timeout {
+            self.simulation.set_timeout(timeout);
+        }
+
         self.start();
 
         let stop_flag = Arc::new(Mutex::new(false));
@@ -100,15 +160,106 @@ impl EndlessRunner {
             .expect("Error setting Ctrl-C handler");
         }
 
-        {
-            log::info!("Waiting for Ctrl-C...");
-            let mut flag = stop_flag.lock();
-            while !*flag {
-                stop_cond.wait(&mut flag);
+        std::thread::scope(|scope| {
+            // Also wake up if the simulation stopped by itself
+            scope.spawn(|| {
+                if let Err(err) = self.simulation.wait_for_stop() {
+                    log::error!("{err:#}");
+                }
+                *stop_flag.lock() = true;
+                stop_cond.notify_all();
+            });
+
+            {
+                log::info!("Waiting for Ctrl-C...");
+                let mut summary = self
+                    .summary_interval
+                    .map(|interval| (interval, Summary::new(&self.simulation)));
+                let mut flag = stop_flag.lock();
+
+                while !*flag {
+                    stop_cond.wait_for(&mut flag, POLL_INTERVAL);
+
+                    if !*flag && let Some((interval, summary)) = &mut summary {
+                        summary.update(&self.simulation, self.num_nodes, *interval);
+                    }
+                }
             }
+
+            if let Some(path) = &self.block_graph_file {
+                let graph = self.simulation.get_block_graph();
+
+                match graph.write_to_file(Path::new(path)) {
+                    Ok(()) => log::info!("Exported {} block(s) to {path}", graph.blocks.len()),
+                    Err(err) => log::error!("Failed to export block graph: {err:?}"),
+                }
+            }
+
+            self.stop();
+        });
+    }
+}
+
+/// The chain's progress at the time of the last summary
+struct Summary {
+    time: Time,
+    num_blocks: u64,
+    num_transactions: u64,
+}
+
+impl Summary {
+    /// Consider the entire chain
+    const TIMEOUT: TimeoutConfig = TimeoutConfig::Blocks {
+        warmup: 0,
+        runtime: u64::MAX,
+    };
+
+    fn new(simulation: &Simulation) -> Self {
+        let metrics = simulation.get_chain_metrics(Self::TIMEOUT);
+
+        Self {
+            time: simulation.get_current_time(),
+            num_blocks: metrics.longest_chain_length,
+            num_transactions: metrics.num_transactions,
+        }
+    }
+
+    /// Logs the progress since the last summary, if at least `interval` has passed
+    fn update(&mut self, simulation: &Simulation, num_nodes: u32, interval: Duration) {
+        let now = simulation.get_current_time();
+        let elapsed = now - self.time;
+        if elapsed < interval {
+            return;
         }
 
-        self.stop();
+        let metrics = simulation.get_chain_metrics(Self::TIMEOUT);
+        let elapsed = elapsed.as_seconds_f64();
+        let block_rate =
+            metrics.longest_chain_length.saturating_sub(self.num_blocks) as f64 / elapsed;
+        let throughput = metrics
+            .num_transactions
+            .saturating_sub(self.num_transactions) as f64
+            / elapsed;
+
+        let total_mempool: usize = (0..num_nodes)
+            .map(|idx| simulation.get_mempool(idx).len())
+            .sum();
+        let avg_mempool = (total_mempool as f64) / (num_nodes as f64);
+
+        let format = NumberFormat::from_env();
+        log::info!(
+            "{} elapsed: {} blocks/s, {} txns/s, {} transactions per mempool",
+            format.time(now),
+            format.decimal(block_rate, 3),
+            format.decimal(throughput, 1),
+            format.decimal(avg_mempool, 1)
+        );
+
+        *self = Self {
+            time: now,
+            num_blocks: metrics.longest_chain_length,
+            num_transactions: metrics.num_transactions,
+        };
     }
 }
 
@@ -116,34 +267,339 @@ impl EndlessRunner {
 //TODO better error handling
 pub struct TestRunner {
     simulation: Simulation,
+    test_name: String,
     test: TestConfiguration,
+    protocol: ProtocolConfiguration,
+    network: NetworkConfiguration,
+    baseline_path: PathBuf,
+    update_baseline: bool,
+    /// Where to write a summary of the run to (if anywhere)
+    report_file: Option<String>,
+    /// The network statistics collected for the report
+    statistics: Arc<Mutex<Vec<(Time, GlobalStatistics)>>>,
+}
+
+/// Metric values recorded by a previous test run
+type Baseline = BTreeMap<String, f64>;
+
+/// The peak resident set size of this process (in KiB)
+///
+/// Experiment steps run as threads of the same process, so with parallelism
+/// this is an upper bound for the memory used by a single step.
+/// Returns None on platforms without procfs.
+fn get_peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Returns all values of a metric, starting at the data point with index `start`
+/// (only relevant for time series)
+fn get_samples(
+    simulation: &Simulation,
+    metric: &MetricType,
+    chain_metrics: &ChainMetrics,
+    start: usize,
+) -> Vec<f64> {
+    match metric {
+        MetricType::Chain(cmetric) => vec![chain_metrics.get(cmetric)],
+        MetricType::Network(nmetric) => vec![simulation.get_network_metric(*nmetric)],
+        MetricType::Statistic(statistic) => simulation
+            .get_statistics_history()
+            .iter()
+            .skip(start)
+            .map(|data_point| data_point.get(statistic))
+            .collect(),
+    }
+}
+
+fn mean(samples: &[f64]) -> f64 {
+    samples.iter().sum::<f64>() / (samples.len() as f64)
+}
+
+/// Checks a constraint against all samples of a metric and logs any violation
+fn check_constraint(metric: &MetricType, constraint: &Constraint, samples: &[f64]) -> bool {
+    if samples.is_empty() {
+        log::error!("Metric \"{metric}\" has no samples");
+        return false;
+    }
+
+    match *constraint {
+        Constraint::InRange { min, max } => {
+            let value = mean(samples);
+            if value < min || value > max {
+                log::error!("Metric \"{metric}\" not in range [{min}, {max}]; was {value}");
+                return false;
+            }
+        }
+        Constraint::GreaterThan(min) => {
+            let value = mean(samples);
+            if value <= min {
+                log::error!("Metric \"{metric}\" is not greater than {min}; was {value}");
+                return false;
+            }
+        }
+        Constraint::AlwaysInRange { min, max } => {
+            if let Some((idx, value)) = samples
+                .iter()
+                .enumerate()
+                .find(|(_, value)| **value < min || **value > max)
+            {
+                log::error!(
+                    "Metric \"{metric}\" left range [{min}, {max}] at sample {idx}; was {value}"
+                );
+                return false;
+            }
+        }
+        Constraint::Stable { max_deviation } => {
+            let expected = mean(samples);
+            if let Some((idx, value)) = samples
+                .iter()
+                .enumerate()
+                .find(|(_, value)| relative_deviation(**value, expected) > max_deviation)
+            {
+                log::error!(
+                    "Metric \"{metric}\" deviated from its mean {expected} by more than {:.2}% at sample {idx}; was {value}",
+                    max_deviation * 100.0
+                );
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// How far a value is off from the expected value, relative to the expected value
+fn relative_deviation(value: f64, expected: f64) -> f64 {
+    if expected == 0.0 {
+        value.abs()
+    } else {
+        ((value - expected) / expected).abs()
+    }
 }
 
 impl TestRunner {
+    /// Set up a test
+    ///
+    /// If `update_baseline` is set, the results of this run will
+    /// replace the stored baseline instead of being compared against it.
+    /// If `report_file` is set, a Markdown or HTML summary is written there after the run.
     pub fn new(
         library_path: &str,
         test_name: &str,
         stats_file: Option<String>,
+        update_baseline: bool,
+        report_file: Option<String>,
     ) -> anyhow::Result<Self> {
+        if let Some(path) = &report_file
+            && ReportFormat::from_path(Path::new(path)).is_none()
+        {
+            anyhow::bail!("Report file \"{path}\" must end with .md, .markdown, .html, or .htm");
+        }
+
         let library = Library::new(library_path)?;
 
         let test = library.get_test(test_name).clone();
+        if test.allowed_failures >= test.repetitions {
+            anyhow::bail!(
+                "Test \"{test_name}\" allows {} failure(s) but only runs {} time(s)",
+                test.allowed_failures,
+                test.repetitions
+            );
+        }
+
         let protocol = library.get_protocol(&test.protocol)?.clone();
         let network = library.get_network(&test.network)?.clone();
+        validate_setup(&protocol, &network, None, &[])?;
+        validate_timeout(&protocol, &test.timeout)?;
 
         let failures = Failures::none(network.num_nodes());
-        let simulation = Simulation::new(protocol, network, failures, stats_file)?;
+        let simulation = Simulation::new(protocol.clone(), network.clone(), failures, stats_file)?;
+
+        let baseline_path = Path::new(library_path)
+            .join("baselines")
+            .join(format!("{test_name}.json"));
+
+        if test.baseline.is_some() && !update_baseline && !baseline_path.exists() {
+            anyhow::bail!(
+                "No baseline found for test \"{test_name}\" at {baseline_path:?}. Run with --update-baseline to record one."
+            );
+        }
+
+        let statistics = Arc::new(Mutex::new(vec![]));
+
+        // Only keep the network-wide statistics, as per-node data adds up quickly
+        if report_file.is_some() {
+            let statistics = statistics.clone();
+            simulation.set_stats_event_callback(Box::new(move |event| {
+                let StatisticsEvent::Updated(snapshot) = event;
+                statistics.lock().push((snapshot.time, snapshot.global));
+            }));
+        }
+
+        Ok(Self {
+            simulation,
+            test_name: test_name.to_string(),
+            test,
+            protocol,
+            network,
+            baseline_path,
+            update_baseline,
+            report_file,
+            statistics,
+        })
+    }
+
+    /// Time series are reduced to their mean
+    fn get_metric(
+        simulation: &Simulation,
+        metric: &MetricType,
+        chain_metrics: &ChainMetrics,
+    ) -> f64 {
+        mean(&get_samples(simulation, metric, chain_metrics, 0))
+    }
+
+    fn load_baseline(&self) -> anyhow::Result<Baseline> {
+        let file = File::open(&self.baseline_path)
+            .with_context(|| format!("Failed to open baseline file {:?}", self.baseline_path))?;
+
+        let baseline = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse baseline file {:?}", self.baseline_path))?;
+
+        Ok(baseline)
+    }
+
+    fn store_baseline(&self, baseline: &Baseline) -> anyhow::Result<()> {
+        if let Some(parent) = self.baseline_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
 
-        Ok(Self { simulation, test })
+        let file = File::create(&self.baseline_path)
+            .with_context(|| format!("Failed to create baseline file {:?}", self.baseline_path))?;
+        serde_json::to_writer_pretty(file, baseline)?;
+
+        log::info!("Wrote baseline to {:?}", self.baseline_path);
+        Ok(())
     }
 
+    /// Checks that the metrics of this run are close to the stored baseline
+    fn check_baseline(&self, chain_metrics: &ChainMetrics) -> anyhow::Result<bool> {
+        let Some(config) = &self.test.baseline else {
+            return Ok(true);
+        };
+
+        let mut current = Baseline::new();
+        for metric in config.metrics.iter() {
+            current.insert(
+                metric.to_string(),
+                Self::get_metric(&self.simulation, metric, chain_metrics),
+            );
+        }
+
+        if self.update_baseline {
+            self.store_baseline(&current)?;
+            return Ok(true);
+        }
+
+        let baseline = self.load_baseline()?;
+        let mut success = true;
+
+        for (key, value) in current.iter() {
+            let Some(expected) = baseline.get(key) else {
+                log::error!("Metric \"{key}\" is not part of the baseline. Please update it.");
+                success = false;
+                continue;
+            };
+
+            let deviation = relative_deviation(*value, *expected);
+
+            if deviation > config.tolerance {
+                log::error!(
+                    "Metric \"{key}\" deviates from baseline by {:.2}%; was {value}, expected {expected}",
+                    deviation * 100.0
+                );
+                success = false;
+            } else {
+                log::debug!("Metric \"{key}\" is within tolerance of baseline");
+            }
+        }
+
+        Ok(success)
+    }
+
+    /// Runs the test (repeatedly if configured) and returns whether it passed
+    ///
+    /// The baseline and the report only consider the first run.
     pub fn run(&self) -> bool {
         let test = &self.test;
         log::info!("Test set up. Timeout is set to {:?}", test.timeout,);
 
-        let simulation = &self.simulation;
+        let (chain_metrics, passed) = self.run_once(&self.simulation);
+        let mut num_failures = u32::from(!passed);
+
+        for repetition in 1..test.repetitions {
+            log::info!("Starting run {} of {}", repetition + 1, test.repetitions);
+
+            let failures = Failures::none(self.network.num_nodes());
+            let passed = match Simulation::new(
+                self.protocol.clone(),
+                self.network.clone(),
+                failures,
+                None,
+            ) {
+                Ok(simulation) => self.run_once(&simulation).1,
+                Err(err) => {
+                    log::error!("Failed to set up run: {err}");
+                    false
+                }
+            };
+
+            if !passed {
+                num_failures += 1;
+            }
+        }
+
+        let mut success = num_failures <= test.allowed_failures;
+        if test.repetitions > 1 {
+            log::info!(
+                "{num_failures} of {} runs violated asserts ({} allowed)",
+                test.repetitions,
+                test.allowed_failures
+            );
+        }
+
+        match self.check_baseline(&chain_metrics) {
+            Ok(true) => {}
+            Ok(false) => success = false,
+            Err(err) => {
+                log::error!("Failed to compare against baseline: {err}");
+                success = false;
+            }
+        }
+
+        if let Some(path) = &self.report_file {
+            let report = self.generate_report(chain_metrics, success);
+
+            match report.write_to_file(Path::new(path)) {
+                Ok(()) => log::info!("Wrote report to {path}"),
+                Err(err) => log::error!("Failed to write report: {err:?}"),
+            }
+        }
+
+        success
+    }
 
-        simulation.run_until(test.timeout);
+    /// Runs a single simulation and checks all asserts against it
+    fn run_once(&self, simulation: &Simulation) -> (ChainMetrics, bool) {
+        let test = &self.test;
+
+        let mut success = true;
+        if let Err(err) = simulation.run_until(test.timeout) {
+            log::error!("{err:#}");
+            success = false;
+        }
         let chain_metrics = simulation.get_chain_metrics(test.timeout);
 
         log::info!("Done.");
@@ -154,36 +610,155 @@ impl TestRunner {
             "Average block interval was {}s",
             chain_metrics.avg_block_interval
         );
+        log::info!("Run fingerprint is {}", simulation.get_fingerprint());
 
-        let mut success = true;
         for assert in test.asserts.iter() {
-            let value = match assert.metric {
-                MetricType::Chain(cmetric) => chain_metrics.get(&cmetric),
-                MetricType::Network(nmetric) => simulation.get_network_metric(nmetric),
-            };
+            let samples = get_samples(simulation, &assert.metric, &chain_metrics, 0);
 
-            match assert.constraint {
-                Constraint::InRange { min, max } => {
-                    if value < min || value > max {
-                        log::error!(
-                            "Metric \"{}\" not in range [{min}, {max}]; was {value}",
-                            assert.metric
-                        );
-                        success = false;
+            if !check_constraint(&assert.metric, &assert.constraint, &samples) {
+                success = false;
+            }
+        }
+
+        (chain_metrics, success)
+    }
+
+    fn generate_report(&self, chain_metrics: ChainMetrics, passed: bool) -> RunReport {
+        RunReport {
+            title: format!("Test \"{}\"", self.test_name),
+            protocol: self.protocol.clone(),
+            network: self.network.clone(),
+            timeout: self.test.timeout,
+            fingerprint: self.simulation.get_fingerprint(),
+            passed: Some(passed),
+            chain_metrics,
+            reorg_histogram: self.simulation.get_reorg_histogram(),
+            propagation: self.simulation.get_propagation_statistics(),
+            clients: self.simulation.get_client_assignments(),
+            statistics: std::mem::take(&mut *self.statistics.lock()),
+        }
+    }
+}
+
+/// Runs a scenario of scheduled failures and checkpoints
+pub struct ScenarioRunner {
+    simulation: Simulation,
+    scenario: ScenarioConfiguration,
+}
+
+/// A step of a scenario's timeline
+enum ScenarioStep<'a> {
+    Checkpoint(&'a Checkpoint),
+    Event(&'a ScenarioAction),
+}
+
+impl ScenarioRunner {
+    pub fn new(
+        library_path: &str,
+        scenario_name: &str,
+        stats_file: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let library = Library::new(library_path)?;
+
+        let scenario = library.get_scenario(scenario_name)?.clone();
+        if scenario.checkpoints.is_empty() {
+            anyhow::bail!("Scenario \"{scenario_name}\" has no checkpoints");
+        }
+
+        let mut times: Vec<_> = scenario.checkpoints.iter().map(|cp| cp.time).collect();
+        times.sort();
+        if times[0] == 0 || times.windows(2).any(|pair| pair[0] == pair[1]) {
+            anyhow::bail!(
+                "Checkpoints of scenario \"{scenario_name}\" must be at distinct times after the start"
+            );
+        }
+
+        let protocol = library.get_protocol(&scenario.protocol)?.clone();
+        let network = library.get_network(&scenario.network)?.clone();
+        validate_setup(&protocol, &network, None, &[])?;
+
+        let failures = Failures::none(network.num_nodes());
+        let simulation = Simulation::new(protocol, network, failures, stats_file)?;
+
+        Ok(Self {
+            simulation,
+            scenario,
+        })
+    }
+
+    /// Runs until the last checkpoint and returns whether all checkpoints passed
+    pub fn run(&self) -> bool {
+        let mut timeline = vec![];
+        for checkpoint in self.scenario.checkpoints.iter() {
+            timeline.push((checkpoint.time, ScenarioStep::Checkpoint(checkpoint)));
+        }
+        for event in self.scenario.events.iter() {
+            timeline.push((event.time, ScenarioStep::Event(&event.action)));
+        }
+
+        // Stable sort, so checkpoints run before events at the same time
+        timeline.sort_by_key(|(time, _)| *time);
+
+        let simulation = &self.simulation;
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let mut last_checkpoint = 0;
+        let mut success = true;
+
+        for (time, step) in timeline {
+            simulation.step_until(Time::from_seconds(time));
+
+            match step {
+                ScenarioStep::Event(action) => {
+                    log::info!("Applying {action:?} at {time}s");
+
+                    match action {
+                        ScenarioAction::CrashNodes(nodes) => {
+                            for node_idx in nodes {
+                                simulation.crash_node(*node_idx);
+                            }
+                        }
+                        ScenarioAction::Partition(groups) => {
+                            simulation.partition_network(groups.clone());
+                        }
+                        ScenarioAction::HealPartition => simulation.heal_network(),
                     }
                 }
-                Constraint::GreaterThan(min) => {
-                    if value <= min {
-                        log::error!(
-                            "Metric \"{}\" is not greater than {min}; was {value}",
-                            assert.metric
+                ScenarioStep::Checkpoint(checkpoint) => {
+                    let timeout = TimeoutConfig::Seconds {
+                        warmup: last_checkpoint,
+                        runtime: time - last_checkpoint,
+                    };
+                    let chain_metrics = simulation.get_chain_metrics(timeout);
+
+                    let mut passed = true;
+                    for assert in checkpoint.asserts.iter() {
+                        let samples = get_samples(
+                            simulation,
+                            &assert.metric,
+                            &chain_metrics,
+                            last_checkpoint as usize,
                         );
+
+                        if !check_constraint(&assert.metric, &assert.constraint, &samples) {
+                            passed = false;
+                        }
+                    }
+
+                    if passed {
+                        log::info!("Checkpoint at {time}s passed");
+                    } else {
+                        log::error!("Checkpoint at {time}s failed");
                         success = false;
                     }
+
+                    last_checkpoint = time;
                 }
             }
         }
 
+        simulation.stop();
         success
     }
 }
@@ -196,6 +771,43 @@ pub struct ExperimentRunner {
     parallelism: usize,
     log_messages: bool,
     stats_file: Option<String>,
+    cancellation: Arc<Cancellation>,
+}
+
+/// Lets a Ctrl-C handler stop all experiment steps that are currently running
+#[derive(Default)]
+struct Cancellation {
+    cancelled: AtomicBool,
+    simulations: Mutex<Vec<Weak<Simulation>>>,
+}
+
+impl Cancellation {
+    /// Stop all running simulations and do not start new ones
+    fn cancel(&self) {
+        let simulations = self.simulations.lock();
+        self.cancelled.store(true, Ordering::SeqCst);
+
+        for simulation in simulations.iter().filter_map(Weak::upgrade) {
+            simulation.stop();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Track a simulation that has already been started
+    /// Returns false if the experiment was cancelled in the meantime
+    fn register(&self, simulation: &Arc<Simulation>) -> bool {
+        let mut simulations = self.simulations.lock();
+        if self.is_cancelled() {
+            return false;
+        }
+
+        simulations.retain(|simulation| simulation.strong_count() > 0);
+        simulations.push(Arc::downgrade(simulation));
+        true
+    }
 }
 
 struct IntervalGenerator {
@@ -279,9 +891,24 @@ impl ExperimentRunner {
         };
 
         let config = library.get_experiment(exp_name).clone();
-
-        let mut csv_file = csv::Writer::from_path(format!("results-{exp_name}.csv"))
-            .expect("Failed to open CSV file to write to");
+        Self::validate_steps(&library, &config)?;
+
+        let results_path = format!("results-{exp_name}.csv");
+        let mut csv_file =
+            csv::Writer::from_path(&results_path).expect("Failed to open CSV file to write to");
+
+        // Steps override some parameters of these, as listed in the experiment configuration
+        let snapshot = ConfigSnapshot {
+            schema_version: SCHEMA_VERSION,
+            protocol: library.get_protocol(&config.protocol)?.clone(),
+            network: library.get_network(&config.network)?.clone(),
+            secondary: None,
+            seed: None,
+            faulty_nodes: vec![],
+            asynchrony: None,
+            experiment: Some(config.clone()),
+        };
+        snapshot.write_sidecar(&results_path)?;
 
         let mut record = vec![];
         for (key, _) in config.data_ranges.iter() {
@@ -292,6 +919,15 @@ impl ExperimentRunner {
             record.push(format!("{metric}"));
         }
 
+        record.push("Fingerprint".to_string());
+        record.push("Cancelled".to_string());
+
+        if config.resource_usage {
+            record.push("WallClockSeconds".to_string());
+            record.push("PeakRssKiB".to_string());
+            record.push("ProcessedEvents".to_string());
+        }
+
         // Write header
         csv_file
             .write_record(&record)
@@ -306,9 +942,34 @@ impl ExperimentRunner {
             parallelism,
             stats_file,
             log_messages,
+            cancellation: Default::default(),
         })
     }
 
+    /// Checks the setup of every step before running any of them
+    fn validate_steps(library: &Library, config: &ExperimentConfiguration) -> anyhow::Result<()> {
+        let protocol = library.get_protocol(&config.protocol)?;
+        let network = library.get_network(&config.network)?;
+        let generator = IntervalGenerator::new(config.data_ranges.clone())?;
+
+        // Parameters do not change the kind of protocol
+        validate_timeout(protocol, &config.timeout)?;
+
+        // Many steps share the same problem, so only report each once
+        let mut problems = vec![];
+        for index in 0..generator.num_steps() {
+            let params = generator.get_step(index).expect("Invalid step");
+            for problem in find_setup_problems(protocol, network, config.failures.as_ref(), &params)
+            {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+
+        report_problems(&problems)
+    }
+
     pub fn single_step(&self, index: usize) -> anyhow::Result<()> {
         let config = self.config.clone();
         let library = self.library.clone();
@@ -323,6 +984,7 @@ impl ExperimentRunner {
             value,
             self.stats_file.clone(),
             self.log_messages,
+            &self.cancellation,
         )?;
         self.write_record(record)?;
         let mut csv_file = csv::Reader::from_path(format!("results-{name}.csv"))
@@ -344,49 +1006,87 @@ impl ExperimentRunner {
         Ok(())
     }
 
+    /// Runs all steps of the experiment
+    ///
+    /// On Ctrl-C, all running steps are stopped and their partial results
+    /// are written with the "Cancelled" column set. Remaining steps are skipped.
+    /// The same happens if a step fails, in which case its error is returned.
     pub fn run(&self) -> anyhow::Result<()> {
         log::info!("Running up to {} experiments in parallel", self.parallelism);
 
-        let parallelism = self.parallelism;
-        let config = self.config.clone();
-        let library = self.library.clone();
-
-        let mut generator = IntervalGenerator::new(self.config.data_ranges.clone())?;
-        let mut at_end = false;
-
-        while !at_end {
-            let mut tasks = vec![];
-
-            while tasks.len() < parallelism {
-                let next_value = match generator.get_next() {
-                    Some(val) => val,
-                    None => {
-                        at_end = true;
-                        break;
-                    }
-                };
-
-                let hdl = {
-                    let library = library.clone();
-                    let config = config.clone();
-                    let log_messages = self.log_messages;
-                    let stats_file = self.stats_file.clone();
+        {
+            let cancellation = self.cancellation.clone();
+            if let Err(err) = ctrlc::set_handler(move || {
+                log::warn!("Got Ctrl-C. Cancelling experiment...");
+                cancellation.cancel();
+            }) {
+                log::warn!("Failed to set Ctrl-C handler: {err}");
+            }
+        }
 
-                    std::thread::spawn(move || {
-                        Self::run_next(&library, &config, next_value, stats_file, log_messages)
+        let generator = IntervalGenerator::new(self.config.data_ranges.clone())?;
+        let num_workers = self.parallelism.min(generator.num_steps()).max(1);
+        let generator = Mutex::new(generator);
+
+        // Every worker picks up the next step as soon as it is done with the previous one,
+        // so that a slow step does not hold up the others
+        let results: Vec<anyhow::Result<()>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..num_workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            self.run_worker(&generator)
+                        }))
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Experiment step panicked")));
+
+                        // There is no point in running the remaining steps
+                        if result.is_err() {
+                            self.cancellation.cancel();
+                        }
+
+                        result
                     })
-                };
+                })
+                .collect();
+
+            log::info!("Spawned {num_workers} concurrent workers");
+
+            workers
+                .into_iter()
+                .map(|hdl| {
+                    hdl.join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Experiment worker panicked")))
+                })
+                .collect()
+        });
+
+        for result in results {
+            result?;
+        }
 
-                tasks.push(hdl);
-            }
+        if self.cancellation.is_cancelled() {
+            log::warn!("Experiment was cancelled; results are incomplete");
+        }
+
+        Ok(())
+    }
 
-            log::info!("Spawned {} concurrent experiments", tasks.len());
+    /// Runs steps until there are none left or the experiment is cancelled
+    fn run_worker(&self, generator: &Mutex<IntervalGenerator>) -> anyhow::Result<()> {
+        while !self.cancellation.is_cancelled() {
+            let Some(next_value) = generator.lock().get_next() else {
+                break;
+            };
 
-            assert!(!tasks.is_empty());
-            for hdl in tasks.into_iter() {
-                let record = hdl.join().expect("Experiment failed")?;
-                self.write_record(record)?;
-            }
+            let record = Self::run_next(
+                &self.library,
+                &self.config,
+                next_value,
+                self.stats_file.clone(),
+                self.log_messages,
+                &self.cancellation,
+            )?;
+            self.write_record(record)?;
         }
 
         Ok(())
@@ -408,6 +1108,7 @@ impl ExperimentRunner {
         params: Vec<(ParameterType, ParameterValue)>,
         stats_file: Option<String>,
         log_messages: bool,
+        cancellation: &Cancellation,
     ) -> anyhow::Result<Vec<String>> {
         let mut protocol = library.get_protocol(&config.protocol)?.clone();
         let mut network = library.get_network(&config.network)?.clone();
@@ -420,9 +1121,15 @@ impl ExperimentRunner {
 
         log::info!("Running next step with {params:#?}");
 
-        let failures = Failures::new(network.num_nodes(), config.failures.clone());
+        let failures = Failures::new(
+            &protocol,
+            &network,
+            config.failures.clone(),
+            network.get_seed(),
+        );
         let simulation = Simulation::new(protocol, network, failures, stats_file)
             .with_context(|| "Failed to initialize simulation")?;
+        let simulation = Arc::new(simulation);
 
         if log_messages {
             let logger = MessageLogger::new()?;
@@ -433,7 +1140,21 @@ impl ExperimentRunner {
             ));
         }
 
-        simulation.run_until(config.timeout);
+        let start = std::time::Instant::now();
+        simulation.set_timeout(config.timeout);
+        simulation.start();
+
+        // Only running simulations can be stopped
+        let cancelled = if cancellation.register(&simulation) {
+            false
+        } else {
+            simulation.stop();
+            true
+        };
+
+        simulation.wait_for_stop()?;
+        let cancelled = cancelled || cancellation.is_cancelled();
+        let wall_clock = start.elapsed();
         let metrics = simulation.get_chain_metrics(config.timeout);
 
         let mut record = vec![];
@@ -446,15 +1167,279 @@ impl ExperimentRunner {
             record.push(format!("{value}"));
         }
 
+        record.push(simulation.get_fingerprint().to_string());
+        record.push(cancelled.to_string());
+
+        if config.resource_usage {
+            record.push(format!("{}", wall_clock.as_secs_f64()));
+            record.push(
+                get_peak_rss()
+                    .map(|kib| kib.to_string())
+                    .unwrap_or_default(),
+            );
+            record.push(format!("{}", simulation.get_num_processed_events()));
+        }
+
         Ok(record)
     }
 }
 
+/// The metrics aggregated by a seed sweep
+const SWEEP_METRICS: [ChainMetricType; 6] = [
+    ChainMetricType::Throughput,
+    ChainMetricType::Latency,
+    ChainMetricType::BlockInterval,
+    ChainMetricType::WinRate,
+    ChainMetricType::OrphanRate,
+    ChainMetricType::BlockPropagationDelay,
+];
+
+/// A metric aggregated over all runs of a seed sweep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepSummary {
+    pub metric: ChainMetricType,
+    pub mean: f64,
+    /// The sample standard deviation (zero if there was only one run)
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl SweepSummary {
+    fn new(metric: ChainMetricType, samples: &[f64]) -> Self {
+        let mean = mean(samples);
+
+        let std_dev = if samples.len() > 1 {
+            let sum_sq: f64 = samples.iter().map(|s| (s - mean).powi(2)).sum();
+            (sum_sq / (samples.len() - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Self {
+            metric,
+            mean,
+            std_dev,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Runs the same network and protocol once for every seed in a range
+///
+/// This is a lighter-weight alternative to experiments for studying
+/// how much results vary between runs. Each run is written to a CSV file.
+pub struct SeedSweepRunner {
+    protocol: ProtocolConfiguration,
+    network: NetworkConfiguration,
+    failures: Option<FailureConfig>,
+    seeds: RangeInclusive<u64>,
+    timeout: TimeoutConfig,
+    parallelism: usize,
+    csv_file: Mutex<csv::Writer<File>>,
+    cancellation: Arc<Cancellation>,
+}
+
+impl SeedSweepRunner {
+    pub fn new(
+        library: &Library,
+        network_name: &str,
+        protocol_name: &str,
+        failures: Option<FailureConfig>,
+        overwrites: Vec<(ParameterType, ParameterValue)>,
+        seeds: RangeInclusive<u64>,
+        timeout: TimeoutConfig,
+    ) -> anyhow::Result<Self> {
+        if seeds.is_empty() {
+            anyhow::bail!("Seed range {seeds:?} is empty");
+        }
+
+        let mut protocol = library.get_protocol(protocol_name)?.clone();
+        let mut network = library.get_network(network_name)?.clone();
+
+        validate_setup(&protocol, &network, failures.as_ref(), &overwrites)?;
+        validate_timeout(&protocol, &timeout)?;
+        for (param, val) in overwrites {
+            protocol.set(&param, val);
+            network.set(&param, val);
+        }
+
+        let results_path = format!("results-seeds-{network_name}-{protocol_name}.csv");
+        let mut csv_file = csv::Writer::from_path(&results_path)
+            .with_context(|| format!("Failed to open \"{results_path}\""))?;
+
+        // Every run uses a different seed
+        let snapshot = ConfigSnapshot {
+            schema_version: SCHEMA_VERSION,
+            protocol: protocol.clone(),
+            network: network.clone(),
+            secondary: None,
+            seed: None,
+            faulty_nodes: vec![],
+            asynchrony: None,
+            experiment: None,
+        };
+        snapshot.write_sidecar(&results_path)?;
+
+        let mut record = vec!["Seed".to_string()];
+        for metric in SWEEP_METRICS.iter() {
+            record.push(format!("{metric}"));
+        }
+        record.push("Fingerprint".to_string());
+        record.push("Cancelled".to_string());
+        csv_file.write_record(&record)?;
+
+        Ok(Self {
+            protocol,
+            network,
+            failures,
+            seeds,
+            timeout,
+            parallelism: num_cpus::get(),
+            csv_file: Mutex::new(csv_file),
+            cancellation: Default::default(),
+        })
+    }
+
+    /// How many runs to execute concurrently (the number of cores by default)
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    /// Runs the simulation for every seed and aggregates the results
+    ///
+    /// On Ctrl-C, all running simulations are stopped and remaining seeds are skipped.
+    /// Cancelled runs are written to the CSV file, but not aggregated.
+    pub fn run(&self) -> anyhow::Result<Vec<SweepSummary>> {
+        {
+            let cancellation = self.cancellation.clone();
+            if let Err(err) = ctrlc::set_handler(move || {
+                log::warn!("Got Ctrl-C. Cancelling seed sweep...");
+                cancellation.cancel();
+            }) {
+                log::warn!("Failed to set Ctrl-C handler: {err}");
+            }
+        }
+
+        let num_runs = self.seeds.clone().count();
+        let num_workers = self.parallelism.min(num_runs).max(1);
+        log::info!("Running {num_runs} seeds with {num_workers} concurrent workers");
+
+        let seeds = Mutex::new(self.seeds.clone());
+        let results = Mutex::new(vec![]);
+
+        let outcomes: Vec<anyhow::Result<()>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..num_workers)
+                .map(|_| scope.spawn(|| self.run_worker(&seeds, &results)))
+                .collect();
+
+            workers
+                .into_iter()
+                .map(|hdl| hdl.join().expect("Seed sweep failed"))
+                .collect()
+        });
+
+        for outcome in outcomes {
+            outcome?;
+        }
+
+        let results = results.into_inner();
+        if results.is_empty() {
+            anyhow::bail!("No run completed");
+        } else if results.len() < num_runs {
+            log::warn!(
+                "Seed sweep was cancelled; only {} of {num_runs} runs completed",
+                results.len()
+            );
+        }
+
+        let summaries = SWEEP_METRICS
+            .iter()
+            .map(|metric| {
+                let samples: Vec<f64> = results.iter().map(|m| m.get(metric)).collect();
+                SweepSummary::new(*metric, &samples)
+            })
+            .collect();
+
+        Ok(summaries)
+    }
+
+    /// Runs seeds until there are none left or the sweep is cancelled
+    fn run_worker(
+        &self,
+        seeds: &Mutex<RangeInclusive<u64>>,
+        results: &Mutex<Vec<ChainMetrics>>,
+    ) -> anyhow::Result<()> {
+        while !self.cancellation.is_cancelled() {
+            let Some(seed) = seeds.lock().next() else {
+                break;
+            };
+
+            let (metrics, cancelled) = self.run_seed(seed)?;
+            if !cancelled {
+                results.lock().push(metrics);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_seed(&self, seed: u64) -> anyhow::Result<(ChainMetrics, bool)> {
+        log::info!("Running with seed {seed}");
+
+        let mut network = self.network.clone();
+        network.set_seed(seed);
+
+        let failures = Failures::new(&self.protocol, &network, self.failures.clone(), Some(seed));
+        let simulation = Simulation::new(self.protocol.clone(), network, failures, None)
+            .with_context(|| format!("Failed to initialize simulation with seed {seed}"))?;
+        let simulation = Arc::new(simulation);
+
+        simulation.set_timeout(self.timeout);
+        simulation.start();
+
+        // Only running simulations can be stopped
+        let cancelled = if self.cancellation.register(&simulation) {
+            false
+        } else {
+            simulation.stop();
+            true
+        };
+
+        simulation
+            .wait_for_stop()
+            .with_context(|| format!("Simulation with seed {seed} failed"))?;
+        let cancelled = cancelled || self.cancellation.is_cancelled();
+        let metrics = simulation.get_chain_metrics(self.timeout);
+
+        let mut record = vec![seed.to_string()];
+        for metric in SWEEP_METRICS.iter() {
+            record.push(format!("{}", metrics.get(metric)));
+        }
+        record.push(simulation.get_fingerprint().to_string());
+        record.push(cancelled.to_string());
+
+        let mut csv_file = self.csv_file.lock();
+        csv_file.write_record(&record)?;
+        csv_file.flush()?;
+
+        Ok((metrics, cancelled))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::config::{Interval, ParameterType, ParameterValue};
+    use crate::config::{Constraint, Interval, ParameterType, ParameterValue};
+    use crate::metrics::{ChainMetricType, MetricType};
+    use crate::schema::ConfigSnapshot;
+    use crate::stats::StatisticType;
 
-    use super::IntervalGenerator;
+    use super::{
+        ExperimentRunner, IntervalGenerator, SweepSummary, check_constraint, get_peak_rss,
+        relative_deviation,
+    };
 
     #[test]
     fn interval_generator() {
@@ -481,4 +1466,75 @@ mod tests {
         assert_eq!(last_step[0].1, ParameterValue::Int(60),);
         assert_eq!(last_step[1].1, ParameterValue::Float(1.0));
     }
+
+    #[test]
+    fn experiment_writes_every_step() {
+        let _ = env_logger::try_init();
+
+        let runner = ExperimentRunner::new("../library", "gossip", Some(2), None, false).unwrap();
+        runner.run().unwrap();
+
+        let path = "results-gossip.csv";
+        let num_rows = csv::Reader::from_path(path).unwrap().records().count();
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(ConfigSnapshot::get_sidecar_path(path)).unwrap();
+
+        // One row for every retry delay
+        assert_eq!(num_rows, 5);
+    }
+
+    #[test]
+    fn baseline_deviation() {
+        assert_eq!(relative_deviation(10.0, 10.0), 0.0);
+        assert_eq!(relative_deviation(11.0, 10.0), 0.1);
+        assert_eq!(relative_deviation(5.0, 10.0), 0.5);
+        assert_eq!(relative_deviation(0.5, 0.0), 0.5);
+    }
+
+    #[test]
+    fn time_series_constraints() {
+        let metric = MetricType::Statistic(StatisticType::MempoolSize);
+        let samples = [90.0, 100.0, 110.0, 300.0];
+
+        // The mean is within range, but one sample is not
+        let in_range = Constraint::InRange {
+            min: 0.0,
+            max: 200.0,
+        };
+        let always_in_range = Constraint::AlwaysInRange {
+            min: 0.0,
+            max: 200.0,
+        };
+        assert!(check_constraint(&metric, &in_range, &samples));
+        assert!(!check_constraint(&metric, &always_in_range, &samples));
+        assert!(check_constraint(&metric, &always_in_range, &samples[..3]));
+
+        let stable = Constraint::Stable { max_deviation: 0.2 };
+        assert!(!check_constraint(&metric, &stable, &samples));
+        assert!(check_constraint(&metric, &stable, &samples[..3]));
+
+        assert!(!check_constraint(&metric, &in_range, &[]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn peak_rss() {
+        let peak = get_peak_rss().expect("No memory usage on Linux");
+        assert!(peak > 0);
+    }
+
+    #[test]
+    fn sweep_summary() {
+        let summary = SweepSummary::new(ChainMetricType::Throughput, &[2.0, 4.0, 4.0, 6.0]);
+
+        assert_eq!(summary.mean, 4.0);
+        assert_eq!(summary.min, 2.0);
+        assert_eq!(summary.max, 6.0);
+        assert!((summary.std_dev - (8.0f64 / 3.0).sqrt()).abs() < 1e-9);
+
+        let single = SweepSummary::new(ChainMetricType::Latency, &[5.0]);
+        assert_eq!(single.std_dev, 0.0);
+        assert_eq!(single.min, single.max);
+    }
 }
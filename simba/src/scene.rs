@@ -1,20 +1,27 @@
+use crate::bridge::Bridge;
 use crate::clients::Client;
 use crate::events::{Event, LinkEvent, NodeEvent};
-use crate::link::Link;
+use crate::link::{Link, LinkStatistics};
 use crate::node::{Node, NodeIndex};
 use crate::object::{Object, ObjectId, ObjectMap};
+use crate::swap::AtomicSwap;
 use crate::{RcCell, emit_event};
 
 use std::cell::Ref;
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 pub struct Scene {
     clients: RefCell<Vec<Rc<Client>>>,
+    bridges: RefCell<Vec<Rc<Bridge>>>,
+    swaps: RefCell<Vec<Rc<AtomicSwap>>>,
     objects: RcCell<ObjectMap>,
     links: RefCell<BTreeMap<ObjectId, Rc<Link>>>,
+    link_statistics: RefCell<BTreeMap<ObjectId, Rc<LinkStatistics>>>,
     nodes: RefCell<BTreeMap<NodeIndex, Rc<Node>>>,
+    /// Links that connect two sides of a network partition
+    severed_links: RefCell<HashSet<ObjectId>>,
 }
 
 impl Default for Scene {
@@ -23,9 +30,13 @@ impl Default for Scene {
 
         Self {
             clients: RefCell::new(Default::default()),
+            bridges: RefCell::new(Default::default()),
+            swaps: RefCell::new(Default::default()),
             objects,
             links: RefCell::new(Default::default()),
+            link_statistics: RefCell::new(Default::default()),
             nodes: RefCell::new(Default::default()),
+            severed_links: RefCell::new(Default::default()),
         }
     }
 }
@@ -43,7 +54,12 @@ impl Scene {
         self.nodes.borrow_mut().insert(node_idx, node);
     }
 
-    pub(crate) fn add_link(&self, link_id: ObjectId, link: Rc<Link>) {
+    pub(crate) fn add_link(
+        &self,
+        link_id: ObjectId,
+        link: Rc<Link>,
+        statistics: Rc<LinkStatistics>,
+    ) {
         let (node1, node2) = {
             let (node1, node2) = link.get_nodes();
             (node1.get_index(), node2.get_index())
@@ -51,6 +67,9 @@ impl Scene {
 
         self.objects.borrow_mut().insert(link_id, link.clone());
         self.links.borrow_mut().insert(link_id, link);
+        self.link_statistics
+            .borrow_mut()
+            .insert(link_id, statistics);
 
         emit_event!(Event::Link {
             identifier: link_id,
@@ -63,10 +82,100 @@ impl Scene {
         self.clients.borrow_mut().push(client);
     }
 
+    pub(crate) fn add_bridge(&self, bridge: Rc<Bridge>) {
+        self.bridges.borrow_mut().push(bridge);
+    }
+
+    pub(crate) fn add_swap(&self, swap: Rc<AtomicSwap>) {
+        self.swaps.borrow_mut().push(swap);
+    }
+
+    /// Split the network into groups of nodes that cannot reach each other
+    /// All nodes not listed in any group form one more group; no groups heal the partition.
+    pub(crate) fn set_partition(&self, groups: &[Vec<NodeIndex>]) {
+        let mut group_of = HashMap::new();
+        for (group_idx, group) in groups.iter().enumerate() {
+            for node_idx in group {
+                group_of.insert(*node_idx, group_idx);
+            }
+        }
+
+        // Nodes that are not listed form their own group
+        let get_group = |idx: &NodeIndex| group_of.get(idx).copied().unwrap_or(groups.len());
+
+        let nodes = self.get_nodes();
+        for (idx, node) in nodes.iter() {
+            let unreachable = nodes
+                .iter()
+                .filter(|(peer_idx, _)| get_group(peer_idx) != get_group(idx))
+                .map(|(_, peer)| peer.get_identifier())
+                .collect();
+
+            let group = if groups.is_empty() {
+                None
+            } else {
+                Some(get_group(idx) as u32)
+            };
+
+            node.get_data().set_unreachable_peers(unreachable);
+            node.get_data().set_partition_group(group);
+        }
+
+        let severed = self
+            .get_links()
+            .iter()
+            .filter(|(_, link)| {
+                let (node1, node2) = link.get_nodes();
+                get_group(&node1.get_index()) != get_group(&node2.get_index())
+            })
+            .map(|(link_id, _)| *link_id)
+            .collect();
+        self.set_severed_links(severed);
+    }
+
+    /// Marks the given links as severed and all others as restored
+    fn set_severed_links(&self, severed: HashSet<ObjectId>) {
+        let previous = self.severed_links.replace(severed);
+        let current = self.severed_links.borrow();
+
+        for link_id in current.difference(&previous) {
+            emit_event!(Event::Link {
+                identifier: *link_id,
+                event: LinkEvent::Severed,
+            });
+        }
+
+        for link_id in previous.difference(&current) {
+            emit_event!(Event::Link {
+                identifier: *link_id,
+                event: LinkEvent::Restored,
+            });
+        }
+    }
+
+    /// Makes a node leave or (re-)join the network, so that its peers stop or resume talking to it
+    pub(crate) fn set_node_offline(&self, node_idx: NodeIndex, offline: bool) {
+        let nodes = self.nodes.borrow();
+        let node = nodes.get(&node_idx).expect("No such node");
+        let node_id = node.get_identifier();
+
+        for (idx, peer) in nodes.iter() {
+            if *idx != node_idx {
+                peer.get_data().set_peer_offline(node_id, offline);
+            }
+        }
+
+        node.get_data().set_offline(offline);
+    }
+
     pub fn get_links(&self) -> Ref<BTreeMap<ObjectId, Rc<Link>>> {
         self.links.borrow()
     }
 
+    pub(crate) fn get_link_statistics(&self) -> Ref<BTreeMap<ObjectId, Rc<LinkStatistics>>> {
+        self.link_statistics.borrow()
+    }
+
     pub fn get_nodes(&self) -> Ref<BTreeMap<NodeIndex, Rc<Node>>> {
         self.nodes.borrow()
     }
@@ -75,6 +184,14 @@ impl Scene {
         self.clients.borrow()
     }
 
+    pub fn get_bridges(&self) -> Ref<Vec<Rc<Bridge>>> {
+        self.bridges.borrow()
+    }
+
+    pub fn get_swaps(&self) -> Ref<Vec<Rc<AtomicSwap>>> {
+        self.swaps.borrow()
+    }
+
     pub fn get_node_by_index(&self, idx: &NodeIndex) -> Option<Rc<Node>> {
         self.nodes.borrow().get(idx).cloned()
     }
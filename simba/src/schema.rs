@@ -0,0 +1,364 @@
+//! Stable representations of simulation outputs for external tools
+//!
+//! The internal types change frequently, so tools such as the web dashboard
+//! should parse these records instead. `SCHEMA_VERSION` is increased whenever
+//! a change would break existing parsers.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use asim::time::Time;
+
+use serde::{Deserialize, Serialize};
+
+use crate::anomalies::Anomaly;
+use crate::config::{
+    AsynchronyConfig, ExperimentConfiguration, NetworkConfiguration, ProtocolConfiguration,
+    SecondaryChainConfiguration,
+};
+use crate::events::{BlockEvent, LinkEvent, NodeEvent};
+use crate::logic::BlockId;
+use crate::metrics::ChainMetrics;
+use crate::node::NodeIndex;
+use crate::object::ObjectId;
+use crate::stats::{GlobalStatistics, NodeStatistics, StatisticsSnapshot};
+
+/// The version of the record format defined in this module
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A single output of the simulator
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Record {
+    pub schema_version: u32,
+    /// Virtual time at which the record was created (in milliseconds)
+    pub time: u64,
+    pub data: RecordData,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum RecordData {
+    ChainMetrics(ChainMetricsRecord),
+    Statistics(StatisticsRecord),
+    Event(EventRecord),
+}
+
+/// Same as `ChainMetrics`, but with all durations in seconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ChainMetricsRecord {
+    pub total_blocks_mined: u64,
+    pub total_blocks_accepted: u64,
+    pub longest_chain_length: u64,
+    pub avg_block_interval: f64,
+    pub num_transactions: u64,
+    pub avg_latency: f64,
+    pub avg_block_propagation: f64,
+    pub elapsed: f64,
+    pub avg_block_size: f64,
+    pub num_network_messages: u64,
+    pub avg_duplicate_messages: f64,
+    #[serde(default)]
+    pub avg_validator_block_propagation: f64,
+    /// Transactions per second
+    pub throughput: f64,
+    /// Blocks per second
+    pub block_rate: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StatisticsRecord {
+    pub global: GlobalStatistics,
+    pub nodes: BTreeMap<NodeIndex, NodeStatistics>,
+    #[serde(default)]
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// Block and object identifiers are encoded as hexadecimal strings
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum EventRecord {
+    BlockCreated {
+        block: String,
+        height: u64,
+        parent: String,
+        uncles: Vec<String>,
+        num_transactions: u64,
+    },
+    Reorg {
+        old_head: String,
+        new_head: String,
+        depth: u64,
+    },
+    BlockCommitted {
+        block: String,
+        /// Time from block creation until it was committed (in seconds)
+        delay: f64,
+    },
+    NodeCreated {
+        node: NodeIndex,
+        identifier: String,
+    },
+    NodeOverloaded {
+        node: NodeIndex,
+    },
+    NodeRecovered {
+        node: NodeIndex,
+    },
+    NodeCrashed {
+        node: NodeIndex,
+    },
+    NodePartitioned {
+        node: NodeIndex,
+        /// The side of the partition the node is on (absent once healed)
+        group: Option<u32>,
+    },
+    NodeRemoved {
+        node: NodeIndex,
+    },
+    NodeJoined {
+        node: NodeIndex,
+    },
+    LinkCreated {
+        link: String,
+        node1: NodeIndex,
+        node2: NodeIndex,
+    },
+    LinkActive {
+        link: String,
+    },
+    LinkInactive {
+        link: String,
+    },
+    LinkSevered {
+        link: String,
+    },
+    LinkRestored {
+        link: String,
+    },
+}
+
+fn block_to_string(block: &BlockId) -> String {
+    format!("{block:X}")
+}
+
+/// The fully-resolved configuration of a run
+///
+/// This is written as a JSON file next to statistics and results files,
+/// so that they can still be interpreted later on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    pub schema_version: u32,
+    pub protocol: ProtocolConfiguration,
+    pub network: NetworkConfiguration,
+    pub secondary: Option<SecondaryChainConfiguration>,
+    /// The seed all random decisions were derived from
+    pub seed: Option<u64>,
+    /// The nodes that were picked to be faulty
+    pub faulty_nodes: Vec<NodeIndex>,
+    pub asynchrony: Option<AsynchronyConfig>,
+    /// Only set for experiment results, where each step changes some parameters
+    pub experiment: Option<ExperimentConfiguration>,
+}
+
+impl ConfigSnapshot {
+    /// The sidecar of an output file, e.g., `stats.config.json` for `stats.csv`
+    pub fn get_sidecar_path(output_path: &str) -> PathBuf {
+        Path::new(output_path).with_extension("config.json")
+    }
+
+    /// Writes the configuration next to the given output file
+    pub fn write_sidecar(&self, output_path: &str) -> anyhow::Result<()> {
+        let path = Self::get_sidecar_path(output_path);
+        let file = File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        log::debug!("Wrote configuration to {path:?}");
+        Ok(())
+    }
+}
+
+impl Record {
+    pub fn new(time: Time, data: RecordData) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            time: time.to_millis(),
+            data,
+        }
+    }
+}
+
+impl From<&ChainMetrics> for ChainMetricsRecord {
+    fn from(metrics: &ChainMetrics) -> Self {
+        Self {
+            total_blocks_mined: metrics.total_blocks_mined,
+            total_blocks_accepted: metrics.total_blocks_accepted,
+            longest_chain_length: metrics.longest_chain_length,
+            avg_block_interval: metrics.avg_block_interval,
+            num_transactions: metrics.num_transactions,
+            avg_latency: metrics.avg_latency,
+            avg_block_propagation: metrics.avg_block_propagation,
+            elapsed: metrics.elapsed.as_seconds_f64(),
+            avg_block_size: metrics.avg_block_size,
+            num_network_messages: metrics.num_network_messages,
+            avg_duplicate_messages: metrics.avg_duplicate_messages,
+            avg_validator_block_propagation: metrics.avg_validator_block_propagation,
+            throughput: metrics.get_throughput(),
+            block_rate: metrics.get_block_rate(),
+        }
+    }
+}
+
+impl From<&StatisticsSnapshot> for StatisticsRecord {
+    fn from(snapshot: &StatisticsSnapshot) -> Self {
+        Self {
+            global: snapshot.global.clone(),
+            nodes: snapshot.nodes.clone(),
+            anomalies: snapshot.anomalies.clone(),
+        }
+    }
+}
+
+impl EventRecord {
+    pub fn from_block_event(block: &BlockId, event: &BlockEvent) -> Self {
+        match event {
+            BlockEvent::Created {
+                height,
+                parent,
+                uncles,
+                num_transactions,
+            } => Self::BlockCreated {
+                block: block_to_string(block),
+                height: *height,
+                parent: block_to_string(parent),
+                uncles: uncles.iter().map(block_to_string).collect(),
+                num_transactions: *num_transactions as u64,
+            },
+            BlockEvent::Reorg {
+                old_head,
+                new_head,
+                depth,
+            } => Self::Reorg {
+                old_head: block_to_string(old_head),
+                new_head: block_to_string(new_head),
+                depth: *depth,
+            },
+            BlockEvent::Committed { delay } => Self::BlockCommitted {
+                block: block_to_string(block),
+                delay: delay.as_seconds_f64(),
+            },
+        }
+    }
+
+    pub fn from_node_event(node: NodeIndex, event: &NodeEvent) -> Self {
+        match event {
+            NodeEvent::Created(identifier) => Self::NodeCreated {
+                node,
+                identifier: identifier.to_string(),
+            },
+            NodeEvent::Overloaded => Self::NodeOverloaded { node },
+            NodeEvent::Recovered => Self::NodeRecovered { node },
+            NodeEvent::Crashed => Self::NodeCrashed { node },
+            NodeEvent::Partitioned { group } => Self::NodePartitioned {
+                node,
+                group: *group,
+            },
+            NodeEvent::Removed => Self::NodeRemoved { node },
+            NodeEvent::Joined => Self::NodeJoined { node },
+        }
+    }
+
+    pub fn from_link_event(link: &ObjectId, event: &LinkEvent) -> Self {
+        let link = link.to_string();
+
+        match event {
+            LinkEvent::Created { node1, node2 } => Self::LinkCreated {
+                link,
+                node1: *node1,
+                node2: *node2,
+            },
+            LinkEvent::Active => Self::LinkActive { link },
+            LinkEvent::Inactive => Self::LinkInactive { link },
+            LinkEvent::Severed => Self::LinkSevered { link },
+            LinkEvent::Restored => Self::LinkRestored { link },
+        }
+    }
+}
+
+/// Generates a JSON schema describing `Record`
+#[cfg(feature = "schema")]
+pub fn get_json_schema() -> String {
+    let schema = schemars::schema_for!(Record);
+    serde_json::to_string_pretty(&schema).expect("Failed to serialize schema")
+}
+
+#[cfg(test)]
+mod tests {
+    use asim::time::{Duration, Time};
+
+    use super::{ConfigSnapshot, EventRecord, Record, RecordData, SCHEMA_VERSION};
+    use crate::config::{NetworkConfiguration, ProtocolConfiguration};
+    use crate::events::BlockEvent;
+
+    #[test]
+    fn record_roundtrip() {
+        let event = BlockEvent::Committed {
+            delay: Duration::from_millis(1500),
+        };
+        let record = Record::new(
+            Time::from_millis(2000),
+            RecordData::Event(EventRecord::from_block_event(&0xAB, &event)),
+        );
+
+        let json = serde_json::to_string(&record).unwrap();
+        let parsed: Record = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            parsed.data,
+            RecordData::Event(EventRecord::BlockCommitted {
+                block: "AB".to_string(),
+                delay: 1.5,
+            })
+        );
+    }
+
+    #[test]
+    fn config_sidecar() {
+        let output_path = std::env::temp_dir().join("simba-sidecar-test.csv");
+        let output_path = output_path.to_str().unwrap();
+
+        let snapshot = ConfigSnapshot {
+            schema_version: SCHEMA_VERSION,
+            protocol: ProtocolConfiguration::SpeedTest {
+                send_speed: 10,
+                pattern: Default::default(),
+            },
+            network: NetworkConfiguration::default(),
+            secondary: None,
+            seed: Some(42),
+            faulty_nodes: vec![2],
+            asynchrony: None,
+            experiment: None,
+        };
+        snapshot.write_sidecar(output_path).unwrap();
+
+        let sidecar_path = ConfigSnapshot::get_sidecar_path(output_path);
+        assert!(
+            sidecar_path
+                .to_str()
+                .unwrap()
+                .ends_with("simba-sidecar-test.config.json")
+        );
+
+        let file = std::fs::File::open(&sidecar_path).unwrap();
+        let parsed: ConfigSnapshot = serde_json::from_reader(file).unwrap();
+        assert_eq!(parsed.faulty_nodes, vec![2]);
+
+        std::fs::remove_file(sidecar_path).unwrap();
+    }
+}
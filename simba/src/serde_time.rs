@@ -0,0 +1,48 @@
+//! Serialization of time types, which do not implement serde themselves
+
+use asim::time::{Duration, START_TIME, Time};
+
+/// A duration in whole microseconds
+pub(crate) fn to_micros(duration: Duration) -> u64 {
+    (duration.as_millis_f64() * 1000.0).round() as u64
+}
+
+/// A point in time as microseconds since the simulation started
+pub(crate) fn time_to_micros(time: Time) -> u64 {
+    to_micros(time - START_TIME)
+}
+
+/// The inverse of `time_to_micros`
+pub(crate) fn time_from_micros(micros: u64) -> Time {
+    START_TIME + Duration::from_micros(micros)
+}
+
+/// (De-)serializes a point in time as milliseconds
+pub mod time_millis {
+    use asim::time::Time;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &Time, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(time.to_millis())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Time, D::Error> {
+        u64::deserialize(deserializer).map(Time::from_millis)
+    }
+}
+
+/// (De-)serializes a duration as microseconds
+pub mod duration_micros {
+    use asim::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(super::to_micros(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_micros)
+    }
+}
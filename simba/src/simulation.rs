@@ -1,4 +1,5 @@
-use std::cmp::Ordering;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
@@ -12,25 +13,53 @@ use asim::time::{Duration, START_TIME, Time};
 
 use parking_lot::{Condvar, Mutex};
 
+use serde::{Deserialize, Serialize};
+
+use crate::anomalies::AnomalyThresholds;
+use crate::block_graph::{BlockDetails, BlockGraph};
+use crate::bridge::Bridge;
+use crate::churn::spawn_churn;
 use crate::clients::Client;
-use crate::config::{Connectivity, NetworkConfiguration, ProtocolConfiguration, TimeoutConfig};
+use crate::config::{
+    AtomicSwapConfig, BridgeConfig, BridgeDirection, ClientAssignment, Connectivity,
+    GenesisAccount, GenesisConfig, InboxConfig, NetworkConfiguration, OverflowPolicy,
+    PartitionWindow, ProtocolConfiguration, RegionOutage, SecondaryChainConfiguration,
+    TimeoutConfig, TransactionModel,
+};
 use crate::events::{
     BlockEvent, Command, EVENT_HANDLER, Event, LinkEvent, NodeEvent, OpRequest, OpResult,
     StatisticsEvent,
 };
 use crate::failures::Failures;
+use crate::fingerprint::{Fingerprint, FingerprintBuilder};
+use crate::ledger::genesis_output_id;
 use crate::link::create_link;
-use crate::link::{Bandwidth, Link};
+use crate::link::{Bandwidth, Link, LinkDirection, LinkDirectionStatistics};
 use crate::logic::{
-    BlockId, GlobalLogic, GossipGlobalLogic, NakamotoGlobalLogic, PbftGlobalLogic,
-    SnowballGlobalLogic, SpeedTestGlobalLogic,
+    AccountId, AccountState, BlockId, GlobalLogic, GossipGlobalLogic, NakamotoGlobalLogic,
+    NodeChainInfo, OutputId, PbftGlobalLogic, RaftGlobalLogic, SnowballGlobalLogic,
+    SnowmanGlobalLogic, SpeedTestGlobalLogic, Transaction, TransactionId, TransactionSpec,
+    TransactionStatus,
 };
+use crate::memory::MemoryUsage;
 use crate::message::MessageType;
-use crate::node::{Node, NodeIndex, create_node};
+use crate::node::{Node, NodeData, NodeIndex, create_node};
 use crate::object::{Object, ObjectId};
+use crate::random;
 use crate::scene::Scene;
+use crate::schema::{ConfigSnapshot, SCHEMA_VERSION};
 use crate::stats::{GlobalStatistics, NodeStatistics, Statistics};
-use crate::{ChainMetrics, Location, NetworkMetricType};
+use crate::swap::{AtomicSwap, SwapResults};
+use crate::topology::{
+    Topology, can_connect, connect_components, find_components, generate_sparse_network,
+    random_regular_links, small_world_links,
+};
+use crate::validation::{find_protocol_problems, report_problems};
+use crate::{
+    ChainMetrics, ChainSplitStatistics, ClientInfo, ConfirmationStatistics, FeeMarketStatistics,
+    LinkGoodput, Location, MempoolStatistics, MiningPoolStatistics, NetworkMetricType,
+    OrderingStatistics, PeerBanStatistics, PropagationStatistics,
+};
 
 pub type EventCallback<I, T> = Box<dyn Fn(I, T) + Send + Sync>;
 pub type StatsEventCallback = Box<dyn Fn(StatisticsEvent) + Send + Sync>;
@@ -58,6 +87,56 @@ enum State {
     Destroyed,
 }
 
+/// How fast the simulation is running compared to real time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SimulationSpeed {
+    /// Virtual time elapsed per unit of real time
+    /// E.g., 2.0 means the simulation runs twice as fast as real time
+    pub speedup: f64,
+    /// Timer events processed per (real) second
+    pub events_per_second: f64,
+}
+
+/// How often the measured simulation speed is updated
+const SPEED_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Log the simulation speed every N updates
+const SPEED_LOG_INTERVAL: u32 = 10;
+
+/// The initial balance of the accounts used by bridges and swaps
+const ENDPOINT_BALANCE: u64 = 1 << 48;
+
+thread_local! {
+    /// The first error that occurred while the simulation was running (if any)
+    static FAILURE: RefCell<Option<anyhow::Error>> = const { RefCell::new(None) };
+}
+
+/// Stops the simulation of the current thread with the given error
+///
+/// For errors that cannot be passed on to the caller, e.g., within protocol tasks.
+/// Only the first error is kept; all others are only logged.
+pub(crate) fn report_failure(err: anyhow::Error) {
+    log::error!("Simulation failed: {err:#}");
+
+    FAILURE.with_borrow_mut(|failure| {
+        if failure.is_none() {
+            *failure = Some(err);
+        }
+    });
+}
+
+/// Returns the value or, on error, stops the simulation and returns a placeholder
+pub(crate) fn unwrap_or_report<T: Default>(result: anyhow::Result<T>) -> T {
+    result.unwrap_or_else(|err| {
+        report_failure(err);
+        T::default()
+    })
+}
+
+fn take_failure() -> Option<anyhow::Error> {
+    FAILURE.with_borrow_mut(Option::take)
+}
+
 pub struct Simulation {
     worker_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
     handler_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
@@ -67,6 +146,9 @@ pub struct Simulation {
     command_cond: Arc<Condvar>,
     rate_limit: Arc<Mutex<Option<u32>>>,
     rate_limit_cond: Arc<Condvar>,
+    speed: Arc<Mutex<SimulationSpeed>>,
+    /// Why the simulation stopped early (if it did)
+    failure: Arc<Mutex<Option<String>>>,
     pending_operations: Arc<DashMap<u64, Arc<PendingOp>>>,
     next_op_id: AtomicU64,
     msg_sent_event_callback: Arc<OnceLock<MessageSentEventCallback>>,
@@ -76,13 +158,81 @@ pub struct Simulation {
     stats_event_callback: Arc<OnceLock<StatsEventCallback>>,
 }
 
+/// The second chain of a multi-chain simulation
+struct SecondaryChain {
+    config: SecondaryChainConfiguration,
+    genesis: Vec<GenesisAccount>,
+    /// Funded accounts for the bridge and swap endpoints on this chain
+    endpoints: Vec<GenesisAccount>,
+    failures: Failures,
+}
+
+/// The protocol logic of all chains in the simulation
+struct ChainLogic {
+    main: Rc<dyn GlobalLogic>,
+    secondary: Option<Rc<dyn GlobalLogic>>,
+}
+
+/// Everything needed to build the nodes and clients of a chain
+struct ChainSetup<'a> {
+    global_logic: &'a dyn GlobalLogic,
+    protocol_config: &'a ProtocolConfiguration,
+    network_config: &'a NetworkConfiguration,
+    genesis: &'a [GenesisAccount],
+    failures: &'a Failures,
+    /// The global index of the chain's first node
+    first_node: NodeIndex,
+}
+
+impl ChainSetup<'_> {
+    /// Clients use the genesis accounts in order, if there are any
+    /// If there are more clients than accounts, some clients share an account
+    fn get_client_account(&self, client_idx: usize) -> AccountId {
+        if self.genesis.is_empty() {
+            random::random()
+        } else {
+            self.genesis[client_idx % self.genesis.len()].account
+        }
+    }
+
+    /// Clients send funds to other genesis accounts if the protocol executes transactions
+    fn get_transfer_recipients(&self) -> (Vec<AccountId>, u64) {
+        match self.protocol_config.get_execution() {
+            Some(execution) if execution.transfer_amount > 0 => (
+                self.genesis.iter().map(|account| account.account).collect(),
+                execution.transfer_amount,
+            ),
+            _ => (vec![], 0),
+        }
+    }
+
+    /// With the UTXO model, clients start by spending the output of their genesis account
+    fn get_client_unspent_output(&self, client_idx: usize) -> Option<(OutputId, u64)> {
+        let execution = self.protocol_config.get_execution()?;
+        if execution.model != TransactionModel::Utxo || self.genesis.is_empty() {
+            return None;
+        }
+
+        let idx = client_idx % self.genesis.len();
+        Some((genesis_output_id(idx), self.genesis[idx].balance))
+    }
+}
+
 pub struct SimulationInner {
     scene: Rc<Scene>,
     protocol_config: ProtocolConfiguration,
     network_config: NetworkConfiguration,
     failures: Failures,
+    genesis: Vec<GenesisAccount>,
+    /// Funded accounts for the bridge and swap endpoints on the main chain
+    endpoints: Vec<GenesisAccount>,
+    secondary: Option<SecondaryChain>,
     rate_limit: Arc<Mutex<Option<u32>>>,
     rate_limit_cond: Arc<Condvar>,
+    /// Set once a breakpoint (see `Simulation::pause_at`) is reached
+    pause_requested: Rc<Cell<bool>>,
+    speed: Arc<Mutex<SimulationSpeed>>,
+    failure: Arc<Mutex<Option<String>>>,
     asim: Rc<asim::Runtime>,
     statistics: Rc<Statistics>,
     command_queue: Arc<Mutex<Vec<Command>>>,
@@ -110,11 +260,82 @@ impl Simulation {
         network_config: NetworkConfiguration,
         failures: Failures,
         stats_file: Option<String>,
+    ) -> anyhow::Result<Self> {
+        Self::create(protocol_config, network_config, None, failures, stats_file)
+    }
+
+    /// Sets up a simulation with a second, independent, chain
+    /// The failures only apply to the main chain
+    pub fn new_multi_chain(
+        protocol_config: ProtocolConfiguration,
+        network_config: NetworkConfiguration,
+        secondary_config: SecondaryChainConfiguration,
+        failures: Failures,
+        stats_file: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let has_cross_chain_clients =
+            !secondary_config.bridges.is_empty() || !secondary_config.swaps.is_empty();
+
+        if has_cross_chain_clients
+            && (protocol_config.get_genesis().is_none()
+                || secondary_config.protocol.get_genesis().is_none())
+        {
+            anyhow::bail!("Bridges and swaps require both chains to support transactions");
+        }
+
+        let endpoints = secondary_config
+            .bridges
+            .iter()
+            .map(|bridge| (bridge.main_node, bridge.secondary_node))
+            .chain(
+                secondary_config
+                    .swaps
+                    .iter()
+                    .map(|swap| (swap.main_node, swap.secondary_node)),
+            );
+
+        for (main_node, secondary_node) in endpoints {
+            if main_node >= network_config.num_nodes() {
+                anyhow::bail!("Non-existent main node #{main_node}");
+            }
+            if secondary_node >= secondary_config.network.num_nodes() {
+                anyhow::bail!("Non-existent secondary node #{secondary_node}");
+            }
+        }
+
+        for swap in secondary_config.swaps.iter() {
+            if swap.participant_timeout >= swap.initiator_timeout {
+                anyhow::bail!("Participant timeout must be less than the initiator timeout");
+            }
+        }
+
+        Self::create(
+            protocol_config,
+            network_config,
+            Some(secondary_config),
+            failures,
+            stats_file,
+        )
+    }
+
+    fn create(
+        protocol_config: ProtocolConfiguration,
+        network_config: NetworkConfiguration,
+        secondary_config: Option<SecondaryChainConfiguration>,
+        failures: Failures,
+        stats_file: Option<String>,
     ) -> anyhow::Result<Self> {
         log::debug!("Setting up simulation");
 
+        report_problems(&find_protocol_problems(&protocol_config))?;
+        if let Some(config) = &secondary_config {
+            report_problems(&find_protocol_problems(&config.protocol))?;
+        }
+
         let rate_limit = Arc::new(Mutex::new(None));
         let rate_limit_cond = Arc::new(Condvar::new());
+        let speed = Arc::new(Mutex::new(SimulationSpeed::default()));
+        let failure = Arc::new(Mutex::new(None));
         let state = Arc::new(Mutex::new(State::SettingUp));
         let state_cond = Arc::new(Condvar::new());
         let (event_sender, event_receiver) = mpsc::channel();
@@ -128,29 +349,72 @@ impl Simulation {
         let link_event_callback = Arc::new(OnceLock::new());
         let stats_event_callback = Arc::new(OnceLock::new());
 
+        let seed = random::resolve_seed(network_config.get_seed());
+        log::info!("Using random seed {seed}");
+
         let stats_file = if let Some(path) = stats_file {
+            let snapshot = ConfigSnapshot {
+                schema_version: SCHEMA_VERSION,
+                protocol: protocol_config.clone(),
+                network: network_config.clone(),
+                secondary: secondary_config.clone(),
+                seed: Some(seed),
+                faulty_nodes: failures.get_faulty_nodes(),
+                asynchrony: failures.get_asynchrony().cloned(),
+                experiment: None,
+            };
+            snapshot.write_sidecar(&path)?;
+
             Some(csv::Writer::from_path(path)?)
         } else {
             None
         };
 
+        let genesis = load_genesis(&protocol_config)?;
+
+        let secondary = if let Some(config) = secondary_config {
+            let genesis = load_genesis(&config.protocol)?;
+            let failures = Failures::none(config.network.num_nodes());
+
+            Some(SecondaryChain {
+                config,
+                genesis,
+                // Generated by the worker thread, so they depend on the seed
+                endpoints: vec![],
+                failures,
+            })
+        } else {
+            None
+        };
+
+        // Tells whether the protocol logic could be set up
+        let (setup_sender, setup_receiver) = mpsc::channel();
+
         let worker_thread = {
             log::debug!("Starting simulation worker thread");
 
             let rate_limit = rate_limit.clone();
             let rate_limit_cond = rate_limit_cond.clone();
+            let speed = speed.clone();
+            let failure = failure.clone();
             let state = state.clone();
             let state_cond = state_cond.clone();
             let command_queue = command_queue.clone();
             let command_cond = command_cond.clone();
 
             std::thread::spawn(move || {
+                random::set_seed(seed);
+
                 let inner = SimulationInner::new(
                     protocol_config,
                     network_config,
                     rate_limit,
                     rate_limit_cond,
+                    speed,
+                    failure,
                     failures,
+                    genesis,
+                    secondary,
                     command_queue,
                     command_cond,
                     event_sender,
@@ -158,10 +422,34 @@ impl Simulation {
                     state_cond,
                     stats_file,
                 );
-                inner.run();
+
+                match inner.initialize_chains() {
+                    Ok(logic) => {
+                        setup_sender.send(Ok(())).unwrap();
+                        inner.run(logic);
+                    }
+                    Err(err) => {
+                        setup_sender.send(Err(err)).unwrap();
+                    }
+                }
             })
         };
 
+        // The worker thread exits if the setup failed
+        match setup_receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                worker_thread.join().unwrap();
+                return Err(err);
+            }
+            Err(_) => {
+                if let Err(err) = worker_thread.join() {
+                    std::panic::resume_unwind(err);
+                }
+                anyhow::bail!("Simulation worker thread terminated during setup");
+            }
+        }
+
         let handler_thread = {
             let pending_operations = pending_operations.clone();
 
@@ -194,6 +482,8 @@ impl Simulation {
             handler_thread: Mutex::new(Some(handler_thread)),
             rate_limit,
             rate_limit_cond,
+            speed,
+            failure,
             state,
             state_cond,
             msg_sent_event_callback,
@@ -304,6 +594,11 @@ impl Simulation {
         *self.rate_limit.lock()
     }
 
+    /// The simulation speed as measured during the last second (in real time)
+    pub fn get_speed(&self) -> SimulationSpeed {
+        *self.speed.lock()
+    }
+
     pub fn start(&self) {
         let mut state = self.state.lock();
         assert_eq!(*state, State::SettingUp);
@@ -317,10 +612,27 @@ impl Simulation {
     }
 
     /// Runs until the specified timeout
-    pub fn run_until(&self, timeout: TimeoutConfig) {
-        self.issue_command(Command::SetTimeout(timeout));
+    /// Returns an error if the simulation stopped early because of a failure
+    pub fn run_until(&self, timeout: TimeoutConfig) -> anyhow::Result<()> {
+        self.set_timeout(timeout);
         self.start();
-        self.wait_for_stop();
+        self.wait_for_stop()
+    }
+
+    /// Stop the simulation once the specified timeout is reached
+    ///
+    /// Must be called before `start()`
+    pub fn set_timeout(&self, timeout: TimeoutConfig) {
+        self.issue_command(Command::SetTimeout(timeout));
+    }
+
+    /// Pause the simulation once the specified (virtual) time is reached
+    ///
+    /// All events up to that time are processed before pausing, so the
+    /// resulting state is the same for every run with the same seed.
+    /// Use `set_rate_limit` to resume afterwards.
+    pub fn pause_at(&self, time: Time) {
+        self.issue_command(Command::PauseAt(time));
     }
 
     pub fn set_block_event_callback(&self, callback: EventCallback<BlockId, BlockEvent>) {
@@ -398,6 +710,24 @@ impl Simulation {
         }
     }
 
+    /// Get the status of a transaction as seen by the specified node
+    pub fn get_transaction_status(
+        &self,
+        node_idx: NodeIndex,
+        txn_id: TransactionId,
+    ) -> TransactionStatus {
+        let result = self.issue_operation(OpRequest::TransactionStatus {
+            node: node_idx,
+            transaction: txn_id,
+        });
+
+        if let OpResult::TransactionStatus(status) = result {
+            status
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
     pub fn get_global_statistics(&self) -> GlobalStatistics {
         let result = self.issue_operation(OpRequest::GlobalStatistics);
 
@@ -408,6 +738,280 @@ impl Simulation {
         }
     }
 
+    /// Get the network-wide statistics of every second since the start (or the end of warmup)
+    pub fn get_statistics_history(&self) -> Vec<GlobalStatistics> {
+        let result = self.issue_operation(OpRequest::StatisticsHistory);
+
+        if let OpResult::StatisticsHistory(history) = result {
+            history
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get how often chain reorganizations of a specific depth happened (across all nodes)
+    pub fn get_reorg_histogram(&self) -> BTreeMap<u64, u64> {
+        let result = self.issue_operation(OpRequest::ReorgHistogram);
+
+        if let OpResult::ReorgHistogram(histogram) = result {
+            histogram
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get how long transactions waited in mempools and how many of them nodes
+    /// already had when the including block arrived (across all nodes)
+    pub fn get_mempool_statistics(&self) -> MempoolStatistics {
+        let result = self.issue_operation(OpRequest::MempoolStatistics);
+
+        if let OpResult::MempoolStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get which node each client of the main chain is attached to, in the order clients were created
+    pub fn get_client_assignments(&self) -> Vec<ClientInfo> {
+        let result = self.issue_operation(OpRequest::ClientAssignments);
+
+        if let OpResult::ClientAssignments(clients) = result {
+            clients
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the rate at which data arrived over each link (and direction) that carried traffic
+    /// Useful to validate the network model, e.g., with the speed test protocol
+    pub fn get_link_goodput(&self) -> Vec<LinkGoodput> {
+        let result = self.issue_operation(OpRequest::LinkGoodput);
+
+        if let OpResult::LinkGoodput(links) = result {
+            links
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get how quickly each block of the longest chain reached the other nodes
+    pub fn get_propagation_statistics(&self) -> PropagationStatistics {
+        let result = self.issue_operation(OpRequest::PropagationStatistics);
+
+        if let OpResult::PropagationStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the estimated memory used by each protocol structure of the specified node
+    pub fn get_node_memory_usage(&self, node_idx: NodeIndex) -> MemoryUsage {
+        let result = self.issue_operation(OpRequest::MemoryUsage(Some(node_idx)));
+
+        if let OpResult::MemoryUsage(usage) = result {
+            usage
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the estimated memory used by each protocol structure, summed over all nodes
+    /// and including structures shared by all nodes, such as the global ledger
+    pub fn get_memory_usage(&self) -> MemoryUsage {
+        let result = self.issue_operation(OpRequest::MemoryUsage(None));
+
+        if let OpResult::MemoryUsage(usage) = result {
+            usage
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the time from creation until commit for all committed blocks (in ascending order)
+    pub fn get_commit_delays(&self) -> Vec<Duration> {
+        let result = self.issue_operation(OpRequest::CommitDelays);
+
+        if let OpResult::CommitDelays(delays) = result {
+            delays
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get traffic statistics for one direction of a link
+    pub fn get_link_statistics(
+        &self,
+        link_id: ObjectId,
+        direction: LinkDirection,
+    ) -> LinkDirectionStatistics {
+        let result = self.issue_operation(OpRequest::LinkStatistics {
+            link: link_id,
+            direction,
+        });
+
+        if let OpResult::LinkStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Process the specified number of timer events and return the new time
+    /// This should only be called while the simulation is paused
+    pub fn step(&self, num_events: u64) -> Time {
+        let result = self.issue_operation(OpRequest::Step(num_events));
+
+        if let OpResult::CurrentTime(time) = result {
+            time
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Process timer events until the specified time is reached
+    /// Returns the time of the last processed event
+    pub fn step_until(&self, time: Time) -> Time {
+        let result = self.issue_operation(OpRequest::StepUntil(time));
+
+        if let OpResult::CurrentTime(time) = result {
+            time
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get all transactions in the mempool of the specified node
+    pub fn get_mempool(&self, node_idx: NodeIndex) -> Vec<TransactionId> {
+        let result = self.issue_operation(OpRequest::Mempool(node_idx));
+
+        if let OpResult::Mempool(transactions) = result {
+            transactions
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the identifier and height of the chain head as seen by the specified node
+    pub fn get_chain_head(&self, node_idx: NodeIndex) -> Option<(BlockId, u64)> {
+        let result = self.issue_operation(OpRequest::ChainHead(node_idx));
+
+        if let OpResult::ChainHead(head) = result {
+            head
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the chain head, height, and number of known forks of the specified node
+    /// Returns `None` for protocols without a chain
+    pub fn get_node_chain_info(&self, node_idx: NodeIndex) -> Option<NodeChainInfo> {
+        let result = self.issue_operation(OpRequest::NodeChainInfo(node_idx));
+
+        if let OpResult::NodeChainInfo(info) = result {
+            info
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Get the balance and nonce of an account as seen by the specified node
+    /// Returns `None` if the account does not exist or the protocol does not track state
+    pub fn get_account_state(
+        &self,
+        node_idx: NodeIndex,
+        account: AccountId,
+    ) -> Option<AccountState> {
+        let result = self.issue_operation(OpRequest::AccountState {
+            node: node_idx,
+            account,
+        });
+
+        if let OpResult::AccountState(account_state) = result {
+            account_state
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Create a new transaction and hand it to the specified node
+    /// The transaction uses the next nonce of the node's account, as seen by the node's chain head.
+    /// Returns `None` if the node has crashed or its protocol does not process transactions
+    pub fn inject_transaction(&self, node_idx: NodeIndex) -> Option<TransactionId> {
+        let result = self.issue_operation(OpRequest::InjectTransaction(node_idx));
+
+        if let OpResult::InjectedTransaction(txn_id) = result {
+            txn_id
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Hand a specific transaction to the specified node, either now or at the given time
+    ///
+    /// Unlike `inject_transaction`, this returns before the node has processed the transaction.
+    /// Use `get_transaction_status` to check whether the node accepted it.
+    pub fn submit_transaction(&self, node_idx: NodeIndex, spec: TransactionSpec) -> TransactionId {
+        let result = self.issue_operation(OpRequest::SubmitTransaction {
+            node: node_idx,
+            spec,
+        });
+
+        if let OpResult::SubmittedTransaction(txn_id) = result {
+            txn_id
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Stop the specified node from processing any messages or transactions
+    pub fn crash_node(&self, node_idx: NodeIndex) {
+        let result = self.issue_operation(OpRequest::CrashNode(node_idx));
+
+        if result != OpResult::Done {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Change when statistics data points are annotated as anomalies
+    pub fn set_anomaly_thresholds(&self, thresholds: AnomalyThresholds) {
+        let result = self.issue_operation(OpRequest::SetAnomalyThresholds(thresholds));
+
+        if result != OpResult::Done {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Split the network into groups of nodes that cannot reach each other
+    ///
+    /// All nodes not listed in any group form one more group.
+    /// Messages between groups are dropped until the partition is changed or healed.
+    pub fn partition_network(&self, groups: Vec<Vec<NodeIndex>>) {
+        let result = self.issue_operation(OpRequest::Partition(groups));
+
+        if result != OpResult::Done {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Remove any network partition
+    pub fn heal_network(&self) {
+        self.partition_network(vec![]);
+    }
+
+    /// Compute a digest of the ledger and network traffic
+    /// Two runs with the same inputs should return the same fingerprint
+    pub fn get_fingerprint(&self) -> Fingerprint {
+        let result = self.issue_operation(OpRequest::Fingerprint);
+
+        if let OpResult::Fingerprint(fingerprint) = result {
+            fingerprint
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
     fn issue_operation(&self, request: OpRequest) -> OpResult {
         let op_id = self.next_op_id.fetch_add(1, AtomicOrdering::SeqCst);
         let pending_op = Arc::new(PendingOp {
@@ -434,12 +1038,18 @@ impl Simulation {
         }
     }
 
-    pub fn wait_for_stop(&self) {
+    /// Returns an error if the simulation stopped early because of a failure
+    pub fn wait_for_stop(&self) -> anyhow::Result<()> {
         let mut state = self.state.lock();
 
         while *state != State::Stopped {
             self.state_cond.wait(&mut state);
         }
+
+        match &*self.failure.lock() {
+            Some(failure) => Err(anyhow::anyhow!("Simulation failed: {failure}")),
+            None => Ok(()),
+        }
     }
 
     fn issue_command(&self, command: Command) {
@@ -460,20 +1070,221 @@ impl Simulation {
             panic!("Got unexpected op result");
         }
     }
-}
 
-impl SimulationInner {
-    #[allow(clippy::too_many_arguments)]
-    fn new(
-        protocol_config: ProtocolConfiguration,
-        network_config: NetworkConfiguration,
-        rate_limit: Arc<Mutex<Option<u32>>>,
-        rate_limit_cond: Arc<Condvar>,
-        failures: Failures,
-        command_queue: Arc<Mutex<Vec<Command>>>,
-        command_cond: Arc<Condvar>,
-        event_sender: mpsc::Sender<(Time, Event)>,
-        state: Arc<Mutex<State>>,
+    /// Same as `get_chain_metrics` but for the second chain
+    /// Returns `None` if this is not a multi-chain simulation
+    pub fn get_secondary_chain_metrics(&self, timeout: TimeoutConfig) -> Option<ChainMetrics> {
+        let result = self.issue_operation(OpRequest::SecondaryChainMetrics(timeout));
+
+        if let OpResult::SecondaryChainMetrics(metrics) = result {
+            metrics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// The revenue of all mining pools of the main chain
+    pub fn get_mining_pool_statistics(&self) -> Vec<MiningPoolStatistics> {
+        let result = self.issue_operation(OpRequest::MiningPoolStatistics);
+
+        if let OpResult::MiningPoolStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// How often nodes of the main chain rejected blocks because they did not adopt an upgrade
+    /// Returns `None` if all nodes follow the same protocol version
+    pub fn get_chain_split_statistics(&self) -> Option<ChainSplitStatistics> {
+        let result = self.issue_operation(OpRequest::ChainSplitStatistics);
+
+        if let OpResult::ChainSplitStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// How often nodes of the main chain banned their peers, and how many of those peers were correct
+    /// Returns `None` if nodes do not ban their peers
+    pub fn get_peer_ban_statistics(&self) -> Option<PeerBanStatistics> {
+        let result = self.issue_operation(OpRequest::PeerBanStatistics);
+
+        if let OpResult::PeerBanStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Base fees and inclusion delays of the main chain
+    /// Returns `None` if the protocol does not have a fee market
+    pub fn get_fee_market_statistics(&self) -> Option<FeeMarketStatistics> {
+        let result = self.issue_operation(OpRequest::FeeMarketStatistics);
+
+        if let OpResult::FeeMarketStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// All blocks of the main chain created so far, including forks and uncles
+    pub fn get_block_graph(&self) -> BlockGraph {
+        let result = self.issue_operation(OpRequest::BlockGraph);
+
+        if let OpResult::BlockGraph(graph) = result {
+            graph
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// A single block of the main chain and its transactions
+    /// Returns `None` if there is no such block
+    pub fn get_block_details(&self, block_id: BlockId) -> Option<BlockDetails> {
+        let result = self.issue_operation(OpRequest::BlockDetails(block_id));
+
+        if let OpResult::BlockDetails(details) = result {
+            details
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// The number of timer events processed since the simulation started
+    pub fn get_num_processed_events(&self) -> u64 {
+        let result = self.issue_operation(OpRequest::NumProcessedEvents);
+
+        if let OpResult::NumProcessedEvents(num_events) = result {
+            num_events
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// How transaction ordering affected victims of front-running on the main chain
+    pub fn get_ordering_statistics(&self) -> OrderingStatistics {
+        let result = self.issue_operation(OpRequest::OrderingStatistics);
+
+        if let OpResult::OrderingStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// Commit latencies of clients grouped by the number of confirmations they wait for
+    pub fn get_confirmation_statistics(&self) -> ConfirmationStatistics {
+        let result = self.issue_operation(OpRequest::ConfirmationStatistics);
+
+        if let OpResult::ConfirmationStatistics(statistics) = result {
+            statistics
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// The outcome of all atomic swaps performed so far
+    pub fn get_swap_results(&self) -> SwapResults {
+        let result = self.issue_operation(OpRequest::SwapResults);
+
+        if let OpResult::SwapResults(results) = result {
+            results
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+
+    /// The end-to-end latencies of all completed bridge transfers
+    pub fn get_bridge_latencies(&self) -> Vec<Duration> {
+        let result = self.issue_operation(OpRequest::BridgeLatencies);
+
+        if let OpResult::BridgeLatencies(latencies) = result {
+            latencies
+        } else {
+            panic!("Got unexpected op result");
+        }
+    }
+}
+
+/// Picks the node (by position) each client is attached to
+fn assign_clients(
+    assignment: ClientAssignment,
+    num_clients: u32,
+    locations: &[Location],
+) -> Vec<usize> {
+    let num_nodes = locations.len();
+
+    match assignment {
+        ClientAssignment::Random => (0..num_clients)
+            .map(|_| (random::random::<u32>() as usize) % num_nodes)
+            .collect(),
+        ClientAssignment::RoundRobin => (0..num_clients as usize)
+            .map(|client_idx| client_idx % num_nodes)
+            .collect(),
+        ClientAssignment::Regional { .. } => {
+            // Regions without any nodes are skipped
+            let mut regions: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+            for (node_idx, location) in locations.iter().enumerate() {
+                let region = assignment.get_region(location).unwrap();
+                regions.entry(region).or_default().push(node_idx);
+            }
+            let regions: Vec<_> = regions.into_values().collect();
+
+            (0..num_clients as usize)
+                .map(|client_idx| {
+                    let nodes = &regions[client_idx % regions.len()];
+                    nodes[(client_idx / regions.len()) % nodes.len()]
+                })
+                .collect()
+        }
+    }
+}
+
+fn load_genesis(protocol_config: &ProtocolConfiguration) -> anyhow::Result<Vec<GenesisAccount>> {
+    Ok(protocol_config
+        .get_genesis()
+        .map(GenesisConfig::load_accounts)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Bridges and swaps need funded accounts if the chain executes transactions
+/// These are added to the genesis state after the regular genesis accounts.
+fn make_endpoint_accounts(
+    protocol_config: &ProtocolConfiguration,
+    count: usize,
+) -> Vec<GenesisAccount> {
+    if protocol_config.get_execution().is_none() {
+        return vec![];
+    }
+
+    (0..count)
+        .map(|_| GenesisAccount {
+            account: random::random(),
+            balance: ENDPOINT_BALANCE,
+        })
+        .collect()
+}
+
+impl SimulationInner {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        protocol_config: ProtocolConfiguration,
+        network_config: NetworkConfiguration,
+        rate_limit: Arc<Mutex<Option<u32>>>,
+        rate_limit_cond: Arc<Condvar>,
+        speed: Arc<Mutex<SimulationSpeed>>,
+        failure: Arc<Mutex<Option<String>>>,
+        failures: Failures,
+        genesis: Vec<GenesisAccount>,
+        secondary: Option<SecondaryChain>,
+        command_queue: Arc<Mutex<Vec<Command>>>,
+        command_cond: Arc<Condvar>,
+        event_sender: mpsc::Sender<(Time, Event)>,
+        state: Arc<Mutex<State>>,
         state_cond: Arc<Condvar>,
         stats_file: Option<csv::Writer<File>>,
     ) -> Self {
@@ -481,14 +1292,30 @@ impl SimulationInner {
         let asim = Rc::new(asim::Runtime::default());
         let statistics = Rc::new(Statistics::new(scene.clone(), stats_file));
 
+        // Every bridge and swap has one endpoint on each chain
+        let num_endpoints = secondary.as_ref().map_or(0, |secondary| {
+            secondary.config.bridges.len() + secondary.config.swaps.len()
+        });
+        let endpoints = make_endpoint_accounts(&protocol_config, num_endpoints);
+        let secondary = secondary.map(|secondary| SecondaryChain {
+            endpoints: make_endpoint_accounts(&secondary.config.protocol, num_endpoints),
+            ..secondary
+        });
+
         Self {
             rate_limit,
             rate_limit_cond,
+            pause_requested: Default::default(),
+            speed,
+            failure,
             statistics,
             asim,
             scene,
             state,
             failures,
+            genesis,
+            endpoints,
+            secondary,
             state_cond,
             event_sender,
             command_queue,
@@ -499,38 +1326,97 @@ impl SimulationInner {
     }
 
     /// Set up the protocol-specific global logic
-    fn initialize_logic(&self, failures: &Failures) -> Rc<dyn GlobalLogic> {
-        match self.protocol_config {
+    fn initialize_logic(
+        protocol_config: &ProtocolConfiguration,
+        network_config: &NetworkConfiguration,
+        genesis: &[GenesisAccount],
+        failures: &Failures,
+    ) -> anyhow::Result<Rc<dyn GlobalLogic>> {
+        let logic = match *protocol_config {
             ProtocolConfiguration::NakamotoConsensus {
                 ref block_generation,
                 use_ghost,
                 commit_delay,
                 max_block_size,
+                max_block_bytes,
+                premined_blocks,
+                genesis_age,
+                ref mining_pools,
+                ref fee_market,
+                ref execution,
+                ref ordering_policy,
+                first_seen_sampling,
+                ref upgrades,
+                ref miner_policies,
+                ref request_retries,
+                ref peer_scoring,
+                ref block_store,
+                ..
             } => NakamotoGlobalLogic::instantiate(
                 block_generation.clone(),
                 max_block_size,
                 failures.num_correct_nodes(),
+                max_block_bytes,
                 commit_delay,
                 use_ghost,
-            ),
+                premined_blocks,
+                genesis,
+                Duration::from_seconds(genesis_age),
+                mining_pools,
+                fee_market.clone(),
+                execution.clone(),
+                ordering_policy.clone(),
+                first_seen_sampling,
+                upgrades.clone(),
+                miner_policies.clone(),
+                request_retries.clone(),
+                peer_scoring.clone(),
+                block_store.clone(),
+                failures.get_faulty_nodes(),
+            )?,
             ProtocolConfiguration::PracticalBFT {
                 max_block_size,
                 max_block_interval,
+                rotate_proposer,
+                view_change_timeout,
+                ..
             } => PbftGlobalLogic::instantiate(
-                failures.num_correct_nodes(),
+                failures,
                 max_block_size,
                 max_block_interval,
+                view_change_timeout.unwrap_or(4 * max_block_interval),
+                genesis,
+                rotate_proposer,
+                network_config.get_voting_power(),
+                network_config.get_mining_nodes(),
             ),
-            ProtocolConfiguration::SpeedTest { send_speed } => {
-                SpeedTestGlobalLogic::instantiate(send_speed)
-            }
+            ProtocolConfiguration::Raft {
+                max_block_size,
+                max_block_interval,
+                election_timeout,
+                ..
+            } => RaftGlobalLogic::instantiate(
+                failures,
+                max_block_size,
+                max_block_interval,
+                election_timeout.unwrap_or(4 * max_block_interval),
+                genesis,
+                network_config.get_mining_nodes(),
+            ),
+            ProtocolConfiguration::SpeedTest {
+                send_speed,
+                pattern,
+            } => SpeedTestGlobalLogic::instantiate(send_speed, pattern),
             ProtocolConfiguration::Gossip {
                 block_size,
                 retry_delay,
+                fanout,
             } => GossipGlobalLogic::instantiate(
                 block_size,
                 retry_delay,
-                failures.num_correct_nodes(),
+                failures,
+                network_config.get_mining_nodes(),
+                fanout,
             ),
             ProtocolConfiguration::Snowball {
                 acceptance_threshold,
@@ -542,44 +1428,166 @@ impl SimulationInner {
                 sample_size_weighted,
                 query_threshold_weighted,
             ),
-        }
+            ProtocolConfiguration::Snowman {
+                max_block_size,
+                max_block_interval,
+                acceptance_threshold,
+                sample_size_weighted,
+                query_threshold_weighted,
+                ..
+            } => SnowmanGlobalLogic::instantiate(
+                failures,
+                max_block_size,
+                max_block_interval,
+                genesis,
+                acceptance_threshold,
+                sample_size_weighted,
+                query_threshold_weighted,
+                network_config.get_mining_nodes(),
+            ),
+        };
+
+        Ok(logic)
+    }
+
+    /// Sets up the global logic of all chains
+    fn initialize_chains(&self) -> anyhow::Result<ChainLogic> {
+        log::debug!("Setting up global logic");
+
+        // Needed for premined blocks
+        let _ctx = self.asim.with_context();
+
+        let main = Self::initialize_logic(
+            &self.protocol_config,
+            &self.network_config,
+            &[&self.genesis[..], &self.endpoints[..]].concat(),
+            &self.failures,
+        )?;
+        let secondary = match &self.secondary {
+            Some(secondary) => Some(Self::initialize_logic(
+                &secondary.config.protocol,
+                &secondary.config.network,
+                &[&secondary.genesis[..], &secondary.endpoints[..]].concat(),
+                &secondary.failures,
+            )?),
+            None => None,
+        };
+
+        Ok(ChainLogic { main, secondary })
     }
 
     fn generate_node(
         &self,
-        global_logic: &dyn GlobalLogic,
-        failures: &Failures,
+        chain: &ChainSetup,
         node_index: NodeIndex,
         location: Location,
         bandwidth: u64,
         mining: bool,
     ) -> Rc<Node> {
-        let logic = global_logic.new_node_logic(node_index);
-        let bandwidth = Bandwidth::from_megabits_per_second(bandwidth);
+        // Protocols expect node indices to start at zero
+        let logic = chain.global_logic.new_node_logic(node_index);
+        let global_index = chain.first_node + node_index;
+
+        // Queries need to compete with messages for the node's time,
+        // even if the node can otherwise process an unlimited number of messages
+        let inbox = match chain.network_config.get_node_inbox() {
+            Some(inbox) => Some(inbox.clone()),
+            None if chain.network_config.get_rpc_load().is_some() => Some(InboxConfig {
+                capacity: usize::MAX,
+                processing_time: 0,
+                overflow_policy: OverflowPolicy::Delay,
+            }),
+            None => None,
+        };
 
         let node = create_node(
-            node_index,
+            global_index,
             location,
             bandwidth,
+            inbox,
+            chain.failures.get_asynchrony().cloned(),
             logic.clone(),
             mining,
-            failures.is_faulty(&node_index),
+            chain.failures.is_faulty(&node_index),
         );
 
         logic.init(node.clone());
 
-        self.scene.add_node(node_index, node.clone());
+        self.scene.add_node(global_index, node.clone());
         node
     }
 
-    fn build_scene(&self, global_logic: &dyn GlobalLogic) {
+    fn build_scene(&self, logic: &ChainLogic) {
         let start = Instant::now();
 
         log::debug!("Generating nodes");
 
-        let mut mining_nodes = vec![];
+        let main_nodes = self.build_chain(&ChainSetup {
+            global_logic: &*logic.main,
+            protocol_config: &self.protocol_config,
+            network_config: &self.network_config,
+            genesis: &self.genesis,
+            failures: &self.failures,
+            first_node: 0,
+        });
+        let mut num_nodes = main_nodes.len();
+
+        if let Some(secondary) = &self.secondary {
+            log::debug!("Generating nodes of the secondary chain");
+
+            let secondary_nodes = self.build_chain(&ChainSetup {
+                global_logic: &**logic.secondary.as_ref().expect("No secondary logic"),
+                protocol_config: &secondary.config.protocol,
+                network_config: &secondary.config.network,
+                genesis: &secondary.genesis,
+                failures: &secondary.failures,
+                first_node: num_nodes as NodeIndex,
+            });
+            num_nodes += secondary_nodes.len();
+
+            // Bridges use the first endpoint accounts and swaps the remaining ones
+            let num_bridges = secondary.config.bridges.len();
+
+            for (idx, bridge_cfg) in secondary.config.bridges.iter().enumerate() {
+                self.build_bridge(bridge_cfg, idx, &main_nodes, &secondary_nodes, secondary);
+            }
+
+            for (idx, swap_cfg) in secondary.config.swaps.iter().enumerate() {
+                self.build_swap(
+                    swap_cfg,
+                    num_bridges + idx,
+                    &main_nodes,
+                    &secondary_nodes,
+                    secondary,
+                );
+            }
+        }
+
+        let elapsed = (Instant::now() - start).as_secs_f64();
+
+        log::info!(
+            "Simulation started with {} nodes, {} clients, and {} network links",
+            num_nodes,
+            self.scene.get_clients().len(),
+            self.scene.get_links().len(),
+        );
+        log::debug!("It took {elapsed} seconds to build the network");
+    }
 
-        match &self.network_config {
+    /// Create the nodes, links, and clients of a single chain
+    fn build_chain(&self, chain: &ChainSetup) -> Vec<Rc<Node>> {
+        let mut mining_nodes = vec![];
+        let victim_fraction = chain
+            .protocol_config
+            .get_ordering_policy()
+            .map_or(0.0, |policy| policy.get_victim_fraction());
+        let transaction_sizes = chain
+            .protocol_config
+            .get_transaction_sizes()
+            .cloned()
+            .unwrap_or_default();
+
+        match chain.network_config {
             NetworkConfiguration::Random {
                 num_mining_nodes,
                 num_non_mining_nodes,
@@ -588,110 +1596,126 @@ impl SimulationInner {
                 node_bandwidth,
                 link_latency,
                 link_bandwidth,
+                reachability,
+                ..
             } => {
-                for node_index in 0..*num_mining_nodes {
-                    let node = self.generate_node(
-                        global_logic,
-                        &self.failures,
-                        node_index,
-                        Location::new_random(),
-                        *node_bandwidth,
-                        true,
+                if !chain
+                    .global_logic
+                    .is_compatible_with_connectivity(connectivity)
+                {
+                    panic!(
+                        "Logic {:?} not compatible with connectivity {connectivity:?}",
+                        chain.protocol_config
                     );
-                    mining_nodes.push(node);
                 }
 
-                for node_index in *num_mining_nodes..(*num_non_mining_nodes + *num_mining_nodes) {
-                    let node = self.generate_node(
-                        global_logic,
-                        &self.failures,
-                        node_index,
-                        Location::new_random(),
-                        *node_bandwidth,
-                        false,
-                    );
-                    mining_nodes.push(node);
-                }
+                let num_nodes = (*num_mining_nodes + *num_non_mining_nodes) as usize;
+                let mut locations: Vec<_> =
+                    (0..num_nodes).map(|_| Location::new_random()).collect();
 
-                if !global_logic.is_compatible_with_connectivity(connectivity) {
-                    panic!(
-                        "Logic {:?} not compatible with connectivity {connectivity:?}",
-                        self.protocol_config
-                    );
+                let reachable = reachability.sample(num_nodes);
+                let num_nat = reachable.iter().filter(|r| !**r).count();
+                if num_nat > 0 {
+                    log::info!("{num_nat} node(s) do not accept inbound connections");
                 }
 
                 // TODO move this to a separate method
                 log::debug!("Generating network links");
-                match connectivity {
+                let mut links = match connectivity {
                     Connectivity::Full => {
-                        for idx1 in 0..mining_nodes.len() {
-                            for idx2 in idx1 + 1..mining_nodes.len() {
-                                let node1 = &mining_nodes[idx1];
-                                let node2 = &mining_nodes[idx2];
-
-                                self.build_connection(node1, node2, *link_bandwidth, *link_latency);
+                        let mut links = BTreeSet::new();
+                        for idx1 in 0..num_nodes {
+                            for idx2 in idx1 + 1..num_nodes {
+                                if can_connect(&reachable, idx1, idx2) {
+                                    links.insert((idx1, idx2));
+                                }
                             }
                         }
+                        links
                     }
-                    Connectivity::Sparse { min_conns_per_node } => {
+                    Connectivity::Sparse {
+                        min_conns_per_node,
+                        on_disconnected,
+                    } => {
                         assert!(
                             *min_conns_per_node > 1,
                             "Need at least two connections per node"
                         );
 
-                        let mut conns_per_nodes = vec![0; mining_nodes.len()];
-                        let mut known_links = std::collections::HashSet::new();
-
-                        for idx1 in 0..mining_nodes.len() {
-                            // Find the closest nodes
-                            let mut sorted_nodes = vec![];
-                            for idx2 in 0..mining_nodes.len() {
-                                if idx1 == idx2 {
-                                    continue;
-                                }
-
-                                let src = &mining_nodes[idx1];
-                                let dst = &mining_nodes[idx2];
-                                let distance = src.get_location().distance(dst.get_location());
+                        let network = generate_sparse_network(
+                            locations,
+                            *min_conns_per_node,
+                            *on_disconnected,
+                            &reachable,
+                            Location::new_random,
+                        );
 
-                                sorted_nodes.push((distance, idx2));
-                            }
+                        if network.num_components > 1 {
+                            log::warn!(
+                                "Generated network had {} disconnected parts after {} attempt(s); added {} bridge link(s)",
+                                network.num_components,
+                                network.attempts,
+                                network.num_bridge_links
+                            );
+                        } else if network.attempts > 1 {
+                            log::info!(
+                                "Generated a connected network after {} attempts",
+                                network.attempts
+                            );
+                        }
 
-                            sorted_nodes.sort_by(|(dist_a, _), (dist_b, _)| {
-                                dist_a
-                                    .partial_cmp(dist_b)
-                                    .expect("Failed to compare node locations")
-                            });
+                        locations = network.locations;
+                        network.links
+                    }
+                    Connectivity::RandomRegular { degree } => {
+                        random_regular_links(num_nodes, *degree, &reachable)
+                    }
+                    Connectivity::SmallWorld {
+                        nearest,
+                        long_range,
+                        exponent,
+                    } => {
+                        small_world_links(&locations, *nearest, *long_range, *exponent, &reachable)
+                    }
+                };
 
-                            for (_, idx2) in sorted_nodes.drain(..) {
-                                // Done?
-                                if conns_per_nodes[idx1] >= *min_conns_per_node {
-                                    break;
-                                }
+                // Sparse networks handle this according to their policy
+                let needs_repair = matches!(
+                    connectivity,
+                    Connectivity::RandomRegular { .. } | Connectivity::SmallWorld { .. }
+                );
+                if needs_repair && find_components(num_nodes, &links).len() > 1 {
+                    let num_added = connect_components(&locations, &mut links, &reachable);
+                    log::warn!(
+                        "Generated network was disconnected; added {num_added} bridge link(s)"
+                    );
+                }
 
-                                let key = match idx1.cmp(&idx2) {
-                                    Ordering::Less => (idx1, idx2),
-                                    Ordering::Greater => (idx2, idx1),
-                                    Ordering::Equal => continue,
-                                };
-
-                                // Don't add the same connection twice
-                                if known_links.contains(&key) {
-                                    continue;
-                                } else {
-                                    known_links.insert(key);
-                                }
+                if !matches!(connectivity, Connectivity::Full) || num_nat > 0 {
+                    let diameter = Topology::from_links(num_nodes, &links).diameter();
+                    log::info!(
+                        "Generated network with {} links and a diameter of {diameter} hops",
+                        links.len()
+                    );
+                }
 
-                                let node1 = &mining_nodes[idx1];
-                                let node2 = &mining_nodes[idx2];
+                for (node_index, location) in locations.into_iter().enumerate() {
+                    let node_index = node_index as NodeIndex;
+                    let node = self.generate_node(
+                        chain,
+                        node_index,
+                        location,
+                        *node_bandwidth,
+                        node_index < *num_mining_nodes,
+                    );
+                    mining_nodes.push(node);
+                }
 
-                                self.build_connection(node1, node2, *link_bandwidth, *link_latency);
+                for (idx1, idx2) in links {
+                    let node1 = &mining_nodes[idx1];
+                    let node2 = &mining_nodes[idx2];
 
-                                conns_per_nodes[idx1] += 1;
-                                conns_per_nodes[idx2] += 1;
-                            }
-                        }
-                    }
+                    self.build_connection(node1, node2, *link_bandwidth, *link_latency);
                 }
 
                 log::debug!("Generating client workload");
@@ -703,25 +1727,57 @@ impl SimulationInner {
                     workload.client_startup_interval
                 );
 
-                for client_idx in 0..workload.num_clients {
-                    // pick a random node
-                    let node_idx =
-                        rand::random::<u32>() % (num_mining_nodes + num_non_mining_nodes);
-                    let node = &mining_nodes[node_idx as usize];
+                let locations: Vec<_> = mining_nodes
+                    .iter()
+                    .map(|node| node.get_location().clone())
+                    .collect();
+                let assignments =
+                    assign_clients(workload.client_assignment, workload.num_clients, &locations);
+
+                for (client_idx, node_idx) in assignments.into_iter().enumerate() {
+                    let node = &mining_nodes[node_idx];
 
                     let start_delay = Duration::from_micros(client_spacing * (client_idx as u64));
 
                     // place client on same queue as node for better concurrency
                     let transaction_interval = Duration::from_millis(workload.transaction_interval);
 
-                    let client =
-                        Rc::new(Client::new(start_delay, transaction_interval, node.clone()));
+                    let confirmations = if workload.confirmation_depths.is_empty() {
+                        None
+                    } else {
+                        let idx = client_idx % workload.confirmation_depths.len();
+                        Some(workload.confirmation_depths[idx])
+                    };
+
+                    let client = Rc::new(
+                        Client::new(
+                            chain.get_client_account(client_idx),
+                            start_delay,
+                            transaction_interval,
+                            node.clone(),
+                            chain.protocol_config.get_fee_market().cloned(),
+                            victim_fraction,
+                            confirmations,
+                        )
+                        .with_transaction_sizes(transaction_sizes.clone())
+                        .with_fees(workload.fees.clone())
+                        .with_transfers(chain.get_transfer_recipients())
+                        .with_unspent_output(chain.get_client_unspent_output(client_idx))
+                        .with_latency(Duration::from_millis(workload.client_latency)),
+                    );
 
                     {
                         let client = client.clone();
                         self.asim.spawn(async move { client.run().await });
                     }
 
+                    if let Some(rpc_load) = &workload.rpc_load {
+                        let client = client.clone();
+                        let rpc_load = rpc_load.clone();
+                        self.asim
+                            .spawn(async move { client.run_rpc_queries(rpc_load).await });
+                    }
+
                     node.add_client(&client);
                     self.scene.add_client(client.get_identifier(), client);
                 }
@@ -730,15 +1786,15 @@ impl SimulationInner {
                 clients: client_cfgs,
                 nodes: node_cfgs,
                 links: link_cfgs,
+                ..
             } => {
                 for (node_index, node_cfg) in node_cfgs.iter().enumerate() {
                     let node = self.generate_node(
-                        global_logic,
-                        &self.failures,
+                        chain,
                         node_index as NodeIndex,
                         node_cfg.location.clone(),
                         node_cfg.bandwidth,
-                        true,
+                        node_cfg.is_mining,
                     );
                     mining_nodes.push(node);
                 }
@@ -754,7 +1810,7 @@ impl SimulationInner {
                     self.build_connection(node1, node2, link_cfg.bandwidth, link_cfg.latency);
                 }
 
-                for client_cfg in client_cfgs {
+                for (client_idx, client_cfg) in client_cfgs.iter().enumerate() {
                     let node_idx = client_cfg.node as usize;
                     let node = &mining_nodes[node_idx];
 
@@ -765,8 +1821,21 @@ impl SimulationInner {
                     let transaction_interval =
                         Duration::from_millis(client_cfg.transaction_interval);
 
-                    let client =
-                        Rc::new(Client::new(start_delay, transaction_interval, node.clone()));
+                    let client = Rc::new(
+                        Client::new(
+                            chain.get_client_account(client_idx as usize),
+                            start_delay,
+                            transaction_interval,
+                            node.clone(),
+                            chain.protocol_config.get_fee_market().cloned(),
+                            victim_fraction,
+                            client_cfg.confirmations,
+                        )
+                        .with_transaction_sizes(transaction_sizes.clone())
+                        .with_transfers(chain.get_transfer_recipients())
+                        .with_unspent_output(chain.get_client_unspent_output(client_idx as usize))
+                        .with_latency(Duration::from_millis(client_cfg.latency)),
+                    );
 
                     {
                         let client = client.clone();
@@ -779,15 +1848,142 @@ impl SimulationInner {
             }
         }
 
-        let elapsed = (Instant::now() - start).as_secs_f64();
+        if let Some(churn) = chain.network_config.get_churn() {
+            spawn_churn(
+                &self.scene,
+                churn,
+                chain.first_node,
+                chain.network_config.num_nodes(),
+            );
+        }
 
-        log::info!(
-            "Simulation started with {} nodes, {} clients, and {} network links",
-            mining_nodes.len(),
-            self.scene.get_clients().len(),
-            self.scene.get_links().len(),
+        mining_nodes
+    }
+
+    /// Create a bridge between the two chains
+    fn build_bridge(
+        &self,
+        config: &BridgeConfig,
+        endpoint_idx: usize,
+        main_nodes: &[Rc<Node>],
+        secondary_nodes: &[Rc<Node>],
+        secondary: &SecondaryChain,
+    ) {
+        let main_endpoint = Self::make_endpoint(
+            &main_nodes[config.main_node as usize],
+            &self.protocol_config,
+            &self.genesis,
+            &self.endpoints,
+            endpoint_idx,
         );
-        log::debug!("It took {elapsed} seconds to build the network");
+        let secondary_endpoint = Self::make_endpoint(
+            &secondary_nodes[config.secondary_node as usize],
+            &secondary.config.protocol,
+            &secondary.genesis,
+            &secondary.endpoints,
+            endpoint_idx,
+        );
+
+        let (source, destination) = match config.direction {
+            BridgeDirection::MainToSecondary => (main_endpoint, secondary_endpoint),
+            BridgeDirection::SecondaryToMain => (secondary_endpoint, main_endpoint),
+        };
+
+        let bridge = Rc::new(Bridge::new(
+            source,
+            destination,
+            config.amount,
+            Duration::from_millis(config.transfer_interval),
+        ));
+
+        {
+            let bridge = bridge.clone();
+            self.asim.spawn(async move { bridge.run().await });
+        }
+
+        self.scene.add_bridge(bridge);
+    }
+
+    /// Set up two parties that perform atomic swaps between the chains
+    fn build_swap(
+        &self,
+        config: &AtomicSwapConfig,
+        endpoint_idx: usize,
+        main_nodes: &[Rc<Node>],
+        secondary_nodes: &[Rc<Node>],
+        secondary: &SecondaryChain,
+    ) {
+        let main_endpoint = Self::make_endpoint(
+            &main_nodes[config.main_node as usize],
+            &self.protocol_config,
+            &self.genesis,
+            &self.endpoints,
+            endpoint_idx,
+        );
+        let secondary_endpoint = Self::make_endpoint(
+            &secondary_nodes[config.secondary_node as usize],
+            &secondary.config.protocol,
+            &secondary.genesis,
+            &secondary.endpoints,
+            endpoint_idx,
+        );
+
+        let swap = Rc::new(AtomicSwap::new(
+            main_endpoint,
+            secondary_endpoint,
+            Duration::from_millis(config.initiator_timeout),
+            Duration::from_millis(config.participant_timeout),
+            Duration::from_millis(config.swap_interval),
+        ));
+
+        {
+            let swap = swap.clone();
+            self.asim.spawn(async move { swap.run().await });
+        }
+
+        self.scene.add_swap(swap);
+    }
+
+    /// Creates a client for bridges and swaps
+    /// These are not spawned; they only issue transactions when told to
+    fn make_endpoint(
+        node: &Rc<Node>,
+        protocol_config: &ProtocolConfiguration,
+        genesis: &[GenesisAccount],
+        endpoints: &[GenesisAccount],
+        endpoint_idx: usize,
+    ) -> Rc<Client> {
+        // Without execution, there are no endpoint accounts and any account will do
+        let (account, unspent_output) = match endpoints.get(endpoint_idx) {
+            Some(endpoint) => {
+                let is_utxo = protocol_config
+                    .get_execution()
+                    .is_some_and(|execution| execution.model == TransactionModel::Utxo);
+                let output = is_utxo.then(|| {
+                    (
+                        genesis_output_id(genesis.len() + endpoint_idx),
+                        endpoint.balance,
+                    )
+                });
+                (endpoint.account, output)
+            }
+            None => (random::random(), None),
+        };
+
+        let client = Rc::new(
+            Client::new(
+                account,
+                Duration::ZERO,
+                Duration::ZERO,
+                node.clone(),
+                protocol_config.get_fee_market().cloned(),
+                0.0,
+                None,
+            )
+            .with_unspent_output(unspent_output),
+        );
+        node.add_client(&client);
+        client
     }
 
     /// Create a connection between two nodes
@@ -801,15 +1997,20 @@ impl SimulationInner {
         let bandwidth = bandwidth.map(Bandwidth::from_megabits_per_second);
         let latency = Duration::from_millis(latency);
 
-        let link = create_link(node1.clone(), node2.clone(), bandwidth, latency);
-        self.scene.add_link(link.get_identifier(), link.clone());
+        let (link, statistics) = create_link(node1.clone(), node2.clone(), bandwidth, latency);
+        node1.add_peer_link(node2.get_identifier(), statistics.clone());
+        node2.add_peer_link(node1.get_identifier(), statistics.clone());
+        self.scene
+            .add_link(link.get_identifier(), link.clone(), statistics);
 
         link
     }
 
     /// Processes all pending commands. Return true if there were any.
     /// Setting blocking to true will make this function wait until there are commands to process.
-    fn process_commands(&self, global_logic: &Rc<dyn GlobalLogic>, blocking: bool) -> bool {
+    fn process_commands(&self, logic: &ChainLogic, blocking: bool) -> bool {
+        let global_logic = &logic.main;
+
         let cmds = {
             let mut lock = self.command_queue.lock();
 
@@ -875,6 +2076,19 @@ impl SimulationInner {
                         }
                     }
                 }
+                Command::PauseAt(time) => {
+                    let now = self.asim.get_timer().now();
+                    if time < now {
+                        log::warn!("Cannot pause at {time}; simulation is already at {now}");
+                        continue;
+                    }
+
+                    let pause_requested = self.pause_requested.clone();
+                    self.asim.spawn(async move {
+                        asim::time::sleep(time - asim::time::now()).await;
+                        pause_requested.set(true);
+                    });
+                }
                 Command::EnableEvents => {
                     EVENT_HANDLER.with(|hdl| {
                         if hdl
@@ -895,16 +2109,205 @@ impl SimulationInner {
                             let node = self.scene.get_node_by_index(&idx).expect("No such node");
                             OpResult::NodeIdentifier(node.get_identifier())
                         }
-                        OpRequest::ChainMetrics(timeout) => {
-                            let links = self.scene.get_links();
-                            let metrics = global_logic.get_metrics(
-                                timeout,
-                                &self.scene.get_clients(),
-                                &links,
-                            );
-
+                        OpRequest::TransactionStatus { node, transaction } => {
+                            let node = self.scene.get_node_by_index(&node).expect("No such node");
+                            let status = NodeData::get_transaction_status(&node, &transaction);
+                            OpResult::TransactionStatus(status)
+                        }
+                        OpRequest::Step(num_events) => {
+                            for _ in 0..num_events {
+                                self.update();
+                            }
+                            OpResult::CurrentTime(self.asim.get_timer().now())
+                        }
+                        OpRequest::StepUntil(time) => {
+                            while self.asim.get_timer().now() < time {
+                                self.update();
+                            }
+                            OpResult::CurrentTime(self.asim.get_timer().now())
+                        }
+                        OpRequest::Mempool(idx) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            OpResult::Mempool(NodeData::get_mempool(&node))
+                        }
+                        OpRequest::ChainHead(idx) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            OpResult::ChainHead(NodeData::get_chain_head(&node))
+                        }
+                        OpRequest::NodeChainInfo(idx) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            OpResult::NodeChainInfo(NodeData::get_chain_info(&node))
+                        }
+                        OpRequest::AccountState { node, account } => {
+                            let node = self.scene.get_node_by_index(&node).expect("No such node");
+                            OpResult::AccountState(NodeData::get_account_state(&node, &account))
+                        }
+                        OpRequest::InjectTransaction(idx) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            let _ctx = self.asim.with_context();
+
+                            let account = node.get_account_id();
+                            let nonce = NodeData::get_account_state(&node, &account)
+                                .map_or(0, |state| state.get_nonce())
+                                + 1;
+                            let transaction = Rc::new(Transaction::new(account, nonce));
+                            let txn_id = *transaction.get_identifier();
+
+                            if NodeData::add_transaction(&node, transaction) {
+                                OpResult::InjectedTransaction(Some(txn_id))
+                            } else {
+                                OpResult::InjectedTransaction(None)
+                            }
+                        }
+                        OpRequest::SubmitTransaction { node, spec } => {
+                            let node = self.scene.get_node_by_index(&node).expect("No such node");
+                            let txn_id = random::random::<TransactionId>();
+
+                            self.asim.spawn(async move {
+                                if let Some(submit_at) = spec.submit_at {
+                                    let now = asim::time::now();
+                                    if submit_at > now {
+                                        asim::time::sleep(submit_at - now).await;
+                                    }
+                                }
+
+                                let source = spec.source.unwrap_or_else(|| node.get_account_id());
+                                let transaction = Rc::new(Transaction::new_with_id(
+                                    txn_id, source, spec.nonce, spec.fee,
+                                ));
+
+                                if !NodeData::add_transaction(&node, transaction) {
+                                    log::warn!(
+                                        "Node {} did not accept submitted transaction {txn_id:#X}",
+                                        node.get_index()
+                                    );
+                                }
+                            });
+
+                            OpResult::SubmittedTransaction(txn_id)
+                        }
+                        OpRequest::CrashNode(idx) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            node.crash();
+                            OpResult::Done
+                        }
+                        OpRequest::Partition(groups) => {
+                            self.scene.set_partition(&groups);
+                            OpResult::Done
+                        }
+                        OpRequest::SetAnomalyThresholds(thresholds) => {
+                            self.statistics.set_anomaly_thresholds(thresholds);
+                            OpResult::Done
+                        }
+                        OpRequest::Fingerprint => {
+                            OpResult::Fingerprint(self.compute_fingerprint(logic))
+                        }
+                        OpRequest::ChainMetrics(timeout) => {
+                            let links = self.scene.get_links();
+                            let metrics = global_logic.get_metrics(
+                                timeout,
+                                &self.get_chain_clients(false),
+                                &links,
+                            );
+
                             OpResult::ChainMetrics(metrics)
                         }
+                        OpRequest::SecondaryChainMetrics(timeout) => {
+                            let links = self.scene.get_links();
+                            let metrics = logic.secondary.as_ref().map(|secondary_logic| {
+                                secondary_logic.get_metrics(
+                                    timeout,
+                                    &self.get_chain_clients(true),
+                                    &links,
+                                )
+                            });
+
+                            OpResult::SecondaryChainMetrics(metrics)
+                        }
+                        OpRequest::BridgeLatencies => {
+                            let latencies = self
+                                .scene
+                                .get_bridges()
+                                .iter()
+                                .flat_map(|bridge| bridge.get_latencies())
+                                .collect();
+
+                            OpResult::BridgeLatencies(latencies)
+                        }
+                        OpRequest::MiningPoolStatistics => OpResult::MiningPoolStatistics(
+                            global_logic.get_mining_pool_statistics(),
+                        ),
+                        OpRequest::ChainSplitStatistics => OpResult::ChainSplitStatistics(
+                            global_logic.get_chain_split_statistics(),
+                        ),
+                        OpRequest::PeerBanStatistics => {
+                            OpResult::PeerBanStatistics(global_logic.get_peer_ban_statistics())
+                        }
+                        OpRequest::BlockDetails(block_id) => {
+                            OpResult::BlockDetails(global_logic.get_block_details(&block_id))
+                        }
+                        OpRequest::BlockGraph => {
+                            OpResult::BlockGraph(global_logic.get_block_graph())
+                        }
+                        OpRequest::StatisticsHistory => {
+                            OpResult::StatisticsHistory(self.statistics.get_history())
+                        }
+                        OpRequest::NumProcessedEvents => {
+                            OpResult::NumProcessedEvents(self.statistics.get_total_events())
+                        }
+                        OpRequest::FeeMarketStatistics => {
+                            let statistics =
+                                global_logic
+                                    .get_fee_market_statistics()
+                                    .map(|mut statistics| {
+                                        statistics.demand = self
+                                            .get_chain_clients(false)
+                                            .iter()
+                                            .map(|client| client.get_demand())
+                                            .sum();
+                                        statistics
+                                    });
+
+                            OpResult::FeeMarketStatistics(statistics)
+                        }
+                        OpRequest::OrderingStatistics => {
+                            let mut statistics = OrderingStatistics {
+                                front_run_victims: global_logic.get_front_run_victims(),
+                                ..Default::default()
+                            };
+
+                            for client in self.get_chain_clients(false).iter() {
+                                statistics.latencies.merge(&client.get_latencies());
+                                statistics
+                                    .victim_latencies
+                                    .merge(&client.get_victim_latencies());
+                            }
+
+                            OpResult::OrderingStatistics(statistics)
+                        }
+                        OpRequest::ConfirmationStatistics => {
+                            let mut statistics = ConfirmationStatistics::default();
+
+                            for client in self.get_chain_clients(false).iter() {
+                                if let Some(confirmations) = client.get_confirmations() {
+                                    statistics
+                                        .latencies
+                                        .entry(confirmations)
+                                        .or_default()
+                                        .merge(&client.get_latencies());
+                                }
+                            }
+
+                            OpResult::ConfirmationStatistics(statistics)
+                        }
+                        OpRequest::SwapResults => {
+                            let mut results = SwapResults::default();
+                            for swap in self.scene.get_swaps().iter() {
+                                results.merge(&swap.get_results());
+                            }
+
+                            OpResult::SwapResults(results)
+                        }
                         OpRequest::NetworkMetric(nmetric) => {
                             log::trace!("Got network metric request {nmetric:?}");
 
@@ -928,17 +2331,47 @@ impl SimulationInner {
                                     count as f64
                                 }
                                 NetworkMetricType::NumMiningNodes => {
-                                    let count = self.scene.get_nodes().len();
+                                    let nodes = self.scene.get_nodes();
+                                    let count = nodes.values().filter(|n| n.is_mining()).count();
                                     count as f64
                                 }
                                 NetworkMetricType::NumNonMiningNodes => {
-                                    let count = self.scene.get_nodes().len();
+                                    let nodes = self.scene.get_nodes();
+                                    let count = nodes.values().filter(|n| !n.is_mining()).count();
                                     count as f64
                                 }
                                 NetworkMetricType::NumLinks => {
                                     let count = self.scene.get_links().len();
                                     count as f64
                                 }
+                                NetworkMetricType::AverageNodeDegree => {
+                                    Topology::from_scene(&self.scene).average_degree()
+                                }
+                                NetworkMetricType::Diameter => {
+                                    Topology::from_scene(&self.scene).diameter() as f64
+                                }
+                                NetworkMetricType::AverageShortestPathLatency => {
+                                    Topology::from_scene(&self.scene)
+                                        .average_shortest_path_latency()
+                                }
+                                NetworkMetricType::TotalBytesTransferred => {
+                                    let total: u64 = self
+                                        .scene
+                                        .get_link_statistics()
+                                        .values()
+                                        .map(|stats| {
+                                            stats.get(LinkDirection::Forward).num_bytes
+                                                + stats.get(LinkDirection::Backward).num_bytes
+                                        })
+                                        .sum();
+                                    total as f64
+                                }
+                                NetworkMetricType::LinkUtilization(link_id, direction) => self
+                                    .scene
+                                    .get_link_statistics()
+                                    .get(&link_id)
+                                    .expect("No such link")
+                                    .get_utilization(direction),
                             };
 
                             OpResult::NetworkMetric(value)
@@ -958,6 +2391,94 @@ impl SimulationInner {
 
                             OpResult::GlobalStatistics(data_point)
                         }
+                        OpRequest::ClientAssignments => {
+                            let assignment = self.network_config.get_client_assignment();
+                            let clients = self
+                                .get_chain_clients(false)
+                                .iter()
+                                .map(|client| {
+                                    let node = client.get_node();
+                                    let latencies = client.get_latencies();
+
+                                    ClientInfo {
+                                        account: *client.get_account_id(),
+                                        node: node.get_index(),
+                                        region: assignment.and_then(|assignment| {
+                                            assignment.get_region(node.get_location())
+                                        }),
+                                        num_transactions: latencies.len(),
+                                        avg_latency: latencies.get_mean(),
+                                    }
+                                })
+                                .collect();
+
+                            OpResult::ClientAssignments(clients)
+                        }
+                        OpRequest::LinkGoodput => {
+                            let link_statistics = self.scene.get_link_statistics();
+                            let mut result = vec![];
+
+                            for (link_id, link) in self.scene.get_links().iter() {
+                                let stats = &link_statistics[link_id];
+                                let (node1, node2) = link.get_nodes();
+
+                                for (direction, source, destination) in [
+                                    (LinkDirection::Forward, &node1, &node2),
+                                    (LinkDirection::Backward, &node2, &node1),
+                                ] {
+                                    if stats.get(direction).num_delivered == 0 {
+                                        continue;
+                                    }
+
+                                    let bandwidth = source.get_bandwidth() as f64 * 1_000_000.0;
+                                    let avg_delay = stats.get_latency().as_seconds_f64() * 1000.0
+                                        + stats.get(direction).get_average_queueing_delay();
+
+                                    result.push(LinkGoodput {
+                                        source: source.get_index(),
+                                        destination: destination.get_index(),
+                                        goodput: stats.get_goodput(direction),
+                                        bandwidth,
+                                        avg_delay,
+                                    });
+                                }
+                            }
+
+                            result.sort_by_key(|link| (link.source, link.destination));
+                            OpResult::LinkGoodput(result)
+                        }
+                        OpRequest::MemoryUsage(Some(idx)) => {
+                            let node = self.scene.get_node_by_index(&idx).expect("No such node");
+                            OpResult::MemoryUsage(NodeData::get_memory_usage(&node))
+                        }
+                        OpRequest::MemoryUsage(None) => {
+                            let mut usage = global_logic.get_memory_usage();
+                            for node in self.scene.get_nodes().values() {
+                                usage.merge(&NodeData::get_memory_usage(node));
+                            }
+                            OpResult::MemoryUsage(usage)
+                        }
+                        OpRequest::PropagationStatistics => OpResult::PropagationStatistics(
+                            global_logic.get_propagation_statistics(),
+                        ),
+                        OpRequest::MempoolStatistics => {
+                            OpResult::MempoolStatistics(self.statistics.get_mempool_statistics())
+                        }
+                        OpRequest::ReorgHistogram => {
+                            OpResult::ReorgHistogram(self.statistics.get_reorg_histogram())
+                        }
+                        OpRequest::LinkStatistics { link, direction } => {
+                            let statistics = self
+                                .scene
+                                .get_link_statistics()
+                                .get(&link)
+                                .expect("No such link")
+                                .get(direction);
+                            OpResult::LinkStatistics(statistics)
+                        }
+                        OpRequest::CommitDelays => {
+                            OpResult::CommitDelays(self.statistics.get_commit_delays())
+                        }
                         OpRequest::CurrentTime => {
                             let time = self.asim.get_timer().now();
                             OpResult::CurrentTime(time)
@@ -981,7 +2502,7 @@ impl SimulationInner {
         true
     }
 
-    fn run(&self) {
+    fn run(&self, logic: ChainLogic) {
         {
             let mut state = self.state.lock();
             while *state == State::SettingUp {
@@ -989,16 +2510,21 @@ impl SimulationInner {
             }
         }
 
-        log::debug!("Setting up global logic");
-        let global_logic = self.initialize_logic(&self.failures);
-
         // Enables event handling, if requested
-        self.process_commands(&global_logic, false);
+        self.process_commands(&logic, false);
 
         log::debug!("Building scene");
         {
             let _ctx = self.asim.with_context();
-            self.build_scene(&*global_logic);
+            self.build_scene(&logic);
+        }
+
+        if let Some(outage) = self.failures.get_region_outage() {
+            self.schedule_region_outage(outage.clone());
+        }
+
+        for window in self.failures.get_partitions() {
+            self.schedule_partition(window.clone());
         }
 
         // Run initial tasks until they sleep for timer events
@@ -1007,9 +2533,10 @@ impl SimulationInner {
         // Start statistics collection
         {
             let statistics = self.statistics.clone();
+            let global_logic = logic.main.clone();
 
             self.asim.spawn(async move {
-                statistics.run(Duration::ZERO).await;
+                statistics.run(global_logic, Duration::ZERO).await;
             });
         }
 
@@ -1021,8 +2548,11 @@ impl SimulationInner {
         log::debug!("All set up. Will start regular operation.");
         let mut last_hour = 0;
         let mut last_rate_limit = (START_TIME, Instant::now());
+        let mut last_speed_update = (START_TIME, Instant::now());
+        let mut num_events = 0;
+        let mut num_speed_updates = 0;
 
-        loop {
+        'main: loop {
             {
                 let state = self.state.lock();
                 if *state != State::Running {
@@ -1030,7 +2560,11 @@ impl SimulationInner {
                 }
             }
 
-            self.process_commands(&global_logic, false);
+            if self.check_failure() {
+                break;
+            }
+
+            self.process_commands(&logic, false);
 
             let this_hour = self.asim.get_timer().now().to_hours();
             if this_hour != last_hour {
@@ -1039,17 +2573,50 @@ impl SimulationInner {
             }
 
             self.update();
+            num_events += 1;
+
+            let real_elapsed = Instant::now() - last_speed_update.1;
+            if real_elapsed >= SPEED_UPDATE_INTERVAL {
+                let now = self.asim.get_timer().now();
+                let virtual_elapsed = now - last_speed_update.0;
+                let speed = SimulationSpeed {
+                    speedup: virtual_elapsed.as_seconds_f64() / real_elapsed.as_secs_f64(),
+                    events_per_second: (num_events as f64) / real_elapsed.as_secs_f64(),
+                };
+
+                *self.speed.lock() = speed;
+                last_speed_update = (now, Instant::now());
+                num_events = 0;
+
+                num_speed_updates += 1;
+                if num_speed_updates % SPEED_LOG_INTERVAL == 0 {
+                    self.log_speed(&speed);
+                }
+            }
 
             // Rate limit once ever virtual second
             let mut rate_limit = self.rate_limit.lock();
 
+            if self.pause_requested.take() {
+                log::info!("Pausing at {}", self.asim.get_timer().now());
+                *rate_limit = Some(0);
+            }
+
             // Stay paused
             while let Some(val) = *rate_limit
                 && val == 0
             {
                 log::debug!("Simulation stopped. Will wait...");
-                self.process_commands(&global_logic, false);
+                self.process_commands(&logic, false);
                 self.update_stopped();
+
+                // Breakpoints reached while stepping do not pause again after resuming
+                self.pause_requested.set(false);
+
+                if self.check_failure() {
+                    break 'main;
+                }
+
                 self.rate_limit_cond.wait(&mut rate_limit);
             }
 
@@ -1106,7 +2673,7 @@ impl SimulationInner {
                 }
             }
 
-            self.process_commands(&global_logic, true);
+            self.process_commands(&logic, true);
         }
 
         self.event_sender
@@ -1114,6 +2681,93 @@ impl SimulationInner {
             .unwrap();
     }
 
+    /// Returns the clients of either the main or the secondary chain
+    fn get_chain_clients(&self, secondary: bool) -> Vec<Rc<Client>> {
+        // Nodes of the secondary chain come after those of the main chain
+        let first_secondary_node = self.network_config.num_nodes();
+
+        self.scene
+            .get_clients()
+            .iter()
+            .filter(|client| (client.get_node().get_index() >= first_secondary_node) == secondary)
+            .cloned()
+            .collect()
+    }
+
+    fn compute_fingerprint(&self, logic: &ChainLogic) -> Fingerprint {
+        let mut fingerprint = FingerprintBuilder::default();
+        fingerprint.add_u64(self.asim.get_timer().now().to_millis());
+
+        logic.main.add_to_fingerprint(&mut fingerprint);
+        if let Some(secondary_logic) = &logic.secondary {
+            secondary_logic.add_to_fingerprint(&mut fingerprint);
+        }
+
+        // Object identifiers are random, so links are identified by their endpoints
+        let mut traffic = vec![];
+        let link_statistics = self.scene.get_link_statistics();
+
+        for (link_id, link) in self.scene.get_links().iter() {
+            let stats = &link_statistics[link_id];
+            let (node1, node2) = link.get_nodes();
+            let (node1, node2) = (node1.get_index(), node2.get_index());
+
+            let forward = stats.get(LinkDirection::Forward);
+            let backward = stats.get(LinkDirection::Backward);
+
+            if node1 < node2 {
+                traffic.push((node1, node2, forward.num_bytes, backward.num_bytes));
+            } else {
+                traffic.push((node2, node1, backward.num_bytes, forward.num_bytes));
+            }
+        }
+
+        traffic.sort();
+        for (node1, node2, forward, backward) in traffic {
+            fingerprint.add_u64(node1 as u64);
+            fingerprint.add_u64(node2 as u64);
+            fingerprint.add_u64(forward);
+            fingerprint.add_u64(backward);
+        }
+
+        fingerprint.finish()
+    }
+
+    fn log_speed(&self, speed: &SimulationSpeed) {
+        let SimulationSpeed {
+            speedup,
+            events_per_second,
+        } = speed;
+
+        match *self.rate_limit.lock() {
+            Some(rate_limit) if rate_limit > 0 => {
+                let target = (rate_limit as f64) / 1000.0;
+                log::info!(
+                    "Simulation running at {speedup:.2}x (target is {target:.2}x) and {events_per_second:.0} events/s"
+                );
+
+                if *speedup < 0.9 * target {
+                    log::warn!("Simulation cannot keep up with the requested speed");
+                }
+            }
+            _ => {
+                log::info!(
+                    "Simulation running at {speedup:.2}x and {events_per_second:.0} events/s"
+                );
+            }
+        }
+    }
+
+    /// Returns true if the simulation has to stop because of a failure
+    fn check_failure(&self) -> bool {
+        let Some(err) = take_failure() else {
+            return false;
+        };
+
+        *self.failure.lock() = Some(format!("{err:#}"));
+        true
+    }
+
     fn update_stopped(&self) {
         // Tasks might wake up other tasks so we loop here
         loop {
@@ -1124,17 +2778,63 @@ impl SimulationInner {
         }
     }
 
+    /// Crashes all nodes of the main chain in the given regions once the outage begins
+    fn schedule_region_outage(&self, outage: RegionOutage) {
+        let scene = self.scene.clone();
+        let num_nodes = self.network_config.num_nodes();
+
+        self.asim.spawn(async move {
+            let start = Duration::from_millis(outage.time);
+            if !start.is_zero() {
+                asim::time::sleep(start).await;
+            }
+
+            log::info!("Regions {:?} went down", outage.regions);
+
+            for (idx, node) in scene.get_nodes().iter() {
+                let region = node.get_location().get_region(outage.num_regions);
+                if *idx < num_nodes && outage.regions.contains(&region) {
+                    node.crash();
+                }
+            }
+        });
+    }
+
+    /// Splits the network once the window begins and heals it once the window ends
+    fn schedule_partition(&self, window: PartitionWindow) {
+        let scene = self.scene.clone();
+
+        self.asim.spawn(async move {
+            let start = Duration::from_millis(window.start);
+            if !start.is_zero() {
+                asim::time::sleep(start).await;
+            }
+
+            log::info!("Network partitioned into {:?}", window.groups);
+            scene.set_partition(&window.groups);
+
+            asim::time::sleep(Duration::from_millis(window.duration)).await;
+
+            log::info!("Network partition healed");
+            scene.set_partition(&[]);
+        });
+    }
+
     fn update(&self) {
         // Move time to the next event and execute it
         self.asim.get_timer().advance();
 
         // Tasks might wake up other tasks so we loop here
+        let mut task_passes = 0;
         loop {
             let did_work = self.asim.execute_tasks();
             if !did_work {
                 break;
             }
+            task_passes += 1;
         }
+
+        self.statistics.record_simulator_tick(task_passes);
     }
 }
 
@@ -1162,6 +2862,13 @@ impl Drop for Simulation {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{
+        AsynchronyConfig, ChurnAction, ChurnConfig, ChurnEvent, DisconnectedPolicy,
+        ExecutionConfig, MinerPolicy, PeerScoringConfig, ProtocolUpgrade, RequestRetryConfig,
+        RpcLoadConfig, StallWindow, TrafficPattern, UpgradeActivation, VotingPowerDistribution,
+        Workload,
+    };
+    use crate::logic::TransactionFee;
     use test_log::test;
 
     #[test]
@@ -1178,6 +2885,11 @@ mod tests {
             link_bandwidth: None,
             link_latency: 0,
             workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
         };
 
         let failures = Failures::none(num_mining_nodes);
@@ -1193,6 +2905,24 @@ mod tests {
             simulation.get_network_metric(NetworkMetricType::NodePeerCount(4)) as u32,
             num_mining_nodes - 1
         );
+
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::NumMiningNodes) as u32,
+            num_mining_nodes
+        );
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::NumNonMiningNodes) as u32,
+            0
+        );
+
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::AverageNodeDegree) as u32,
+            num_mining_nodes - 1
+        );
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::Diameter) as u32,
+            1
+        );
     }
 
     #[test]
@@ -1206,11 +2936,17 @@ mod tests {
             num_non_mining_nodes: 0,
             connectivity: Connectivity::Sparse {
                 min_conns_per_node: 4,
+                on_disconnected: DisconnectedPolicy::Repair,
             },
             node_bandwidth: 50,
             link_bandwidth: None,
             link_latency: 0,
             workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
         };
 
         let failures = Failures::none(num_mining_nodes);
@@ -1239,6 +2975,11 @@ mod tests {
             link_bandwidth: None,
             link_latency: 0,
             workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
         };
 
         let failures = Failures::none(num_mining_nodes);
@@ -1250,4 +2991,1795 @@ mod tests {
             1
         );
     }
+
+    #[test]
+    fn pause_at() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.pause_at(Time::from_seconds(10));
+        simulation.start();
+
+        while simulation.get_rate_limit() != Some(0) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        assert_eq!(simulation.get_current_time(), Time::from_seconds(10));
+    }
+
+    #[test]
+    fn inject_and_crash() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let txn_id = simulation.inject_transaction(0).unwrap();
+        assert!(simulation.get_mempool(0).contains(&txn_id));
+        assert_eq!(
+            simulation.get_transaction_status(0, txn_id),
+            TransactionStatus::Pending
+        );
+
+        simulation.crash_node(1);
+        assert_eq!(simulation.inject_transaction(1), None);
+    }
+
+    #[test]
+    fn inject_without_transactions() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 1,
+            pattern: TrafficPattern::Pair,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // Speed tests only send raw data, so the transaction is dropped
+        assert_eq!(simulation.inject_transaction(0), None);
+    }
+
+    #[test]
+    fn regional_client_assignment() {
+        // Two nodes in the western and one in the eastern hemisphere
+        let locations = [
+            Location::new(-100, 0),
+            Location::new(-50, 10),
+            Location::new(100, 0),
+        ];
+        let assignment = ClientAssignment::Regional { num_regions: 2 };
+
+        // Clients alternate between regions first and then between nodes of a region
+        assert_eq!(assign_clients(assignment, 6, &locations), vec![
+            0, 2, 1, 2, 0, 2
+        ]);
+        assert_eq!(
+            assign_clients(ClientAssignment::RoundRobin, 4, &locations),
+            vec![0, 1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn client_assignments() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 6,
+                client_assignment: ClientAssignment::RoundRobin,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let clients = simulation.get_client_assignments();
+        let nodes: Vec<_> = clients.iter().map(|client| client.node).collect();
+        assert_eq!(nodes, vec![0, 1, 2, 0, 1, 2]);
+        assert!(clients.iter().all(|client| client.region.is_none()));
+    }
+
+    #[test]
+    fn confirmation_depths() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 4,
+                confirmation_depths: vec![1, 3],
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10_000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 10 {
+                break;
+            }
+
+            simulation.step(1000);
+        }
+
+        let statistics = simulation.get_confirmation_statistics();
+        assert_eq!(
+            statistics.latencies.keys().copied().collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+
+        // Waiting for more blocks takes longer
+        let fast = statistics.get_avg_latency(1).unwrap();
+        let slow = statistics.get_avg_latency(3).unwrap();
+        assert!(fast < slow);
+    }
+
+    #[test]
+    fn empty_block_policy() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let mut protocol = ProtocolConfiguration::default();
+        if let ProtocolConfiguration::NakamotoConsensus { miner_policies, .. } = &mut protocol {
+            miner_policies.push(MinerPolicy {
+                min_mempool_size: u32::MAX,
+                ..Default::default()
+            });
+        }
+
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 4,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10_000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 10 {
+                break;
+            }
+
+            simulation.step(1000);
+        }
+
+        // Miners never include any of the pending transactions
+        assert!(simulation.get_ordering_statistics().latencies.is_empty());
+        assert!(!simulation.get_mempool(0).is_empty());
+    }
+
+    /// Every client has its own funded account, so none of their transfers are left out
+    /// Transfers only move funds between the clients, and overdrafts are never included.
+    fn check_nakamoto_execution(model: TransactionModel) {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let num_clients = 4;
+        let mut protocol = ProtocolConfiguration::default();
+        if let ProtocolConfiguration::NakamotoConsensus {
+            genesis, execution, ..
+        } = &mut protocol
+        {
+            *genesis = GenesisConfig::Accounts(
+                (1..=num_clients)
+                    .map(|account| GenesisAccount {
+                        account: account as AccountId,
+                        balance: 1000,
+                    })
+                    .collect(),
+            );
+            *execution = Some(ExecutionConfig {
+                model,
+                transfer_amount: 10,
+                block_reward: 50,
+            });
+        }
+
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // An account without funds (or inputs) cannot pay the fee
+        let unfunded_account = 1000;
+        let overdraft = simulation.submit_transaction(0, TransactionSpec {
+            source: Some(unfunded_account),
+            nonce: 1,
+            fee: TransactionFee {
+                max_fee: 10,
+                max_tip: 10,
+            },
+            submit_at: None,
+        });
+
+        for _ in 0..10_000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 10 {
+                break;
+            }
+
+            simulation.step(1000);
+        }
+
+        for client in simulation.get_client_assignments() {
+            assert!(client.num_transactions > 0);
+        }
+
+        let accounts: Vec<_> = (1..=num_clients)
+            .map(|account| {
+                simulation
+                    .get_account_state(0, account as AccountId)
+                    .expect("Client account does not exist")
+            })
+            .collect();
+
+        // Miners are paid to their own accounts, so the clients' funds only move between them
+        let total: u64 = accounts.iter().map(AccountState::get_balance).sum();
+        assert_eq!(total, 1000 * num_clients as u64);
+
+        // Outputs do not have nonces
+        if model == TransactionModel::Accounts {
+            for account in accounts.iter() {
+                // Every transaction sent the transfer amount to another client
+                assert!(account.get_nonce() > 0);
+                assert!(account.get_balance() + 10 * account.get_nonce() >= 1000);
+            }
+        }
+
+        assert!(matches!(
+            simulation.get_transaction_status(0, overdraft),
+            TransactionStatus::Unknown | TransactionStatus::Pending
+        ));
+        assert_eq!(simulation.get_account_state(0, unfunded_account), None);
+    }
+
+    #[test]
+    fn nakamoto_accounts() {
+        check_nakamoto_execution(TransactionModel::Accounts);
+    }
+
+    #[test]
+    fn nakamoto_utxos() {
+        check_nakamoto_execution(TransactionModel::Utxo);
+    }
+
+    #[test]
+    fn node_chain_info() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let info = simulation.get_node_chain_info(0).unwrap();
+        assert_eq!(simulation.get_chain_head(0), Some((info.head, info.height)));
+        assert!(info.num_forks >= 1);
+    }
+
+    #[test]
+    fn pbft_rotate_proposer() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: true,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // Every node has to propose at least twice for the chain to get this long
+        let target_height = 2 * (num_mining_nodes as u64) + 1;
+
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= target_height {
+                return;
+            }
+
+            simulation.step(100);
+        }
+
+        panic!("Chain stopped growing after rotating the proposer");
+    }
+
+    #[test]
+    fn pbft_voting_power() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: VotingPowerDistribution::Explicit(vec![5, 1, 1, 1]),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // The leader and one replica still hold a quorum (6 out of 8),
+        // while half of the nodes would not be enough with equal voting power
+        simulation.crash_node(2);
+        simulation.crash_node(3);
+
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 3 {
+                return;
+            }
+
+            simulation.step(100);
+        }
+
+        panic!("Chain did not grow with a weighted quorum");
+    }
+
+    #[test]
+    fn pbft_observers() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let num_non_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes + num_non_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // Observers do not count towards the quorum,
+        // so three out of four validators are enough
+        simulation.crash_node(3);
+        simulation.crash_node(4);
+        simulation.crash_node(5);
+
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 3 {
+                return;
+            }
+
+            simulation.step(100);
+        }
+
+        panic!("Chain did not grow without observers");
+    }
+
+    fn run_pbft_with_crashed_node(rotate_proposer: bool, crashed: NodeIndex, target_height: u64) {
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        simulation.crash_node(crashed);
+        let correct_node = (crashed + 1) % num_mining_nodes;
+
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(correct_node).unwrap();
+            if height >= target_height {
+                return;
+            }
+
+            simulation.step(100);
+        }
+
+        panic!("Replicas did not replace the crashed leader");
+    }
+
+    #[test]
+    fn pbft_view_change() {
+        let _ = env_logger::try_init();
+
+        // Node 0 is the initial leader
+        run_pbft_with_crashed_node(false, 0, 3);
+    }
+
+    #[test]
+    fn pbft_view_change_rotating() {
+        let _ = env_logger::try_init();
+
+        // The crashed node would be the proposer of every fourth slot
+        run_pbft_with_crashed_node(true, 2, 9);
+    }
+
+    fn make_raft_simulation(num_mining_nodes: u32, num_non_mining_nodes: u32) -> Simulation {
+        let protocol = ProtocolConfiguration::Raft {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            election_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: Some(42),
+        };
+
+        let failures = Failures::none(num_mining_nodes + num_non_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+        simulation
+    }
+
+    /// Steps the simulation until the node committed `target_height` blocks
+    fn wait_for_height(simulation: &Simulation, node_idx: NodeIndex, target_height: u64) -> bool {
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(node_idx).unwrap();
+            if height >= target_height {
+                return true;
+            }
+
+            simulation.step(100);
+        }
+
+        false
+    }
+
+    #[test]
+    fn raft_learners() {
+        let _ = env_logger::try_init();
+
+        let simulation = make_raft_simulation(3, 2);
+
+        // Learners replicate the log without counting towards the quorum
+        assert!(wait_for_height(&simulation, 3, 3), "Learner did not commit");
+
+        simulation.crash_node(3);
+        simulation.crash_node(4);
+        assert!(
+            wait_for_height(&simulation, 0, 6),
+            "Chain did not grow without learners"
+        );
+    }
+
+    #[test]
+    fn raft_leader_election() {
+        let _ = env_logger::try_init();
+
+        // All runs are identical until the crash, so one of them crashes the leader
+        for crashed in 0..3 {
+            let simulation = make_raft_simulation(3, 0);
+            assert!(wait_for_height(&simulation, 0, 2), "No leader was elected");
+
+            simulation.crash_node(crashed);
+            let correct_node = (crashed + 1) % 3;
+            let (_, height) = simulation.get_chain_head(correct_node).unwrap();
+
+            assert!(
+                wait_for_height(&simulation, correct_node, height + 3),
+                "Chain did not grow after node #{crashed} crashed"
+            );
+        }
+
+        // Two out of three voters are needed for a quorum
+        let simulation = make_raft_simulation(3, 0);
+        assert!(wait_for_height(&simulation, 0, 2), "No leader was elected");
+
+        simulation.crash_node(1);
+        simulation.crash_node(2);
+        simulation.step(1000);
+
+        let (_, height) = simulation.get_chain_head(0).unwrap();
+        simulation.step(10_000);
+        assert_eq!(simulation.get_chain_head(0).unwrap().1, height);
+    }
+
+    fn make_snowman_simulation(num_mining_nodes: u32, num_non_mining_nodes: u32) -> Simulation {
+        let protocol = ProtocolConfiguration::Snowman {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            acceptance_threshold: 3,
+            sample_size_weighted: 0.5,
+            query_threshold_weighted: 0.6,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: Some(42),
+        };
+
+        let failures = Failures::none(num_mining_nodes + num_non_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+        simulation
+    }
+
+    #[test]
+    fn snowman_chain() {
+        let _ = env_logger::try_init();
+
+        let simulation = make_snowman_simulation(5, 1);
+        assert!(wait_for_height(&simulation, 0, 5), "Chain did not grow");
+
+        // Non-mining nodes accept blocks by querying validators
+        assert!(
+            wait_for_height(&simulation, 5, 5),
+            "Non-mining node did not accept any blocks"
+        );
+    }
+
+    #[test]
+    fn snowman_crashed_validator() {
+        let _ = env_logger::try_init();
+
+        // Every fifth height is proposed by the crashed node
+        let simulation = make_snowman_simulation(5, 0);
+        simulation.crash_node(2);
+
+        assert!(
+            wait_for_height(&simulation, 0, 8),
+            "Chain did not grow with a crashed validator"
+        );
+    }
+
+    #[test]
+    fn peer_bans() {
+        let _ = env_logger::try_init();
+
+        // Node 2 keeps including transactions, which the other nodes no longer allow
+        let mut protocol = ProtocolConfiguration::default();
+        if let ProtocolConfiguration::NakamotoConsensus {
+            upgrades,
+            peer_scoring,
+            ..
+        } = &mut protocol
+        {
+            upgrades.push(ProtocolUpgrade {
+                activation: UpgradeActivation::Height(0),
+                max_block_size: Some(0),
+                max_block_bytes: None,
+                legacy_nodes: vec![2],
+            });
+            *peer_scoring = Some(PeerScoringConfig {
+                unrequested_penalty: 1,
+                invalid_block_penalty: 10,
+                ban_threshold: 10,
+            });
+        }
+
+        let num_mining_nodes = 3;
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 4,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10_000 {
+            let statistics = simulation.get_peer_ban_statistics().unwrap();
+            if statistics.num_bans > 0 {
+                // Node 2 is not faulty, it just follows different rules
+                assert_eq!(statistics.false_positive_bans, statistics.num_bans);
+                assert_eq!(statistics.get_false_positive_rate(), 1.0);
+                return;
+            }
+
+            simulation.step(1000);
+        }
+
+        panic!("No peer was banned");
+    }
+
+    #[test]
+    fn request_retries() {
+        let _ = env_logger::try_init();
+
+        let mut protocol = ProtocolConfiguration::default();
+        if let ProtocolConfiguration::NakamotoConsensus {
+            request_retries, ..
+        } = &mut protocol
+        {
+            *request_retries = Some(RequestRetryConfig {
+                timeout: 500,
+                max_attempts: 2,
+            });
+        }
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes: 2,
+            num_non_mining_nodes: 1,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 100,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(3);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        // Crash node 0 right after it announced a new block,
+        // so that the other nodes never get an answer to their requests
+        let mut crashed = false;
+        for _ in 0..10_000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            let (_, other_height) = simulation.get_chain_head(1).unwrap();
+
+            if height > other_height {
+                simulation.crash_node(0);
+                crashed = true;
+                break;
+            }
+
+            simulation.step_until(simulation.get_current_time() + Duration::from_millis(10));
+        }
+        assert!(crashed, "Node 0 did not mine a block");
+
+        // Give nodes enough time to give up on their requests
+        simulation.step_until(simulation.get_current_time() + Duration::from_seconds(5));
+
+        let stalled_requests: u64 = simulation
+            .get_statistics_history()
+            .iter()
+            .map(|stats| stats.stalled_requests)
+            .sum();
+        assert!(stalled_requests > 0);
+
+        // Node 1 still mines on its own chain
+        let (_, height) = simulation.get_chain_head(1).unwrap();
+        for _ in 0..10_000 {
+            if simulation.get_chain_head(1).unwrap().1 > height {
+                return;
+            }
+
+            simulation.step(1000);
+        }
+
+        panic!("Chain did not grow after the requests stalled");
+    }
+
+    #[test]
+    fn memory_usage() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..1000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            if height >= 3 {
+                break;
+            }
+
+            simulation.step(100);
+        }
+
+        let node_usage = simulation.get_node_memory_usage(0);
+        assert!(node_usage.get("rounds") > 0);
+
+        // The total also includes the blocks of the global ledger
+        let usage = simulation.get_memory_usage();
+        assert!(usage.get("all_blocks") > 0);
+        assert!(usage.get("rounds") >= node_usage.get("rounds"));
+
+        let node_total: u64 = (0..num_mining_nodes)
+            .map(|idx| simulation.get_node_memory_usage(idx).get_total())
+            .sum();
+        assert_eq!(usage.get_total(), node_total + usage.get("all_blocks"));
+    }
+
+    #[test]
+    fn client_latency() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 10,
+                client_latency: 250,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+        simulation.step_until(Time::from_seconds(5));
+
+        // Every commit notification has to travel to the client
+        let latencies = simulation.get_ordering_statistics().latencies;
+        assert!(!latencies.is_empty());
+        assert!(latencies.get_percentile(0.0) >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn gossip_relays() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let num_non_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::Gossip {
+            retry_delay: 10_000,
+            block_size: 1024,
+            fanout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 10,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes + num_non_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..100 {
+            simulation.step(1000);
+        }
+
+        let timeout = TimeoutConfig::Seconds {
+            warmup: 0,
+            runtime: 1,
+        };
+        let metrics = simulation.get_chain_metrics(timeout);
+
+        // Relays receive the block as well, but are not needed to reach all miners
+        assert_eq!(metrics.total_blocks_accepted, 1);
+        assert!(metrics.avg_validator_block_propagation > 0.0);
+        assert!(metrics.avg_validator_block_propagation <= metrics.avg_block_propagation);
+    }
+
+    #[test]
+    fn asynchrony() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let stall_end = Time::from_millis(2000);
+        let asynchrony = AsynchronyConfig::Windows(vec![StallWindow {
+            start: 0,
+            length: 2000,
+        }]);
+
+        let failures = Failures::none(num_mining_nodes).with_asynchrony(asynchrony);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10000 {
+            let (_, height) = simulation.get_chain_head(0).unwrap();
+            let now = simulation.get_current_time();
+
+            if height > 0 {
+                assert!(now >= stall_end, "Block committed during stall");
+                return;
+            }
+
+            simulation.step(10);
+        }
+
+        panic!("Chain did not grow after the stall");
+    }
+
+    #[test]
+    fn gossip_fanout() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 8;
+        let fanout = 5;
+        let protocol = ProtocolConfiguration::Gossip {
+            retry_delay: 10_000,
+            block_size: 1024,
+            fanout: Some(fanout),
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..100 {
+            simulation.step(1000);
+        }
+
+        let timeout = TimeoutConfig::Seconds {
+            warmup: 0,
+            runtime: 1,
+        };
+        let metrics = simulation.get_chain_metrics(timeout);
+
+        // Every node relays the block once, and all but the first announcement
+        // (or any announcement at the creator) are redundant
+        let num_announcements = num_mining_nodes * fanout;
+        let num_duplicates = num_announcements - (num_mining_nodes - 1);
+        let expected = (num_duplicates as f64) / (num_mining_nodes as f64);
+        assert!((metrics.avg_duplicate_messages - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn network_partition() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 1,
+            pattern: TrafficPattern::AllToAll,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        simulation.step_until(Time::from_seconds(3));
+        assert!(simulation.get_node_statistics(0).incoming_data > 0);
+
+        // Cut off the first node
+        simulation.partition_network(vec![vec![0]]);
+        simulation.step_until(Time::from_seconds(6));
+        assert_eq!(simulation.get_node_statistics(0).incoming_data, 0);
+        assert!(simulation.get_node_statistics(1).incoming_data > 0);
+
+        simulation.heal_network();
+        simulation.step_until(Time::from_seconds(9));
+        assert!(simulation.get_node_statistics(0).incoming_data > 0);
+    }
+
+    #[test]
+    fn scheduled_partition() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 1,
+            pattern: TrafficPattern::AllToAll,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        // Cut off the first node from two to seven seconds
+        let failures = Failures::none(num_mining_nodes).with_partitions(vec![PartitionWindow {
+            start: 2000,
+            duration: 5000,
+            groups: vec![vec![0]],
+        }]);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        simulation.step_until(Time::from_millis(1900));
+        assert!(simulation.get_node_statistics(0).incoming_data > 0);
+
+        simulation.step_until(Time::from_seconds(6));
+        assert_eq!(simulation.get_node_statistics(0).incoming_data, 0);
+        assert!(simulation.get_node_statistics(1).incoming_data > 0);
+
+        simulation.step_until(Time::from_seconds(10));
+        assert!(simulation.get_node_statistics(0).incoming_data > 0);
+    }
+
+    #[test]
+    fn nakamoto_churn() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let protocol = ProtocolConfiguration::default();
+
+        // The only non-mining node joins after two minutes
+        let churn = ChurnConfig::Scripted(vec![
+            ChurnEvent {
+                time: 0,
+                node: num_mining_nodes,
+                action: ChurnAction::Leave,
+            },
+            ChurnEvent {
+                time: 120_000,
+                node: num_mining_nodes,
+                action: ChurnAction::Join,
+            },
+        ]);
+
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 1,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: Some(churn),
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes + 1);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        simulation.step_until(Time::from_seconds(119));
+        let (_, height) = simulation.get_chain_head(0).unwrap();
+        assert!(height > 0);
+        assert_eq!(
+            simulation
+                .get_node_statistics(num_mining_nodes)
+                .incoming_data,
+            0
+        );
+
+        // The node catches up once it learns about the next block
+        for _ in 0..10_000 {
+            let (_, synced_height) = simulation.get_chain_head(num_mining_nodes).unwrap();
+            if synced_height >= height {
+                return;
+            }
+
+            simulation.step(1000);
+        }
+
+        panic!("Node did not sync after joining");
+    }
+
+    #[test]
+    fn speed_test_star() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 4;
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 1,
+            pattern: TrafficPattern::Star,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10 {
+            simulation.step(1000);
+        }
+
+        // Only the first node sends, and it does so to every other node
+        let links = simulation.get_link_goodput();
+        assert_eq!(links.len() as u32, num_mining_nodes - 1);
+
+        for link in links {
+            assert_eq!(link.source, 0);
+            assert!(link.goodput > 0.0);
+            assert!(link.goodput <= link.bandwidth);
+        }
+    }
+
+    #[test]
+    fn shared_uplink() {
+        let _ = env_logger::try_init();
+
+        // The sender wants to send 40 Mbit/s to each of its three peers,
+        // but its uplink only provides 50 Mbit/s in total
+        let num_mining_nodes = 4;
+        let node_bandwidth = 50;
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 40,
+            pattern: TrafficPattern::Star,
+        };
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+        simulation.step_until(Time::from_seconds(5));
+
+        let total_goodput: f64 = simulation
+            .get_link_goodput()
+            .iter()
+            .map(|link| link.goodput)
+            .sum();
+        assert!(total_goodput <= (node_bandwidth as f64) * 1_000_000.0 * 1.01);
+
+        assert_eq!(simulation.get_node_statistics(0).saturation_time, 1_000_000);
+        assert_eq!(simulation.get_node_statistics(1).saturation_time, 0);
+    }
+
+    #[test]
+    fn submit_transaction() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let submit_at = simulation.get_current_time() + Duration::from_millis(100);
+        let spec = TransactionSpec {
+            nonce: 5,
+            submit_at: Some(submit_at),
+            ..Default::default()
+        };
+
+        let txn_id = simulation.submit_transaction(0, spec);
+        assert_eq!(
+            simulation.get_transaction_status(0, txn_id),
+            TransactionStatus::Unknown
+        );
+
+        let mut now = simulation.get_current_time();
+        while simulation.get_transaction_status(0, txn_id) == TransactionStatus::Unknown {
+            assert!(now <= submit_at, "Transaction was not submitted in time");
+            now = simulation.step(1);
+        }
+
+        assert!(now >= submit_at);
+        assert!(simulation.get_mempool(0).contains(&txn_id));
+    }
+
+    #[test]
+    fn statistics_snapshot() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 3;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_stats_event_callback(Box::new(move |event| {
+            let _ = sender.send(event);
+        }));
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let mut snapshot = None;
+        for _ in 0..100 {
+            simulation.step(1000);
+
+            if let Ok(StatisticsEvent::Updated(data)) =
+                receiver.recv_timeout(std::time::Duration::from_millis(10))
+            {
+                snapshot = Some(data);
+                break;
+            }
+        }
+
+        let snapshot = snapshot.expect("Did not receive any statistics");
+        assert_eq!(snapshot.nodes.len() as u32, num_mining_nodes);
+
+        let header = snapshot.get_csv_header();
+        assert_eq!(header[0], "time");
+        assert_eq!(header.len(), snapshot.get_csv_record().len());
+    }
+
+    #[test]
+    fn rpc_load() {
+        let _ = env_logger::try_init();
+
+        let num_mining_nodes = 2;
+        let protocol = ProtocolConfiguration::default();
+        let network = NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Workload {
+                num_clients: 4,
+                rpc_load: Some(RpcLoadConfig {
+                    query_interval: 100,
+                    block_query_fraction: 0.5,
+                    processing_time: 1000,
+                }),
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let failures = Failures::none(num_mining_nodes);
+        let simulation = Simulation::new(protocol, network, failures, None).unwrap();
+        simulation.set_stats_event_callback(Box::new(move |event| {
+            let _ = sender.send(event);
+        }));
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        let mut snapshot = None;
+        for _ in 0..100 {
+            simulation.step(1000);
+
+            if let Ok(StatisticsEvent::Updated(data)) =
+                receiver.recv_timeout(std::time::Duration::from_millis(10))
+            {
+                snapshot = Some(data);
+                break;
+            }
+        }
+
+        let snapshot = snapshot.expect("Did not receive any statistics");
+        assert!(snapshot.global.rpc_queries > 0);
+        assert_eq!(snapshot.global.dropped_rpc_queries, 0);
+
+        let rpc_traffic: u64 = snapshot.nodes.values().map(|node| node.rpc_traffic).sum();
+        assert!(rpc_traffic > 0);
+    }
+
+    #[test]
+    fn multi_chain() {
+        let _ = env_logger::try_init();
+
+        let make_network = |num_mining_nodes| NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 0,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let bridge = BridgeConfig {
+            main_node: 0,
+            secondary_node: 1,
+            direction: BridgeDirection::MainToSecondary,
+            transfer_interval: 0,
+            amount: 1,
+        };
+
+        let invalid_secondary = SecondaryChainConfiguration {
+            protocol: ProtocolConfiguration::default(),
+            network: make_network(2),
+            bridges: vec![BridgeConfig {
+                secondary_node: 2,
+                ..bridge.clone()
+            }],
+            swaps: vec![],
+        };
+        assert!(
+            Simulation::new_multi_chain(
+                ProtocolConfiguration::default(),
+                make_network(3),
+                invalid_secondary,
+                Failures::none(3),
+                None
+            )
+            .is_err()
+        );
+
+        let invalid_swap = SecondaryChainConfiguration {
+            protocol: ProtocolConfiguration::default(),
+            network: make_network(2),
+            bridges: vec![],
+            swaps: vec![AtomicSwapConfig {
+                main_node: 0,
+                secondary_node: 0,
+                initiator_timeout: 1000,
+                participant_timeout: 1000,
+                swap_interval: 0,
+            }],
+        };
+        assert!(
+            Simulation::new_multi_chain(
+                ProtocolConfiguration::default(),
+                make_network(3),
+                invalid_swap,
+                Failures::none(3),
+                None
+            )
+            .is_err()
+        );
+
+        let secondary = SecondaryChainConfiguration {
+            protocol: ProtocolConfiguration::default(),
+            network: make_network(2),
+            bridges: vec![bridge],
+            swaps: vec![AtomicSwapConfig {
+                main_node: 1,
+                secondary_node: 0,
+                initiator_timeout: 10 * 60 * 1000,
+                participant_timeout: 5 * 60 * 1000,
+                swap_interval: 0,
+            }],
+        };
+        let simulation = Simulation::new_multi_chain(
+            ProtocolConfiguration::default(),
+            make_network(3),
+            secondary,
+            Failures::none(3),
+            None,
+        )
+        .unwrap();
+        simulation.start();
+
+        // The two chains are not connected to each other
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::NumMiningNodes) as u32,
+            5
+        );
+        assert_eq!(
+            simulation.get_network_metric(NetworkMetricType::NumLinks) as u32,
+            4
+        );
+        assert!(simulation.get_chain_head(4).is_some());
+    }
+
+    #[test]
+    fn secondary_gossip_chain() {
+        let _ = env_logger::try_init();
+
+        let make_network = |num_mining_nodes| NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 10,
+            workload: Default::default(),
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        // The block producer is the first node of the secondary chain, not of the simulation
+        let secondary = SecondaryChainConfiguration {
+            protocol: ProtocolConfiguration::Gossip {
+                retry_delay: 10_000,
+                block_size: 1024,
+                fanout: None,
+            },
+            network: make_network(4),
+            bridges: vec![],
+            swaps: vec![],
+        };
+        let simulation = Simulation::new_multi_chain(
+            ProtocolConfiguration::default(),
+            make_network(3),
+            secondary,
+            Failures::none(3),
+            None,
+        )
+        .unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..100 {
+            simulation.step(1000);
+        }
+
+        let timeout = TimeoutConfig::Seconds {
+            warmup: 0,
+            runtime: 1,
+        };
+        let metrics = simulation
+            .get_secondary_chain_metrics(timeout)
+            .expect("No secondary chain");
+        assert_eq!(metrics.total_blocks_accepted, 1);
+    }
+
+    /// Bridge endpoints are funded, so locks and releases commit even if both chains execute them
+    #[test]
+    fn bridge_with_execution() {
+        let _ = env_logger::try_init();
+
+        let make_protocol = || {
+            let mut protocol = ProtocolConfiguration::default();
+            if let ProtocolConfiguration::NakamotoConsensus { execution, .. } = &mut protocol {
+                *execution = Some(ExecutionConfig {
+                    model: TransactionModel::Accounts,
+                    transfer_amount: 10,
+                    block_reward: 50,
+                });
+            }
+            protocol
+        };
+        let make_network = |num_mining_nodes| NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            connectivity: Connectivity::Full,
+            node_bandwidth: 50,
+            link_bandwidth: None,
+            link_latency: 10,
+            workload: Workload {
+                num_clients: 0,
+                ..Default::default()
+            },
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        };
+
+        let secondary = SecondaryChainConfiguration {
+            protocol: make_protocol(),
+            network: make_network(2),
+            bridges: vec![BridgeConfig {
+                main_node: 0,
+                secondary_node: 0,
+                direction: BridgeDirection::MainToSecondary,
+                transfer_interval: 0,
+                amount: 100,
+            }],
+            swaps: vec![],
+        };
+        let simulation = Simulation::new_multi_chain(
+            make_protocol(),
+            make_network(3),
+            secondary,
+            Failures::none(3),
+            None,
+        )
+        .unwrap();
+        simulation.set_rate_limit(0);
+        simulation.start();
+
+        for _ in 0..10_000 {
+            if !simulation.get_bridge_latencies().is_empty() {
+                break;
+            }
+
+            simulation.step(1000);
+        }
+
+        assert!(!simulation.get_bridge_latencies().is_empty());
+    }
 }
@@ -1,34 +1,261 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::rc::Rc;
 
+use crate::anomalies::{Anomaly, AnomalyDetector, AnomalyThresholds};
 use crate::emit_event;
 use crate::events::{Event, StatisticsEvent};
+use crate::logic::GlobalLogic;
+use crate::memory::MemoryUsage;
+use crate::metrics::MempoolStatistics;
+use crate::node::{NodeData, NodeIndex};
 use crate::scene::Scene;
 
-use asim::time::Duration;
+use asim::time::{Duration, Time};
+
+use serde::{Deserialize, Serialize};
 
 use struct_iterable::Iterable as StructIterable;
 
 #[derive(
-    PartialEq, Eq, Clone, Debug, Default, StructIterable, derive_more::AddAssign, derive_more::Div,
+    PartialEq,
+    Eq,
+    Clone,
+    Debug,
+    Default,
+    StructIterable,
+    derive_more::AddAssign,
+    derive_more::Div,
+    Serialize,
+    Deserialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[iterable(std::fmt::Display)]
 pub struct NodeStatistics {
     /// Incoming data in bytes/s
     pub incoming_data: u64,
+    /// Messages dropped due to a full inbox (per second)
+    pub dropped_messages: u64,
+    /// Number of times the node switched to another fork (per second)
+    pub reorgs: u64,
+    /// The deepest reorg within a second
+    pub max_reorg_depth: u64,
+    /// Transactions committed by the node (per second)
+    pub committed_transactions: u64,
+    /// The longest time a block took to reach the node within a second (in milliseconds)
+    pub max_block_delay: u64,
+    /// Blocks that violated the node's protocol rules (per second)
+    pub rejected_blocks: u64,
+    /// Client queries answered (per second)
+    pub rpc_queries: u64,
+    /// Client queries dropped due to a full inbox (per second)
+    pub dropped_rpc_queries: u64,
+    /// Data sent in response to client queries in bytes/s
+    pub rpc_traffic: u64,
+    /// Transactions in the mempool when the data point was taken
+    pub mempool_size: u64,
+    /// Time the node's uplink was busy (in microseconds per second)
+    /// All links share the uplink, so a value of 1,000,000 means it was saturated
+    pub saturation_time: u64,
+    /// Requests for blocks or transactions that peers did not answer in time (per second)
+    pub stalled_requests: u64,
+    /// Estimated memory used by the node's protocol structures when the data point was taken (in bytes)
+    pub memory_usage: u64,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Default, StructIterable)]
+#[derive(PartialEq, Eq, Clone, Debug, Default, StructIterable, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[iterable(std::fmt::Display)]
 pub struct GlobalStatistics {
     /// Total network traffic in bytes/s
     pub network_traffic: u64,
+    /// Total messages dropped due to full inboxes (per second)
+    pub dropped_messages: u64,
+    /// Total number of chain reorganizations (per second)
+    pub reorgs: u64,
+    /// The deepest reorg at any node within a second
+    pub max_reorg_depth: u64,
+    /// Transactions committed per second (averaged over all nodes)
+    pub committed_transactions: u64,
+    /// The longest time a block took to reach any node within a second (in milliseconds)
+    pub max_block_delay: u64,
+    /// Total blocks rejected for violating a node's protocol rules (per second)
+    pub rejected_blocks: u64,
+    /// Total client queries answered (per second)
+    pub rpc_queries: u64,
+    /// Total client queries dropped due to full inboxes (per second)
+    pub dropped_rpc_queries: u64,
+    /// Timer events processed by the simulator (per second)
+    pub simulator_events: u64,
+    /// Passes over runnable tasks made by the simulator (per second)
+    pub simulator_task_passes: u64,
+    /// Total transactions in all mempools
+    pub mempool_size: u64,
+    /// Estimated size of the ledger state at the head of the longest chain (in bytes)
+    pub state_size: u64,
+    /// Total requests for blocks or transactions that peers did not answer in time (per second)
+    pub stalled_requests: u64,
+    /// Estimated memory used by all protocol structures, including those of the nodes (in bytes)
+    pub memory_usage: u64,
+}
+
+/// A network-wide statistic that is sampled every (virtual) second
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    derive_more::Display,
+    derive_more::FromStr,
+    Serialize,
+    Deserialize,
+)]
+pub enum StatisticType {
+    NetworkTraffic,
+    DroppedMessages,
+    Reorgs,
+    RpcQueries,
+    DroppedRpcQueries,
+    MempoolSize,
+    StateSize,
+    MemoryUsage,
+    StalledRequests,
+}
+
+/// The latest statistics of the entire network
+///
+/// This is pushed with every statistics update, so that observers,
+/// such as the GUI, do not have to query each node individually.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct StatisticsSnapshot {
+    /// When the statistics were collected
+    #[serde(with = "crate::serde_time::time_millis")]
+    pub time: Time,
+    pub global: GlobalStatistics,
+    /// The latest data point of every node
+    pub nodes: BTreeMap<NodeIndex, NodeStatistics>,
+    /// Unusual changes compared to the previous data points
+    pub anomalies: Vec<Anomaly>,
+    /// Memory used by each protocol structure, summed over all nodes
+    pub memory: MemoryUsage,
+}
+
+impl StatisticsSnapshot {
+    /// The column names for `get_csv_record`
+    pub fn get_csv_header(&self) -> Vec<String> {
+        let mut keys = vec!["time".to_string()];
+
+        for (key, _) in self.global.iter() {
+            keys.push(format!("network.{key}"));
+        }
+
+        for (idx, node_stats) in self.nodes.iter() {
+            for (key, _) in node_stats.iter() {
+                keys.push(format!("nodes.{idx}.{key}"));
+            }
+        }
+
+        for (name, _) in self.memory.iter() {
+            keys.push(format!("memory.{name}"));
+        }
+
+        keys.push("anomalies".to_string());
+        keys
+    }
+
+    /// The time (in milliseconds) followed by the global and per-node statistics,
+    /// and the memory used by each protocol structure
+    /// The last column describes all anomalies found, separated by semicolons
+    pub fn get_csv_record(&self) -> Vec<String> {
+        let mut values = vec![self.time.to_millis().to_string()];
+
+        for (_, val) in self.global.iter() {
+            values.push(val.to_string());
+        }
+
+        for node_stats in self.nodes.values() {
+            for (_, val) in node_stats.iter() {
+                values.push(val.to_string());
+            }
+        }
+
+        for (_, bytes) in self.memory.iter() {
+            values.push(bytes.to_string());
+        }
+
+        let anomalies: Vec<String> = self.anomalies.iter().map(|a| a.to_string()).collect();
+        values.push(anomalies.join("; "));
+
+        values
+    }
+
+    /// The same data as `get_csv_record`, but nested by category instead of flattened into columns
+    pub fn get_json_record(&self) -> serde_json::Value {
+        let memory: BTreeMap<&str, u64> = self.memory.iter().collect();
+
+        serde_json::json!({
+            "time": self.time.to_millis(),
+            "network": self.global,
+            "nodes": self.nodes,
+            "memory": memory,
+            "anomalies": self.anomalies,
+        })
+    }
+}
+
+impl GlobalStatistics {
+    /// The names of all statistics, in the same order as `get_csv_record`
+    pub fn get_csv_header(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key.to_string()).collect()
+    }
+
+    pub fn get_csv_record(&self) -> Vec<String> {
+        self.iter().map(|(_, val)| val.to_string()).collect()
+    }
+
+    pub fn get(&self, statistic: &StatisticType) -> f64 {
+        let value = match statistic {
+            StatisticType::NetworkTraffic => self.network_traffic,
+            StatisticType::DroppedMessages => self.dropped_messages,
+            StatisticType::Reorgs => self.reorgs,
+            StatisticType::RpcQueries => self.rpc_queries,
+            StatisticType::DroppedRpcQueries => self.dropped_rpc_queries,
+            StatisticType::MempoolSize => self.mempool_size,
+            StatisticType::StateSize => self.state_size,
+            StatisticType::MemoryUsage => self.memory_usage,
+            StatisticType::StalledRequests => self.stalled_requests,
+        };
+
+        value as f64
+    }
+
+    /// How many passes over runnable tasks were needed on average to process a timer event?
+    /// A high value indicates that the simulation is dominated by tasks waking up other tasks.
+    pub fn get_task_passes_per_tick(&self) -> f64 {
+        if self.simulator_events == 0 {
+            0.0
+        } else {
+            (self.simulator_task_passes as f64) / (self.simulator_events as f64)
+        }
+    }
 }
 
 impl std::ops::AddAssign<NodeStatistics> for GlobalStatistics {
     fn add_assign(&mut self, node_stats: NodeStatistics) {
         self.network_traffic += node_stats.incoming_data;
+        self.dropped_messages += node_stats.dropped_messages;
+        self.reorgs += node_stats.reorgs;
+        self.max_reorg_depth = self.max_reorg_depth.max(node_stats.max_reorg_depth);
+        self.committed_transactions += node_stats.committed_transactions;
+        self.max_block_delay = self.max_block_delay.max(node_stats.max_block_delay);
+        self.rejected_blocks += node_stats.rejected_blocks;
+        self.rpc_queries += node_stats.rpc_queries;
+        self.dropped_rpc_queries += node_stats.dropped_rpc_queries;
+        self.mempool_size += node_stats.mempool_size;
+        self.stalled_requests += node_stats.stalled_requests;
+        self.memory_usage += node_stats.memory_usage;
     }
 }
 
@@ -36,6 +263,11 @@ impl std::ops::AddAssign<NodeStatistics> for GlobalStatistics {
 pub struct NodeStatsCollector {
     pending: NodeStatistics,
     data_points: Vec<NodeStatistics>,
+    /// Maps reorg depth to the number of occurrences
+    reorg_depths: BTreeMap<u64, u64>,
+    /// Time from creation until commit for blocks committed by this node first
+    commit_delays: Vec<Duration>,
+    mempool: MempoolStatistics,
 }
 
 impl NodeStatsCollector {
@@ -62,14 +294,96 @@ impl NodeStatsCollector {
         self.pending.incoming_data += bytes;
     }
 
+    pub fn record_dropped_message(&mut self) {
+        self.pending.dropped_messages += 1;
+    }
+
+    pub fn record_rpc_query(&mut self, response_size: u64) {
+        self.pending.rpc_queries += 1;
+        self.pending.rpc_traffic += response_size;
+    }
+
+    pub fn set_mempool_size(&mut self, size: usize) {
+        self.pending.mempool_size = size as u64;
+    }
+
+    pub fn set_memory_usage(&mut self, bytes: u64) {
+        self.pending.memory_usage = bytes;
+    }
+
+    pub fn set_saturation_time(&mut self, busy_time: Duration) {
+        self.pending.saturation_time = (busy_time.as_seconds_f64() * 1_000_000.0).round() as u64;
+    }
+
+    pub fn record_dropped_rpc_query(&mut self) {
+        self.pending.dropped_rpc_queries += 1;
+    }
+
+    pub fn record_reorg(&mut self, depth: u64) {
+        self.pending.reorgs += 1;
+        self.pending.max_reorg_depth = self.pending.max_reorg_depth.max(depth);
+        *self.reorg_depths.entry(depth).or_default() += 1;
+    }
+
+    pub fn record_stalled_request(&mut self) {
+        self.pending.stalled_requests += 1;
+    }
+
+    pub fn record_transaction_commit(&mut self) {
+        self.pending.committed_transactions += 1;
+    }
+
+    /// Record how long it took for a block to reach this node after it was created
+    pub fn record_block_delay(&mut self, delay: Duration) {
+        let delay = (delay.as_seconds_f64() * 1000.0).round() as u64;
+        self.pending.max_block_delay = self.pending.max_block_delay.max(delay);
+    }
+
+    pub fn record_rejected_block(&mut self) {
+        self.pending.rejected_blocks += 1;
+    }
+
+    pub fn record_block_commit(&mut self, delay: Duration) {
+        self.commit_delays.push(delay);
+    }
+
+    /// Record a transaction of a block received by this node
+    /// `known` is set if the node had the transaction before the block arrived.
+    pub fn record_block_transaction(&mut self, known: bool) {
+        if known {
+            self.mempool.num_known_transactions += 1;
+        } else {
+            self.mempool.num_missing_transactions += 1;
+        }
+    }
+
+    /// Record how long a transaction was in the mempool before it was included in a block
+    pub fn record_mempool_age(&mut self, age: Duration) {
+        self.mempool.mempool_ages.record(age);
+    }
+
     fn reset(&mut self) {
         self.data_points.clear();
+        self.reorg_depths.clear();
+        self.commit_delays.clear();
+        self.mempool = Default::default();
     }
 }
 
+/// Keeps track of the work done by the simulator itself
+#[derive(Default)]
+struct RuntimeStatsCollector {
+    num_events: Cell<u64>,
+    num_task_passes: Cell<u64>,
+    /// Not reset after warmup, as it tracks the cost of the entire run
+    total_events: Cell<u64>,
+}
+
 pub struct Statistics {
+    runtime: RuntimeStatsCollector,
     stats_file: RefCell<Option<csv::Writer<File>>>,
     data_points: RefCell<Vec<GlobalStatistics>>,
+    anomaly_detector: RefCell<AnomalyDetector>,
     scene: Rc<Scene>,
 }
 
@@ -77,81 +391,110 @@ impl Statistics {
     pub fn new(scene: Rc<Scene>, stats_file: Option<csv::Writer<File>>) -> Self {
         Self {
             scene,
+            runtime: Default::default(),
             stats_file: RefCell::new(stats_file),
             data_points: RefCell::new(Default::default()),
+            anomaly_detector: RefCell::new(Default::default()),
         }
     }
 
     /// Will update statistics every second
-    pub async fn run(&self, warmup_time: Duration) {
+    pub async fn run(&self, global_logic: Rc<dyn GlobalLogic>, warmup_time: Duration) {
         if !warmup_time.is_zero() {
             asim::time::sleep(warmup_time);
         }
 
         log::debug!("Started statistics collection");
         let mut stats_file = self.stats_file.borrow_mut().take();
+        let mut wrote_header = false;
 
-        // Create CSV header
-        if let &mut Some(ref mut stats_file) = &mut stats_file {
+        if stats_file.is_some() {
             log::debug!("Writing statistics to file");
-
-            let global_stats = GlobalStatistics::default();
-            let mut keys = vec!["time".to_string()];
-
-            for (key, _) in global_stats.iter() {
-                keys.push(format!("network.{key}"));
-            }
-
-            for idx in 0..self.scene.get_nodes().len() {
-                let node_stats = NodeStatistics::default();
-                for (key, _) in node_stats.iter() {
-                    keys.push(format!("nodes.{idx}.{key}"));
-                }
-            }
-
-            stats_file.write_record(keys).unwrap();
         }
 
         loop {
             log::trace!("Updating statistics");
             let mut global_stats = GlobalStatistics::default();
+            let mut node_data = BTreeMap::new();
+            let mut memory = global_logic.get_memory_usage();
+
+            for (node_idx, node) in self.scene.get_nodes().iter() {
+                let mempool_size = NodeData::get_mempool(node).len();
+                let memory_usage = NodeData::get_memory_usage(node);
+                let busy_time = node.get_data().take_upload_busy_time();
+                memory.merge(&memory_usage);
 
-            for (_, node) in self.scene.get_nodes().iter() {
                 let data = {
                     let mut node_stats = node.get_data().get_statistics();
+                    node_stats.set_mempool_size(mempool_size);
+                    node_stats.set_memory_usage(memory_usage.get_total());
+                    node_stats.set_saturation_time(busy_time);
                     node_stats.update();
                     node_stats.get_latest_data_point()
                 };
 
-                global_stats += data;
+                global_stats += data.clone();
+                node_data.insert(*node_idx, data);
             }
 
-            if let &mut Some(ref mut stats_file) = &mut stats_file {
-                let global_stats = GlobalStatistics::default();
-                let mut values = vec![asim::time::now().to_millis().to_string()];
+            if !node_data.is_empty() {
+                global_stats.committed_transactions /= node_data.len() as u64;
+            }
 
-                for (_, val) in global_stats.iter() {
-                    values.push(val.to_string());
-                }
+            global_stats.simulator_events = self.runtime.num_events.take();
+            global_stats.simulator_task_passes = self.runtime.num_task_passes.take();
+            global_stats.state_size = global_logic.get_state_size();
+            global_stats.memory_usage = memory.get_total();
 
-                for (_, node) in self.scene.get_nodes().iter() {
-                    let node_stats = node.get_data().get_statistics().get_latest_data_point();
+            let anomalies = self.anomaly_detector.borrow_mut().check(&global_stats);
+            let now = asim::time::now();
+            for anomaly in anomalies.iter() {
+                log::info!("Anomaly at {}ms: {anomaly}", now.to_millis());
+            }
 
-                    for (_, val) in node_stats.iter() {
-                        values.push(val.to_string());
-                    }
+            let snapshot = StatisticsSnapshot {
+                time: now,
+                global: global_stats.clone(),
+                nodes: node_data,
+                anomalies,
+                memory,
+            };
+
+            if let Some(stats_file) = &mut stats_file {
+                if !wrote_header {
+                    stats_file.write_record(snapshot.get_csv_header()).unwrap();
+                    wrote_header = true;
                 }
 
-                stats_file.write_record(values).unwrap();
+                stats_file.write_record(snapshot.get_csv_record()).unwrap();
                 stats_file.flush().unwrap();
             }
 
-            emit_event!(Event::Statistics(StatisticsEvent::Updated));
+            emit_event!(Event::Statistics(StatisticsEvent::Updated(snapshot)));
             self.data_points.borrow_mut().push(global_stats);
             asim::time::sleep(Duration::from_seconds(1)).await;
         }
     }
 
+    /// Record that the simulator advanced to the next timer event
+    /// and needed the specified number of passes to run all tasks woken up by it
+    pub(crate) fn record_simulator_tick(&self, task_passes: u64) {
+        self.runtime
+            .num_events
+            .set(self.runtime.num_events.get() + 1);
+        self.runtime
+            .num_task_passes
+            .set(self.runtime.num_task_passes.get() + task_passes);
+        self.runtime
+            .total_events
+            .set(self.runtime.total_events.get() + 1);
+    }
+
+    /// The number of timer events processed since the simulation started
+    pub(crate) fn get_total_events(&self) -> u64 {
+        self.runtime.total_events.get()
+    }
+
     /// Reset statistics
     /// Used, for example, after warmup
     pub fn reset(&self) {
@@ -159,7 +502,65 @@ impl Statistics {
             node.get_data().get_statistics().reset();
         }
 
+        for (_, link_stats) in self.scene.get_link_statistics().iter() {
+            link_stats.reset();
+        }
+
+        self.runtime.num_events.set(0);
+        self.runtime.num_task_passes.set(0);
         self.data_points.borrow_mut().clear();
+        self.anomaly_detector.borrow_mut().reset();
+    }
+
+    /// Changes when data points are annotated as anomalies
+    pub fn set_anomaly_thresholds(&self, thresholds: AnomalyThresholds) {
+        self.anomaly_detector
+            .borrow_mut()
+            .set_thresholds(thresholds);
+    }
+
+    /// Maps reorg depth to the number of times it occurred at any node
+    pub fn get_reorg_histogram(&self) -> BTreeMap<u64, u64> {
+        let mut result = BTreeMap::new();
+
+        for (_, node) in self.scene.get_nodes().iter() {
+            let node_stats = node.get_data().get_statistics();
+            for (depth, count) in node_stats.reorg_depths.iter() {
+                *result.entry(*depth).or_default() += count;
+            }
+        }
+
+        result
+    }
+
+    /// Get the time from creation until commit for all committed blocks (in ascending order)
+    pub fn get_commit_delays(&self) -> Vec<Duration> {
+        let mut result = vec![];
+
+        for (_, node) in self.scene.get_nodes().iter() {
+            let node_stats = node.get_data().get_statistics();
+            result.extend_from_slice(&node_stats.commit_delays);
+        }
+
+        result.sort();
+        result
+    }
+
+    /// Combines the mempool statistics of all nodes
+    pub fn get_mempool_statistics(&self) -> MempoolStatistics {
+        let mut result = MempoolStatistics::default();
+
+        for (_, node) in self.scene.get_nodes().iter() {
+            let node_stats = node.get_data().get_statistics();
+            result.merge(&node_stats.mempool);
+        }
+
+        result
+    }
+
+    /// All data points collected since the start (or the end of warmup)
+    pub fn get_history(&self) -> Vec<GlobalStatistics> {
+        self.data_points.borrow().clone()
     }
 
     pub fn get_latest_data_point(&self) -> GlobalStatistics {
@@ -0,0 +1,169 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use asim::time::{Duration, Time};
+
+use crate::clients::Client;
+
+/// The outcome of all atomic swaps in a simulation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapResults {
+    /// How long each successful swap took
+    pub completion_times: Vec<Duration>,
+    /// Swaps that failed because a timeout expired before the claim was committed
+    pub num_failed: u64,
+}
+
+impl SwapResults {
+    pub fn num_swaps(&self) -> u64 {
+        self.completion_times.len() as u64 + self.num_failed
+    }
+
+    /// The fraction of swaps that succeeded
+    /// Returns `None` if no swap has finished yet
+    pub fn get_success_rate(&self) -> Option<f64> {
+        let num_swaps = self.num_swaps();
+
+        if num_swaps == 0 {
+            None
+        } else {
+            Some((self.completion_times.len() as f64) / (num_swaps as f64))
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: &SwapResults) {
+        self.completion_times
+            .extend_from_slice(&other.completion_times);
+        self.num_failed += other.num_failed;
+    }
+}
+
+/// Repeatedly performs HTLC-style atomic swaps between the two chains
+///
+/// The initiator locks funds on the main chain and the participant then locks
+/// funds on the secondary chain. The initiator claims the participant's funds,
+/// which reveals the secret that lets the participant claim the initiator's funds.
+/// Each lock can be refunded once its timeout expires, so a swap only succeeds
+/// if both claims are committed in time.
+pub struct AtomicSwap {
+    main: Rc<Client>,
+    secondary: Rc<Client>,
+    initiator_timeout: Duration,
+    participant_timeout: Duration,
+    swap_interval: Duration,
+    results: RefCell<SwapResults>,
+}
+
+impl AtomicSwap {
+    pub(crate) fn new(
+        main: Rc<Client>,
+        secondary: Rc<Client>,
+        initiator_timeout: Duration,
+        participant_timeout: Duration,
+        swap_interval: Duration,
+    ) -> Self {
+        assert!(
+            participant_timeout < initiator_timeout,
+            "Participant must be able to refund before the initiator"
+        );
+
+        Self {
+            main,
+            secondary,
+            initiator_timeout,
+            participant_timeout,
+            swap_interval,
+            results: Default::default(),
+        }
+    }
+
+    pub(crate) async fn run(&self) {
+        loop {
+            if self.main.get_node().is_crashed() || self.secondary.get_node().is_crashed() {
+                log::trace!("Swap endpoint crashed; will not start more swaps");
+                return;
+            }
+
+            let start = asim::time::now();
+
+            // Both parties lock their funds
+            self.main.submit_transaction().await;
+            self.secondary.submit_transaction().await;
+
+            // The initiator claims and, thereby, reveals the secret
+            let success = if Self::claim(&self.secondary, start, self.participant_timeout).await {
+                Self::claim(&self.main, start, self.initiator_timeout).await
+            } else {
+                log::trace!("Initiator did not claim in time; both parties refund");
+                Self::refund(&self.main, start, self.initiator_timeout).await;
+                false
+            };
+
+            {
+                let mut results = self.results.borrow_mut();
+                if success {
+                    results.completion_times.push(asim::time::now() - start);
+                } else {
+                    results.num_failed += 1;
+                }
+            }
+
+            if !self.swap_interval.is_zero() {
+                asim::time::sleep(self.swap_interval).await;
+            }
+        }
+    }
+
+    /// Claims the lock on the chain of `client` if its timeout has not expired yet
+    ///
+    /// Returns false if the lock was refunded instead, which happens if the timeout
+    /// expired before the claim was submitted or before it was committed.
+    async fn claim(client: &Client, start: Time, timeout: Duration) -> bool {
+        if asim::time::now() - start <= timeout {
+            client.submit_transaction().await;
+
+            if asim::time::now() - start <= timeout {
+                return true;
+            }
+        }
+
+        Self::refund(client, start, timeout).await;
+        false
+    }
+
+    /// Refunds the lock on the chain of `client`, which is only possible once its timeout expired
+    async fn refund(client: &Client, start: Time, timeout: Duration) {
+        let deadline = start + timeout;
+        let now = asim::time::now();
+        if deadline > now {
+            asim::time::sleep(deadline - now).await;
+        }
+
+        client.submit_transaction().await;
+    }
+
+    pub fn get_results(&self) -> SwapResults {
+        self.results.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asim::time::Duration;
+
+    use super::SwapResults;
+
+    #[test]
+    fn success_rate() {
+        let mut results = SwapResults::default();
+        assert_eq!(results.get_success_rate(), None);
+
+        results.merge(&SwapResults {
+            completion_times: vec![Duration::from_seconds(1); 3],
+            num_failed: 1,
+        });
+
+        assert_eq!(results.num_swaps(), 4);
+        assert_eq!(results.get_success_rate(), Some(0.75));
+    }
+}
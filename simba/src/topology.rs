@@ -0,0 +1,599 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+
+use rand::Rng;
+
+use crate::config::DisconnectedPolicy;
+use crate::node::{Location, NodeIndex};
+use crate::object::Object;
+use crate::random;
+use crate::scene::Scene;
+
+/// A snapshot of the network graph
+/// Used to compute metrics about the topology of the network
+pub(crate) struct Topology {
+    /// For each node, its neighbors and the latency (in milliseconds) to reach them
+    adjacency: Vec<Vec<(usize, f64)>>,
+}
+
+impl Topology {
+    pub fn from_scene(scene: &Scene) -> Self {
+        let nodes = scene.get_nodes();
+        let positions: HashMap<NodeIndex, usize> = nodes
+            .keys()
+            .enumerate()
+            .map(|(pos, idx)| (*idx, pos))
+            .collect();
+
+        let mut adjacency = vec![vec![]; nodes.len()];
+        let link_stats = scene.get_link_statistics();
+
+        for (link_id, link) in scene.get_links().iter() {
+            let (node1, node2) = link.get_nodes();
+            let pos1 = positions[&node1.get_index()];
+            let pos2 = positions[&node2.get_index()];
+
+            let latency = link_stats
+                .get(link_id)
+                .map(|stats| stats.get_latency().as_seconds_f64() * 1000.0)
+                .unwrap_or_else(|| panic!("No statistics for link {}", link.get_identifier()));
+
+            adjacency[pos1].push((pos2, latency));
+            adjacency[pos2].push((pos1, latency));
+        }
+
+        Self { adjacency }
+    }
+
+    /// Builds a topology from generated links, which do not have latencies yet
+    pub fn from_links(num_nodes: usize, links: &BTreeSet<(usize, usize)>) -> Self {
+        let edges: Vec<_> = links
+            .iter()
+            .map(|(node1, node2)| (*node1, *node2, 0.0))
+            .collect();
+        Self::from_edges(num_nodes, &edges)
+    }
+
+    fn from_edges(num_nodes: usize, edges: &[(usize, usize, f64)]) -> Self {
+        let mut adjacency = vec![vec![]; num_nodes];
+        for (node1, node2, latency) in edges {
+            adjacency[*node1].push((*node2, *latency));
+            adjacency[*node2].push((*node1, *latency));
+        }
+
+        Self { adjacency }
+    }
+
+    pub fn average_degree(&self) -> f64 {
+        if self.adjacency.is_empty() {
+            return 0.0;
+        }
+
+        let total: usize = self.adjacency.iter().map(|peers| peers.len()).sum();
+        (total as f64) / (self.adjacency.len() as f64)
+    }
+
+    /// The longest shortest path (in hops) between any two connected nodes
+    pub fn diameter(&self) -> u64 {
+        let mut diameter = 0;
+
+        for start in 0..self.adjacency.len() {
+            let mut distances = vec![None; self.adjacency.len()];
+            let mut queue = VecDeque::from([start]);
+            distances[start] = Some(0);
+
+            while let Some(current) = queue.pop_front() {
+                let distance = distances[current].unwrap();
+                diameter = diameter.max(distance);
+
+                for (peer, _) in self.adjacency[current].iter() {
+                    if distances[*peer].is_none() {
+                        distances[*peer] = Some(distance + 1);
+                        queue.push_back(*peer);
+                    }
+                }
+            }
+        }
+
+        diameter
+    }
+
+    /// The average latency (in milliseconds) of the fastest path between any two connected nodes
+    pub fn average_shortest_path_latency(&self) -> f64 {
+        let mut total = 0.0;
+        let mut num_paths: u64 = 0;
+
+        for start in 0..self.adjacency.len() {
+            for (dest, latency) in self.shortest_path_latencies(start).into_iter().enumerate() {
+                if dest != start
+                    && let Some(latency) = latency
+                {
+                    total += latency;
+                    num_paths += 1;
+                }
+            }
+        }
+
+        if num_paths == 0 {
+            0.0
+        } else {
+            total / (num_paths as f64)
+        }
+    }
+
+    /// Dijkstra's algorithm; returns None for unreachable nodes
+    fn shortest_path_latencies(&self, start: usize) -> Vec<Option<f64>> {
+        let mut result = vec![None; self.adjacency.len()];
+
+        // Latencies are non-negative, so their bit representation preserves ordering
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0.0f64.to_bits(), start)));
+
+        while let Some(Reverse((latency, current))) = queue.pop() {
+            if result[current].is_some() {
+                continue;
+            }
+
+            let latency = f64::from_bits(latency);
+            result[current] = Some(latency);
+
+            for (peer, link_latency) in self.adjacency[current].iter() {
+                if result[*peer].is_none() {
+                    queue.push(Reverse(((latency + link_latency).to_bits(), *peer)));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// The outcome of generating a sparse network
+pub(crate) struct SparseNetwork {
+    pub locations: Vec<Location>,
+    /// Pairs of node positions to connect; the smaller position comes first
+    pub links: BTreeSet<(usize, usize)>,
+    /// How often the node locations were picked
+    pub attempts: u32,
+    /// The number of disconnected parts before any repair
+    pub num_components: usize,
+    /// The number of links added to connect the network
+    pub num_bridge_links: usize,
+}
+
+/// Connects every node to (at least) its `min_conns_per_node` closest neighbors
+///
+/// Depending on the policy, a disconnected result is repaired, resampled
+/// using `new_location`, or returned as is.
+/// Nodes that are not `reachable` are never connected to each other.
+pub(crate) fn generate_sparse_network(
+    mut locations: Vec<Location>,
+    min_conns_per_node: u32,
+    policy: DisconnectedPolicy,
+    reachable: &[bool],
+    mut new_location: impl FnMut() -> Location,
+) -> SparseNetwork {
+    let mut attempts = 1;
+
+    loop {
+        let mut links = nearest_neighbor_links(&locations, min_conns_per_node, reachable);
+        let num_components = find_components(locations.len(), &links).len();
+
+        let repair = match policy {
+            _ if num_components <= 1 => false,
+            DisconnectedPolicy::Ignore => false,
+            DisconnectedPolicy::Repair => true,
+            DisconnectedPolicy::Resample { max_attempts } => {
+                if attempts < max_attempts {
+                    attempts += 1;
+                    locations = locations.iter().map(|_| new_location()).collect();
+                    continue;
+                }
+
+                log::warn!(
+                    "Network still disconnected after {attempts} attempts; adding bridge links instead"
+                );
+                true
+            }
+        };
+
+        let num_bridge_links = if repair {
+            connect_components(&locations, &mut links, reachable)
+        } else {
+            0
+        };
+
+        return SparseNetwork {
+            locations,
+            links,
+            attempts,
+            num_components,
+            num_bridge_links,
+        };
+    }
+}
+
+/// Whether a link between the two nodes can be established
+/// At least one of them has to accept inbound connections
+pub(crate) fn can_connect(reachable: &[bool], idx1: usize, idx2: usize) -> bool {
+    reachable[idx1] || reachable[idx2]
+}
+
+fn nearest_neighbor_links(
+    locations: &[Location],
+    min_conns_per_node: u32,
+    reachable: &[bool],
+) -> BTreeSet<(usize, usize)> {
+    let mut conns_per_node = vec![0; locations.len()];
+    let mut links = BTreeSet::new();
+
+    for idx1 in 0..locations.len() {
+        // Find the closest nodes
+        let mut sorted_nodes: Vec<_> = (0..locations.len())
+            .filter(|idx2| *idx2 != idx1 && can_connect(reachable, idx1, *idx2))
+            .map(|idx2| (locations[idx1].distance(&locations[idx2]), idx2))
+            .collect();
+
+        sorted_nodes.sort_by(|(dist_a, _), (dist_b, _)| {
+            dist_a
+                .partial_cmp(dist_b)
+                .expect("Failed to compare node locations")
+        });
+
+        for (_, idx2) in sorted_nodes {
+            // Done?
+            if conns_per_node[idx1] >= min_conns_per_node {
+                break;
+            }
+
+            // Don't add the same connection twice
+            if links.insert((idx1.min(idx2), idx1.max(idx2))) {
+                conns_per_node[idx1] += 1;
+                conns_per_node[idx2] += 1;
+            }
+        }
+    }
+
+    links
+}
+
+/// Connects every node to `degree` other nodes picked uniformly at random
+///
+/// Links are created by pairing random connection slots of the nodes while avoiding
+/// self-loops, duplicate links, and links between unreachable nodes. If pairing gets stuck repeatedly, the last attempt is
+/// returned, so a few nodes might end up with fewer links.
+pub(crate) fn random_regular_links(
+    num_nodes: usize,
+    degree: u32,
+    reachable: &[bool],
+) -> BTreeSet<(usize, usize)> {
+    const MAX_ATTEMPTS: u32 = 100;
+
+    assert!(
+        (degree as usize) < num_nodes,
+        "Degree must be smaller than the number of nodes"
+    );
+    assert!(
+        (num_nodes * degree as usize) % 2 == 0,
+        "Number of nodes times degree must be even"
+    );
+
+    let mut rng = random::rng();
+    let mut links = BTreeSet::new();
+
+    'attempts: for attempt in 1..=MAX_ATTEMPTS {
+        links.clear();
+
+        // Every node appears once for each link it still needs
+        let mut slots: Vec<usize> = (0..num_nodes)
+            .flat_map(|idx| std::iter::repeat_n(idx, degree as usize))
+            .collect();
+
+        while !slots.is_empty() {
+            let is_valid = |pos1: usize, pos2: usize| {
+                let (idx1, idx2) = (slots[pos1], slots[pos2]);
+                idx1 != idx2
+                    && can_connect(reachable, idx1, idx2)
+                    && !links.contains(&(idx1.min(idx2), idx1.max(idx2)))
+            };
+
+            // Random guesses are fast while there are many options left
+            let mut pair = (0..100)
+                .map(|_| {
+                    (
+                        rng.random_range(0..slots.len()),
+                        rng.random_range(0..slots.len()),
+                    )
+                })
+                .find(|(pos1, pos2)| is_valid(*pos1, *pos2));
+
+            if pair.is_none() {
+                pair = (0..slots.len())
+                    .flat_map(|pos1| (pos1 + 1..slots.len()).map(move |pos2| (pos1, pos2)))
+                    .find(|(pos1, pos2)| is_valid(*pos1, *pos2));
+            }
+
+            let Some((pos1, pos2)) = pair else {
+                if attempt < MAX_ATTEMPTS {
+                    continue 'attempts;
+                }
+
+                log::warn!(
+                    "Could not generate a regular network after {attempt} attempts; {} connection(s) are missing",
+                    slots.len() / 2
+                );
+                break 'attempts;
+            };
+
+            let (idx1, idx2) = (slots[pos1], slots[pos2]);
+            links.insert((idx1.min(idx2), idx1.max(idx2)));
+
+            // Remove the higher position first, so the lower one stays valid
+            slots.swap_remove(pos1.max(pos2));
+            slots.swap_remove(pos1.min(pos2));
+        }
+
+        break;
+    }
+
+    links
+}
+
+/// Connects every node to (at least) its `nearest` closest neighbors and
+/// adds `long_range` links per node to other nodes (Kleinberg-style)
+///
+/// The peer of a long-range link is picked with a probability proportional to
+/// `distance^-exponent`, so an exponent of zero picks peers uniformly at random.
+pub(crate) fn small_world_links(
+    locations: &[Location],
+    nearest: u32,
+    long_range: u32,
+    exponent: f64,
+    reachable: &[bool],
+) -> BTreeSet<(usize, usize)> {
+    let mut links = nearest_neighbor_links(locations, nearest, reachable);
+    let mut rng = random::rng();
+
+    for idx1 in 0..locations.len() {
+        let mut candidates: Vec<(usize, f64)> = (0..locations.len())
+            .filter(|idx2| {
+                *idx2 != idx1
+                    && can_connect(reachable, idx1, *idx2)
+                    && !links.contains(&(idx1.min(*idx2), idx1.max(*idx2)))
+            })
+            .map(|idx2| {
+                // Nodes at the same location are treated as one unit apart
+                let distance = (locations[idx1].distance(&locations[idx2]) as f64).max(1.0);
+                (idx2, distance.powf(-exponent))
+            })
+            .collect();
+
+        for _ in 0..long_range {
+            let total: f64 = candidates.iter().map(|(_, weight)| weight).sum();
+            if candidates.is_empty() || total <= 0.0 {
+                break;
+            }
+
+            let mut choice = rng.random_range(0.0..total);
+            let pos = candidates
+                .iter()
+                .position(|(_, weight)| {
+                    if choice < *weight {
+                        true
+                    } else {
+                        choice -= weight;
+                        false
+                    }
+                })
+                .unwrap_or(candidates.len() - 1);
+
+            let (idx2, _) = candidates.swap_remove(pos);
+            links.insert((idx1.min(idx2), idx1.max(idx2)));
+        }
+    }
+
+    links
+}
+
+/// Groups node positions by the part of the network they belong to
+pub(crate) fn find_components(
+    num_nodes: usize,
+    links: &BTreeSet<(usize, usize)>,
+) -> Vec<Vec<usize>> {
+    let mut adjacency = vec![vec![]; num_nodes];
+    for (node1, node2) in links.iter() {
+        adjacency[*node1].push(*node2);
+        adjacency[*node2].push(*node1);
+    }
+
+    let mut visited = vec![false; num_nodes];
+    let mut components = vec![];
+
+    for start in 0..num_nodes {
+        if visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(current) = queue.pop_front() {
+            for peer in adjacency[current].iter() {
+                if !visited[*peer] {
+                    visited[*peer] = true;
+                    component.push(*peer);
+                    queue.push_back(*peer);
+                }
+            }
+        }
+
+        components.push(component);
+    }
+
+    components
+}
+
+/// Repeatedly links the closest pair of nodes between the first part
+/// of the network and any other part until only one part remains
+///
+/// Returns the number of links added
+pub(crate) fn connect_components(
+    locations: &[Location],
+    links: &mut BTreeSet<(usize, usize)>,
+) -> usize {
+    let mut num_added = 0;
+
+    loop {
+        let mut components = find_components(locations.len(), links);
+        if components.len() <= 1 {
+            return num_added;
+        }
+
+        let first = components.remove(0);
+        let bridge = first
+            .iter()
+            .flat_map(|idx1| {
+                components
+                    .iter()
+                    .flatten()
+                    .filter(move |idx2| can_connect(reachable, *idx1, **idx2))
+                    .map(move |idx2| (locations[*idx1].distance(&locations[*idx2]), *idx1, *idx2))
+            })
+            .min_by(|(dist_a, ..), (dist_b, ..)| {
+                dist_a.partial_cmp(dist_b).unwrap_or(Ordering::Equal)
+            })
+            .expect("No nodes to connect");
+
+        let (_, idx1, idx2) = bridge;
+        links.insert((idx1.min(idx2), idx1.max(idx2)));
+        num_added += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Topology, find_components, generate_sparse_network, random_regular_links, small_world_links,
+    };
+    use crate::config::DisconnectedPolicy;
+    use crate::node::Location;
+
+    #[test]
+    fn line_topology() {
+        let topology = Topology::from_edges(4, &[(0, 1, 10.0), (1, 2, 10.0), (2, 3, 10.0)]);
+
+        assert_eq!(topology.average_degree(), 1.5);
+        assert_eq!(topology.diameter(), 3);
+
+        // 3 paths of length 10, 2 of length 20, and 1 of length 30 (in each direction)
+        assert_eq!(topology.average_shortest_path_latency(), 100.0 / 6.0);
+    }
+
+    #[test]
+    fn shortcut() {
+        let topology = Topology::from_edges(3, &[(0, 1, 10.0), (1, 2, 10.0), (0, 2, 50.0)]);
+
+        assert_eq!(topology.diameter(), 1);
+        assert_eq!(topology.shortest_path_latencies(0)[2], Some(20.0));
+    }
+
+    /// Two clusters on opposite sides of the globe
+    fn two_clusters() -> Vec<Location> {
+        let mut locations = vec![];
+        for offset in 0..4 {
+            locations.push(Location::new(-170 + offset, 0));
+            locations.push(Location::new(170 - offset, 0));
+        }
+        locations
+    }
+
+    #[test]
+    fn repair_disconnected() {
+        let network = generate_sparse_network(
+            two_clusters(),
+            2,
+            DisconnectedPolicy::Repair,
+            &[true; 8],
+            || panic!("Should not resample"),
+        );
+
+        assert_eq!(network.num_components, 2);
+        assert_eq!(network.num_bridge_links, 1);
+        assert_eq!(find_components(8, &network.links).len(), 1);
+    }
+
+    #[test]
+    fn ignore_disconnected() {
+        let network = generate_sparse_network(
+            two_clusters(),
+            2,
+            DisconnectedPolicy::Ignore,
+            &[true; 8],
+            || panic!("Should not resample"),
+        );
+
+        assert_eq!(network.num_bridge_links, 0);
+        assert_eq!(find_components(8, &network.links).len(), 2);
+    }
+
+    #[test]
+    fn random_regular() {
+        let links = random_regular_links(20, 4, &[true; 20]);
+        let topology = Topology::from_links(20, &links);
+
+        assert_eq!(links.len(), 40);
+        assert_eq!(topology.average_degree(), 4.0);
+        assert!(topology.adjacency.iter().all(|peers| peers.len() == 4));
+    }
+
+    #[test]
+    fn small_world() {
+        let locations = two_clusters();
+        let nearest = super::nearest_neighbor_links(&locations, 2, &[true; 8]);
+        let links = small_world_links(&locations, 2, 1, 2.0, &[true; 8]);
+
+        // Long-range links are added on top of the nearest neighbors
+        assert!(nearest.is_subset(&links));
+        assert!(links.len() > nearest.len());
+        assert!(links.len() <= nearest.len() + locations.len());
+    }
+
+    #[test]
+    fn unreachable_nodes() {
+        // Only the first node of each cluster accepts inbound connections
+        let mut reachable = [false; 8];
+        reachable[0] = true;
+        reachable[1] = true;
+
+        let network = generate_sparse_network(
+            two_clusters(),
+            2,
+            DisconnectedPolicy::Repair,
+            &reachable,
+            || panic!("Should not resample"),
+        );
+
+        for (idx1, idx2) in network.links.iter() {
+            assert!(reachable[*idx1] || reachable[*idx2]);
+        }
+        assert_eq!(find_components(8, &network.links).len(), 1);
+    }
+
+    #[test]
+    fn resample_disconnected() {
+        let mut num_sampled = 0;
+        let policy = DisconnectedPolicy::Resample { max_attempts: 3 };
+
+        let network = generate_sparse_network(two_clusters(), 2, policy, &[true; 8], || {
+            num_sampled += 1;
+            Location::new(num_sampled, 0)
+        });
+
+        // Nodes on a line are always connected
+        assert_eq!(network.attempts, 2);
+        assert_eq!(num_sampled, 8);
+        assert_eq!(network.num_components, 1);
+        assert_eq!(network.num_bridge_links, 0);
+    }
+}
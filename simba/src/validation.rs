@@ -0,0 +1,561 @@
+//! Checks whether a protocol can run on a network before any simulation starts
+//!
+//! Incompatible setups would otherwise panic deep inside the simulation's worker thread,
+//! one problem at a time, so runners check their setup upfront and report all problems at once.
+
+use crate::config::{
+    Connectivity, FailureConfig, FailureTarget, NetworkConfiguration, ParameterType,
+    ParameterValue, ProtocolConfiguration, TimeoutConfig,
+};
+use crate::failures::get_targeted_nodes;
+use crate::node::NodeIndex;
+
+/// A reason why a setup cannot run
+#[derive(Clone, Debug, PartialEq, derive_more::Display)]
+pub enum SetupProblem {
+    #[display("{protocol} needs a fully connected network, but the network uses {connectivity}")]
+    Connectivity {
+        protocol: &'static str,
+        connectivity: &'static str,
+    },
+    #[display("Parameter {parameter} does not apply to the protocol ({protocol})")]
+    UnsupportedByProtocol {
+        parameter: ParameterType,
+        protocol: &'static str,
+    },
+    #[display("Parameter {parameter} cannot be set for a pre-defined network")]
+    UnsupportedByNetwork { parameter: ParameterType },
+    #[display("Parameter {parameter} must be a non-negative integer, but got {value}")]
+    InvalidValue {
+        parameter: ParameterType,
+        value: ParameterValue,
+    },
+    #[display("{protocol} needs at least one mining node")]
+    NoMiningNodes { protocol: &'static str },
+    #[display("The fraction of faulty nodes must be between 0 and 1, but is {_0}")]
+    InvalidFaultyFraction(f64),
+    #[display("Node #{_0} cannot be faulty, because it does not exist")]
+    UnknownFaultyNode(NodeIndex),
+    #[display("Region {region} cannot go down, as there are only {num_regions} regions")]
+    UnknownRegion { region: u32, num_regions: u32 },
+    #[display("{protocol} cannot stop after a number of blocks; use a timeout in seconds instead")]
+    BlockTimeoutUnsupported { protocol: &'static str },
+    #[display("Node #{_0} cannot withhold blocks, because it is not a member of its mining pool")]
+    NotAPoolMember(NodeIndex),
+    #[display("Node #{_0} cannot be partitioned, because it does not exist")]
+    UnknownPartitionedNode(NodeIndex),
+    #[display("The partition starting at {_0}ms never takes effect, because its duration is zero")]
+    EmptyPartition(u64),
+    #[display(
+        "{protocol} tolerates less than {tolerated} of the voting power being faulty, but {faulty:.2} is expected to be"
+    )]
+    QuorumInfeasible {
+        protocol: &'static str,
+        tolerated: &'static str,
+        faulty: f64,
+    },
+    #[display("Blocks cannot be stored in {_0:?}, because it is not a directory")]
+    BlockStoreDirectory(String),
+    #[display("{protocol} needs {parameter} to be above 0 and at most 1, but it is {value}")]
+    InvalidSampling {
+        protocol: &'static str,
+        parameter: &'static str,
+        value: f64,
+    },
+    #[display("Node inboxes must have room for at least one message")]
+    EmptyInbox,
+}
+
+fn get_protocol_name(protocol: &ProtocolConfiguration) -> &'static str {
+    match protocol {
+        ProtocolConfiguration::NakamotoConsensus { .. } => "Nakamoto consensus",
+        ProtocolConfiguration::PracticalBFT { .. } => "PBFT",
+        ProtocolConfiguration::Raft { .. } => "Raft",
+        ProtocolConfiguration::SpeedTest { .. } => "Speed test",
+        ProtocolConfiguration::Gossip { .. } => "Gossip",
+        ProtocolConfiguration::Snowball { .. } => "Snowball",
+        ProtocolConfiguration::Snowman { .. } => "Snowman",
+    }
+}
+
+fn get_connectivity_name(connectivity: &Connectivity) -> &'static str {
+    match connectivity {
+        Connectivity::Full => "full connectivity",
+        Connectivity::Sparse { .. } => "sparse connectivity",
+        Connectivity::RandomRegular { .. } => "a random regular topology",
+        Connectivity::SmallWorld { .. } => "a small-world topology",
+    }
+}
+
+/// Finds problems of the protocol configuration alone
+///
+/// These do not depend on the network, so the simulation checks for them as well.
+pub(crate) fn find_protocol_problems(protocol: &ProtocolConfiguration) -> Vec<SetupProblem> {
+    let mut problems = vec![];
+
+    if let ProtocolConfiguration::NakamotoConsensus {
+        mining_pools,
+        block_store,
+        ..
+    } = protocol
+    {
+        for pool in mining_pools.iter() {
+            for idx in pool.withholding_members.iter() {
+                if !pool.members.contains(idx) {
+                    problems.push(SetupProblem::NotAPoolMember(*idx));
+                }
+            }
+        }
+
+        if let Some(config) = block_store
+            && !std::path::Path::new(&config.directory).is_dir()
+        {
+            problems.push(SetupProblem::BlockStoreDirectory(config.directory.clone()));
+        }
+    }
+
+    problems
+}
+
+/// Finds all problems of running `protocol` on `network` with the given
+/// parameters applied (as done by the runners) and failures injected
+///
+/// Returns an empty list if the setup can run.
+pub fn find_setup_problems(
+    protocol: &ProtocolConfiguration,
+    network: &NetworkConfiguration,
+    failures: Option<&FailureConfig>,
+    parameters: &[(ParameterType, ParameterValue)],
+) -> Vec<SetupProblem> {
+    let mut problems = vec![];
+    let protocol_name = get_protocol_name(protocol);
+
+    for (parameter, value) in parameters {
+        let parameter = *parameter;
+
+        if !protocol.supports_parameter(&parameter) {
+            problems.push(SetupProblem::UnsupportedByProtocol {
+                parameter,
+                protocol: protocol_name,
+            });
+        }
+
+        if !network.supports_parameter(&parameter) {
+            problems.push(SetupProblem::UnsupportedByNetwork { parameter });
+        }
+
+        if TryInto::<u32>::try_into(*value).is_err() {
+            problems.push(SetupProblem::InvalidValue {
+                parameter,
+                value: *value,
+            });
+        }
+    }
+
+    // Parameters can only be applied if they are all valid
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let mut protocol = protocol.clone();
+    let mut network = network.clone();
+    for (parameter, value) in parameters {
+        protocol.set(parameter, *value);
+        network.set(parameter, *value);
+    }
+
+    if protocol.needs_full_connectivity()
+        && let Some(connectivity) = network.get_connectivity()
+        && !matches!(connectivity, Connectivity::Full)
+    {
+        problems.push(SetupProblem::Connectivity {
+            protocol: protocol_name,
+            connectivity: get_connectivity_name(connectivity),
+        });
+    }
+
+    if let Some(inbox) = network.get_node_inbox()
+        && inbox.capacity == 0
+    {
+        problems.push(SetupProblem::EmptyInbox);
+    }
+
+    problems.extend(find_protocol_problems(&protocol));
+
+    let mining_nodes = network.get_mining_nodes();
+    let needs_mining_nodes = !matches!(
+        protocol,
+        ProtocolConfiguration::SpeedTest { .. }
+            | ProtocolConfiguration::Gossip { .. }
+            | ProtocolConfiguration::Snowball { .. }
+    );
+    if needs_mining_nodes && !mining_nodes.contains(&true) {
+        problems.push(SetupProblem::NoMiningNodes {
+            protocol: protocol_name,
+        });
+    }
+
+    if let ProtocolConfiguration::Snowball {
+        sample_size_weighted,
+        query_threshold_weighted,
+        ..
+    }
+    | ProtocolConfiguration::Snowman {
+        sample_size_weighted,
+        query_threshold_weighted,
+        ..
+    } = &protocol
+    {
+        for (parameter, value) in [
+            ("sample_size_weighted", *sample_size_weighted),
+            ("query_threshold_weighted", *query_threshold_weighted),
+        ] {
+            if !(value > 0.0 && value <= 1.0) {
+                problems.push(SetupProblem::InvalidSampling {
+                    protocol: protocol_name,
+                    parameter,
+                    value,
+                });
+            }
+        }
+    }
+
+    let Some(failures) = failures else {
+        return problems;
+    };
+
+    if !(0.0..=1.0).contains(&failures.faulty_nodes) {
+        problems.push(SetupProblem::InvalidFaultyFraction(failures.faulty_nodes));
+        return problems;
+    }
+
+    let num_nodes = network.num_nodes();
+    let unknown_nodes: Vec<_> = failures
+        .nodes
+        .iter()
+        .filter(|idx| **idx >= num_nodes)
+        .collect();
+    for idx in unknown_nodes.iter() {
+        problems.push(SetupProblem::UnknownFaultyNode(**idx));
+    }
+
+    if let Some(outage) = &failures.region_outage {
+        for region in outage.regions.iter() {
+            if *region >= outage.num_regions {
+                problems.push(SetupProblem::UnknownRegion {
+                    region: *region,
+                    num_regions: outage.num_regions,
+                });
+            }
+        }
+    }
+
+    for window in failures.partitions.iter() {
+        if window.duration == 0 {
+            problems.push(SetupProblem::EmptyPartition(window.start));
+        }
+
+        for idx in window.groups.iter().flatten() {
+            if *idx >= num_nodes {
+                problems.push(SetupProblem::UnknownPartitionedNode(*idx));
+            }
+        }
+    }
+
+    // Faulty nodes cannot be picked if some do not exist
+    if !unknown_nodes.is_empty() {
+        return problems;
+    }
+
+    // Nodes picked at random are faulty with the same probability,
+    // so we can only reason about the expected share of faulty voting power
+    let targeted_nodes = get_targeted_nodes(&protocol, &network, failures);
+    let get_faulty_probability = |idx: usize| {
+        if targeted_nodes.contains(&(idx as NodeIndex)) {
+            1.0
+        } else if failures.target == FailureTarget::AnyNode && idx > 0 {
+            failures.faulty_nodes
+        } else {
+            0.0
+        }
+    };
+
+    let tolerance = match protocol {
+        ProtocolConfiguration::PracticalBFT { .. } => Some(("a third", 1.0 / 3.0)),
+        ProtocolConfiguration::Raft { .. } => Some(("half", 0.5)),
+        _ => None,
+    };
+
+    if let Some((tolerated, max_fraction)) = tolerance {
+        // Raft voters all count the same
+        let voting_power: Vec<f64> = match protocol {
+            ProtocolConfiguration::PracticalBFT { .. } => network
+                .get_voting_power()
+                .into_iter()
+                .map(|power| power as f64)
+                .collect(),
+            _ => vec![1.0; mining_nodes.len()],
+        };
+
+        let (total, faulty) = voting_power
+            .iter()
+            .zip(mining_nodes.iter())
+            .enumerate()
+            .filter(|(_, (_, is_mining))| **is_mining)
+            .fold((0.0, 0.0), |(total, faulty), (idx, (power, _))| {
+                (total + power, faulty + get_faulty_probability(idx) * power)
+            });
+
+        if total > 0.0 && faulty / total >= max_fraction {
+            problems.push(SetupProblem::QuorumInfeasible {
+                protocol: protocol_name,
+                tolerated,
+                faulty: faulty / total,
+            });
+        }
+    }
+
+    problems
+}
+
+/// Fails with a list of all problems if the setup cannot run (see `find_setup_problems`)
+pub fn validate_setup(
+    protocol: &ProtocolConfiguration,
+    network: &NetworkConfiguration,
+    failures: Option<&FailureConfig>,
+    parameters: &[(ParameterType, ParameterValue)],
+) -> anyhow::Result<()> {
+    let problems = find_setup_problems(protocol, network, failures, parameters);
+    report_problems(&problems)
+}
+
+/// Fails if the protocol cannot run until the given timeout
+pub fn validate_timeout(
+    protocol: &ProtocolConfiguration,
+    timeout: &TimeoutConfig,
+) -> anyhow::Result<()> {
+    let mut problems = vec![];
+
+    if matches!(timeout, TimeoutConfig::Blocks { .. }) && !protocol.supports_block_timeout() {
+        problems.push(SetupProblem::BlockTimeoutUnsupported {
+            protocol: get_protocol_name(protocol),
+        });
+    }
+
+    report_problems(&problems)
+}
+
+/// Turns a non-empty list of problems into an error that lists all of them
+pub(crate) fn report_problems(problems: &[SetupProblem]) -> anyhow::Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let lines: Vec<String> = problems
+        .iter()
+        .map(|problem| format!("  - {problem}"))
+        .collect();
+    anyhow::bail!(
+        "Setup cannot run due to {} problem(s):\n{}",
+        problems.len(),
+        lines.join("\n")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::{
+        BlockStoreConfig, InboxConfig, MiningPoolConfig, TrafficPattern, Workload,
+    };
+
+    fn make_network(num_mining_nodes: u32, connectivity: Connectivity) -> NetworkConfiguration {
+        NetworkConfiguration::Random {
+            num_mining_nodes,
+            num_non_mining_nodes: 0,
+            workload: Workload::default(),
+            link_latency: 10,
+            link_bandwidth: None,
+            node_bandwidth: 100,
+            connectivity,
+            node_inbox: None,
+            voting_power: Default::default(),
+            reachability: Default::default(),
+            churn: None,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn default_setup() {
+        let problems = find_setup_problems(
+            &ProtocolConfiguration::default(),
+            &NetworkConfiguration::default(),
+            None,
+            &[(ParameterType::MaxBlockSize, ParameterValue::Int(100))],
+        );
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let protocol = ProtocolConfiguration::SpeedTest {
+            send_speed: 1,
+            pattern: TrafficPattern::Pair,
+        };
+        let parameters = [
+            (ParameterType::GossipFanout, ParameterValue::Int(3)),
+            (ParameterType::NumClients, ParameterValue::Float(0.5)),
+        ];
+
+        let network = make_network(4, Connectivity::Sparse {
+            min_conns_per_node: 2,
+            on_disconnected: Default::default(),
+        });
+
+        let problems = find_setup_problems(&protocol, &network, None, &parameters);
+        assert_eq!(problems.len(), 3);
+
+        // Problems of the setup itself are found once the parameters are valid
+        let problems = find_setup_problems(&protocol, &network, None, &[]);
+        assert_eq!(problems, vec![SetupProblem::Connectivity {
+            protocol: "Speed test",
+            connectivity: "sparse connectivity",
+        }]);
+    }
+
+    #[test]
+    fn bft_quorum() {
+        let protocol = ProtocolConfiguration::PracticalBFT {
+            max_block_size: 100,
+            max_block_interval: 1000,
+            genesis: Default::default(),
+            rotate_proposer: false,
+            view_change_timeout: None,
+        };
+        let network = make_network(4, Connectivity::Full);
+
+        let failures = |faulty_nodes| FailureConfig {
+            faulty_nodes,
+            ..Default::default()
+        };
+
+        let problems = find_setup_problems(&protocol, &network, Some(&failures(0.1)), &[]);
+        assert!(problems.is_empty());
+
+        let problems = find_setup_problems(&protocol, &network, Some(&failures(0.5)), &[]);
+        assert!(matches!(problems[..], [
+            SetupProblem::QuorumInfeasible { .. }
+        ]));
+
+        let params = [(ParameterType::NumMiningNodes, ParameterValue::Int(0))];
+        let problems = find_setup_problems(&protocol, &network, None, &params);
+        assert_eq!(problems, vec![SetupProblem::NoMiningNodes {
+            protocol: "PBFT"
+        }]);
+    }
+
+    #[test]
+    fn block_timeout() {
+        let protocol = ProtocolConfiguration::default();
+        let blocks = TimeoutConfig::Blocks {
+            warmup: 0,
+            runtime: 10,
+        };
+        let seconds = TimeoutConfig::Seconds {
+            warmup: 0,
+            runtime: 10,
+        };
+
+        assert!(validate_timeout(&protocol, &blocks).is_err());
+        assert!(validate_timeout(&protocol, &seconds).is_ok());
+
+        let raft = ProtocolConfiguration::Raft {
+            max_block_size: 100,
+            max_block_interval: 50,
+            genesis: Default::default(),
+            election_timeout: None,
+        };
+        assert!(validate_timeout(&raft, &blocks).is_ok());
+    }
+
+    #[test]
+    fn withholding_outside_pool() {
+        let mut protocol = ProtocolConfiguration::default();
+        let ProtocolConfiguration::NakamotoConsensus { mining_pools, .. } = &mut protocol else {
+            panic!("Default protocol is not Nakamoto consensus");
+        };
+        mining_pools.push(MiningPoolConfig {
+            members: vec![0, 1],
+            withholding_members: vec![1, 2],
+        });
+
+        let network = make_network(4, Connectivity::Full);
+        let problems = find_setup_problems(&protocol, &network, None, &[]);
+        assert_eq!(problems, vec![SetupProblem::NotAPoolMember(2)]);
+    }
+
+    #[test]
+    fn missing_block_store_directory() {
+        let mut protocol = ProtocolConfiguration::default();
+        let ProtocolConfiguration::NakamotoConsensus { block_store, .. } = &mut protocol else {
+            panic!("Default protocol is not Nakamoto consensus");
+        };
+        *block_store = Some(BlockStoreConfig {
+            directory: "/does/not/exist".to_string(),
+            cache_size: 100,
+        });
+
+        let network = make_network(4, Connectivity::Full);
+        let problems = find_setup_problems(&protocol, &network, None, &[]);
+        assert_eq!(problems, vec![SetupProblem::BlockStoreDirectory(
+            "/does/not/exist".to_string()
+        )]);
+    }
+
+    #[test]
+    fn empty_inbox() {
+        let mut network = make_network(4, Connectivity::Full);
+        let NetworkConfiguration::Random { node_inbox, .. } = &mut network else {
+            panic!("Expected a random network");
+        };
+        *node_inbox = Some(InboxConfig {
+            capacity: 0,
+            processing_time: 100,
+            overflow_policy: Default::default(),
+        });
+
+        let problems = find_setup_problems(&ProtocolConfiguration::default(), &network, None, &[]);
+        assert_eq!(problems, vec![SetupProblem::EmptyInbox]);
+    }
+
+    #[test]
+    fn targeted_failures() {
+        let protocol = ProtocolConfiguration::Raft {
+            max_block_size: 100,
+            max_block_interval: 1000,
+            genesis: Default::default(),
+            election_timeout: None,
+        };
+        let network = make_network(4, Connectivity::Full);
+
+        // One faulty leader is fine, but not two
+        let mut failures = FailureConfig {
+            faulty_nodes: 0.25,
+            target: FailureTarget::Leaders,
+            ..Default::default()
+        };
+        assert!(find_setup_problems(&protocol, &network, Some(&failures), &[]).is_empty());
+
+        failures.nodes = vec![3, 4];
+        let problems = find_setup_problems(&protocol, &network, Some(&failures), &[]);
+        assert_eq!(problems, vec![SetupProblem::UnknownFaultyNode(4)]);
+
+        failures.nodes = vec![3];
+        let problems = find_setup_problems(&protocol, &network, Some(&failures), &[]);
+        assert!(matches!(problems[..], [
+            SetupProblem::QuorumInfeasible { .. }
+        ]));
+    }
+}
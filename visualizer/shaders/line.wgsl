@@ -8,6 +8,8 @@ struct LineStyle {
     border_color: vec4f,
     line_width: f32,
     border_width: f32,
+    dash_length: f32,
+    _unused: f32,
 }
 
 struct LineConfig {
@@ -22,7 +24,13 @@ fn total_width(style: LineStyle) -> f32 {
 @fragment
 fn main_fs(
     @location(1) normal: vec2f,
+    @location(2) distance: f32,
 ) -> @location(0) vec4f {
+    // Leave out every other segment of dashed lines
+    if style.dash_length > 0.0 && fract(distance / (2.0 * style.dash_length)) > 0.5 {
+        discard;
+    }
+
     let rel_inner_width = max(style.line_width / total_width(style), 0.0);
 
     if length(normal) > rel_inner_width {
@@ -35,6 +43,7 @@ fn main_fs(
 struct VertexOutput {
     @builtin(position) pos: vec4f,
     @location(1) normal: vec2f,
+    @location(2) distance: f32,
 }
 
 @vertex
@@ -44,11 +53,14 @@ fn main_vs(
 ) -> VertexOutput {
     let model_view = view_proj[0] * model;
 
+    // Distance from the start of the line
+    let distance = (position_in.x + 0.5) * config.length;
+
     var out_position = vec4(position_in, 1.0);
     out_position.x *= config.length;
     out_position.y *= total_width(style);
 
     out_position = view_proj[1] * model_view * out_position;
 
-    return VertexOutput(out_position, normal);
+    return VertexOutput(out_position, normal, distance);
 }
@@ -0,0 +1,40 @@
+//! Saving generated files, such as statistics or recordings, from the GUI
+
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+
+/// Writes the file to the working directory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(name: &str, content: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    std::fs::write(name, content).with_context(|| format!("Failed to write {name}"))?;
+    log::info!("Saved {name}");
+    Ok(())
+}
+
+/// There is no file system in the browser, so this triggers a download instead
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(name: &str, content: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let to_error = |err: JsValue| anyhow::anyhow!("Failed to download {name}: {err:?}");
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(content.as_ref()));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(to_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(to_error)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| anyhow::anyhow!("Cannot access the html document"))?;
+
+    let anchor = document
+        .create_element("a")
+        .map_err(to_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| anyhow::anyhow!("Failed to create link element"))?;
+
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(to_error)
+}
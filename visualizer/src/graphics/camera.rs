@@ -88,6 +88,11 @@ impl Camera {
         config.dirty = true;
     }
 
+    /// The point the camera is centered on
+    pub fn get_position(&self) -> Vec2 {
+        self.configuration.lock().position.truncate()
+    }
+
     pub fn get_position_from_cursor(&self, cursor_pos: LogicalPosition<f64>) -> Vec2 {
         let config = self.configuration.lock();
 
@@ -209,6 +214,12 @@ impl Camera {
         }
     }
 
+    /// Is the camera moving or did its configuration change?
+    pub fn needs_update(&self) -> bool {
+        let velocity = self.movement.lock().velocity;
+        velocity != Vec2::new(0.0, 0.0) || self.configuration.lock().dirty
+    }
+
     pub fn notify_button_pressed(&self, direction: InputDirection) {
         let mut movement = self.movement.lock();
         let zoom = self.get_zoom();
@@ -67,6 +67,10 @@ impl Color {
     pub fn into_vec4(self) -> Vec4 {
         Vec4::new(self.r, self.g, self.b, self.a)
     }
+
+    pub fn into_iced(self) -> iced::Color {
+        iced::Color::from_rgba(self.r, self.g, self.b, self.a)
+    }
 }
 
 impl Vertex {
@@ -1,14 +1,11 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use parking_lot::Mutex;
 
 use glam::Vec2;
 
 use wgpu::util::DeviceExt;
-use wgpu::{
-    BindGroup, Buffer, CommandBuffer, LoadOp, RenderPassColorAttachment, RenderPassDescriptor,
-    StoreOp, TextureView,
-};
+use wgpu::{BindGroup, Buffer, CommandEncoder, RenderBundle};
 
 use crate::graphics::{BoundingBox, DrawableId, Material, Renderer};
 
@@ -22,58 +19,67 @@ pub struct Drawable {
     pub(super) position: Vec2,
     pub(super) z_index: u16,
     pub(super) bounding_box: BoundingBox,
+    /// A style change that has not been uploaded to the GPU yet
     pub(super) style_bytes: Mutex<Option<Vec<u8>>>,
+    /// The draw commands of this drawable
+    ///
+    /// Position and style live in uniform buffers, so these only need to be recorded once
+    pub(super) render_bundle: OnceLock<RenderBundle>,
 }
 
 impl Drawable {
-    pub(super) async fn draw(&self, render_buffer: &TextureView) -> CommandBuffer {
-        let mut encoder = self.renderer.make_command_encoder();
-
-        if let Some(style_bytes) = self.style_bytes.lock().take() {
-            let device = self.renderer.get_device();
-            let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                contents: &style_bytes,
-                usage: wgpu::BufferUsages::COPY_SRC,
-                label: None,
-            });
-
-            encoder.copy_buffer_to_buffer(
-                &staging_buffer,
-                0,
-                &self.style_buffer,
-                0,
-                style_bytes.len() as u64,
-            );
-        }
-
-        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: Some("sprite render pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: render_buffer,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: LoadOp::Load,
-                    store: StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
+    /// Uploads the latest style (if it changed since the last frame)
+    pub(super) fn update_style(&self, encoder: &mut CommandEncoder) {
+        let Some(style_bytes) = self.style_bytes.lock().take() else {
+            return;
+        };
+
+        let device = self.renderer.get_device();
+        let staging_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            contents: &style_bytes,
+            usage: wgpu::BufferUsages::COPY_SRC,
+            label: None,
         });
 
-        render_pass.set_pipeline(&self.material.pipeline);
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.material.vertex_buffer.slice(0..));
-        render_pass.set_index_buffer(
-            self.material.index_buffer.slice(0..),
-            wgpu::IndexFormat::Uint16,
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.style_buffer,
+            0,
+            style_bytes.len() as u64,
         );
+    }
 
-        render_pass.draw_indexed(0..6, 0, 0..1);
+    pub(super) fn get_render_bundle(&self) -> &RenderBundle {
+        self.render_bundle.get_or_init(|| {
+            let device = self.renderer.get_device();
+            let mut encoder =
+                device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: Some("drawable"),
+                    color_formats: &[Some(self.renderer.get_texture_format())],
+                    depth_stencil: None,
+                    sample_count: 1,
+                    multiview: None,
+                });
+
+            encoder.set_pipeline(&self.material.pipeline);
+            encoder.set_bind_group(0, &self.uniform_bind_group, &[]);
+            encoder.set_vertex_buffer(0, self.material.vertex_buffer.slice(0..));
+            encoder.set_index_buffer(
+                self.material.index_buffer.slice(0..),
+                wgpu::IndexFormat::Uint16,
+            );
+            encoder.draw_indexed(0..6, 0, 0..1);
 
-        drop(render_pass);
+            encoder.finish(&wgpu::RenderBundleDescriptor {
+                label: Some("drawable"),
+            })
+        })
+    }
 
-        encoder.finish()
+    /// Did the style change since this was last drawn?
+    pub fn is_dirty(&self) -> bool {
+        self.style_bytes.lock().is_some()
     }
 
     pub fn set_style<T: bytemuck::Zeroable + bytemuck::Pod>(&self, style: T) {
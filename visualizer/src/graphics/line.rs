@@ -16,7 +16,9 @@ pub struct LineStyle {
     pub border_color: Vec4,
     pub line_width: f32,
     pub border_width: f32,
-    pub _unused: f64,
+    /// Draws a dashed line if greater than zero
+    pub dash_length: f32,
+    pub _unused: f32,
 }
 
 #[derive(Default, Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
@@ -256,6 +258,7 @@ pub(super) async fn new_drawable(
         z_index,
         bounding_box,
         style_bytes: Default::default(),
+        render_bundle: Default::default(),
     }
 }
 
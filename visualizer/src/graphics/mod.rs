@@ -2,7 +2,7 @@ mod data;
 pub use data::*;
 
 mod render_loop;
-pub use render_loop::RenderLoop;
+pub use render_loop::{RenderLoop, RenderLoopConfig};
 
 mod rectangle;
 pub use rectangle::RectangleStyle;
@@ -192,12 +192,34 @@ impl Graphics {
             }
         });
 
-        // Also sort by idx so overlapping nodes always overlap the same way
+        let mut encoder = self.renderer.make_command_encoder();
+
+        // Only drawables whose style changed need to be updated
         for drawable in drawables.iter() {
-            let cmds = drawable.draw(render_buffer).await;
-            commands.push(cmds);
+            drawable.update_style(&mut encoder);
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("scene render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: render_buffer,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            // Also sorted by idx so overlapping nodes always overlap the same way
+            render_pass.execute_bundles(drawables.iter().map(|d| d.get_render_bundle()));
         }
 
+        commands.push(encoder.finish());
         commands
     }
 }
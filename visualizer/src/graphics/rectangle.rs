@@ -231,6 +231,7 @@ pub(super) async fn new_drawable(
         z_index,
         bounding_box,
         style_bytes: Default::default(),
+        render_bundle: Default::default(),
     }
 }
 
@@ -1,14 +1,16 @@
 use std::process::exit;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use instant::Instant;
 
-use simba::Simulation;
+use crate::simulation::SimulationHandle;
 
 use wgpu::{LoadOp, RenderPassDescriptor, StoreOp, SurfaceConfiguration, TextureUsages};
 
 use crate::graphics::Graphics;
+use crate::recording::Recorder;
 use crate::scene::SceneManager;
 use crate::ui::{CursorPosition, UiEvents, UiMessages, UiRenderLoop};
 
@@ -17,16 +19,53 @@ pub struct RenderContext<'a> {
     pub depth_buffer: wgpu::Texture,
 }
 
+/// Controls how often frames are drawn
+#[derive(Clone, Debug)]
+pub struct RenderLoopConfig {
+    /// Upper bound for the frame rate (None means unlimited)
+    pub target_fps: Option<u32>,
+    /// Wait for the display's vertical sync before presenting a frame
+    pub vsync: bool,
+    /// Do not draw frames if neither the scene nor the UI changed
+    pub skip_unchanged_frames: bool,
+}
+
+impl Default for RenderLoopConfig {
+    fn default() -> Self {
+        Self {
+            target_fps: Some(60),
+            vsync: true,
+            skip_unchanged_frames: true,
+        }
+    }
+}
+
+impl RenderLoopConfig {
+    fn get_frame_interval(&self) -> Option<Duration> {
+        self.target_fps
+            .filter(|fps| *fps > 0)
+            .map(|fps| Duration::from_secs_f64(1.0 / (fps as f64)))
+    }
+}
+
 pub struct RenderLoop<'a> {
+    config: RenderLoopConfig,
     graphics: Arc<Graphics>,
     ui_render_loop: UiRenderLoop,
     window: Arc<winit::window::Window>,
     scene_mgr: Arc<SceneManager>,
     render_context: RenderContext<'a>,
+    recorder: Arc<Recorder>,
     stop_flag: Arc<AtomicBool>,
 }
 
 impl<'a> RenderLoop<'a> {
+    /// The UI shows the simulation's progress, so draw it at least this often
+    const IDLE_REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// How long to wait after skipping a frame, if there is no frame rate limit
+    const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         graphics: Arc<Graphics>,
@@ -35,9 +74,10 @@ impl<'a> RenderLoop<'a> {
         cursor_position: Arc<CursorPosition>,
         window: winit::window::Window,
         surface: wgpu::Surface<'a>,
-        simulation: Arc<Simulation>,
+        simulation: Arc<dyn SimulationHandle>,
         scene_mgr: Arc<SceneManager>,
         stop_flag: Arc<AtomicBool>,
+        config: RenderLoopConfig,
     ) -> Self {
         let renderer = graphics.get_renderer();
         let adapter = renderer.get_adapter();
@@ -48,12 +88,20 @@ impl<'a> RenderLoop<'a> {
             let geometry = renderer.get_geometry();
 
             log::debug!("Creating render surface");
-            Self::update_surface(&surface, adapter, device, &geometry.window_size);
+            Self::update_surface(
+                &surface,
+                adapter,
+                device,
+                &geometry.window_size,
+                config.vsync,
+            );
 
             log::debug!("Creating depth buffer");
             Self::make_depth_buffer(device, &geometry.window_size)
         };
 
+        let recorder = Arc::new(Recorder::default());
+
         let ui_render_loop = UiRenderLoop::new(
             renderer,
             ui_messages,
@@ -62,6 +110,7 @@ impl<'a> RenderLoop<'a> {
             window.clone(),
             simulation,
             scene_mgr.clone(),
+            recorder.clone(),
         )
         .await;
 
@@ -71,32 +120,69 @@ impl<'a> RenderLoop<'a> {
         };
 
         Self {
+            config,
             graphics,
             window,
             ui_render_loop,
             scene_mgr,
             render_context,
+            recorder,
             stop_flag,
         }
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn run(&mut self) {
+        let frame_interval = self.config.get_frame_interval();
         let mut last_frame_time = Instant::now();
+        let mut last_draw_time = last_frame_time;
 
-        //TODO only draw when something changes
         while !self.stop_flag.load(Ordering::Relaxed) {
-            // Don't draw too frequently
             let start = Instant::now();
             let elapsed = start - last_frame_time;
 
             self.scene_mgr.update();
-            self.draw(elapsed.as_secs_f64()).await;
+
+            let drawn = if self.needs_redraw(start - last_draw_time) {
+                self.draw(elapsed.as_secs_f64()).await;
+                last_draw_time = start;
+                true
+            } else {
+                log::trace!("Nothing changed. Skipping frame");
+                false
+            };
 
             last_frame_time = start;
+
+            // Don't draw too frequently
+            let wait_time = match frame_interval {
+                Some(interval) => interval.checked_sub(start.elapsed()),
+                None if !drawn => Some(Self::IDLE_POLL_INTERVAL),
+                None => None,
+            };
+
+            if let Some(wait_time) = wait_time {
+                crate::sleep(wait_time).await;
+            }
         }
     }
 
+    fn needs_redraw(&self, since_last_draw: Duration) -> bool {
+        if !self.config.skip_unchanged_frames {
+            return true;
+        }
+
+        // Evaluate all of these, so that no change notification is left pending
+        let scene_changed = self.scene_mgr.needs_redraw();
+        let geometry_changed = self.graphics.get_renderer().get_geometry().dirty;
+        let ui_changed = self.ui_render_loop.has_pending_input();
+
+        scene_changed
+            || geometry_changed
+            || ui_changed
+            || since_last_draw >= Self::IDLE_REFRESH_INTERVAL
+    }
+
     #[tracing::instrument(skip(self))]
     async fn draw(&mut self, elapsed: f64) {
         let renderer = self.graphics.get_renderer();
@@ -113,6 +199,7 @@ impl<'a> RenderLoop<'a> {
                     adapter,
                     device,
                     &geometry.window_size,
+                    self.config.vsync,
                 );
 
                 self.render_context.depth_buffer =
@@ -195,6 +282,8 @@ impl<'a> RenderLoop<'a> {
 
         log::trace!("Presenting frame");
         swap_frame.present();
+
+        self.recorder.capture(&self.window);
     }
 
     fn make_depth_buffer(
@@ -222,7 +311,14 @@ impl<'a> RenderLoop<'a> {
         adapter: &wgpu::Adapter,
         device: &wgpu::Device,
         size: &winit::dpi::PhysicalSize<u32>,
+        vsync: bool,
     ) {
+        let present_mode = if vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        };
+
         let format = *surface
             .get_capabilities(adapter)
             .formats
@@ -235,7 +331,7 @@ impl<'a> RenderLoop<'a> {
             width: size.width,
             height: size.height,
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            present_mode: wgpu::PresentMode::AutoVsync,
+            present_mode,
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         })
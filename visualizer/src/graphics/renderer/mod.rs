@@ -154,6 +154,15 @@ impl Renderer {
     /// Note: Calling this while holding other locks (e.g., to device) may cause a deadlock
     pub fn set_window_size(&self, size: PhysicalSize<u32>) {
         log::debug!("Window was resized to {size:?}.");
+
+        // Surfaces cannot be empty or larger than the maximum texture size
+        // (e.g., when a canvas is hidden or stretched across multiple screens)
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let size = PhysicalSize::new(
+            size.width.clamp(1, max_size),
+            size.height.clamp(1, max_size),
+        );
+
         let mut geometry = self.geometry.lock();
         geometry.window_size = size;
         geometry.dirty = true;
@@ -1,8 +1,13 @@
 #![allow(clippy::bool_to_int_with_if)]
 #![allow(clippy::arc_with_non_send_sync)]
 
+pub mod export;
 pub mod graphics;
+pub mod recording;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
 pub mod scene;
+pub mod simulation;
 pub mod ui;
 pub mod window_loop;
 
@@ -16,11 +21,19 @@ cfg_if::cfg_if! {
             F::Output: 'static {
             tokio::task::spawn_local(future)
         }
+
+        /// There are no timers in the browser's tokio runtime,
+        /// so frames are only paced by the display (through vsync)
+        pub async fn sleep(_duration: std::time::Duration) {}
     } else {
         pub fn spawn_task<F>(future: F) -> JoinHandle<F::Output> where
             F: Future + Send + 'static,
             F::Output: Send+ 'static {
             tokio::spawn(future)
         }
+
+        pub async fn sleep(duration: std::time::Duration) {
+            tokio::time::sleep(duration).await
+        }
     }
 }
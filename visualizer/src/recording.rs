@@ -0,0 +1,220 @@
+//! Recording the network view as an animated GIF
+//!
+//! Frames are sampled from the canvas at a low frame rate and encoded right away,
+//! so only the compressed animation is kept in memory.
+//! Only the web build can sample frames; see `Recorder::is_supported`.
+
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use instant::Instant;
+
+/// An ongoing recording
+#[derive(Default)]
+struct Capture {
+    /// Created once the size of the first frame is known
+    encoder: Option<gif::Encoder<Vec<u8>>>,
+    width: u16,
+    height: u16,
+    num_frames: usize,
+    last_frame: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    capture: StdMutex<Option<Capture>>,
+}
+
+impl Recorder {
+    /// Sample five frames per second
+    const FRAME_INTERVAL: Duration = Duration::from_millis(200);
+
+    /// Frames are scaled down to at most this width (in pixels)
+    const MAX_WIDTH: u32 = 480;
+
+    /// Stop sampling after five minutes, to bound memory usage
+    const MAX_FRAMES: usize = 1500;
+
+    /// Speed of the color quantization (1 is slowest, 30 is fastest)
+    const QUANTIZATION_SPEED: i32 = 20;
+
+    /// Can frames be sampled on this platform?
+    pub fn is_supported() -> bool {
+        cfg!(target_arch = "wasm32")
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.capture.lock().unwrap().is_some()
+    }
+
+    /// The number of frames recorded so far
+    pub fn num_frames(&self) -> usize {
+        self.capture
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |capture| capture.num_frames)
+    }
+
+    /// Discards any ongoing recording and starts a new one
+    pub fn start(&self) {
+        log::info!("Started recording");
+        *self.capture.lock().unwrap() = Some(Capture::default());
+    }
+
+    /// Returns the encoded animation, or `None` if no frames were recorded
+    pub fn stop(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(capture) = self.capture.lock().unwrap().take() else {
+            return Ok(None);
+        };
+
+        log::info!("Stopped recording after {} frames", capture.num_frames);
+
+        match capture.encoder {
+            Some(encoder) => Ok(Some(encoder.into_inner()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Samples the content of the window, if a recording is active and the next frame is due
+    /// Must be called right after a frame was presented, as the canvas may be cleared afterwards
+    pub fn capture(&self, window: &winit::window::Window) {
+        let mut lock = self.capture.lock().unwrap();
+        let Some(capture) = lock.as_mut() else {
+            return;
+        };
+
+        let now = Instant::now();
+        if let Some(last_frame) = capture.last_frame
+            && now - last_frame < Self::FRAME_INTERVAL
+        {
+            return;
+        }
+
+        if capture.num_frames >= Self::MAX_FRAMES {
+            return;
+        }
+
+        if let Err(err) = Self::add_frame(capture, window, now) {
+            log::error!("Failed to record frame: {err:?}");
+        }
+    }
+
+    fn add_frame(
+        capture: &mut Capture,
+        window: &winit::window::Window,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        if capture.encoder.is_none() {
+            let size = window.inner_size();
+            if size.width == 0 || size.height == 0 {
+                return Ok(());
+            }
+
+            let width = size.width.min(Self::MAX_WIDTH);
+            let height = (size.height as u64 * width as u64 / size.width as u64).max(1);
+
+            capture.width = width.try_into()?;
+            capture.height = height.try_into()?;
+
+            let mut encoder = gif::Encoder::new(vec![], capture.width, capture.height, &[])?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            capture.encoder = Some(encoder);
+        }
+
+        let Some(mut pixels) = sample_frame(window, capture.width, capture.height)? else {
+            return Ok(());
+        };
+
+        // The delay of a frame is given in hundredths of a second
+        let delay = capture
+            .last_frame
+            .map_or(Self::FRAME_INTERVAL, |last_frame| now - last_frame);
+
+        let mut frame = gif::Frame::from_rgba_speed(
+            capture.width,
+            capture.height,
+            &mut pixels,
+            Self::QUANTIZATION_SPEED,
+        );
+        frame.delay = (delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+
+        capture
+            .encoder
+            .as_mut()
+            .expect("No encoder")
+            .write_frame(&frame)?;
+
+        capture.num_frames += 1;
+        capture.last_frame = Some(now);
+
+        if capture.num_frames == Self::MAX_FRAMES {
+            log::warn!("Recording reached the maximum length; no more frames will be added");
+        }
+
+        Ok(())
+    }
+}
+
+/// Draws the canvas scaled to the given size and returns its RGBA pixels
+#[cfg(target_arch = "wasm32")]
+fn sample_frame(
+    window: &winit::window::Window,
+    width: u16,
+    height: u16,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    use wasm_bindgen::{JsCast, JsValue};
+    use winit::platform::web::WindowExtWebSys;
+
+    let to_error = |err: JsValue| anyhow::anyhow!("Failed to sample canvas: {err:?}");
+
+    let Some(canvas) = window.canvas() else {
+        return Ok(None);
+    };
+    let canvas: &web_sys::HtmlCanvasElement = &canvas;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| anyhow::anyhow!("Cannot access the html document"))?;
+
+    let scaled = document
+        .create_element("canvas")
+        .map_err(to_error)?
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .map_err(|_| anyhow::anyhow!("Failed to create canvas element"))?;
+    scaled.set_width(width as u32);
+    scaled.set_height(height as u32);
+
+    let context = scaled
+        .get_context("2d")
+        .map_err(to_error)?
+        .ok_or_else(|| anyhow::anyhow!("No 2D context available"))?
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .map_err(|_| anyhow::anyhow!("Failed to get 2D context"))?;
+
+    context
+        .draw_image_with_html_canvas_element_and_dw_and_dh(
+            canvas,
+            0.0,
+            0.0,
+            width as f64,
+            height as f64,
+        )
+        .map_err(to_error)?;
+
+    let image = context
+        .get_image_data(0.0, 0.0, width as f64, height as f64)
+        .map_err(to_error)?;
+
+    Ok(Some(image.data().0))
+}
+
+/// There is no canvas to sample outside of the browser
+#[cfg(not(target_arch = "wasm32"))]
+fn sample_frame(
+    _window: &winit::window::Window,
+    _width: u16,
+    _height: u16,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    Ok(None)
+}
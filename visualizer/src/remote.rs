@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, OnceLock, mpsc};
+
+use anyhow::Context;
+
+use parking_lot::Mutex;
+
+use simba::{
+    BlockDetails, BlockEvent, BlockGraph, BlockId, EventCallback, LinkEvent, Location,
+    NodeChainInfo, NodeEvent, NodeIndex, ObjectId, RemoteEvent, RemoteMessage, RemoteRequest,
+    RemoteResponse, SimulationSpeed, StatisticsEvent, StatsEventCallback, Time,
+    read_remote_message, write_remote_message,
+};
+
+use crate::simulation::SimulationHandle;
+
+/// An event with identifiers that are valid in this process
+enum LocalEvent {
+    Block(BlockId, BlockEvent),
+    Node(NodeIndex, NodeEvent),
+    Link(ObjectId, LinkEvent),
+    Statistics(StatisticsEvent),
+}
+
+#[derive(Default)]
+struct Callbacks {
+    block: OnceLock<EventCallback<BlockId, BlockEvent>>,
+    node: OnceLock<EventCallback<NodeIndex, NodeEvent>>,
+    link: OnceLock<EventCallback<ObjectId, LinkEvent>>,
+    stats: OnceLock<StatsEventCallback>,
+    /// Events received before their callback was set
+    pending: Mutex<Vec<LocalEvent>>,
+}
+
+impl Callbacks {
+    /// Returns the event if there is no callback for it (yet)
+    fn try_dispatch(&self, event: LocalEvent) -> Option<LocalEvent> {
+        match event {
+            LocalEvent::Block(block_id, event) => {
+                if let Some(callback) = self.block.get() {
+                    callback(block_id, event);
+                    None
+                } else {
+                    Some(LocalEvent::Block(block_id, event))
+                }
+            }
+            LocalEvent::Node(node_idx, event) => {
+                if let Some(callback) = self.node.get() {
+                    callback(node_idx, event);
+                    None
+                } else {
+                    Some(LocalEvent::Node(node_idx, event))
+                }
+            }
+            LocalEvent::Link(link_id, event) => {
+                if let Some(callback) = self.link.get() {
+                    callback(link_id, event);
+                    None
+                } else {
+                    Some(LocalEvent::Link(link_id, event))
+                }
+            }
+            LocalEvent::Statistics(event) => {
+                if let Some(callback) = self.stats.get() {
+                    callback(event);
+                    None
+                } else {
+                    Some(LocalEvent::Statistics(event))
+                }
+            }
+        }
+    }
+
+    fn dispatch(&self, event: LocalEvent) {
+        // Hold the lock so that the event cannot overtake pending ones
+        let mut pending = self.pending.lock();
+
+        if let Some(event) = self.try_dispatch(event) {
+            pending.push(event);
+        }
+    }
+
+    /// Sets a callback and hands it all events it missed
+    fn set<C>(&self, cell: &OnceLock<C>, callback: C) {
+        let mut pending = self.pending.lock();
+
+        cell.set(callback)
+            .unwrap_or_else(|_| panic!("Event callback already set"));
+
+        for event in std::mem::take(&mut *pending) {
+            if let Some(event) = self.try_dispatch(event) {
+                pending.push(event);
+            }
+        }
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    responses: mpsc::Receiver<RemoteResponse>,
+}
+
+/// A simulation running in another process, e.g., started with `simba serve`
+///
+/// The simulation starts once connected and stops when this is dropped.
+pub struct RemoteSimulation {
+    connection: Mutex<Connection>,
+    callbacks: Arc<Callbacks>,
+}
+
+impl RemoteSimulation {
+    pub fn connect(address: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let stream =
+            TcpStream::connect(address).with_context(|| "Failed to connect to simulation")?;
+        stream.set_nodelay(true)?;
+
+        let reader = BufReader::new(stream.try_clone()?);
+        let callbacks = Arc::new(Callbacks::default());
+        let (response_sender, responses) = mpsc::channel();
+
+        {
+            let callbacks = callbacks.clone();
+            std::thread::spawn(move || Self::receive(reader, callbacks, response_sender));
+        }
+
+        Ok(Self {
+            connection: Mutex::new(Connection { stream, responses }),
+            callbacks,
+        })
+    }
+
+    fn receive(
+        mut reader: BufReader<TcpStream>,
+        callbacks: Arc<Callbacks>,
+        responses: mpsc::Sender<RemoteResponse>,
+    ) {
+        // Object identifiers are numbered by the server
+        let mut identifiers = HashMap::<u64, ObjectId>::new();
+
+        loop {
+            let message = match read_remote_message(&mut reader) {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    log::info!("Simulation closed the connection");
+                    break;
+                }
+                Err(err) => {
+                    log::error!("Lost connection to simulation: {err:?}");
+                    break;
+                }
+            };
+
+            let event = match message {
+                RemoteMessage::Response(response) => {
+                    if responses.send(response).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                RemoteMessage::Event(event) => event,
+            };
+
+            let mut get_object_id = |identifier| {
+                *identifiers
+                    .entry(identifier)
+                    .or_insert_with(ObjectId::random)
+            };
+
+            let event = match event {
+                RemoteEvent::Block { identifier, event } => LocalEvent::Block(identifier, event),
+                RemoteEvent::NodeCreated { index, identifier } => {
+                    LocalEvent::Node(index, NodeEvent::Created(get_object_id(identifier)))
+                }
+                RemoteEvent::Node { index, event } => LocalEvent::Node(index, event),
+                RemoteEvent::Link { identifier, event } => {
+                    LocalEvent::Link(get_object_id(identifier), event)
+                }
+                RemoteEvent::Statistics(event) => LocalEvent::Statistics(event),
+            };
+
+            callbacks.dispatch(event);
+        }
+    }
+
+    fn issue_request(&self, request: RemoteRequest) -> RemoteResponse {
+        // Only one request at a time, so that responses cannot get mixed up
+        let mut connection = self.connection.lock();
+
+        write_remote_message(&mut connection.stream, &request)
+            .expect("Lost connection to simulation");
+
+        let response = connection
+            .responses
+            .recv()
+            .expect("Lost connection to simulation");
+
+        if let RemoteResponse::Error(err) = response {
+            panic!("Simulation rejected request: {err}");
+        }
+
+        response
+    }
+}
+
+impl Drop for RemoteSimulation {
+    fn drop(&mut self) {
+        // Stops the simulation
+        let _ = self.connection.lock().stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl SimulationHandle for RemoteSimulation {
+    fn set_block_event_callback(&self, callback: EventCallback<BlockId, BlockEvent>) {
+        self.callbacks.set(&self.callbacks.block, callback);
+    }
+
+    fn set_node_event_callback(&self, callback: EventCallback<NodeIndex, NodeEvent>) {
+        self.callbacks.set(&self.callbacks.node, callback);
+    }
+
+    fn set_link_event_callback(&self, callback: EventCallback<ObjectId, LinkEvent>) {
+        self.callbacks.set(&self.callbacks.link, callback);
+    }
+
+    fn set_stats_event_callback(&self, callback: StatsEventCallback) {
+        self.callbacks.set(&self.callbacks.stats, callback);
+    }
+
+    fn get_current_time(&self) -> Time {
+        if let RemoteResponse::CurrentTime(millis) = self.issue_request(RemoteRequest::CurrentTime)
+        {
+            Time::from_millis(millis)
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn get_node_location(&self, node_index: NodeIndex) -> Location {
+        if let RemoteResponse::NodeLocation(location) =
+            self.issue_request(RemoteRequest::NodeLocation(node_index))
+        {
+            location
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn get_node_chain_info(&self, node_index: NodeIndex) -> Option<NodeChainInfo> {
+        if let RemoteResponse::NodeChainInfo(info) =
+            self.issue_request(RemoteRequest::NodeChainInfo(node_index))
+        {
+            info
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn get_speed(&self) -> SimulationSpeed {
+        if let RemoteResponse::Speed(speed) = self.issue_request(RemoteRequest::Speed) {
+            speed
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn set_rate_limit(&self, rate_limit: u32) {
+        self.issue_request(RemoteRequest::SetRateLimit(rate_limit));
+    }
+
+    fn get_rate_limit(&self) -> Option<u32> {
+        if let RemoteResponse::RateLimit(rate_limit) = self.issue_request(RemoteRequest::RateLimit)
+        {
+            rate_limit
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn get_rate_limit_f64(&self) -> Option<f64> {
+        self.get_rate_limit()
+            .map(|rate_limit| (rate_limit as f64) / 1000.0)
+    }
+
+    fn pause_at(&self, time: Time) {
+        self.issue_request(RemoteRequest::PauseAt(time.to_millis()));
+    }
+
+    fn get_block_graph(&self) -> BlockGraph {
+        if let RemoteResponse::BlockGraph(graph) = self.issue_request(RemoteRequest::BlockGraph) {
+            graph
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+
+    fn get_block_details(&self, block_id: BlockId) -> Option<BlockDetails> {
+        if let RemoteResponse::BlockDetails(details) =
+            self.issue_request(RemoteRequest::BlockDetails(block_id))
+        {
+            details
+        } else {
+            panic!("Got unexpected response");
+        }
+    }
+}
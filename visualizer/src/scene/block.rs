@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use simba::{BlockId, GENESIS_BLOCK};
 
 use super::SceneObject;
 use crate::graphics::{Drawable, Graphics, RectangleStyle};
-use crate::scene::ObjectId;
+use crate::scene::{LegendEntry, LegendShape, ObjectId};
 use crate::ui::{ObjectPropertyValue, UiMessage, UiMessages};
 
 pub struct BlockMetrics {
@@ -21,26 +22,46 @@ pub struct Block {
     rectangle: Arc<Drawable>,
     ui_messages: Arc<UiMessages>,
     metrics: BlockMetrics,
+    is_selected: AtomicBool,
+    /// Did any node switch to this block from another fork?
+    is_reorg_target: AtomicBool,
+    is_committed: AtomicBool,
 }
 
-fn unselected_block_style() -> RectangleStyle {
-    RectangleStyle {
-        width: 10.0,
-        height: 10.0,
-        border_width: 1.0,
-        fill_color: super::COLOR1.into_vec4(),
-        border_color: super::COLOR4.into_vec4(),
-        ..Default::default()
-    }
-}
+/// The meaning of all block colors
+pub(super) const BLOCK_LEGEND: [LegendEntry; 3] = [
+    LegendEntry::new(LegendShape::Square, super::COLOR1, "Block"),
+    LegendEntry::new(
+        LegendShape::Square,
+        super::COLOR2,
+        "Head after a reorganization",
+    ),
+    LegendEntry::new(LegendShape::Square, super::COLOR3, "Committed (border)"),
+];
+
+fn block_style(selected: bool, reorg_target: bool, committed: bool) -> RectangleStyle {
+    let fill_color = if reorg_target {
+        super::COLOR2.into_vec4()
+    } else {
+        super::COLOR1.into_vec4()
+    };
+
+    let border_color = if selected {
+        super::COLOR_BLACK.into_vec4()
+    } else if committed {
+        super::COLOR3.into_vec4()
+    } else {
+        super::COLOR4.into_vec4()
+    };
+
+    let border_width = if selected || committed { 2.0 } else { 1.0 };
 
-fn selected_block_style() -> RectangleStyle {
     RectangleStyle {
         width: 10.0,
         height: 10.0,
-        border_width: 2.0,
-        fill_color: super::COLOR1.into_vec4(),
-        border_color: super::COLOR_BLACK.into_vec4(),
+        border_width,
+        fill_color,
+        border_color,
         ..Default::default()
     }
 }
@@ -55,7 +76,7 @@ impl Block {
         metrics: BlockMetrics,
     ) -> Self {
         let rectangle = graphics
-            .create_rectangle(position, 5, unselected_block_style())
+            .create_rectangle(position, 5, block_style(false, false, false))
             .await;
         Self {
             identifier,
@@ -63,6 +84,31 @@ impl Block {
             rectangle,
             ui_messages,
             metrics,
+            is_selected: AtomicBool::new(false),
+            is_reorg_target: AtomicBool::new(false),
+            is_committed: AtomicBool::new(false),
+        }
+    }
+
+    fn update_style(&self) {
+        self.rectangle.set_style(block_style(
+            self.is_selected.load(Ordering::SeqCst),
+            self.is_reorg_target.load(Ordering::SeqCst),
+            self.is_committed.load(Ordering::SeqCst),
+        ));
+    }
+
+    /// Highlights this block as the new head of a chain reorganization
+    pub fn mark_as_reorg_target(&self) {
+        if !self.is_reorg_target.swap(true, Ordering::SeqCst) {
+            self.update_style();
+        }
+    }
+
+    /// Highlights this block as final
+    pub fn mark_as_committed(&self) {
+        if !self.is_committed.swap(true, Ordering::SeqCst) {
+            self.update_style();
         }
     }
 }
@@ -83,7 +129,8 @@ impl SceneObject for Block {
     }
 
     fn select(&self) {
-        self.rectangle.set_style(selected_block_style());
+        self.is_selected.store(true, Ordering::SeqCst);
+        self.update_style();
 
         let mut properties = HashMap::new();
 
@@ -124,10 +171,13 @@ impl SceneObject for Block {
         let msg = UiMessage::ObjectSelected { name, properties };
 
         self.ui_messages.push(msg);
+        self.ui_messages
+            .push(UiMessage::BlockSelected(self.block_id));
     }
 
     fn unselect(&self) {
-        self.rectangle.set_style(unselected_block_style());
+        self.is_selected.store(false, Ordering::SeqCst);
+        self.update_style();
 
         let msg = UiMessage::ObjectUnselected;
         self.ui_messages.push(msg);
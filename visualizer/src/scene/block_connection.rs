@@ -1,5 +1,5 @@
 use crate::graphics::{Drawable, Graphics, LineStyle};
-use crate::scene::ObjectId;
+use crate::scene::{LegendEntry, LegendShape, ObjectId};
 
 use std::sync::Arc;
 
@@ -10,6 +10,12 @@ pub struct BlockConnection {
     line: Arc<Drawable>,
 }
 
+/// The meaning of all connection colors
+pub(super) const BLOCK_CONNECTION_LEGEND: [LegendEntry; 2] = [
+    LegendEntry::new(LegendShape::Line, super::COLOR3, "Parent"),
+    LegendEntry::new(LegendShape::Line, super::COLOR2, "Uncle"),
+];
+
 fn parent_style() -> LineStyle {
     LineStyle {
         fill_color: super::COLOR3.into_vec4(),
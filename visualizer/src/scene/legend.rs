@@ -0,0 +1,27 @@
+use crate::graphics::Color;
+
+/// The shape an object is drawn with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LegendShape {
+    Circle,
+    Square,
+    Line,
+}
+
+/// Explains what a color means in a specific view
+#[derive(Debug, Copy, Clone)]
+pub struct LegendEntry {
+    pub shape: LegendShape,
+    pub color: Color,
+    pub label: &'static str,
+}
+
+impl LegendEntry {
+    pub const fn new(shape: LegendShape, color: Color, label: &'static str) -> Self {
+        Self {
+            shape,
+            color,
+            label,
+        }
+    }
+}
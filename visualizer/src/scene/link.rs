@@ -3,13 +3,15 @@ use std::sync::Arc;
 use parking_lot::Mutex;
 
 use crate::graphics::{Drawable, Graphics, LineStyle};
-use crate::scene::ObjectId;
+use crate::scene::{LegendEntry, LegendShape, ObjectId};
 
 use super::SceneObject;
 
 struct LinkState {
     active_current: bool,
     active_new: bool,
+    severed_current: bool,
+    severed_new: bool,
 }
 
 pub struct Link {
@@ -18,6 +20,13 @@ pub struct Link {
     state: Mutex<LinkState>,
 }
 
+/// The meaning of all link colors
+pub(super) const LINK_LEGEND: [LegendEntry; 3] = [
+    LegendEntry::new(LegendShape::Line, super::COLOR3, "Active link"),
+    LegendEntry::new(LegendShape::Line, super::COLOR4, "Inactive link"),
+    LegendEntry::new(LegendShape::Line, super::COLOR_WARNING, "Severed link"),
+];
+
 fn active_link_style() -> LineStyle {
     LineStyle {
         fill_color: super::COLOR3.into_vec4(),
@@ -38,6 +47,18 @@ fn inactive_link_style() -> LineStyle {
     }
 }
 
+/// Links across a network partition are dashed
+fn severed_link_style() -> LineStyle {
+    LineStyle {
+        fill_color: super::COLOR_WARNING.into_vec4(),
+        border_color: super::COLOR_WARNING.into_vec4(),
+        line_width: 0.5,
+        border_width: 0.05,
+        dash_length: 1.0,
+        ..Default::default()
+    }
+}
+
 impl Link {
     pub async fn new(
         identifier: ObjectId,
@@ -51,6 +72,8 @@ impl Link {
         let state = Mutex::new(LinkState {
             active_current: false,
             active_new: false,
+            severed_current: false,
+            severed_new: false,
         });
 
         Self {
@@ -69,6 +92,12 @@ impl Link {
         let mut state = self.state.lock();
         state.active_new = false;
     }
+
+    /// Set while the link connects two sides of a network partition
+    pub fn mark_severed(&self, severed: bool) {
+        let mut state = self.state.lock();
+        state.severed_new = severed;
+    }
 }
 
 #[cfg_attr(target_arch="wasm32", async_trait::async_trait(?Send))]
@@ -79,19 +108,24 @@ impl SceneObject for Link {
     }
 
     fn update(&self) {
-        let new_active = {
+        let new_state = {
             let mut state = self.state.lock();
 
-            if state.active_new == state.active_current {
+            if state.active_new == state.active_current
+                && state.severed_new == state.severed_current
+            {
                 None
             } else {
                 state.active_current = state.active_new;
-                Some(state.active_current)
+                state.severed_current = state.severed_new;
+                Some((state.active_current, state.severed_current))
             }
         };
 
-        if let Some(is_active) = new_active {
-            if is_active {
+        if let Some((is_active, is_severed)) = new_state {
+            if is_severed {
+                self.line.set_style(severed_link_style());
+            } else if is_active {
                 self.line.set_style(active_link_style());
             } else {
                 self.line.set_style(inactive_link_style());
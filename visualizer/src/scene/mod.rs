@@ -3,7 +3,9 @@ use std::sync::Arc;
 
 use parking_lot::Mutex;
 
-use simba::Simulation;
+use simba::{BlockId, StatisticsSnapshot};
+
+use crate::simulation::SimulationHandle;
 
 use crate::graphics::{Camera, Color, Drawable, Graphics};
 use crate::ui::UiMessages;
@@ -20,31 +22,45 @@ pub use block::{Block, BlockMetrics};
 mod block_connection;
 pub use block_connection::BlockConnection;
 
+mod partition;
+pub use partition::PartitionRegion;
+
 mod scenes;
 pub use scenes::Scene;
 
+mod legend;
+pub use legend::{LegendEntry, LegendShape};
+
 pub const COLOR1: Color = Color::from_rgba(154, 173, 191, 255);
 pub const COLOR2: Color = Color::from_rgba(109, 152, 186, 255);
 pub const COLOR3: Color = Color::from_rgba(158, 228, 147, 255);
 pub const COLOR4: Color = Color::from_rgba(59, 37, 44, 255);
 pub const COLOR5: Color = Color::from_rgba(33, 2, 3, 255);
 pub const COLOR_BLACK: Color = Color::from_rgba(0, 0, 0, 255);
+pub const COLOR_WARNING: Color = Color::from_rgba(214, 69, 65, 255);
+pub const COLOR_LAGGING: Color = Color::from_rgba(232, 167, 62, 255);
+pub const COLOR_CRASHED: Color = Color::from_rgba(128, 128, 128, 255);
+pub const COLOR_OFFLINE: Color = Color::from_rgba(210, 210, 210, 255);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, derive_more::Display)]
 pub enum ViewType {
     Network,
     Blockchain,
+    /// A view registered using a `SceneBuilder`
+    #[display("{_0}")]
+    Custom(&'static str),
 }
 
 pub type ObjectId = u64;
 
 impl ViewType {
-    pub const ALL: [Self; 2] = [Self::Network, Self::Blockchain];
+    /// The views that are always available
+    pub const BUILTIN: [Self; 2] = [Self::Network, Self::Blockchain];
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 #[async_trait::async_trait]
-trait SceneObject: Send + Sync {
+pub trait SceneObject: Send + Sync {
     fn get_identifier(&self) -> ObjectId;
 
     fn update(&self) {}
@@ -58,11 +74,14 @@ trait SceneObject: Send + Sync {
     fn select(&self) {}
 
     fn unselect(&self) {}
+
+    /// Called whenever the simulation pushes new statistics
+    fn notify_statistics(&self, _snapshot: &StatisticsSnapshot) {}
 }
 
 #[cfg(target_arch = "wasm32")]
 #[async_trait::async_trait(?Send)]
-trait SceneObject {
+pub trait SceneObject {
     fn get_identifier(&self) -> ObjectId;
 
     fn update(&self) {}
@@ -76,10 +95,51 @@ trait SceneObject {
     fn select(&self) {}
 
     fn unselect(&self) {}
+
+    /// Called whenever the simulation pushes new statistics
+    fn notify_statistics(&self, _snapshot: &StatisticsSnapshot) {}
+}
+
+/// Creates a custom view that shows up in the view selector
+/// next to the built-in views
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait::async_trait]
+pub trait SceneBuilder: Send + Sync {
+    /// The name shown in the view selector (must be unique)
+    fn get_name(&self) -> &'static str;
+
+    /// Create the scene; use `Scene::new`, `Scene::add_object`,
+    /// and `Scene::add_to_legend` to populate it
+    async fn build(
+        &self,
+        graphics: Arc<Graphics>,
+        ui_messages: Arc<UiMessages>,
+        simulation: Arc<dyn SimulationHandle>,
+    ) -> Arc<Scene>;
+}
+
+/// Creates a custom view that shows up in the view selector
+/// next to the built-in views
+#[cfg(target_arch = "wasm32")]
+#[async_trait::async_trait(?Send)]
+pub trait SceneBuilder {
+    /// The name shown in the view selector (must be unique)
+    fn get_name(&self) -> &'static str;
+
+    /// Create the scene; use `Scene::new`, `Scene::add_object`,
+    /// and `Scene::add_to_legend` to populate it
+    async fn build(
+        &self,
+        graphics: Arc<Graphics>,
+        ui_messages: Arc<UiMessages>,
+        simulation: Arc<dyn SimulationHandle>,
+    ) -> Arc<Scene>;
 }
 
 pub struct SceneManager {
     scenes: HashMap<ViewType, Arc<Scene>>,
+    /// All available views in the order they are shown in the selector
+    view_types: Vec<ViewType>,
     active_scene: Mutex<ViewType>,
 }
 
@@ -87,26 +147,58 @@ impl SceneManager {
     pub async fn new(
         graphics: Arc<Graphics>,
         ui_messages: Arc<UiMessages>,
-        simulation: Arc<Simulation>,
+        simulation: Arc<dyn SimulationHandle>,
+    ) -> Self {
+        Self::new_with_custom_views(graphics, ui_messages, simulation, vec![]).await
+    }
+
+    /// Like `new`, but additionally builds the views of the given scene builders
+    pub async fn new_with_custom_views(
+        graphics: Arc<Graphics>,
+        ui_messages: Arc<UiMessages>,
+        simulation: Arc<dyn SimulationHandle>,
+        custom_views: Vec<Box<dyn SceneBuilder>>,
     ) -> Self {
         let network_scene =
             Scene::build_network(graphics.clone(), ui_messages.clone(), simulation.clone()).await;
         let blockchain_scene =
-            Scene::build_blockchain(graphics.clone(), ui_messages, &simulation).await;
+            Scene::build_blockchain(graphics.clone(), ui_messages.clone(), &simulation).await;
 
         let mut scenes = HashMap::new();
         scenes.insert(ViewType::Network, network_scene);
         scenes.insert(ViewType::Blockchain, blockchain_scene);
 
+        let mut view_types = ViewType::BUILTIN.to_vec();
+
+        for builder in custom_views {
+            let view_type = ViewType::Custom(builder.get_name());
+            assert!(
+                !scenes.contains_key(&view_type),
+                "View \"{view_type}\" was registered twice"
+            );
+
+            let scene = builder
+                .build(graphics.clone(), ui_messages.clone(), simulation.clone())
+                .await;
+            scenes.insert(view_type, scene);
+            view_types.push(view_type);
+        }
+
         let active_scene = ViewType::Network;
         scenes[&active_scene].resume();
 
         Self {
             scenes,
+            view_types,
             active_scene: Mutex::new(active_scene),
         }
     }
 
+    /// All views that can be selected
+    pub fn get_view_types(&self) -> &[ViewType] {
+        &self.view_types
+    }
+
     pub fn update(&self) {
         self.get_active_scene().update();
     }
@@ -137,6 +229,11 @@ impl SceneManager {
         &self.scenes[&active_scene]
     }
 
+    /// Selects a block in the blockchain view
+    pub fn select_block(&self, block_id: &BlockId) {
+        self.scenes[&ViewType::Blockchain].select_block(block_id);
+    }
+
     pub fn get_active_camera(&self) -> &Arc<Camera> {
         let active_scene = self.get_active_scene_type();
         let scene = &self.scenes[&active_scene];
@@ -144,12 +241,34 @@ impl SceneManager {
         scene.get_camera()
     }
 
+    /// The camera of a specific view (None if there is no such view)
+    pub fn get_camera(&self, view_type: ViewType) -> Option<&Arc<Camera>> {
+        self.scenes.get(&view_type).map(|scene| scene.get_camera())
+    }
+
+    /// Forwards the latest statistics to all scenes (including inactive ones)
+    pub fn notify_statistics(&self, snapshot: &StatisticsSnapshot) {
+        for (_, scene) in self.scenes.iter() {
+            scene.notify_statistics(snapshot);
+        }
+    }
+
     pub fn notify_resize(&self) {
         for (_, scene) in self.scenes.iter() {
             scene.get_camera().notify_resize();
         }
     }
 
+    /// Explains the colors of the active view
+    pub fn get_legend(&self) -> Vec<LegendEntry> {
+        self.get_active_scene().get_legend()
+    }
+
+    /// Does the active scene need to be drawn again?
+    pub fn needs_redraw(&self) -> bool {
+        self.get_active_scene().needs_redraw()
+    }
+
     pub async fn get_drawables(&self) -> (&Arc<Camera>, Vec<Arc<Drawable>>) {
         let active_scene = self.get_active_scene_type();
         let scene = &self.scenes[&active_scene];
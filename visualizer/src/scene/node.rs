@@ -1,11 +1,13 @@
-use simba::{NodeIndex, ObjectId as SimObjectId, Simulation};
+use simba::{NodeIndex, NodeStatistics, ObjectId as SimObjectId, StatisticsSnapshot};
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use parking_lot::Mutex;
 
 use crate::graphics::{CircleStyle, Drawable, Graphics};
-use crate::scene::ObjectId;
+use crate::scene::{LegendEntry, LegendShape, ObjectId};
 use crate::ui::{
     ObjectPropertyMap, ObjectPropertyUnit, ObjectPropertyValue, UiMessage, UiMessages,
 };
@@ -19,24 +21,66 @@ pub struct Node {
     ui_messages: Arc<UiMessages>,
     circle: Arc<Drawable>,
     is_selected: AtomicBool,
-    simulation: Arc<Simulation>,
+    is_overloaded: AtomicBool,
+    is_crashed: AtomicBool,
+    /// The node left the network and has not joined again yet
+    is_offline: AtomicBool,
+    /// The height of this node's chain head
+    chain_height: AtomicU64,
+    /// How many blocks this node is behind the longest chain of any node
+    chain_lag: AtomicU64,
+    /// The latest statistics pushed by the simulation
+    statistics: Mutex<NodeStatistics>,
+}
+
+/// The meaning of all node colors
+pub(super) const NODE_LEGEND: [LegendEntry; 5] = [
+    LegendEntry::new(LegendShape::Circle, super::COLOR1, "Node"),
+    LegendEntry::new(LegendShape::Circle, super::COLOR_WARNING, "Overloaded node"),
+    LegendEntry::new(LegendShape::Circle, super::COLOR_LAGGING, "Lagging node"),
+    LegendEntry::new(LegendShape::Circle, super::COLOR_CRASHED, "Crashed node"),
+    LegendEntry::new(LegendShape::Circle, super::COLOR_OFFLINE, "Offline node"),
+];
+
+fn node_fill_color(crashed: bool, offline: bool, overloaded: bool, lagging: bool) -> glam::Vec4 {
+    if crashed {
+        super::COLOR_CRASHED.into_vec4()
+    } else if offline {
+        super::COLOR_OFFLINE.into_vec4()
+    } else if overloaded {
+        super::COLOR_WARNING.into_vec4()
+    } else if lagging {
+        super::COLOR_LAGGING.into_vec4()
+    } else {
+        super::COLOR1.into_vec4()
+    }
 }
 
-fn selected_node_style() -> CircleStyle {
+fn selected_node_style(
+    crashed: bool,
+    offline: bool,
+    overloaded: bool,
+    lagging: bool,
+) -> CircleStyle {
     CircleStyle {
         radius: 4.0,
         border_width: 1.0,
-        fill_color: super::COLOR1.into_vec4(),
+        fill_color: node_fill_color(crashed, offline, overloaded, lagging),
         border_color: super::COLOR_BLACK.into_vec4(),
         ..Default::default()
     }
 }
 
-fn unselected_node_style() -> CircleStyle {
+fn unselected_node_style(
+    crashed: bool,
+    offline: bool,
+    overloaded: bool,
+    lagging: bool,
+) -> CircleStyle {
     CircleStyle {
         radius: 4.0,
         border_width: 1.0,
-        fill_color: super::COLOR1.into_vec4(),
+        fill_color: node_fill_color(crashed, offline, overloaded, lagging),
         border_color: super::COLOR4.into_vec4(),
         ..Default::default()
     }
@@ -49,25 +93,33 @@ impl Node {
         node_index: NodeIndex,
         graphics: &Graphics,
         ui_messages: Arc<UiMessages>,
-        simulation: Arc<Simulation>,
         position: glam::Vec2,
     ) -> Self {
         let circle = graphics
-            .create_circle(position, 2, unselected_node_style())
+            .create_circle(
+                position,
+                2,
+                unselected_node_style(false, false, false, false),
+            )
             .await;
         Self {
             is_selected: AtomicBool::new(false),
+            is_overloaded: AtomicBool::new(false),
+            is_crashed: AtomicBool::new(false),
+            is_offline: AtomicBool::new(false),
+            chain_height: AtomicU64::new(0),
+            chain_lag: AtomicU64::new(0),
             identifier,
             object_id,
             node_index,
             circle,
             ui_messages,
-            simulation,
+            statistics: Default::default(),
         }
     }
 
     fn generate_properties(&self) -> ObjectPropertyMap {
-        let stats = self.simulation.get_node_statistics(self.node_index);
+        let stats = self.statistics.lock().clone();
         let mut properties = HashMap::new();
         properties.insert(
             "object_id".to_string(),
@@ -82,10 +134,80 @@ impl Node {
             ),
         );
 
+        properties.insert(
+            "dropped_messages".to_string(),
+            (
+                ObjectPropertyValue::Int(stats.dropped_messages as i64),
+                None,
+            ),
+        );
+
+        properties.insert(
+            "chain_height".to_string(),
+            (
+                ObjectPropertyValue::Int(self.chain_height.load(Ordering::SeqCst) as i64),
+                None,
+            ),
+        );
+
+        properties.insert(
+            "chain_lag".to_string(),
+            (
+                ObjectPropertyValue::Int(self.chain_lag.load(Ordering::SeqCst) as i64),
+                None,
+            ),
+        );
+
         properties
     }
 
-    pub fn notify_properties_changed(&self) {
+    /// Highlights the node while its inbox is full
+    pub fn set_overloaded(&self, overloaded: bool) {
+        self.is_overloaded.store(overloaded, Ordering::SeqCst);
+        self.update_style();
+    }
+
+    /// Greys out the node once it stopped processing messages
+    pub fn set_crashed(&self) {
+        self.is_crashed.store(true, Ordering::SeqCst);
+        self.update_style();
+    }
+
+    /// Fades out the node while it is not part of the network
+    pub fn set_offline(&self, offline: bool) {
+        self.is_offline.store(offline, Ordering::SeqCst);
+        self.update_style();
+    }
+
+    /// Highlights the node while its chain is behind the longest one
+    pub fn set_chain_lag(&self, height: u64, lag: u64) {
+        self.chain_height.store(height, Ordering::SeqCst);
+        let prev = self.chain_lag.swap(lag, Ordering::SeqCst);
+
+        if (prev > 0) != (lag > 0) {
+            self.update_style();
+        }
+    }
+
+    fn update_style(&self) {
+        let crashed = self.is_crashed.load(Ordering::SeqCst);
+        let offline = self.is_offline.load(Ordering::SeqCst);
+        let overloaded = self.is_overloaded.load(Ordering::SeqCst);
+        let lagging = self.chain_lag.load(Ordering::SeqCst) > 0;
+
+        if self.is_selected.load(Ordering::SeqCst) {
+            self.circle
+                .set_style(selected_node_style(crashed, offline, overloaded, lagging));
+        } else {
+            self.circle
+                .set_style(unselected_node_style(crashed, offline, overloaded, lagging));
+        }
+    }
+
+    /// Stores the latest statistics and refreshes the properties shown, if selected
+    fn update_statistics(&self, statistics: NodeStatistics) {
+        *self.statistics.lock() = statistics;
+
         if self.is_selected.load(Ordering::SeqCst) {
             let properties = self.generate_properties();
             let msg = UiMessage::UpdateSelectedObject { properties };
@@ -113,7 +235,7 @@ impl SceneObject for Node {
 
     fn select(&self) {
         self.is_selected.store(true, Ordering::SeqCst);
-        self.circle.set_style(selected_node_style());
+        self.update_style();
 
         let name = format!("Node #{}", self.node_index);
         let properties = self.generate_properties();
@@ -122,8 +244,14 @@ impl SceneObject for Node {
         self.ui_messages.push(msg);
     }
 
+    fn notify_statistics(&self, snapshot: &StatisticsSnapshot) {
+        if let Some(statistics) = snapshot.nodes.get(&self.node_index) {
+            self.update_statistics(statistics.clone());
+        }
+    }
+
     fn unselect(&self) {
         self.is_selected.store(false, Ordering::SeqCst);
-        self.circle.set_style(unselected_node_style());
+        self.update_style();
     }
 }
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use crate::graphics::{CircleStyle, Color, Drawable, Graphics};
+use crate::scene::{LegendEntry, LegendShape, ObjectId};
+
+use super::SceneObject;
+
+/// Pale colors to tell the sides of a network partition apart
+const PARTITION_COLORS: [Color; 4] = [
+    Color::from_rgba(214, 226, 240, 255),
+    Color::from_rgba(240, 222, 206, 255),
+    Color::from_rgba(218, 238, 214, 255),
+    Color::from_rgba(232, 216, 238, 255),
+];
+
+/// The meaning of the shaded regions
+pub(super) const PARTITION_LEGEND: [LegendEntry; 1] = [LegendEntry::new(
+    LegendShape::Circle,
+    PARTITION_COLORS[0],
+    "Network partition",
+)];
+
+fn partition_style(group: u32) -> CircleStyle {
+    let color = PARTITION_COLORS[group as usize % PARTITION_COLORS.len()];
+
+    CircleStyle {
+        radius: 9.0,
+        border_width: 0.0,
+        fill_color: color.into_vec4(),
+        border_color: color.into_vec4(),
+        ..Default::default()
+    }
+}
+
+/// Shades the area around a node by the side of the network partition it is on
+pub struct PartitionRegion {
+    identifier: ObjectId,
+    circle: Arc<Drawable>,
+}
+
+impl PartitionRegion {
+    pub async fn new(
+        identifier: ObjectId,
+        graphics: &Graphics,
+        position: glam::Vec2,
+        group: u32,
+    ) -> Self {
+        let circle = graphics
+            .create_circle(position, 0, partition_style(group))
+            .await;
+
+        Self { identifier, circle }
+    }
+}
+
+#[cfg_attr(target_arch="wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl SceneObject for PartitionRegion {
+    fn get_identifier(&self) -> ObjectId {
+        self.identifier
+    }
+
+    fn get_drawable(&self) -> Arc<Drawable> {
+        self.circle.clone()
+    }
+}
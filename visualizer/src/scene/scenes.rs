@@ -1,8 +1,11 @@
 use std::collections::{HashMap, hash_map};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 
-use simba::{BlockEvent, BlockId, GENESIS_BLOCK, LinkEvent, Location, NodeEvent, Simulation};
+use simba::{
+    BlockEvent, BlockId, GENESIS_BLOCK, LinkEvent, Location, NodeEvent, NodeIndex,
+    StatisticsSnapshot,
+};
 
 use glam::Vec2;
 
@@ -12,7 +15,16 @@ use parking_lot::Mutex;
 use tokio::sync::mpsc;
 
 use crate::graphics::{Camera, Drawable, Graphics};
-use crate::scene::{Block, BlockConnection, BlockMetrics, Link, Node, ObjectId, SceneObject};
+use crate::scene::block::BLOCK_LEGEND;
+use crate::scene::block_connection::BLOCK_CONNECTION_LEGEND;
+use crate::scene::link::LINK_LEGEND;
+use crate::scene::node::NODE_LEGEND;
+use crate::scene::partition::PARTITION_LEGEND;
+use crate::scene::{
+    Block, BlockConnection, BlockMetrics, LegendEntry, Link, Node, ObjectId, PartitionRegion,
+    SceneObject,
+};
+use crate::simulation::SimulationHandle;
 use crate::ui::UiMessages;
 
 use crate::spawn_task;
@@ -31,15 +43,76 @@ pub struct Scene {
     next_object_id: AtomicU64,
     camera: Arc<Camera>,
     objects: DashMap<ObjectId, ObjWrapper>,
+    /// Nodes by their index (only populated in the network view)
+    nodes: DashMap<NodeIndex, Arc<Node>>,
+    /// The shaded regions of partitioned nodes (only populated in the network view)
+    partition_regions: DashMap<NodeIndex, ObjectId>,
+    /// Blocks by their identifier (only populated in the blockchain view)
+    blocks: DashMap<BlockId, Arc<Block>>,
+    /// Used to check which nodes lag behind (only set in the network view)
+    simulation: OnceLock<Arc<dyn SimulationHandle>>,
     selected: Mutex<Option<Arc<dyn SceneObject>>>,
+    /// Explains the colors used by the objects of this scene
+    legend: Mutex<Vec<LegendEntry>>,
+    /// Set when objects were added or removed since the last frame
+    changed: AtomicBool,
 }
 
 impl Scene {
+    /// Creates an empty scene
+    pub fn new(camera: Arc<Camera>) -> Arc<Self> {
+        Arc::new(Scene {
+            objects: Default::default(),
+            nodes: Default::default(),
+            partition_regions: Default::default(),
+            blocks: Default::default(),
+            simulation: Default::default(),
+            camera,
+            selected: Mutex::new(None),
+            legend: Default::default(),
+            next_object_id: AtomicU64::new(1),
+            changed: AtomicBool::new(true),
+        })
+    }
+
+    /// Returns an unused identifier for a new object
+    pub fn generate_object_id(&self) -> ObjectId {
+        self.next_object_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Adds an object to the scene
+    /// The object's identifier must have been generated by this scene
+    pub fn add_object(&self, object: Arc<dyn SceneObject>) {
+        self.objects
+            .insert(object.get_identifier(), ObjWrapper(object));
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// Adds entries to the legend shown for this scene
+    pub fn add_to_legend(&self, entries: &[LegendEntry]) {
+        self.legend.lock().extend_from_slice(entries);
+    }
+
+    pub fn get_legend(&self) -> Vec<LegendEntry> {
+        self.legend.lock().clone()
+    }
+
+    pub fn remove_object(&self, identifier: ObjectId) {
+        if self.objects.remove(&identifier).is_some() {
+            self.changed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns true if objects were added or removed since the last call
+    fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+
     /// This creates all the visual representations of nodes and links
     pub async fn build_network(
         graphics: Arc<Graphics>,
         ui_messages: Arc<UiMessages>,
-        simulation: Arc<Simulation>,
+        simulation: Arc<dyn SimulationHandle>,
     ) -> Arc<Self> {
         let min_pos = Vec2::new(
             Location::MIN_LONGITUDE as f32,
@@ -55,14 +128,12 @@ impl Scene {
         camera.look_at(Vec2::new(0.0, 0.0));
         camera.set_zoom(2.0);
 
-        let obj = Arc::new(Scene {
-            objects: Default::default(),
-            camera,
-            selected: Mutex::new(None),
-            next_object_id: AtomicU64::new(1),
-        });
+        let obj = Scene::new(camera);
+        let _ = obj.simulation.set(simulation.clone());
+        obj.add_to_legend(&NODE_LEGEND);
+        obj.add_to_legend(&LINK_LEGEND);
+        obj.add_to_legend(&PARTITION_LEGEND);
 
-        let node_map = Arc::new(DashMap::new());
         let (node_event_sender, mut node_event_receiver) = mpsc::unbounded_channel();
 
         let sim_cpy = simulation.clone();
@@ -77,10 +148,7 @@ impl Scene {
 
                     match event {
                         NodeEvent::Created(node_id) => {
-                            let node_map = node_map.clone();
-                            let simulation = sim_cpy.clone();
-
-                            let loc = simulation.get_node_location(node_idx);
+                            let loc = sim_cpy.get_node_location(node_idx);
                             let position = Vec2::new(loc.longitude as f32, loc.latitude as f32);
                             let obj_id = scene.next_object_id.fetch_add(1, Ordering::SeqCst);
 
@@ -91,20 +159,53 @@ impl Scene {
                                     node_idx,
                                     &graphics,
                                     ui_messages.clone(),
-                                    simulation,
                                     position,
                                 )
                                 .await,
                             );
 
-                            scene.objects.insert(obj_id, ObjWrapper(scene_obj.clone()));
-                            node_map.insert(node_idx, scene_obj);
+                            scene.add_object(scene_obj.clone());
+                            scene.nodes.insert(node_idx, scene_obj);
 
                             log::trace!("Created render object for node #{node_id}");
                         }
-                        NodeEvent::StatisticsUpdated => {
-                            let node = node_map.get(&node_idx).expect("No such node");
-                            node.notify_properties_changed();
+                        NodeEvent::Overloaded => {
+                            let node = scene.nodes.get(&node_idx).expect("No such node");
+                            node.set_overloaded(true);
+                        }
+                        NodeEvent::Recovered => {
+                            let node = scene.nodes.get(&node_idx).expect("No such node");
+                            node.set_overloaded(false);
+                        }
+                        NodeEvent::Crashed => {
+                            let node = scene.nodes.get(&node_idx).expect("No such node");
+                            node.set_crashed();
+                        }
+                        NodeEvent::Removed => {
+                            let node = scene.nodes.get(&node_idx).expect("No such node");
+                            node.set_offline(true);
+                        }
+                        NodeEvent::Joined => {
+                            let node = scene.nodes.get(&node_idx).expect("No such node");
+                            node.set_offline(false);
+                        }
+                        NodeEvent::Partitioned { group } => {
+                            if let Some((_, region_id)) = scene.partition_regions.remove(&node_idx)
+                            {
+                                scene.remove_object(region_id);
+                            }
+
+                            if let Some(group) = group {
+                                let loc = sim_cpy.get_node_location(node_idx);
+                                let position = Vec2::new(loc.longitude as f32, loc.latitude as f32);
+                                let obj_id = scene.generate_object_id();
+
+                                let scene_obj = Arc::new(
+                                    PartitionRegion::new(obj_id, &graphics, position, group).await,
+                                );
+                                scene.add_object(scene_obj);
+                                scene.partition_regions.insert(node_idx, obj_id);
+                            }
                         }
                     }
                 }
@@ -139,7 +240,7 @@ impl Scene {
 
                             let scene_obj =
                                 Arc::new(Link::new(obj_id, &graphics, start, end).await);
-                            scene.objects.insert(obj_id, ObjWrapper(scene_obj.clone()));
+                            scene.add_object(scene_obj.clone());
                             links.insert(link_id, scene_obj);
                         }
                         LinkEvent::Active => {
@@ -148,6 +249,18 @@ impl Scene {
                         LinkEvent::Inactive => {
                             links.get(&link_id).expect("no such link").mark_inactive();
                         }
+                        LinkEvent::Severed => {
+                            links
+                                .get(&link_id)
+                                .expect("no such link")
+                                .mark_severed(true);
+                        }
+                        LinkEvent::Restored => {
+                            links
+                                .get(&link_id)
+                                .expect("no such link")
+                                .mark_severed(false);
+                        }
                     }
                 }
             });
@@ -165,7 +278,7 @@ impl Scene {
     pub async fn build_blockchain(
         graphics: Arc<Graphics>,
         ui_messages: Arc<UiMessages>,
-        simulation: &Simulation,
+        simulation: &dyn SimulationHandle,
     ) -> Arc<Self> {
         let layout = Arc::new(BlockchainLayout::default());
 
@@ -200,15 +313,11 @@ impl Scene {
         camera.look_at(Vec2::new(0.0, 0.0));
         camera.set_zoom(1.0);
 
-        let objects: DashMap<ObjectId, ObjWrapper> = DashMap::new();
-        objects.insert(0, ObjWrapper(genesis_block));
-
-        let obj = Arc::new(Scene {
-            objects,
-            camera,
-            selected: Mutex::new(None),
-            next_object_id: AtomicU64::new(1),
-        });
+        let obj = Scene::new(camera);
+        obj.add_to_legend(&BLOCK_LEGEND);
+        obj.add_to_legend(&BLOCK_CONNECTION_LEGEND);
+        obj.add_object(genesis_block.clone());
+        obj.blocks.insert(GENESIS_BLOCK, genesis_block);
 
         let (block_event_sender, mut block_event_receiver) = mpsc::unbounded_channel();
 
@@ -309,14 +418,15 @@ impl Scene {
                             )
                             .await,
                         );
-                        scene.objects.insert(obj_id, ObjWrapper(block_obj));
+                        scene.add_object(block_obj.clone());
+                        scene.blocks.insert(block_id, block_obj);
 
                         let conn_id = scene.next_object_id.fetch_add(1, Ordering::SeqCst);
                         let conn_obj = Arc::new(
                             BlockConnection::new_parent(conn_id, &graphics, parent_pos, pos).await,
                         );
 
-                        scene.objects.insert(conn_id, ObjWrapper(conn_obj));
+                        scene.add_object(conn_obj);
 
                         for uncle_pos in uncle_positions.drain(..) {
                             let conn_id = scene.next_object_id.fetch_add(1, Ordering::SeqCst);
@@ -324,7 +434,23 @@ impl Scene {
                                 BlockConnection::new_uncle(conn_id, &graphics, uncle_pos, pos)
                                     .await,
                             );
-                            scene.objects.insert(conn_id, ObjWrapper(obj));
+                            scene.add_object(obj);
+                        }
+                    }
+                    BlockEvent::Reorg {
+                        new_head, depth, ..
+                    } => {
+                        log::trace!("Chain head moved to block #{new_head:X} (depth={depth})");
+
+                        if let Some(block) = scene.blocks.get(&new_head) {
+                            block.mark_as_reorg_target();
+                        }
+                    }
+                    BlockEvent::Committed { delay } => {
+                        log::trace!("Block #{block_id:X} was committed after {delay:?}");
+
+                        if let Some(block) = scene.blocks.get(&block_id) {
+                            block.mark_as_committed();
                         }
                     }
                 }
@@ -334,6 +460,45 @@ impl Scene {
         obj
     }
 
+    /// Passes the latest statistics on to all objects of this scene
+    pub fn notify_statistics(&self, snapshot: &StatisticsSnapshot) {
+        for obj in self.objects.iter() {
+            obj.0.notify_statistics(snapshot);
+        }
+
+        self.update_chain_lag();
+    }
+
+    /// Highlights nodes whose chain is shorter than the longest chain of any node
+    fn update_chain_lag(&self) {
+        let Some(simulation) = self.simulation.get() else {
+            return;
+        };
+
+        // Do not hold on to the map while waiting for the simulation
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+
+        let heights: Vec<_> = nodes
+            .into_iter()
+            .filter_map(|(node_idx, node)| {
+                let info = simulation.get_node_chain_info(node_idx)?;
+                Some((node, info.height))
+            })
+            .collect();
+
+        let Some(max_height) = heights.iter().map(|(_, height)| *height).max() else {
+            return;
+        };
+
+        for (node, height) in heights {
+            node.set_chain_lag(height, max_height - height);
+        }
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn update(&self) {
         for obj in self.objects.iter() {
@@ -366,6 +531,38 @@ impl Scene {
         }
     }
 
+    /// Selects the given block and moves the camera to it
+    pub fn select_block(&self, block_id: &BlockId) {
+        let Some(block) = self.blocks.get(block_id).map(|block| block.clone()) else {
+            log::warn!("No block #{block_id:X} in this view");
+            return;
+        };
+        let block: Arc<dyn SceneObject> = block;
+
+        let mut selected = self.selected.lock();
+        if let Some(prev) = selected.take() {
+            prev.unselect();
+        }
+
+        block.select();
+        self.camera.look_at(block.get_drawable().get_position());
+        *selected = Some(block);
+    }
+
+    /// Did anything visible change since the last frame?
+    pub fn needs_redraw(&self) -> bool {
+        if self.take_changed() || self.camera.needs_update() {
+            return true;
+        }
+
+        let view_bbox = self.camera.get_view_bbox();
+
+        self.objects.iter().any(|obj| {
+            let drawable = obj.0.get_drawable();
+            drawable.is_dirty() && view_bbox.overlaps(&drawable.get_bbox())
+        })
+    }
+
     pub async fn get_drawables(&self) -> Vec<Arc<Drawable>> {
         let mut result = vec![];
 
@@ -399,5 +596,6 @@ impl Scene {
     #[tracing::instrument(skip(self))]
     pub fn resume(&self) {
         self.camera.resume();
+        self.changed.store(true, Ordering::SeqCst);
     }
 }
@@ -0,0 +1,103 @@
+use simba::{
+    BlockDetails, BlockEvent, BlockGraph, BlockId, EventCallback, LinkEvent, Location,
+    NodeChainInfo, NodeEvent, NodeIndex, ObjectId, Simulation, SimulationSpeed, StatsEventCallback,
+    Time,
+};
+
+/// Everything the visualizer needs from a simulation
+///
+/// This allows visualizing simulations that do not run in the same process.
+/// The visualizer only relies on events and a few queries,
+/// so an implementation can forward these over the network (see `RemoteSimulation`).
+pub trait SimulationHandle: Send + Sync {
+    fn set_block_event_callback(&self, callback: EventCallback<BlockId, BlockEvent>);
+
+    fn set_node_event_callback(&self, callback: EventCallback<NodeIndex, NodeEvent>);
+
+    fn set_link_event_callback(&self, callback: EventCallback<ObjectId, LinkEvent>);
+
+    fn set_stats_event_callback(&self, callback: StatsEventCallback);
+
+    fn get_current_time(&self) -> Time;
+
+    fn get_node_location(&self, node_index: NodeIndex) -> Location;
+
+    /// Returns `None` for protocols without a chain
+    fn get_node_chain_info(&self, node_index: NodeIndex) -> Option<NodeChainInfo>;
+
+    fn get_speed(&self) -> SimulationSpeed;
+
+    fn set_rate_limit(&self, rate_limit: u32);
+
+    fn get_rate_limit(&self) -> Option<u32>;
+
+    /// Returns the rate limit (if any) as a factor
+    fn get_rate_limit_f64(&self) -> Option<f64>;
+
+    /// Pause once the specified (virtual) time is reached
+    fn pause_at(&self, time: Time);
+
+    fn get_block_graph(&self) -> BlockGraph;
+
+    /// Returns `None` if there is no such block
+    fn get_block_details(&self, block_id: BlockId) -> Option<BlockDetails>;
+}
+
+/// A simulation running in the same process
+impl SimulationHandle for Simulation {
+    fn set_block_event_callback(&self, callback: EventCallback<BlockId, BlockEvent>) {
+        Simulation::set_block_event_callback(self, callback)
+    }
+
+    fn set_node_event_callback(&self, callback: EventCallback<NodeIndex, NodeEvent>) {
+        Simulation::set_node_event_callback(self, callback)
+    }
+
+    fn set_link_event_callback(&self, callback: EventCallback<ObjectId, LinkEvent>) {
+        Simulation::set_link_event_callback(self, callback)
+    }
+
+    fn set_stats_event_callback(&self, callback: StatsEventCallback) {
+        Simulation::set_stats_event_callback(self, callback)
+    }
+
+    fn get_current_time(&self) -> Time {
+        Simulation::get_current_time(self)
+    }
+
+    fn get_node_location(&self, node_index: NodeIndex) -> Location {
+        Simulation::get_node_location(self, node_index)
+    }
+
+    fn get_node_chain_info(&self, node_index: NodeIndex) -> Option<NodeChainInfo> {
+        Simulation::get_node_chain_info(self, node_index)
+    }
+
+    fn get_speed(&self) -> SimulationSpeed {
+        Simulation::get_speed(self)
+    }
+
+    fn set_rate_limit(&self, rate_limit: u32) {
+        Simulation::set_rate_limit(self, rate_limit)
+    }
+
+    fn get_rate_limit(&self) -> Option<u32> {
+        Simulation::get_rate_limit(self)
+    }
+
+    fn get_rate_limit_f64(&self) -> Option<f64> {
+        Simulation::get_rate_limit_f64(self)
+    }
+
+    fn pause_at(&self, time: Time) {
+        Simulation::pause_at(self, time)
+    }
+
+    fn get_block_graph(&self) -> BlockGraph {
+        Simulation::get_block_graph(self)
+    }
+
+    fn get_block_details(&self, block_id: BlockId) -> Option<BlockDetails> {
+        Simulation::get_block_details(self, block_id)
+    }
+}
@@ -0,0 +1,158 @@
+//! Keyboard navigation, screen reader support, and high-contrast colors for the UI
+
+use iced::theme::Palette;
+use iced::{Border, Color, Theme};
+
+/// Controls that can be reached with the keyboard, in the order Tab visits them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UiControl {
+    ViewSelector,
+    ExportBlocks,
+    ExportStatisticsCsv,
+    ExportStatisticsJson,
+    Recording,
+    HighContrast,
+    DecreaseSpeed,
+    IncreaseSpeed,
+    PauseTime,
+    SetBreakpoint,
+}
+
+impl UiControl {
+    const ALL: [Self; 10] = [
+        Self::ViewSelector,
+        Self::ExportBlocks,
+        Self::ExportStatisticsCsv,
+        Self::ExportStatisticsJson,
+        Self::Recording,
+        Self::HighContrast,
+        Self::DecreaseSpeed,
+        Self::IncreaseSpeed,
+        Self::PauseTime,
+        Self::SetBreakpoint,
+    ];
+
+    /// Describes the control for screen readers
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            Self::ViewSelector => "View selector. Use the arrow keys to switch views",
+            Self::ExportBlocks => "Export blocks button",
+            Self::ExportStatisticsCsv => "Export statistics as CSV button",
+            Self::ExportStatisticsJson => "Export statistics as JSON button",
+            Self::Recording => "Record animation button",
+            Self::DecreaseSpeed => "Decrease simulation speed button",
+            Self::IncreaseSpeed => "Increase simulation speed button",
+            Self::PauseTime => "Pause time in seconds. Type digits, then press Enter",
+            Self::SetBreakpoint => "Set pause time button",
+            Self::HighContrast => "High contrast colors toggle",
+        }
+    }
+
+    /// The control after (or before) this one, skipping controls that are not shown
+    /// Starts at the first (or last) control if nothing is focused
+    pub fn next(
+        current: Option<Self>,
+        backwards: bool,
+        is_shown: impl Fn(&Self) -> bool,
+    ) -> Option<Self> {
+        let len = Self::ALL.len();
+        let start = match current {
+            Some(control) => Self::ALL
+                .iter()
+                .position(|other| *other == control)
+                .unwrap(),
+            None if backwards => 0,
+            None => len - 1,
+        };
+
+        (1..=len)
+            .map(|offset| {
+                if backwards {
+                    Self::ALL[(start + len - offset) % len]
+                } else {
+                    Self::ALL[(start + offset) % len]
+                }
+            })
+            .find(|control| is_shown(control))
+    }
+}
+
+/// Strong colors on a black background
+pub fn make_high_contrast_theme() -> Theme {
+    Theme::custom("High Contrast".to_string(), Palette {
+        background: Color::BLACK,
+        text: Color::WHITE,
+        primary: Color::from_rgb(1.0, 1.0, 0.0),
+        success: Color::from_rgb(0.0, 1.0, 0.0),
+        danger: Color::from_rgb(1.0, 0.3, 0.3),
+    })
+}
+
+/// The outline drawn around the control that has keyboard focus
+pub fn get_focus_border(high_contrast: bool) -> Border {
+    let color = if high_contrast {
+        Color::WHITE
+    } else {
+        Color::from_rgb(0.0, 0.3, 0.9)
+    };
+
+    Border {
+        color,
+        width: 3.0,
+        radius: 3.0.into(),
+    }
+}
+
+/// Tells screen readers about a change, e.g., which control gained focus
+///
+/// Writes to a visually hidden live region next to the canvas,
+/// as the canvas itself is opaque to assistive technology.
+#[cfg(target_arch = "wasm32")]
+pub fn announce(text: &str) {
+    const REGION_ID: &str = "simba-announcements";
+
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        log::error!("Cannot access the html document");
+        return;
+    };
+
+    let region = match document.get_element_by_id(REGION_ID) {
+        Some(region) => region,
+        None => {
+            let Ok(region) = document.create_element("div") else {
+                log::error!("Failed to create live region");
+                return;
+            };
+
+            let attributes = [
+                ("id", REGION_ID),
+                ("role", "status"),
+                ("aria-live", "polite"),
+                (
+                    "style",
+                    "position: absolute; left: -10000px; width: 1px; height: 1px; overflow: hidden;",
+                ),
+            ];
+            for (name, value) in attributes {
+                if let Err(err) = region.set_attribute(name, value) {
+                    log::error!("Failed to set up live region: {err:?}");
+                }
+            }
+
+            if let Some(body) = document.body()
+                && let Err(err) = body.append_child(&region)
+            {
+                log::error!("Failed to add live region: {err:?}");
+            }
+            region
+        }
+    };
+
+    region.set_text_content(Some(text));
+}
+
+/// There is no screen reader bridge for native windows yet
+#[cfg(not(target_arch = "wasm32"))]
+pub fn announce(text: &str) {
+    log::debug!("Announcing \"{text}\"");
+}
@@ -1,20 +1,25 @@
-use crate::scene::{SceneManager, ViewType};
-use crate::ui::{ObjectPropertyMap, Statistics, UiMessage, UiMessages};
+use crate::recording::Recorder;
+use crate::scene::{LegendEntry, LegendShape, SceneManager, ViewType};
+use crate::ui::accessibility::{self, UiControl};
+use crate::ui::{ObjectPropertyMap, Statistics, StatisticsFormat, UiMessage, UiMessages};
 
 use std::sync::Arc;
 
 use tokio::sync::mpsc;
 
 use iced::alignment;
+use iced::keyboard::{Key, key};
 use iced::widget::pick_list;
-use iced::widget::{Button, Column, Row, Space, Text};
-use iced::{Length, Theme};
+use iced::widget::{Button, Column, Container, Row, Space, Text, TextInput, container};
+use iced::{Color, Length, Theme};
 //use iced_aw::Card;
 use iced_runtime::program::Program;
 
-use simba::{GlobalStatistics, Simulation, StatisticsEvent};
+use simba::{Anomaly, BlockDetails, GlobalStatistics, NumberFormat, StatisticsEvent, Time};
 
-use crate::spawn_task;
+use crate::simulation::SimulationHandle;
+
+use crate::{export, spawn_task};
 
 type UiElement<'a> = iced::Element<'a, UiMessage, Theme, iced_wgpu::Renderer>;
 
@@ -24,29 +29,45 @@ struct SelectedObject {
 }
 
 pub struct UiLogic {
-    simulation: Arc<Simulation>,
+    simulation: Arc<dyn SimulationHandle>,
     scene_manager: Arc<SceneManager>,
+    statistics: Arc<Statistics>,
+    recorder: Arc<Recorder>,
 
     /// State
     selected_view: Option<ViewType>,
     selected_object: Option<SelectedObject>,
+    /// Transactions and related blocks of the selected block (if any)
+    block_details: Option<BlockDetails>,
     global_stats: GlobalStatistics,
+    /// Unusual moments of the run so far (oldest first)
+    anomalies: Vec<(Time, Anomaly)>,
+    /// How numbers are shown (based on the user's locale)
+    number_format: NumberFormat,
+    /// Contents of the breakpoint input (in seconds)
+    pause_time: String,
+    breakpoint: Option<Time>,
+    /// The control that has keyboard focus (if any)
+    focus: Option<UiControl>,
+    high_contrast: bool,
 }
 
 impl UiLogic {
     pub fn new(
-        simulation: Arc<Simulation>,
+        simulation: Arc<dyn SimulationHandle>,
         scene_manager: Arc<SceneManager>,
         ui_messages: Arc<UiMessages>,
+        recorder: Arc<Recorder>,
     ) -> Self {
-        let stats_observer = Arc::new(Statistics::new(ui_messages, simulation.clone()));
+        let statistics = Arc::new(Statistics::new(ui_messages, scene_manager.clone()));
+        let stats_observer = statistics.clone();
 
         let (stats_event_sender, mut stats_event_receiver) = mpsc::unbounded_channel();
 
         spawn_task(async move {
             while let Some(event) = stats_event_receiver.recv().await {
-                assert_eq!(event, StatisticsEvent::Updated);
-                stats_observer.notify_updated();
+                let StatisticsEvent::Updated(snapshot) = event;
+                stats_observer.notify_updated(snapshot);
             }
         });
 
@@ -60,8 +81,269 @@ impl UiLogic {
             simulation,
             selected_view: Some(scene_manager.get_active_scene_type()),
             scene_manager,
+            statistics,
+            recorder,
             global_stats: Default::default(),
+            anomalies: Default::default(),
             selected_object: None,
+            block_details: None,
+            number_format: NumberFormat::from_env(),
+            pause_time: String::new(),
+            breakpoint: None,
+            focus: None,
+            high_contrast: false,
+        }
+    }
+
+    pub fn get_theme(&self) -> Theme {
+        if self.high_contrast {
+            accessibility::make_high_contrast_theme()
+        } else {
+            Theme::Light
+        }
+    }
+
+    /// The default color of text
+    pub fn get_text_color(&self) -> Color {
+        if self.high_contrast {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        }
+    }
+
+    /// Does any control have keyboard focus?
+    /// If so, keys go to that control instead of moving the camera.
+    pub fn has_focus(&self) -> bool {
+        self.focus.is_some()
+    }
+
+    /// Draws an outline around the control if it has keyboard focus
+    fn focusable<'a>(
+        &self,
+        control: UiControl,
+        element: impl Into<UiElement<'a>>,
+    ) -> UiElement<'a> {
+        let border = if self.focus == Some(control) {
+            accessibility::get_focus_border(self.high_contrast)
+        } else {
+            Default::default()
+        };
+
+        Container::new(element)
+            .padding(2)
+            .style(move |_| container::Style {
+                border,
+                ..Default::default()
+            })
+            .into()
+    }
+
+    /// Gives panels a solid background, so that their text does not blend into the scene
+    fn panel<'a>(&self, element: impl Into<UiElement<'a>>) -> UiElement<'a> {
+        if !self.high_contrast {
+            return element.into();
+        }
+
+        Container::new(element)
+            .padding(5)
+            .style(|_| container::Style {
+                background: Some(Color::BLACK.into()),
+                border: iced::border::width(2).color(Color::WHITE),
+                ..Default::default()
+            })
+            .into()
+    }
+
+    fn is_shown(control: &UiControl) -> bool {
+        *control != UiControl::Recording || Recorder::is_supported()
+    }
+
+    fn move_focus(&mut self, backwards: bool) {
+        self.focus = UiControl::next(self.focus, backwards, Self::is_shown);
+
+        if let Some(control) = self.focus {
+            accessibility::announce(control.get_label());
+        }
+    }
+
+    /// What pressing Enter or Space does for the focused control
+    fn get_activation_message(&self, control: UiControl) -> Option<UiMessage> {
+        let message = match control {
+            UiControl::ViewSelector => return None,
+            UiControl::ExportBlocks => UiMessage::ExportBlockGraph,
+            UiControl::ExportStatisticsCsv => UiMessage::ExportStatistics(StatisticsFormat::Csv),
+            UiControl::ExportStatisticsJson => UiMessage::ExportStatistics(StatisticsFormat::Json),
+            UiControl::Recording => UiMessage::ToggleRecording,
+            UiControl::DecreaseSpeed => UiMessage::DecreaseSpeed,
+            UiControl::IncreaseSpeed => UiMessage::IncreaseSpeed,
+            UiControl::PauseTime | UiControl::SetBreakpoint => UiMessage::SetBreakpoint,
+            UiControl::HighContrast => UiMessage::SetHighContrast(!self.high_contrast),
+        };
+        Some(message)
+    }
+
+    /// Handles keys other than Tab, Enter, Space, and Escape for the focused control
+    fn handle_focused_key(&mut self, key: Key) {
+        match (self.focus, key) {
+            (Some(UiControl::ViewSelector), Key::Named(named)) => {
+                let backwards = match named {
+                    key::Named::ArrowUp | key::Named::ArrowLeft => true,
+                    key::Named::ArrowDown | key::Named::ArrowRight => false,
+                    _ => return,
+                };
+
+                let view_types = self.scene_manager.get_view_types();
+                let Some(position) = self
+                    .selected_view
+                    .and_then(|current| view_types.iter().position(|other| *other == current))
+                else {
+                    return;
+                };
+
+                let len = view_types.len();
+                let view_type = if backwards {
+                    view_types[(position + len - 1) % len]
+                } else {
+                    view_types[(position + 1) % len]
+                };
+
+                self.scene_manager.set_active_scene(view_type);
+                self.selected_view = Some(view_type);
+                accessibility::announce(&format!("{view_type} view"));
+            }
+            (Some(UiControl::PauseTime), Key::Named(key::Named::Backspace)) => {
+                self.pause_time.pop();
+            }
+            (Some(UiControl::PauseTime), Key::Character(c)) => {
+                if c.chars().all(|c| c.is_ascii_digit()) {
+                    self.pause_time.push_str(&c);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// How many anomalies are shown in the timeline at most
+    const MAX_LISTED_ANOMALIES: usize = 10;
+
+    /// How many transactions of the selected block are listed at most
+    const MAX_LISTED_TRANSACTIONS: usize = 20;
+
+    /// Shows the miner and transactions of a block, and allows jumping to related blocks
+    fn view_block_details(details: &BlockDetails) -> UiElement {
+        let mut content = Column::new().spacing(5);
+
+        if let Some(miner) = details.miner {
+            content = content.push(Text::new(format!("Miner: {miner:X}")));
+        }
+
+        let mut links = Row::new().spacing(5);
+        if let Some(parent) = details.parent {
+            links = links.push(Button::new("Parent").on_press(UiMessage::JumpToBlock(parent)));
+        }
+        for (idx, uncle) in details.uncles.iter().enumerate() {
+            links = links.push(
+                Button::new(Text::new(format!("Uncle {}", idx + 1)))
+                    .on_press(UiMessage::JumpToBlock(*uncle)),
+            );
+        }
+        content = content.push(links);
+
+        content = content.push(Text::new(format!(
+            "Transactions ({}):",
+            details.transactions.len()
+        )));
+        for (txn_id, size) in details
+            .transactions
+            .iter()
+            .take(Self::MAX_LISTED_TRANSACTIONS)
+        {
+            content = content.push(Text::new(format!("{txn_id:X} ({size} bytes)")));
+        }
+
+        let num_hidden = details
+            .transactions
+            .len()
+            .saturating_sub(Self::MAX_LISTED_TRANSACTIONS);
+        if num_hidden > 0 {
+            content = content.push(Text::new(format!("... and {num_hidden} more")));
+        }
+
+        content.into()
+    }
+
+    /// A colored swatch in the shape of the object, followed by its meaning
+    fn view_legend_entry<'a>(entry: LegendEntry) -> UiElement<'a> {
+        let (width, height, radius) = match entry.shape {
+            LegendShape::Circle => (12.0, 12.0, 6.0),
+            LegendShape::Square => (12.0, 12.0, 0.0),
+            LegendShape::Line => (16.0, 3.0, 0.0),
+        };
+
+        let color = entry.color.into_iced();
+        let swatch = Container::new(Space::new(Length::Fixed(width), Length::Fixed(height))).style(
+            move |_| container::Style {
+                background: Some(color.into()),
+                border: iced::border::rounded(radius),
+                ..Default::default()
+            },
+        );
+
+        Row::new()
+            .spacing(5)
+            .align_y(alignment::Vertical::Center)
+            .push(Container::new(swatch).width(Length::Fixed(20.0)))
+            .push(Text::new(entry.label))
+            .into()
+    }
+
+    /// The name of the exported block graph
+    const BLOCK_GRAPH_FILE: &'static str = "blocks.dot";
+
+    /// The names of the exported statistics history
+    const STATISTICS_CSV_FILE: &'static str = "statistics.csv";
+    const STATISTICS_JSON_FILE: &'static str = "statistics.json";
+
+    /// The name of the recorded animation
+    const RECORDING_FILE: &'static str = "simulation.gif";
+
+    fn export_block_graph(&self) {
+        let dot = self.simulation.get_block_graph().to_dot();
+
+        if let Err(err) = export::save_file(Self::BLOCK_GRAPH_FILE, &dot) {
+            log::error!("Failed to export block graph: {err:?}");
+        }
+    }
+
+    fn export_statistics(&self, format: StatisticsFormat) {
+        let (name, content) = match format {
+            StatisticsFormat::Csv => (Self::STATISTICS_CSV_FILE, self.statistics.to_csv()),
+            StatisticsFormat::Json => (Self::STATISTICS_JSON_FILE, self.statistics.to_json()),
+        };
+
+        if let Err(err) = export::save_file(name, &content) {
+            log::error!("Failed to export statistics: {err:?}");
+        }
+    }
+
+    /// Starts a new recording, or saves the current one
+    fn toggle_recording(&self) {
+        if !self.recorder.is_recording() {
+            self.recorder.start();
+            return;
+        }
+
+        let result = self.recorder.stop().and_then(|recording| match recording {
+            Some(data) => export::save_file(Self::RECORDING_FILE, data),
+            None => {
+                log::warn!("Recording contains no frames");
+                Ok(())
+            }
+        });
+
+        if let Err(err) = result {
+            log::error!("Failed to save recording: {err:?}");
         }
     }
 }
@@ -78,35 +360,99 @@ impl Program for UiLogic {
 
         // Allows switching between views
         let view_picker = {
-            let pick_list = pick_list::PickList::new(
-                &ViewType::ALL[..],
-                self.selected_view,
-                UiMessage::ViewSelected,
+            let pick_list = self.focusable(
+                UiControl::ViewSelector,
+                pick_list::PickList::new(
+                    self.scene_manager.get_view_types(),
+                    self.selected_view,
+                    UiMessage::ViewSelected,
+                ),
             );
 
             //Card::new(Text::new("View"), pick_list).width(Length::Fixed(150.0))
 
-            Column::new().push(Text::new("View")).push(pick_list)
+            let export_blocks = self.focusable(
+                UiControl::ExportBlocks,
+                Button::new("Export Blocks").on_press(UiMessage::ExportBlockGraph),
+            );
+            let export_csv = self.focusable(
+                UiControl::ExportStatisticsCsv,
+                Button::new("Export Statistics (CSV)")
+                    .on_press(UiMessage::ExportStatistics(StatisticsFormat::Csv)),
+            );
+            let export_json = self.focusable(
+                UiControl::ExportStatisticsJson,
+                Button::new("Export Statistics (JSON)")
+                    .on_press(UiMessage::ExportStatistics(StatisticsFormat::Json)),
+            );
+            let high_contrast = self.focusable(
+                UiControl::HighContrast,
+                Button::new(if self.high_contrast {
+                    "High Contrast: On"
+                } else {
+                    "High Contrast: Off"
+                })
+                .on_press(UiMessage::SetHighContrast(!self.high_contrast)),
+            );
+
+            let column = Column::new()
+                .spacing(5)
+                .push(Text::new("View"))
+                .push(pick_list)
+                .push(export_blocks)
+                .push(export_csv)
+                .push(export_json);
+
+            // Frames can only be sampled in the browser
+            let column = if Recorder::is_supported() {
+                let label = if self.recorder.is_recording() {
+                    format!("Stop Recording ({} frames)", self.recorder.num_frames())
+                } else {
+                    "Record GIF".to_string()
+                };
+                column.push(self.focusable(
+                    UiControl::Recording,
+                    Button::new(Text::new(label)).on_press(UiMessage::ToggleRecording),
+                ))
+            } else {
+                column
+            };
+
+            let column = column.push(high_contrast);
+
+            // Tells keyboard users what the focused control does
+            let column = match self.focus {
+                Some(control) => column.push(Text::new(format!("Focus: {}", control.get_label()))),
+                None => column.push(Text::new("Press Tab to use the keyboard")),
+            };
+
+            self.panel(column.width(Length::Fixed(250.0)))
         };
 
         // Allows changing simulation speed
         let speed_controls = {
-            let time_text =
-                Text::new(format!("Elapsed Time: {time}")).align_y(alignment::Vertical::Center);
+            let time_text = Text::new(format!("Elapsed Time: {}", self.number_format.time(time)))
+                .align_y(alignment::Vertical::Center);
             let speed = if let Some(rate_limit) = self.simulation.get_rate_limit_f64() {
                 format!("{rate_limit}x")
             } else {
                 "max".to_string()
             };
             let speed_text = Text::new(speed).align_y(alignment::Vertical::Center);
-            let slower_button = Button::new("<")
-                .width(Length::Fixed(30.0))
-                .padding(0)
-                .on_press(UiMessage::DecreaseSpeed);
-            let faster_button = Button::new(">")
-                .width(Length::Fixed(30.0))
-                .padding(0)
-                .on_press(UiMessage::IncreaseSpeed);
+            let slower_button = self.focusable(
+                UiControl::DecreaseSpeed,
+                Button::new("<")
+                    .width(Length::Fixed(30.0))
+                    .padding(0)
+                    .on_press(UiMessage::DecreaseSpeed),
+            );
+            let faster_button = self.focusable(
+                UiControl::IncreaseSpeed,
+                Button::new(">")
+                    .width(Length::Fixed(30.0))
+                    .padding(0)
+                    .on_press(UiMessage::IncreaseSpeed),
+            );
 
             let controls = Row::new()
                 .spacing(5)
@@ -114,7 +460,44 @@ impl Program for UiLogic {
                 .push(slower_button)
                 .push(speed_text)
                 .push(faster_button);
-            let content = Column::new().spacing(5).push(time_text).push(controls);
+            let measured_speed = self.simulation.get_speed();
+            let measured_speed_text = Text::new(format!(
+                "Actual Speed: {:.2}x ({:.0} events/s)",
+                measured_speed.speedup, measured_speed.events_per_second
+            ));
+
+            let pause_input = self.focusable(
+                UiControl::PauseTime,
+                TextInput::new("seconds", &self.pause_time)
+                    .width(Length::Fixed(100.0))
+                    .on_input(UiMessage::PauseTimeChanged)
+                    .on_submit(UiMessage::SetBreakpoint),
+            );
+            let pause_button = self.focusable(
+                UiControl::SetBreakpoint,
+                Button::new("Set").on_press(UiMessage::SetBreakpoint),
+            );
+            let breakpoint_text = match self.breakpoint {
+                Some(breakpoint) if breakpoint > time => {
+                    format!("Will pause at {}", self.number_format.time(breakpoint))
+                }
+                Some(breakpoint) => format!("Paused at {}", self.number_format.time(breakpoint)),
+                None => String::new(),
+            };
+
+            let pause_controls = Row::new()
+                .spacing(5)
+                .push(Text::new("Pause at: "))
+                .push(pause_input)
+                .push(pause_button);
+
+            let content = Column::new()
+                .spacing(5)
+                .push(time_text)
+                .push(controls)
+                .push(measured_speed_text)
+                .push(pause_controls)
+                .push(Text::new(breakpoint_text));
 
             //Card::new(Text::new("Simulation"), content)
 
@@ -125,32 +508,58 @@ impl Program for UiLogic {
             let header = Text::new("Global Statistics");
 
             let stats = &self.global_stats;
+            // Network traffic is measured in bytes per second
             let content = Text::new(format!(
-                "Bandwidth Usage {:.3} Mbit/s",
-                (stats.network_traffic as f64) / (1024.0 * 1024.0)
+                "Bandwidth Usage {}",
+                self.number_format
+                    .bitrate((stats.network_traffic as f64) * 8.0)
             ));
 
             Column::new().push(header).push(content)
             //Card::new(header, content)
         };
 
+        // The most recent anomalies, newest first
+        let timeline = self
+            .anomalies
+            .iter()
+            .rev()
+            .take(Self::MAX_LISTED_ANOMALIES)
+            .fold(
+                Column::new().spacing(5).push(Text::new("Timeline")),
+                |timeline, (time, anomaly)| {
+                    timeline.push(Text::new(format!(
+                        "{}: {anomaly}",
+                        self.number_format.time(*time)
+                    )))
+                },
+            );
+
+        // Explains the colors of the active view
+        let legend = self.scene_manager.get_legend().into_iter().fold(
+            Column::new().spacing(5).push(Text::new("Legend")),
+            |legend, entry| legend.push(Self::view_legend_entry(entry)),
+        );
+
         // The UI elements on the right showing more info
         let cards = Column::new()
             .spacing(10)
             .width(Length::Fixed(400.0))
             .push(speed_controls)
-            .push(global_stats);
+            .push(global_stats)
+            .push(timeline)
+            .push(legend);
 
         // Add info about the selected object (if any)
         let cards = if let Some(SelectedObject { name, properties }) = &self.selected_object {
             let mut content = Column::new();
             for (name, (value, unit)) in properties {
-                if let Some(unit) = unit {
-                    content =
-                        content.push(Text::new(format!("{name} = {value} {}", unit.get_suffix())));
-                } else {
-                    content = content.push(Text::new(format!("{name} = {value}")));
-                }
+                let value = value.format(unit.as_ref(), &self.number_format);
+                content = content.push(Text::new(format!("{name} = {value}")));
+            }
+
+            if let Some(details) = &self.block_details {
+                content = content.push(Self::view_block_details(details));
             }
 
             let selected_card = Column::new().push(Text::new(name)).push(content);
@@ -159,6 +568,7 @@ impl Program for UiLogic {
         } else {
             cards
         };
+        let cards = self.panel(cards);
 
         Row::new()
             .width(Length::Fill)
@@ -192,10 +602,21 @@ impl Program for UiLogic {
             }
             UiMessage::ObjectUnselected => {
                 self.selected_object = None;
+                self.block_details = None;
+            }
+            UiMessage::BlockSelected(block_id) => {
+                self.block_details = self.simulation.get_block_details(block_id);
+            }
+            UiMessage::JumpToBlock(block_id) => {
+                self.scene_manager.select_block(&block_id);
             }
             UiMessage::UpdateGlobalStatistics(stats) => {
                 self.global_stats = stats;
             }
+            UiMessage::AnomaliesDetected(time, anomalies) => {
+                self.anomalies
+                    .extend(anomalies.into_iter().map(|anomaly| (time, anomaly)));
+            }
             UiMessage::IncreaseSpeed => {
                 let rate_limit = if let Some(current) = self.simulation.get_rate_limit() {
                     if current < 1000 {
@@ -209,6 +630,48 @@ impl Program for UiLogic {
 
                 self.simulation.set_rate_limit(rate_limit);
             }
+            UiMessage::PauseTimeChanged(pause_time) => {
+                self.pause_time = pause_time;
+            }
+            UiMessage::SetBreakpoint => match self.pause_time.trim().parse::<u64>() {
+                Ok(seconds) => {
+                    let time = Time::from_seconds(seconds);
+                    self.simulation.pause_at(time);
+                    self.breakpoint = Some(time);
+                }
+                Err(err) => {
+                    log::warn!("Invalid pause time \"{}\": {err}", self.pause_time);
+                }
+            },
+            UiMessage::ExportBlockGraph => {
+                self.export_block_graph();
+            }
+            UiMessage::ExportStatistics(format) => {
+                self.export_statistics(format);
+            }
+            UiMessage::ToggleRecording => {
+                self.toggle_recording();
+            }
+            UiMessage::SetHighContrast(high_contrast) => {
+                self.high_contrast = high_contrast;
+            }
+            UiMessage::FocusNext => self.move_focus(false),
+            UiMessage::FocusPrevious => self.move_focus(true),
+            UiMessage::ClearFocus => {
+                self.focus = None;
+                accessibility::announce("Keyboard controls the camera");
+            }
+            UiMessage::ActivateFocused => {
+                if let Some(message) = self
+                    .focus
+                    .and_then(|control| self.get_activation_message(control))
+                {
+                    return self.update(message);
+                }
+            }
+            UiMessage::FocusedKeyPressed(key) => {
+                self.handle_focused_key(key);
+            }
             UiMessage::DecreaseSpeed => {
                 let rate_limit = if let Some(current) = self.simulation.get_rate_limit() {
                     if current <= 100 {
@@ -7,10 +7,12 @@ pub use logic::UiLogic;
 mod statistics;
 pub use statistics::Statistics;
 
+mod accessibility;
+
 use std::collections::HashMap;
 use std::sync::Mutex as StdMutex;
 
-use simba::GlobalStatistics;
+use simba::{Anomaly, BlockId, GlobalStatistics, NumberFormat, Time};
 
 use winit::dpi::PhysicalPosition;
 
@@ -42,10 +44,16 @@ pub enum ObjectPropertyUnit {
     BitsPerSecond,
 }
 
-impl ObjectPropertyUnit {
-    fn get_suffix(&self) -> &str {
-        match self {
-            Self::BitsPerSecond => "bits/s",
+impl ObjectPropertyValue {
+    /// Formats the value for display, abbreviating large numbers
+    pub fn format(&self, unit: Option<&ObjectPropertyUnit>, format: &NumberFormat) -> String {
+        match (self, unit) {
+            (Self::Int(i), Some(ObjectPropertyUnit::BitsPerSecond)) => format.bitrate(*i as f64),
+            (Self::Float(f), Some(ObjectPropertyUnit::BitsPerSecond)) => format.bitrate(*f),
+            (Self::Int(i), None) if *i < 0 => format!("-{}", format.integer(i.unsigned_abs())),
+            (Self::Int(i), None) => format.integer(*i as u64),
+            (Self::Float(f), None) => format.decimal(*f, 2),
+            (value, _) => value.to_string(),
         }
     }
 }
@@ -83,9 +91,45 @@ pub enum UiMessage {
         properties: ObjectPropertyMap,
     },
     ObjectUnselected,
+    /// Show the transactions of this block (sent in addition to `ObjectSelected`)
+    BlockSelected(BlockId),
+    /// Select another block, e.g., the parent of the current one
+    JumpToBlock(BlockId),
     UpdateGlobalStatistics(GlobalStatistics),
+    /// Unusual moments found in the latest statistics
+    AnomaliesDetected(Time, Vec<Anomaly>),
     IncreaseSpeed,
     DecreaseSpeed,
+    /// The user edited the breakpoint input (in seconds)
+    PauseTimeChanged(String),
+    /// Pause the simulation at the time entered by the user
+    SetBreakpoint,
+    /// Save all blocks (see `UiLogic::BLOCK_GRAPH_FILE`)
+    ExportBlockGraph,
+    /// Save the statistics history in the given format
+    ExportStatistics(StatisticsFormat),
+    /// Start recording the network view, or save the recording (see `UiLogic::RECORDING_FILE`)
+    ToggleRecording,
+    /// Switch between the regular and the high-contrast theme
+    SetHighContrast(bool),
+    /// Move keyboard focus to the next control (Tab)
+    FocusNext,
+    /// Move keyboard focus to the previous control (Shift+Tab)
+    FocusPrevious,
+    /// Give the keyboard back to the camera (Escape)
+    ClearFocus,
+    /// Press the focused control (Enter or Space)
+    ActivateFocused,
+    /// Any other key while a control has focus
+    FocusedKeyPressed(iced::keyboard::Key),
+}
+
+/// The file formats the statistics history can be exported to
+#[derive(Clone, Copy, Debug)]
+pub enum StatisticsFormat {
+    /// The same format as `--log-stats`
+    Csv,
+    Json,
 }
 
 impl UiMessages {
@@ -98,4 +142,8 @@ impl UiMessages {
         let mut lock = self.inner.lock().unwrap();
         lock.push(msg);
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().is_empty()
+    }
 }
@@ -3,7 +3,7 @@ use std::sync::Mutex as StdMutex;
 
 use winit::dpi::PhysicalPosition;
 
-use iced::keyboard::{Event as KeyboardEvent, Key, key};
+use iced::keyboard::{Event as KeyboardEvent, Key, Modifiers, key};
 use iced::mouse::{
     Button as MouseButton, Cursor, Event as MouseEvent, ScrollDelta as MouseScrollDelta,
 };
@@ -12,12 +12,13 @@ use iced_runtime::{Debug, program};
 use iced_wgpu::graphics::Viewport;
 use iced_winit::conversion;
 
-use simba::Simulation;
+use crate::simulation::SimulationHandle;
 
 use crate::graphics::Geometry;
 use crate::graphics::{InputDirection, Renderer};
+use crate::recording::Recorder;
 use crate::scene::SceneManager;
-use crate::ui::{CursorPosition, UiEvents, UiLogic, UiMessages};
+use crate::ui::{CursorPosition, UiEvents, UiLogic, UiMessage, UiMessages};
 
 pub struct UiRenderLoop {
     renderer: Arc<Renderer>,
@@ -39,8 +40,9 @@ impl UiRenderLoop {
         events: Arc<UiEvents>,
         cursor_position: Arc<CursorPosition>,
         window: Arc<winit::window::Window>,
-        simulation: Arc<Simulation>,
+        simulation: Arc<dyn SimulationHandle>,
         scene_manager: Arc<SceneManager>,
+        recorder: Arc<Recorder>,
     ) -> Self {
         let clipboard = iced_winit::Clipboard::connect(window);
         let viewport = {
@@ -65,7 +67,12 @@ impl UiRenderLoop {
 
         let mut debug = Debug::new();
 
-        let ui_logic = UiLogic::new(simulation, scene_manager.clone(), messages.clone());
+        let ui_logic = UiLogic::new(
+            simulation,
+            scene_manager.clone(),
+            messages.clone(),
+            recorder,
+        );
 
         let state = program::State::new(
             ui_logic,
@@ -87,6 +94,11 @@ impl UiRenderLoop {
         }
     }
 
+    /// Are there events or messages the UI has not processed yet?
+    pub fn has_pending_input(&self) -> bool {
+        !self.events.lock().unwrap().is_empty() || !self.messages.is_empty()
+    }
+
     pub async fn update_and_draw(
         &mut self,
         geometry: Geometry,
@@ -112,6 +124,8 @@ impl UiRenderLoop {
             }
 
             let cursor_position = *self.cursor_position.lock().unwrap();
+            let theme = self.state.program().get_theme();
+            let text_color = self.state.program().get_text_color();
 
             let (uncaught_events, _) = self.state.update(
                 viewport.logical_size(),
@@ -120,10 +134,8 @@ impl UiRenderLoop {
                     geometry.scale_factor,
                 )),
                 &mut self.ui_renderer,
-                &iced::Theme::Light,
-                &iced_core::renderer::Style {
-                    text_color: iced::Color::BLACK,
-                },
+                &theme,
+                &iced_core::renderer::Style { text_color },
                 &mut self.clipboard,
                 &mut debug,
             );
@@ -203,8 +215,14 @@ impl UiRenderLoop {
                 }
             }
             Event::Keyboard(keyboard_event) => match keyboard_event {
-                KeyboardEvent::KeyPressed { key, .. } => {
-                    if let Some(dir) = Self::to_direction(&key) {
+                KeyboardEvent::KeyPressed { key, modifiers, .. } => {
+                    let has_focus = self.state.program().has_focus();
+
+                    if let Some(message) = Self::to_focus_message(&key, modifiers, has_focus) {
+                        self.messages.push(message);
+                    } else if has_focus {
+                        self.messages.push(UiMessage::FocusedKeyPressed(key));
+                    } else if let Some(dir) = Self::to_direction(&key) {
                         let camera = self.scene_manager.get_active_camera();
                         camera.notify_button_pressed(dir);
                     }
@@ -221,6 +239,19 @@ impl UiRenderLoop {
         }
     }
 
+    /// Keys that move keyboard focus between controls or press the focused control
+    fn to_focus_message(key: &Key, modifiers: Modifiers, has_focus: bool) -> Option<UiMessage> {
+        match key {
+            Key::Named(key::Named::Tab) if modifiers.shift() => Some(UiMessage::FocusPrevious),
+            Key::Named(key::Named::Tab) => Some(UiMessage::FocusNext),
+            Key::Named(key::Named::Enter | key::Named::Space) if has_focus => {
+                Some(UiMessage::ActivateFocused)
+            }
+            Key::Named(key::Named::Escape) if has_focus => Some(UiMessage::ClearFocus),
+            _ => None,
+        }
+    }
+
     fn to_direction(key: &Key) -> Option<InputDirection> {
         match key {
             Key::Character(c) => match c.as_str() {
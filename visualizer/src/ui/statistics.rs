@@ -1,25 +1,64 @@
-use simba::Simulation;
+use simba::StatisticsSnapshot;
 
 use super::{UiMessage, UiMessages};
 
-use std::sync::Arc;
+use crate::scene::SceneManager;
+
+use std::sync::{Arc, Mutex};
 
 pub struct Statistics {
     ui_messages: Arc<UiMessages>,
-    simulation: Arc<Simulation>,
+    scene_manager: Arc<SceneManager>,
+    /// All snapshots received so far, so they can be exported at any time
+    history: Mutex<Vec<StatisticsSnapshot>>,
 }
 
 impl Statistics {
-    pub fn new(ui_messages: Arc<UiMessages>, simulation: Arc<Simulation>) -> Self {
+    pub fn new(ui_messages: Arc<UiMessages>, scene_manager: Arc<SceneManager>) -> Self {
         Self {
             ui_messages,
-            simulation,
+            scene_manager,
+            history: Default::default(),
         }
     }
 
-    pub fn notify_updated(&self) {
-        let data_point = self.simulation.get_global_statistics();
-        let msg = UiMessage::UpdateGlobalStatistics(data_point);
+    /// Takes the statistics pushed by the simulation, so we never have to poll individual nodes
+    pub fn notify_updated(&self, snapshot: StatisticsSnapshot) {
+        self.scene_manager.notify_statistics(&snapshot);
+
+        let msg = UiMessage::UpdateGlobalStatistics(snapshot.global.clone());
         self.ui_messages.push(msg);
+
+        if !snapshot.anomalies.is_empty() {
+            let msg = UiMessage::AnomaliesDetected(snapshot.time, snapshot.anomalies.clone());
+            self.ui_messages.push(msg);
+        }
+
+        self.history.lock().unwrap().push(snapshot);
+    }
+
+    /// The statistics history in the same format as `--log-stats`
+    pub fn to_csv(&self) -> String {
+        let history = self.history.lock().unwrap();
+
+        let Some(first) = history.first() else {
+            return String::new();
+        };
+
+        let mut lines = vec![first.get_csv_header().join(",")];
+        lines.extend(history.iter().map(|entry| entry.get_csv_record().join(",")));
+
+        lines.join("\n") + "\n"
+    }
+
+    /// The statistics history as a JSON array with one object per data point
+    pub fn to_json(&self) -> String {
+        let history = self.history.lock().unwrap();
+        let records: Vec<serde_json::Value> = history
+            .iter()
+            .map(StatisticsSnapshot::get_json_record)
+            .collect();
+
+        serde_json::to_string_pretty(&records).expect("Failed to serialize statistics")
     }
 }
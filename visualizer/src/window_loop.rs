@@ -76,6 +76,7 @@ impl WinitHandler for ApplicationHandler {
                 log::debug!("Scale factor changed from {scale_factor} to {new_val}");
                 scale_factor = new_val;
                 self.graphics.get_renderer().set_scale_factor(scale_factor);
+                self.scene_mgr.notify_resize();
             }
             WindowEvent::Resized(new_size) => {
                 log::debug!("Window resized to {new_size:?}");
@@ -1,19 +1,23 @@
 #![allow(clippy::arc_with_non_send_sync)]
 
-use simba_visualizer::graphics::{Graphics, RenderLoop};
+use simba_visualizer::graphics::{Graphics, RenderLoop, RenderLoopConfig};
 use simba_visualizer::scene::SceneManager;
 use simba_visualizer::ui::{CursorPosition, UiEvents, UiMessages};
 use simba_visualizer::window_loop::WindowLoop;
 
 use simba::{Failures, NetworkConfiguration, ProtocolConfiguration, Simulation};
 
+mod recorder;
+use recorder::{RecordingSimulation, StatisticsRecorder};
+
 use std::process::exit;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 use anyhow::Context;
 
-use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
 
 use winit::platform::web::WindowBuilderExtWebSys;
 use winit::window::WindowBuilder;
@@ -59,10 +63,24 @@ async fn async_run() -> anyhow::Result<()> {
         })
         .unwrap();
 
+    // Let the page layout decide the canvas size.
+    // winit observes the canvas and emits resize events whenever it changes.
+    let style = canvas.style();
+    style
+        .set_property("width", "100%")
+        .and_then(|_| style.set_property("height", "100%"))
+        .map_err(|err| anyhow::anyhow!("Failed to set canvas style: {err:?}"))?;
+
+    // The canvas takes keyboard input itself; the UI announces changes in a live region
+    canvas
+        .set_attribute("role", "application")
+        .and_then(|_| canvas.set_attribute("aria-label", "SimBA network simulation"))
+        .and_then(|_| canvas.set_attribute("tabindex", "0"))
+        .map_err(|err| anyhow::anyhow!("Failed to set canvas attributes: {err:?}"))?;
+
     let window = WindowBuilder::new()
         .with_title("SimBA")
         .with_canvas(Some(canvas))
-        .with_resizable(false)
         .build(&winit_loop)
         .with_context(|| "Failed to create web window")?;
 
@@ -77,14 +95,18 @@ async fn async_run() -> anyhow::Result<()> {
     log::debug!("Setting up simulation");
     let network = NetworkConfiguration::default();
     let protocol = ProtocolConfiguration::default();
-    let failures = Failures::new(network.num_nodes(), None);
+    let failures = Failures::new(&protocol, &network, None, network.get_seed());
+
+    // There is no file system to write statistics to, so they are recorded for downloading
+    let simulation = Arc::new(Simulation::new(protocol, network, failures, None).unwrap());
+    let recorder = Arc::new(StatisticsRecorder::default());
+    recorder.add_download_buttons(&document)?;
 
-    let simulation = Arc::new(Simulation::new(protocol, network, failures).unwrap());
+    let handle = Arc::new(RecordingSimulation::new(simulation.clone(), recorder));
 
     log::debug!("Setting up scene manager");
-    let scene_mgr = Arc::new(
-        SceneManager::new(graphics.clone(), ui_messages.clone(), simulation.clone()).await,
-    );
+    let scene_mgr =
+        Arc::new(SceneManager::new(graphics.clone(), ui_messages.clone(), handle.clone()).await);
 
     log::debug!("Everything set up!");
 
@@ -98,7 +120,6 @@ async fn async_run() -> anyhow::Result<()> {
         let graphics = graphics.clone();
         let ui_events = ui_events.clone();
         let cursor_position = cursor_position.clone();
-        let simulation = simulation.clone();
         let scene_mgr = scene_mgr.clone();
 
         // Window is not send on WebAssembly
@@ -110,8 +131,10 @@ async fn async_run() -> anyhow::Result<()> {
             cursor_position,
             window,
             surface,
-            simulation,
+            handle,
             scene_mgr.clone(),
+            Arc::new(AtomicBool::new(false)),
+            RenderLoopConfig::default(),
         )
         .await;
 
@@ -0,0 +1,192 @@
+//! Recording statistics in the browser, where there is no file system for `--log-stats`
+
+use simba::{
+    BlockDetails, BlockEvent, BlockGraph, BlockId, EventCallback, GlobalStatistics, LinkEvent,
+    Location, NodeChainInfo, NodeEvent, NodeIndex, ObjectId, Simulation, SimulationSpeed,
+    StatisticsEvent, StatsEventCallback, Time,
+};
+
+use simba_visualizer::export;
+use simba_visualizer::simulation::SimulationHandle;
+
+use std::sync::{Arc, Mutex};
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+
+/// Buffers all global statistics updates, so they can be downloaded at any time
+#[derive(Default)]
+pub struct StatisticsRecorder {
+    history: Mutex<Vec<(Time, GlobalStatistics)>>,
+}
+
+impl StatisticsRecorder {
+    const CSV_FILE: &'static str = "statistics.csv";
+    const JSON_FILE: &'static str = "statistics.json";
+
+    fn record(&self, event: &StatisticsEvent) {
+        let StatisticsEvent::Updated(snapshot) = event;
+
+        self.history
+            .lock()
+            .unwrap()
+            .push((snapshot.time, snapshot.global.clone()));
+    }
+
+    /// One line per update, starting with the time in milliseconds
+    pub fn to_csv(&self) -> String {
+        let history = self.history.lock().unwrap();
+
+        let Some((_, first)) = history.first() else {
+            return String::new();
+        };
+
+        let mut header = vec!["time".to_string()];
+        header.extend(first.get_csv_header());
+
+        let mut lines = vec![header.join(",")];
+
+        for (time, statistics) in history.iter() {
+            let mut record = vec![time.to_millis().to_string()];
+            record.extend(statistics.get_csv_record());
+            lines.push(record.join(","));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// A JSON array with one object per update
+    pub fn to_json(&self) -> String {
+        let history = self.history.lock().unwrap();
+        let records: Vec<serde_json::Value> = history
+            .iter()
+            .map(|(time, statistics)| {
+                serde_json::json!({
+                    "time": time.to_millis(),
+                    "network": statistics,
+                })
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&records).expect("Failed to serialize statistics")
+    }
+
+    /// Adds buttons below the canvas that download the recorded statistics
+    pub fn add_download_buttons(
+        self: &Arc<Self>,
+        document: &web_sys::Document,
+    ) -> anyhow::Result<()> {
+        let body = document
+            .body()
+            .ok_or_else(|| anyhow::anyhow!("Html document has no body"))?;
+
+        let buttons: [(&str, fn(&Self) -> String, &'static str); 2] = [
+            ("Download Statistics (CSV)", Self::to_csv, Self::CSV_FILE),
+            ("Download Statistics (JSON)", Self::to_json, Self::JSON_FILE),
+        ];
+
+        for (label, generate, name) in buttons {
+            let to_error = |err: JsValue| anyhow::anyhow!("Failed to add download button: {err:?}");
+
+            let button = document.create_element("button").map_err(to_error)?;
+            button.set_text_content(Some(label));
+
+            let recorder = self.clone();
+            let on_click = Closure::<dyn FnMut()>::new(move || {
+                if let Err(err) = export::save_file(name, generate(&recorder)) {
+                    log::error!("Failed to download statistics: {err:?}");
+                }
+            });
+
+            button
+                .add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())
+                .map_err(to_error)?;
+
+            // The buttons live as long as the page
+            on_click.forget();
+
+            body.append_child(&button).map_err(to_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Passes all statistics updates to a recorder before handing them to the visualizer
+pub struct RecordingSimulation {
+    simulation: Arc<Simulation>,
+    recorder: Arc<StatisticsRecorder>,
+}
+
+impl RecordingSimulation {
+    pub fn new(simulation: Arc<Simulation>, recorder: Arc<StatisticsRecorder>) -> Self {
+        Self {
+            simulation,
+            recorder,
+        }
+    }
+}
+
+impl SimulationHandle for RecordingSimulation {
+    fn set_block_event_callback(&self, callback: EventCallback<BlockId, BlockEvent>) {
+        self.simulation.set_block_event_callback(callback)
+    }
+
+    fn set_node_event_callback(&self, callback: EventCallback<NodeIndex, NodeEvent>) {
+        self.simulation.set_node_event_callback(callback)
+    }
+
+    fn set_link_event_callback(&self, callback: EventCallback<ObjectId, LinkEvent>) {
+        self.simulation.set_link_event_callback(callback)
+    }
+
+    fn set_stats_event_callback(&self, callback: StatsEventCallback) {
+        let recorder = self.recorder.clone();
+
+        self.simulation
+            .set_stats_event_callback(Box::new(move |event| {
+                recorder.record(&event);
+                callback(event);
+            }))
+    }
+
+    fn get_current_time(&self) -> Time {
+        self.simulation.get_current_time()
+    }
+
+    fn get_node_location(&self, node_index: NodeIndex) -> Location {
+        self.simulation.get_node_location(node_index)
+    }
+
+    fn get_node_chain_info(&self, node_index: NodeIndex) -> Option<NodeChainInfo> {
+        self.simulation.get_node_chain_info(node_index)
+    }
+
+    fn get_speed(&self) -> SimulationSpeed {
+        self.simulation.get_speed()
+    }
+
+    fn set_rate_limit(&self, rate_limit: u32) {
+        self.simulation.set_rate_limit(rate_limit)
+    }
+
+    fn get_rate_limit(&self) -> Option<u32> {
+        self.simulation.get_rate_limit()
+    }
+
+    fn get_rate_limit_f64(&self) -> Option<f64> {
+        self.simulation.get_rate_limit_f64()
+    }
+
+    fn pause_at(&self, time: Time) {
+        self.simulation.pause_at(time)
+    }
+
+    fn get_block_graph(&self) -> BlockGraph {
+        self.simulation.get_block_graph()
+    }
+
+    fn get_block_details(&self, block_id: BlockId) -> Option<BlockDetails> {
+        self.simulation.get_block_details(block_id)
+    }
+}